@@ -0,0 +1,100 @@
+// Author: Giulian Biolo, github.com/giulianbiolo
+// Date: 16/10/2026
+// Description: This file implements the micro benchmarks of the intersection kernels and of the path tracing of the reference scenes, run with "cargo bench"
+
+use std::sync::Arc;
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use glam::{Vec2, Vec3A};
+
+use gbrt::acceleration::{self, BVHParams};
+use gbrt::camera::Camera;
+use gbrt::color::Color;
+use gbrt::hittable_list::{Hittable, HittableList};
+use gbrt::material::Lambertian;
+use gbrt::parser;
+use gbrt::point3::Point3;
+use gbrt::ray::Ray;
+use gbrt::raytracer;
+use gbrt::rectangle::XYRectangle;
+use gbrt::sphere::Sphere;
+use gbrt::texture::SolidColor;
+use gbrt::triangle::Triangle;
+use gbrt::utility::{self, Constants, load_environment};
+
+
+// The scenes of the --benchmark mode, each one traced with its own constants
+const SCENES: [(&str, &str); 2] = [
+    ("cornell_box", include_str!("../configs/cornell_box.yaml")),
+    ("glass_spheres", include_str!("../configs/benchmarks/glass_spheres.yaml")),
+];
+// Rays cycled through by every benchmark, so that the branches don't always go the same way
+const RAYS: usize = 1024;
+
+// Rays from around the origin towards a unit sized target at z = -5, a fixed sequence from a seeded generator
+fn _rays() -> Vec<Ray> {
+    let rng: fastrand::Rng = fastrand::Rng::with_seed(0x6762_7274);
+    (0..RAYS).map(|_| {
+        let target: Vec2 = Vec2::new(rng.f32(), rng.f32()) * 2.0 - Vec2::ONE;
+        Ray::new(Point3::ZERO, Vec3A::new(target.x, target.y, -5.0))
+    }).collect()
+}
+
+// Times the closest hit of the object along each of the rays in turn
+fn _bench_hits(c: &mut Criterion, name: &str, object: &(dyn Hittable + Send + Sync)) {
+    let rays: Vec<Ray> = _rays();
+    let mut idx: usize = 0;
+    c.bench_function(name, |b| b.iter(|| {
+        idx = (idx + 1) % RAYS;
+        black_box(object.hit(black_box(&rays[idx]), utility::NEAR_ZERO, utility::INFINITY).map(|rec| rec.t))
+    }));
+}
+
+fn intersection(c: &mut Criterion) {
+    let material = || Box::new(Lambertian::new(Color::splat(0.5)));
+    _bench_hits(c, "sphere", &Sphere::new(Point3::new(0.0, 0.0, -5.0), 0.8, material(), 0));
+    _bench_hits(c, "triangle", &Triangle::new(
+        Box::new([Point3::new(-1.0, -1.0, -5.0), Point3::new(1.0, -1.0, -5.0), Point3::new(0.0, 1.0, -5.0)]),
+        Box::new([Vec3A::Z; 3]), material(), 0
+    ));
+    _bench_hits(c, "rectangle", &XYRectangle::new(-0.8, 0.8, -0.8, 0.8, -5.0, material(), 0));
+    // A sphere of about 130k triangles, traversing the BVH of the mesh and its packets of 4 triangles
+    let mesh = Sphere::new(Point3::new(0.0, 0.0, -5.0), 0.8, material(), 0).tessellate(&SolidColor::new(Color::ZERO), 0.0, 512, &BVHParams::default());
+    _bench_hits(c, "mesh_bvh", &mesh);
+    // The BVH over a grid of spheres, the way the objects of a scene are traversed
+    let mut spheres: Vec<Sphere> = (0..1024).map(|i| Sphere::new(Point3::new((i % 32) as f32 / 16.0 - 1.0, (i / 32) as f32 / 16.0 - 1.0, -5.0), 0.02, material(), 0)).collect();
+    let bvh = acceleration::build(&mut spheres, &BVHParams::default());
+    let rays: Vec<Ray> = _rays();
+    let mut idx: usize = 0;
+    c.bench_function("spheres_bvh", |b| b.iter(|| {
+        idx = (idx + 1) % RAYS;
+        let ray: &Ray = black_box(&rays[idx]);
+        let mut closest: Option<f32> = None;
+        acceleration::traverse(&bvh, ray, utility::NEAR_ZERO, utility::INFINITY, |object, t_max| match spheres[object].hit(ray, utility::NEAR_ZERO, t_max) {
+            Some(rec) => { closest = Some(rec.t); rec.t },
+            None => t_max,
+        });
+        black_box(closest)
+    }));
+}
+
+// Times the whole path of a camera ray through the reference scenes, light sampling and materials included
+fn ray_color(c: &mut Criterion) {
+    for (name, content) in SCENES {
+        let doc = yaml_rust::YamlLoader::load_from_str(content).unwrap().remove(0);
+        let (consts, cam, world): (Constants, Camera, HittableList) = (parser::parse_constants(&doc), parser::parse_camera(&doc), parser::parse_scene(&doc));
+        let (environment_map, _): (Arc<dyn Hittable + Send + Sync>, f32) = load_environment(&Vec::new(), &consts);
+        let lights: HittableList = raytracer::sample_lights(&world, raytracer::get_lights(&world));
+        let rng: fastrand::Rng = fastrand::Rng::with_seed(0x6762_7274);
+        let rays: Vec<Ray> = (0..RAYS).map(|_| cam.get_ray(rng.f32(), rng.f32())).collect();
+        let (mut idx, mut path) = (0_usize, Vec::new());
+        c.bench_function(&format!("ray_color_{}", name), |b| b.iter(|| {
+            idx = (idx + 1) % RAYS;
+            path.clear();
+            black_box(raytracer::ray_color(black_box(&rays[idx]), &world, &lights, &environment_map, None, &consts, 0, &mut path))
+        }));
+    }
+}
+
+criterion_group!(benches, intersection, ray_color);
+criterion_main!(benches);
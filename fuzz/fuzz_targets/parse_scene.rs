@@ -0,0 +1,17 @@
+// Author: Giulian Biolo, github.com/giulianbiolo
+// Date: 16/10/2026
+// Description: This file implements the fuzz target of the scene parser, which has to turn any malformed scene into an error, any panic being reported as a crash
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+use gbrt::parser;
+
+
+// Run with "cargo fuzz run parse_scene fuzz/corpus/parse_scene configs", the scenes of configs/ seeding the corpus
+fuzz_target!(|data: &[u8]| {
+    if let Ok(content) = std::str::from_utf8(data) {
+        let _ = parser::try_parse_scene(content);
+    }
+});
@@ -0,0 +1,259 @@
+// Author: Giulian Biolo, github.com/giulianbiolo
+// Date: 16/10/2026
+// Description: This file implements the BVH build parameters, a binned SAH builder, the ordered traversal and its statistics
+
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+use bvh::aabb::AABB;
+use bvh::bounding_hierarchy::BHShape;
+use bvh::bvh::{BVH, BVHNode};
+use glam::Vec3A;
+
+use crate::ray::Ray;
+use crate::simd::LANES;
+
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BVHParams {
+    // Primitives grouped in a single leaf (a packet tested at once), at most LANES
+    pub max_leaf_primitives: usize,
+    // Candidate split planes evaluated per axis by the SAH builder
+    pub bins: usize,
+}
+
+// Past this many bins the build gets slower without finding better splits
+const MAX_BINS: usize = 1024;
+// The traversal keeps the nodes left to visit in a fixed array, one per level at most
+const STACK_SIZE: usize = 64;
+// Below this depth the nodes are split in half rather than by the SAH, so that no leaf is deeper than the stack even for billions of primitives
+const SAH_MAX_DEPTH: u32 = 24;
+
+impl Default for BVHParams {
+    fn default() -> Self { Self { max_leaf_primitives: LANES, bins: 12 } }
+}
+
+impl BVHParams {
+    pub fn new(max_leaf_primitives: usize, bins: usize) -> Result<BVHParams, String> {
+        if !(1..=LANES).contains(&max_leaf_primitives) { return Err(format!("maxLeafPrimitives must be between 1 and {}, got {}", LANES, max_leaf_primitives)); }
+        if !(2..=MAX_BINS).contains(&bins) { return Err(format!("The BVH needs between 2 and {} bins, got {}", MAX_BINS, bins)); }
+        Ok(BVHParams { max_leaf_primitives, bins })
+    }
+}
+
+/****************** Builder ******************/
+fn _vec(point: bvh::Point3) -> Vec3A { Vec3A::new(point.x, point.y, point.z) }
+
+#[derive(Clone, Copy)]
+struct Bin {
+    aabb: AABB,
+    count: usize,
+}
+
+// Builds a BVH with one primitive per leaf, splitting every node at the cheapest of the bins of all three axes
+pub fn build<T: BHShape>(shapes: &mut [T], params: &BVHParams) -> BVH {
+    let mut nodes: Vec<BVHNode> = Vec::with_capacity(shapes.len() * 2);
+    if shapes.is_empty() { return BVH { nodes }; }
+    let aabbs: Vec<AABB> = shapes.iter().map(|shape| shape.aabb()).collect();
+    let centers: Vec<Vec3A> = aabbs.iter().map(|aabb| _vec(aabb.center())).collect();
+    let mut indices: Vec<usize> = (0..shapes.len()).collect();
+    _build_node(shapes, &aabbs, &centers, &mut indices, &mut nodes, 0, 0, params.bins);
+    BVH { nodes }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn _build_node<T: BHShape>(shapes: &mut [T], aabbs: &[AABB], centers: &[Vec3A], indices: &mut [usize], nodes: &mut Vec<BVHNode>, parent_index: usize, depth: u32, bins: usize) -> usize {
+    let node_index: usize = nodes.len();
+    if indices.len() == 1 {
+        nodes.push(BVHNode::Leaf { parent_index, depth, shape_index: indices[0] });
+        shapes[indices[0]].set_bh_node_index(node_index);
+        return node_index;
+    }
+    // The parent is pushed before its children, which need its index
+    nodes.push(BVHNode::Leaf { parent_index, depth, shape_index: 0 });
+    let (centroid_min, centroid_max) = indices.iter().fold((Vec3A::splat(f32::INFINITY), Vec3A::splat(f32::NEG_INFINITY)), |(min, max), idx| (min.min(centers[*idx]), max.max(centers[*idx])));
+    let extent: Vec3A = centroid_max - centroid_min;
+    let mut best: Option<(f32, usize, f32)> = None;
+    for axis in 0..3 {
+        if extent[axis] <= f32::EPSILON { continue; }
+        let bin_of = |idx: usize| -> usize { (((centers[idx][axis] - centroid_min[axis]) / extent[axis] * bins as f32) as usize).min(bins - 1) };
+        let mut binned: Vec<Bin> = vec![Bin { aabb: AABB::empty(), count: 0 }; bins];
+        for idx in indices.iter() {
+            let bin: &mut Bin = &mut binned[bin_of(*idx)];
+            bin.aabb.join_mut(&aabbs[*idx]);
+            bin.count += 1;
+        }
+        // Sweeping from the right gives the cost of every right side, then the left side is grown bin by bin
+        let mut right_costs: Vec<f32> = vec![0.0; bins];
+        let mut right: Bin = Bin { aabb: AABB::empty(), count: 0 };
+        for split in (1..bins).rev() {
+            right.aabb.join_mut(&binned[split].aabb);
+            right.count += binned[split].count;
+            right_costs[split] = if right.count > 0 { right.count as f32 * right.aabb.surface_area() } else { f32::INFINITY };
+        }
+        let mut left: Bin = Bin { aabb: AABB::empty(), count: 0 };
+        for split in 1..bins {
+            left.aabb.join_mut(&binned[split - 1].aabb);
+            left.count += binned[split - 1].count;
+            if left.count == 0 { continue; }
+            let cost: f32 = left.count as f32 * left.aabb.surface_area() + right_costs[split];
+            if best.is_none_or(|(best_cost, _, _)| cost < best_cost) {
+                best = Some((cost, axis, centroid_min[axis] + extent[axis] * split as f32 / bins as f32));
+            }
+        }
+    }
+    let middle: usize = match best {
+        Some((_, axis, plane)) if depth < SAH_MAX_DEPTH => _partition(indices, |idx| centers[idx][axis] < plane),
+        _ => 0,
+    };
+    // Primitives with coincident centers can't be separated by any plane, so they are just split in half
+    let middle: usize = if middle == 0 || middle == indices.len() { indices.len() / 2 } else { middle };
+    let (left_indices, right_indices) = indices.split_at_mut(middle);
+    let child_l_aabb: AABB = left_indices.iter().fold(AABB::empty(), |aabb, idx| aabb.join(&aabbs[*idx]));
+    let child_r_aabb: AABB = right_indices.iter().fold(AABB::empty(), |aabb, idx| aabb.join(&aabbs[*idx]));
+    let child_l_index: usize = _build_node(shapes, aabbs, centers, left_indices, nodes, node_index, depth + 1, bins);
+    let child_r_index: usize = _build_node(shapes, aabbs, centers, right_indices, nodes, node_index, depth + 1, bins);
+    nodes[node_index] = BVHNode::Node { parent_index, depth, child_l_index, child_l_aabb, child_r_index, child_r_aabb };
+    node_index
+}
+
+// Moves the indices satisfying the predicate to the front, returns how many they are
+fn _partition(indices: &mut [usize], predicate: impl Fn(usize) -> bool) -> usize {
+    let mut middle: usize = 0;
+    for i in 0..indices.len() {
+        if predicate(indices[i]) {
+            indices.swap(i, middle);
+            middle += 1;
+        }
+    }
+    middle
+}
+
+/****************** Traversal ******************/
+// Entry and exit distances of the ray in the box, None if it misses it or the box is outside [t_min, t_max]
+fn _intersect_aabb(aabb: &AABB, origin: Vec3A, inv_direction: Vec3A, t_min: f32, t_max: f32) -> Option<f32> {
+    let t0: Vec3A = (_vec(aabb.min) - origin) * inv_direction;
+    let t1: Vec3A = (_vec(aabb.max) - origin) * inv_direction;
+    let t_enter: f32 = t0.min(t1).max_element().max(t_min);
+    let t_exit: f32 = t0.max(t1).min_element().min(t_max);
+    if t_enter <= t_exit { Some(t_enter) } else { None }
+}
+
+// Visits the leaves hit by the ray nearest first, the visitor returns the new t_max so that farther nodes are skipped
+// A t_max below t_min ends the traversal, for the queries that are done at the first hit
+pub fn traverse(bvh: &BVH, ray: &Ray, t_min: f32, t_max: f32, mut visit: impl FnMut(usize, f32) -> f32) {
+    if bvh.nodes.is_empty() { return; }
+    let (origin, inv_direction) = (ray.origin(), ray.direction().recip());
+    let mut t_max: f32 = t_max;
+    let (mut nodes, mut leaves) = (0_u64, 0_u64);
+    let (mut stack, mut size): ([(usize, f32); STACK_SIZE], usize) = ([(0, t_min); STACK_SIZE], 1);
+    while size > 0 {
+        size -= 1;
+        let (node, t_enter): (usize, f32) = stack[size];
+        // The node was pushed before a closer hit shrunk t_max
+        if t_enter > t_max { continue; }
+        nodes += 1;
+        match bvh.nodes[node] {
+            BVHNode::Leaf { shape_index, .. } => {
+                leaves += 1;
+                t_max = visit(shape_index, t_max);
+                if t_max < t_min { break; }
+            },
+            BVHNode::Node { child_l_index, ref child_l_aabb, child_r_index, ref child_r_aabb, .. } => {
+                let left: Option<f32> = _intersect_aabb(child_l_aabb, origin, inv_direction, t_min, t_max);
+                let right: Option<f32> = _intersect_aabb(child_r_aabb, origin, inv_direction, t_min, t_max);
+                let mut push = |entry: (usize, f32)| { stack[size] = entry; size += 1; };
+                match (left, right) {
+                    // The farther child is pushed first, so that the nearer one is popped first
+                    (Some(l), Some(r)) if l <= r => { push((child_r_index, r)); push((child_l_index, l)); },
+                    (Some(l), Some(r)) => { push((child_l_index, l)); push((child_r_index, r)); },
+                    (Some(l), None) => push((child_l_index, l)),
+                    (None, Some(r)) => push((child_r_index, r)),
+                    (None, None) => {},
+                }
+            },
+        }
+    }
+    if STATS_ENABLED.load(Ordering::Relaxed) {
+        STATS[0].fetch_add(1, Ordering::Relaxed);
+        STATS[1].fetch_add(nodes, Ordering::Relaxed);
+        STATS[2].fetch_add(leaves, Ordering::Relaxed);
+    }
+}
+
+/****************** Statistics ******************/
+static STATS_ENABLED: AtomicBool = AtomicBool::new(false);
+// Traversals, nodes visited, leaves visited and primitives tested
+static STATS: [AtomicU64; 4] = [AtomicU64::new(0), AtomicU64::new(0), AtomicU64::new(0), AtomicU64::new(0)];
+
+// The counters are shared by all threads, so they are only updated when asked for
+pub fn enable_stats() { STATS_ENABLED.store(true, Ordering::Relaxed); }
+
+pub fn record_primitives(count: usize) {
+    if STATS_ENABLED.load(Ordering::Relaxed) { STATS[3].fetch_add(count as u64, Ordering::Relaxed); }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct TraversalStats {
+    pub traversals: u64,
+    pub nodes: u64,
+    pub leaves: u64,
+    pub primitives: u64,
+}
+
+// Returns the statistics collected since the last call, resetting them
+pub fn take_stats() -> TraversalStats {
+    TraversalStats {
+        traversals: STATS[0].swap(0, Ordering::Relaxed),
+        nodes: STATS[1].swap(0, Ordering::Relaxed),
+        leaves: STATS[2].swap(0, Ordering::Relaxed),
+        primitives: STATS[3].swap(0, Ordering::Relaxed),
+    }
+}
+
+impl std::fmt::Display for TraversalStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.traversals == 0 { return write!(f, "BVH traversals: none"); }
+        let per_traversal = |count: u64| count as f64 / self.traversals as f64;
+        write!(f, "BVH traversals: {}, {:.2} nodes, {:.2} leaves, {:.2} primitives per traversal",
+            self.traversals, per_traversal(self.nodes), per_traversal(self.leaves), per_traversal(self.primitives))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::Color;
+    use crate::hittable_list::Hittable;
+    use crate::material::Lambertian;
+    use crate::point3::Point3;
+    use crate::sphere::Sphere;
+
+    #[test]
+    fn test_build_and_traverse() {
+        let mut spheres: Vec<Sphere> = (0..50).map(|i| Sphere::new(Point3::new((i % 10) as f32, (i / 10) as f32, -5.0 - (i % 3) as f32), 0.3, Box::new(Lambertian::new(Color::ONE)), 0)).collect();
+        for bins in [2, 6, 32] {
+            let bvh: BVH = build(&mut spheres, &BVHParams::new(LANES, bins).unwrap());
+            assert_eq!(bvh.nodes.len(), 2 * spheres.len() - 1);
+            assert!(bvh.is_consistent(&spheres));
+            for direction in [Vec3A::new(0.0, 0.0, -1.0), Vec3A::new(0.5, 0.3, -1.0), Vec3A::new(1.2, 0.7, -1.0)] {
+                let ray: Ray = Ray::new(Point3::new(0.1, 0.2, 0.0), direction);
+                let expected: Option<f32> = spheres.iter().filter_map(|sphere| sphere.hit(&ray, 0.001, f32::INFINITY)).map(|rec| rec.t).reduce(f32::min);
+                let mut closest: Option<f32> = None;
+                traverse(&bvh, &ray, 0.001, f32::INFINITY, |idx, t_max| match spheres[idx].hit(&ray, 0.001, t_max) {
+                    Some(rec) => { closest = Some(rec.t); rec.t },
+                    None => t_max,
+                });
+                assert_eq!(closest, expected);
+            }
+        }
+        // Spheres farther and farther apart, which the SAH would split off one at a time, still fit the traversal stack
+        let mut chain: Vec<Sphere> = (0..100).map(|i| Sphere::new(Point3::new(2.0_f32.powi(i), 0.0, -5.0), 0.3, Box::new(Lambertian::new(Color::ONE)), 0)).collect();
+        let bvh: BVH = build(&mut chain, &BVHParams::default());
+        let depth: u32 = bvh.nodes.iter().map(|node| match node { BVHNode::Leaf { depth, .. } | BVHNode::Node { depth, .. } => *depth }).max().unwrap();
+        assert!(depth > SAH_MAX_DEPTH && depth <= SAH_MAX_DEPTH + 7 && (depth as usize) < STACK_SIZE);
+        let ray: Ray = Ray::new(Point3::new(2.0_f32.powi(10), 0.0, 0.0), -Vec3A::Z);
+        let mut hits: Vec<usize> = Vec::new();
+        traverse(&bvh, &ray, 0.001, f32::INFINITY, |idx, t_max| { if chain[idx].hit(&ray, 0.001, t_max).is_some() { hits.push(idx); } t_max });
+        assert_eq!(hits, vec![10]);
+    }
+}
@@ -0,0 +1,62 @@
+// Author: Giulian Biolo, github.com/giulianbiolo
+// Date: 16/10/2026
+// Description: This file implements the adaptive sampling, which stops sampling a pixel once the error of its estimate is small enough
+
+use crate::color::Color;
+use crate::light_sampler::luminance;
+
+
+// Pixels stop after min_samples once the standard error of their luminance is under threshold times the luminance
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AdaptiveSampling {
+    pub threshold: f32,
+    pub min_samples: u32,
+}
+
+impl AdaptiveSampling {
+    pub fn converged(&self, estimate: &PixelEstimate) -> bool {
+        if estimate.samples < self.min_samples.max(2) { return false; }
+        let samples: f32 = estimate.samples as f32;
+        let mean: f32 = estimate.sum / samples;
+        let variance: f32 = ((estimate.sum_squared / samples - mean * mean) * samples / (samples - 1.0)).max(0.0);
+        // Dark pixels are held to an absolute error instead, or they would never converge
+        (variance / samples).sqrt() <= self.threshold * mean.max(0.01)
+    }
+}
+
+/****************** PixelEstimate ******************/
+// The running sums of the luminance of the samples of a pixel
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct PixelEstimate {
+    pub samples: u32,
+    pub sum: f32,
+    pub sum_squared: f32,
+}
+
+impl PixelEstimate {
+    pub fn add(&mut self, color: Color) {
+        let value: f32 = luminance(color);
+        self.samples += 1;
+        self.sum += value;
+        self.sum_squared += value * value;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_adaptive_convergence() {
+        let adaptive: AdaptiveSampling = AdaptiveSampling { threshold: 0.05, min_samples: 4 };
+        let (mut flat, mut noisy) = (PixelEstimate::default(), PixelEstimate::default());
+        for idx in 0..16 {
+            flat.add(Color::splat(0.5));
+            noisy.add(if idx % 2 == 0 { Color::ZERO } else { Color::ONE });
+            // A constant pixel converges as soon as it's allowed to, an alternating one is still a quarter off after 16 samples
+            assert_eq!(adaptive.converged(&flat), idx >= 3);
+            assert!(!adaptive.converged(&noisy));
+        }
+        assert!(AdaptiveSampling { threshold: 0.3, min_samples: 4 }.converged(&noisy));
+    }
+}
@@ -0,0 +1,152 @@
+// Author: Giulian Biolo, github.com/giulianbiolo
+// Date: 16/10/2026
+// Description: This file implements the arbitrary output variables (AOVs), per pixel statistics of the paths saved as heatmaps next to the image
+
+use std::path::Path;
+
+use glam::Vec3A;
+use image::{ImageBuffer, Rgb};
+
+
+// The statistics of the integrator that can be saved, for tuning maxDepth, minDepth and the russian roulette
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Aov {
+    // Average number of bounces of the paths
+    Bounces,
+    // Average distance travelled by the paths, in scene units
+    PathLength,
+    // Fraction of the paths cut short by maxDepth
+    DepthLimit,
+    // Number of samples taken, which the adaptive sampling lowers where the pixel converged
+    Samples,
+}
+
+impl Aov {
+    pub fn from_name(name: &str) -> Result<Aov, String> {
+        match name {
+            "bounces" => Ok(Aov::Bounces),
+            "pathLength" => Ok(Aov::PathLength),
+            "depthLimit" => Ok(Aov::DepthLimit),
+            "samples" => Ok(Aov::Samples),
+            name => Err(format!("Unknown AOV: {}, expected bounces, pathLength, depthLimit or samples", name)),
+        }
+    }
+    // The name of the constants, the inverse of from_name
+    pub fn name(&self) -> &'static str {
+        match self {
+            Aov::Bounces => "bounces",
+            Aov::PathLength => "pathLength",
+            Aov::DepthLimit => "depthLimit",
+            Aov::Samples => "samples",
+        }
+    }
+    // Appended to the name of the output for the image of the AOV
+    pub fn suffix(&self) -> &'static str {
+        match self {
+            Aov::Bounces => "bounces",
+            Aov::PathLength => "path_length",
+            Aov::DepthLimit => "depth_limit",
+            Aov::Samples => "samples",
+        }
+    }
+}
+
+/****************** PathStats ******************/
+// The statistics of the samples of a pixel, summed over them
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct PathStats {
+    pub samples: u32,
+    pub bounces: u32,
+    pub length: f32,
+    pub depth_limited: u32,
+}
+
+impl PathStats {
+    // Adds a sample from its path as ray_color leaves it, the camera origin followed by a vertex per bounce and the one where the path ended
+    pub fn add_path(&mut self, path: &[Vec3A], max_depth: u32) {
+        let bounces: u32 = path.len().saturating_sub(2) as u32;
+        self.samples += 1;
+        self.bounces += bounces;
+        self.length += path.windows(2).map(|segment| (segment[1] - segment[0]).length()).sum::<f32>();
+        // The ray after the last allowed bounce is never traced
+        if bounces >= max_depth { self.depth_limited += 1; }
+    }
+    // Merges the samples of another pass over the same pixel
+    pub fn add(&mut self, other: &PathStats) {
+        self.samples += other.samples;
+        self.bounces += other.bounces;
+        self.length += other.length;
+        self.depth_limited += other.depth_limited;
+    }
+    // The average of the samples, zero without any, or their number
+    pub fn value(&self, aov: Aov) -> f32 {
+        if self.samples == 0 { return 0.0; }
+        let total: f32 = match aov {
+            Aov::Bounces => self.bounces as f32,
+            Aov::PathLength => self.length,
+            Aov::DepthLimit => self.depth_limited as f32,
+            Aov::Samples => return self.samples as f32,
+        };
+        total / self.samples as f32
+    }
+}
+
+/****************** AovBuffer ******************/
+pub struct AovBuffer {
+    pub width: u32,
+    pub height: u32,
+    // Row major pixels
+    pub pixels: Vec<PathStats>,
+}
+
+impl AovBuffer {
+    pub fn new(width: u32, height: u32) -> AovBuffer { AovBuffer { width, height, pixels: vec![PathStats::default(); (width * height) as usize] } }
+    pub fn row_mut(&mut self, y: u32) -> &mut [PathStats] {
+        let start: usize = (y * self.width) as usize;
+        &mut self.pixels[start..start + self.width as usize]
+    }
+    // Saves a heatmap per AOV as <output>_<suffix>.png, scaled to the largest value of the image (the fraction of depth limited paths as it is)
+    pub fn save(&self, aovs: &[Aov], filename: &str) {
+        let stem: String = Path::new(filename).with_extension("").to_string_lossy().to_string();
+        for aov in aovs {
+            let values: Vec<f32> = self.pixels.iter().map(|stats| stats.value(*aov)).collect();
+            let (min, max) = values.iter().fold((f32::INFINITY, 0.0_f32), |(min, max), value| (min.min(*value), max.max(*value)));
+            let scale: f32 = if *aov == Aov::DepthLimit { 1.0 } else { max };
+            let image: ImageBuffer<Rgb<u8>, Vec<u8>> = ImageBuffer::from_fn(self.width, self.height, |x, y| heatmap(values[(y * self.width + x) as usize] / scale.max(f32::MIN_POSITIVE)));
+            let output: String = format!("{}_{}.png", stem, aov.suffix());
+            image.save(&output).unwrap();
+            println!("Saved the {} AOV to {}, from {:.3} to {:.3} (mean {:.3})", aov.suffix(), output, min, max, values.iter().sum::<f32>() / values.len().max(1) as f32);
+        }
+    }
+}
+
+// The color of a value from 0 to 1, going from dark blue through cyan, green and yellow to red
+pub fn heatmap(value: f32) -> Rgb<u8> {
+    const STOPS: [[f32; 3]; 6] = [[0.0, 0.0, 0.3], [0.0, 0.2, 1.0], [0.0, 0.9, 1.0], [0.1, 0.9, 0.1], [1.0, 0.9, 0.0], [1.0, 0.0, 0.0]];
+    let position: f32 = if value.is_finite() { value.clamp(0.0, 1.0) * (STOPS.len() - 1) as f32 } else { 0.0 };
+    let idx: usize = (position as usize).min(STOPS.len() - 2);
+    let color: Vec3A = Vec3A::from(STOPS[idx]).lerp(Vec3A::from(STOPS[idx + 1]), position - idx as f32);
+    Rgb([(color.x * 255.0) as u8, (color.y * 255.0) as u8, (color.z * 255.0) as u8])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_path_stats() {
+        let mut stats: PathStats = PathStats::default();
+        // A camera ray hitting a light 2 away, then a path bouncing once before escaping, then one cut by a max depth of 2
+        stats.add_path(&[Vec3A::ZERO, Vec3A::new(0.0, 0.0, -2.0)], 2);
+        stats.add_path(&[Vec3A::ZERO, Vec3A::new(0.0, 0.0, -1.0), Vec3A::new(0.0, 3.0, -1.0)], 2);
+        let bounce: Vec3A = Vec3A::new(1.0, 0.0, 0.0);
+        stats.add_path(&[Vec3A::ZERO, bounce, Vec3A::ZERO, Vec3A::ZERO], 2);
+        assert_eq!((stats.samples, stats.bounces, stats.depth_limited), (3, 3, 1));
+        assert!((stats.value(Aov::PathLength) - 8.0 / 3.0).abs() < 1e-5);
+        assert!((stats.value(Aov::Bounces) - 1.0).abs() < 1e-5 && (stats.value(Aov::DepthLimit) - 1.0 / 3.0).abs() < 1e-5);
+        assert_eq!(PathStats::default().value(Aov::Bounces), 0.0);
+        assert_eq!((heatmap(0.0), heatmap(1.0), heatmap(f32::NAN)), (Rgb([0, 0, 76]), Rgb([255, 0, 0]), Rgb([0, 0, 76])));
+        assert_eq!(Aov::from_name("pathLength").unwrap().suffix(), "path_length");
+        assert_eq!(stats.value(Aov::Samples), 3.0);
+    }
+}
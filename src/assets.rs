@@ -0,0 +1,227 @@
+// Author: Giulian Biolo, github.com/giulianbiolo
+// Date: 16/10/2026
+// Description: This file implements the loading of the asset files, read from a stack of sources: the assets handed over in memory, the mounted archives and directories, and the file system
+
+use std::collections::HashMap;
+use std::io::{Cursor, Error, ErrorKind, Read};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, RwLock};
+
+use image::{DynamicImage, ImageFormat, ImageResult};
+use lazy_static::lazy_static;
+use zip::ZipArchive;
+use zip::result::ZipError;
+
+
+// The scene of an archive given as the config file
+pub const ARCHIVE_SCENE: &str = "scene.yaml";
+
+/****************** AssetSource ******************/
+pub trait AssetSource: Send + Sync {
+    // An error of kind NotFound lets the next source of the stack try
+    fn read(&self, filename: &str) -> std::io::Result<Vec<u8>>;
+    fn read_to_string(&self, filename: &str) -> std::io::Result<String> {
+        String::from_utf8(self.read(filename)?).map_err(|err| Error::new(ErrorKind::InvalidData, err))
+    }
+}
+
+// Paths relative to the root, which is the working directory when empty
+pub struct FileSystemSource {
+    root: PathBuf,
+}
+
+impl FileSystemSource {
+    pub fn new(root: &str) -> FileSystemSource { FileSystemSource { root: PathBuf::from(root) } }
+}
+
+impl AssetSource for FileSystemSource {
+    #[cfg(not(target_arch = "wasm32"))]
+    fn read(&self, filename: &str) -> std::io::Result<Vec<u8>> { std::fs::read(self.root.join(filename)) }
+    // There's no file system in the browser, everything has to be registered
+    #[cfg(target_arch = "wasm32")]
+    fn read(&self, filename: &str) -> std::io::Result<Vec<u8>> { Err(Error::new(ErrorKind::NotFound, format!("{} wasn't registered", filename))) }
+}
+
+#[derive(Default)]
+pub struct MemorySource {
+    files: RwLock<HashMap<String, Vec<u8>>>,
+}
+
+impl MemorySource {
+    pub fn new() -> MemorySource { MemorySource { files: RwLock::new(HashMap::new()) } }
+    pub fn insert(&self, filename: &str, bytes: Vec<u8>) { self.files.write().unwrap().insert(filename.to_string(), bytes); }
+}
+
+impl AssetSource for MemorySource {
+    fn read(&self, filename: &str) -> std::io::Result<Vec<u8>> {
+        self.files.read().unwrap().get(filename).cloned().ok_or_else(|| Error::new(ErrorKind::NotFound, format!("{} isn't in memory", filename)))
+    }
+}
+
+// The files of a zip archive, by their path inside of it
+pub struct ZipSource {
+    archive: Mutex<ZipArchive<Cursor<Vec<u8>>>>,
+}
+
+impl ZipSource {
+    pub fn new(bytes: Vec<u8>) -> std::io::Result<ZipSource> {
+        let archive: ZipArchive<Cursor<Vec<u8>>> = ZipArchive::new(Cursor::new(bytes)).map_err(_zip_error)?;
+        Ok(ZipSource { archive: Mutex::new(archive) })
+    }
+    // The archive itself is read from the sources mounted so far
+    pub fn open(filename: &str) -> std::io::Result<ZipSource> { ZipSource::new(read(filename)?) }
+}
+
+impl AssetSource for ZipSource {
+    fn read(&self, filename: &str) -> std::io::Result<Vec<u8>> {
+        let name: String = filename.replace('\\', "/").trim_start_matches("./").to_string();
+        let mut archive = self.archive.lock().unwrap();
+        let mut file = archive.by_name(&name).map_err(_zip_error)?;
+        let mut bytes: Vec<u8> = Vec::with_capacity(file.size() as usize);
+        file.read_to_end(&mut bytes)?;
+        Ok(bytes)
+    }
+}
+
+fn _zip_error(err: ZipError) -> Error {
+    match err {
+        ZipError::Io(err) => err,
+        ZipError::FileNotFound => Error::new(ErrorKind::NotFound, err),
+        err => Error::new(ErrorKind::InvalidData, err),
+    }
+}
+
+// Paths relative to the base URL, and absolute http(s) URLs as they are
+#[cfg(feature = "http")]
+pub struct HttpSource {
+    base_url: String,
+}
+
+#[cfg(feature = "http")]
+impl HttpSource {
+    pub fn new(base_url: &str) -> HttpSource { HttpSource { base_url: base_url.trim_end_matches('/').to_string() } }
+}
+
+#[cfg(feature = "http")]
+impl AssetSource for HttpSource {
+    fn read(&self, filename: &str) -> std::io::Result<Vec<u8>> {
+        let url: String = if is_url(filename) { filename.to_string() }
+            else if self.base_url.is_empty() { return Err(Error::new(ErrorKind::NotFound, format!("{} isn't a URL", filename))); }
+            else { format!("{}/{}", self.base_url, filename.trim_start_matches("./")) };
+        match ureq::get(&url).call() {
+            Ok(response) => {
+                let mut bytes: Vec<u8> = Vec::new();
+                response.into_reader().read_to_end(&mut bytes)?;
+                Ok(bytes)
+            },
+            Err(ureq::Error::Status(404, _)) => Err(Error::new(ErrorKind::NotFound, format!("{} wasn't found", url))),
+            Err(err) => Err(Error::other(format!("Failed to download {}: {}", url, err))),
+        }
+    }
+}
+
+/****************** Sources stack ******************/
+lazy_static! {
+    // Contents of the assets handed over in memory, which come before every other source
+    static ref REGISTERED: Arc<MemorySource> = Arc::new(MemorySource::new());
+    static ref SOURCES: RwLock<Vec<Arc<dyn AssetSource>>> = RwLock::new(_default_sources());
+}
+
+fn _default_sources() -> Vec<Arc<dyn AssetSource>> {
+    #[allow(unused_mut)]
+    let mut sources: Vec<Arc<dyn AssetSource>> = vec![REGISTERED.clone(), Arc::new(FileSystemSource::new(""))];
+    #[cfg(feature = "http")]
+    sources.push(Arc::new(HttpSource::new("")));
+    sources
+}
+
+// The content is used instead of the file with the same name, which doesn't need to exist
+#[allow(dead_code)]
+pub fn register(filename: &str, bytes: Vec<u8>) { REGISTERED.insert(filename, bytes); }
+
+// The source comes before the file system and the sources mounted earlier
+pub fn mount(source: Arc<dyn AssetSource>) { SOURCES.write().unwrap().insert(1, source); }
+
+// Zip archives, the .gbrt scene archives included
+pub fn is_archive(location: &str) -> bool { location.ends_with(".zip") || location.ends_with(".gbrt") }
+
+pub fn is_url(location: &str) -> bool { location.starts_with("http://") || location.starts_with("https://") }
+
+// A base URL, a zip archive or a directory
+pub fn open_source(location: &str) -> Arc<dyn AssetSource> {
+    if is_url(location) {
+        #[cfg(feature = "http")]
+        return Arc::new(HttpSource::new(location));
+        #[cfg(not(feature = "http"))]
+        panic!("Can't read the assets from {}, gbrt was built without the http feature", location);
+    }
+    if is_archive(location) { return Arc::new(ZipSource::open(location).unwrap_or_else(|err| panic!("Failed to open the archive {}: {}", location, err))); }
+    Arc::new(FileSystemSource::new(location))
+}
+
+// The first source having the asset reads it
+pub fn read(filename: &str) -> std::io::Result<Vec<u8>> {
+    let sources: Vec<Arc<dyn AssetSource>> = SOURCES.read().unwrap().clone();
+    for source in sources.iter() {
+        match source.read(filename) {
+            Err(err) if err.kind() == ErrorKind::NotFound => continue,
+            result => return result,
+        }
+    }
+    Err(Error::new(ErrorKind::NotFound, format!("{} wasn't found in any asset source", filename)))
+}
+
+pub fn read_to_string(filename: &str) -> std::io::Result<String> {
+    String::from_utf8(read(filename)?).map_err(|err| Error::new(ErrorKind::InvalidData, err))
+}
+
+// The format comes from the extension, like image::open, or from the content when it has none
+pub fn open_image(filename: &str) -> ImageResult<DynamicImage> {
+    let bytes: Vec<u8> = read(filename)?;
+    match ImageFormat::from_path(filename) {
+        Ok(format) => image::load_from_memory_with_format(&bytes, format),
+        Err(_) => image::load_from_memory(&bytes),
+    }
+}
+
+// The size of the image, decoding its header only
+pub fn image_dimensions(filename: &str) -> ImageResult<(u32, u32)> {
+    let bytes: Cursor<Vec<u8>> = Cursor::new(read(filename)?);
+    match ImageFormat::from_path(filename) {
+        Ok(format) => image::io::Reader::with_format(bytes, format).into_dimensions(),
+        Err(_) => image::io::Reader::new(bytes).with_guessed_format()?.into_dimensions(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use image::{Rgb, RgbImage};
+
+    #[test]
+    fn test_in_memory() {
+        let mut png: Vec<u8> = Vec::new();
+        RgbImage::from_pixel(2, 3, Rgb([255, 0, 0])).write_to(&mut Cursor::new(&mut png), ImageFormat::Png).unwrap();
+        register("in_memory/red.png", png);
+        register("in_memory/scene.yaml", b"world: []".to_vec());
+        let image: DynamicImage = open_image("in_memory/red.png").unwrap();
+        assert_eq!((image.width(), image.height()), (2, 3));
+        assert_eq!(read_to_string("in_memory/scene.yaml").unwrap(), "world: []");
+        assert!(read("in_memory/missing.png").is_err());
+    }
+    #[test]
+    fn test_zip_source() {
+        let mut bytes: Vec<u8> = Vec::new();
+        {
+            let mut writer = zip::ZipWriter::new(Cursor::new(&mut bytes));
+            writer.start_file("models/cube.obj", zip::write::FileOptions::default()).unwrap();
+            writer.write_all(b"v 0 0 0").unwrap();
+            writer.finish().unwrap();
+        }
+        let archive: ZipSource = ZipSource::new(bytes).unwrap();
+        assert_eq!(archive.read_to_string("./models/cube.obj").unwrap(), "v 0 0 0");
+        // Missing files fall through to the next source
+        assert_eq!(archive.read("models/sphere.obj").unwrap_err().kind(), ErrorKind::NotFound);
+    }
+}
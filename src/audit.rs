@@ -0,0 +1,119 @@
+// Author: Giulian Biolo, github.com/giulianbiolo
+// Date: 16/10/2026
+// Description: This file implements the energy conservation audit, rendering every material in a white furnace
+
+use std::sync::Arc;
+
+use glam::Vec3A;
+use crate::parallel::*;
+use yaml_rust::Yaml;
+
+use crate::color::Color;
+use crate::hittable_list::{Hittable, HittableList};
+use crate::material::{Material, Lambertian, Metal, Dielectric, Plastic, GGXGlossy, Coated, DiffuseLight};
+use crate::onb::ONB;
+use crate::parser;
+use crate::point3::Point3;
+use crate::ray::Ray;
+use crate::raytracer;
+use crate::sphere::Sphere;
+use crate::utility;
+
+
+const SAMPLES: u32 = 200_000;
+// Deviations from a perfect furnace smaller than this are considered noise
+const TOLERANCE: f32 = 0.01;
+
+#[derive(Debug, PartialEq)]
+enum Verdict {
+    Conserving,
+    Gains,
+    Loses,
+}
+
+// A white material must reflect all of the light of a white environment: more means energy is created, less that it's destroyed
+fn _verdict(mean: f32, standard_error: f32) -> Verdict {
+    let threshold: f32 = TOLERANCE.max(3.0 * standard_error);
+    if mean > 1.0 + threshold { Verdict::Gains } else if mean < 1.0 - threshold { Verdict::Loses } else { Verdict::Conserving }
+}
+
+// Reference materials, all of them with a white albedo
+fn _reference_materials() -> Vec<(String, Box<dyn Material + Send + Sync>)> {
+    vec![
+        ("Lambertian".to_string(), Box::new(Lambertian::new(Color::ONE))),
+        ("Metal (fuzz 0.0)".to_string(), Box::new(Metal::new(Color::ONE, 0.0))),
+        ("Metal (fuzz 0.5)".to_string(), Box::new(Metal::new(Color::ONE, 0.5))),
+        ("Dielectric (ior 1.5)".to_string(), Box::new(Dielectric::new(Color::ONE, 1.5, 0.0))),
+        ("Plastic (0.5, fuzz 0.2)".to_string(), Box::new(Plastic::new(Color::ONE, 0.5, 0.2))),
+        ("GGX (roughness 0.3)".to_string(), Box::new(GGXGlossy::new(Color::ONE, 0.3, 0.5))),
+        ("GGX (roughness 0.8)".to_string(), Box::new(GGXGlossy::new(Color::ONE, 0.8, 1.0))),
+        ("Coated Lambertian".to_string(), Box::new(Coated::new(Box::new(Lambertian::new(Color::ONE)), 1.5, 0.1))),
+    ]
+}
+
+// The materials of the objects in the scene, except for the lights
+fn _scene_materials(config_file: &str) -> Vec<(String, Box<dyn Material + Send + Sync>)> {
+    let doc: Yaml = parser::load_config(config_file).unwrap_or_else(|| panic!("Failed to load the config file {}", config_file));
+    doc["world"].as_vec().unwrap().iter().enumerate()
+        .filter_map(|(idx, hashobj)| {
+            let hash = hashobj.as_hash().unwrap();
+            if !hash.contains_key(&Yaml::String("material".to_string())) { return None; }
+            let material = parser::parse_material(hash).unwrap_or_else(|err| panic!("{}", err));
+            if material.is_light() { return None; }
+            Some((format!("object {} ({})", idx, hashobj["material"]["matType"].as_str().unwrap_or("?")), material))
+        })
+        .collect()
+}
+
+// Mean and standard error of the radiance leaving a unit sphere made of the material, lit by a uniform white environment
+fn _furnace(material: Box<dyn Material + Send + Sync>) -> (f32, f32) {
+    let world: HittableList = vec![Arc::new(Sphere::new(Point3::ZERO, 1.0, material, 0))];
+    let lights: HittableList = HittableList::new();
+    let furnace: Arc<dyn Hittable + Send + Sync> = Arc::new(Sphere::new(Point3::ZERO, 100.0, Box::new(DiffuseLight::new(Color::ONE, 1.0)), 0));
+    let (sum, sum_squared) = (0..SAMPLES).into_par_iter().map(|_| {
+        // Parallel rays from a random direction, hitting a random point of the disk the sphere projects onto
+        let direction: Vec3A = utility::random_unit_vector();
+        let mut onb: ONB = ONB::new();
+        onb.build_from_w(&direction);
+        let offset: Vec3A = onb.local_vec(&utility::random_in_unit_disk());
+        let ray: Ray = Ray::new(offset - direction * 3.0, direction);
+        let mut path: Vec<Vec3A> = vec![ray.origin()];
+        let radiance: f32 = raytracer::ray_color(&ray, &world, &lights, &furnace, None, &utility::CONSTS, 0, &mut path).dot(Vec3A::ONE) / 3.0;
+        if radiance.is_finite() { (radiance as f64, (radiance * radiance) as f64) } else { (0.0, 0.0) }
+    }).reduce(|| (0.0, 0.0), |a, b| (a.0 + b.0, a.1 + b.1));
+    let mean: f64 = sum / SAMPLES as f64;
+    let variance: f64 = (sum_squared / SAMPLES as f64 - mean * mean).max(0.0);
+    (mean as f32, (variance / SAMPLES as f64).sqrt() as f32)
+}
+
+pub fn run(config_file: &str) {
+    let mut materials: Vec<(String, Box<dyn Material + Send + Sync>)> = _reference_materials();
+    if !config_file.is_empty() { materials.extend(_scene_materials(config_file)); }
+    println!("White furnace audit, {} samples per material (scene materials lose energy when their albedo isn't white)", SAMPLES);
+    let mut flagged: usize = 0;
+    for (name, material) in materials {
+        let (mean, standard_error) = _furnace(material);
+        let verdict: Verdict = _verdict(mean, standard_error);
+        let note: &str = match verdict {
+            Verdict::Conserving => "ok",
+            Verdict::Gains => { flagged += 1; "CREATES ENERGY" },
+            Verdict::Loses => "loses energy",
+        };
+        println!("{:>28}: {:.4} +- {:.4} ({:+.2}%) {}", name, mean, standard_error, (mean - 1.0) * 100.0, note);
+    }
+    if flagged > 0 { println!("{} materials create energy", flagged); }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_verdict() {
+        assert_eq!(_verdict(1.002, 0.001), Verdict::Conserving);
+        assert_eq!(_verdict(1.1, 0.001), Verdict::Gains);
+        assert_eq!(_verdict(0.8, 0.001), Verdict::Loses);
+        // Noisy estimates need a bigger deviation to be flagged
+        assert_eq!(_verdict(1.05, 0.02), Verdict::Conserving);
+    }
+}
@@ -12,13 +12,53 @@ use glam::Vec3A;
 
 use crate::ray::Ray;
 use crate::hit_record::HitRecord;
+use crate::diffraction::Edge;
+use crate::gpu_scene::FlatTriangle;
 use crate::hittable_list::Hittable;
+use crate::light_sampler::LightEstimate;
 use crate::material::Material;
 use crate::point3::Point3;
-use crate::rectangle::{XZRectangle, YZRectangle, Rectangle, XYRectangle};
+use crate::rectangle::{XZRectangle, YZRectangle, Rectangle, XYRectangle, UVTransform};
 use crate::utility;
 
 
+// The faces of a box, the front one being on the -Z side, where the camera usually looks from
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BoxFace {
+    Floor,
+    Ceiling,
+    Left,
+    Right,
+    Back,
+    Front,
+}
+
+impl BoxFace {
+    pub const ALL: [BoxFace; 6] = [BoxFace::Floor, BoxFace::Ceiling, BoxFace::Left, BoxFace::Right, BoxFace::Back, BoxFace::Front];
+    pub fn from_name(name: &str) -> Result<BoxFace, String> {
+        match name {
+            "floor" => Ok(BoxFace::Floor),
+            "ceiling" => Ok(BoxFace::Ceiling),
+            "left" => Ok(BoxFace::Left),
+            "right" => Ok(BoxFace::Right),
+            "back" => Ok(BoxFace::Back),
+            "front" => Ok(BoxFace::Front),
+            _ => Err(format!("Unknown box face: {}, expected floor, ceiling, left, right, back or front", name)),
+        }
+    }
+    // The rectangle of the face of the box from min to max, with the normal along the positive side of its axis
+    pub fn rectangle(&self, min: Point3, max: Point3, material: Box<dyn Material>) -> Rectangle {
+        match self {
+            BoxFace::Floor => Rectangle::XZRectangle(XZRectangle::new(min.x, max.x, min.z, max.z, min.y, material, 0)),
+            BoxFace::Ceiling => Rectangle::XZRectangle(XZRectangle::new(min.x, max.x, min.z, max.z, max.y, material, 0)),
+            BoxFace::Left => Rectangle::YZRectangle(YZRectangle::new(min.y, max.y, min.z, max.z, min.x, material, 0)),
+            BoxFace::Right => Rectangle::YZRectangle(YZRectangle::new(min.y, max.y, min.z, max.z, max.x, material, 0)),
+            BoxFace::Back => Rectangle::XYRectangle(XYRectangle::new(min.x, max.x, min.y, max.y, max.z, material, 0)),
+            BoxFace::Front => Rectangle::XYRectangle(XYRectangle::new(min.x, max.x, min.y, max.y, min.z, material, 0)),
+        }
+    }
+}
+
 pub struct BBox {
     faces: Vec<Rectangle>,
     bvh: BVH,
@@ -31,13 +71,14 @@ unsafe impl Send for BBox {}
 impl BBox {
     #[allow(dead_code)]
     pub fn new(center: Point3, dimensions: Vec3A, material: Box<dyn Material>) -> BBox {
-        let mut faces: Vec<Rectangle> = Vec::with_capacity(6);
-        faces.push(Rectangle::XYRectangle(XYRectangle::new(center.x - dimensions.x / 2.0, center.x + dimensions.x / 2.0, center.y - dimensions.y / 2.0, center.y + dimensions.y / 2.0, center.z - dimensions.z / 2.0, material.clone(), 0)));
-        faces.push(Rectangle::XYRectangle(XYRectangle::new(center.x - dimensions.x / 2.0, center.x + dimensions.x / 2.0, center.y - dimensions.y / 2.0, center.y + dimensions.y / 2.0, center.z + dimensions.z / 2.0, material.clone(), 0)));
-        faces.push(Rectangle::XZRectangle(XZRectangle::new(center.x - dimensions.x / 2.0, center.x + dimensions.x / 2.0, center.z - dimensions.z / 2.0, center.z + dimensions.z / 2.0, center.y - dimensions.y / 2.0, material.clone(), 0)));
-        faces.push(Rectangle::XZRectangle(XZRectangle::new(center.x - dimensions.x / 2.0, center.x + dimensions.x / 2.0, center.z - dimensions.z / 2.0, center.z + dimensions.z / 2.0, center.y + dimensions.y / 2.0, material.clone(), 0)));
-        faces.push(Rectangle::YZRectangle(YZRectangle::new(center.y - dimensions.y / 2.0, center.y + dimensions.y / 2.0, center.z - dimensions.z / 2.0, center.z + dimensions.z / 2.0, center.x - dimensions.x / 2.0, material.clone(), 0)));
-        faces.push(Rectangle::YZRectangle(YZRectangle::new(center.y - dimensions.y / 2.0, center.y + dimensions.y / 2.0, center.z - dimensions.z / 2.0, center.z + dimensions.z / 2.0, center.x + dimensions.x / 2.0, material.clone(), 0)));
+        BBox::new_with_uv_transforms(center, dimensions, material, [UVTransform::default(); 6])
+    }
+    // The texture coordinates of each face get their own transform, in the order of BoxFace::ALL
+    pub fn new_with_uv_transforms(center: Point3, dimensions: Vec3A, material: Box<dyn Material>, uv_transforms: [UVTransform; 6]) -> BBox {
+        let (min, max) = (center - dimensions / 2.0, center + dimensions / 2.0);
+        let mut faces: Vec<Rectangle> = BoxFace::ALL.iter().zip(uv_transforms)
+            .map(|(face, uv_transform)| face.rectangle(min, max, material.clone()).with_uv_transform(uv_transform))
+            .collect();
         let bvh: BVH = BVH::build(&mut faces);
         BBox {
             faces,
@@ -63,7 +104,7 @@ impl BHShape for BBox {
 }
 
 impl Hittable for BBox {
-    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
         let bvhray: BVHRay = BVHRay::new(BVHPoint3::new(ray.origin[0], ray.origin[1], ray.origin[2]), BVHVector3::new(ray.direction[0], ray.direction[1], ray.direction[2]));
         let hit_faces_aabb: Vec<&Rectangle> = self.bvh.traverse(&bvhray, &self.faces);
 
@@ -72,12 +113,17 @@ impl Hittable for BBox {
         .filter(|hit| hit.t > t_min && hit.t < t_max)
         .min_by(|hit1, hit2| { hit1.t.partial_cmp(&hit2.t).unwrap() })
     }
+    fn occluded(&self, ray: &Ray, t_min: f32, t_max: f32) -> bool { self.faces.iter().any(|face| face.occluded(ray, t_min, t_max)) }
     fn is_light(&self) -> bool { self.faces.iter().any(|face| face.is_light()) }
+    fn world_bounds(&self) -> Option<bvh::aabb::AABB> { Some(self.aabb()) }
+    fn light_estimate(&self) -> Option<LightEstimate> { LightEstimate::merge(self.faces.iter().filter_map(|face| face.light_estimate())) }
     fn pdf_value(&self, origin: &Point3, v: &Vec3A) -> f32 {
         let weight: f32 = 1.0 / self.faces.len() as f32;
         self.faces.iter().map(|triangle| triangle.pdf_value(origin, v) * weight).sum()
     }
     fn random(&self, o: &Point3) -> Vec3A { self.faces[utility::random_usize_range(0, self.faces.len())].random(o) }
+    fn edges(&self) -> Vec<Edge> { self.faces.iter().flat_map(|face| face.edges()).collect() }
+    fn tessellation(&self) -> Vec<FlatTriangle> { self.faces.iter().flat_map(|face| face.tessellation()).collect() }
 }
 
 #[cfg(test)]
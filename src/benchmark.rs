@@ -0,0 +1,167 @@
+// Author: Giulian Biolo, github.com/giulianbiolo
+// Date: 16/10/2026
+// Description: This file implements the benchmark and regression modes, rendering reference scenes embedded in the binary
+
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Instant;
+
+use image::RgbImage;
+use yaml_rust::{Yaml, YamlLoader};
+
+use crate::acceleration;
+use crate::camera::Camera;
+use crate::cli::ARGS;
+use crate::color::Color;
+use crate::compare;
+use crate::hittable_list::{Hittable, HittableList};
+use crate::parser;
+use crate::raytracer;
+use crate::sampling_filters::Filter;
+use crate::utility::{self, CONSTS, load_environment, load_filter};
+
+
+// The reference scenes are embedded at compile time, so that the benchmark doesn't depend on the working directory
+const SCENES: [(&str, &str); 2] = [
+    ("cornell_box", include_str!("../configs/cornell_box.yaml")),
+    ("glass_spheres", include_str!("../configs/benchmarks/glass_spheres.yaml")),
+];
+const SEED: u64 = 0x6762_7274;
+// Highest mean FLIP error a regression render can have against its reference
+const REGRESSION_TOLERANCE: f32 = 0.05;
+
+// Every scene is rendered with the same constants, the ones in the scene files are ignored
+pub fn constants() -> utility::Constants {
+    utility::Constants {
+        width: 256,
+        height: 256,
+        aspect_ratio: 1.0,
+        samples_per_pixel: 16,
+        max_depth: 16,
+        min_depth: 5,
+        environment_intensity: Some(0.0),
+        ..Default::default()
+    }
+}
+
+// Tiny renders, cheap enough to run on every test
+pub fn regression_constants() -> utility::Constants {
+    utility::Constants {
+        width: 64,
+        height: 64,
+        aspect_ratio: 1.0,
+        samples_per_pixel: 8,
+        max_depth: 8,
+        min_depth: 5,
+        environment_intensity: Some(0.0),
+        ..Default::default()
+    }
+}
+
+fn _load_scene(content: &str) -> (Camera, HittableList) {
+    let doc: Yaml = YamlLoader::load_from_str(content).unwrap().remove(0);
+    (parser::parse_camera(&doc), parser::parse_scene(&doc))
+}
+
+// Renders a scene with fixed seeds, returning the image and the number of path vertices
+fn _render_scene(content: &str, environment_map: &Arc<dyn Hittable + Send + Sync>, filter: &(dyn Filter + Send + Sync)) -> (RgbImage, u64) {
+    let (cam, world) = _load_scene(content);
+    let lights: HittableList = raytracer::sample_lights(&world, raytracer::get_lights(&world));
+    let mut accumulator: Vec<Color> = vec![Color::ZERO; (CONSTS.width * CONSTS.height) as usize];
+    let vertices: u64 = (0..CONSTS.samples_per_pixel)
+        .map(|pass| raytracer::render_pass(&world, &lights, environment_map, None, filter, &cam, &CONSTS, &mut accumulator, SEED + pass as u64))
+        .sum();
+    (raytracer::accumulator_to_image(&accumulator, &CONSTS, CONSTS.samples_per_pixel), vertices)
+}
+
+pub fn run() {
+    println!("Benchmarking {} scenes at {}x{}, {} samples per pixel", SCENES.len(), CONSTS.width, CONSTS.height, CONSTS.samples_per_pixel);
+    // The scenes are all small enough for the default environment sphere
+    let (environment_map, _): (Arc<dyn Hittable + Send + Sync>, f32) = load_environment(&Vec::new(), &CONSTS);
+    let filter: Box<dyn Filter + Send + Sync> = load_filter();
+    let (mut total_vertices, mut total_seconds) = (0_u64, 0.0_f64);
+    for (name, content) in SCENES {
+        let start_time: Instant = Instant::now();
+        let (image, vertices) = _render_scene(content, &environment_map, filter.as_ref());
+        let seconds: f64 = start_time.elapsed().as_secs_f64();
+        println!("{:>28}: {:>8.3}s, {:>10} path vertices, {:>8.3} M path vertices/s", name, seconds, vertices, vertices as f64 / seconds / 1e6);
+        if ARGS.bvh_stats { println!("{:>28}  {}", "", acceleration::take_stats()); }
+        image.save(format!("benchmark_{}.png", name)).unwrap();
+        total_vertices += vertices;
+        total_seconds += seconds;
+    }
+    println!("{:>28}: {:>8.3}s, {:>10} path vertices, {:>8.3} M path vertices/s", "total", total_seconds, total_vertices, total_vertices as f64 / total_seconds / 1e6);
+}
+
+// The .yaml scenes of the directory by name, each one with the filter named in its constants
+fn _directory_scenes(references_dir: &str) -> Vec<(String, String, Box<dyn Filter + Send + Sync>)> {
+    let mut files: Vec<PathBuf> = match std::fs::read_dir(references_dir) {
+        Ok(entries) => entries.map(|entry| entry.unwrap().path()).filter(|path| path.extension().is_some_and(|extension| extension == "yaml")).collect(),
+        Err(_) => Vec::new(),
+    };
+    files.sort();
+    files.into_iter().map(|file| {
+        let content: String = std::fs::read_to_string(&file).unwrap_or_else(|err| panic!("Failed to read the scene {}: {}", file.display(), err));
+        let doc: Yaml = YamlLoader::load_from_str(&content).unwrap().remove(0);
+        let filter: Box<dyn Filter + Send + Sync> = utility::filter_from_name(doc["constants"]["filter"].as_str());
+        (file.file_stem().unwrap().to_string_lossy().to_string(), content, filter)
+    }).collect()
+}
+
+// Renders the embedded scenes and the golden ones in the directory, and compares each with its reference there, returns false if any of them changed
+// The golden scenes are tiny and isolate a material, a primitive or a filter, so that a failure points at what changed
+pub fn regression(references_dir: &str, update: bool) -> bool {
+    // The scenes are all small enough for the default environment sphere
+    let (environment_map, _): (Arc<dyn Hittable + Send + Sync>, f32) = load_environment(&Vec::new(), &CONSTS);
+    let mut scenes: Vec<(String, String, Box<dyn Filter + Send + Sync>)> = SCENES.iter().map(|(name, content)| (name.to_string(), content.to_string(), load_filter())).collect();
+    scenes.extend(_directory_scenes(references_dir));
+    let mut passed: bool = true;
+    for (name, content, filter) in &scenes {
+        let (image, _) = _render_scene(content, &environment_map, filter.as_ref());
+        let reference_file: PathBuf = Path::new(references_dir).join(format!("{}.png", name));
+        if update {
+            std::fs::create_dir_all(references_dir).unwrap();
+            image.save(&reference_file).unwrap();
+            println!("{:>28}: reference updated", name);
+            continue;
+        }
+        let reference: RgbImage = match image::open(&reference_file) {
+            Ok(reference) => reference.to_rgb8(),
+            Err(err) => panic!("Failed to load the reference render {}: {}", reference_file.display(), err),
+        };
+        let error: f32 = compare::flip(&reference, &image);
+        println!("{:>28}: FLIP {:.6}, RMSE {:.6}", name, error, compare::rmse(&reference, &image));
+        if error > REGRESSION_TOLERANCE {
+            let failed_file: PathBuf = std::env::temp_dir().join(format!("regression_{}.png", name));
+            image.save(&failed_file).unwrap();
+            println!("{:>28}: differs from its reference, the new render was saved to {}", name, failed_file.display());
+            passed = false;
+        }
+    }
+    passed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_embedded_scenes() {
+        for (_, content) in SCENES {
+            let (_, world) = _load_scene(content);
+            assert!(!world.is_empty());
+            assert!(!raytracer::get_lights(&world).is_empty());
+        }
+    }
+    #[test]
+    fn test_golden_scenes() {
+        // The golden scenes are found next to their references, in order, and load with a light
+        let scenes = _directory_scenes("tests/references");
+        assert!(scenes.iter().any(|(name, _, _)| name == "material_lambertian"));
+        assert!(scenes.windows(2).all(|pair| pair[0].0 < pair[1].0));
+        for (name, content, _) in &scenes {
+            assert!(Path::new("tests/references").join(format!("{}.png", name)).exists());
+            assert!(!raytracer::get_lights(&_load_scene(content).1).is_empty());
+        }
+    }
+}
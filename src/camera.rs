@@ -2,15 +2,19 @@
 // Date: 24/01/2023
 // Description: This file implements the Camera struct
 
+use std::sync::Arc;
+
 use glam::Vec3A;
+use image::GrayImage;
 
+use crate::assets;
 use crate::utility;
-use crate::ray::Ray;
+use crate::ray::{Ray, RayDifferential};
 use crate::point3::Point3;
 use crate::parser;
 
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Debug)]
 pub struct Camera {
     pub origin: Point3,
     lower_left_corner: Point3,
@@ -20,6 +24,8 @@ pub struct Camera {
     pub v: Vec3A,
     pub w: Vec3A,
     lens_radius: f32,
+    // The shape of the lens opening, a disk when None
+    aperture: Option<Arc<Aperture>>,
 }
 
 impl Camera {
@@ -48,17 +54,90 @@ impl Camera {
             v,
             w,
             lens_radius,
+            aperture: None,
         }
     }
+    pub fn with_aperture(mut self, aperture: Aperture) -> Camera {
+        self.aperture = Some(Arc::new(aperture));
+        self
+    }
     pub fn new_from_yaml(filename: &str) -> Camera { parser::parse_yaml_camera(filename) }
     pub fn get_ray(&self, u: f32, v: f32) -> Ray {
-        let rd: Vec3A = utility::random_in_unit_disk() * self.lens_radius;
+        let rd: Vec3A = match &self.aperture {
+            Some(aperture) => aperture.sample(),
+            None => utility::random_in_unit_disk(),
+        } * self.lens_radius;
         let offset: Vec3A = self.u * rd.x + self.v * rd.y;
         Ray::new(
             self.origin + offset,
             self.lower_left_corner + self.horizontal * u + self.vertical * v - self.origin - offset
         )
     }
+    // Same as get_ray, with the differentials of the rays du and dv away on the image plane (one pixel), through the same lens point
+    pub fn get_ray_differential(&self, u: f32, v: f32, du: f32, dv: f32) -> Ray {
+        let mut ray: Ray = self.get_ray(u, v);
+        ray.differential = Some(RayDifferential {
+            rx_origin: ray.origin(),
+            rx_direction: ray.direction() + self.horizontal * du,
+            ry_origin: ray.origin(),
+            ry_direction: ray.direction() + self.vertical * dv,
+        });
+        ray
+    }
+    // Direction of the ray through (u, v) from the center of the lens
+    pub fn pinhole_direction(&self, u: f32, v: f32) -> Vec3A { self.lower_left_corner + self.horizontal * u + self.vertical * v - self.origin }
+    // The (u, v) the point is seen at through the center of the lens, the inverse of pinhole_direction, None behind the camera
+    pub fn project(&self, p: &Point3) -> Option<(f32, f32)> {
+        let direction: Vec3A = *p - self.origin;
+        let forward: f32 = -direction.dot(self.w);
+        if forward <= 0.0 { return None; }
+        let focus_dist: f32 = (self.origin - self.lower_left_corner).dot(self.w);
+        let on_plane: Vec3A = direction * (focus_dist / forward) + self.origin - self.lower_left_corner;
+        Some((on_plane.dot(self.horizontal) / self.horizontal.length_squared(), on_plane.dot(self.vertical) / self.vertical.length_squared()))
+    }
+    // The camera as plain numbers, to be saved along with a frame
+    pub fn to_floats(&self) -> [f32; 22] {
+        let mut floats: [f32; 22] = [0.0; 22];
+        for (idx, vector) in [self.origin, self.lower_left_corner, self.horizontal, self.vertical, self.u, self.v, self.w].iter().enumerate() {
+            floats[idx * 3..idx * 3 + 3].copy_from_slice(&vector.to_array());
+        }
+        floats[21] = self.lens_radius;
+        floats
+    }
+    pub fn from_floats(floats: &[f32; 22]) -> Camera {
+        let vector = |idx: usize| -> Vec3A { Vec3A::from_slice(&floats[idx * 3..idx * 3 + 3]) };
+        Camera { origin: vector(0), lower_left_corner: vector(1), horizontal: vector(2), vertical: vector(3), u: vector(4), v: vector(5), w: vector(6), lens_radius: floats[21], aperture: None }
+    }
+}
+
+/****************** Aperture ******************/
+// An aperture image, whose bright pixels let the light through, so that the out of focus highlights (bokeh) take its shape
+#[derive(Debug)]
+pub struct Aperture {
+    width: u32,
+    height: u32,
+    // Cumulative brightness of the pixels, row major
+    cdf: Vec<f32>,
+}
+
+impl Aperture {
+    pub fn new(image: &GrayImage) -> Result<Aperture, String> {
+        let cdf: Vec<f32> = image.pixels().scan(0.0, |total, pixel| { *total += pixel.0[0] as f32; Some(*total) }).collect();
+        if cdf.last().is_none_or(|total| *total <= 0.0) { return Err("The aperture image is black, no light would get through".to_string()); }
+        Ok(Aperture { width: image.width(), height: image.height(), cdf })
+    }
+    pub fn from_file(filename: &str) -> Result<Aperture, String> {
+        Aperture::new(&assets::open_image(filename).map_err(|err| format!("Failed to load the aperture image {}: {}", filename, err))?.to_luma8())
+    }
+    // A point of the lens, in the square [-1, 1] the longest side of the image spans, picked proportionally to the brightness of the image
+    pub fn sample(&self) -> Vec3A {
+        let target: f32 = utility::random_f32() * self.cdf.last().unwrap();
+        let idx: u32 = self.cdf.partition_point(|cumulative| *cumulative <= target).min(self.cdf.len() - 1) as u32;
+        let x: f32 = (idx % self.width) as f32 + utility::random_f32();
+        let y: f32 = (idx / self.width) as f32 + utility::random_f32();
+        let side: f32 = self.width.max(self.height) as f32;
+        Vec3A::new((2.0 * x - self.width as f32) / side, (self.height as f32 - 2.0 * y) / side, 0.0)
+    }
 }
 
 #[cfg(test)]
@@ -81,4 +160,23 @@ mod tests {
         assert_eq!(ray.origin(), Point3::new(0.0, 0.0, 0.0));
         Ok(())
     }
+    #[test]
+    fn test_camera_project() {
+        let camera: Camera = Camera::new(&Point3::new(1.0, 2.0, 3.0), &Point3::ZERO, &Vec3A::Y, 60.0, 1.5, 0.0, 2.0);
+        let (u, v) = camera.project(&(camera.origin + camera.pinhole_direction(0.3, 0.8) * 4.0)).unwrap();
+        assert!((u - 0.3).abs() < utility::EPSILON && (v - 0.8).abs() < utility::EPSILON);
+        assert!(camera.project(&(camera.origin + camera.w)).is_none());
+        assert_eq!(Camera::from_floats(&camera.to_floats()).to_floats(), camera.to_floats());
+    }
+    #[test]
+    fn test_aperture() {
+        // Only the top right pixel lets the light through
+        let mut image: GrayImage = GrayImage::new(4, 2);
+        image.put_pixel(3, 0, image::Luma([255]));
+        let aperture: Aperture = Aperture::new(&image).unwrap();
+        for _ in 0..100 {
+            let p: Vec3A = aperture.sample();
+            assert!((0.5..=1.0).contains(&p.x) && (0.0..=0.5).contains(&p.y), "{}", p);
+        }
+    }
 }
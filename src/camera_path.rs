@@ -0,0 +1,72 @@
+// Author: Giulian Biolo, github.com/giulianbiolo
+// Date: 16/10/2026
+// Description: This file implements the camera paths of the animations, Catmull-Rom splines through timed waypoints of the camera and of its target
+
+use crate::point3::Point3;
+
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Waypoint {
+    // Seconds from the start of the animation
+    pub time: f32,
+    pub look_from: Point3,
+    pub look_at: Point3,
+}
+
+// The camera goes through every waypoint at its time, smoothly, and stays at the first and last ones before and after the path
+#[derive(Debug, Clone, PartialEq)]
+pub struct CameraPath {
+    waypoints: Vec<Waypoint>,
+}
+
+impl CameraPath {
+    pub fn new(mut waypoints: Vec<Waypoint>) -> Result<CameraPath, String> {
+        if waypoints.is_empty() { return Err("A camera path needs at least one waypoint".to_string()); }
+        waypoints.sort_by(|a, b| a.time.total_cmp(&b.time));
+        Ok(CameraPath { waypoints })
+    }
+    // The position and the target of the camera at the given time
+    pub fn at(&self, time: f32) -> (Point3, Point3) {
+        let last: usize = self.waypoints.len() - 1;
+        let idx: usize = self.waypoints.partition_point(|waypoint| waypoint.time <= time);
+        if idx == 0 { return (self.waypoints[0].look_from, self.waypoints[0].look_at); }
+        if idx > last { return (self.waypoints[last].look_from, self.waypoints[last].look_at); }
+        (self.interpolate(idx - 1, time, |waypoint| waypoint.look_from), self.interpolate(idx - 1, time, |waypoint| waypoint.look_at))
+    }
+    // Cubic Hermite between the waypoints idx and idx + 1, the tangents being the Catmull-Rom ones for uneven times
+    fn interpolate(&self, idx: usize, time: f32, point: impl Fn(&Waypoint) -> Point3) -> Point3 {
+        let (start, end) = (&self.waypoints[idx], &self.waypoints[idx + 1]);
+        let duration: f32 = end.time - start.time;
+        if duration <= 0.0 { return point(end); }
+        let tangent = |idx: usize| -> Point3 {
+            let (before, after) = (&self.waypoints[idx.saturating_sub(1)], &self.waypoints[(idx + 1).min(self.waypoints.len() - 1)]);
+            if after.time > before.time { (point(after) - point(before)) / (after.time - before.time) } else { Point3::ZERO }
+        };
+        let t: f32 = (time - start.time) / duration;
+        let (t2, t3) = (t * t, t * t * t);
+        point(start) * (2.0 * t3 - 3.0 * t2 + 1.0) + tangent(idx) * duration * (t3 - 2.0 * t2 + t)
+            + point(end) * (-2.0 * t3 + 3.0 * t2) + tangent(idx + 1) * duration * (t3 - t2)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_camera_path() {
+        let waypoint = |time: f32, x: f32| Waypoint { time, look_from: Point3::new(x, 1.0, 0.0), look_at: Point3::new(x, 0.0, -1.0) };
+        // Evenly spaced points on a line are followed at a constant speed
+        let path: CameraPath = CameraPath::new(vec![waypoint(2.0, 2.0), waypoint(0.0, 0.0), waypoint(1.0, 1.0), waypoint(3.0, 3.0)]).unwrap();
+        for time in [0.25_f32, 1.5, 2.75] {
+            let (look_from, look_at) = path.at(time);
+            assert!(look_from.abs_diff_eq(Point3::new(time, 1.0, 0.0), 1e-5) && look_at.abs_diff_eq(Point3::new(time, 0.0, -1.0), 1e-5));
+        }
+        assert_eq!(path.at(-1.0).0, Point3::new(0.0, 1.0, 0.0));
+        assert_eq!(path.at(5.0).0, Point3::new(3.0, 1.0, 0.0));
+        // The curve goes through the waypoints and overshoots the middle one of a turn smoothly
+        let turn: CameraPath = CameraPath::new(vec![waypoint(0.0, 0.0), waypoint(1.0, 2.0), waypoint(2.0, 0.0)]).unwrap();
+        assert!(turn.at(1.0).0.abs_diff_eq(Point3::new(2.0, 1.0, 0.0), 1e-5));
+        assert!(turn.at(0.9).0.x > 1.9 && turn.at(1.1).0.x > 1.9);
+    }
+}
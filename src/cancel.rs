@@ -0,0 +1,28 @@
+// Author: Giulian Biolo, github.com/giulianbiolo
+// Date: 16/10/2026
+// Description: This file implements the cooperative cancellation of the render on ctrl-C
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use lazy_static::lazy_static;
+
+
+lazy_static! { static ref CANCELLED: Arc<AtomicBool> = Arc::new(AtomicBool::new(false)); }
+
+// The first ctrl-C stops the render, which then saves what it has, a second one kills the process as usual
+#[cfg(not(target_arch = "wasm32"))]
+pub fn install_handler() {
+    use signal_hook::consts::SIGINT;
+    // Registered first, so that it only sees the flag the previous ctrl-C set
+    signal_hook::flag::register_conditional_shutdown(SIGINT, 130, CANCELLED.clone()).unwrap();
+    signal_hook::flag::register(SIGINT, CANCELLED.clone()).unwrap();
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn install_handler() {}
+
+pub fn cancelled() -> bool { CANCELLED.load(Ordering::Relaxed) }
+
+#[allow(dead_code)]
+pub fn cancel() { CANCELLED.store(true, Ordering::Relaxed); }
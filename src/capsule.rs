@@ -0,0 +1,154 @@
+// Author: Giulian Biolo, github.com/giulianbiolo
+// Date: 16/10/2026
+// Description: This file implements the Capsule struct, a cylinder between two points closed by two hemispheres
+
+use bvh::aabb::{AABB, Bounded};
+use bvh::bounding_hierarchy::BHShape;
+use bvh::Point3 as BVHPoint3;
+
+use glam::Vec3A;
+
+use crate::onb::ONB;
+use crate::ray::Ray;
+use crate::hit_record::HitRecord;
+use crate::hittable_list::Hittable;
+use crate::light_sampler::LightEstimate;
+use crate::material::Material;
+use crate::point3::Point3;
+use crate::utility::{self, PI};
+
+
+pub struct Capsule {
+    start: Point3,
+    end: Point3,
+    radius: f32,
+    // Frame around the axis, from start to end, for the texture coordinates
+    frame: ONB,
+    material: Box<dyn Material>,
+    node_index: usize,
+}
+
+unsafe impl Sync for Capsule {}
+unsafe impl Send for Capsule {}
+
+impl Capsule {
+    pub fn new(start: Point3, end: Point3, radius: f32, material: Box<dyn Material>, node_index: usize) -> Capsule {
+        let mut frame: ONB = ONB::new();
+        // A capsule with both ends in the same point is a sphere, standing along Y
+        frame.build_from_w(&(end - start).try_normalize().unwrap_or(Vec3A::Y));
+        Capsule { start, end, radius, frame, material, node_index }
+    }
+    pub fn area(&self) -> f32 { 2.0 * PI * self.radius * (self.end - self.start).length() + 4.0 * PI * self.radius * self.radius }
+    // Distance along the axis of the projection of the point, from the start
+    fn _height(&self, p: &Point3) -> f32 { (*p - self.start).dot(self.frame.w) }
+    fn _closest_on_axis(&self, p: &Point3) -> Point3 { self.start + self.frame.w * self._height(p).clamp(0.0, (self.end - self.start).length()) }
+    // The roots of the cylinder between the ends and of the hemispheres beyond them
+    fn _roots(&self, ray: &Ray) -> Vec<f32> {
+        let length: f32 = (self.end - self.start).length();
+        let (origin, direction) = (ray.origin(), ray.direction());
+        let mut roots: Vec<f32> = Vec::new();
+        // Cylinder: only the components orthogonal to the axis count towards the distance from it
+        let oc: Vec3A = (origin - self.start) - self.frame.w * (origin - self.start).dot(self.frame.w);
+        let d: Vec3A = direction - self.frame.w * direction.dot(self.frame.w);
+        if let Some((t0, t1)) = utility::solve_quadratic(d.dot(d), oc.dot(d), oc.dot(oc) - self.radius * self.radius) {
+            roots.extend([t0, t1].into_iter().filter(|t| (0.0..=length).contains(&self._height(&ray.at(*t)))));
+        }
+        for (center, start_side) in [(self.start, true), (self.end, false)] {
+            let oc: Vec3A = origin - center;
+            if let Some((t0, t1)) = utility::solve_quadratic(direction.dot(direction), oc.dot(direction), oc.dot(oc) - self.radius * self.radius) {
+                roots.extend([t0, t1].into_iter().filter(|t| {
+                    let height: f32 = self._height(&ray.at(*t));
+                    if start_side { height <= 0.0 } else { height >= length }
+                }));
+            }
+        }
+        roots
+    }
+    // The u goes around the axis, the v along the profile from the pole of the start to the one of the end, proportionally to its length
+    fn _get_capsule_uv(&self, p: &Point3) -> (f32, f32) {
+        let length: f32 = (self.end - self.start).length();
+        let local: Vec3A = *p - self.start;
+        let u: f32 = (local.dot(self.frame.u)).atan2(local.dot(self.frame.v)) / (2.0 * PI) + 0.5;
+        let height: f32 = self._height(p);
+        let quarter: f32 = 0.5 * PI * self.radius;
+        let arc: f32 = if height < 0.0 { quarter - self.radius * (-height / self.radius).clamp(-1.0, 1.0).asin() }
+            else if height > length { quarter + length + self.radius * ((height - length) / self.radius).clamp(-1.0, 1.0).asin() }
+            else { quarter + height };
+        (u, arc / (2.0 * quarter + length))
+    }
+    // Derivatives of the position along the uvs of _get_capsule_uv, degenerate at the poles
+    fn _get_capsule_derivatives(&self, p: &Point3, normal: &Vec3A) -> (Vec3A, Vec3A) {
+        let offset: Vec3A = *p - self._closest_on_axis(p);
+        let radial: Vec3A = offset - self.frame.w * offset.dot(self.frame.w);
+        let profile: Vec3A = match (self.frame.w - *normal * normal.dot(self.frame.w)).try_normalize() {
+            Some(profile) => profile,
+            None => return (Vec3A::ZERO, Vec3A::ZERO),
+        };
+        let total: f32 = PI * self.radius + (self.end - self.start).length();
+        (self.frame.w.cross(radial) * 2.0 * PI, profile * total)
+    }
+    fn _nearest_root(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<f32> {
+        let mut roots: Vec<f32> = self._roots(ray).into_iter().filter(|root| t_min <= *root && *root <= t_max).collect();
+        roots.sort_by(|t1, t2| t1.partial_cmp(t2).unwrap());
+        roots.into_iter().find(|root| {
+            let (u, v) = self._get_capsule_uv(&ray.at(*root));
+            self.material.alpha_test(u, v, &ray.at(*root))
+        })
+    }
+}
+
+impl Bounded for Capsule {
+    fn aabb(&self) -> AABB {
+        let (min, max) = (self.start.min(self.end) - self.radius, self.start.max(self.end) + self.radius);
+        AABB::with_bounds(BVHPoint3::new(min.x, min.y, min.z), BVHPoint3::new(max.x, max.y, max.z))
+    }
+}
+
+impl BHShape for Capsule {
+    fn set_bh_node_index(&mut self, index: usize) { self.node_index = index; }
+    fn bh_node_index(&self) -> usize { self.node_index }
+}
+
+impl Hittable for Capsule {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        let root: f32 = self._nearest_root(ray, t_min, t_max)?;
+        let p: Point3 = ray.at(root);
+        let normal: Vec3A = (p - self._closest_on_axis(&p)) / self.radius;
+        let (u, v) = self._get_capsule_uv(&p);
+        let mut rec: HitRecord = HitRecord::new(p, normal, self.material.as_ref(), root, u, v, false);
+        rec.set_face_normal(ray, &normal);
+        (rec.dpdu, rec.dpdv) = self._get_capsule_derivatives(&p, &normal);
+        Some(rec)
+    }
+    fn occluded(&self, ray: &Ray, t_min: f32, t_max: f32) -> bool { self._nearest_root(ray, t_min, t_max).is_some() }
+    fn is_light(&self) -> bool { self.material.is_light() }
+    fn world_bounds(&self) -> Option<AABB> { Some(self.aabb()) }
+    fn light_estimate(&self) -> Option<LightEstimate> {
+        if !self.material.is_light() { return None; }
+        let center: Point3 = (self.start + self.end) / 2.0;
+        Some(LightEstimate::new(center, (self.end - self.start).length() / 2.0 + self.radius, self.area(), self.material.emitted(0.5, 0.5, &center)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::Color;
+    use crate::material::Lambertian;
+
+    #[test]
+    fn test_capsule_hit() {
+        let capsule: Capsule = Capsule::new(Point3::new(0.0, -1.0, 0.0), Point3::new(0.0, 1.0, 0.0), 0.5, Box::new(Lambertian::new(Color::ONE)), 0);
+        // The side of the cylinder
+        let rec: HitRecord = capsule.hit(&Ray::new(Point3::new(0.0, 0.3, -5.0), Vec3A::Z), 0.001, f32::INFINITY).unwrap();
+        assert!((rec.t - 4.5).abs() < utility::EPSILON && (rec.normal - -Vec3A::Z).length() < utility::EPSILON);
+        // The caps, from above on the pole and from the side past the end of the cylinder
+        let rec: HitRecord = capsule.hit(&Ray::new(Point3::new(0.0, 5.0, 0.0), -Vec3A::Y), 0.001, f32::INFINITY).unwrap();
+        assert!((rec.t - 3.5).abs() < utility::EPSILON && (rec.v - 1.0).abs() < utility::EPSILON);
+        let rec: HitRecord = capsule.hit(&Ray::new(Point3::new(0.3, -1.3, -5.0), Vec3A::Z), 0.001, f32::INFINITY).unwrap();
+        assert!((rec.normal.length() - 1.0).abs() < utility::EPSILON && rec.normal.y < 0.0 && rec.v < 0.5);
+        assert!(capsule.hit(&Ray::new(Point3::new(0.45, 1.45, -5.0), Vec3A::Z), 0.001, f32::INFINITY).is_none());
+        // From the inside the ray leaves through the back of the surface
+        assert!(!capsule.hit(&Ray::new(Point3::ZERO, Vec3A::Y), 0.001, f32::INFINITY).unwrap().front_face);
+    }
+}
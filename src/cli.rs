@@ -0,0 +1,382 @@
+// Author: Giulian Biolo, github.com/giulianbiolo
+// Date: 16/10/2026
+// Description: This file implements the parsing of the command line arguments
+
+use std::env::args;
+use std::path::Path;
+use lazy_static::lazy_static;
+use yaml_rust::{Yaml, YamlLoader};
+
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MissingAssetPolicy {
+    // Abort the whole render as soon as an asset can't be loaded
+    Error,
+    // Print a warning and replace the asset with a placeholder one
+    Placeholder,
+}
+
+impl MissingAssetPolicy {
+    pub fn from_name(policy: &str) -> MissingAssetPolicy {
+        match policy {
+            "error" => MissingAssetPolicy::Error,
+            "placeholder" => MissingAssetPolicy::Placeholder,
+            _ => panic!("Unknown missing assets policy: {} (expected \"error\" or \"placeholder\")", policy),
+        }
+    }
+    // Called whenever an asset fails to load: either the error to abort with or a warning that a placeholder is being used
+    pub fn check(&self, kind: &str, filename: &str, error: &dyn std::fmt::Display) -> Result<(), String> {
+        match self {
+            MissingAssetPolicy::Error => Err(format!("Failed to load {} from file {}: {}", kind, filename, error)),
+            MissingAssetPolicy::Placeholder => {
+                println!("Warning: failed to load {} from file {} ({}), using a placeholder instead", kind, filename, error);
+                tracing::warn!(kind, filename, error = %error, "asset replaced by a placeholder");
+                Ok(())
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CliArgs {
+    pub config_file: String,
+    pub missing_assets: MissingAssetPolicy,
+    pub watch: bool,
+    pub benchmark: bool,
+    pub audit: bool,
+    // Prints the BVH traversal statistics of every render
+    pub bvh_stats: bool,
+    // Traces the non-finite samples again and prints where they came from
+    pub nan_report: bool,
+    // Worker threads of the render, overriding the threads of the constants
+    pub threads: Option<usize>,
+    // Lowest priority for the render threads, with --nice, which also rest for --nice-pause milliseconds after every tile
+    pub nice: bool,
+    pub nice_pause: Option<u64>,
+    // Images to compare, with the "compare <reference> <test>" subcommand
+    pub compare: Option<(String, String)>,
+    // Directory of the reference renders, with the "regression <dir>" subcommand
+    pub regression: Option<String>,
+    pub update_references: bool,
+    // Values of the scene config replaced with --set key.path=value, in order
+    pub overrides: Vec<(String, String)>,
+    // Renders one image per combination of the sweep block of the config
+    pub sweep: bool,
+    // Ranks the candidate positions of the sources of the placement block by the coverage of its region
+    pub placement: bool,
+    // Renders the power grid instead of the image, one per floor of the powerGrid block
+    pub power_grid: bool,
+    // Draws the silhouettes and creases of the scene to the output instead of rendering it
+    pub line_art: bool,
+    // Saves the texel density view of the scene to the output and the UV layouts of its meshes next to it instead of rendering it
+    pub uv_inspect: bool,
+    pub output: String,
+    // Directories, zip archives and base URLs the assets are read from before the working directory, the last one first
+    pub assets: Vec<String>,
+    // Directory the giant textures are split into tiles in, the temporary one if none
+    pub tile_cache: Option<String>,
+    // Scene and archive, with the "pack <scene> <archive>" subcommand
+    pub pack: Option<(String, String)>,
+    // Scene, points and CSV matrix, with the "visibility <scene> <points> <matrix>" subcommand
+    pub visibility: Option<(String, String, String)>,
+    // Scene, points and CSV matrix, with the "coverage <scene> <points> <matrix>" subcommand
+    pub coverage: Option<(String, String, String)>,
+    // Scene and voxel grid, with the "voxelize <scene> <grid>" subcommand
+    pub voxelize: Option<(String, String)>,
+    // Scene and output directory, with the "export-gpu <scene> <directory>" subcommand
+    pub export_gpu: Option<(String, String)>,
+    // Seed and output file of the random scene, with the "export-scene <seed> <file>" subcommand
+    pub export_scene: Option<(u64, String)>,
+    // Material file rendered on the shader ball to the output, with the "matpreview <material>" subcommand
+    pub matpreview: Option<String>,
+    // Pixel whose paths are traced and printed instead of rendering, with --debug-pixel x y, and how many paths
+    pub debug_pixel: Option<(u32, u32)>,
+    pub debug_paths: u32,
+    // Pixel whose object is printed instead of rendering, with --pick x y
+    pub pick: Option<(u32, u32)>,
+    // JSON lines file the structured events of the run are appended to, {frame} standing for the frame of the constants
+    pub log_file: Option<String>,
+    // Prints the settings of the scene with the defaults and overrides applied instead of rendering, with --print-config
+    pub print_config: bool,
+    // Renders a preview at 1/N of the resolution and of the samples, with --preview-scale N, saved next to the output as <output>_preview
+    pub preview_scale: Option<u32>,
+}
+
+impl Default for CliArgs {
+    fn default() -> Self {
+        Self {
+            config_file: "".to_string(),
+            missing_assets: MissingAssetPolicy::Error,
+            watch: false,
+            benchmark: false,
+            audit: false,
+            bvh_stats: false,
+            nan_report: false,
+            threads: None,
+            nice: false,
+            nice_pause: None,
+            compare: None,
+            regression: None,
+            update_references: false,
+            overrides: Vec::new(),
+            sweep: false,
+            placement: false,
+            power_grid: false,
+            line_art: false,
+            uv_inspect: false,
+            output: "test.png".to_string(),
+            assets: Vec::new(),
+            tile_cache: None,
+            pack: None,
+            visibility: None,
+            coverage: None,
+            voxelize: None,
+            export_gpu: None,
+            export_scene: None,
+            matpreview: None,
+            debug_pixel: None,
+            debug_paths: 8,
+            pick: None,
+            log_file: None,
+            print_config: false,
+            preview_scale: None,
+        }
+    }
+}
+
+impl CliArgs {
+    pub fn parse(arguments: Vec<String>) -> CliArgs {
+        let mut cli_args: CliArgs = CliArgs::default();
+        let mut arguments = arguments.into_iter();
+        while let Some(arg) = arguments.next() {
+            match arg.as_str() {
+                "--missing-assets" => {
+                    let policy: String = arguments.next().expect("Missing value for --missing-assets");
+                    cli_args.missing_assets = MissingAssetPolicy::from_name(&policy);
+                },
+                "--watch" => cli_args.watch = true,
+                "--benchmark" => cli_args.benchmark = true,
+                "--audit" => cli_args.audit = true,
+                "--bvh-stats" => cli_args.bvh_stats = true,
+                "--nan-report" => cli_args.nan_report = true,
+                "--nice" => cli_args.nice = true,
+                "--nice-pause" => {
+                    cli_args.nice = true;
+                    cli_args.nice_pause = Some(arguments.next().and_then(|value| value.parse().ok()).expect("Missing milliseconds for --nice-pause"));
+                },
+                "--threads" => cli_args.threads = Some(arguments.next().and_then(|value| value.parse().ok()).expect("Missing number of threads for --threads")),
+                "--update-references" => cli_args.update_references = true,
+                "--sweep" => cli_args.sweep = true,
+                "--placement" => cli_args.placement = true,
+                "--power-grid" => cli_args.power_grid = true,
+                "--line-art" => cli_args.line_art = true,
+                "--uv-inspect" => cli_args.uv_inspect = true,
+                "--output" => cli_args.output = arguments.next().expect("Missing file name for --output"),
+                "--assets" => cli_args.assets.push(arguments.next().expect("Missing directory, archive or URL for --assets")),
+                "--debug-pixel" => {
+                    let mut coordinate = || arguments.next().and_then(|value| value.parse::<u32>().ok()).expect("Missing pixel x y for --debug-pixel");
+                    cli_args.debug_pixel = Some((coordinate(), coordinate()));
+                },
+                "--pick" => {
+                    let mut coordinate = || arguments.next().and_then(|value| value.parse::<u32>().ok()).expect("Missing pixel x y for --pick");
+                    cli_args.pick = Some((coordinate(), coordinate()));
+                },
+                "--tile-cache" => cli_args.tile_cache = Some(arguments.next().expect("Missing directory for --tile-cache")),
+                "--log-file" => cli_args.log_file = Some(arguments.next().expect("Missing file name for --log-file")),
+                "--print-config" => cli_args.print_config = true,
+                "--preview-scale" => cli_args.preview_scale = Some(arguments.next().and_then(|value| value.parse().ok()).filter(|scale| *scale > 0).expect("Missing positive scale for --preview-scale")),
+                "--debug-paths" => cli_args.debug_paths = arguments.next().and_then(|value| value.parse().ok()).expect("Missing number of paths for --debug-paths"),
+                "--set" => cli_args.overrides.push(parse_override(&arguments.next().expect("Missing key=value for --set"))),
+                "compare" if cli_args.config_file.is_empty() && cli_args.compare.is_none() => {
+                    let reference: String = arguments.next().expect("Missing reference image for compare");
+                    let test: String = arguments.next().expect("Missing test image for compare");
+                    cli_args.compare = Some((reference, test));
+                },
+                "pack" if cli_args.config_file.is_empty() && cli_args.pack.is_none() => {
+                    let scene: String = arguments.next().expect("Missing scene for pack");
+                    let archive: String = arguments.next().expect("Missing archive for pack");
+                    cli_args.pack = Some((scene, archive));
+                },
+                "visibility" if cli_args.config_file.is_empty() && cli_args.visibility.is_none() => {
+                    let scene: String = arguments.next().expect("Missing scene for visibility");
+                    let points: String = arguments.next().expect("Missing points file for visibility");
+                    let matrix: String = arguments.next().expect("Missing CSV file for visibility");
+                    cli_args.visibility = Some((scene, points, matrix));
+                },
+                "coverage" if cli_args.config_file.is_empty() && cli_args.coverage.is_none() => {
+                    let scene: String = arguments.next().expect("Missing scene for coverage");
+                    let points: String = arguments.next().expect("Missing points file for coverage");
+                    let matrix: String = arguments.next().expect("Missing CSV file for coverage");
+                    cli_args.coverage = Some((scene, points, matrix));
+                },
+                "voxelize" if cli_args.config_file.is_empty() && cli_args.voxelize.is_none() => {
+                    let scene: String = arguments.next().expect("Missing scene for voxelize");
+                    let grid: String = arguments.next().expect("Missing grid file for voxelize");
+                    cli_args.voxelize = Some((scene, grid));
+                },
+                "export-gpu" if cli_args.config_file.is_empty() && cli_args.export_gpu.is_none() => {
+                    let scene: String = arguments.next().expect("Missing scene for export-gpu");
+                    let directory: String = arguments.next().expect("Missing output directory for export-gpu");
+                    cli_args.export_gpu = Some((scene, directory));
+                },
+                "export-scene" if cli_args.config_file.is_empty() && cli_args.export_scene.is_none() => {
+                    let seed: u64 = arguments.next().and_then(|seed| seed.parse().ok()).expect("Missing seed for export-scene");
+                    let filename: String = arguments.next().expect("Missing output file for export-scene");
+                    cli_args.export_scene = Some((seed, filename));
+                },
+                "matpreview" if cli_args.config_file.is_empty() && cli_args.matpreview.is_none() => {
+                    cli_args.matpreview = Some(arguments.next().expect("Missing material file for matpreview"));
+                },
+                "regression" if cli_args.config_file.is_empty() && cli_args.regression.is_none() => {
+                    cli_args.regression = Some(arguments.next().expect("Missing references directory for regression"));
+                },
+                _ if arg.starts_with("--") => println!("Ignoring unknown argument: {}", arg),
+                _ => if cli_args.config_file.is_empty() { cli_args.config_file = arg; },
+            }
+        }
+        if cli_args.preview_scale.is_some() { cli_args.output = preview_output(&cli_args.output); }
+        cli_args
+    }
+}
+
+// The file a preview is saved to, next to the final output so that it isn't overwritten: render.png gives render_preview.png
+pub fn preview_output(output: &str) -> String {
+    let path: &Path = Path::new(output);
+    let preview: String = format!("{}_preview", path.file_stem().unwrap_or_default().to_string_lossy());
+    match path.extension() {
+        Some(extension) => path.with_file_name(format!("{}.{}", preview, extension.to_string_lossy())),
+        None => path.with_file_name(preview),
+    }.to_string_lossy().to_string()
+}
+
+// Command line arguments, the defaults under the tests, whose command line is the one of the test harness
+lazy_static! { pub static ref ARGS: CliArgs = if cfg!(test) { CliArgs::default() } else { CliArgs::parse(args().skip(1).collect()) }; }
+
+/****************** Config overrides ******************/
+// Overrides from the GBRT_SET environment variable, separated by semicolons, come before the --set ones
+pub const OVERRIDES_VARIABLE: &str = "GBRT_SET";
+
+pub fn parse_override(assignment: &str) -> (String, String) {
+    match assignment.split_once('=') {
+        Some((path, value)) if !path.is_empty() => (path.trim().to_string(), value.trim().to_string()),
+        _ => panic!("Invalid override: {}, expected key.path=value", assignment),
+    }
+}
+
+pub fn overrides() -> Vec<(String, String)> {
+    let mut overrides: Vec<(String, String)> = match std::env::var(OVERRIDES_VARIABLE) {
+        Ok(assignments) => assignments.split(';').filter(|assignment| !assignment.trim().is_empty()).map(parse_override).collect(),
+        Err(_) => Vec::new(),
+    };
+    overrides.extend(ARGS.overrides.iter().cloned());
+    overrides
+}
+
+// Replaces the value at the dotted path of the document, numbers indexing into lists (world.0.radius)
+// The value is parsed as YAML, so lists and strings work too, and missing keys are added along the way
+pub fn apply_override(doc: &mut Yaml, path: &str, value: &str) {
+    let mut value: Yaml = YamlLoader::load_from_str(value).ok().and_then(|mut docs| docs.pop()).unwrap_or(Yaml::String(value.to_string()));
+    let mut node: &mut Yaml = doc;
+    for key in path.split('.') {
+        node = match node {
+            Yaml::Hash(hash) => hash.entry(Yaml::String(key.to_string())).or_insert(Yaml::Hash(Default::default())),
+            Yaml::Array(array) => {
+                let idx: usize = key.parse().unwrap_or_else(|_| panic!("Invalid override {}: {} is not an index of a list", path, key));
+                let len: usize = array.len();
+                array.get_mut(idx).unwrap_or_else(|| panic!("Invalid override {}: index {} out of a list of {}", path, idx, len))
+            },
+            _ => panic!("Invalid override {}: {} is not inside of a map or a list", path, key),
+        };
+    }
+    // Whole numbers replacing decimal ones stay decimal, for the readers of the objects that only take reals (the constants take both)
+    if let (Yaml::Real(_), Yaml::Integer(integer)) = (&*node, &value) { value = Yaml::Real(format!("{:.1}", *integer as f64)); }
+    *node = value;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_defaults() {
+        let cli_args: CliArgs = CliArgs::parse(vec![]);
+        assert_eq!(cli_args.config_file, "");
+        assert_eq!(cli_args.missing_assets, MissingAssetPolicy::Error);
+        assert!(!cli_args.watch);
+        assert!(!cli_args.benchmark);
+        assert!(!cli_args.audit);
+        assert!(!cli_args.bvh_stats);
+        assert!(!cli_args.nan_report);
+        assert!(!cli_args.sweep);
+        assert!(!cli_args.placement);
+        assert_eq!(cli_args.output, "test.png");
+    }
+    #[test]
+    fn test_parse_missing_assets() {
+        let cli_args: CliArgs = CliArgs::parse(vec!["--missing-assets".to_string(), "placeholder".to_string(), "configs/jet.yaml".to_string(), "--watch".to_string(), "--bvh-stats".to_string()]);
+        assert_eq!(cli_args.config_file, "configs/jet.yaml");
+        assert_eq!(cli_args.missing_assets, MissingAssetPolicy::Placeholder);
+        assert!(cli_args.watch);
+        assert!(cli_args.bvh_stats);
+        let cli_args: CliArgs = CliArgs::parse(vec!["scene.yaml".to_string(), "--assets".to_string(), "textures.zip".to_string(), "--assets".to_string(), "models".to_string()]);
+        assert_eq!(cli_args.assets, vec!["textures.zip".to_string(), "models".to_string()]);
+        let cli_args: CliArgs = CliArgs::parse(["scene.yaml", "--debug-pixel", "120", "45", "--debug-paths", "3", "--nan-report", "--threads", "4"].map(String::from).to_vec());
+        assert_eq!((cli_args.config_file.as_str(), cli_args.debug_pixel, cli_args.debug_paths, cli_args.nan_report), ("scene.yaml", Some((120, 45)), 3, true));
+        assert_eq!(cli_args.threads, Some(4));
+        assert_eq!(CliArgs::parse(["scene.yaml", "--pick", "10", "20"].map(String::from).to_vec()).pick, Some((10, 20)));
+        assert_eq!(CliArgs::parse(["scene.yaml", "--log-file", "render_{frame}.log"].map(String::from).to_vec()).log_file.as_deref(), Some("render_{frame}.log"));
+        assert!(CliArgs::parse(["scene.yaml", "--print-config"].map(String::from).to_vec()).print_config);
+        let cli_args: CliArgs = CliArgs::parse(["scene.yaml", "--preview-scale", "4", "--output", "renders/shot.png"].map(String::from).to_vec());
+        assert_eq!((cli_args.preview_scale, cli_args.output.as_str()), (Some(4), "renders/shot_preview.png"));
+        let cli_args: CliArgs = CliArgs::parse(["--nice-pause", "20", "scene.yaml"].map(String::from).to_vec());
+        assert_eq!((cli_args.nice, cli_args.nice_pause, cli_args.config_file.as_str()), (true, Some(20), "scene.yaml"));
+        let cli_args: CliArgs = CliArgs::parse(["scene.yaml", "--placement"].map(String::from).to_vec());
+        assert!(cli_args.placement && cli_args.config_file == "scene.yaml");
+        let cli_args: CliArgs = CliArgs::parse(["--power-grid", "building.yaml"].map(String::from).to_vec());
+        assert!(cli_args.power_grid && !cli_args.placement && cli_args.config_file == "building.yaml");
+        let cli_args: CliArgs = CliArgs::parse(["part.yaml", "--line-art", "--output", "part.svg"].map(String::from).to_vec());
+        assert!(cli_args.line_art && !cli_args.power_grid && cli_args.output == "part.svg");
+        let cli_args: CliArgs = CliArgs::parse(["--uv-inspect", "asset.yaml"].map(String::from).to_vec());
+        assert!(cli_args.uv_inspect && !cli_args.line_art && cli_args.config_file == "asset.yaml");
+    }
+    #[test]
+    fn test_parse_subcommands() {
+        let cli_args: CliArgs = CliArgs::parse(vec!["compare".to_string(), "a.png".to_string(), "b.png".to_string()]);
+        assert_eq!(cli_args.compare, Some(("a.png".to_string(), "b.png".to_string())));
+        assert_eq!(cli_args.config_file, "");
+        let cli_args: CliArgs = CliArgs::parse(vec!["regression".to_string(), "tests/references".to_string(), "--update-references".to_string()]);
+        assert_eq!(cli_args.regression, Some("tests/references".to_string()));
+        assert!(cli_args.update_references);
+        let cli_args: CliArgs = CliArgs::parse(vec!["pack".to_string(), "scene.yaml".to_string(), "scene.gbrt".to_string()]);
+        assert_eq!(cli_args.pack, Some(("scene.yaml".to_string(), "scene.gbrt".to_string())));
+        let cli_args: CliArgs = CliArgs::parse(vec!["visibility".to_string(), "house.yaml".to_string(), "antennas.yaml".to_string(), "matrix.csv".to_string()]);
+        assert_eq!(cli_args.visibility, Some(("house.yaml".to_string(), "antennas.yaml".to_string(), "matrix.csv".to_string())));
+        let cli_args: CliArgs = CliArgs::parse(vec!["voxelize".to_string(), "house.yaml".to_string(), "house.vox".to_string()]);
+        assert_eq!(cli_args.voxelize, Some(("house.yaml".to_string(), "house.vox".to_string())));
+        let cli_args: CliArgs = CliArgs::parse(vec!["export-gpu".to_string(), "house.yaml".to_string(), "house_gpu".to_string()]);
+        assert_eq!(cli_args.export_gpu, Some(("house.yaml".to_string(), "house_gpu".to_string())));
+        assert_eq!(CliArgs::parse(["export-scene", "42", "spheres.yaml"].map(String::from).to_vec()).export_scene, Some((42, "spheres.yaml".to_string())));
+        let cli_args: CliArgs = CliArgs::parse(["matpreview", "gold.yaml", "--output", "gold.png"].map(String::from).to_vec());
+        assert_eq!((cli_args.matpreview.as_deref(), cli_args.output.as_str(), cli_args.config_file.as_str()), (Some("gold.yaml"), "gold.png", ""));
+        let cli_args: CliArgs = CliArgs::parse(vec!["coverage".to_string(), "house.yaml".to_string(), "antennas.yaml".to_string(), "loss.csv".to_string()]);
+        assert_eq!(cli_args.coverage, Some(("house.yaml".to_string(), "antennas.yaml".to_string(), "loss.csv".to_string())));
+    }
+    #[test]
+    fn test_overrides() {
+        let cli_args: CliArgs = CliArgs::parse(vec!["scene.yaml".to_string(), "--set".to_string(), "camera.vfov=35".to_string()]);
+        assert_eq!(cli_args.overrides, vec![("camera.vfov".to_string(), "35".to_string())]);
+        let mut doc: Yaml = YamlLoader::load_from_str("{camera: {vfov: 60.0}, world: [{radius: 1.0}]}").unwrap().remove(0);
+        apply_override(&mut doc, "camera.vfov", "35");
+        apply_override(&mut doc, "world.0.center", "[0.0, 1.0, 0.0]");
+        apply_override(&mut doc, "constants.samplesPerPixel", "16");
+        assert_eq!(doc["camera"]["vfov"].as_f64(), Some(35.0));
+        assert_eq!(doc["world"][0]["center"][1].as_f64(), Some(1.0));
+        assert_eq!(doc["constants"]["samplesPerPixel"].as_i64(), Some(16));
+        // A whole number for a real constant missing from the scene is still read as a real
+        for (key, value) in [("width", "8"), ("height", "8"), ("maxDepth", "4"), ("minDepth", "1"), ("environmentIntensity", "2"), ("powerRenderCenter", "[1, 0, 0]")] {
+            apply_override(&mut doc, &format!("constants.{}", key), value);
+        }
+        let consts: crate::utility::Constants = crate::parser::parse_constants(&doc);
+        assert_eq!((consts.environment_intensity, consts.power_render_center.x), (Some(2.0), 1.0));
+    }
+}
@@ -0,0 +1,123 @@
+// Author: Giulian Biolo, github.com/giulianbiolo
+// Date: 16/10/2026
+// Description: This file implements the clipping planes, cutting objects open for cutaway and section renders
+
+use std::sync::Arc;
+
+use glam::Vec3A;
+
+use crate::hit_record::HitRecord;
+use crate::hittable_list::Hittable;
+use crate::light_sampler::LightEstimate;
+use crate::material::Material;
+use crate::plane::Plane;
+use crate::point3::Point3;
+use crate::ray::Ray;
+
+
+// Removes everything on the side of the plane the normal points to
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ClipPlane {
+    pub point: Point3,
+    pub normal: Vec3A,
+}
+
+impl ClipPlane {
+    pub fn new(point: Point3, normal: Vec3A) -> ClipPlane { ClipPlane { point, normal: normal.normalize() } }
+}
+
+/****************** Clipped ******************/
+// An object cut by clipping planes, keeping only the part behind all of them
+// With a cap material, the cuts of closed objects are filled with it instead of showing the inside of the object
+pub struct Clipped {
+    object: Arc<dyn Hittable + Send + Sync>,
+    planes: Vec<ClipPlane>,
+    // One cap for each plane, empty when the cuts are left open
+    caps: Vec<Plane>,
+}
+
+impl Clipped {
+    pub fn new(object: Arc<dyn Hittable + Send + Sync>, planes: Vec<ClipPlane>, cap: Option<Box<dyn Material>>) -> Clipped {
+        let caps: Vec<Plane> = match cap {
+            Some(cap) => planes.iter().map(|plane| Plane::new(plane.point, plane.normal, cap.clone())).collect(),
+            None => Vec::new(),
+        };
+        Clipped { object, planes, caps }
+    }
+    // The span of the ray in the kept region, which is convex, and the planes it enters and leaves it through
+    fn _kept_span(&self, ray: &Ray) -> Option<(f32, Option<usize>, f32, Option<usize>)> {
+        let (mut enter, mut enter_plane, mut exit, mut exit_plane) = (f32::NEG_INFINITY, None, f32::INFINITY, None);
+        for (idx, plane) in self.planes.iter().enumerate() {
+            let distance: f32 = (ray.origin() - plane.point).dot(plane.normal);
+            let denominator: f32 = ray.direction().dot(plane.normal);
+            if denominator.abs() < 1e-8 {
+                if distance > 0.0 { return None; } // Runs parallel to the plane on the removed side
+                continue;
+            }
+            let t: f32 = -distance / denominator;
+            if denominator < 0.0 && t > enter { (enter, enter_plane) = (t, Some(idx)); }
+            if denominator > 0.0 && t < exit { (exit, exit_plane) = (t, Some(idx)); }
+        }
+        if enter > exit { return None; }
+        Some((enter, enter_plane, exit, exit_plane))
+    }
+    // Whether the point of the ray at t is inside of the object, as the next surface along the ray is seen from its back
+    fn _inside(&self, ray: &Ray, t: f32) -> bool { self.object.hit(ray, t, f32::INFINITY).is_some_and(|rec| !rec.front_face) }
+    fn _cap(&self, ray: &Ray, plane: Option<usize>, t: f32, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        let plane: usize = plane?;
+        if self.caps.is_empty() || t < t_min || t > t_max || !self._inside(ray, t) { return None; }
+        self.caps[plane].hit(ray, t_min, t_max)
+    }
+}
+
+impl Hittable for Clipped {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        let (enter, enter_plane, exit, exit_plane) = self._kept_span(ray)?;
+        let (lo, hi) = (t_min.max(enter), t_max.min(exit));
+        if lo > hi { return None; }
+        // Entering the kept region inside of the object the ray hits the cap first, otherwise the surface in the span
+        if let Some(rec) = self._cap(ray, enter_plane, enter, t_min, t_max) { return Some(rec); }
+        if let Some(rec) = self.object.hit(ray, lo, hi) { return Some(rec); }
+        // A ray leaving the kept region from inside of the object hits the back of the cap
+        self._cap(ray, exit_plane, exit, t_min, t_max)
+    }
+    fn is_light(&self) -> bool { self.object.is_light() }
+    fn light_estimate(&self) -> Option<LightEstimate> { self.object.light_estimate() }
+    fn pdf_value(&self, origin: &Point3, v: &Vec3A) -> f32 { self.object.pdf_value(origin, v) }
+    fn random(&self, origin: &Point3) -> Vec3A { self.object.random(origin) }
+    fn wrapped(&self) -> Option<&(dyn Hittable + Send + Sync)> { Some(&*self.object) }
+    fn wrapped_mut(&mut self) -> Option<&mut (dyn Hittable + Send + Sync + 'static)> { Arc::get_mut(&mut self.object) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::Color;
+    use crate::material::Lambertian;
+    use crate::sphere::Sphere;
+    use crate::utility;
+
+    fn _clipped_sphere(cap: bool) -> Clipped {
+        let sphere: Arc<dyn Hittable + Send + Sync> = Arc::new(Sphere::new(Point3::ZERO, 1.0, Box::new(Lambertian::new(Color::ONE)), 0));
+        let cap: Option<Box<dyn Material>> = if cap { Some(Box::new(Lambertian::new(Color::ZERO))) } else { None };
+        // Cuts away the half of the sphere facing the camera
+        Clipped::new(sphere, vec![ClipPlane::new(Point3::ZERO, -Vec3A::Z)], cap)
+    }
+
+    #[test]
+    fn test_clipping() {
+        let ray: Ray = Ray::new(Point3::new(0.0, 0.0, -5.0), Vec3A::Z);
+        // Without a cap the ray goes through the cut and sees the inside of the back half
+        let (open, capped) = (_clipped_sphere(false), _clipped_sphere(true));
+        let rec: HitRecord = open.hit(&ray, 0.001, f32::INFINITY).unwrap();
+        assert!(!rec.front_face && (rec.t - 6.0).abs() < utility::EPSILON);
+        // With it, the cut is closed by the cap, facing the camera
+        let rec: HitRecord = capped.hit(&ray, 0.001, f32::INFINITY).unwrap();
+        assert!(rec.front_face && (rec.t - 5.0).abs() < utility::EPSILON && (rec.normal - -Vec3A::Z).length() < utility::EPSILON);
+        // Beside the sphere there is nothing to cap
+        assert!(capped.hit(&Ray::new(Point3::new(1.5, 0.0, -5.0), Vec3A::Z), 0.001, f32::INFINITY).is_none());
+        // Rays on the kept side see the back half as usual
+        let rec: HitRecord = capped.hit(&Ray::new(Point3::new(0.0, 0.0, 5.0), -Vec3A::Z), 0.001, f32::INFINITY).unwrap();
+        assert!(rec.front_face && (rec.t - 4.0).abs() < utility::EPSILON);
+    }
+}
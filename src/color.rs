@@ -20,6 +20,32 @@ pub fn to_rgb(pixel_color: Color, samples_per_pixel: f32) -> image::Rgb<u8> {
     ])
 }
 
+// Returns the (linear, normalized to a maximum of 1) color of a black body at the given temperature in Kelvin
+pub fn blackbody(temperature: f32) -> Color {
+    // Planck's law integrated against the CIE 1931 color matching functions, using the multi-lobe gaussian fit by Wyman et al.
+    let gaussian = |lambda: f64, mu: f64, sigma1: f64, sigma2: f64| {
+        let t: f64 = (lambda - mu) / if lambda < mu { sigma1 } else { sigma2 };
+        (-0.5 * t * t).exp()
+    };
+    let temperature: f64 = temperature.max(1.0) as f64;
+    let (mut x, mut y, mut z) = (0.0_f64, 0.0_f64, 0.0_f64);
+    for lambda in (380..=780).step_by(5) {
+        let lambda: f64 = lambda as f64;
+        let lambda_um: f64 = lambda / 1000.0;
+        let radiance: f64 = 1.0 / (lambda_um.powi(5) * ((14387.77 / (lambda_um * temperature)).exp() - 1.0));
+        x += radiance * (1.056 * gaussian(lambda, 599.8, 37.9, 31.0) + 0.362 * gaussian(lambda, 442.0, 16.0, 26.7) - 0.065 * gaussian(lambda, 501.1, 20.4, 26.2));
+        y += radiance * (0.821 * gaussian(lambda, 568.8, 46.9, 40.5) + 0.286 * gaussian(lambda, 530.9, 16.3, 31.1));
+        z += radiance * (1.217 * gaussian(lambda, 437.0, 11.8, 36.0) + 0.681 * gaussian(lambda, 459.0, 26.0, 13.8));
+    }
+    // XYZ to linear sRGB
+    let rgb: Color = Color::new(
+        (3.2406 * x - 1.5372 * y - 0.4986 * z) as f32,
+        (-0.9689 * x + 1.8758 * y + 0.0415 * z) as f32,
+        (0.0557 * x - 0.2040 * y + 1.0570 * z) as f32,
+    ).max(Color::ZERO);
+    if rgb.max_element() > 0.0 { rgb / rgb.max_element() } else { Color::ZERO }
+}
+
 
 #[cfg(test)]
 mod tests {
@@ -31,4 +57,13 @@ mod tests {
         to_rgb(c, 1.0);
         Ok(())
     }
+    #[test]
+    fn test_blackbody() {
+        let candle: Color = blackbody(1900.0);
+        assert!(candle.x > candle.y && candle.y > candle.z);
+        let daylight: Color = blackbody(6500.0);
+        assert!(daylight.min_element() > 0.8);
+        let blue_sky: Color = blackbody(15000.0);
+        assert!(blue_sky.z > blue_sky.y && blue_sky.y > blue_sky.x);
+    }
 }
@@ -0,0 +1,225 @@
+// Author: Giulian Biolo, github.com/giulianbiolo
+// Date: 16/10/2026
+// Description: This file implements the comparison of two images, with the RMSE and the LDR-FLIP perceptual metric
+
+use image::RgbImage;
+
+use crate::color::Color;
+
+
+// FLIP parameters, for a 4K monitor 0.7m wide seen from 0.7m away
+const PIXELS_PER_DEGREE: f32 = 67.0;
+const QC: f32 = 0.7;
+const QF: f32 = 0.5;
+const PC: f32 = 0.4;
+const PT: f32 = 0.95;
+
+// A single channel image, stored by rows
+struct Plane {
+    width: usize,
+    height: usize,
+    data: Vec<f32>,
+}
+
+impl Plane {
+    fn new(width: usize, height: usize) -> Plane { Plane { width, height, data: vec![0.0; width * height] } }
+    fn get(&self, x: isize, y: isize) -> f32 {
+        // Clamp to the border
+        let x: usize = x.clamp(0, self.width as isize - 1) as usize;
+        let y: usize = y.clamp(0, self.height as isize - 1) as usize;
+        self.data[y * self.width + x]
+    }
+    // Separable convolution, first along x and then along y
+    fn convolve(&self, kernel_x: &[f32], kernel_y: &[f32]) -> Plane {
+        let (rx, ry) = ((kernel_x.len() / 2) as isize, (kernel_y.len() / 2) as isize);
+        let mut horizontal: Plane = Plane::new(self.width, self.height);
+        for y in 0..self.height {
+            for x in 0..self.width {
+                horizontal.data[y * self.width + x] = kernel_x.iter().enumerate().map(|(i, k)| k * self.get(x as isize + i as isize - rx, y as isize)).sum();
+            }
+        }
+        let mut result: Plane = Plane::new(self.width, self.height);
+        for y in 0..self.height {
+            for x in 0..self.width {
+                result.data[y * self.width + x] = kernel_y.iter().enumerate().map(|(i, k)| k * horizontal.get(x as isize, y as isize + i as isize - ry)).sum();
+            }
+        }
+        result
+    }
+}
+
+// Root mean squared error of the sRGB values, in [0, 1]
+pub fn rmse(reference: &RgbImage, test: &RgbImage) -> f32 {
+    _check_sizes(reference, test);
+    let sum: f64 = reference.as_raw().iter().zip(test.as_raw().iter())
+        .map(|(a, b)| ((*a as f64 - *b as f64) / 255.0).powi(2))
+        .sum();
+    (sum / reference.as_raw().len() as f64).sqrt() as f32
+}
+
+// Mean of the LDR-FLIP error map (Andersson et al. 2020), 0 for identical images and up to 1
+pub fn flip(reference: &RgbImage, test: &RgbImage) -> f32 {
+    let error_map: Vec<f32> = flip_error_map(reference, test);
+    error_map.iter().map(|error| *error as f64).sum::<f64>() as f32 / error_map.len() as f32
+}
+
+pub fn flip_error_map(reference: &RgbImage, test: &RgbImage) -> Vec<f32> {
+    _check_sizes(reference, test);
+    let (reference_ycxcz, test_ycxcz) = (_to_ycxcz(reference), _to_ycxcz(test));
+    // Color pipeline: filter with the contrast sensitivity functions, then compare in the Hunt adjusted L*a*b* space
+    let (reference_lab, test_lab) = (_filtered_lab(&reference_ycxcz), _filtered_lab(&test_ycxcz));
+    let max_color_error: f32 = _hyab(_hunt(_linear_rgb_to_lab(Color::new(0.0, 1.0, 0.0))), _hunt(_linear_rgb_to_lab(Color::new(0.0, 0.0, 1.0)))).powf(QC);
+    // Feature pipeline: edges and points detected on the achromatic channel
+    let (reference_edges, reference_points) = _features(&reference_ycxcz[0]);
+    let (test_edges, test_points) = _features(&test_ycxcz[0]);
+    (0..reference_lab.len()).map(|idx| {
+        let color_error: f32 = _hyab(_hunt(reference_lab[idx]), _hunt(test_lab[idx])).powf(QC);
+        let color_error: f32 = if color_error < PC * max_color_error {
+            PT / (PC * max_color_error) * color_error
+        } else { PT + (color_error - PC * max_color_error) / (max_color_error - PC * max_color_error) * (1.0 - PT) };
+        let feature_error: f32 = (reference_edges[idx] - test_edges[idx]).abs().max((reference_points[idx] - test_points[idx]).abs());
+        let feature_error: f32 = (feature_error / 2.0_f32.sqrt()).powf(QF);
+        color_error.powf(1.0 - feature_error)
+    }).collect()
+}
+
+fn _check_sizes(reference: &RgbImage, test: &RgbImage) {
+    if reference.dimensions() != test.dimensions() {
+        panic!("Can't compare images of different sizes: {:?} and {:?}", reference.dimensions(), test.dimensions());
+    }
+}
+
+fn _srgb_to_linear(value: f32) -> f32 { if value <= 0.04045 { value / 12.92 } else { ((value + 0.055) / 1.055).powf(2.4) } }
+
+fn _linear_rgb_to_xyz(rgb: Color) -> Color {
+    Color::new(
+        0.4124564 * rgb.x + 0.3575761 * rgb.y + 0.1804375 * rgb.z,
+        0.2126729 * rgb.x + 0.7151522 * rgb.y + 0.0721750 * rgb.z,
+        0.0193339 * rgb.x + 0.119192 * rgb.y + 0.9503041 * rgb.z,
+    )
+}
+
+fn _xyz_to_linear_rgb(xyz: Color) -> Color {
+    Color::new(
+        3.2404542 * xyz.x - 1.5371385 * xyz.y - 0.4985314 * xyz.z,
+        -0.969266 * xyz.x + 1.8760108 * xyz.y + 0.0415560 * xyz.z,
+        0.0556434 * xyz.x - 0.2040259 * xyz.y + 1.0572252 * xyz.z,
+    )
+}
+
+// The D65 reference white, in XYZ
+fn _white() -> Color { _linear_rgb_to_xyz(Color::ONE) }
+
+fn _xyz_to_ycxcz(xyz: Color) -> Color {
+    let xyz: Color = xyz / _white();
+    Color::new(116.0 * xyz.y - 16.0, 500.0 * (xyz.x - xyz.y), 200.0 * (xyz.y - xyz.z))
+}
+
+fn _ycxcz_to_xyz(ycxcz: Color) -> Color {
+    let y: f32 = (ycxcz.x + 16.0) / 116.0;
+    Color::new(y + ycxcz.y / 500.0, y, y - ycxcz.z / 200.0) * _white()
+}
+
+fn _xyz_to_lab(xyz: Color) -> Color {
+    let delta: f32 = 6.0 / 29.0;
+    let f = |t: f32| if t > delta.powi(3) { t.cbrt() } else { t / (3.0 * delta * delta) + 4.0 / 29.0 };
+    let xyz: Color = xyz / _white();
+    Color::new(116.0 * f(xyz.y) - 16.0, 500.0 * (f(xyz.x) - f(xyz.y)), 200.0 * (f(xyz.y) - f(xyz.z)))
+}
+
+fn _linear_rgb_to_lab(rgb: Color) -> Color { _xyz_to_lab(_linear_rgb_to_xyz(rgb)) }
+
+fn _hunt(lab: Color) -> Color { Color::new(lab.x, 0.01 * lab.x * lab.y, 0.01 * lab.x * lab.z) }
+
+fn _hyab(a: Color, b: Color) -> f32 { (a.x - b.x).abs() + ((a.y - b.y).powi(2) + (a.z - b.z).powi(2)).sqrt() }
+
+fn _to_ycxcz(image: &RgbImage) -> [Plane; 3] {
+    let (width, height) = (image.width() as usize, image.height() as usize);
+    let mut planes: [Plane; 3] = [Plane::new(width, height), Plane::new(width, height), Plane::new(width, height)];
+    for (idx, pixel) in image.pixels().enumerate() {
+        let rgb: Color = Color::new(_srgb_to_linear(pixel[0] as f32 / 255.0), _srgb_to_linear(pixel[1] as f32 / 255.0), _srgb_to_linear(pixel[2] as f32 / 255.0));
+        let ycxcz: Color = _xyz_to_ycxcz(_linear_rgb_to_xyz(rgb));
+        for (channel, plane) in planes.iter_mut().enumerate() { plane.data[idx] = ycxcz[channel]; }
+    }
+    planes
+}
+
+// Spatial filtering of the opponent channels with the contrast sensitivity functions, as the sum of up to two gaussians
+fn _filtered_lab(ycxcz: &[Plane; 3]) -> Vec<Color> {
+    let parameters: [(f32, f32, f32, f32); 3] = [(1.0, 0.0047, 0.0, 1e-5), (1.0, 0.0053, 0.0, 1e-5), (34.1, 0.04, 13.5, 0.025)];
+    let radius: isize = (3.0 * (0.04_f32 / (2.0 * std::f32::consts::PI.powi(2))).sqrt() * PIXELS_PER_DEGREE).ceil() as isize;
+    let filtered: Vec<Plane> = ycxcz.iter().zip(parameters.iter()).map(|(plane, (a1, b1, a2, b2))| {
+        let gaussian = |b: f32| -> Vec<f32> {
+            (-radius..=radius).map(|x| (-(std::f32::consts::PI.powi(2)) * (x as f32 / PIXELS_PER_DEGREE).powi(2) / b).exp()).collect()
+        };
+        let (g1, g2) = (gaussian(*b1), gaussian(*b2));
+        // Normalize the whole 2D kernel to a unit sum, each gaussian term is separable
+        let (w1, w2) = (a1 * (std::f32::consts::PI / b1).sqrt(), a2 * (std::f32::consts::PI / b2).sqrt());
+        let total: f32 = w1 * g1.iter().sum::<f32>().powi(2) + w2 * g2.iter().sum::<f32>().powi(2);
+        let mut result: Plane = plane.convolve(&g1, &g1);
+        let second: Plane = plane.convolve(&g2, &g2);
+        for (value, other) in result.data.iter_mut().zip(second.data.iter()) { *value = (w1 * *value + w2 * other) / total; }
+        result
+    }).collect();
+    (0..filtered[0].data.len()).map(|idx| {
+        let rgb: Color = _xyz_to_linear_rgb(_ycxcz_to_xyz(Color::new(filtered[0].data[idx], filtered[1].data[idx], filtered[2].data[idx])));
+        _linear_rgb_to_lab(rgb.clamp(Color::ZERO, Color::ONE))
+    }).collect()
+}
+
+// Magnitudes of the edge and point features of the normalized achromatic channel
+fn _features(luminance: &Plane) -> (Vec<f32>, Vec<f32>) {
+    let mut normalized: Plane = Plane::new(luminance.width, luminance.height);
+    for (value, y) in normalized.data.iter_mut().zip(luminance.data.iter()) { *value = (y + 16.0) / 116.0; }
+    let sd: f32 = 0.5 * 0.082 * PIXELS_PER_DEGREE;
+    let radius: isize = (3.0 * sd).ceil() as isize;
+    let gaussian: Vec<f32> = (-radius..=radius).map(|x| (-(x * x) as f32 / (2.0 * sd * sd)).exp()).collect();
+    let gaussian_sum: f32 = gaussian.iter().sum();
+    // The positive and negative weights of the 2D kernels are normalized separately to a unit sum
+    let normalize = |kernel: Vec<f32>| -> Vec<f32> {
+        let positive: f32 = kernel.iter().filter(|k| **k > 0.0).sum::<f32>() * gaussian_sum;
+        let negative: f32 = -kernel.iter().filter(|k| **k < 0.0).sum::<f32>() * gaussian_sum;
+        kernel.iter().map(|k| if *k > 0.0 { k / positive } else if *k < 0.0 { k / negative } else { 0.0 }).collect()
+    };
+    let edge: Vec<f32> = normalize((-radius..=radius).zip(gaussian.iter()).map(|(x, g)| -x as f32 * g).collect());
+    let point: Vec<f32> = normalize((-radius..=radius).zip(gaussian.iter()).map(|(x, g)| ((x * x) as f32 / (sd * sd) - 1.0) * g).collect());
+    let magnitude = |kernel: &[f32]| -> Vec<f32> {
+        let (along_x, along_y) = (normalized.convolve(kernel, &gaussian), normalized.convolve(&gaussian, kernel));
+        along_x.data.iter().zip(along_y.data.iter()).map(|(x, y)| (x * x + y * y).sqrt()).collect()
+    };
+    (magnitude(&edge), magnitude(&point))
+}
+
+// Compares two image files and prints the metrics
+pub fn run(reference_file: &str, test_file: &str) {
+    let reference: RgbImage = image::open(reference_file).unwrap_or_else(|err| panic!("Failed to open {}: {}", reference_file, err)).to_rgb8();
+    let test: RgbImage = image::open(test_file).unwrap_or_else(|err| panic!("Failed to open {}: {}", test_file, err)).to_rgb8();
+    println!("Comparing {} (reference) with {}", reference_file, test_file);
+    println!("RMSE: {:.6}", rmse(&reference, &test));
+    println!("FLIP: {:.6}", flip(&reference, &test));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgb;
+
+    #[test]
+    fn test_rmse() {
+        let black: RgbImage = RgbImage::from_pixel(8, 8, Rgb([0, 0, 0]));
+        let gray: RgbImage = RgbImage::from_pixel(8, 8, Rgb([51, 51, 51]));
+        assert_eq!(rmse(&black, &black), 0.0);
+        assert!((rmse(&black, &gray) - 0.2).abs() < 1e-6);
+    }
+    #[test]
+    fn test_flip() {
+        let checker: RgbImage = RgbImage::from_fn(32, 32, |x, y| if (x / 4 + y / 4) % 2 == 0 { Rgb([200, 50, 50]) } else { Rgb([20, 20, 20]) });
+        let shifted: RgbImage = RgbImage::from_fn(32, 32, |x, y| if (x / 4 + y / 4) % 2 == 0 { Rgb([210, 50, 50]) } else { Rgb([20, 20, 20]) });
+        let white: RgbImage = RgbImage::from_pixel(32, 32, Rgb([255, 255, 255]));
+        let black: RgbImage = RgbImage::from_pixel(32, 32, Rgb([0, 0, 0]));
+        assert_eq!(flip(&checker, &checker), 0.0);
+        assert!(flip(&checker, &shifted) < 0.2);
+        assert!(flip(&white, &black) > 0.9);
+        assert!(flip(&checker, &shifted) < flip(&checker, &black));
+    }
+}
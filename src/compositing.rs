@@ -0,0 +1,245 @@
+// Author: Giulian Biolo, github.com/giulianbiolo
+// Date: 16/10/2026
+// Description: This file implements the passes for compositing, a metric depth (Z) pass, a world position pass and the motion vectors written as OpenEXR, and the deep pixels for holdouts
+
+use std::path::Path;
+
+use exr::prelude::{AnyChannel, AnyChannels, Encoding, FlatSamples, Image, Layer, LayerAttributes, SmallVec, WritableImage};
+use glam::{Vec2, Vec3A};
+
+use crate::camera::Camera;
+use crate::hit_record::HitRecord;
+use crate::hittable_list::{Hittable, HittableList};
+use crate::parallel::*;
+use crate::point3::Point3;
+use crate::ray::Ray;
+use crate::temporal;
+use crate::utility;
+
+
+const DEEP_MAGIC: &[u8; 8] = b"GBRTDEEP";
+
+// The depth pass is always written, the deep pixels only with some deep samples
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DepthOutput {
+    // Rays per pixel of the deep image, 0 for none
+    pub deep_samples: u32,
+    // Depths within this fraction of each other are merged into one deep sample
+    pub merge_tolerance: f32,
+}
+
+impl DepthOutput {
+    // Saves <output>_depth.exr, and <output>_deep.bin with the deep samples
+    pub fn save(&self, world: &HittableList, cam: &Camera, width: u32, height: u32, filename: &str) {
+        let stem: String = Path::new(filename).with_extension("").to_string_lossy().to_string();
+        let depth_file: String = format!("{}_depth.exr", stem);
+        write_exr(&depth_file, width, height, vec![("Z", z_pass(world, cam, width, height))]).unwrap_or_else(|err| panic!("Failed to save the depth pass to {}: {}", depth_file, err));
+        println!("Saved the depth pass to {}", depth_file);
+        if self.deep_samples == 0 { return; }
+        let deep_file: String = format!("{}_deep.bin", stem);
+        let deep: DeepImage = DeepImage::render(world, cam, width, height, self);
+        deep.save(&deep_file).unwrap_or_else(|err| panic!("Failed to save the deep pixels to {}: {}", deep_file, err));
+        println!("Saved the deep pixels to {}, {} samples", deep_file, deep.pixels.iter().map(|pixel| pixel.len()).sum::<usize>());
+    }
+}
+
+// Writes the named float channels, row major from the top, as a single layer OpenEXR
+pub fn write_exr(filename: &str, width: u32, height: u32, channels: Vec<(&str, Vec<f32>)>) -> exr::error::UnitResult {
+    let channels: SmallVec<[AnyChannel<FlatSamples>; 4]> = channels.into_iter().map(|(name, values)| AnyChannel::new(name, FlatSamples::F32(values))).collect();
+    let layer = Layer::new((width as usize, height as usize), LayerAttributes::default(), Encoding::FAST_LOSSLESS, AnyChannels::sort(channels));
+    Image::from_layer(layer).write().to_file(filename)
+}
+
+// The distance of the first surface seen by the center of each pixel along the view axis (not along the ray), as the compositors expect it
+// Infinite where the pixel sees the environment
+pub fn z_pass(world: &HittableList, cam: &Camera, width: u32, height: u32) -> Vec<f32> {
+    temporal::depth_pass(world, cam, width, height).into_iter().enumerate().map(|(idx, distance)| {
+        let (x, y) = ((idx as u32 % width) as f32 + 0.5, (idx as u32 / width) as f32 + 0.5);
+        distance * pixel_direction(cam, width, height, x, y).dot(-cam.w)
+    }).collect()
+}
+
+// The direction through the lens center to the point (x, y) of the image, in pixels from the top left corner
+pub fn pixel_direction(cam: &Camera, width: u32, height: u32, x: f32, y: f32) -> Vec3A {
+    cam.pinhole_direction(x / (width as f32 - 1.0), (height as f32 - y) / (height as f32 - 1.0)).normalize()
+}
+
+/****************** Position pass ******************/
+// The world position (and normal) of the surface seen by each pixel, for projecting textures and relighting in the compositor
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PositionOutput {
+    // Moves the positions off the surfaces along their normals, so that projections onto them don't flicker with the noise of the depth
+    pub normal_offset: f32,
+}
+
+impl PositionOutput {
+    // Saves the P.X, P.Y, P.Z and N.X, N.Y, N.Z channels to <output>_position.exr, zero on the environment
+    pub fn save(&self, world: &HittableList, cam: &Camera, width: u32, height: u32, filename: &str) {
+        let output: String = format!("{}_position.exr", Path::new(filename).with_extension("").to_string_lossy());
+        let surfaces: Vec<(Vec3A, Vec3A)> = self.pass(world, cam, width, height);
+        let channel = |name: &'static str, value: fn(&(Vec3A, Vec3A)) -> f32| (name, surfaces.iter().map(value).collect::<Vec<f32>>());
+        let channels: Vec<(&str, Vec<f32>)> = vec![
+            channel("P.X", |surface| surface.0.x), channel("P.Y", |surface| surface.0.y), channel("P.Z", |surface| surface.0.z),
+            channel("N.X", |surface| surface.1.x), channel("N.Y", |surface| surface.1.y), channel("N.Z", |surface| surface.1.z),
+        ];
+        write_exr(&output, width, height, channels).unwrap_or_else(|err| panic!("Failed to save the position pass to {}: {}", output, err));
+        println!("Saved the position pass to {}", output);
+    }
+    // The offset position and the normal facing the camera of the first surface seen by the center of each pixel
+    pub fn pass(&self, world: &HittableList, cam: &Camera, width: u32, height: u32) -> Vec<(Vec3A, Vec3A)> {
+        (0..height).into_par_iter().map(|y| {
+            (0..width).map(|x| {
+                let direction: Vec3A = pixel_direction(cam, width, height, x as f32 + 0.5, y as f32 + 0.5);
+                let hit: Option<HitRecord> = world.hit(&Ray::new(cam.origin, direction), utility::NEAR_ZERO, utility::INFINITY);
+                hit.map_or((Vec3A::ZERO, Vec3A::ZERO), |rec| (rec.p + rec.normal * self.normal_offset, rec.normal))
+            }).collect::<Vec<(Vec3A, Vec3A)>>()
+        }).reduce(Vec::new, |mut a, b| { a.extend(b); a })
+    }
+}
+
+/****************** Motion vectors ******************/
+// The cameras of the frames before and after the one being rendered, for the motion of what each pixel sees
+#[derive(Debug, Clone)]
+pub struct MotionVectors {
+    pub previous: Camera,
+    pub next: Camera,
+}
+
+impl MotionVectors {
+    // Saves the motion to the next frame as forward.u, forward.v and to the previous one as backward.u, backward.v to <output>_motion.exr
+    pub fn save(&self, world: &HittableList, cam: &Camera, width: u32, height: u32, filename: &str) {
+        let output: String = format!("{}_motion.exr", Path::new(filename).with_extension("").to_string_lossy());
+        let motion: Vec<(Vec2, Vec2)> = self.pass(world, cam, width, height);
+        let channel = |name: &'static str, value: fn(&(Vec2, Vec2)) -> f32| (name, motion.iter().map(value).collect::<Vec<f32>>());
+        let channels: Vec<(&str, Vec<f32>)> = vec![
+            channel("forward.u", |motion| motion.0.x), channel("forward.v", |motion| motion.0.y),
+            channel("backward.u", |motion| motion.1.x), channel("backward.v", |motion| motion.1.y),
+        ];
+        write_exr(&output, width, height, channels).unwrap_or_else(|err| panic!("Failed to save the motion vectors to {}: {}", output, err));
+        let largest: f32 = motion.iter().fold(0.0_f32, |largest, (forward, backward)| largest.max(forward.length()).max(backward.length()));
+        println!("Saved the motion vectors to {}, up to {:.1} pixels", output, largest);
+    }
+    // How far, in pixels to the right and down, the surface seen by the center of each pixel moves in the next and in the previous frame
+    // The environment moves with the rotations of the camera, and what goes behind it doesn't move
+    pub fn pass(&self, world: &HittableList, cam: &Camera, width: u32, height: u32) -> Vec<(Vec2, Vec2)> {
+        (0..height).into_par_iter().map(|y| {
+            (0..width).map(|x| {
+                let pixel: Vec2 = Vec2::new(x as f32 + 0.5, y as f32 + 0.5);
+                let direction: Vec3A = pixel_direction(cam, width, height, pixel.x, pixel.y);
+                let hit: Option<HitRecord> = world.hit(&Ray::new(cam.origin, direction), utility::NEAR_ZERO, utility::INFINITY);
+                let p: Point3 = hit.map_or(cam.origin + direction * 1e6, |rec| rec.p);
+                let motion = |camera: &Camera| image_point(camera, width, height, &p).map_or(Vec2::ZERO, |moved| moved - pixel);
+                (motion(&self.next), motion(&self.previous))
+            }).collect::<Vec<(Vec2, Vec2)>>()
+        }).reduce(Vec::new, |mut a, b| { a.extend(b); a })
+    }
+}
+
+// Where the camera sees the point, in pixels from the top left corner, the inverse of pixel_direction
+pub fn image_point(cam: &Camera, width: u32, height: u32, p: &Point3) -> Option<Vec2> {
+    cam.project(p).map(|(u, v)| Vec2::new(u * (width as f32 - 1.0), height as f32 - v * (height as f32 - 1.0)))
+}
+
+/****************** Deep pixels ******************/
+// The part of a pixel covered by the surfaces between two depths
+// The alpha is the one of OpenEXR deep images, covering what the samples in front leave uncovered, so that compositing them front to back gives the coverage
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DeepSample {
+    pub z_front: f32,
+    pub z_back: f32,
+    pub alpha: f32,
+}
+
+// Merges the depths seen by the rays of a pixel into its deep samples, front to back, the environment leaving the pixel uncovered
+pub fn deep_pixel(mut depths: Vec<f32>, tolerance: f32) -> Vec<DeepSample> {
+    let rays: f32 = depths.len() as f32;
+    depths.retain(|depth| depth.is_finite());
+    depths.sort_by(f32::total_cmp);
+    let (mut samples, mut covered, mut idx) = (Vec::new(), 0.0_f32, 0);
+    while idx < depths.len() {
+        let z_front: f32 = depths[idx];
+        let count: usize = depths[idx..].iter().take_while(|depth| **depth <= z_front * (1.0 + tolerance)).count();
+        let coverage: f32 = count as f32 / rays;
+        samples.push(DeepSample { z_front, z_back: depths[idx + count - 1], alpha: (coverage / (1.0 - covered)).min(1.0) });
+        covered += coverage;
+        idx += count;
+    }
+    samples
+}
+
+pub struct DeepImage {
+    pub width: u32,
+    pub height: u32,
+    // Row major pixels from the top, each with its samples front to back
+    pub pixels: Vec<Vec<DeepSample>>,
+}
+
+impl DeepImage {
+    // Traces the rays of each pixel spread over it by the R2 sequence, the same ones for every pixel
+    pub fn render(world: &HittableList, cam: &Camera, width: u32, height: u32, output: &DepthOutput) -> DeepImage {
+        let offsets: Vec<(f32, f32)> = (0..output.deep_samples).map(|idx| ((0.5 + idx as f32 * 0.754_877_7).fract(), (0.5 + idx as f32 * 0.569_840_3).fract())).collect();
+        let pixels: Vec<Vec<DeepSample>> = (0..height).into_par_iter().map(|y| {
+            (0..width).map(|x| {
+                let depths: Vec<f32> = offsets.iter().map(|(dx, dy)| {
+                    let direction: Vec3A = pixel_direction(cam, width, height, x as f32 + dx, y as f32 + dy);
+                    world.hit(&Ray::new(cam.origin, direction), utility::NEAR_ZERO, utility::INFINITY).map_or(f32::INFINITY, |rec| rec.t * direction.dot(-cam.w))
+                }).collect();
+                deep_pixel(depths, output.merge_tolerance)
+            }).collect::<Vec<Vec<DeepSample>>>()
+        }).reduce(Vec::new, |mut a, b| { a.extend(b); a });
+        DeepImage { width, height, pixels }
+    }
+    // GBRTDEEP, the width and the height, then for each pixel the number of its samples followed by their front depth, back depth and alpha, all little endian
+    pub fn save(&self, filename: &str) -> std::io::Result<()> {
+        let mut bytes: Vec<u8> = DEEP_MAGIC.to_vec();
+        bytes.extend_from_slice(&self.width.to_le_bytes());
+        bytes.extend_from_slice(&self.height.to_le_bytes());
+        for pixel in &self.pixels {
+            bytes.extend_from_slice(&(pixel.len() as u32).to_le_bytes());
+            for sample in pixel {
+                for float in [sample.z_front, sample.z_back, sample.alpha] { bytes.extend_from_slice(&float.to_le_bytes()); }
+            }
+        }
+        std::fs::write(filename, bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_deep_pixel() {
+        // Half of the pixel on a surface at 2, a quarter on one at 5 (two rays a bit apart) and a quarter on the environment
+        let samples: Vec<DeepSample> = deep_pixel(vec![5.0, 2.0, f32::INFINITY, 2.0, 5.02, 2.0, 2.0, f32::INFINITY], 0.01);
+        assert_eq!(samples.len(), 2);
+        assert_eq!((samples[0].z_front, samples[0].z_back, samples[0].alpha), (2.0, 2.0, 0.5));
+        assert_eq!((samples[1].z_front, samples[1].z_back), (5.0, 5.02));
+        // The back surface covers half of what the front one leaves, the pixel being three quarters covered once composited
+        assert!((samples[1].alpha - 0.5).abs() < 1e-6);
+        let coverage: f32 = samples.iter().fold(0.0, |coverage, sample| coverage + (1.0 - coverage) * sample.alpha);
+        assert!((coverage - 0.75).abs() < 1e-6);
+        assert!(deep_pixel(vec![f32::INFINITY; 4], 0.01).is_empty());
+    }
+
+    #[test]
+    fn test_position_pass() {
+        use std::sync::Arc;
+        use crate::material::Lambertian;
+        use crate::point3::Point3;
+        use crate::sphere::Sphere;
+        // A unit sphere filling the middle of the frame, the corners seeing the environment
+        let world: HittableList = vec![Arc::new(Sphere::new(Point3::ZERO, 1.0, Box::new(Lambertian::new(crate::color::Color::ONE)), 0))];
+        let cam: Camera = Camera::new(&Vec3A::new(0.0, 0.0, 5.0), &Vec3A::ZERO, &Vec3A::Y, 30.0, 1.0, 0.0, 5.0);
+        let surfaces: Vec<(Vec3A, Vec3A)> = PositionOutput { normal_offset: 0.1 }.pass(&world, &cam, 9, 9);
+        assert_eq!(surfaces[0], (Vec3A::ZERO, Vec3A::ZERO));
+        let (position, normal) = surfaces[4 * 9 + 4];
+        assert!(position.z > 1.0 && (position.length() - 1.1).abs() < 1e-4 && (normal - position / 1.1).length() < 1e-4);
+        // Moving the camera to the right, the sphere moves to the left of the image and the far away environment stays
+        let camera_at = |x: f32| Camera::new(&Vec3A::new(x, 0.0, 5.0), &Vec3A::new(x, 0.0, 0.0), &Vec3A::Y, 30.0, 1.0, 0.0, 5.0);
+        let motion: Vec<(Vec2, Vec2)> = MotionVectors { previous: camera_at(-0.1), next: camera_at(0.1) }.pass(&world, &cam, 9, 9);
+        let (forward, backward) = motion[4 * 9 + 4];
+        assert!(forward.x < -0.1 && forward.y.abs() < 1e-3 && (forward + backward).length() < 1e-2);
+        assert!(motion[0].0.length() < 1e-3);
+    }
+}
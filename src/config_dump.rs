@@ -0,0 +1,166 @@
+// Author: Giulian Biolo, github.com/giulianbiolo
+// Date: 16/10/2026
+// Description: This file implements --print-config, writing the settings a render would use as a YAML scene, with the defaults filled in and the overrides merged
+
+use glam::Vec3A;
+use yaml_rust::yaml::Hash;
+use yaml_rust::{Yaml, YamlEmitter};
+
+use crate::lidar::ScanPattern;
+use crate::parser;
+use crate::point3::Point3;
+use crate::utility::Constants;
+
+
+fn _key(name: &str) -> Yaml { Yaml::String(name.to_string()) }
+// Always with a decimal point, since the parser reads the integers and the reals apart
+fn _real(value: f32) -> Yaml { Yaml::Real(format!("{:?}", value)) }
+fn _integer(value: u64) -> Yaml { Yaml::Integer(value as i64) }
+fn _vec3(value: Vec3A) -> Yaml { Yaml::Array(value.to_array().iter().map(|coordinate| _real(*coordinate)).collect()) }
+fn _hash(entries: Vec<(&str, Yaml)>) -> Yaml { Yaml::Hash(entries.into_iter().map(|(key, value)| (_key(key), value)).collect()) }
+
+// The constants section of the settings, with every key the parser knows and the defaults it would take
+// The optional blocks only when they're on, as they have no default, and the threads of the command line over those of the scene
+pub fn constants_yaml(consts: &Constants, threads: Option<usize>) -> Yaml {
+    let mut entries: Vec<(&str, Yaml)> = vec![
+        ("width", _integer(consts.width as u64)),
+        ("height", _integer(consts.height as u64)),
+        ("samplesPerPixel", _integer(consts.samples_per_pixel as u64)),
+        ("maxDepth", _integer(consts.max_depth as u64)),
+        ("minDepth", _integer(consts.min_depth as u64)),
+        ("maxSpecularDepth", _integer(consts.max_specular_depth as u64)),
+        ("maxDiffuseDepth", _integer(consts.max_diffuse_depth as u64)),
+        ("lightSampling", Yaml::Boolean(consts.light_sampling)),
+        ("manifoldSampling", Yaml::Boolean(consts.manifold_sampling)),
+        ("environmentSH", Yaml::Boolean(consts.environment_sh)),
+        ("filter", Yaml::String(consts.filter.clone().unwrap_or_else(|| "UniformFilter".to_string()))),
+        ("sourcesLambda", _real(consts.sources_lambda)),
+        ("powerRenderCenter", _vec3(consts.power_render_center)),
+        ("streamTextures", Yaml::Boolean(consts.stream_textures)),
+        ("pinThreads", Yaml::Boolean(consts.pin_threads)),
+        ("seed", _integer(consts.seed)),
+        ("seedPattern", Yaml::String(consts.seed_pattern.name().to_string())),
+        ("frame", _integer(consts.frame)),
+        ("compactAccumulation", Yaml::Boolean(consts.compact_accumulation)),
+        ("motionVectors", Yaml::Boolean(consts.motion_vectors.is_some())),
+    ];
+    if let Some(environment_map) = &consts.environment_map { entries.push(("environmentMap", Yaml::String(environment_map.clone()))); }
+    if let Some(faces) = &consts.environment_cube_map { entries.push(("environmentCubeMap", Yaml::Array(faces.iter().map(|face| Yaml::String(face.clone())).collect()))); }
+    if let Some(distance) = consts.environment_distance { entries.push(("environmentDistance", _real(distance))); }
+    entries.push(("environmentIntensity", _real(consts.environment_intensity.unwrap_or(1.0))));
+    if let Some(background_map) = &consts.background_map { entries.push(("backgroundMap", Yaml::String(background_map.clone()))); }
+    if let Some(intensity) = consts.background_intensity { entries.push(("backgroundIntensity", _real(intensity))); }
+    if let Some(projection) = &consts.ground_projection {
+        entries.push(("groundProjection", _hash(vec![("groundLevel", _real(projection.ground_level)), ("height", _real(projection.height)), ("radius", _real(projection.radius))])));
+    }
+    if let Some(budget) = &consts.link_budget {
+        entries.push(("linkBudget", _hash(vec![
+            ("transmitPower", _real(budget.transmit_power)), ("transmitGain", _real(budget.transmit_gain)),
+            ("receiveGain", _real(budget.receive_gain)), ("referenceDistance", _real(budget.reference_distance)),
+        ])));
+    }
+    if let Some(megabytes) = consts.memory_budget { entries.push(("memoryBudget", _integer(megabytes as u64))); }
+    if let Some(threads) = threads.or(consts.threads) { entries.push(("threads", _integer(threads as u64))); }
+    if let Some(seconds) = consts.max_render_seconds { entries.push(("maxRenderSeconds", _real(seconds))); }
+    if let Some(seconds) = consts.progressive_interval { entries.push(("progressiveOutput", _real(seconds))); }
+    if let Some(samples) = consts.max_total_samples { entries.push(("maxTotalSamples", _integer(samples))); }
+    if let Some(temporal) = &consts.temporal {
+        entries.push(("temporal", _hash(vec![("history", Yaml::String(temporal.history_file.clone())), ("blend", _real(temporal.blend)), ("depthTolerance", _real(temporal.depth_tolerance))])));
+    }
+    if let Some(transient) = &consts.transient { entries.push(("transient", _hash(vec![("bins", _integer(transient.bins as u64)), ("maxDistance", _real(transient.max_distance))]))); }
+    if let Some(lidar) = &consts.lidar {
+        let mut lidar_entries: Vec<(&str, Yaml)> = match lidar.pattern {
+            ScanPattern::Camera { .. } => vec![("pattern", _key("camera"))],
+            ScanPattern::Spinning { channels, steps, min_elevation, max_elevation } => vec![
+                ("pattern", _key("spinning")), ("channels", _integer(channels as u64)), ("steps", _integer(steps as u64)),
+                ("minElevation", _real(min_elevation)), ("maxElevation", _real(max_elevation)),
+            ],
+        };
+        lidar_entries.extend([("maxRange", _real(lidar.max_range)), ("rangeNoise", _real(lidar.range_noise)), ("format", _key(lidar.format.extension()))]);
+        entries.push(("lidar", _hash(lidar_entries)));
+    }
+    if !consts.aovs.is_empty() { entries.push(("aovs", Yaml::Array(consts.aovs.iter().map(|aov| _key(aov.name())).collect()))); }
+    if let Some(adaptive) = &consts.adaptive_sampling {
+        entries.push(("adaptiveSampling", _hash(vec![("threshold", _real(adaptive.threshold)), ("minSamples", _integer(adaptive.min_samples as u64))])));
+    }
+    if let Some(priority) = &consts.priority {
+        let mut priority_entries: Vec<(&str, Yaml)> = vec![("tileSize", _integer(priority.tile_size as u64)), ("minWeight", _real(priority.min_weight)), ("maxSamples", _integer(priority.max_samples as u64))];
+        if let Some(mask) = &priority.mask { priority_entries.insert(0, ("mask", Yaml::String(mask.clone()))); }
+        entries.push(("priority", _hash(priority_entries)));
+    }
+    if let Some(depth) = &consts.depth_output {
+        entries.push(("depthOutput", _hash(vec![("deepSamples", _integer(depth.deep_samples as u64)), ("mergeTolerance", _real(depth.merge_tolerance))])));
+    }
+    if let Some(position) = &consts.position_output { entries.push(("positionOutput", _hash(vec![("normalOffset", _real(position.normal_offset))]))); }
+    if let Some(exposure) = &consts.exposure_analysis { entries.push(("exposureAnalysis", _hash(vec![("under", _real(exposure.under)), ("over", _real(exposure.over))]))); }
+    _hash(entries)
+}
+
+// The camera section, where the camera path of the animation puts it at the frame of the constants
+pub fn camera_yaml(doc: &Yaml) -> Yaml {
+    let mut camera: Hash = doc["camera"].as_hash().expect("The scene has no camera").clone();
+    let point = |key: &str| -> Point3 { Vec3A::from_slice(&doc["camera"][key].as_vec().unwrap().iter().map(|value| value.as_f64().unwrap() as f32).collect::<Vec<f32>>()) };
+    if let Some((path, fps)) = parser::parse_camera_path(doc, point("lookAt")).unwrap_or_else(|err| panic!("{}", err)) {
+        let (look_from, look_at) = path.at(doc["constants"]["frame"].as_i64().unwrap_or(0) as f32 / fps);
+        camera.insert(_key("lookFrom"), _vec3(look_from));
+        camera.insert(_key("lookAt"), _vec3(look_at));
+    }
+    Yaml::Hash(camera)
+}
+
+// The whole scene as it would be rendered: the resolved constants and camera, the world with the named materials in place, and the other sections as they are
+pub fn effective_config(doc: &Yaml, threads: Option<usize>) -> Yaml {
+    let mut config: Hash = Hash::new();
+    config.insert(_key("constants"), constants_yaml(&parser::parse_constants(doc), threads));
+    config.insert(_key("camera"), camera_yaml(doc));
+    for (key, value) in doc.as_hash().expect("The scene isn't a YAML mapping") {
+        if !config.contains_key(key) && key.as_str() != Some("materials") { config.insert(key.clone(), value.clone()); }
+    }
+    Yaml::Hash(config)
+}
+
+pub fn to_string(config: &Yaml) -> String {
+    let mut output: String = String::new();
+    YamlEmitter::new(&mut output).dump(config).unwrap();
+    output.push('\n');
+    output
+}
+
+// Prints the effective settings of the scene, which render the same when saved and given back as the scene
+pub fn run(config_file: &str, threads: Option<usize>) {
+    let doc: Yaml = parser::load_config(config_file).unwrap_or_else(|| panic!("Failed to load the config file {}", config_file));
+    print!("{}", to_string(&effective_config(&doc, threads)));
+}
+
+#[cfg(test)]
+mod tests {
+    use yaml_rust::YamlLoader;
+
+    use super::*;
+
+    #[test]
+    fn test_effective_config() {
+        let scene: &str = "
+constants: { width: 64, height: 32, samplesPerPixel: 4, maxDepth: 8, minDepth: 2, lightSampling: true, seedPattern: fixed, aovs: [bounces, depthLimit],
+  lidar: { pattern: spinning, channels: 8 }, adaptiveSampling: { threshold: 0.02 }, frame: 12, exposureAnalysis: {} }
+camera: { lookFrom: [0.0, 1.0, 5.0], lookAt: [0.0, 0.0, 0.0], vup: [0.0, 1.0, 0.0], vfov: 40.0, aspectRatio: 2.0, aperture: 0.0, focusDistance: 5.0 }
+animation: { fps: 24, cameraPath: [{ time: 0.0, lookFrom: [0.0, 1.0, 5.0] }, { time: 1.0, lookFrom: [4.0, 1.0, 5.0] }] }
+materials: { red: { matType: Lambertian, texType: SolidColor, texture: { albedo: [1.0, 0.0, 0.0] } } }
+world: [{ objType: Sphere, center: [0.0, 0.0, 0.0], radius: 1.0, material: red }]
+";
+        let mut doc: Yaml = YamlLoader::load_from_str(scene).unwrap().remove(0);
+        parser::resolve_materials(&mut doc).unwrap();
+        let config: Yaml = YamlLoader::load_from_str(&to_string(&effective_config(&doc, Some(3)))).unwrap().remove(0);
+        // The defaults are written out
+        assert_eq!((config["constants"]["maxSpecularDepth"].as_i64(), config["constants"]["filter"].as_str()), (Some(8), Some("UniformFilter")));
+        assert_eq!((config["constants"]["lidar"]["steps"].as_i64(), config["constants"]["threads"].as_i64()), (Some(1024), Some(3)));
+        // Parsing them back gives the same constants, only with the defaults the renderer would have taken spelled out
+        let mut expected: Constants = parser::parse_constants(&doc);
+        (expected.threads, expected.filter, expected.environment_intensity) = (Some(3), Some("UniformFilter".to_string()), Some(1.0));
+        assert_eq!(format!("{:?}", parser::parse_constants(&config)), format!("{:?}", expected));
+        // The camera is where the path puts it at frame 12, halfway, and the world has its material in place
+        assert_eq!(config["camera"]["lookFrom"][0].as_f64(), Some(2.0));
+        assert_eq!(config["world"][0]["material"]["matType"].as_str(), Some("Lambertian"));
+        assert!(config["materials"].is_badvalue());
+    }
+}
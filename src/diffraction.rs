@@ -0,0 +1,157 @@
+// Author: Giulian Biolo, github.com/giulianbiolo
+// Date: 16/10/2026
+// Description: This file implements the knife-edge diffraction of the RF simulations, finding the edges of the scene and the power they bend around the obstacles
+
+use std::collections::HashMap;
+
+use glam::Vec3A;
+
+use crate::hittable_list::{Hittable, HittableList};
+use crate::point3::Point3;
+use crate::utility::{self, PI};
+use crate::visibility;
+
+
+// A straight edge of a face of an object, with the normal of the face
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Edge {
+    pub start: Point3,
+    pub end: Point3,
+    pub normal: Vec3A,
+}
+
+// An edge of the scene that diffracts: the border of a lone face, or the crease where two faces meet at an angle
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Wedge {
+    pub start: Point3,
+    pub end: Point3,
+}
+
+// Faces meeting at less than this angle (in degrees) are taken as one flat surface, e.g. the triangles of a quad
+const CREASE_ANGLE: f32 = 20.0;
+// How far off of the edge the diffracted paths are traced, so that they don't graze the faces of the wedge
+const LIFT: f32 = 10.0 * utility::NEAR_ZERO;
+
+// The quantized end points of an edge, in either order, so that the faces sharing it find each other
+type EdgeKey = ([i64; 3], [i64; 3]);
+
+// The edges of the outline of a flat polygon, for the objects made of faces
+pub fn polygon_edges(corners: &[Point3], normal: Vec3A) -> Vec<Edge> {
+    (0..corners.len()).map(|i| Edge { start: corners[i], end: corners[(i + 1) % corners.len()], normal }).collect()
+}
+
+// The edge detection pass: the edges of the faces of the world, the shared ones merged and kept only where they crease
+pub fn detect(world: &HittableList) -> Vec<Wedge> {
+    let key = |point: Point3| (point / utility::EPSILON).round().to_array().map(|value| value as i64);
+    let mut faces: HashMap<EdgeKey, (Wedge, Vec<Vec3A>)> = HashMap::new();
+    for edge in world.edges() {
+        if (edge.end - edge.start).length() < utility::EPSILON { continue; }
+        let (a, b) = (key(edge.start), key(edge.end));
+        let entry = faces.entry(if a <= b { (a, b) } else { (b, a) }).or_insert((Wedge { start: edge.start, end: edge.end }, Vec::new()));
+        entry.1.push(edge.normal);
+    }
+    let flat: f32 = CREASE_ANGLE.to_radians().cos();
+    let mut wedges: Vec<(EdgeKey, Wedge)> = faces.into_iter().filter(|(_, (_, normals))| {
+        // The normals of the faces of a mesh may face either side, so only the angle between their lines counts
+        normals.len() == 1 || normals.iter().any(|a| normals.iter().any(|b| a.dot(*b).abs() < flat))
+    }).map(|(key, (wedge, _))| (key, wedge)).collect();
+    wedges.sort_by_key(|(key, _)| *key);
+    wedges.into_iter().map(|(_, wedge)| wedge).collect()
+}
+
+// The loss in dB of a knife edge from its Fresnel-Kirchhoff parameter (the ITU-R P.526 approximation)
+pub fn knife_edge_loss(v: f32) -> f32 {
+    if v <= -0.78 { return 0.0; }
+    6.9 + 20.0 * (((v - 0.1).powi(2) + 1.0).sqrt() + v - 0.1).log10()
+}
+
+fn _closest_on_line(from: Point3, to: Point3, point: Point3) -> Point3 {
+    let direction: Vec3A = (to - from).normalize();
+    from + direction * (point - from).dot(direction)
+}
+
+// The point of the edge with the shortest path from one point to the other through it, the path length being convex along the edge
+fn _diffraction_point(wedge: &Wedge, from: Point3, to: Point3) -> Point3 {
+    let length = |s: f32| { let point: Point3 = wedge.start.lerp(wedge.end, s); (point - from).length() + (to - point).length() };
+    let (mut low, mut high) = (0.0_f32, 1.0_f32);
+    for _ in 0..40 {
+        let (a, b) = (low + (high - low) / 3.0, high - (high - low) / 3.0);
+        if length(a) < length(b) { high = b; } else { low = a; }
+    }
+    wedge.start.lerp(wedge.end, (low + high) / 2.0)
+}
+
+// The Fresnel-Kirchhoff parameter of the edge between the two points, positive when the edge stands in the way of the line between them
+fn _fresnel_parameter(from: Point3, edge: Point3, to: Point3, up: Vec3A, lambda: f32) -> f32 {
+    let (d1, d2) = ((edge - from).length(), (to - edge).length());
+    let height: f32 = (edge - _closest_on_line(from, to, edge)).dot(up);
+    height * (2.0 * (d1 + d2) / (lambda * d1 * d2)).sqrt()
+}
+
+fn _free_space(distance: f32, lambda: f32) -> f32 { (lambda / (4.0 * PI * distance)).powi(2) }
+
+// The path gain from the source to the receiver around the edges, for when the line between them is blocked
+// The main path wins, over one edge or over two (the Epstein-Peterson method, e.g. over the two top edges of a thick wall)
+pub fn gain(world: &HittableList, wedges: &[Wedge], source: Point3, receiver: Point3, lambda: f32) -> f32 {
+    // Each edge bends the path at its point closest to the straight line, on the side away from it
+    let points: Vec<(Point3, Vec3A)> = wedges.iter().filter_map(|wedge| {
+        let point: Point3 = _diffraction_point(wedge, source, receiver);
+        let up: Vec3A = (point - _closest_on_line(source, receiver, point)).normalize_or_zero();
+        if up == Vec3A::ZERO { return None; }
+        Some((point + up * LIFT, up))
+    }).collect();
+    let seen_from_source: Vec<bool> = points.iter().map(|(point, _)| visibility::line_of_sight(world, &source, point)).collect();
+    let seen_from_receiver: Vec<bool> = points.iter().map(|(point, _)| visibility::line_of_sight(world, point, &receiver)).collect();
+    let mut best: f32 = 0.0;
+    for (i, (point, up)) in points.iter().enumerate() {
+        if !seen_from_source[i] { continue; }
+        if seen_from_receiver[i] {
+            let loss: f32 = knife_edge_loss(_fresnel_parameter(source, *point, receiver, *up, lambda));
+            best = best.max(_free_space((*point - source).length() + (receiver - *point).length(), lambda) * 10.0_f32.powf(-loss / 10.0));
+        }
+        for (j, (second, second_up)) in points.iter().enumerate() {
+            if j == i || !seen_from_receiver[j] || seen_from_receiver[i] || !visibility::line_of_sight(world, point, second) { continue; }
+            let loss: f32 = knife_edge_loss(_fresnel_parameter(source, *point, *second, *up, lambda)) + knife_edge_loss(_fresnel_parameter(*point, *second, receiver, *second_up, lambda));
+            let distance: f32 = (*point - source).length() + (*second - *point).length() + (receiver - *second).length();
+            best = best.max(_free_space(distance, lambda) * 10.0_f32.powf(-loss / 10.0));
+        }
+    }
+    best
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use crate::bbox::BBox;
+    use crate::color::Color;
+    use crate::material::Lambertian;
+    use crate::rectangle::{Rectangle, XYRectangle};
+    use crate::triangle::Triangle;
+
+    #[test]
+    fn test_diffraction() {
+        // The edge grazing the line of sight takes half of the field away
+        assert!((knife_edge_loss(0.0) - 6.03).abs() < 0.05);
+        assert_eq!(knife_edge_loss(-1.0), 0.0);
+        let gray = || Box::new(Lambertian::new(Color::splat(0.5)));
+        // Every edge of a box creases, the diagonal of a quad made of two triangles doesn't
+        let cube: HittableList = vec![Arc::new(BBox::new(Point3::ZERO, Vec3A::ONE, gray()))];
+        assert_eq!(detect(&cube).len(), 12);
+        let corners: [Point3; 4] = [Point3::new(0.0, 0.0, 0.0), Point3::new(1.0, 0.0, 0.0), Point3::new(1.0, 1.0, 0.0), Point3::new(0.0, 1.0, 0.0)];
+        let quad: HittableList = vec![
+            Arc::new(Triangle::new(Box::new([corners[0], corners[1], corners[2]]), Box::new([Vec3A::Z; 3]), gray(), 0)),
+            Arc::new(Triangle::new(Box::new([corners[0], corners[2], corners[3]]), Box::new([Vec3A::Z; 3]), gray(), 0)),
+        ];
+        assert_eq!(detect(&quad).len(), 4);
+        // Behind a thin wall the power bends over its top edge, fainter than in free space; a thick wall needs both of its top edges
+        let lambda: f32 = 0.125;
+        let (source, receiver) = (Point3::new(0.0, 1.0, -2.0), Point3::new(0.0, 1.0, 2.0));
+        let wall: HittableList = vec![Arc::new(Rectangle::XYRectangle(XYRectangle::new(-10.0, 10.0, -1.0, 1.5, 0.0, gray(), 0)))];
+        let thin: f32 = gain(&wall, &detect(&wall), source, receiver, lambda);
+        assert!(thin > 0.0 && thin < _free_space(4.0, lambda));
+        let thick: HittableList = vec![Arc::new(BBox::new(Point3::new(0.0, 0.25, 0.0), Vec3A::new(20.0, 2.5, 0.5), gray()))];
+        let thick: f32 = gain(&thick, &detect(&thick), source, receiver, lambda);
+        assert!(thick > 0.0 && thick < thin);
+    }
+}
@@ -0,0 +1,81 @@
+// Author: Giulian Biolo, github.com/giulianbiolo
+// Date: 16/10/2026
+// Description: This file implements the ground projection of the environment map
+
+use std::sync::Arc;
+
+use glam::Vec3A;
+
+use crate::hit_record::HitRecord;
+use crate::hittable_list::Hittable;
+use crate::light_sampler::LightEstimate;
+use crate::point3::Point3;
+use crate::ray::Ray;
+
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GroundProjection {
+    // Height of the virtual ground plane
+    pub ground_level: f32,
+    // Height above the ground the HDRI was captured from
+    pub height: f32,
+    // Radius of the projected ground disk, outside of it the environment is looked up as usual
+    pub radius: f32,
+}
+
+// Wraps the environment sphere, projecting its lower hemisphere onto a ground plane so that objects don't look like they float
+pub struct GroundProjectedEnvironment {
+    environment: Arc<dyn Hittable + Send + Sync>,
+    projection: GroundProjection,
+}
+
+impl GroundProjectedEnvironment {
+    pub fn new(environment: Arc<dyn Hittable + Send + Sync>, projection: GroundProjection) -> GroundProjectedEnvironment {
+        GroundProjectedEnvironment { environment, projection }
+    }
+}
+
+impl Hittable for GroundProjectedEnvironment {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        if ray.direction().y < 0.0 {
+            let t: f32 = (self.projection.ground_level - ray.origin().y) / ray.direction().y;
+            let ground: Point3 = ray.at(t);
+            if t > t_min && t < t_max && Vec3A::new(ground.x, 0.0, ground.z).length() <= self.projection.radius {
+                // Look the environment up from where it was captured, towards the point on the ground
+                let capture: Point3 = Point3::new(0.0, self.projection.ground_level + self.projection.height, 0.0);
+                let mut rec: HitRecord = self.environment.hit(&Ray::new(capture, ground - capture), 0.0, f32::INFINITY)?;
+                rec.p = ground;
+                rec.t = t;
+                return Some(rec);
+            }
+        }
+        self.environment.hit(ray, t_min, t_max)
+    }
+    fn is_light(&self) -> bool { self.environment.is_light() }
+    fn light_estimate(&self) -> Option<LightEstimate> { self.environment.light_estimate() }
+    fn pdf_value(&self, origin: &Point3, v: &Vec3A) -> f32 { self.environment.pdf_value(origin, v) }
+    fn random(&self, origin: &Point3) -> Vec3A { self.environment.random(origin) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::Color;
+    use crate::material::DiffuseLight;
+    use crate::sphere::Sphere;
+
+    #[test]
+    fn test_ground_projection() {
+        let sphere: Sphere = Sphere::new(Point3::ZERO, 100.0, Box::new(DiffuseLight::new(Color::ONE, 1.0)), 0);
+        let projection: GroundProjection = GroundProjection { ground_level: 0.0, height: 2.0, radius: 10.0 };
+        let environment: GroundProjectedEnvironment = GroundProjectedEnvironment::new(Arc::new(sphere), projection);
+        // Looking down, the ray lands on the ground disk
+        let ray: Ray = Ray::new(Point3::new(0.0, 1.0, 0.0), Vec3A::new(1.0, -1.0, 0.0));
+        let rec: HitRecord = environment.hit(&ray, 0.001, f32::INFINITY).unwrap();
+        assert!(rec.p.abs_diff_eq(Point3::new(1.0, 0.0, 0.0), 1e-5));
+        // Past the radius of the disk the environment sphere is hit as usual
+        let ray: Ray = Ray::new(Point3::new(0.0, 1.0, 0.0), Vec3A::new(50.0, -1.0, 0.0));
+        let rec: HitRecord = environment.hit(&ray, 0.001, f32::INFINITY).unwrap();
+        assert!((rec.p.length() - 100.0).abs() < 0.01);
+    }
+}
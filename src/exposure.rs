@@ -0,0 +1,146 @@
+// Author: Giulian Biolo, github.com/giulianbiolo
+// Date: 16/10/2026
+// Description: This file implements the exposure analysis of the HDR result, a luminance histogram and a false color image of the under and over exposed pixels saved next to the image
+
+use std::path::Path;
+
+use image::{ImageBuffer, Rgb};
+
+use crate::color::Color;
+use crate::light_sampler::luminance;
+
+
+// The luminance the key of a well exposed image sits at
+const MIDDLE_GRAY: f32 = 0.18;
+// The histogram spans the stops (EV) from MIN_STOPS to MAX_STOPS around a luminance of 1, in BINS_PER_STOP bins each
+const MIN_STOPS: f32 = -12.0;
+const MAX_STOPS: f32 = 4.0;
+const BINS_PER_STOP: usize = 4;
+const HISTOGRAM_HEIGHT: u32 = 160;
+const BIN_WIDTH: u32 = 8;
+
+// The thresholds of the exposure analysis, as linear values of the HDR result
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ExposureAnalysis {
+    // Pixels of a lower luminance are crushed to black
+    pub under: f32,
+    // Pixels with a channel over it are clipped by the 8 bit output
+    pub over: f32,
+}
+
+// The exposure of a pixel as the false color image shows it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Exposure {
+    Under,
+    MiddleGray,
+    Normal,
+    Over,
+}
+
+// The summary of the exposure of an image
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExposureReport {
+    // Pixels per bin of the histogram, the ones out of its range in the first and last bins
+    pub histogram: Vec<u32>,
+    pub under: f32,
+    pub over: f32,
+    // Geometric mean of the luminance of the pixels that aren't black, the key of the image
+    pub log_average: f32,
+    // Stops to scale the lights by for the key to land on middle gray
+    pub suggested_ev: f32,
+}
+
+impl ExposureAnalysis {
+    pub fn classify(&self, color: Color) -> Exposure {
+        if !color.is_finite() || color.max_element() >= self.over { return Exposure::Over; }
+        let value: f32 = luminance(color);
+        if value < self.under { Exposure::Under }
+        // Within a third of a stop of middle gray, where skin and the key of the scene usually go
+        else if (value / MIDDLE_GRAY).log2().abs() < 1.0 / 3.0 { Exposure::MiddleGray }
+        else { Exposure::Normal }
+    }
+
+    pub fn report(&self, colors: &[Color]) -> ExposureReport {
+        let bins: usize = ((MAX_STOPS - MIN_STOPS) as usize) * BINS_PER_STOP;
+        let mut histogram: Vec<u32> = vec![0; bins];
+        let (mut under, mut over, mut lit, mut log_sum) = (0, 0, 0, 0.0_f64);
+        for color in colors {
+            let value: f32 = if color.is_finite() { luminance(*color).max(0.0) } else { f32::INFINITY };
+            let bin: f32 = (value.log2() - MIN_STOPS) * BINS_PER_STOP as f32;
+            histogram[if bin.is_nan() { 0 } else { (bin.max(0.0) as usize).min(bins - 1) }] += 1;
+            match self.classify(*color) {
+                Exposure::Under => under += 1,
+                Exposure::Over => over += 1,
+                _ => {},
+            }
+            // The black pixels are left out of the key, as the noise of a short render would drag it down by stops
+            if value.is_finite() && value > 0.0 { (lit, log_sum) = (lit + 1, log_sum + (value as f64).ln()); }
+        }
+        let pixels: f32 = colors.len().max(1) as f32;
+        let log_average: f32 = if lit > 0 { (log_sum / lit as f64).exp() as f32 } else { 0.0 };
+        ExposureReport { histogram, under: under as f32 / pixels, over: over as f32 / pixels, log_average, suggested_ev: (MIDDLE_GRAY / log_average).log2() }
+    }
+
+    // Saves <output>_histogram.png and <output>_false_color.png, printing the summary
+    pub fn save(&self, colors: &[Color], width: u32, height: u32, filename: &str) {
+        let stem: String = Path::new(filename).with_extension("").to_string_lossy().to_string();
+        let report: ExposureReport = self.report(colors);
+        // The false color image: blue crushed, red clipped, green middle gray and the rest in grays of their displayed brightness
+        let false_color: ImageBuffer<Rgb<u8>, Vec<u8>> = ImageBuffer::from_fn(width, height, |x, y| {
+            let color: Color = colors[(y * width + x) as usize];
+            match self.classify(color) {
+                Exposure::Under => Rgb([40, 60, 255]),
+                Exposure::Over => Rgb([255, 30, 30]),
+                Exposure::MiddleGray => Rgb([60, 220, 60]),
+                Exposure::Normal => { let gray: u8 = (luminance(color).sqrt().clamp(0.0, 0.999) * 256.0) as u8; Rgb([gray, gray, gray]) },
+            }
+        });
+        false_color.save(format!("{}_false_color.png", stem)).unwrap();
+        // The histogram: a bar per bin, colored like the false color image
+        // Its heights are on a log scale, so that the black pixels of a noisy render don't flatten the others
+        let highest: f32 = (report.histogram.iter().copied().max().unwrap_or(0).max(1) as f32).ln_1p();
+        let histogram: ImageBuffer<Rgb<u8>, Vec<u8>> = ImageBuffer::from_fn(report.histogram.len() as u32 * BIN_WIDTH, HISTOGRAM_HEIGHT, |x, y| {
+            let bin: usize = (x / BIN_WIDTH) as usize;
+            let bar: u32 = ((report.histogram[bin] as f32).ln_1p() / highest * HISTOGRAM_HEIGHT as f32).ceil() as u32;
+            if HISTOGRAM_HEIGHT - y > bar || x % BIN_WIDTH == BIN_WIDTH - 1 { return Rgb([24, 24, 24]); }
+            let value: f32 = 2.0_f32.powf(MIN_STOPS + (bin as f32 + 0.5) / BINS_PER_STOP as f32);
+            match self.classify(Color::splat(value)) {
+                Exposure::Under => Rgb([40, 60, 255]),
+                Exposure::Over => Rgb([255, 30, 30]),
+                Exposure::MiddleGray => Rgb([60, 220, 60]),
+                Exposure::Normal => Rgb([200, 200, 200]),
+            }
+        });
+        histogram.save(format!("{}_histogram.png", stem)).unwrap();
+        println!(
+            "Exposure: {:.1}% of the pixels under exposed and {:.1}% clipped, log-average luminance {:.4} ({:+.1} EV to middle gray), saved to {}_histogram.png and {}_false_color.png",
+            report.under * 100.0, report.over * 100.0, report.log_average, report.suggested_ev, stem, stem
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exposure_analysis() {
+        let analysis: ExposureAnalysis = ExposureAnalysis { under: 0.001, over: 1.0 };
+        assert_eq!(analysis.classify(Color::splat(0.0001)), Exposure::Under);
+        assert_eq!(analysis.classify(Color::splat(0.18)), Exposure::MiddleGray);
+        assert_eq!(analysis.classify(Color::splat(0.5)), Exposure::Normal);
+        // A single clipped channel is enough, and the NaNs count as clipped
+        assert_eq!(analysis.classify(Color::new(1.5, 0.1, 0.1)), Exposure::Over);
+        assert_eq!(analysis.classify(Color::new(f32::NAN, 0.1, 0.1)), Exposure::Over);
+        // Half of the image a stop under middle gray, the other half clipped three stops over it
+        let colors: Vec<Color> = [Color::splat(0.09); 2].into_iter().chain([Color::splat(1.44); 2]).collect();
+        let report: ExposureReport = analysis.report(&colors);
+        assert_eq!((report.under, report.over), (0.0, 0.5));
+        // The black pixels count as under exposed but don't move the key
+        let blacks: ExposureReport = analysis.report(&[colors.clone(), vec![Color::ZERO; 4]].concat());
+        assert_eq!((blacks.under, blacks.log_average), (0.5, report.log_average));
+        assert!((report.log_average - 0.36).abs() < 1e-3 && (report.suggested_ev + 1.0).abs() < 1e-3);
+        // A stop under middle gray is at -3.47 EV, the 34th quarter stop bin from -12 EV
+        assert_eq!((report.histogram.len(), report.histogram[34], report.histogram.iter().sum::<u32>()), (64, 2, 4));
+    }
+}
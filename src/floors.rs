@@ -0,0 +1,168 @@
+// Author: Giulian Biolo, github.com/giulianbiolo
+// Date: 16/10/2026
+// Description: This file implements the multi-floor power grid, slicing the building into horizontal floors with a heatmap and the coverage statistics of each one
+
+use std::io::Write;
+use std::sync::Arc;
+
+use glam::Vec2;
+use image::{ImageBuffer, Luma};
+use yaml_rust::Yaml;
+
+use crate::camera::Camera;
+use crate::hittable_list::{Hittable, HittableList};
+use crate::link_budget::LinkBudget;
+use crate::parallel::*;
+use crate::parser;
+use crate::point3::Point3;
+use crate::power_report::{self, Report, ReportOptions, Volume};
+use crate::raytracer;
+use crate::utility;
+
+
+// A horizontal slab of the building, from its elevation up to its height above it
+#[derive(Debug, Clone, PartialEq)]
+pub struct Floor {
+    pub name: String,
+    pub elevation: f32,
+    pub height: f32,
+}
+
+// The powerGrid block of the scene:
+// powerGrid: { min: [x, z], max: [x, z], step: 0.2, receiverHeight: 1.0, floors: [{ name: ground, elevation: 0.0, height: 3.0 }, ..] }
+// The thresholds of the coverage are the ones of the report
+#[derive(Debug, Clone, PartialEq)]
+pub struct FloorGrid {
+    // The horizontal extent of the grid, the same for every floor
+    pub min: Vec2,
+    pub max: Vec2,
+    pub step: f32,
+    // How high above its floor the power is sampled, where the devices are
+    pub receiver_height: f32,
+    pub floors: Vec<Floor>,
+}
+
+fn _parse_f32(value: &Yaml) -> Option<f32> { value.as_f64().or(value.as_i64().map(|value| value as f64)).map(|value| value as f32) }
+
+fn _parse_vec2(value: &Yaml, key: &str) -> Vec2 {
+    let values: Vec<f32> = value.as_vec().unwrap_or_else(|| panic!("Missing the {} [x, z] of the power grid", key)).iter().map(|value| _parse_f32(value).unwrap()).collect();
+    Vec2::new(values[0], values[1])
+}
+
+impl FloorGrid {
+    // None when the scene has no floors in its powerGrid block
+    pub fn parse(doc: &Yaml) -> Option<FloorGrid> {
+        let block: &Yaml = &doc["powerGrid"];
+        let floors: Vec<Floor> = block["floors"].as_vec()?.iter().enumerate().map(|(i, floor)| Floor {
+            name: floor["name"].as_str().map_or_else(|| format!("floor{}", i), str::to_string),
+            elevation: _parse_f32(&floor["elevation"]).unwrap_or_else(|| panic!("Missing the elevation of floor {} of the power grid", i)),
+            height: _parse_f32(&floor["height"]).unwrap_or(3.0),
+        }).collect();
+        Some(FloorGrid {
+            min: _parse_vec2(&block["min"], "min"),
+            max: _parse_vec2(&block["max"], "max"),
+            step: _parse_f32(&block["step"]).unwrap_or(0.2),
+            receiver_height: _parse_f32(&block["receiverHeight"]).unwrap_or(1.0),
+            floors,
+        })
+    }
+    // Points along x and along z, both corners included
+    pub fn dims(&self) -> (usize, usize) {
+        let count = |extent: f32| (extent / self.step + 1e-3).floor().max(0.0) as usize + 1;
+        (count(self.max.x - self.min.x), count(self.max.y - self.min.y))
+    }
+    // The points of the floor, in rows along x from the lowest z, at the receiver height clamped inside of the slab
+    pub fn points(&self, floor: &Floor) -> Vec<Point3> {
+        let (width, depth) = self.dims();
+        let y: f32 = floor.elevation + self.receiver_height.clamp(0.0, floor.height);
+        (0..width * depth).map(|i| Point3::new(self.min.x + (i % width) as f32 * self.step, y, self.min.y + (i / width) as f32 * self.step)).collect()
+    }
+}
+
+// The median of every point and its neighbours within the slice, against the noise of the paths
+pub fn median_filter(values: &[f32], width: usize, depth: usize) -> Vec<f32> {
+    (0..width * depth).map(|i| {
+        let (x, z) = ((i % width) as isize, (i / width) as isize);
+        let mut neighbours: Vec<f32> = (-1..=1).flat_map(|dz| (-1..=1).map(move |dx| (x + dx, z + dz)))
+            .filter(|(x, z)| *x >= 0 && *z >= 0 && (*x as usize) < width && (*z as usize) < depth)
+            .map(|(x, z)| values[z as usize * width + x as usize])
+            .collect();
+        neighbours.sort_by(f32::total_cmp);
+        neighbours[neighbours.len() / 2]
+    }).collect()
+}
+
+// Saves a heatmap and the decibels of every floor, power_grid_<floor>.png and power_grid_<floor>_db.bin, and the coverage report of all of them
+// The heatmaps share the same scale, from the weakest to the strongest finite point of the building
+pub fn render(world: &HittableList, grid: &FloorGrid, options: &ReportOptions) {
+    let (environment_map, _): (Arc<dyn Hittable + Send + Sync>, f32) = utility::load_environment(world, &utility::CONSTS);
+    let mut lights: HittableList = raytracer::get_lights(world);
+    if utility::environment_is_light(&utility::CONSTS) { lights.push(environment_map.clone()); }
+    let lights: HittableList = raytracer::sample_lights(world, lights);
+    let (width, depth) = grid.dims();
+    println!("Power grid of {} floors, {}x{} points each", grid.floors.len(), width, depth);
+    let powers: Vec<Vec<f32>> = grid.floors.iter().enumerate().map(|(i, floor)| {
+        let powers: Vec<f32> = grid.points(floor).into_par_iter().map(|point| raytracer::power_at(&point, world, &lights, &environment_map)).collect();
+        print!("{:.2}% complete\r", (i + 1) as f32 / grid.floors.len() as f32 * 100.0);
+        let _ = std::io::stdout().flush();
+        median_filter(&powers, width, depth)
+    }).collect();
+    let calibration_offset: Option<f32> = raytracer::power_calibration(world, &environment_map);
+    let max: f32 = powers.iter().flatten().copied().fold(0.0, f32::max);
+    let (unit, to_db): (&str, Box<dyn Fn(f32) -> f32>) = match calibration_offset {
+        Some(offset) => ("dBm", Box::new(move |power| LinkBudget::to_dbm(power, offset))),
+        None => ("dB", Box::new(move |power| 10.0 * (power / max).log10())),
+    };
+    let decibels: Vec<Vec<f32>> = powers.iter().map(|powers| powers.iter().map(|power| to_db(*power)).collect()).collect();
+    let (low, high) = decibels.iter().flatten().filter(|value| value.is_finite()).fold((f32::INFINITY, f32::NEG_INFINITY), |(low, high), value| (low.min(*value), high.max(*value)));
+    let mut reports: Vec<Report> = Vec::new();
+    for (floor, decibels) in grid.floors.iter().zip(&decibels) {
+        let heatmap: ImageBuffer<Luma<u8>, Vec<u8>> = ImageBuffer::from_fn(width as u32, depth as u32, |x, z| {
+            let value: f32 = decibels[z as usize * width + x as usize];
+            Luma([if value.is_finite() && high > low { ((value - low) / (high - low) * 255.0) as u8 } else { 0 }])
+        });
+        let image_file: String = format!("power_grid_{}.png", floor.name);
+        heatmap.save(&image_file).unwrap_or_else(|err| panic!("Failed to write {}: {}", image_file, err));
+        // The same layout as the single power grid, with one row along y
+        let mut data: Vec<u8> = [width, 1, depth].iter().flat_map(|size| size.to_le_bytes()).collect();
+        data.extend(decibels.iter().flat_map(|value| value.to_le_bytes()));
+        let data_file: String = format!("power_grid_{}_db.bin", floor.name);
+        std::fs::write(&data_file, data).unwrap_or_else(|err| panic!("Failed to write {}: {}", data_file, err));
+        let origin: Point3 = grid.points(floor)[0];
+        let report: Report = power_report::report(&Volume { name: floor.name.clone(), origin, step: grid.step, dims: [width, 1, depth], values: decibels.clone() }, unit, options);
+        let coverage: String = report.coverage.iter().map(|coverage| format!(", {:.1}% above {:.1} {}", coverage.percent, coverage.threshold, unit)).collect();
+        println!("Floor {} at {:.2}m: min {:.1} {}, mean {:.1} {}, median {:.1} {}{}", floor.name, floor.elevation, report.minimum, unit, report.mean, unit, report.median, unit, coverage);
+        reports.push(report);
+    }
+    power_report::save(&reports, "power_grid");
+}
+
+// The floors of the powerGrid block, or the single grid around the powerRenderCenter without them
+pub fn run(world: &HittableList, cam: Camera, config_file: &str, output: &str) {
+    let doc: Option<Yaml> = if config_file.is_empty() { None } else { parser::load_config(config_file) };
+    let options: ReportOptions = doc.as_ref().map_or_else(ReportOptions::default, ReportOptions::parse);
+    match doc.as_ref().and_then(FloorGrid::parse) {
+        Some(grid) => render(world, &grid, &options),
+        None => raytracer::render_power_grid(world, cam, output, &options),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_floor_grid() {
+        let doc: Yaml = yaml_rust::YamlLoader::load_from_str("{powerGrid: {min: [0, 0], max: [2.0, 1.0], step: 0.5, floors: [{name: ground, elevation: 0}, {elevation: 3.0, height: 0.5}]}}").unwrap().remove(0);
+        let grid: FloorGrid = FloorGrid::parse(&doc).unwrap();
+        assert_eq!((grid.dims(), grid.floors[1].name.as_str()), ((5, 3), "floor1"));
+        // The receivers stand inside of their own floor
+        let points: Vec<Point3> = grid.points(&grid.floors[1]);
+        assert_eq!((points.len(), points[0], points[14]), (15, Point3::new(0.0, 3.5, 0.0), Point3::new(2.0, 3.5, 1.0)));
+        assert!(FloorGrid::parse(&Yaml::Null).is_none());
+        // A lone spike of noise is smoothed away
+        let mut values: Vec<f32> = vec![1.0; 9];
+        values[4] = 100.0;
+        assert!(median_filter(&values, 3, 3).iter().all(|value| *value == 1.0));
+    }
+}
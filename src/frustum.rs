@@ -0,0 +1,85 @@
+// Author: Giulian Biolo, github.com/giulianbiolo
+// Date: 16/10/2026
+// Description: This file implements the view frustum of the camera, telling the objects it may see from their bounds without tracing any ray
+
+use bvh::aabb::AABB;
+use glam::Vec3A;
+
+use crate::camera::Camera;
+use crate::hittable_list::{self, HittableList};
+use crate::point3::Point3;
+
+
+// The volume seen through the center of the lens: the four planes through the sides of the image and the one of the lens, without a far plane
+// The rays of a wide aperture start off of the center, so with depth of field it leaves out what is only seen at the very border of the image
+pub struct Frustum {
+    // The points on the positive side of the planes (normal, offset) are inside
+    planes: [(Vec3A, f32); 5],
+}
+
+impl Frustum {
+    pub fn new(cam: &Camera) -> Frustum {
+        let corners: [Vec3A; 4] = [(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)].map(|(u, v)| cam.pinhole_direction(u, v));
+        let center: Vec3A = cam.pinhole_direction(0.5, 0.5);
+        let side = |idx: usize| -> (Vec3A, f32) {
+            let normal: Vec3A = corners[idx].cross(corners[(idx + 1) % 4]).normalize();
+            // Turned towards the middle of the image, whichever way around the corners go
+            let normal: Vec3A = if normal.dot(center) < 0.0 { -normal } else { normal };
+            (normal, -normal.dot(cam.origin))
+        };
+        Frustum { planes: [side(0), side(1), side(2), side(3), (-cam.w, cam.w.dot(cam.origin))] }
+    }
+    #[allow(dead_code)]
+    pub fn contains(&self, p: &Point3) -> bool { self.planes.iter().all(|(normal, offset)| normal.dot(*p) + offset >= 0.0) }
+    // Whether the box may be seen, false only when it's all outside of one of the planes
+    // So a box just outside of two sides next to a corner of the image passes, which is fine for culling
+    pub fn intersects(&self, bounds: &AABB) -> bool {
+        let corners: [Point3; 8] = hittable_list::bounds_corners(bounds);
+        self.planes.iter().all(|(normal, offset)| corners.iter().any(|corner| normal.dot(*corner) + offset >= 0.0))
+    }
+}
+
+// The indices of the objects of the world the camera may see, the unbounded ones always
+pub fn visible(world: &HittableList, cam: &Camera) -> Vec<usize> {
+    let frustum: Frustum = Frustum::new(cam);
+    world.iter().enumerate()
+        .filter(|(_, object)| object.world_bounds().is_none_or(|bounds| frustum.intersects(&bounds)))
+        .map(|(idx, _)| idx)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use glam::Affine3A;
+
+    use super::*;
+    use crate::color::Color;
+    use crate::hittable_list::Hittable;
+    use crate::material::Lambertian;
+    use crate::plane::Plane;
+    use crate::sphere::Sphere;
+    use crate::transform::Transformed;
+
+    fn _sphere(center: Point3) -> Arc<Sphere> { Arc::new(Sphere::new(center, 0.5, Box::new(Lambertian::new(Color::ONE)), 0)) }
+
+    #[test]
+    fn test_visible() {
+        // Looking down -z: the sphere ahead is seen, the ones behind and far to the side aren't, the floor always is
+        let cam: Camera = Camera::new(&Vec3A::ZERO, &-Vec3A::Z, &Vec3A::Y, 60.0, 1.0, 0.0, 1.0);
+        let moved: Transformed = Transformed::new(_sphere(Point3::ZERO), Affine3A::from_translation(glam::Vec3::new(0.0, 0.0, -5.0)));
+        let world: HittableList = vec![
+            _sphere(Point3::new(0.0, 0.0, -5.0)),
+            _sphere(Point3::new(0.0, 0.0, 5.0)),
+            _sphere(Point3::new(20.0, 0.0, -5.0)),
+            Arc::new(Plane::new(Point3::new(0.0, -1.0, 0.0), Vec3A::Y, Box::new(Lambertian::new(Color::ONE)))),
+            Arc::new(moved),
+        ];
+        assert_eq!(visible(&world, &cam), vec![0, 3, 4]);
+        // The bounds of the scene leave the floor out, and follow the transform of the last sphere
+        let bounds: AABB = world.world_bounds().unwrap();
+        assert_eq!((bounds.min.x, bounds.max.x, bounds.min.z, bounds.max.z), (-0.5, 20.5, -5.5, 5.5));
+        assert!(Frustum::new(&cam).contains(&Point3::new(0.0, 0.0, -1.0)) && !Frustum::new(&cam).contains(&Point3::new(0.0, 2.0, -1.0)));
+    }
+}
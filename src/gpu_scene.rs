@@ -0,0 +1,303 @@
+// Author: Giulian Biolo, github.com/giulianbiolo
+// Date: 16/10/2026
+// Description: This file implements the flattened export of the scene, its triangles, BVH nodes, material table and textures packed into GPU friendly buffers
+
+use std::path::Path;
+
+use bvh::aabb::{AABB, Bounded};
+use bvh::bounding_hierarchy::BHShape;
+use bvh::bvh::{BVH, BVHNode};
+use bvh::Point3 as BVHPoint3;
+use glam::{Vec2, Vec3A};
+use image::{DynamicImage, RgbaImage};
+use serde::Serialize;
+use yaml_rust::{Yaml, YamlEmitter};
+
+use crate::acceleration::{self, BVHParams};
+use crate::assets;
+use crate::hittable_list::HittableList;
+use crate::parser;
+use crate::point3::Point3;
+use crate::scene_desc::MaterialDesc;
+use crate::utility::PI;
+
+
+// A triangle of the surface of an object, the vertices in world space with their shading normals and texture coordinates
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FlatTriangle {
+    pub vertices: [Point3; 3],
+    pub normals: [Vec3A; 3],
+    pub uvs: [Vec2; 3],
+}
+
+// The rings and segments of the tessellated spheres
+pub const SPHERE_RINGS: usize = 16;
+pub const SPHERE_SEGMENTS: usize = 32;
+
+// The material types by their index in the material table, the others being exported as their albedo only
+pub const MATERIAL_KINDS: [&str; 11] = ["Lambertian", "Metal", "Dielectric", "Plastic", "GGX", "MetallicRoughness", "Coated", "Conductor", "DiffuseLight", "Translucent", "Fluorescent"];
+// The key of the single scalar parameter of each material, in the order of MATERIAL_KINDS
+const MATERIAL_PARAMETERS: [&str; 11] = ["", "fuzz", "refractionIdx", "roughness", "roughness", "roughness", "roughness", "roughness", "intensity", "transmission", "efficiency"];
+
+// A flat polygon fanned into triangles, for the objects made of faces
+pub fn polygon_triangles(corners: &[Point3], normal: Vec3A, uvs: &[Vec2]) -> Vec<FlatTriangle> {
+    (1..corners.len().saturating_sub(1)).map(|i| FlatTriangle {
+        vertices: [corners[0], corners[i], corners[i + 1]],
+        normals: [normal; 3],
+        uvs: [uvs[0], uvs[i], uvs[i + 1]],
+    }).collect()
+}
+
+// The longitude/latitude grid of a sphere, from its uvs back to its positions, so the texture maps as on the analytic one
+pub fn sphere_triangles(center: Point3, radius: f32, map_uv: impl Fn(f32, f32) -> (f32, f32)) -> Vec<FlatTriangle> {
+    let vertex = |ring: usize, segment: usize| -> (Point3, Vec3A, Vec2) {
+        let (u, v) = (segment as f32 / SPHERE_SEGMENTS as f32, ring as f32 / SPHERE_RINGS as f32);
+        let (angle, polar) = (2.0 * PI * u - PI, PI * v);
+        let normal: Vec3A = Vec3A::new(angle.cos() * polar.sin(), -polar.cos(), -angle.sin() * polar.sin());
+        let (u, v) = map_uv(u, v);
+        (center + normal * radius, normal, Vec2::new(u, v))
+    };
+    let triangle = |a: (Point3, Vec3A, Vec2), b: (Point3, Vec3A, Vec2), c: (Point3, Vec3A, Vec2)| FlatTriangle { vertices: [a.0, b.0, c.0], normals: [a.1, b.1, c.1], uvs: [a.2, b.2, c.2] };
+    (0..SPHERE_RINGS).flat_map(|ring| (0..SPHERE_SEGMENTS).map(move |segment| (ring, segment))).flat_map(|(ring, segment)| {
+        let (a, b, c, d) = (vertex(ring, segment), vertex(ring, segment + 1), vertex(ring + 1, segment + 1), vertex(ring + 1, segment));
+        // The poles only need one of the two triangles of their quads
+        let mut triangles: Vec<FlatTriangle> = Vec::with_capacity(2);
+        if ring + 1 < SPHERE_RINGS { triangles.push(triangle(a, c, d)); }
+        if ring > 0 { triangles.push(triangle(a, b, c)); }
+        triangles
+    }).collect()
+}
+
+/****************** Packing ******************/
+// A node of the flattened BVH: an inner node has its left child right after it and the right one at left_first, a leaf has count triangles from left_first
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GpuNode {
+    pub min: [f32; 3],
+    pub left_first: u32,
+    pub max: [f32; 3],
+    pub count: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GpuMaterial {
+    pub albedo: [f32; 3],
+    pub parameter: f32,
+    // The index in MATERIAL_KINDS, or u32::MAX for the other types, and the texture of the albedo or u32::MAX
+    pub kind: u32,
+    pub texture: u32,
+}
+
+// The RGBA8 texels of every texture one after the other, a texture starting at its offset (in texels)
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct GpuTexture {
+    pub offset: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Manifest {
+    pub triangles: usize,
+    pub nodes: usize,
+    pub material_kinds: Vec<String>,
+    pub materials: Vec<Option<MaterialDesc>>,
+    pub textures: Vec<GpuTexture>,
+    pub texture_files: Vec<String>,
+    // The objects of the world without any triangles, the curved ones and the media
+    pub skipped_objects: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct GpuScene {
+    // In the order of the leaves of the BVH, with the index of their material
+    pub triangles: Vec<(FlatTriangle, u32)>,
+    pub nodes: Vec<GpuNode>,
+    pub materials: Vec<GpuMaterial>,
+    pub material_descs: Vec<Option<MaterialDesc>>,
+    pub textures: Vec<GpuTexture>,
+    pub texture_files: Vec<String>,
+    pub texels: Vec<u8>,
+    pub skipped_objects: usize,
+}
+
+struct _Primitive {
+    aabb: AABB,
+    node_index: usize,
+}
+
+impl Bounded for _Primitive {
+    fn aabb(&self) -> AABB { self.aabb }
+}
+
+impl BHShape for _Primitive {
+    fn set_bh_node_index(&mut self, index: usize) { self.node_index = index; }
+    fn bh_node_index(&self) -> usize { self.node_index }
+}
+
+// Depth first, so the left child of an inner node always follows it
+fn _flatten(bvh: &BVH, node: usize, aabb: AABB, order: &mut Vec<usize>, nodes: &mut Vec<GpuNode>) {
+    let index: usize = nodes.len();
+    nodes.push(GpuNode { min: aabb.min.to_array(), left_first: order.len() as u32, max: aabb.max.to_array(), count: 1 });
+    match bvh.nodes[node] {
+        BVHNode::Leaf { shape_index, .. } => order.push(shape_index),
+        BVHNode::Node { child_l_index, child_l_aabb, child_r_index, child_r_aabb, .. } => {
+            _flatten(bvh, child_l_index, child_l_aabb, order, nodes);
+            nodes[index].left_first = nodes.len() as u32;
+            nodes[index].count = 0;
+            _flatten(bvh, child_r_index, child_r_aabb, order, nodes);
+        },
+    }
+}
+
+fn _material_desc(material: &Yaml) -> Option<MaterialDesc> {
+    if material.is_badvalue() { return None; }
+    let mut content: String = String::new();
+    YamlEmitter::new(&mut content).dump(material).ok()?;
+    serde_yaml::from_str(&content).ok()
+}
+
+fn _albedo(material: &Yaml) -> [f32; 3] {
+    let albedo: Vec<f32> = material["texture"]["albedo"].as_vec().map_or_else(Vec::new, |albedo| albedo.iter().filter_map(|value| value.as_f64().or(value.as_i64().map(|value| value as f64))).map(|value| value as f32).collect());
+    if albedo.len() == 3 { [albedo[0], albedo[1], albedo[2]] } else { [1.0; 3] }
+}
+
+impl GpuScene {
+    // The world paired with the leaves of its scene file, whose materials make the table, the same materials sharing an entry
+    pub fn flatten(world: &HittableList, leaves: &[Yaml]) -> GpuScene {
+        let mut scene: GpuScene = GpuScene { triangles: Vec::new(), nodes: Vec::new(), materials: Vec::new(), material_descs: Vec::new(), textures: Vec::new(), texture_files: Vec::new(), texels: Vec::new(), skipped_objects: 0 };
+        let mut keys: Vec<Yaml> = Vec::new();
+        let mut triangles: Vec<(FlatTriangle, u32)> = Vec::new();
+        for (i, object) in world.iter().enumerate() {
+            let tessellation: Vec<FlatTriangle> = object.tessellation();
+            if tessellation.is_empty() { scene.skipped_objects += 1; continue; }
+            let material: Yaml = leaves.get(i).map_or(Yaml::BadValue, |leaf| leaf["material"].clone());
+            let index: usize = keys.iter().position(|key| *key == material).unwrap_or_else(|| {
+                keys.push(material.clone());
+                let packed: GpuMaterial = scene._pack_material(&material);
+                scene.materials.push(packed);
+                scene.material_descs.push(_material_desc(&material));
+                keys.len() - 1
+            });
+            triangles.extend(tessellation.into_iter().map(|triangle| (triangle, index as u32)));
+        }
+        let mut primitives: Vec<_Primitive> = triangles.iter().map(|(triangle, _)| {
+            let (min, max) = (triangle.vertices[0].min(triangle.vertices[1]).min(triangle.vertices[2]), triangle.vertices[0].max(triangle.vertices[1]).max(triangle.vertices[2]));
+            _Primitive { aabb: AABB::with_bounds(BVHPoint3::new(min.x, min.y, min.z), BVHPoint3::new(max.x, max.y, max.z)), node_index: 0 }
+        }).collect();
+        if primitives.is_empty() { return scene; }
+        let bvh: BVH = acceleration::build(&mut primitives, &BVHParams::default());
+        let root: AABB = primitives.iter().fold(AABB::empty(), |aabb, primitive| aabb.join(&primitive.aabb));
+        let mut order: Vec<usize> = Vec::with_capacity(triangles.len());
+        _flatten(&bvh, 0, root, &mut order, &mut scene.nodes);
+        scene.triangles = order.iter().map(|index| triangles[*index]).collect();
+        scene
+    }
+    fn _pack_material(&mut self, material: &Yaml) -> GpuMaterial {
+        let kind: Option<usize> = material["matType"].as_str().and_then(|name| MATERIAL_KINDS.iter().position(|kind| *kind == name));
+        let parameter: f32 = kind.and_then(|kind| {
+            let value: &Yaml = &material[MATERIAL_PARAMETERS[kind]];
+            value.as_f64().or(value.as_i64().map(|value| value as f64))
+        }).unwrap_or(0.0) as f32;
+        let texture: u32 = match (material["texType"].as_str(), material["texture"]["filename"].as_str()) {
+            (Some("ImageTexture"), Some(filename)) => self._texture(filename),
+            _ => u32::MAX,
+        };
+        GpuMaterial { albedo: _albedo(material), parameter, kind: kind.map_or(u32::MAX, |kind| kind as u32), texture }
+    }
+    // The index of the texture, loaded once for all of the materials using it
+    fn _texture(&mut self, filename: &str) -> u32 {
+        if let Some(index) = self.texture_files.iter().position(|file| file == filename) { return index as u32; }
+        let image: DynamicImage = assets::open_image(filename).unwrap_or_else(|err| panic!("Failed to load the texture {}: {}", filename, err));
+        let image: RgbaImage = image.to_rgba8();
+        self.textures.push(GpuTexture { offset: (self.texels.len() / 4) as u32, width: image.width(), height: image.height() });
+        self.texels.extend_from_slice(image.as_raw());
+        self.texture_files.push(filename.to_string());
+        (self.textures.len() - 1) as u32
+    }
+
+    /****************** Buffers ******************/
+    // 3 vertices of 8 f32 (position and u, normal and v), then the material and 3 u32 of padding: 112 bytes
+    pub fn triangle_buffer(&self) -> Vec<u8> {
+        self.triangles.iter().flat_map(|(triangle, material)| {
+            let mut words: Vec<[u8; 4]> = (0..3).flat_map(|i| {
+                let (position, normal, uv) = (triangle.vertices[i], triangle.normals[i], triangle.uvs[i]);
+                [position.x, position.y, position.z, uv.x, normal.x, normal.y, normal.z, uv.y].map(f32::to_le_bytes)
+            }).collect();
+            words.extend([*material, 0, 0, 0].map(u32::to_le_bytes));
+            words.into_iter().flatten()
+        }).collect()
+    }
+    // The min and left_first, then the max and count: 32 bytes
+    pub fn node_buffer(&self) -> Vec<u8> {
+        self.nodes.iter().flat_map(|node| {
+            let [a, b, c] = node.min.map(f32::to_le_bytes);
+            let [d, e, f] = node.max.map(f32::to_le_bytes);
+            [a, b, c, node.left_first.to_le_bytes(), d, e, f, node.count.to_le_bytes()].into_iter().flatten()
+        }).collect()
+    }
+    // The albedo and the parameter, then the kind, the texture and 2 u32 of padding: 32 bytes
+    pub fn material_buffer(&self) -> Vec<u8> {
+        self.materials.iter().flat_map(|material| {
+            let [r, g, b] = material.albedo.map(f32::to_le_bytes);
+            [r, g, b, material.parameter.to_le_bytes(), material.kind.to_le_bytes(), material.texture.to_le_bytes(), [0; 4], [0; 4]].into_iter().flatten()
+        }).collect()
+    }
+    pub fn manifest(&self) -> Manifest {
+        Manifest {
+            triangles: self.triangles.len(),
+            nodes: self.nodes.len(),
+            material_kinds: MATERIAL_KINDS.iter().map(|kind| kind.to_string()).collect(),
+            materials: self.material_descs.clone(),
+            textures: self.textures.clone(),
+            texture_files: self.texture_files.clone(),
+            skipped_objects: self.skipped_objects,
+        }
+    }
+    // triangles.bin, nodes.bin, materials.bin, textures.bin and scene.json in the directory, all little endian
+    pub fn save(&self, directory: &str) -> std::io::Result<()> {
+        std::fs::create_dir_all(directory)?;
+        let path = |name: &str| Path::new(directory).join(name);
+        std::fs::write(path("triangles.bin"), self.triangle_buffer())?;
+        std::fs::write(path("nodes.bin"), self.node_buffer())?;
+        std::fs::write(path("materials.bin"), self.material_buffer())?;
+        std::fs::write(path("textures.bin"), &self.texels)?;
+        std::fs::write(path("scene.json"), serde_json::to_string_pretty(&self.manifest()).map_err(std::io::Error::other)?)
+    }
+}
+
+pub fn run(config_file: &str, directory: &str) {
+    let doc: Yaml = parser::load_config(config_file).unwrap_or_else(|| panic!("Failed to load the config file {}", config_file));
+    let world: HittableList = parser::parse_scene(&doc);
+    let scene: GpuScene = GpuScene::flatten(&world, &parser::parse_object_leaves(&doc));
+    scene.save(directory).unwrap_or_else(|err| panic!("Failed to write the GPU scene to {}: {}", directory, err));
+    println!("Saved {} triangles, {} BVH nodes, {} materials and {} textures to {}", scene.triangles.len(), scene.nodes.len(), scene.materials.len(), scene.textures.len(), directory);
+    if scene.skipped_objects > 0 { println!("Skipped {} objects without triangles", scene.skipped_objects); }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gpu_scene() {
+        let doc: Yaml = yaml_rust::YamlLoader::load_from_str("{world: [
+            {objType: Box, position: [0.0, 0.0, 0.0], width: 1.0, height: 1.0, depth: 1.0, material: {matType: Metal, fuzz: 0.25, texType: SolidColor, texture: {albedo: [0.8, 0.2, 0.1]}}},
+            {objType: Sphere, center: [3.0, 0.0, 0.0], radius: 0.5, material: {matType: Metal, fuzz: 0.25, texType: SolidColor, texture: {albedo: [0.8, 0.2, 0.1]}}},
+            {objType: Group, transform: {translate: [0.0, 2.0, 0.0]}, children: [{objType: Box, position: [0.0, 0.0, 0.0], width: 1.0, height: 1.0, depth: 1.0, material: {matType: DiffuseLight, intensity: 4.0, texType: SolidColor, texture: {albedo: [1.0, 1.0, 1.0]}}}]}
+        ]}").unwrap().remove(0);
+        let world: HittableList = parser::parse_scene(&doc);
+        let scene: GpuScene = GpuScene::flatten(&world, &parser::parse_object_leaves(&doc));
+        // The boxes are 12 triangles, the sphere shares the material of the first box
+        let sphere: usize = SPHERE_SEGMENTS * (2 * SPHERE_RINGS - 2);
+        assert_eq!((scene.triangles.len(), scene.nodes.len(), scene.materials.len()), (24 + sphere, 2 * (24 + sphere) - 1, 2));
+        assert_eq!(scene.materials[0], GpuMaterial { albedo: [0.8, 0.2, 0.1], parameter: 0.25, kind: 1, texture: u32::MAX });
+        assert_eq!((scene.materials[1].kind, scene.materials[1].parameter), (8, 4.0));
+        assert!(scene.triangles.iter().filter(|(_, material)| *material == 1).all(|(triangle, _)| triangle.vertices.iter().all(|vertex| vertex.y >= 1.5 - 1e-4)));
+        // Every vertex of the sphere lies on it, and the root bounds the whole scene
+        assert!(world[1].tessellation().iter().flat_map(|triangle| triangle.vertices).all(|vertex| ((vertex - Point3::X * 3.0).length() - 0.5).abs() < 1e-4));
+        assert_eq!((scene.nodes[0].min, scene.nodes[0].max), ([-0.5, -0.5, -0.5], [3.5, 2.5, 0.5]));
+        assert_eq!((scene.triangle_buffer().len(), scene.node_buffer().len(), scene.material_buffer().len()), (112 * scene.triangles.len(), 32 * scene.nodes.len(), 64));
+        assert_eq!(scene.manifest().materials[1].as_ref().map(|material| material.mat_type.as_str()), Some("DiffuseLight"));
+    }
+}
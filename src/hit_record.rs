@@ -2,37 +2,73 @@
 // Date: 24/01/2023
 // Description: This file implements the HitRecord struct
 
-use glam::Vec3A;
+use glam::{Vec2, Vec3A};
+use lazy_static::lazy_static;
 
+use crate::light_linking::UNLINKED;
 use crate::ray::Ray;
 use crate::material::{Material, Lambertian};
 use crate::color::Color;
 use crate::point3::Point3;
 
+// Material of the default (empty) records
+lazy_static! { static ref NO_MATERIAL: Lambertian = Lambertian::new(Color::new(0.0, 0.0, 0.0)); }
+
+// The material is borrowed from the object that was hit, so that hits don't allocate
 #[derive(Clone)]
-pub struct HitRecord {
+pub struct HitRecord<'a> {
     pub p: Point3,
     pub normal: Vec3A,
-    pub mat_ptr: Box<dyn Material>,
+    pub mat_ptr: &'a dyn Material,
     pub t: f32,
     pub u: f32,
     pub v: f32,
     pub front_face: bool,
+    // Derivatives of the position along the texture coordinates, zero when the surface doesn't provide them
+    pub dpdu: Vec3A,
+    pub dpdv: Vec3A,
+    // Derivatives of the texture coordinates across the pixel, from the ray differentials
+    pub duvdx: Vec2,
+    pub duvdy: Vec2,
+    // Light linking bit of the object that was hit, and the bits of the lights allowed to illuminate it
+    pub link_id: u64,
+    pub link_mask: u64,
+    // How concave the surface is around the hit, from 0 (flat or convex) to 1, only computed by the meshes with dirt
+    pub cavity: f32,
+    // Distance from the hit to the nearest edge of the triangle, only computed by the meshes with a wireframe
+    pub edge_distance: f32,
+    // Index of the instance of the group the object was placed by, and of the primitive inside of the object (a sphere of an array, a triangle of a mesh), for the textures that vary per instance
+    pub instance: u32,
+    pub primitive: u32,
 }
 
-impl HitRecord {
+impl<'a> HitRecord<'a> {
     pub fn empty() -> Self {
         HitRecord {
             p: Point3::new(0.0, 0.0, 0.0),
             normal: Vec3A::new(0.0, 0.0, 0.0),
-            mat_ptr: Box::new(Lambertian::new(Color::new(0.0, 0.0, 0.0))),
+            mat_ptr: &*NO_MATERIAL,
             t: 0.0,
             u: 0.0,
             v: 0.0,
-            front_face: false
+            front_face: false,
+            dpdu: Vec3A::ZERO,
+            dpdv: Vec3A::ZERO,
+            duvdx: Vec2::ZERO,
+            duvdy: Vec2::ZERO,
+            link_id: UNLINKED,
+            link_mask: u64::MAX,
+            cavity: 0.0,
+            edge_distance: f32::INFINITY,
+            instance: 0,
+            primitive: 0,
         }
     }
-    pub fn new(p: Point3, normal: Vec3A, mat_ptr: Box<dyn Material>, t: f32, u: f32, v: f32, front_face: bool) -> Self { HitRecord { p, normal, mat_ptr, t, u, v, front_face } }
+    pub fn new(p: Point3, normal: Vec3A, mat_ptr: &'a dyn Material, t: f32, u: f32, v: f32, front_face: bool) -> Self {
+        HitRecord { p, normal, mat_ptr, t, u, v, front_face, dpdu: Vec3A::ZERO, dpdv: Vec3A::ZERO, duvdx: Vec2::ZERO, duvdy: Vec2::ZERO, link_id: UNLINKED, link_mask: u64::MAX, cavity: 0.0, edge_distance: f32::INFINITY, instance: 0, primitive: 0 }
+    }
+    // Width of the pixel footprint in texture space, zero for point sampling
+    pub fn footprint(&self) -> f32 { self.duvdx.length().max(self.duvdy.length()) }
     pub fn set_face_normal(&mut self, ray: &Ray, outward_normal: &Vec3A) {
         // This function is used to determine whether the ray is inside or outside the object.
         self.front_face = ray.direction().dot(*outward_normal) < 0.0;
@@ -41,7 +77,7 @@ impl HitRecord {
     pub fn reset(&mut self) {
         self.p = Point3::new(0.0, 0.0, 0.0);
         self.normal = Vec3A::new(0.0, 0.0, 0.0);
-        self.mat_ptr = Box::new(Lambertian::new(Color::new(0.0, 0.0, 0.0)));
+        self.mat_ptr = &*NO_MATERIAL;
         self.t = 0.0;
         self.front_face = false;
     }
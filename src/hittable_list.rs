@@ -2,39 +2,113 @@
 // Date: 24/01/2023
 // Description: This file implements the HittableList struct and the Hittable trait
 
+use std::any::Any;
 use std::sync::Arc;
 
+use bvh::aabb::AABB;
 use glam::Vec3A;
 
+use crate::diffraction::Edge;
+use crate::gpu_scene::FlatTriangle;
 use crate::hit_record::HitRecord;
+use crate::light_sampler::LightEstimate;
 use crate::ray::Ray;
 use crate::point3::Point3;
 use crate::utility;
 
 
-pub trait Hittable: Sync + Send {
+// Lets the world objects be downcast back to their concrete type, implemented for every type
+#[allow(dead_code)]
+pub trait AsAny {
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+impl<T: Any> AsAny for T {
+    fn as_any(&self) -> &dyn Any { self }
+    fn as_any_mut(&mut self) -> &mut dyn Any { self }
+}
+
+pub trait Hittable: Sync + Send + AsAny {
     // The hit function returns true if the ray hits the object.
-    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord>;
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>>;
+    // Whether anything blocks the ray in (t_min, t_max), for shadow rays which don't need the closest hit nor its HitRecord
+    #[allow(dead_code)]
+    fn occluded(&self, ray: &Ray, t_min: f32, t_max: f32) -> bool { self.hit(ray, t_min, t_max).is_some() }
     fn is_light(&self) -> bool;
+    // Where and how bright an emitter is, for picking which light to sample
+    fn light_estimate(&self) -> Option<LightEstimate> { None }
     fn pdf_value(&self, _o: &Point3, _v: &Vec3A) -> f32 { 0.0 }
     fn random(&self, _o: &Point3) -> Vec3A { Vec3A::X }
+    // The straight edges of the faces of the object, for the diffraction of the RF simulations, none for the curved ones
+    fn edges(&self) -> Vec<Edge> { self.wrapped().map_or_else(Vec::new, |object| object.edges()) }
+    // The triangles of the surface of the object in world space, for the GPU export, none for the objects that can't be tessellated
+    fn tessellation(&self) -> Vec<FlatTriangle> { self.wrapped().map_or_else(Vec::new, |object| object.tessellation()) }
+    // The box around the object in world space, through its wrappers and transforms, None for the unbounded ones (planes, the environment)
+    #[allow(dead_code)]
+    fn world_bounds(&self) -> Option<AABB> { self.wrapped().and_then(|object| object.world_bounds()) }
+    // The object wrapped by this one (clipped, light linked, ..), so that the queries see through the wrappers
+    #[allow(dead_code)]
+    fn wrapped(&self) -> Option<&(dyn Hittable + Send + Sync)> { None }
+    #[allow(dead_code)]
+    fn wrapped_mut(&mut self) -> Option<&mut (dyn Hittable + Send + Sync + 'static)> { None }
 }
 
 pub type HittableList = Vec<Arc<dyn Hittable + Sync + Send>>;
 
 impl Hittable for HittableList {
-    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
         self.iter()
         .filter_map(|object| object.hit(ray, t_min, t_max))
         .filter(|hit| hit.t > t_min && hit.t < t_max)
         .min_by(|hit1, hit2| { hit1.t.partial_cmp(&hit2.t).unwrap() })
     }
+    fn occluded(&self, ray: &Ray, t_min: f32, t_max: f32) -> bool { self.iter().any(|object| object.occluded(ray, t_min, t_max)) }
     fn is_light(&self) -> bool { false }
+    fn light_estimate(&self) -> Option<LightEstimate> { LightEstimate::merge(self.iter().filter_map(|object| object.light_estimate())) }
     fn pdf_value(&self, origin: &Point3, v: &Vec3A) -> f32 {
         let weight: f32 = 1.0 / self.len() as f32;
         self.iter().map(|object| weight * object.pdf_value(origin, v)).sum()
     }
     fn random(&self, o: &Point3) -> Vec3A { self[utility::random_usize_range(0, self.len())].random(o) }
+    fn edges(&self) -> Vec<Edge> { self.iter().flat_map(|object| object.edges()).collect() }
+    fn tessellation(&self) -> Vec<FlatTriangle> { self.iter().flat_map(|object| object.tessellation()).collect() }
+    // The box around the bounded objects, the unbounded ones are left out rather than making the whole scene unbounded
+    fn world_bounds(&self) -> Option<AABB> { self.iter().filter_map(|object| object.world_bounds()).reduce(|a, b| a.join(&b)) }
+}
+
+// The eight corners of a box, the bits of the index choosing the max side along x, y and z
+#[allow(dead_code)]
+pub fn bounds_corners(bounds: &AABB) -> [Point3; 8] {
+    std::array::from_fn(|idx| Point3::new(
+        if idx & 1 == 0 { bounds.min.x } else { bounds.max.x },
+        if idx & 2 == 0 { bounds.min.y } else { bounds.max.y },
+        if idx & 4 == 0 { bounds.min.z } else { bounds.max.z },
+    ))
+}
+
+/****************** Typed queries ******************/
+// The object as its concrete type, e.g. downcast_ref::<Sphere>(&*world[0]), looking through the wrappers around it
+// Called on the object itself and not on the Arc, whose own as_any would downcast the Arc
+#[allow(dead_code)]
+pub fn downcast_ref<T: Hittable + 'static>(object: &(dyn Hittable + Send + Sync)) -> Option<&T> {
+    (*object).as_any().downcast_ref::<T>().or_else(|| object.wrapped().and_then(downcast_ref))
+}
+
+// The object as its concrete type to modify it, None when the Arc is shared (the world should be the only owner between renders)
+#[allow(dead_code)]
+pub fn downcast_mut<T: Hittable + 'static>(object: &mut Arc<dyn Hittable + Send + Sync>) -> Option<&mut T> { _downcast_mut(Arc::get_mut(object)?) }
+
+#[allow(dead_code)]
+fn _downcast_mut<'a, T: Hittable + 'static>(object: &'a mut (dyn Hittable + Send + Sync + 'static)) -> Option<&'a mut T> {
+    if (*object).as_any().is::<T>() { return (*object).as_any_mut().downcast_mut::<T>(); }
+    object.wrapped_mut().and_then(_downcast_mut)
+}
+
+// The object of the world with the name given in the scene file, see parser::parse_object_names
+#[allow(dead_code)]
+pub fn find_named<'a, T: Hittable + 'static>(world: &'a HittableList, names: &[Option<String>], name: &str) -> Option<&'a T> {
+    names.iter().position(|object_name| object_name.as_deref() == Some(name)).and_then(|idx| downcast_ref(&*world[idx]))
 }
 
 impl std::fmt::Debug for dyn Hittable + Sync + Send {
@@ -49,6 +123,7 @@ mod tests {
     use crate::material::Lambertian;
     use crate::sphere::Sphere;
     use crate::color::Color;
+    use crate::light_linking::{self, LightLink, LinkRule};
     use crate::point3::Point3;
 
     #[test]
@@ -59,4 +134,26 @@ mod tests {
         let r: Ray = Ray::new(Point3::new(0.0, 0.0, 0.0), Point3::new(0.0, 0.0, -1.0));
         assert!(world.hit(&r, 0.0, 100.0).is_some());
     }
+    #[test]
+    fn test_occluded() {
+        let world: HittableList = vec![Arc::new(Sphere::new(Point3::new(0.0, 0.0, -1.0), 0.5, Box::new(Lambertian::new(Color::ONE)), 0))];
+        let r: Ray = Ray::new(Point3::new(0.0, 0.0, 0.0), Point3::new(0.0, 0.0, -1.0));
+        assert!(world.occluded(&r, 0.001, 100.0));
+        // The sphere is past the end of the shadow ray
+        assert!(!world.occluded(&r, 0.001, 0.4));
+        assert!(!world.occluded(&Ray::new(Point3::ZERO, Point3::new(0.0, 1.0, 0.0)), 0.001, 100.0));
+    }
+    #[test]
+    fn test_downcast() {
+        let sphere: Arc<dyn Hittable + Send + Sync> = Arc::new(Sphere::new(Point3::ZERO, 0.5, Box::new(Lambertian::new(Color::ONE)), 0));
+        let names: Vec<Option<String>> = vec![None, Some("ball".to_string())];
+        let links: Vec<LightLink> = vec![LightLink::new(None, LinkRule::All), LightLink::new(Some("ball".to_string()), LinkRule::Exclude(vec![]))];
+        let mut world: HittableList = light_linking::link(vec![Arc::new(Sphere::new(Point3::ONE, 1.0, Box::new(Lambertian::new(Color::ONE)), 0)), sphere], &links).unwrap();
+        // The named sphere is wrapped by the light linking
+        assert!((*world[1]).as_any().downcast_ref::<Sphere>().is_none());
+        assert_eq!(find_named::<Sphere>(&world, &names, "ball").unwrap().radius, 0.5);
+        assert!(find_named::<Sphere>(&world, &names, "missing").is_none());
+        downcast_mut::<Sphere>(&mut world[1]).unwrap().center = Point3::new(0.0, 0.0, -3.0);
+        assert!(world.hit(&Ray::new(Point3::ZERO, Point3::new(0.0, 0.0, -1.0)), 0.001, 100.0).is_some());
+    }
 }
@@ -21,4 +21,64 @@ pub mod parser;
 pub mod texture;
 pub mod sampling_filters;
 pub mod onb;
-pub mod pdf;
\ No newline at end of file
+pub mod pdf;
+pub mod cli;
+pub mod preview;
+pub mod medium;
+pub mod tiled_texture;
+pub mod environment;
+pub mod benchmark;
+pub mod audit;
+pub mod compare;
+pub mod simd;
+pub mod acceleration;
+pub mod light_sampler;
+pub mod light_linking;
+pub mod room;
+pub mod rounded_box;
+pub mod capsule;
+pub mod plane;
+pub mod clipping;
+pub mod sweep;
+pub mod parallel;
+pub mod assets;
+pub mod web;
+pub mod pack;
+pub mod memory;
+pub mod cancel;
+pub mod temporal;
+pub mod link_budget;
+pub mod transient;
+pub mod lidar;
+pub mod visibility;
+pub mod scene_desc;
+pub mod scene_builder;
+pub mod transform;
+pub mod scene;
+pub mod sampling;
+pub mod aov;
+pub mod adaptive;
+pub mod priority;
+pub mod mean_buffer;
+pub mod sh;
+pub mod camera_path;
+pub mod compositing;
+pub mod pixel_debug;
+pub mod nan_report;
+pub mod voxels;
+pub mod radiosity;
+pub mod placement;
+pub mod floors;
+pub mod diffraction;
+pub mod power_report;
+pub mod gpu_scene;
+pub mod line_art;
+pub mod uv_inspect;
+pub mod frustum;
+pub mod pick;
+pub mod manifold;
+pub mod log_file;
+pub mod config_dump;
+pub mod random_scene;
+pub mod matpreview;
+pub mod exposure;
\ No newline at end of file
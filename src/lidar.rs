@@ -0,0 +1,166 @@
+// Author: Giulian Biolo, github.com/giulianbiolo
+// Date: 16/10/2026
+// Description: This file implements the LiDAR simulation mode, scanning the scene from the camera and saving the returns as a PLY or PCD point cloud
+
+use std::io::Write;
+
+use bumpalo::Bump;
+use glam::Vec3A;
+
+use crate::camera::Camera;
+use crate::hittable_list::{Hittable, HittableList};
+use crate::light_sampler::luminance;
+use crate::material::ScatterRecord;
+use crate::parallel::*;
+use crate::point3::Point3;
+use crate::ray::Ray;
+use crate::utility;
+
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ScanPattern {
+    // A range image, one ray through the center of each pixel of the camera
+    Camera { width: u32, height: u32 },
+    // A spinning LiDAR around the vertical axis of the camera, with its channels spread between the two elevations (degrees)
+    Spinning { channels: u32, steps: u32, min_elevation: f32, max_elevation: f32 },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PointCloudFormat {
+    Ply,
+    Pcd,
+}
+
+impl PointCloudFormat {
+    pub fn from_name(name: &str) -> Result<PointCloudFormat, String> {
+        match name {
+            "ply" => Ok(PointCloudFormat::Ply),
+            "pcd" => Ok(PointCloudFormat::Pcd),
+            _ => Err(format!("Unknown point cloud format: {}, expected ply or pcd", name)),
+        }
+    }
+    pub fn extension(&self) -> &'static str {
+        match self {
+            PointCloudFormat::Ply => "ply",
+            PointCloudFormat::Pcd => "pcd",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Lidar {
+    pub pattern: ScanPattern,
+    pub max_range: f32,
+    // Standard deviation of the gaussian noise added to the ranges
+    pub range_noise: f32,
+    pub format: PointCloudFormat,
+}
+
+// A return of the scan, in world space
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LidarPoint {
+    pub position: Point3,
+    pub normal: Vec3A,
+    pub range: f32,
+    // Reflectance of the surface towards the sensor, its albedo times the cosine of the incidence angle
+    pub intensity: f32,
+}
+
+impl Lidar {
+    // The directions of the beams, in scan order
+    pub fn directions(&self, cam: &Camera) -> Vec<Vec3A> {
+        match self.pattern {
+            ScanPattern::Camera { width, height } => (0..height).flat_map(|y| (0..width).map(move |x| {
+                let (u, v) = ((x as f32 + 0.5) / (width as f32 - 1.0), (height as f32 - (y as f32 + 0.5)) / (height as f32 - 1.0));
+                cam.pinhole_direction(u, v).normalize()
+            })).collect(),
+            ScanPattern::Spinning { channels, steps, min_elevation, max_elevation } => (0..channels).flat_map(|channel| (0..steps).map(move |step| {
+                let elevation: f32 = if channels > 1 { min_elevation + (max_elevation - min_elevation) * channel as f32 / (channels - 1) as f32 } else { min_elevation }.to_radians();
+                let azimuth: f32 = 2.0 * utility::PI * step as f32 / steps as f32;
+                (-cam.w * azimuth.cos() + cam.u * azimuth.sin()) * elevation.cos() + cam.v * elevation.sin()
+            })).collect(),
+        }
+    }
+    // Casts every beam from the camera origin, the beams hitting nothing within the maximum range don't return
+    pub fn scan(&self, world: &HittableList, cam: &Camera) -> Vec<LidarPoint> {
+        let directions: Vec<Vec3A> = self.directions(cam);
+        let chunks: Vec<&[Vec3A]> = directions.chunks(1024).collect();
+        chunks.into_par_iter().enumerate().map(|(chunk, beams)| {
+            utility::seed_stream(*utility::RENDER_SEED, chunk as u64);
+            let arena: Bump = Bump::new();
+            beams.iter().filter_map(|direction| {
+                let ray: Ray = Ray::new(cam.origin, *direction);
+                let rec = world.hit(&ray, utility::NEAR_ZERO, self.max_range)?;
+                let mut srec: ScatterRecord = ScatterRecord::new(&arena);
+                let albedo: f32 = if rec.mat_ptr.scatter(&ray, &rec, &mut srec) { luminance(srec.attenuation) } else { 1.0 };
+                let range: f32 = (rec.t + self.range_noise * _gaussian()).max(0.0);
+                Some(LidarPoint { position: cam.origin + *direction * range, normal: rec.normal, range, intensity: albedo * rec.normal.dot(-*direction).max(0.0) })
+            }).collect::<Vec<LidarPoint>>()
+        }).collect::<Vec<Vec<LidarPoint>>>().concat()
+    }
+}
+
+// A standard normal sample, with the Box-Muller transform
+fn _gaussian() -> f32 { (-2.0 * (1.0 - utility::random_f32()).ln()).sqrt() * (2.0 * utility::PI * utility::random_f32()).cos() }
+
+/****************** Point clouds ******************/
+pub fn write_ply(points: &[LidarPoint], writer: &mut dyn Write) -> std::io::Result<()> {
+    writeln!(writer, "ply\nformat binary_little_endian 1.0\ncomment generated by gbrt")?;
+    writeln!(writer, "element vertex {}", points.len())?;
+    for property in ["x", "y", "z", "nx", "ny", "nz", "range", "intensity"] { writeln!(writer, "property float {}", property)?; }
+    writeln!(writer, "end_header")?;
+    for point in points.iter() {
+        for value in _point_values(point) { writer.write_all(&value.to_le_bytes())?; }
+    }
+    Ok(())
+}
+
+pub fn write_pcd(points: &[LidarPoint], writer: &mut dyn Write) -> std::io::Result<()> {
+    writeln!(writer, "# .PCD v0.7 - Point Cloud Data file format\nVERSION 0.7")?;
+    writeln!(writer, "FIELDS x y z normal_x normal_y normal_z range intensity\nSIZE 4 4 4 4 4 4 4 4\nTYPE F F F F F F F F\nCOUNT 1 1 1 1 1 1 1 1")?;
+    writeln!(writer, "WIDTH {}\nHEIGHT 1\nVIEWPOINT 0 0 0 1 0 0 0\nPOINTS {}\nDATA binary", points.len(), points.len())?;
+    for point in points.iter() {
+        for value in _point_values(point) { writer.write_all(&value.to_le_bytes())?; }
+    }
+    Ok(())
+}
+
+fn _point_values(point: &LidarPoint) -> [f32; 8] {
+    [point.position.x, point.position.y, point.position.z, point.normal.x, point.normal.y, point.normal.z, point.range, point.intensity]
+}
+
+// Scans the scene and saves the point cloud next to the output image, with the extension of the format
+pub fn run(lidar: &Lidar, world: &HittableList, cam: &Camera, output: &str) {
+    let points: Vec<LidarPoint> = lidar.scan(world, cam);
+    let filename: String = std::path::Path::new(output).with_extension(lidar.format.extension()).to_string_lossy().to_string();
+    let mut file = std::io::BufWriter::new(std::fs::File::create(&filename).unwrap_or_else(|err| panic!("Failed to create {}: {}", filename, err)));
+    match lidar.format {
+        PointCloudFormat::Ply => write_ply(&points, &mut file),
+        PointCloudFormat::Pcd => write_pcd(&points, &mut file),
+    }.unwrap_or_else(|err| panic!("Failed to write {}: {}", filename, err));
+    println!("Saved {} of {} LiDAR returns to {}", points.len(), lidar.directions(cam).len(), filename);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use crate::color::Color;
+    use crate::material::Lambertian;
+    use crate::sphere::Sphere;
+
+    #[test]
+    fn test_lidar_scan() {
+        let world: HittableList = vec![Arc::new(Sphere::new(Point3::new(0.0, 0.0, -5.0), 1.0, Box::new(Lambertian::new(Color::splat(0.5))), 0))];
+        let cam: Camera = Camera::new(&Point3::ZERO, &Point3::new(0.0, 0.0, -1.0), &Vec3A::Y, 60.0, 1.0, 0.0, 1.0);
+        let lidar: Lidar = Lidar { pattern: ScanPattern::Spinning { channels: 3, steps: 8, min_elevation: -30.0, max_elevation: 30.0 }, max_range: 100.0, range_noise: 0.0, format: PointCloudFormat::Ply };
+        assert_eq!(lidar.directions(&cam).len(), 24);
+        // Only the beam of the middle channel looking forward hits the sphere, head-on
+        let points: Vec<LidarPoint> = lidar.scan(&world, &cam);
+        assert_eq!(points.len(), 1);
+        assert!((points[0].range - 4.0).abs() < utility::EPSILON && (points[0].intensity - 0.5).abs() < utility::EPSILON);
+        let mut ply: Vec<u8> = Vec::new();
+        write_ply(&points, &mut ply).unwrap();
+        assert!(ply.starts_with(b"ply\nformat binary_little_endian 1.0") && ply.len() > 8 * 4);
+    }
+}
@@ -0,0 +1,129 @@
+// Author: Giulian Biolo, github.com/giulianbiolo
+// Date: 16/10/2026
+// Description: This file implements the light linking, restricting which lights illuminate which objects
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use glam::Vec3A;
+
+use crate::hit_record::HitRecord;
+use crate::hittable_list::{Hittable, HittableList};
+use crate::light_sampler::LightEstimate;
+use crate::point3::Point3;
+use crate::ray::Ray;
+
+
+// Bit shared by the objects without a name nor rules, which can't be told apart
+pub const UNLINKED: u64 = 1 << 63;
+
+/****************** LinkRule ******************/
+// Which objects, by name, are on the other side of the link: the lights illuminating an object, or the objects lit by a light
+#[derive(Debug, Clone, PartialEq)]
+pub enum LinkRule {
+    All,
+    Include(Vec<String>),
+    Exclude(Vec<String>),
+}
+
+impl LinkRule {
+    pub fn allows(&self, name: Option<&str>) -> bool {
+        match self {
+            LinkRule::All => true,
+            LinkRule::Include(names) => name.is_some_and(|name| names.iter().any(|included| included == name)),
+            LinkRule::Exclude(names) => name.is_none_or(|name| names.iter().all(|excluded| excluded != name)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LightLink {
+    pub name: Option<String>,
+    pub rule: LinkRule,
+}
+
+impl LightLink {
+    pub fn new(name: Option<String>, rule: LinkRule) -> LightLink { LightLink { name, rule } }
+}
+
+/****************** Linking ******************/
+// Wraps the objects of the world so that their hits carry their link bit and the bits of the lights that may illuminate them
+// A light and an object are linked when both of their rules allow the other one
+pub fn link(world: HittableList, links: &[LightLink]) -> Result<HittableList, String> {
+    if links.len() != world.len() { panic!("Expected {} light links, got {}", world.len(), links.len()); }
+    if links.iter().all(|link| link.rule == LinkRule::All) { return Ok(world); }
+    let mut ids: Vec<u64> = Vec::with_capacity(links.len());
+    let mut named: HashMap<&str, u64> = HashMap::new();
+    let mut next_bit: u32 = 0;
+    for link in links.iter() {
+        if link.name.is_none() && link.rule == LinkRule::All { ids.push(UNLINKED); continue; }
+        // Objects sharing a name are linked together
+        if let Some(id) = link.name.as_deref().and_then(|name| named.get(name)) { ids.push(*id); continue; }
+        if next_bit >= UNLINKED.trailing_zeros() { return Err(format!("Light linking supports at most {} named or linked objects", UNLINKED.trailing_zeros())); }
+        let id: u64 = 1 << next_bit;
+        next_bit += 1;
+        if let Some(name) = link.name.as_deref() { named.insert(name, id); }
+        ids.push(id);
+    }
+    Ok(world.into_iter().enumerate().map(|(idx, object)| -> Arc<dyn Hittable + Send + Sync> {
+        let mask: u64 = links.iter().zip(ids.iter())
+            .filter(|(light, _)| links[idx].rule.allows(light.name.as_deref()) && light.rule.allows(links[idx].name.as_deref()))
+            .fold(0, |mask, (_, id)| mask | id);
+        Arc::new(LightLinked { object, id: ids[idx], mask })
+    }).collect())
+}
+
+/****************** LightLinked ******************/
+pub struct LightLinked {
+    object: Arc<dyn Hittable + Send + Sync>,
+    id: u64,
+    mask: u64,
+}
+
+impl Hittable for LightLinked {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        let mut rec: HitRecord = self.object.hit(ray, t_min, t_max)?;
+        (rec.link_id, rec.link_mask) = (self.id, self.mask);
+        Some(rec)
+    }
+    fn occluded(&self, ray: &Ray, t_min: f32, t_max: f32) -> bool { self.object.occluded(ray, t_min, t_max) }
+    fn is_light(&self) -> bool { self.object.is_light() }
+    fn light_estimate(&self) -> Option<LightEstimate> { self.object.light_estimate() }
+    fn pdf_value(&self, origin: &Point3, v: &Vec3A) -> f32 { self.object.pdf_value(origin, v) }
+    fn random(&self, origin: &Point3) -> Vec3A { self.object.random(origin) }
+    fn wrapped(&self) -> Option<&(dyn Hittable + Send + Sync)> { Some(&*self.object) }
+    fn wrapped_mut(&mut self) -> Option<&mut (dyn Hittable + Send + Sync + 'static)> { Arc::get_mut(&mut self.object) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::Color;
+    use crate::material::{DiffuseLight, Lambertian};
+    use crate::sphere::Sphere;
+
+    fn _sphere(x: f32, light: bool) -> Arc<dyn Hittable + Send + Sync> {
+        if light { Arc::new(Sphere::new(Point3::new(x, 0.0, 0.0), 0.5, Box::new(DiffuseLight::new(Color::ONE, 1.0)), 0)) }
+        else { Arc::new(Sphere::new(Point3::new(x, 0.0, 0.0), 0.5, Box::new(Lambertian::new(Color::ONE)), 0)) }
+    }
+
+    #[test]
+    fn test_link() {
+        let world: HittableList = vec![_sphere(0.0, true), _sphere(2.0, true), _sphere(4.0, false), _sphere(6.0, false)];
+        let links: Vec<LightLink> = vec![
+            // The key light only lights the hero, which in turn ignores the fill light
+            LightLink::new(Some("key".to_string()), LinkRule::Include(vec!["hero".to_string()])),
+            LightLink::new(Some("fill".to_string()), LinkRule::All),
+            LightLink::new(Some("hero".to_string()), LinkRule::Exclude(vec!["fill".to_string()])),
+            LightLink::new(None, LinkRule::All),
+        ];
+        let world: HittableList = link(world, &links).unwrap();
+        let rec = |x: f32| -> (u64, u64) {
+            let rec: HitRecord = world.hit(&Ray::new(Point3::new(x, 0.0, -5.0), Vec3A::Z), 0.001, f32::INFINITY).unwrap();
+            (rec.link_id, rec.link_mask)
+        };
+        let ((key, _), (fill, _), (_, hero), (_, other)) = (rec(0.0), rec(2.0), rec(4.0), rec(6.0));
+        assert!(hero & key != 0 && hero & fill == 0);
+        assert!(other & key == 0 && other & fill != 0);
+    }
+}
@@ -0,0 +1,212 @@
+// Author: Giulian Biolo, github.com/giulianbiolo
+// Date: 16/10/2026
+// Description: This file implements the adaptive light selection, picking lights by their estimated contribution at the shading point
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::RwLock;
+
+use bvh::aabb::AABB;
+use glam::Vec3A;
+
+use crate::color::Color;
+use crate::hit_record::HitRecord;
+use crate::hittable_list::{Hittable, HittableList};
+use crate::point3::Point3;
+use crate::ray::Ray;
+use crate::utility::{self, NEAR_ZERO, PI};
+
+
+// Lights whose center is hidden from the shading point are still picked sometimes, as the rest of them may be visible
+const OCCLUDED_WEIGHT: f32 = 0.1;
+// Cap on the visibility cache, past it the lights of new cells are all considered visible
+const MAX_CACHED_CELLS: usize = 1 << 16;
+// Only the first lights fit in the visibility mask, the others are always considered visible
+const MAX_CACHED_LIGHTS: usize = 64;
+// Tells the samplers apart in the cache of the threads, as a new one could take the place of a dropped one in memory
+static NEXT_SAMPLER_ID: AtomicU64 = AtomicU64::new(0);
+
+thread_local! {
+    // The probabilities of the last shading point of each thread and the sampler they're of, as the light sampling asks for them twice there (for the direction and its pdf)
+    static PROBABILITIES: RefCell<(u64, Point3, Vec<f32>)> = const { RefCell::new((u64::MAX, Vec3A::ZERO, Vec::new())) };
+}
+
+/****************** LightEstimate ******************/
+// Rough bounding sphere and power (area times radiance) of an emitter
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LightEstimate {
+    pub center: Point3,
+    pub radius: f32,
+    pub power: f32,
+}
+
+impl LightEstimate {
+    pub fn new(center: Point3, radius: f32, area: f32, emitted: Color) -> LightEstimate {
+        LightEstimate { center, radius, power: area * luminance(emitted) }
+    }
+    pub fn sphere(center: Point3, radius: f32, emitted: Color) -> LightEstimate {
+        LightEstimate::new(center, radius, 4.0 * PI * radius * radius, emitted)
+    }
+    // A single estimate for a group of emitters, centered on their power
+    pub fn merge(estimates: impl Iterator<Item = LightEstimate>) -> Option<LightEstimate> {
+        let estimates: Vec<LightEstimate> = estimates.collect();
+        if estimates.is_empty() { return None; }
+        let power: f32 = estimates.iter().map(|estimate| estimate.power).sum();
+        let center: Point3 = if power > 0.0 {
+            estimates.iter().map(|estimate| estimate.center * estimate.power).sum::<Vec3A>() / power
+        } else { estimates.iter().map(|estimate| estimate.center).sum::<Vec3A>() / estimates.len() as f32 };
+        let radius: f32 = estimates.iter().map(|estimate| (estimate.center - center).length() + estimate.radius).fold(0.0, f32::max);
+        Some(LightEstimate { center, radius, power })
+    }
+    // Estimated contribution at a point, the power falls off with the squared distance once outside of the bounding sphere
+    #[allow(dead_code)]
+    pub fn weight(&self, p: &Point3) -> f32 {
+        self.power / (self.center - *p).length_squared().max(self.radius * self.radius).max(NEAR_ZERO)
+    }
+}
+
+pub fn luminance(color: Color) -> f32 { color.dot(Vec3A::new(0.2126, 0.7152, 0.0722)) }
+
+/****************** LightSampler ******************/
+// Samples the lights proportionally to their estimated contribution at the shading point, instead of uniformly
+#[allow(dead_code)]
+pub struct LightSampler {
+    lights: HittableList,
+    // None for the lights that can't be estimated (the environment), which keep the share they'd have with uniform selection
+    estimates: Vec<Option<LightEstimate>>,
+    world: HittableList,
+    cell_size: f32,
+    // Which lights have their center visible from the center of each cell of the grid, one bit per light
+    visibility: RwLock<HashMap<(i32, i32, i32), u64>>,
+    id: u64,
+}
+
+impl LightSampler {
+    pub fn new(lights: HittableList, world: HittableList) -> LightSampler {
+        let estimates: Vec<Option<LightEstimate>> = lights.iter().map(|light| light.light_estimate()).collect();
+        // The cells are as big as the smallest light, finer cells wouldn't change much what it sees
+        let cell_size: f32 = estimates.iter().flatten().map(|estimate| 2.0 * estimate.radius).filter(|size| *size > NEAR_ZERO).reduce(f32::min).unwrap_or(1.0);
+        LightSampler { lights, estimates, world, cell_size, visibility: RwLock::new(HashMap::new()), id: NEXT_SAMPLER_ID.fetch_add(1, Ordering::Relaxed) }
+    }
+    fn _cell(&self, p: &Point3) -> (i32, i32, i32) {
+        let cell: Vec3A = (*p / self.cell_size).floor();
+        (cell.x as i32, cell.y as i32, cell.z as i32)
+    }
+    // The mask only depends on the cell, so it's the same whichever thread computes it first
+    fn _visibility(&self, p: &Point3) -> u64 {
+        let cell: (i32, i32, i32) = self._cell(p);
+        {
+            let visibility = self.visibility.read().unwrap();
+            if let Some(mask) = visibility.get(&cell) { return *mask; }
+            if visibility.len() >= MAX_CACHED_CELLS { return u64::MAX; }
+        }
+        let cell_center: Point3 = (Vec3A::new(cell.0 as f32, cell.1 as f32, cell.2 as f32) + 0.5) * self.cell_size;
+        let mut mask: u64 = u64::MAX;
+        for (idx, estimate) in self.estimates.iter().enumerate().take(MAX_CACHED_LIGHTS) {
+            let estimate: &LightEstimate = match estimate {
+                Some(estimate) => estimate,
+                None => continue,
+            };
+            // The shadow ray stops just before the bounding sphere of the light, which would otherwise occlude itself
+            let to_light: Vec3A = estimate.center - cell_center;
+            let t_max: f32 = 1.0 - 1.01 * estimate.radius / to_light.length();
+            if t_max > NEAR_ZERO && self.world.occluded(&Ray::new(cell_center, to_light), NEAR_ZERO, t_max) { mask &= !(1 << idx); }
+        }
+        self.visibility.write().unwrap().insert(cell, mask);
+        mask
+    }
+    // Probability of picking every light at the point, computed once per shading point and thread in place of the previous ones
+    fn _with_probabilities<T>(&self, p: &Point3, f: impl FnOnce(&[f32]) -> T) -> T {
+        PROBABILITIES.with(|cached| {
+            let mut cached = cached.borrow_mut();
+            if cached.0 != self.id || cached.1 != *p {
+                self._fill_probabilities(p, &mut cached.2);
+                (cached.0, cached.1) = (self.id, *p);
+            }
+            f(&cached.2)
+        })
+    }
+    fn _fill_probabilities(&self, p: &Point3, probabilities: &mut Vec<f32>) {
+        probabilities.clear();
+        let uniform: f32 = 1.0 / self.lights.len() as f32;
+        if self.estimates.iter().all(|estimate| estimate.is_none()) { return probabilities.resize(self.lights.len(), uniform); }
+        let visibility: u64 = self._visibility(p);
+        let weight = |idx: usize, estimate: &LightEstimate| -> f32 {
+            let visible: bool = idx >= MAX_CACHED_LIGHTS || visibility & (1 << idx) != 0;
+            estimate.weight(p) * if visible { 1.0 } else { OCCLUDED_WEIGHT }
+        };
+        // The estimated lights share what is left by the others proportionally to their weights
+        let (estimated, total) = self.estimates.iter().enumerate()
+            .filter_map(|(idx, estimate)| estimate.as_ref().map(|estimate| weight(idx, estimate)))
+            .fold((0, 0.0), |(estimated, total), weight| (estimated + 1, total + weight));
+        probabilities.extend(self.estimates.iter().enumerate().map(|(idx, estimate)| match estimate {
+            Some(estimate) if total > 0.0 => weight(idx, estimate) / total * estimated as f32 * uniform,
+            _ => uniform,
+        }));
+    }
+}
+
+impl Hittable for LightSampler {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> { self.lights.hit(ray, t_min, t_max) }
+    fn occluded(&self, ray: &Ray, t_min: f32, t_max: f32) -> bool { self.lights.occluded(ray, t_min, t_max) }
+    fn is_light(&self) -> bool { true }
+    fn pdf_value(&self, origin: &Point3, v: &Vec3A) -> f32 {
+        self._with_probabilities(origin, |probabilities| probabilities.iter().zip(self.lights.iter())
+            .filter(|(probability, _)| **probability > 0.0)
+            .map(|(probability, light)| probability * light.pdf_value(origin, v))
+            .sum())
+    }
+    fn random(&self, origin: &Point3) -> Vec3A {
+        let mut pick: f32 = utility::random_f32();
+        let idx: usize = self._with_probabilities(origin, |probabilities| probabilities.iter().position(|probability| { pick -= probability; pick < 0.0 })).unwrap_or(self.lights.len() - 1);
+        self.lights[idx].random(origin)
+    }
+    fn world_bounds(&self) -> Option<AABB> { self.lights.world_bounds() }
+    fn light_estimate(&self) -> Option<LightEstimate> {
+        let estimates: Option<Vec<LightEstimate>> = self.estimates.iter().copied().collect();
+        LightEstimate::merge(estimates?.into_iter())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use crate::material::{DiffuseLight, Lambertian};
+    use crate::rectangle::{XYRectangle, XZRectangle};
+    use crate::sphere::Sphere;
+
+    #[test]
+    fn test_probabilities() {
+        let lights: HittableList = vec![
+            Arc::new(XZRectangle::new(-0.5, 0.5, -0.5, 0.5, 2.0, Box::new(DiffuseLight::new(Color::ONE, 10.0)), 0)),
+            Arc::new(XZRectangle::new(-0.5, 0.5, -0.5, 0.5, 20.0, Box::new(DiffuseLight::new(Color::ONE, 1.0)), 0)),
+        ];
+        let sampler: LightSampler = LightSampler::new(lights.clone(), lights.clone());
+        let probabilities: Vec<f32> = sampler._with_probabilities(&Point3::ZERO, |probabilities| probabilities.to_vec());
+        assert!((probabilities.iter().sum::<f32>() - 1.0).abs() < 1e-5);
+        assert!(probabilities[0] > 0.99);
+        // Another sampler at the same point doesn't get the probabilities the thread kept for the first one
+        let reversed: LightSampler = LightSampler::new(vec![lights[1].clone(), lights[0].clone()], lights.clone());
+        assert!(reversed._with_probabilities(&Point3::ZERO, |probabilities| probabilities[1]) > 0.99);
+        // The pdf is the mixture of the ones of the lights, weighted by their probabilities
+        let v: Vec3A = Vec3A::new(0.0, 1.0, 0.0);
+        let expected: f32 = probabilities[0] * lights[0].pdf_value(&Point3::ZERO, &v) + probabilities[1] * lights[1].pdf_value(&Point3::ZERO, &v);
+        assert!((sampler.pdf_value(&Point3::ZERO, &v) - expected).abs() < 1e-4 * expected);
+        // The sampled directions point to the lights
+        let direction: Vec3A = sampler.random(&Point3::ZERO);
+        assert!(sampler.hit(&Ray::new(Point3::ZERO, direction), NEAR_ZERO, f32::INFINITY).is_some());
+    }
+    #[test]
+    fn test_visibility() {
+        let light = |x: f32| -> Arc<dyn Hittable + Send + Sync> { Arc::new(Sphere::new(Point3::new(x, 0.0, 5.0), 0.5, Box::new(DiffuseLight::new(Color::ONE, 1.0)), 0)) };
+        let lights: HittableList = vec![light(-3.0), light(3.0)];
+        // A wall hides the right light from the origin
+        let mut world: HittableList = lights.clone();
+        world.push(Arc::new(XYRectangle::new(0.5, 10.0, -10.0, 10.0, 2.0, Box::new(Lambertian::new(Color::ONE)), 0)));
+        let sampler: LightSampler = LightSampler::new(lights, world);
+        let probabilities: Vec<f32> = sampler._with_probabilities(&Point3::new(0.0, 0.1, 0.1), |probabilities| probabilities.to_vec());
+        assert!((probabilities[0] - probabilities[1] / OCCLUDED_WEIGHT).abs() < 1e-4);
+    }
+}
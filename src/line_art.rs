@@ -0,0 +1,174 @@
+// Author: Giulian Biolo, github.com/giulianbiolo
+// Date: 16/10/2026
+// Description: This file implements the technical illustration mode, drawing the silhouettes and creases of the scene as black lines on white without any shading
+
+use std::fmt::Write;
+use std::path::Path;
+
+use glam::{Vec2, Vec3A};
+use image::{GrayImage, Luma};
+
+use crate::camera::Camera;
+use crate::compositing;
+use crate::diffraction::{self, Wedge};
+use crate::hittable_list::{Hittable, HittableList};
+use crate::material::Material;
+use crate::parallel::*;
+use crate::point3::Point3;
+use crate::ray::Ray;
+use crate::utility;
+
+
+// Neighbouring pixels whose normals are further apart than this angle (in degrees) are split by a crease line
+const CREASE_ANGLE: f32 = 30.0;
+// Neighbouring pixels further off of each other's plane than this fraction of their depth are split by a silhouette line
+const DEPTH_STEP: f32 = 0.02;
+// The points checked for visibility along an edge, per pixel of its length on the image
+const SAMPLES_PER_PIXEL: f32 = 2.0;
+const MAX_SAMPLES: usize = 4096;
+
+// What the camera ray of a pixel sees first: the point, its distance, the normal facing the camera and the material, which tells the parts apart
+#[derive(Debug, Clone, Copy)]
+pub struct Sample {
+    pub p: Point3,
+    pub depth: f32,
+    pub normal: Vec3A,
+    pub material: usize,
+}
+
+// A visible stretch of an edge, from one point to the other in pixels from the top left corner
+pub type Segment = (Vec2, Vec2);
+
+// The first hits of the rays through the centers of the pixels, row by row, with no light bounced and so no shadows
+pub fn trace(world: &HittableList, cam: &Camera, width: u32, height: u32) -> Vec<Option<Sample>> {
+    (0..height).into_par_iter().map(|y| {
+        (0..width).map(|x| {
+            let direction: Vec3A = compositing::pixel_direction(cam, width, height, x as f32 + 0.5, y as f32 + 0.5);
+            world.hit(&Ray::new(cam.origin, direction), utility::NEAR_ZERO, utility::INFINITY).map(|rec| Sample {
+                p: rec.p,
+                depth: rec.t,
+                normal: rec.normal,
+                material: rec.mat_ptr as *const dyn Material as *const u8 as usize,
+            })
+        }).collect::<Vec<Option<Sample>>>()
+    }).reduce(Vec::new, |mut a, b| { a.extend(b); a })
+}
+
+// Whether a line runs between two neighbouring pixels: the outline of an object against the background or a farther one, a crease, or the border of two parts
+fn _split(a: &Option<Sample>, b: &Option<Sample>, flat: f32) -> bool {
+    match (a, b) {
+        (None, None) => false,
+        (Some(_), None) | (None, Some(_)) => true,
+        (Some(a), Some(b)) => {
+            // The distance off of the plane of the other pixel, so that the surfaces seen at grazing angles aren't split
+            let step: f32 = DEPTH_STEP * a.depth.min(b.depth);
+            a.material != b.material || (b.p - a.p).dot(a.normal).abs() > step || (a.p - b.p).dot(b.normal).abs() > step || a.normal.dot(b.normal) < flat
+        },
+    }
+}
+
+// The lines of the traced pixels, black on white, each drawn on the pixel left or above of it
+pub fn lines(samples: &[Option<Sample>], width: u32, height: u32) -> GrayImage {
+    let flat: f32 = CREASE_ANGLE.to_radians().cos();
+    let at = |x: u32, y: u32| &samples[(y * width + x) as usize];
+    GrayImage::from_fn(width, height, |x, y| {
+        let line: bool = (x + 1 < width && _split(at(x, y), at(x + 1, y), flat)) || (y + 1 < height && _split(at(x, y), at(x, y + 1), flat));
+        Luma([if line { 0 } else { 255 }])
+    })
+}
+
+// Whether nothing stands between the camera and the point of an edge, the faces of the edge itself being let through
+fn _visible(world: &HittableList, cam: &Camera, p: Point3) -> bool {
+    let (direction, distance) = ((p - cam.origin).normalize(), (p - cam.origin).length());
+    world.hit(&Ray::new(cam.origin, direction), utility::NEAR_ZERO, distance * (1.0 - 1e-3) - utility::EPSILON).is_none()
+}
+
+// The hidden line removal of an edge: its points in front of the camera and not behind a surface, chained into the stretches between the hidden ones
+fn _visible_segments(world: &HittableList, cam: &Camera, width: u32, height: u32, wedge: &Wedge) -> Vec<Segment> {
+    let (start, end) = (compositing::image_point(cam, width, height, &wedge.start), compositing::image_point(cam, width, height, &wedge.end));
+    let samples: usize = match (start, end) {
+        (Some(start), Some(end)) => ((start - end).length() * SAMPLES_PER_PIXEL).ceil() as usize,
+        _ => MAX_SAMPLES,
+    }.clamp(1, MAX_SAMPLES);
+    let size: Vec2 = Vec2::new(width as f32, height as f32);
+    let mut segments: Vec<Segment> = Vec::new();
+    let mut run: Option<Segment> = None;
+    for i in 0..=samples {
+        let p: Point3 = wedge.start.lerp(wedge.end, i as f32 / samples as f32);
+        let point: Option<Vec2> = compositing::image_point(cam, width, height, &p)
+            .filter(|point| point.cmpge(Vec2::ZERO).all() && point.cmple(size).all() && _visible(world, cam, p));
+        match (point, run) {
+            (Some(point), Some((first, _))) => run = Some((first, point)),
+            (Some(point), None) => run = Some((point, point)),
+            (None, Some(segment)) => { segments.push(segment); run = None; },
+            (None, None) => {},
+        }
+    }
+    segments.extend(run);
+    // A lone visible point is where a hidden edge comes out of a visible corner
+    segments.retain(|(first, last)| first != last);
+    segments
+}
+
+// The visible stretches of the creases and of the borders of the faces of the scene, as found by the edge detection of the diffraction
+pub fn visible_segments(world: &HittableList, cam: &Camera, width: u32, height: u32) -> Vec<Segment> {
+    diffraction::detect(world).into_par_iter()
+        .map(|wedge| _visible_segments(world, cam, width, height, &wedge))
+        .reduce(Vec::new, |mut a, b| { a.extend(b); a })
+}
+
+// The segments as an SVG drawing of the size of the image, the lines one pixel wide
+pub fn svg(segments: &[Segment], width: u32, height: u32) -> String {
+    let mut svg: String = format!("<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{0}\" height=\"{1}\" viewBox=\"0 0 {0} {1}\">\n", width, height);
+    svg.push_str("<rect width=\"100%\" height=\"100%\" fill=\"white\"/>\n<g stroke=\"black\" stroke-width=\"1\" stroke-linecap=\"round\" fill=\"none\">\n");
+    for (start, end) in segments {
+        writeln!(svg, "<line x1=\"{:.2}\" y1=\"{:.2}\" x2=\"{:.2}\" y2=\"{:.2}\"/>", start.x, start.y, end.x, end.y).unwrap();
+    }
+    svg.push_str("</g>\n</svg>\n");
+    svg
+}
+
+// Draws the scene as lines to the output: the visible edges of its faces to an .svg, the outlines and creases of all of its surfaces to an image otherwise
+pub fn run(world: &HittableList, cam: &Camera, output: &str) {
+    let (width, height) = (utility::CONSTS.width, utility::CONSTS.height);
+    if Path::new(output).extension().is_some_and(|extension| extension.eq_ignore_ascii_case("svg")) {
+        let segments: Vec<Segment> = visible_segments(world, cam, width, height);
+        std::fs::write(output, svg(&segments, width, height)).unwrap_or_else(|err| panic!("Failed to save the line drawing to {}: {}", output, err));
+        println!("Saved {} visible edge segments to {}", segments.len(), output);
+    } else {
+        lines(&trace(world, cam, width, height), width, height).save(output).unwrap_or_else(|err| panic!("Failed to save the line drawing to {}: {}", output, err));
+        println!("Saved the line drawing to {}", output);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::bbox::BBox;
+    use crate::color::Color;
+    use crate::material::Lambertian;
+    use crate::sphere::Sphere;
+
+    #[test]
+    fn test_lines() {
+        // A sphere in the middle of the image is outlined, but not shaded or crossed
+        let world: HittableList = vec![Arc::new(Sphere::new(Point3::ZERO, 1.0, Box::new(Lambertian::new(Color::ONE)), 0))];
+        let cam: Camera = Camera::new(&Vec3A::new(0.0, 0.0, 5.0), &Vec3A::ZERO, &Vec3A::Y, 45.0, 1.0, 0.0, 5.0);
+        let image: GrayImage = lines(&trace(&world, &cam, 64, 64), 64, 64);
+        let row: Vec<u8> = (0..64).map(|x| image.get_pixel(x, 32)[0]).collect();
+        assert_eq!(row.iter().filter(|value| **value == 0).count(), 2);
+        assert_eq!((row[0], row[32]), (255, 255));
+    }
+    #[test]
+    fn test_hidden_lines() {
+        // A cube seen straight from the front shows the four edges of its front face, the others are behind it
+        let world: HittableList = vec![Arc::new(BBox::new(Point3::ZERO, Vec3A::ONE, Box::new(Lambertian::new(Color::ONE))))];
+        let cam: Camera = Camera::new(&Vec3A::new(0.0, 0.0, 4.0), &Vec3A::ZERO, &Vec3A::Y, 45.0, 1.0, 0.0, 4.0);
+        let segments: Vec<Segment> = visible_segments(&world, &cam, 100, 100);
+        assert_eq!(segments.len(), 4);
+        assert!(segments.iter().all(|(start, end)| (start.x - end.x).abs() < 1e-3 || (start.y - end.y).abs() < 1e-3));
+        assert!(svg(&segments, 100, 100).matches("<line").count() == 4);
+    }
+}
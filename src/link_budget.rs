@@ -0,0 +1,50 @@
+// Author: Giulian Biolo, github.com/giulianbiolo
+// Date: 16/10/2026
+// Description: This file implements the link budget calibrating the power grid to dBm, from the transmit power and the antenna gains of the sources
+
+use crate::utility;
+
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LinkBudget {
+    // Power fed to the transmitting antenna, in dBm
+    pub transmit_power: f32,
+    // Gains of the transmitting and receiving antennas, in dBi
+    pub transmit_gain: f32,
+    pub receive_gain: f32,
+    // Distance from the source, in meters, at which the simulated power is matched to the free space one
+    pub reference_distance: f32,
+}
+
+impl LinkBudget {
+    // The Friis transmission equation, the power received in free space at the distance from the source
+    pub fn free_space_dbm(&self, distance: f32, lambda: f32) -> f32 {
+        self.transmit_power + self.transmit_gain + self.receive_gain + 20.0 * (lambda / (4.0 * utility::PI * distance)).log10()
+    }
+    // Distances around the reference one spanning a wavelength, so that the phase of the direct path averages out
+    pub fn reference_distances(&self, lambda: f32) -> Vec<f32> { (0..8).map(|step| self.reference_distance + step as f32 * lambda / 8.0).collect() }
+    // The offset in dB turning the raw values of the power grid to dBm, from the values simulated at the reference distances in free space
+    pub fn calibration_offset(&self, reference_values: &[f32], lambda: f32) -> f32 {
+        let free_space_mw: f32 = self.reference_distances(lambda).iter().map(|distance| 10_f32.powf(self.free_space_dbm(*distance, lambda) / 10.0)).sum();
+        let simulated: f32 = reference_values.iter().sum();
+        if simulated <= 0.0 { panic!("No power reaches the reference distance of {}m from the source, can't calibrate the power grid", self.reference_distance); }
+        10.0 * (free_space_mw / simulated).log10()
+    }
+    pub fn to_dbm(value: f32, offset: f32) -> f32 { 10.0 * value.log10() + offset }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_link_budget() {
+        let budget: LinkBudget = LinkBudget { transmit_power: 20.0, transmit_gain: 2.0, receive_gain: 0.0, reference_distance: 1.0 };
+        let lambda: f32 = 299792458.0 / 2.45e9;
+        assert!((budget.free_space_dbm(1.0, lambda) - -18.23).abs() < 0.01);
+        // Simulated values a constant factor away from the free space ones are calibrated back to them
+        let reference_values: Vec<f32> = budget.reference_distances(lambda).iter().map(|distance| 3.0e-7 * 10_f32.powf(budget.free_space_dbm(*distance, lambda) / 10.0)).collect();
+        let offset: f32 = budget.calibration_offset(&reference_values, lambda);
+        assert!((LinkBudget::to_dbm(reference_values[0], offset) - budget.free_space_dbm(1.0, lambda)).abs() < 0.01);
+    }
+}
@@ -0,0 +1,66 @@
+// Author: Giulian Biolo, github.com/giulianbiolo
+// Date: 16/10/2026
+// Description: This file implements the log file of --log-file, the structured events of a run written as JSON lines, for auditing the batch renders later
+
+use std::fs::{File, OpenOptions};
+use std::sync::Mutex;
+
+use tracing::Subscriber;
+
+use crate::utility::CONSTS;
+
+
+// The path of the log with {frame} replaced by the frame, so that every frame of a batch gets its own log, while without it they all go to one global log
+pub fn path_for_frame(path: &str, frame: u64) -> String { path.replace("{frame}", &frame.to_string()) }
+
+// The JSON lines of the events appended to the file, with their time, level, target, fields and the spans they happened in
+pub fn subscriber(path: &str) -> impl Subscriber + Send + Sync {
+    let file: File = OpenOptions::new().create(true).append(true).open(path).unwrap_or_else(|err| panic!("Failed to open the log file {}: {}", path, err));
+    tracing_subscriber::fmt().json().with_writer(Mutex::new(file)).with_current_span(false).finish()
+}
+
+// Sends the events of the whole run to the log file, after those of the previous runs
+pub fn init(path: &str) {
+    // The frame is only read from the constants when asked for, since some subcommands have no scene to read them from
+    let path: String = if path.contains("{frame}") { path_for_frame(path, CONSTS.frame) } else { path.to_string() };
+    tracing::subscriber::set_global_default(subscriber(&path)).unwrap_or_else(|err| panic!("Failed to start the log file {}: {}", path, err));
+}
+
+// The settings the image is rendered with
+pub fn settings(config_file: &str, output: &str, threads: Option<usize>) {
+    tracing::info!(
+        config_file, output, width = CONSTS.width, height = CONSTS.height, samples_per_pixel = CONSTS.samples_per_pixel,
+        max_depth = CONSTS.max_depth, min_depth = CONSTS.min_depth, max_specular_depth = CONSTS.max_specular_depth, max_diffuse_depth = CONSTS.max_diffuse_depth,
+        light_sampling = CONSTS.light_sampling, manifold_sampling = CONSTS.manifold_sampling, filter = CONSTS.filter.as_deref().unwrap_or("UniformFilter"),
+        seed = CONSTS.seed, frame = CONSTS.frame, threads, memory_budget = CONSTS.memory_budget, max_render_seconds = CONSTS.max_render_seconds,
+        "settings"
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_log_file() {
+        assert_eq!(path_for_frame("logs/shot_{frame}.log", 12), "logs/shot_12.log");
+        assert_eq!(path_for_frame("logs/batch.log", 12), "logs/batch.log");
+        // Every event is a JSON object on its own line, with its fields and the span it happened in
+        let path: std::path::PathBuf = std::env::temp_dir().join(format!("gbrt_log_file_{}.log", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        for run in 0..2 {
+            tracing::subscriber::with_default(subscriber(path.to_str().unwrap()), || {
+                let _render = tracing::info_span!("render", frame = 3).entered();
+                tracing::warn!(filename = "part.stl", dropped = 2, run, "degenerate triangles dropped");
+            });
+        }
+        let lines: Vec<serde_json::Value> = std::fs::read_to_string(&path).unwrap().lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+        std::fs::remove_file(&path).unwrap();
+        // The second run is appended after the first one
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[1]["level"], "WARN");
+        assert_eq!(lines[1]["fields"]["message"], "degenerate triangles dropped");
+        assert_eq!((lines[1]["fields"]["dropped"].as_u64(), lines[1]["fields"]["run"].as_u64()), (Some(2), Some(1)));
+        assert_eq!(lines[1]["spans"][0]["frame"].as_u64(), Some(3));
+    }
+}
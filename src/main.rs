@@ -22,23 +22,161 @@ mod texture;
 mod sampling_filters;
 mod onb;
 mod pdf;
-
-use std::env::args;
+mod cli;
+mod preview;
+mod medium;
+mod tiled_texture;
+mod environment;
+mod benchmark;
+mod audit;
+mod compare;
+mod simd;
+mod acceleration;
+mod light_sampler;
+mod light_linking;
+mod room;
+mod rounded_box;
+mod capsule;
+mod plane;
+mod clipping;
+mod sweep;
+mod parallel;
+mod assets;
+mod web;
+mod pack;
+mod memory;
+mod cancel;
+mod temporal;
+mod link_budget;
+mod transient;
+mod lidar;
+mod visibility;
+mod scene_desc;
+mod scene_builder;
+mod transform;
+mod scene;
+mod sampling;
+mod aov;
+mod adaptive;
+mod priority;
+mod mean_buffer;
+mod sh;
+mod camera_path;
+mod compositing;
+mod pixel_debug;
+mod nan_report;
+mod voxels;
+mod radiosity;
+mod placement;
+mod floors;
+mod diffraction;
+mod power_report;
+mod gpu_scene;
+mod line_art;
+mod uv_inspect;
+mod frustum;
+mod pick;
+mod manifold;
+mod log_file;
+mod config_dump;
+mod random_scene;
+mod matpreview;
+mod exposure;
 
 use glam::Vec3A;
 
 use hittable_list::HittableList;
 use camera::Camera;
+use cli::ARGS;
 
 
 fn main() -> Result<(), std::fmt::Error> {
     let start_time = std::time::Instant::now();
     // Load the config file and print various logs
-    let config_file: &str = &args().nth(1).unwrap_or("".to_string());
+    let config_file: &str = &ARGS.config_file;
+    if let Some(log_file) = &ARGS.log_file { log_file::init(log_file); }
+    if ARGS.bvh_stats { acceleration::enable_stats(); }
+    if ARGS.nan_report { nan_report::enable(); }
+    for location in ARGS.assets.iter() { assets::mount(assets::open_source(location)); }
+    if assets::is_archive(config_file) { assets::mount(assets::open_source(config_file)); }
+    if ARGS.benchmark {
+        benchmark::run();
+        return Ok(());
+    }
+    if let Some((reference, test)) = &ARGS.compare {
+        compare::run(reference, test);
+        return Ok(());
+    }
+    if let Some((scene, archive)) = &ARGS.pack {
+        pack::run(scene, archive);
+        return Ok(());
+    }
+    if let Some((scene, points, matrix)) = &ARGS.visibility {
+        visibility::run(scene, points, matrix);
+        return Ok(());
+    }
+    if let Some((scene, points, matrix)) = &ARGS.coverage {
+        radiosity::run(scene, points, matrix);
+        return Ok(());
+    }
+    if let Some((scene, grid)) = &ARGS.voxelize {
+        voxels::run(scene, grid);
+        return Ok(());
+    }
+    if let Some((scene, directory)) = &ARGS.export_gpu {
+        gpu_scene::run(scene, directory);
+        return Ok(());
+    }
+    if let Some((seed, filename)) = &ARGS.export_scene {
+        random_scene::run(*seed, filename);
+        return Ok(());
+    }
+    if let Some(material_file) = &ARGS.matpreview {
+        cancel::install_handler();
+        matpreview::run(material_file, &ARGS.output);
+        return Ok(());
+    }
+    if let Some(references_dir) = &ARGS.regression {
+        if !benchmark::regression(references_dir, ARGS.update_references) { std::process::exit(1); }
+        return Ok(());
+    }
+    if ARGS.audit {
+        audit::run(config_file);
+        return Ok(());
+    }
+    if ARGS.sweep {
+        if config_file.is_empty() { panic!("The --sweep mode requires a config file with a sweep block"); }
+        sweep::run(config_file);
+        return Ok(());
+    }
+    if ARGS.print_config {
+        if config_file.is_empty() { panic!("The --print-config mode requires a config file"); }
+        config_dump::run(config_file, ARGS.threads);
+        return Ok(());
+    }
     if config_file == "" { println!("No config file specified. Falling back to default values..."); }
+    if let Some(scale) = ARGS.preview_scale { println!("Preview at 1/{} of the resolution and samples, saved to {}", scale, ARGS.output); }
     println!("Image Size: {}x{}", utility::CONSTS.width, utility::CONSTS.height);
+    // The events of the render carry its scene and frame, to tell them apart in a global log
+    let _render = tracing::info_span!("render", config_file, frame = utility::CONSTS.frame).entered();
+    log_file::settings(config_file, &ARGS.output, ARGS.threads.or(utility::CONSTS.threads));
+    let thread_options: parallel::ThreadOptions = parallel::ThreadOptions {
+        threads: ARGS.threads.or(utility::CONSTS.threads),
+        pin: utility::CONSTS.pin_threads,
+        nice: ARGS.nice,
+        pause: ARGS.nice_pause.map(std::time::Duration::from_millis),
+    };
+    if ARGS.watch {
+        if config_file.is_empty() { panic!("The --watch mode requires a config file to watch"); }
+        parallel::install(&thread_options, || preview::watch_scene(config_file));
+        return Ok(());
+    }
+
+    // Only the renders are held to the memory budget, the preview reloads the changed parts over and over
+    memory::set_budget(utility::CONSTS.memory_budget.map(|megabytes| memory::Budget::from_megabytes(megabytes, utility::CONSTS.stream_textures)));
 
     // Initialize the camera and the scene
+    let load_time = std::time::Instant::now();
     let cam: Camera;
     let world: HittableList;
     if config_file == "" {
@@ -56,6 +194,40 @@ fn main() -> Result<(), std::fmt::Error> {
         cam = Camera::new_from_yaml(config_file);
         world = raytracer::init_scene_from_yaml(config_file);
     }
+    tracing::info!(objects = world.len(), lights = raytracer::get_lights(&world).len(), elapsed_ms = load_time.elapsed().as_millis() as u64, "scene loaded");
+    // The names of the objects, for the diagnostics finding them by their index in the world
+    let object_names = || -> Vec<Option<String>> { if config_file.is_empty() { Vec::new() } else { parser::parse_object_names(&parser::load_config(config_file).unwrap()) } };
+    if ARGS.placement {
+        if config_file.is_empty() { panic!("The --placement mode requires a config file with a placement block"); }
+        parallel::install(&thread_options, || placement::run(&world, config_file));
+        return Ok(());
+    }
+    if ARGS.power_grid {
+        parallel::install(&thread_options, || floors::run(&world, cam, config_file, &ARGS.output));
+        return Ok(());
+    }
+    if ARGS.line_art {
+        parallel::install(&thread_options, || line_art::run(&world, &cam, &ARGS.output));
+        return Ok(());
+    }
+    if ARGS.uv_inspect {
+        parallel::install(&thread_options, || uv_inspect::run(&world, &cam, &object_names(), &ARGS.output));
+        return Ok(());
+    }
+    if let Some((x, y)) = ARGS.pick {
+        if let Err(err) = pick::run(&world, &cam, &object_names(), &utility::CONSTS, x, y) {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+    if let Some((x, y)) = ARGS.debug_pixel {
+        if let Err(err) = pixel_debug::run(&world, &cam, &object_names(), &utility::CONSTS, x, y, ARGS.debug_paths) {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
 
     /*
     let cam: Camera = Camera::new(
@@ -80,9 +252,17 @@ fn main() -> Result<(), std::fmt::Error> {
         10.0,
     );*/
 
-    // Render the scene to an image
-    raytracer::render_to_image_multithreaded(&world, cam, "test.png");
+    // Render the scene to an image, ctrl-C saving the partial image
+    cancel::install_handler();
+    parallel::install(&thread_options, || raytracer::render_to_image_multithreaded(&world, cam, &ARGS.output));
+    if cancel::cancelled() {
+        tracing::warn!(elapsed_ms = start_time.elapsed().as_millis() as u64, "render cancelled");
+        std::process::exit(130);
+    }
+    if ARGS.bvh_stats { println!("{}", acceleration::take_stats()); }
+    if ARGS.nan_report { print!("{}", nan_report::format_report(&nan_report::take_report(), &object_names())); }
     let end_time = std::time::Instant::now();
     println!("Elapsed time: {}ms", end_time.duration_since(start_time).as_millis());
+    tracing::info!(output = ARGS.output.as_str(), elapsed_ms = end_time.duration_since(start_time).as_millis() as u64, "render finished");
     Ok(())
 }
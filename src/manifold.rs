@@ -0,0 +1,243 @@
+// Author: Giulian Biolo, github.com/giulianbiolo
+// Date: 16/10/2026
+// Description: This file implements the manifold next event estimation, connecting the diffuse bounces to the lights seen through chains of dielectrics
+
+use glam::Vec3A;
+
+use crate::color::Color;
+use crate::hit_record::HitRecord;
+use crate::hittable_list::{Hittable, HittableList};
+use crate::medium::MediumStack;
+use crate::onb::ONB;
+use crate::point3::Point3;
+use crate::ray::Ray;
+use crate::utility;
+
+
+// The most caustic casters a chain crosses, every boundary counting once (so a glass sphere counts twice)
+pub const MAX_CHAIN: u8 = 4;
+const MAX_ITERATIONS: usize = 24;
+// Angle in radians of the finite differences of the direction leaving the shading point
+const STEP: f32 = 1e-3;
+// The solution is accepted once the chain lands this close to the light sample, relative to its distance
+const TOLERANCE: f32 = 1e-4;
+// The rings of starting directions around the straight one, by their offset from it in its tangent plane, and the directions on each
+const RESTART_RADII: [f32; 5] = [0.05, 0.1, 0.2, 0.35, 0.5];
+const RESTART_ANGLES: usize = 8;
+// The most the solved direction can turn away from the one of a path for them to count as the same, as one minus the cosine
+const SAME_PATH: f32 = 1e-4;
+
+// The ray leaving the last caster of a chain, and the light that survived the crossing
+struct Chain {
+    ray: Ray,
+    weight: Color,
+}
+
+// Follows the direction through exactly that many refractions of caustic casters, None if it hits anything else or is totally reflected on the way
+fn _trace(world: &HittableList, origin: Point3, direction: Vec3A, media: &MediumStack, interfaces: u8) -> Option<Chain> {
+    let mut ray: Ray = Ray::new(origin, direction);
+    ray.media = *media;
+    let mut weight: Color = Color::ONE;
+    for _ in 0..interfaces {
+        let rec: HitRecord = world.hit(&ray, utility::NEAR_ZERO, utility::INFINITY)?;
+        let (refracted, transmitted) = rec.mat_ptr.manifold_refraction(&ray, &rec)?;
+        weight *= transmitted * ray.media.transmittance(rec.t * ray.direction().length());
+        let mut next: Ray = Ray::new(rec.p, refracted);
+        next.media = rec.mat_ptr.update_media(&ray.media, &rec, &next);
+        ray = next;
+    }
+    Some(Chain { ray, weight })
+}
+
+// Where the chain leaving in the direction crosses the plane tangent to the light at the sample
+fn _landing(world: &HittableList, origin: Point3, direction: Vec3A, media: &MediumStack, interfaces: u8, target: Point3, normal: Vec3A) -> Option<Point3> {
+    let chain: Chain = _trace(world, origin, direction, media, interfaces)?;
+    let denominator: f32 = normal.dot(chain.ray.direction());
+    if denominator.abs() < 1e-8 { return None; }
+    let t: f32 = normal.dot(target - chain.ray.origin()) / denominator;
+    if t <= 0.0 { None } else { Some(chain.ray.at(t)) }
+}
+
+// The casters on the straight segment from the shading point to the light sample, the seed of the chain, None when anything else is in the way
+fn _seed(world: &HittableList, origin: Point3, target: Point3, media: &MediumStack) -> Option<u8> {
+    let mut ray: Ray = Ray::new(origin, target - origin);
+    ray.media = *media;
+    let mut interfaces: u8 = 0;
+    while let Some(rec) = world.hit(&ray, utility::NEAR_ZERO, 1.0) {
+        if rec.mat_ptr.is_light() { break; }
+        rec.mat_ptr.manifold_refraction(&ray, &rec)?;
+        interfaces += 1;
+        if interfaces > MAX_CHAIN { return None; }
+        // The seed goes straight through, only counting the boundaries
+        let mut next: Ray = Ray::new(rec.p, target - rec.p);
+        next.media = rec.mat_ptr.update_media(&ray.media, &rec, &next);
+        ray = next;
+    }
+    Some(interfaces)
+}
+
+// Newton's method on the direction leaving the shading point until the chain lands on the light sample
+// Returns the direction and the area on the light plane per solid angle around it, from the finite differences of the landing point
+fn _newton(land: &dyn Fn(Vec3A) -> Option<Point3>, mut direction: Vec3A, target: Point3, normal: Vec3A, tolerance: f32) -> Option<(Vec3A, f32)> {
+    let light_frame: ONB = ONB::from_w(&normal);
+    let mut landed: Point3 = land(direction)?;
+    for _ in 0..MAX_ITERATIONS {
+        let frame: ONB = ONB::from_w(&direction);
+        let du: Vec3A = (land((direction + frame.u * STEP).normalize())? - land((direction - frame.u * STEP).normalize())?) / (2.0 * STEP);
+        let dv: Vec3A = (land((direction + frame.v * STEP).normalize())? - land((direction - frame.v * STEP).normalize())?) / (2.0 * STEP);
+        let error: Vec3A = landed - target;
+        if error.length() < tolerance { return Some((direction, du.cross(dv).length())); }
+        // The step in the tangent plane of the direction which cancels the error in the plane of the light, to first order
+        let (a, b, c, d) = (du.dot(light_frame.u), dv.dot(light_frame.u), du.dot(light_frame.v), dv.dot(light_frame.v));
+        let det: f32 = a * d - b * c;
+        if det.abs() < 1e-12 { return None; }
+        let (eu, ev) = (error.dot(light_frame.u), error.dot(light_frame.v));
+        let (mut su, mut sv) = ((b * ev - d * eu) / det, (c * eu - a * ev) / det);
+        // Halves the step while it leaves the chain or lands further away
+        let mut improved: Option<(Vec3A, Point3)> = None;
+        for _ in 0..8 {
+            let candidate: Vec3A = (direction + frame.u * su + frame.v * sv).normalize();
+            if let Some(point) = land(candidate) {
+                if (point - target).length() < error.length() { improved = Some((candidate, point)); break; }
+            }
+            (su, sv) = (su / 2.0, sv / 2.0);
+        }
+        (direction, landed) = improved?;
+    }
+    None
+}
+
+// Solves for the chain landing on the light sample, starting straight towards it
+// A focusing caster folds the chains over, stalling the straight start on the fold, so the solve starts over from the directions on rings around it
+pub fn solve(world: &HittableList, origin: Point3, media: &MediumStack, interfaces: u8, target: Point3, normal: Vec3A) -> Option<(Vec3A, f32)> {
+    let land = |direction: Vec3A| _landing(world, origin, direction, media, interfaces, target, normal);
+    let tolerance: f32 = TOLERANCE * (target - origin).length();
+    let straight: Vec3A = (target - origin).normalize();
+    let frame: ONB = ONB::from_w(&straight);
+    let restarts = RESTART_RADII.iter().flat_map(|radius| (0..RESTART_ANGLES).map(move |k| {
+        let angle: f32 = 2.0 * utility::PI * k as f32 / RESTART_ANGLES as f32;
+        (straight + (frame.u * angle.cos() + frame.v * angle.sin()) * *radius).normalize()
+    }));
+    std::iter::once(straight).chain(restarts).find_map(|start| _newton(&land, start, target, normal, tolerance))
+}
+
+// The refracted path from the shading point to a point on a light, as the manifold sampling finds it
+// Returns the direction leaving the shading point, the jacobian of the solve, the chain and where it ends on the light
+fn _connect<'a>(world: &'a HittableList, origin: Point3, media: &MediumStack, link_mask: u64, light: &HitRecord) -> Option<(Vec3A, f32, Chain, HitRecord<'a>)> {
+    let interfaces: u8 = _seed(world, origin, light.p, media)?;
+    if interfaces == 0 { return None; }
+    let (direction, jacobian) = solve(world, origin, media, interfaces, light.p, light.normal)?;
+    if jacobian <= 0.0 { return None; }
+    // The solved chain has to end on the light that was sampled, seen from the side it shines to and linked to the surface
+    let chain: Chain = _trace(world, origin, direction, media, interfaces)?;
+    let end: HitRecord = world.hit(&chain.ray, utility::NEAR_ZERO, utility::INFINITY)?;
+    let landed: bool = (end.p - light.p).length() < 10.0 * TOLERANCE * (light.p - origin).length();
+    if !end.mat_ptr.is_light() || !landed || link_mask & end.link_id == 0 || (end.mat_ptr.is_one_sided() && !end.front_face) { return None; }
+    Some((direction, jacobian, chain, end))
+}
+
+// The density of the point on the lights seen in the direction, over their area
+fn _area_pdf(lights: &HittableList, origin: Point3, towards: Vec3A, light: &HitRecord) -> f32 {
+    let to_light: Vec3A = light.p - origin;
+    lights.pdf_value(&origin, &towards) * light.normal.dot(to_light.normalize()).abs() / to_light.length_squared()
+}
+
+// The light of a point sampled on the lights reaching the shading point through the caustic casters between them
+// Returns the direction it comes from and its radiance over the density of the sample, None when the sample is in plain sight (left to the light sampling) or no refracted path reaches it
+pub fn sample(world: &HittableList, lights: &HittableList, rec: &HitRecord, media: &MediumStack) -> Option<(Vec3A, Color)> {
+    let towards: Vec3A = lights.random(&rec.p);
+    let light: HitRecord = lights.hit(&Ray::new(rec.p, towards), utility::NEAR_ZERO, utility::INFINITY)?;
+    let pdf: f32 = _area_pdf(lights, rec.p, towards, &light);
+    if !pdf.is_finite() || pdf <= 0.0 { return None; }
+    let (direction, jacobian, chain, end) = _connect(world, rec.p, media, rec.link_mask, &light)?;
+    let transmittance: Color = chain.ray.media.transmittance(end.t * chain.ray.direction().length());
+    let radiance: Color = chain.weight * transmittance * end.mat_ptr.emitted(end.u, end.v, &end.p) / (pdf * jacobian);
+    if radiance.is_finite() { Some((direction, radiance)) } else { None }
+}
+
+// A path followed through caustic casters since a diffuse bounce, remembering where it left it to tell whether the manifold sampling there gathered the light it reaches
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ManifoldChain {
+    origin: Point3,
+    direction: Vec3A,
+    media: MediumStack,
+    link_mask: u64,
+    // The caster boundaries refracted through so far
+    pub interfaces: u8,
+}
+
+impl ManifoldChain {
+    // The chain of the ray scattered by the diffuse bounce, which the manifold sampling gathered with the media it was in
+    pub fn start(rec: &HitRecord, scattered: &Ray, media: &MediumStack) -> ManifoldChain {
+        ManifoldChain { origin: rec.p, direction: scattered.direction().normalize(), media: *media, link_mask: rec.link_mask, interfaces: 0 }
+    }
+    pub fn refracted(&self) -> ManifoldChain { ManifoldChain { interfaces: self.interfaces.saturating_add(1), ..*self } }
+
+    // Whether the manifold sampling at the start of the chain would have found the point of the light along this same path
+    // The solve is deterministic, so the paths it misses, like the far ones of a focusing caster or those it failed on, are left to the chain itself
+    pub fn gathered(&self, world: &HittableList, lights: &HittableList, point: Point3) -> bool {
+        if self.interfaces == 0 || self.interfaces > MAX_CHAIN { return false; }
+        // The manifold sampling only targets the points of the lights seen first from the bounce
+        let towards: Vec3A = point - self.origin;
+        let Some(light) = lights.hit(&Ray::new(self.origin, towards), utility::NEAR_ZERO, utility::INFINITY) else { return false; };
+        let pdf: f32 = _area_pdf(lights, self.origin, towards, &light);
+        if (light.p - point).length() > 10.0 * TOLERANCE * towards.length() || !pdf.is_finite() || pdf <= 0.0 { return false; }
+        _connect(world, self.origin, &self.media, self.link_mask, &light).is_some_and(|(direction, ..)| direction.dot(self.direction) > 1.0 - SAME_PATH)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::material::{BumpMapped, Dielectric, DiffuseLight, Lambertian};
+    use crate::sphere::Sphere;
+    use crate::texture::SolidColor;
+
+    // The share of the samples of the bulb from the point that the solve connects, checking that each lands on it through that many refractions
+    fn _success_rate(world: &HittableList, lights: &HittableList, point: Point3, interfaces: u8) -> f32 {
+        let floor: Lambertian = Lambertian::new(Color::ONE);
+        let below: HitRecord = HitRecord::new(point, Vec3A::Y, &floor, 1.0, 0.0, 0.0, true);
+        let connected: usize = (0..400).filter(|_| sample(world, lights, &below, &MediumStack::new()).is_some_and(|(direction, radiance)| {
+            let chain: Option<Chain> = _trace(world, below.p, direction, &MediumStack::new(), interfaces);
+            radiance.min_element() > 0.0 && chain.and_then(|chain| world.hit(&chain.ray, utility::NEAR_ZERO, utility::INFINITY)).is_some_and(|end| end.mat_ptr.is_light())
+        })).count();
+        connected as f32 / 400.0
+    }
+
+    #[test]
+    fn test_manifold_sampling() {
+        // A small bulb cast in a glass globe above the floor, which sees it only through the glass
+        let bulb: Arc<Sphere> = Arc::new(Sphere::new(Point3::new(0.0, 4.0, 0.0), 0.1, Box::new(DiffuseLight::new(Color::ONE, 10.0)), 0));
+        let globe = |caustics: bool| Arc::new(Sphere::new(Point3::new(0.0, 4.0, 0.0), 1.0, Box::new(Dielectric::new(Color::ONE, 1.5, 0.0).with_caustics(caustics)), 0));
+        let lights: HittableList = vec![bulb.clone()];
+        for x in [0.0, 1.0, 3.0] { assert!(_success_rate(&vec![globe(true), bulb.clone()], &lights, Point3::new(x, 0.0, 0.5), 1) > 0.95); }
+        // The wrappers of the glass, like a bump map, keep it a caster
+        let bumped: Arc<Sphere> = Arc::new(Sphere::new(Point3::new(0.0, 4.0, 0.0), 1.0, Box::new(BumpMapped::new(Box::new(Dielectric::new(Color::ONE, 1.5, 0.0)), Box::new(SolidColor::new(Color::ONE)), 1.0)), 0));
+        for x in [0.0, 1.0, 3.0] { assert!(_success_rate(&vec![bumped.clone(), bulb.clone()], &lights, Point3::new(x, 0.0, 0.5), 1) > 0.95); }
+        // A glass ball between a bulb and the floor focuses it, refracting twice: the straight start stalls on the fold of the far paths, which the restarts find
+        let above: Arc<Sphere> = Arc::new(Sphere::new(Point3::new(0.0, 5.0, 0.0), 0.1, Box::new(DiffuseLight::new(Color::ONE, 10.0)), 0));
+        let ball = |caustics: bool| Arc::new(Sphere::new(Point3::new(0.0, 2.0, 0.0), 1.0, Box::new(Dielectric::new(Color::ONE, 1.5, 0.0).with_caustics(caustics)), 0));
+        let (world, ball_lights): (HittableList, HittableList) = (vec![ball(true), above.clone()], vec![above.clone()]);
+        for x in [0.1, 0.6] { assert!(_success_rate(&world, &ball_lights, Point3::new(x, 0.0, 0.05), 2) > 0.85); }
+        // The path the solve finds is gathered at the light it reaches, while the ones beside it are left to the path tracing
+        let floor: Lambertian = Lambertian::new(Color::ONE);
+        let below: HitRecord = HitRecord::new(Point3::new(0.1, 0.0, 0.05), Vec3A::Y, &floor, 1.0, 0.0, 0.0, true);
+        let (direction, _) = std::iter::repeat_with(|| sample(&world, &ball_lights, &below, &MediumStack::new())).flatten().next().unwrap();
+        let chain: Chain = _trace(&world, below.p, direction, &MediumStack::new(), 2).unwrap();
+        let end: HitRecord = world.hit(&chain.ray, utility::NEAR_ZERO, utility::INFINITY).unwrap();
+        let start: ManifoldChain = ManifoldChain::start(&below, &Ray::new(below.p, direction), &MediumStack::new());
+        assert!(start.refracted().refracted().gathered(&world, &ball_lights, end.p));
+        let beside: ManifoldChain = ManifoldChain::start(&below, &Ray::new(below.p, direction + Vec3A::X * 0.05), &MediumStack::new());
+        assert!(!beside.refracted().refracted().gathered(&world, &ball_lights, end.p) && !start.gathered(&world, &ball_lights, end.p));
+        // The bare bulb is left to the light sampling, and casters without caustics block it
+        let below: HitRecord = HitRecord::new(Point3::ZERO, Vec3A::Y, &floor, 1.0, 0.0, 0.0, true);
+        assert!(sample(&vec![bulb.clone() as Arc<dyn Hittable + Send + Sync>], &lights, &below, &MediumStack::new()).is_none());
+        assert!(sample(&vec![globe(false), bulb], &lights, &below, &MediumStack::new()).is_none());
+        assert!(sample(&vec![ball(false), above], &ball_lights, &below, &MediumStack::new()).is_none());
+        // Out of the shadow of the ball the bulb is in plain sight
+        let aside: HitRecord = HitRecord::new(Point3::new(4.0, 0.0, 0.0), Vec3A::Y, &floor, 1.0, 0.0, 0.0, true);
+        assert!(sample(&world, &ball_lights, &aside, &MediumStack::new()).is_none());
+    }
+}
@@ -0,0 +1,81 @@
+// Author: Giulian Biolo, github.com/giulianbiolo
+// Date: 16/10/2026
+// Description: This file implements the material preview, rendering a material on a shader ball in front of a fixed backdrop to look-dev it on its own
+
+use yaml_rust::{Yaml, YamlLoader};
+
+use crate::assets;
+use crate::camera::Camera;
+use crate::hittable_list::HittableList;
+use crate::parser;
+use crate::raytracer;
+use crate::utility;
+
+
+// The shader ball on a checkered floor in front of a gray wall, a key light up on the left and the gradient environment around them
+// The ball is the last object of the world, its material filled in by the one previewed
+const SCENE: &str = "
+camera: { lookFrom: [0.0, 1.6, 5.5], lookAt: [0.0, 0.9, 0.0], vup: [0.0, 1.0, 0.0], vfov: 30.0, aspectRatio: 1.0, aperture: 0.0, focusDistance: 5.5 }
+world:
+  - { objType: Plane, point: [0.0, 0.0, 0.0], normal: [0.0, 1.0, 0.0], pattern: { type: checker, cellSize: 0.5, colors: [[0.75, 0.75, 0.75], [0.35, 0.35, 0.35]] } }
+  - { objType: Plane, point: [0.0, 0.0, -3.0], normal: [0.0, 0.0, 1.0], material: { matType: Lambertian, texType: SolidColor, texture: { albedo: [0.5, 0.5, 0.5] } } }
+  - { objType: Sphere, center: [-3.0, 5.0, 3.0], radius: 0.5, material: { matType: DiffuseLight, texType: SolidColor, texture: { albedo: [1.0, 1.0, 1.0] }, intensity: 12.0 } }
+  - { objType: Sphere, center: [0.0, 1.0, 0.0], radius: 1.0 }
+";
+
+// Small and quick enough to iterate on a material, with the lights sampled for the highlights
+pub fn constants() -> utility::Constants {
+    utility::Constants {
+        width: 400,
+        height: 400,
+        aspect_ratio: 1.0,
+        samples_per_pixel: 64,
+        max_depth: 16,
+        min_depth: 3,
+        light_sampling: true,
+        ..Default::default()
+    }
+}
+
+// The preview scene with the material on the ball, given as the material block itself or as an object with its material
+pub fn scene(material: &Yaml) -> Yaml {
+    let material: &Yaml = if material["matType"].is_badvalue() && !material["material"].is_badvalue() { &material["material"] } else { material };
+    let mut doc: Yaml = YamlLoader::load_from_str(SCENE).unwrap().remove(0);
+    if let Yaml::Hash(hash) = &mut doc {
+        if let Some(Yaml::Array(world)) = hash.get_mut(&Yaml::String("world".to_string())) {
+            if let Some(Yaml::Hash(ball)) = world.last_mut() { ball.insert(Yaml::String("material".to_string()), material.clone()); }
+        }
+    }
+    doc
+}
+
+// Renders the material of the file on the shader ball to the output
+pub fn run(material_file: &str, output: &str) {
+    let content: String = assets::read_to_string(material_file).unwrap_or_else(|err| panic!("Failed to read the material {}: {}", material_file, err));
+    let material: Yaml = YamlLoader::load_from_str(&content).ok().and_then(|mut docs| docs.pop()).unwrap_or_else(|| panic!("The material {} isn't a YAML document", material_file));
+    let doc: Yaml = scene(&material);
+    let (cam, world): (Camera, HittableList) = (parser::parse_camera(&doc), parser::parse_scene(&doc));
+    println!("Material preview of {}: {}x{}, {} samples per pixel", material_file, utility::CONSTS.width, utility::CONSTS.height, utility::CONSTS.samples_per_pixel);
+    raytracer::render_to_image_multithreaded(&world, cam, output);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pick;
+
+    #[test]
+    fn test_matpreview_scene() {
+        let gold: Yaml = YamlLoader::load_from_str("{ matType: Conductor, metal: gold, fuzz: 0.1 }").unwrap().remove(0);
+        let doc: Yaml = scene(&gold);
+        let (cam, world): (Camera, HittableList) = (parser::parse_camera(&doc), parser::parse_scene(&doc));
+        assert_eq!(world.len(), 4);
+        // The ball fills the middle of the frame, the floor and wall are around it
+        assert_eq!(pick::pick(&world, &cam, 64, 64, 32, 32).map(|picked| (picked.index, picked.material)), Some((3, "Conductor")));
+        assert_eq!(pick::pick(&world, &cam, 64, 64, 32, 62).map(|picked| picked.index), Some(0));
+        assert_eq!(pick::pick(&world, &cam, 64, 64, 2, 2).map(|picked| picked.index), Some(1));
+        // An object with its material works as well as the material alone
+        let object: Yaml = YamlLoader::load_from_str("{ objType: Sphere, material: { matType: Conductor, metal: gold, fuzz: 0.1 } }").unwrap().remove(0);
+        assert_eq!(scene(&object), doc);
+    }
+}
@@ -0,0 +1,65 @@
+// Author: Giulian Biolo, github.com/giulianbiolo
+// Date: 16/10/2026
+// Description: This file implements the compact accumulation buffer of compactAccumulation, for the renders too large to accumulate in padded colors
+
+use glam::Vec3;
+use image::{ImageBuffer, Rgb};
+
+use crate::color::{Color, to_rgb};
+
+
+// The running mean of a pixel rather than its sum, in three f32 without the padding of a Color
+// Half floats would need their rounding error carried alongside to keep the mean moving after thousands of samples, taking as many bytes, and would overflow past 65504
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct MeanPixel {
+    mean: Vec3,
+}
+
+impl MeanPixel {
+    // Adds the sample-th sample of the pixel, counting from 1
+    pub fn add(&mut self, color: Color, sample: u32) { self.mean += (Vec3::from(color) - self.mean) / sample as f32; }
+    pub fn color(&self) -> Color { Color::from(self.mean) }
+}
+
+/****************** MeanAccumulator ******************/
+// 12 bytes a pixel instead of the 16 of a Color
+pub struct MeanAccumulator {
+    pub width: u32,
+    pub height: u32,
+    // Row major pixels
+    pub pixels: Vec<MeanPixel>,
+    // Samples per pixel accumulated so far
+    pub samples: u32,
+}
+
+impl MeanAccumulator {
+    pub fn new(width: u32, height: u32) -> MeanAccumulator { MeanAccumulator { width, height, pixels: vec![MeanPixel::default(); width as usize * height as usize], samples: 0 } }
+    // The mean colors, for the library users
+    #[allow(dead_code)]
+    pub fn colors(&self) -> Vec<Color> { self.pixels.iter().map(|pixel| pixel.color()).collect() }
+    pub fn to_image(&self) -> ImageBuffer<Rgb<u8>, Vec<u8>> { ImageBuffer::from_fn(self.width, self.height, |x, y| to_rgb(self.pixels[(y * self.width + x) as usize].color(), 1.0)) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mean_accumulation() {
+        assert_eq!(std::mem::size_of::<MeanPixel>(), 12);
+        // Thousands of samples around a mean of 0.3, with fireflies past the range of the half floats
+        let mut pixel: MeanPixel = MeanPixel::default();
+        let mut exact: f64 = 0.0;
+        for sample in 1..=8192_u32 {
+            let value: f32 = 0.3 + 0.25 * ((sample * 7919 % 1000) as f32 / 1000.0 - 0.5) + if sample % 1000 == 0 { 1e5 } else { 0.0 };
+            pixel.add(Color::splat(value), sample);
+            exact += value as f64;
+        }
+        let exact: f32 = (exact / 8192.0) as f32;
+        assert!((pixel.color().x - exact).abs() < 1e-4 * exact);
+        let mut accumulator: MeanAccumulator = MeanAccumulator::new(2, 1);
+        accumulator.pixels[1].add(Color::new(1.0, 0.25, 0.0), 1);
+        assert_eq!(accumulator.colors(), vec![Color::ZERO, Color::new(1.0, 0.25, 0.0)]);
+        assert_eq!(*accumulator.to_image().get_pixel(1, 0), to_rgb(Color::new(1.0, 0.25, 0.0), 1.0));
+    }
+}
@@ -0,0 +1,91 @@
+// Author: Giulian Biolo, github.com/giulianbiolo
+// Date: 16/10/2026
+// Description: This file implements the stack of nested media a ray is travelling through
+
+use crate::color::Color;
+
+
+// Deeper nestings than this drop the outermost medium
+const MAX_NESTED_MEDIA: usize = 4;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Medium {
+    pub ior: f32,
+    // Where media overlap the one with the highest priority wins, and the boundaries of the others are ignored
+    pub priority: u32,
+    // Beer-Lambert absorption coefficient per unit of distance
+    pub absorption: Color,
+}
+
+impl Medium {
+    pub fn new(ior: f32, priority: u32, absorption: Color) -> Medium { Medium { ior, priority, absorption } }
+    pub fn air() -> Medium { Medium { ior: 1.0, priority: 0, absorption: Color::ZERO } }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MediumStack {
+    media: [Medium; MAX_NESTED_MEDIA],
+    len: usize,
+}
+
+impl MediumStack {
+    pub fn new() -> MediumStack { MediumStack { media: [Medium::air(); MAX_NESTED_MEDIA], len: 0 } }
+    #[allow(dead_code)]
+    pub fn len(&self) -> usize { self.len }
+    #[allow(dead_code)]
+    pub fn is_empty(&self) -> bool { self.len == 0 }
+    // The medium the ray is actually in: the one with the highest priority, or the most recently entered one on ties
+    pub fn current(&self) -> Option<&Medium> {
+        self.media[..self.len].iter().max_by_key(|medium| medium.priority)
+    }
+    pub fn ior(&self) -> f32 { self.current().map_or(1.0, |medium| medium.ior) }
+    pub fn with(&self, medium: Medium) -> MediumStack {
+        let mut stack: MediumStack = *self;
+        if stack.len == MAX_NESTED_MEDIA {
+            stack.media.rotate_left(1);
+            stack.len -= 1;
+        }
+        stack.media[stack.len] = medium;
+        stack.len += 1;
+        stack
+    }
+    pub fn without(&self, medium: &Medium) -> MediumStack {
+        let mut stack: MediumStack = *self;
+        if let Some(idx) = stack.media[..stack.len].iter().rposition(|other| other == medium) {
+            stack.media.copy_within(idx + 1..stack.len, idx);
+            stack.len -= 1;
+        }
+        stack
+    }
+    // Fraction of light that survives travelling the given distance through the current medium
+    pub fn transmittance(&self, distance: f32) -> Color {
+        match self.current() {
+            Some(medium) if medium.absorption != Color::ZERO => (-medium.absorption * distance).exp(),
+            _ => Color::ONE,
+        }
+    }
+}
+
+impl Default for MediumStack {
+    fn default() -> Self { MediumStack::new() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_medium_stack() {
+        let water: Medium = Medium::new(1.33, 1, Color::new(0.5, 0.1, 0.0));
+        let ice: Medium = Medium::new(1.31, 1, Color::ZERO);
+        let glass: Medium = Medium::new(1.5, 2, Color::ZERO);
+        let stack: MediumStack = MediumStack::new().with(glass).with(water).with(ice);
+        assert_eq!(stack.ior(), 1.5);
+        let stack: MediumStack = stack.without(&glass);
+        assert_eq!(stack.ior(), 1.31);
+        let stack: MediumStack = stack.without(&ice);
+        assert_eq!(stack.ior(), 1.33);
+        assert_eq!(stack.transmittance(2.0), Color::new((-1.0_f32).exp(), (-0.2_f32).exp(), 1.0));
+        assert!(stack.without(&water).is_empty());
+    }
+}
@@ -0,0 +1,105 @@
+// Author: Giulian Biolo, github.com/giulianbiolo
+// Date: 16/10/2026
+// Description: This file implements the tracking of the approximate memory used by the meshes, BVHs and textures, and the memory budget
+
+use std::sync::Mutex;
+
+use lazy_static::lazy_static;
+
+
+const MEGABYTE: f64 = 1024.0 * 1024.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Category {
+    Geometry,
+    Bvh,
+    Textures,
+}
+
+impl Category {
+    fn name(&self) -> &'static str {
+        match self {
+            Category::Geometry => "geometry",
+            Category::Bvh => "BVH",
+            Category::Textures => "textures",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Budget {
+    pub bytes: usize,
+    // Streams the image textures which don't fit anymore as tiles instead of failing
+    pub stream_textures: bool,
+}
+
+impl Budget {
+    pub fn from_megabytes(megabytes: usize, stream_textures: bool) -> Budget { Budget { bytes: megabytes * 1024 * 1024, stream_textures } }
+}
+
+#[derive(Debug, Default)]
+pub struct Tracker {
+    allocations: Vec<(Category, String, usize)>,
+    // Without a budget the usage is only tracked
+    budget: Option<Budget>,
+}
+
+impl Tracker {
+    pub fn used(&self) -> usize { self.allocations.iter().map(|(_, _, bytes)| bytes).sum() }
+    pub fn fits(&self, bytes: usize) -> bool { self.budget.is_none_or(|budget| self.used() + bytes <= budget.bytes) }
+    // Returns false when the allocation goes over the budget
+    pub fn track(&mut self, category: Category, name: &str, bytes: usize) -> bool {
+        let fits: bool = self.fits(bytes);
+        self.allocations.push((category, name.to_string(), bytes));
+        fits
+    }
+    // The usage of each category, with its largest allocation
+    pub fn report(&self) -> String {
+        let mut lines: Vec<String> = vec![format!("Memory usage: {:.1} MB", self.used() as f64 / MEGABYTE)];
+        for category in [Category::Geometry, Category::Bvh, Category::Textures] {
+            let items: Vec<&(Category, String, usize)> = self.allocations.iter().filter(|(item_category, _, _)| *item_category == category).collect();
+            let Some((_, largest, largest_bytes)) = items.iter().max_by_key(|(_, _, bytes)| *bytes) else { continue; };
+            let bytes: usize = items.iter().map(|(_, _, bytes)| bytes).sum();
+            lines.push(format!("{:>12}: {:>9.1} MB in {} items, the largest being {} ({:.1} MB)", category.name(), bytes as f64 / MEGABYTE, items.len(), largest, *largest_bytes as f64 / MEGABYTE));
+        }
+        lines.join("\n")
+    }
+}
+
+/****************** Global tracker ******************/
+lazy_static! { static ref TRACKER: Mutex<Tracker> = Mutex::new(Tracker::default()); }
+
+pub fn set_budget(budget: Option<Budget>) { TRACKER.lock().unwrap().budget = budget; }
+
+pub fn fits(bytes: usize) -> bool { TRACKER.lock().unwrap().fits(bytes) }
+
+pub fn streams_textures() -> bool { TRACKER.lock().unwrap().budget.is_some_and(|budget| budget.stream_textures) }
+
+// Fails with the breakdown of the usage when the allocation goes over the budget, before the render gets killed halfway
+pub fn track(category: Category, name: &str, bytes: usize) {
+    let over_budget: Option<String> = {
+        let mut tracker = TRACKER.lock().unwrap();
+        if tracker.track(category, name, bytes) { None }
+        else { Some(format!("Loading {} ({:.1} MB of {}) goes over the memory budget of {:.1} MB\n{}", name, bytes as f64 / MEGABYTE, category.name(), tracker.budget.unwrap().bytes as f64 / MEGABYTE, tracker.report())) }
+    };
+    if let Some(message) = over_budget { panic!("{}", message); }
+}
+
+pub fn report() -> String { TRACKER.lock().unwrap().report() }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_budget() {
+        let mut tracker: Tracker = Tracker { allocations: Vec::new(), budget: Some(Budget::from_megabytes(1, false)) };
+        assert!(tracker.track(Category::Geometry, "dragon.obj", 512 * 1024));
+        assert!(tracker.track(Category::Bvh, "dragon.obj", 256 * 1024));
+        assert!(tracker.fits(256 * 1024) && !tracker.fits(256 * 1024 + 1));
+        // Still tracked when it doesn't fit, so that the report shows it
+        assert!(!tracker.track(Category::Textures, "earth.png", 1024 * 1024));
+        assert_eq!(tracker.used(), 1792 * 1024);
+        assert!(tracker.report().contains("textures:       1.0 MB in 1 items, the largest being earth.png"));
+    }
+}
@@ -3,24 +3,32 @@
 // Description: This file implements the Mesh struct
 
 use std::collections::HashMap;
+use std::error::Error;
 use std::ops::Mul;
 
 use bvh::aabb::Bounded;
 use bvh::bounding_hierarchy::BHShape;
 use stl_io::{self, Vector};
-use obj::{load_obj, Obj};
+use obj::{load_obj, Obj, TexturedVertex};
 
-use bvh::bvh::BVH;
-use bvh::{Point3 as BVHPoint3, Vector3 as BVHVector3};
-use bvh::ray::Ray as BVHRay;
+use bvh::bvh::{BVH, BVHNode};
 
-use glam::Vec3A;
+use glam::{Vec2, Vec3A, Vec4};
 
+use crate::acceleration::{self, BVHParams};
+use crate::assets;
+use crate::cli::ARGS;
 use crate::ray::Ray;
 use crate::hit_record::HitRecord;
+use crate::diffraction::Edge;
+use crate::gpu_scene::FlatTriangle;
 use crate::hittable_list::Hittable;
-use crate::material::Material;
+use crate::light_sampler::LightEstimate;
+use crate::memory;
+use crate::color::Color;
+use crate::material::{Material, Dirt, Wireframe};
 use crate::point3::Point3;
+use crate::simd::{Packet, Triangle4};
 use crate::triangle::Triangle;
 use crate::utility::{INFINITY, NEG_INFINITY, self};
 
@@ -28,7 +36,12 @@ use crate::utility::{INFINITY, NEG_INFINITY, self};
 #[derive(Clone)]
 pub struct Mesh {
     triangles: Vec<Triangle>,
+    // The BVH leaves are packets of triangles, tested 4 at a time
+    packets: Vec<Packet<Triangle4>>,
     bvh: BVH,
+    // The emissive triangles and their cumulative areas, which are sampled proportionally to their area
+    emitters: Vec<usize>,
+    emitters_cdf: Vec<f32>,
     node_index: usize,
 }
 
@@ -37,26 +50,112 @@ unsafe impl Send for Mesh {}
 
 impl Mesh {
     #[allow(dead_code)]
-    pub fn new(position: Point3, scaling_factor: f32, rotation: Vec3A, filename: &str, material: Box<dyn Material>) -> Mesh {
-        let mut triangles: Vec<Triangle>;
-        match filename.split('.').last().unwrap() {
-            "stl" => triangles = Mesh::_load_stl_triangles(position, scaling_factor, rotation, filename, material),
-            "obj" => triangles = Mesh::_load_obj_triangles(position, scaling_factor, rotation, filename, material),
-            _ => panic!("File format not supported for: {}", filename),
+    pub fn new(position: Point3, scaling_factor: f32, rotation: Vec3A, filename: &str, material: Box<dyn Material>, params: &BVHParams) -> Mesh {
+        Mesh::try_new(position, scaling_factor, rotation, filename, material, params).unwrap_or_else(|err| panic!("{}", err))
+    }
+    // The unsupported formats, and the models failing to load when they aren't replaced by placeholders, come back as errors
+    pub fn try_new(position: Point3, scaling_factor: f32, rotation: Vec3A, filename: &str, material: Box<dyn Material>, params: &BVHParams) -> Result<Mesh, String> {
+        let loaded_triangles: Result<Vec<Triangle>, Box<dyn Error>> = match filename.split('.').last().unwrap() {
+            "stl" => Mesh::_load_stl_triangles(position, scaling_factor, rotation, filename, material.clone()),
+            "obj" => Mesh::_load_obj_triangles(position, scaling_factor, rotation, filename, material.clone()),
+            _ => return Err(format!("File format not supported for: {}", filename)),
+        };
+        let triangles: Vec<Triangle> = match loaded_triangles {
+            Ok(triangles) => triangles,
+            Err(err) => {
+                ARGS.missing_assets.check("mesh", filename, &err)?;
+                Mesh::_placeholder_cube_triangles(position, scaling_factor, rotation, material)
+            },
         };
-        let bvh: BVH = BVH::build(&mut triangles);
-        Mesh { triangles, bvh, node_index: 0 }
+        // The triangles are counted before building the BVH, so that a model too big fails before the build
+        tracing::info!(filename, triangles = triangles.len(), "mesh loaded");
+        memory::track(memory::Category::Geometry, filename, triangles.len() * Mesh::TRIANGLE_BYTES);
+        let mesh: Mesh = Mesh::new_from_triangles(triangles, params);
+        memory::track(memory::Category::Bvh, filename, mesh.bvh.nodes.len() * std::mem::size_of::<BVHNode>());
+        Ok(mesh)
+    }
+    // A triangle, its vertices and normals, and its share of the packets of the BVH leaves
+    const TRIANGLE_BYTES: usize = std::mem::size_of::<Triangle>() + 2 * std::mem::size_of::<[Vec3A; 3]>() + std::mem::size_of::<Packet<Triangle4>>() / crate::simd::LANES;
+    pub fn new_from_triangles(triangles: Vec<Triangle>, params: &BVHParams) -> Mesh {
+        let (triangles, mut packets) = Packet::build(triangles, params, Triangle4::new);
+        let bvh: BVH = acceleration::build(&mut packets, params);
+        let mut mesh: Mesh = Mesh { triangles, packets, bvh, emitters: Vec::new(), emitters_cdf: Vec::new(), node_index: 0 };
+        mesh._find_emitters();
+        mesh
+    }
+    #[allow(dead_code)]
+    pub fn new_placeholder(position: Point3, scaling_factor: f32, rotation: Vec3A, material: Box<dyn Material>) -> Mesh {
+        Mesh::new_from_triangles(Mesh::_placeholder_cube_triangles(position, scaling_factor, rotation, material), &BVHParams::default())
+    }
+    pub fn triangles(&self) -> &[Triangle] { &self.triangles }
+    // Swaps the material of every triangle, keeping the already built BVH
+    pub fn set_material(&mut self, material: Box<dyn Material>) {
+        for triangle in self.triangles.iter_mut() { triangle.set_material(material.clone()); }
+        self._find_emitters();
+    }
+    // Blends the diffuse color of the mesh towards the color of the dirt where it's concave, an amount around 2.0 fills the right angled creases
+    pub fn with_dirt(mut self, color: Color, amount: f32) -> Mesh {
+        let cavities: Vec<[f32; 3]> = _vertex_cavities(&self.triangles);
+        for (triangle, cavity) in self.triangles.iter_mut().zip(cavities) {
+            let material: Box<dyn Material> = Box::new(Dirt::new(dyn_clone::clone_box(triangle.material()), color, amount));
+            triangle.set_material(material);
+            triangle.set_cavity(cavity);
+        }
+        self
+    }
+    // Draws the edges of the triangles over the material of the mesh, as lines of a color, a width in world units and an opacity
+    pub fn with_wireframe(mut self, color: Color, width: f32, opacity: f32) -> Mesh {
+        for triangle in self.triangles.iter_mut() {
+            let material: Box<dyn Material> = Box::new(Wireframe::new(dyn_clone::clone_box(triangle.material()), color, width).with_opacity(opacity));
+            triangle.set_material(material);
+            triangle.set_wireframe();
+        }
+        self
+    }
+    fn _find_emitters(&mut self) {
+        self.emitters = (0..self.triangles.len()).filter(|idx| self.triangles[*idx].is_light()).collect();
+        self.emitters_cdf = self.emitters.iter().scan(0.0, |area, idx| { *area += self.triangles[*idx].area(); Some(*area) }).collect();
     }
-    fn _load_obj_triangles(position: Point3, scaling_factor: f32, rotation: Vec3A, filename: &str, material: Box<dyn Material>) -> Vec<Triangle> {
+    fn _placeholder_cube_triangles(position: Point3, scaling_factor: f32, rotation: Vec3A, material: Box<dyn Material>) -> Vec<Triangle> {
+        // A unit cube transformed exactly like a loaded model would be
+        let rotation_matrix: glam::Mat3A =
+          glam::Mat3A::from_rotation_x(rotation[0].to_radians())
+        * glam::Mat3A::from_rotation_y(rotation[1].to_radians())
+        * glam::Mat3A::from_rotation_z(rotation[2].to_radians());
+        let corners: Vec<Vec3A> = (0..8).map(|i| {
+            let corner: Vec3A = Vec3A::new((i & 1) as f32, ((i >> 1) & 1) as f32, ((i >> 2) & 1) as f32) - Vec3A::splat(0.5);
+            rotation_matrix.mul(corner * scaling_factor + position)
+        }).collect();
+        let faces: [[usize; 3]; 12] = [
+            [0, 2, 1], [1, 2, 3], [4, 5, 6], [5, 7, 6], // -Z, +Z
+            [0, 1, 4], [1, 5, 4], [2, 6, 3], [3, 6, 7], // -Y, +Y
+            [0, 4, 2], [2, 4, 6], [1, 3, 5], [3, 7, 5], // -X, +X
+        ];
+        faces.iter().map(|face| {
+            // Flat shaded, the normals get computed by the triangle itself
+            Triangle::new(Box::new([corners[face[0]], corners[face[1]], corners[face[2]]]), Box::new([Vec3A::ZERO; 3]), material.clone(), 0)
+        }).collect()
+    }
+    fn _load_obj_triangles(position: Point3, scaling_factor: f32, rotation: Vec3A, filename: &str, material: Box<dyn Material>) -> Result<Vec<Triangle>, Box<dyn Error>> {
         // let mut triangles: Vec<Triangle> = Vec::new();
-        let objfile = std::fs::File::open(filename).unwrap();
-        let input = std::io::BufReader::new(objfile);
-        let mut model: Obj = load_obj(input).expect("Failed to load obj");
+        let bytes: Vec<u8> = assets::read(filename)?;
+        // The texture coordinates are kept when the model has them, the loader refusing the models without
+        let (mut positions, uvs, indices): (Vec<[f32; 3]>, Option<Vec<Vec2>>, Vec<u16>) = match load_obj::<TexturedVertex, _, u16>(std::io::Cursor::new(&bytes)) {
+            Ok(model) => (
+                model.vertices.iter().map(|vertex| vertex.position).collect(),
+                Some(model.vertices.iter().map(|vertex| Vec2::new(vertex.texture[0], vertex.texture[1])).collect()),
+                model.indices,
+            ),
+            Err(_) => {
+                let model: Obj = load_obj(std::io::Cursor::new(&bytes))?;
+                (model.vertices.iter().map(|vertex| vertex.position).collect(), None, model.indices)
+            },
+        };
 
-        let (min, max) = model.vertices.iter().fold(
+        let (min, max) = positions.iter().fold(
             (Vec3A::new(INFINITY, INFINITY, INFINITY), Vec3A::new(NEG_INFINITY, NEG_INFINITY, NEG_INFINITY)),
             |(min, max), v| {
-                let ve = Vec3A::new(v.position[0], v.position[1], v.position[2]);
+                let ve = Vec3A::from(*v);
                 (min.min(ve), max.max(ve))
             }
         );
@@ -68,47 +167,44 @@ impl Mesh {
           glam::Mat3A::from_rotation_x(rotation[0].to_radians())
         * glam::Mat3A::from_rotation_y(rotation[1].to_radians())
         * glam::Mat3A::from_rotation_z(rotation[2].to_radians());
-        for vertex in model.vertices.iter_mut() {
-            let mut v: Vec3A = Vec3A::new(vertex.position[0], vertex.position[1], vertex.position[2]);
+        for vertex in positions.iter_mut() {
+            let mut v: Vec3A = Vec3A::from(*vertex);
             v = v * scaling_factor;
             v = v + position;
             v = rotation_matrix.mul(v);
-            vertex.position = [v.x, v.y, v.z];
+            *vertex = [v.x, v.y, v.z];
         }
 
         // ! This hasn't yet been tested, it's been copied from the stl loader, that one works
         // TODO: Test obj loader and see if normals are being computed correctly
         let mut normals_hash: HashMap<usize, Vec3A> = HashMap::new();
-        for face in model.indices.chunks(3) {
-            let v0: Vec3A = Vec3A::new(model.vertices[face[0] as usize].position[0], model.vertices[face[0] as usize].position[1], model.vertices[face[0] as usize].position[2]);
-            let v1: Vec3A = Vec3A::new(model.vertices[face[1] as usize].position[0], model.vertices[face[1] as usize].position[1], model.vertices[face[1] as usize].position[2]);
-            let v2: Vec3A = Vec3A::new(model.vertices[face[2] as usize].position[0], model.vertices[face[2] as usize].position[1], model.vertices[face[2] as usize].position[2]);
+        for face in indices.chunks(3) {
+            let (v0, v1, v2) = (Vec3A::from(positions[face[0] as usize]), Vec3A::from(positions[face[1] as usize]), Vec3A::from(positions[face[2] as usize]));
             let normal: Vec3A = (v1 - v0).cross(v2 - v0).normalize();
             normals_hash.insert(face[0] as usize, normal);
             normals_hash.insert(face[1] as usize, normal);
             normals_hash.insert(face[2] as usize, normal);
         }
         // We then return the triangles
-        (0..model.indices.len()).step_by(3).fold(
+        Ok((0..indices.len()).step_by(3).fold(
             Vec::new(),
             |mut triangles, idx| {
-                let v0: Vec3A = Vec3A::new(model.vertices[model.indices[idx] as usize].position[0], model.vertices[model.indices[idx] as usize].position[1], model.vertices[model.indices[idx] as usize].position[2]);
-                let v1: Vec3A = Vec3A::new(model.vertices[model.indices[idx + 1] as usize].position[0], model.vertices[model.indices[idx + 1] as usize].position[1], model.vertices[model.indices[idx + 1] as usize].position[2]);
-                let v2: Vec3A = Vec3A::new(model.vertices[model.indices[idx + 2] as usize].position[0], model.vertices[model.indices[idx + 2] as usize].position[1], model.vertices[model.indices[idx + 2] as usize].position[2]);
+                let face: [usize; 3] = [indices[idx] as usize, indices[idx + 1] as usize, indices[idx + 2] as usize];
+                let vertices: Box<[Point3; 3]> = Box::new(face.map(|vertex| Vec3A::from(positions[vertex])));
                 // Check whether the triangle is degenerate
                 // if (v0 - v1).length_squared() < EPSILON || (v1 - v2).length_squared() < EPSILON || (v2 - v0).length_squared() < EPSILON { return triangles; }
-                let n0: Vec3A = normals_hash[&(model.indices[idx] as usize)];
-                let n1: Vec3A = normals_hash[&(model.indices[idx + 1] as usize)];
-                let n2: Vec3A = normals_hash[&(model.indices[idx + 2] as usize)];
-                let normals: Box<[Vec3A; 3]> = Box::new([n0, n1, n2]);
-                triangles.push(Triangle::new(Box::new([v0, v1, v2]), normals, material.clone(), 0));
+                let normals: Box<[Vec3A; 3]> = Box::new(face.map(|vertex| normals_hash[&vertex]));
+                triangles.push(match &uvs {
+                    Some(uvs) => Triangle::new_with_uvs(vertices, normals, Box::new(face.map(|vertex| uvs[vertex])), material.clone(), 0),
+                    None => Triangle::new(vertices, normals, material.clone(), 0),
+                });
                 triangles
             }
-        )
+        ))
     }
-    fn _load_stl_triangles(position: Point3, scaling_factor: f32, rotation: Vec3A, filename: &str, material: Box<dyn Material>) -> Vec<Triangle> {
-        let mut stlfile = std::fs::OpenOptions::new().read(true).open(filename).unwrap();
-        let mut stl = stl_io::read_stl(&mut stlfile).unwrap();
+    fn _load_stl_triangles(position: Point3, scaling_factor: f32, rotation: Vec3A, filename: &str, material: Box<dyn Material>) -> Result<Vec<Triangle>, Box<dyn Error>> {
+        let mut stlfile = std::io::Cursor::new(assets::read(filename)?);
+        let mut stl = stl_io::read_stl(&mut stlfile)?;
         // let mut triangles: Vec<Triangle> = Vec::new();
         let (min, max) = stl.vertices.iter().fold(
             (Vec3A::new(INFINITY, INFINITY, INFINITY), Vec3A::new(NEG_INFINITY, NEG_INFINITY, NEG_INFINITY)),
@@ -144,8 +240,8 @@ impl Mesh {
         }
         for normal in normals_hash.values_mut() { *normal = normal.normalize(); }
 
-        // We then return the triangles
-        stl.faces.iter().map(|face|{
+        // We then return the triangles, without the degenerate ones
+        let mut triangles: Vec<Triangle> = stl.faces.iter().map(|face|{
             let v0: Vec3A = Vec3A::new(stl.vertices[face.vertices[0] as usize][0], stl.vertices[face.vertices[0] as usize][1], stl.vertices[face.vertices[0] as usize][2]);
             let v1: Vec3A = Vec3A::new(stl.vertices[face.vertices[1] as usize][0], stl.vertices[face.vertices[1] as usize][1], stl.vertices[face.vertices[1] as usize][2]);
             let v2: Vec3A = Vec3A::new(stl.vertices[face.vertices[2] as usize][0], stl.vertices[face.vertices[2] as usize][1], stl.vertices[face.vertices[2] as usize][2]);
@@ -155,12 +251,48 @@ impl Mesh {
                 normals_hash[&(face.vertices[2] as usize)],
             ]);
             Triangle::new(Box::new([v0, v1, v2]), normals, material.clone(), 0)
-        })
-        .filter(|triangle| triangle.check_not_degenerate())
-        .collect()
+        }).collect();
+        let loaded: usize = triangles.len();
+        triangles.retain(|triangle| triangle.check_not_degenerate());
+        if triangles.len() < loaded { tracing::warn!(filename, dropped = loaded - triangles.len(), "degenerate triangles dropped"); }
+        Ok(triangles)
     }
 }
 
+// How concave the mesh is at the vertices of each triangle, the vertices sharing a position being welded together
+// The cavity is the average elevation of the neighbours over the tangent plane (the sine of their angle with it), zero where the mesh is convex
+fn _vertex_cavities(triangles: &[Triangle]) -> Vec<[f32; 3]> {
+    let mut welded: HashMap<[u32; 3], usize> = HashMap::new();
+    let indices: Vec<[usize; 3]> = triangles.iter().map(|triangle| triangle.vertices().map(|vertex| {
+        let next: usize = welded.len();
+        *welded.entry([vertex.x.to_bits(), vertex.y.to_bits(), vertex.z.to_bits()]).or_insert(next)
+    })).collect();
+    let mut positions: Vec<Point3> = vec![Point3::ZERO; welded.len()];
+    // Area weighted, from the faces since the normals of the files can be missing or flipped
+    let mut normals: Vec<Vec3A> = vec![Vec3A::ZERO; welded.len()];
+    for (triangle, face) in triangles.iter().zip(&indices) {
+        let vertices: &[Point3; 3] = triangle.vertices();
+        let normal: Vec3A = (vertices[1] - vertices[0]).cross(vertices[2] - vertices[0]);
+        for corner in 0..3 {
+            positions[face[corner]] = vertices[corner];
+            normals[face[corner]] += normal;
+        }
+    }
+    let mut elevations: Vec<(f32, u32)> = vec![(0.0, 0); welded.len()];
+    for face in &indices {
+        for corner in 0..3 {
+            let (vertex, neighbour) = (face[corner], face[(corner + 1) % 3]);
+            for (from, to) in [(vertex, neighbour), (neighbour, vertex)] {
+                let edge: Vec3A = (positions[to] - positions[from]).normalize_or_zero();
+                elevations[from].0 += normals[from].normalize_or_zero().dot(edge);
+                elevations[from].1 += 1;
+            }
+        }
+    }
+    let cavity: Vec<f32> = elevations.iter().map(|(elevation, count)| (elevation / (*count).max(1) as f32).clamp(0.0, 1.0)).collect();
+    indices.iter().map(|face| face.map(|vertex| cavity[vertex])).collect()
+}
+
 impl Bounded for Mesh {
     fn aabb(&self) -> bvh::aabb::AABB {
         let (cmin, cmax) = self.triangles.iter().fold((self.triangles[0].aabb().center(), self.triangles[0].aabb().center()), |(cmin, cmax), triangle| {
@@ -177,18 +309,129 @@ impl BHShape for Mesh {
 }
 
 impl Hittable for Mesh {
-    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
-        let bvhray: BVHRay = BVHRay::new(BVHPoint3::new(ray.origin[0], ray.origin[1], ray.origin[2]), BVHVector3::new(ray.direction[0], ray.direction[1], ray.direction[2]));
-        let hit_triangles_aabbs: Vec<&Triangle> = self.bvh.traverse(&bvhray, &self.triangles);
-        hit_triangles_aabbs.iter()
-        .filter_map(|triangle| triangle.hit(ray, t_min, t_max))
-        .filter(|hit| hit.t > t_min && hit.t < t_max)
-        .min_by(|hit1, hit2| { hit1.t.partial_cmp(&hit2.t).unwrap() })
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        let mut closest: Option<HitRecord> = None;
+        acceleration::traverse(&self.bvh, ray, t_min, t_max, |packet_index, mut t_max| {
+            let packet: &Packet<Triangle4> = &self.packets[packet_index];
+            acceleration::record_primitives(packet.count);
+            let distances: Vec4 = packet.kernel.intersect(ray, t_min, t_max);
+            // Only the candidates closer than the current hit need the full (scalar) intersection, which also handles the alpha
+            for lane in packet.hit_lanes(distances) {
+                if distances[lane] >= t_max { break; }
+                if let Some(mut rec) = self.triangles[packet.first + lane].hit(ray, t_min, t_max) {
+                    rec.primitive = packet.primitives[lane];
+                    t_max = rec.t;
+                    closest = Some(rec);
+                }
+            }
+            t_max
+        });
+        closest
+    }
+    fn occluded(&self, ray: &Ray, t_min: f32, t_max: f32) -> bool {
+        let mut occluded: bool = false;
+        acceleration::traverse(&self.bvh, ray, t_min, t_max, |packet_index, t_max| {
+            let packet: &Packet<Triangle4> = &self.packets[packet_index];
+            acceleration::record_primitives(packet.count);
+            let distances: Vec4 = packet.kernel.intersect(ray, t_min, t_max);
+            occluded = packet.hit_lanes(distances).any(|lane| self.triangles[packet.first + lane].occluded(ray, t_min, t_max));
+            // Any hit will do, so the traversal stops at the first one
+            if occluded { NEG_INFINITY } else { t_max }
+        });
+        occluded
     }
     fn is_light(&self) -> bool { self.triangles.iter().any(|triangle| triangle.is_light()) }
+    fn world_bounds(&self) -> Option<bvh::aabb::AABB> { Some(self.aabb()) }
+    fn light_estimate(&self) -> Option<LightEstimate> { LightEstimate::merge(self.triangles.iter().filter_map(|triangle| triangle.light_estimate())) }
+    // Only the emissive triangles are sampled, so the non emissive ones don't get light samples nor count in the density
     fn pdf_value(&self, origin: &Point3, v: &Vec3A) -> f32 {
-        let weight: f32 = 1.0 / self.triangles.len() as f32;
-        self.triangles.iter().map(|triangle| triangle.pdf_value(origin, v) * weight).sum()
+        let total_area: f32 = match self.emitters_cdf.last() {
+            Some(area) => *area,
+            None => return 0.0,
+        };
+        // Every emissive triangle along the line could have been sampled, not just the nearest one
+        let ray: Ray = Ray::new(*origin, *v);
+        let mut pdf: f32 = 0.0;
+        acceleration::traverse(&self.bvh, &ray, utility::NEAR_ZERO, INFINITY, |packet_index, t_max| {
+            let packet: &Packet<Triangle4> = &self.packets[packet_index];
+            let distances: Vec4 = packet.kernel.intersect(&ray, utility::NEAR_ZERO, t_max);
+            for lane in packet.hit_lanes(distances) {
+                if distances[lane] >= t_max { break; }
+                let triangle: &Triangle = &self.triangles[packet.first + lane];
+                if triangle.is_light() { pdf += triangle.pdf_value(origin, v) * triangle.area() / total_area; }
+            }
+            t_max
+        });
+        pdf
+    }
+    fn random(&self, o: &Point3) -> Vec3A {
+        let total_area: f32 = match self.emitters_cdf.last() {
+            Some(area) => *area,
+            None => return Vec3A::X,
+        };
+        let area: f32 = utility::random_f32() * total_area;
+        let idx: usize = self.emitters_cdf.partition_point(|cumulative| *cumulative <= area).min(self.emitters.len() - 1);
+        self.triangles[self.emitters[idx]].random(o)
+    }
+    fn edges(&self) -> Vec<Edge> { self.triangles.iter().flat_map(|triangle| triangle.edges()).collect() }
+    fn tessellation(&self) -> Vec<FlatTriangle> { self.triangles.iter().flat_map(|triangle| triangle.tessellation()).collect() }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::Color;
+    use crate::material::{DiffuseLight, Lambertian};
+
+    #[test]
+    fn test_placeholder_cube_hit() {
+        let material: Box<dyn Material> = Box::new(Lambertian::new(Color::new(0.0, 0.0, 0.0)));
+        let mesh: Mesh = Mesh::new_placeholder(Point3::ZERO, 2.0, Vec3A::ZERO, material);
+        assert_eq!(mesh.triangles.len(), 12);
+        let ray: Ray = Ray::new(Point3::new(0.0, 0.0, -5.0), Vec3A::new(0.0, 0.0, 1.0));
+        let rec: HitRecord = mesh.hit(&ray, 0.0, 100.0).unwrap();
+        assert!((rec.t - 4.0).abs() < utility::EPSILON);
+        assert!(rec.front_face);
+        assert!(mesh.occluded(&ray, 0.0, 100.0));
+        assert!(!mesh.occluded(&ray, 0.0, 3.9));
+    }
+    #[test]
+    fn test_dirt() {
+        // The cube is convex from the outside, while turned inside out every corner is a crevice
+        let material: Box<dyn Material> = Box::new(Lambertian::new(Color::ONE));
+        let cube: Mesh = Mesh::new_placeholder(Point3::ZERO, 2.0, Vec3A::ZERO, material.clone()).with_dirt(Color::ZERO, 1.0);
+        assert_eq!(cube.hit(&Ray::new(Point3::new(0.2, 0.3, -5.0), Vec3A::Z), 0.0, 100.0).unwrap().cavity, 0.0);
+        let inverted: Vec<Triangle> = Mesh::_placeholder_cube_triangles(Point3::ZERO, 2.0, Vec3A::ZERO, material).iter().map(|triangle| {
+            let vertices: &[Point3; 3] = triangle.vertices();
+            Triangle::new(Box::new([vertices[0], vertices[2], vertices[1]]), Box::new([Vec3A::ZERO; 3]), dyn_clone::clone_box(triangle.material()), 0)
+        }).collect();
+        let room: Mesh = Mesh::new_from_triangles(inverted, &BVHParams::default()).with_dirt(Color::ZERO, 1.0);
+        let rec: HitRecord = room.hit(&Ray::new(Point3::ZERO, Vec3A::new(0.9, 0.9, 1.0)), 0.0, 100.0).unwrap();
+        assert!(rec.cavity > 0.3 && rec.front_face, "{}", rec.cavity);
+    }
+    #[test]
+    fn test_wireframe() {
+        // The face of the cube is hit 0.1 off of its border, further from either diagonal, but the triangles without the wireframe have no edges
+        let cube: Mesh = Mesh::new_placeholder(Point3::ZERO, 2.0, Vec3A::ZERO, Box::new(Lambertian::new(Color::ONE)));
+        let ray: Ray = Ray::new(Point3::new(0.9, 0.5, -5.0), Vec3A::Z);
+        assert_eq!(cube.hit(&ray, 0.0, 100.0).unwrap().edge_distance, f32::INFINITY);
+        let distance: f32 = cube.with_wireframe(Color::ZERO, 0.05, 1.0).hit(&ray, 0.0, 100.0).unwrap().edge_distance;
+        assert!((distance - 0.1).abs() < 1e-4, "{}", distance);
+    }
+    #[test]
+    fn test_emitters_pdf() {
+        // Seen from inside of an emissive cube every direction hits a single face, so the density integrates to one over the sphere
+        let mesh: Mesh = Mesh::new_placeholder(Point3::ZERO, 2.0, Vec3A::ZERO, Box::new(DiffuseLight::new(Color::ONE, 1.0)));
+        let origin: Point3 = Point3::new(0.3, -0.2, 0.1);
+        let samples: usize = 20000;
+        let integral: f32 = (0..samples).map(|_| mesh.pdf_value(&origin, &utility::random_unit_vector())).sum::<f32>() * 4.0 * utility::PI / samples as f32;
+        assert!((integral - 1.0).abs() < 0.05, "{}", integral);
+        let direction: Vec3A = mesh.random(&origin);
+        assert!(mesh.pdf_value(&origin, &direction) > 0.0);
+        // Without emissive triangles there is nothing to sample
+        let mut mesh: Mesh = mesh;
+        mesh.set_material(Box::new(Lambertian::new(Color::ONE)));
+        assert_eq!(mesh.pdf_value(&origin, &direction), 0.0);
     }
-    fn random(&self, o: &Point3) -> Vec3A { self.triangles[utility::random_usize_range(0, self.triangles.len())].random(o) }
 }
@@ -0,0 +1,146 @@
+// Author: Giulian Biolo, github.com/giulianbiolo
+// Date: 16/10/2026
+// Description: This file implements the NaN report, finding the bounce, object and material behind every non-finite sample of the render
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::camera::Camera;
+use crate::hittable_list::{Hittable, HittableList};
+use crate::pixel_debug::{self, Bounce};
+use crate::raytracer;
+use crate::sampling_filters::Filter;
+use crate::utility::{self, Constants};
+
+
+// The value of the first bounce of the path that isn't finite
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Quantity {
+    // The hit point, the normal or the direction of the ray
+    Geometry,
+    // The density the scattered direction was sampled with, and the one of the material
+    Pdf,
+    ScatteringPdf,
+    Emitted,
+    Weight,
+    // Every value was finite, their product along the path overflowed
+    Overflow,
+}
+
+impl std::fmt::Display for Quantity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", match self {
+            Quantity::Geometry => "hit point, normal or direction",
+            Quantity::Pdf => "sampling pdf",
+            Quantity::ScatteringPdf => "material pdf",
+            Quantity::Emitted => "emitted light",
+            Quantity::Weight => "bounce weight",
+            Quantity::Overflow => "throughput overflow",
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct NanSource {
+    // Index of the object in the world, None for the environment
+    pub object: Option<usize>,
+    pub material: &'static str,
+    pub depth: u32,
+    pub quantity: Quantity,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct NanCounts {
+    pub nan: u64,
+    pub infinite: u64,
+}
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static SOURCES: Mutex<BTreeMap<NanSource, NanCounts>> = Mutex::new(BTreeMap::new());
+
+// The non-finite samples are only traced again when asked for, they are dropped from the image either way
+pub fn enable() { ENABLED.store(true, Ordering::Relaxed); }
+pub fn enabled() -> bool { ENABLED.load(Ordering::Relaxed) }
+
+fn _is_finite(value: Option<f32>) -> bool { value.is_none_or(f32::is_finite) }
+
+// The first bounce with a non-finite value, or the one where the throughput stopped being finite
+pub fn find_source(world: &HittableList, bounces: &[Bounce]) -> Option<NanSource> {
+    let source = |bounce: &Bounce, quantity: Quantity| NanSource { object: pixel_debug::object_index(world, bounce), material: bounce.material, depth: bounce.depth, quantity };
+    for bounce in bounces {
+        let quantity: Option<Quantity> = if !(bounce.origin.is_finite() && bounce.direction.is_finite() && bounce.point.is_finite() && bounce.normal.is_finite()) { Some(Quantity::Geometry) }
+            else if !_is_finite(bounce.pdf) { Some(Quantity::Pdf) }
+            else if !_is_finite(bounce.scattering_pdf) { Some(Quantity::ScatteringPdf) }
+            else if !bounce.emitted.is_finite() { Some(Quantity::Emitted) }
+            else if !bounce.weight.is_finite() { Some(Quantity::Weight) }
+            else { None };
+        if let Some(quantity) = quantity { return Some(source(bounce, quantity)); }
+    }
+    let (throughputs, _) = pixel_debug::throughputs(bounces);
+    bounces.iter().zip(throughputs).find(|(bounce, throughput)| !(*throughput * bounce.emitted).is_finite() || !(*throughput * bounce.weight).is_finite())
+        .map(|(bounce, _)| source(bounce, Quantity::Overflow))
+}
+
+// Traces again the sample of the pixel drawn from the random state, which came out non-finite, and counts it under its source
+// The random numbers of the thread are left as they were, so the render goes on with the same ones
+#[allow(clippy::too_many_arguments)]
+pub fn record(world: &HittableList, lights: &HittableList, environment_map: &Arc<dyn Hittable + Send + Sync>, background: Option<&Arc<dyn Hittable + Send + Sync>>, filter: &(dyn Filter + Send + Sync), cam: &Camera, consts: &Constants, x: usize, y: usize, state: u64) {
+    let after: u64 = utility::random_state();
+    utility::set_random_state(state);
+    let (radiance, bounces) = pixel_debug::trace_path(&raytracer::camera_ray(filter, cam, consts, x, y), world, lights, environment_map, background, consts);
+    utility::set_random_state(after);
+    // A sample which comes out finite the second time (e.g. hitting the light sampler in another state) isn't counted
+    let source: NanSource = match find_source(world, &bounces) { Some(source) if !radiance.is_finite() => source, _ => return };
+    let mut sources = SOURCES.lock().unwrap();
+    let counts: &mut NanCounts = sources.entry(source).or_default();
+    if radiance.is_nan() { counts.nan += 1; } else { counts.infinite += 1; }
+}
+
+// Returns the sources found since the last call, the most frequent first, resetting them
+pub fn take_report() -> Vec<(NanSource, NanCounts)> {
+    let mut report: Vec<(NanSource, NanCounts)> = std::mem::take(&mut *SOURCES.lock().unwrap()).into_iter().collect();
+    report.sort_by_key(|(_, counts)| std::cmp::Reverse(counts.nan + counts.infinite));
+    report
+}
+
+// One line per source, the objects by their names when they have one
+pub fn format_report(report: &[(NanSource, NanCounts)], names: &[Option<String>]) -> String {
+    if report.is_empty() { return "Non-finite samples: none\n".to_string(); }
+    let total: u64 = report.iter().map(|(_, counts)| counts.nan + counts.infinite).sum();
+    let mut text: String = format!("Non-finite samples: {}\n", total);
+    for (source, counts) in report {
+        let object: String = match source.object {
+            Some(index) => match names.get(index) { Some(Some(name)) => format!("object {} \"{}\"", index, name), _ => format!("object {}", index) },
+            None => "environment".to_string(),
+        };
+        writeln!(text, "  {:>8} NaN {:>8} inf  {} {} at bounce {}, {}", counts.nan, counts.infinite, object, source.material, source.depth, source.quantity).unwrap();
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::Color;
+    use crate::pixel_debug::Event;
+    use crate::point3::Point3;
+    use crate::ray::Ray;
+
+    #[test]
+    fn test_find_source() {
+        let ray: Ray = Ray::new(Point3::ZERO, Point3::Z);
+        let bounce = |depth: u32| Bounce::miss(depth, Event::Diffuse, &ray).with_weight(Color::splat(0.5)).with_pdfs(0.3, 0.3);
+        let world: HittableList = HittableList::new();
+        // The first bad value is blamed, not the ones it spread to further along the path
+        let path: Vec<Bounce> = vec![bounce(0), bounce(1).with_pdfs(0.0, f32::NAN).with_weight(Color::splat(f32::NAN)), bounce(2).with_emitted(Color::splat(f32::NAN))];
+        assert_eq!(find_source(&world, &path), Some(NanSource { object: None, material: "", depth: 1, quantity: Quantity::ScatteringPdf }));
+        assert_eq!(find_source(&world, &path[..1]), None);
+        // Huge but finite weights overflowing the throughput on the way
+        let path: Vec<Bounce> = vec![bounce(0).with_weight(Color::splat(1e30)), bounce(1).with_weight(Color::splat(1e30)), Bounce::miss(2, Event::Environment, &ray).with_emitted(Color::ONE)];
+        assert_eq!(find_source(&world, &path).map(|source| (source.depth, source.quantity)), Some((1, Quantity::Overflow)));
+        let text: String = format_report(&[(NanSource { object: Some(0), material: "Metal", depth: 2, quantity: Quantity::Weight }, NanCounts { nan: 3, infinite: 1 })], &[Some("hull".to_string())]);
+        assert!(text.starts_with("Non-finite samples: 4\n") && text.contains("object 0 \"hull\" Metal at bounce 2, bounce weight"));
+    }
+}
@@ -4,7 +4,7 @@
 
 use glam::Vec3A;
 
-#[derive(Clone)]
+#[derive(Clone, Copy)]
 pub struct ONB {
     pub u: Vec3A,
     pub v: Vec3A,
@@ -13,6 +13,15 @@ pub struct ONB {
 
 impl ONB {
     pub fn new() -> Self { ONB { u: Vec3A::ZERO, v: Vec3A::ZERO, w: Vec3A::ZERO } }
+    // The basis with the w axis along n
+    pub fn from_w(n: &Vec3A) -> Self {
+        let mut onb: ONB = ONB::new();
+        onb.build_from_w(n);
+        onb
+    }
+    // The coordinates of the world space vector in the basis, the inverse of local_vec
+    #[allow(dead_code)]
+    pub fn local_coords(&self, a: &Vec3A) -> Vec3A { Vec3A::new(a.dot(self.u), a.dot(self.v), a.dot(self.w)) }
     pub fn local_vec(&self, a: &Vec3A) -> Vec3A { self.u * a.x + self.v * a.y + self.w * a.z }
     pub fn build_from_w(&mut self, n: &Vec3A) {
         self.w = n.normalize();
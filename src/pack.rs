@@ -0,0 +1,112 @@
+// Author: Giulian Biolo, github.com/giulianbiolo
+// Date: 16/10/2026
+// Description: This file implements the scene archives, bundling a scene with the meshes and textures it refers to in a single .gbrt file
+
+use std::io::{Cursor, Write};
+
+use yaml_rust::{Yaml, YamlEmitter, YamlLoader};
+use zip::ZipWriter;
+use zip::write::FileOptions;
+
+use crate::assets;
+
+
+// The keys whose values are the file names of assets, or lists of them as the six faces of a cube map
+const FILE_KEYS: [&str; 5] = ["filename", "environmentMap", "environmentCubeMap", "backgroundMap", "apertureImage"];
+
+// The file names of the assets the scene refers to, in order and without duplicates
+pub fn referenced_files(doc: &Yaml) -> Vec<String> {
+    let mut files: Vec<String> = Vec::new();
+    _visit_files(&mut doc.clone(), &mut |filename: &mut String| if !files.contains(filename) { files.push(filename.clone()); });
+    files
+}
+
+fn _visit_files(node: &mut Yaml, visit: &mut dyn FnMut(&mut String)) {
+    match node {
+        Yaml::Hash(hash) => for (key, value) in hash.iter_mut() {
+            match (key.as_str(), value) {
+                (Some(key), Yaml::String(filename)) if FILE_KEYS.contains(&key) => visit(filename),
+                (Some(key), Yaml::Array(filenames)) if FILE_KEYS.contains(&key) => for filename in filenames.iter_mut() {
+                    if let Yaml::String(filename) = filename { visit(filename); }
+                },
+                (_, value) => _visit_files(value, visit),
+            }
+        },
+        Yaml::Array(values) => for value in values.iter_mut() { _visit_files(value, visit); },
+        _ => {},
+    }
+}
+
+// The name of the asset inside of the archive: relative paths are kept, absolute ones and the ones leaving the working directory are moved under assets/
+fn _archive_name(filename: &str, index: usize) -> String {
+    let normalized: String = filename.replace('\\', "/").trim_start_matches("./").to_string();
+    let outside: bool = normalized.starts_with('/') || normalized.contains(':') || normalized.split('/').any(|part| part == "..");
+    if !outside { return normalized; }
+    format!("assets/{}_{}", index, normalized.rsplit('/').next().unwrap())
+}
+
+// The archive of the scene, a zip with the scene as scene.yaml at its root and the assets next to it
+pub fn pack(content: &str) -> Vec<u8> {
+    let mut doc: Yaml = YamlLoader::load_from_str(content).unwrap_or_else(|err| panic!("Failed to parse the scene: {}", err)).remove(0);
+    let files: Vec<String> = referenced_files(&doc);
+    let names: Vec<String> = files.iter().enumerate().map(|(index, filename)| _archive_name(filename, index)).collect();
+    let mut bytes: Vec<u8> = Vec::new();
+    let mut writer: ZipWriter<Cursor<&mut Vec<u8>>> = ZipWriter::new(Cursor::new(&mut bytes));
+    for (filename, name) in files.iter().zip(names.iter()) {
+        let asset: Vec<u8> = assets::read(filename).unwrap_or_else(|err| panic!("Failed to pack {}: {}", filename, err));
+        writer.start_file(name.as_str(), FileOptions::default()).unwrap();
+        writer.write_all(&asset).unwrap();
+    }
+    // The scene is kept as it is, comments included, unless some paths had to change
+    let scene: String = if files == names { content.to_string() } else {
+        _visit_files(&mut doc, &mut |filename: &mut String| *filename = names[files.iter().position(|file| file == filename).unwrap()].clone());
+        let mut scene: String = String::new();
+        YamlEmitter::new(&mut scene).dump(&doc).unwrap();
+        scene
+    };
+    writer.start_file(assets::ARCHIVE_SCENE, FileOptions::default()).unwrap();
+    writer.write_all(scene.as_bytes()).unwrap();
+    writer.finish().unwrap();
+    drop(writer);
+    bytes
+}
+
+pub fn run(config_file: &str, archive: &str) {
+    let content: String = assets::read_to_string(config_file).unwrap_or_else(|err| panic!("Failed to read the config file {}: {}", config_file, err));
+    let bytes: Vec<u8> = pack(&content);
+    std::fs::write(archive, &bytes).unwrap_or_else(|err| panic!("Failed to write {}: {}", archive, err));
+    let doc: Yaml = YamlLoader::load_from_str(&content).unwrap().remove(0);
+    println!("Packed {} and {} assets into {} ({} bytes)", config_file, referenced_files(&doc).len(), archive, bytes.len());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assets::{AssetSource, ZipSource};
+
+    #[test]
+    fn test_pack() {
+        assets::register("pack_test/earth.png", b"earth".to_vec());
+        assets::register("pack_test/hexagon.png", b"hexagon".to_vec());
+        assets::register("../shared/teapot.obj", b"teapot".to_vec());
+        let content: &str = "constants: {environmentMap: pack_test/earth.png}\ncamera: {apertureImage: pack_test/hexagon.png}\nworld: [{objType: Mesh, filename: ../shared/teapot.obj}, {objType: Sphere, material: {texType: ImageTexture, texture: {filename: pack_test/earth.png}}}]\n";
+        let doc: Yaml = YamlLoader::load_from_str(content).unwrap().remove(0);
+        assert_eq!(referenced_files(&doc), vec!["pack_test/earth.png".to_string(), "pack_test/hexagon.png".to_string(), "../shared/teapot.obj".to_string()]);
+        // A cube map is either a cross or the list of its six faces
+        let cross: Yaml = YamlLoader::load_from_str("constants: {environmentCubeMap: pack_test/cross.hdr}").unwrap().remove(0);
+        assert_eq!(referenced_files(&cross), vec!["pack_test/cross.hdr".to_string()]);
+        let faces: [&str; 6] = ["px", "nx", "py", "ny", "pz", "nz"];
+        for face in faces { assets::register(&format!("pack_test/{}.hdr", face), face.as_bytes().to_vec()); }
+        let cube_map: String = format!("constants: {{environmentCubeMap: [{}]}}\n", faces.map(|face| format!("pack_test/{}.hdr", face)).join(", "));
+        assert_eq!(referenced_files(&YamlLoader::load_from_str(&cube_map).unwrap().remove(0)).len(), 6);
+        let archive: ZipSource = ZipSource::new(pack(&cube_map)).unwrap();
+        assert_eq!(archive.read("pack_test/nz.hdr").unwrap(), b"nz");
+        let archive: ZipSource = ZipSource::new(pack(content)).unwrap();
+        assert_eq!(archive.read("pack_test/earth.png").unwrap(), b"earth");
+        assert_eq!(archive.read("pack_test/hexagon.png").unwrap(), b"hexagon");
+        // The mesh outside of the scene directory is moved into the archive, and the scene points to it
+        assert_eq!(archive.read("assets/2_teapot.obj").unwrap(), b"teapot");
+        let scene: Yaml = YamlLoader::load_from_str(&archive.read_to_string(assets::ARCHIVE_SCENE).unwrap()).unwrap().remove(0);
+        assert_eq!(scene["world"][0]["filename"].as_str(), Some("assets/2_teapot.obj"));
+    }
+}
@@ -0,0 +1,117 @@
+// Author: Giulian Biolo, github.com/giulianbiolo
+// Date: 16/10/2026
+// Description: This file implements the parallel iterators used by the renderer, from rayon or running on the calling thread without the parallel feature
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+#[cfg(feature = "parallel")]
+pub use rayon::prelude::*;
+
+#[cfg(not(feature = "parallel"))]
+pub use sequential::*;
+
+// The subset of the rayon iterators the renderer uses, with the same names so that the callers don't change
+#[cfg(not(feature = "parallel"))]
+mod sequential {
+    pub struct Sequential<I>(I);
+
+    impl<I: Iterator> Iterator for Sequential<I> {
+        type Item = I::Item;
+        fn next(&mut self) -> Option<I::Item> { self.0.next() }
+    }
+
+    // Shadow the std adapters so that the chains keep the rayon reduce
+    impl<I: Iterator> Sequential<I> {
+        pub fn map<B, F: FnMut(I::Item) -> B>(self, f: F) -> Sequential<std::iter::Map<I, F>> { Sequential(self.0.map(f)) }
+        pub fn enumerate(self) -> Sequential<std::iter::Enumerate<I>> { Sequential(self.0.enumerate()) }
+        pub fn reduce<ID: Fn() -> I::Item, OP: Fn(I::Item, I::Item) -> I::Item>(self, identity: ID, op: OP) -> I::Item { self.0.fold(identity(), op) }
+    }
+
+    pub trait IntoParallelIterator: IntoIterator + Sized {
+        fn into_par_iter(self) -> Sequential<Self::IntoIter> { Sequential(self.into_iter()) }
+    }
+
+    impl<T: IntoIterator> IntoParallelIterator for T {}
+
+    pub trait ParallelSliceMut<T> {
+        fn par_chunks_mut(&mut self, chunk_size: usize) -> Sequential<std::slice::ChunksMut<'_, T>>;
+    }
+
+    impl<T> ParallelSliceMut<T> for [T] {
+        fn par_chunks_mut(&mut self, chunk_size: usize) -> Sequential<std::slice::ChunksMut<'_, T>> { Sequential(self.chunks_mut(chunk_size)) }
+    }
+}
+
+/****************** Thread pool ******************/
+// How the render shares the machine: its number of threads, all of the cores by default, whether they are pinned to their cores,
+// and for the renders running in the background, whether they get the lowest priority and how long they rest after each tile
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ThreadOptions {
+    pub threads: Option<usize>,
+    pub pin: bool,
+    pub nice: bool,
+    pub pause: Option<Duration>,
+}
+
+// Microseconds every thread sleeps after a tile, 0 for none
+static PAUSE_MICROS: AtomicU64 = AtomicU64::new(0);
+
+// Gives the cores to the other programs for a while, in the nice mode
+pub fn pause() {
+    let micros: u64 = PAUSE_MICROS.load(Ordering::Relaxed);
+    if micros > 0 { std::thread::sleep(Duration::from_micros(micros)); }
+}
+
+// Runs the work on its own pool of threads, set up as the options say
+#[cfg(feature = "parallel")]
+pub fn install<R: Send>(options: &ThreadOptions, work: impl FnOnce() -> R + Send) -> R {
+    PAUSE_MICROS.store(options.pause.map_or(0, |pause| pause.as_micros() as u64), Ordering::Relaxed);
+    let (pin, nice) = (options.pin, options.nice);
+    if (pin || nice) && !cfg!(target_os = "linux") { println!("Pinning the threads and lowering their priority are only supported on Linux, ignoring them"); }
+    let pool: rayon::ThreadPool = rayon::ThreadPoolBuilder::new().num_threads(options.threads.unwrap_or(0)).start_handler(move |thread| {
+        if pin { _pin_to_core(thread); }
+        if nice { _lower_priority(); }
+    }).build().expect("Failed to start the render threads");
+    println!("Threads: {}{}{}", pool.current_num_threads(), if pin { ", pinned" } else { "" }, if nice { ", low priority" } else { "" });
+    pool.install(work)
+}
+
+// Everything runs on the calling thread
+#[cfg(not(feature = "parallel"))]
+pub fn install<R>(options: &ThreadOptions, work: impl FnOnce() -> R) -> R {
+    PAUSE_MICROS.store(options.pause.map_or(0, |pause| pause.as_micros() as u64), Ordering::Relaxed);
+    work()
+}
+
+// The thread-th thread of the pool goes to the thread-th allowed core, wrapping around when there are more threads than cores
+#[cfg(all(feature = "parallel", target_os = "linux"))]
+fn _pin_to_core(thread: usize) {
+    // SAFETY: the sets are plain bit masks of the size the calls are given, for the calling thread (pid 0)
+    unsafe {
+        let size: usize = std::mem::size_of::<libc::cpu_set_t>();
+        let mut allowed: libc::cpu_set_t = std::mem::zeroed();
+        if libc::sched_getaffinity(0, size, &mut allowed) != 0 { return; }
+        let cores: Vec<usize> = (0..libc::CPU_SETSIZE as usize).filter(|core| libc::CPU_ISSET(*core, &allowed)).collect();
+        if cores.is_empty() { return; }
+        let mut pinned: libc::cpu_set_t = std::mem::zeroed();
+        libc::CPU_SET(cores[thread % cores.len()], &mut pinned);
+        libc::sched_setaffinity(0, size, &pinned);
+    }
+}
+
+// Linux threads have a nice value of their own, the one of the thread id, 19 being the lowest priority
+#[cfg(all(feature = "parallel", target_os = "linux"))]
+fn _lower_priority() {
+    // SAFETY: plain system calls on the calling thread
+    unsafe {
+        let thread_id = libc::syscall(libc::SYS_gettid) as libc::id_t;
+        libc::setpriority(libc::PRIO_PROCESS, thread_id, 19);
+    }
+}
+
+#[cfg(all(feature = "parallel", not(target_os = "linux")))]
+fn _pin_to_core(_: usize) {}
+
+#[cfg(all(feature = "parallel", not(target_os = "linux")))]
+fn _lower_priority() {}
@@ -2,274 +2,1114 @@
 // Date: 09/02/2023
 // Description: This file implements the parsing of YAML config files
 
+use std::collections::HashMap;
 use std::sync::Arc;
 
 use yaml_rust::{YamlLoader, Yaml};
+use yaml_rust::parser::{Event, EventReceiver, Parser};
 
-use glam::Vec3A;
+use glam::{Affine3A, Mat3, Vec2, Vec3, Vec3A};
 
+use crate::adaptive::AdaptiveSampling;
+use crate::aov::Aov;
+use crate::acceleration::BVHParams;
+use crate::lidar::{Lidar, PointCloudFormat, ScanPattern};
+use crate::link_budget::LinkBudget;
 use crate::material::DiffuseLight;
 use crate::hittable_list::HittableList;
 use crate::hittable_list::Hittable;
+use crate::light_linking::{self, LightLink, LinkRule};
 use crate::sphere::Sphere;
-use crate::texture::{Texture, SolidColor, ChessBoard, ImageTexture};
-use crate::rectangle::{XYRectangle, XZRectangle, YZRectangle};
-use crate::bbox::BBox;
+use crate::temporal::TemporalFilter;
+use crate::transient::Transient;
+use crate::texture::{Texture, SolidColor, ChessBoard, ImageTexture, RandomizePerInstance, UVChecker, Brick, Grid, PolkaDot, Noise};
+use crate::tiled_texture::TiledImageTexture;
+use crate::rectangle::{XYRectangle, XZRectangle, YZRectangle, UVTransform};
+use crate::bbox::{BBox, BoxFace};
+use crate::room::Room;
+use crate::rounded_box::RoundedBox;
+use crate::capsule::Capsule;
+use crate::plane::{Plane, PlanePattern};
+use crate::clipping::{ClipPlane, Clipped};
 use crate::mesh::Mesh;
-use crate::material::{Material, Lambertian, Metal, Dielectric, Plastic, GGXGlossy};
-use crate::camera::Camera;
+use crate::material::{Material, Lambertian, Translucent, Fluorescent, Metal, Dielectric, Plastic, GGXGlossy, MetallicRoughness, Coated, Conductor, Fresnel, AlphaMasked, Occluded, BumpMapped};
+use crate::camera::{Aperture, Camera};
+use crate::camera_path::{CameraPath, Waypoint};
 use crate::sphere_array::SphereArray;
-use crate::utility;
-use crate::color::Color;
+use crate::scene_desc::SceneDesc;
+use crate::utility::{self, SeedPattern};
+use crate::environment::GroundProjection;
+use crate::assets::{self, AssetSource};
+use crate::memory;
+use crate::cli::{self, ARGS};
+use crate::color::{self, Color};
 use crate::point3::Point3;
+use crate::priority::RenderPriority;
+use crate::compositing::{DepthOutput, MotionVectors, PositionOutput};
+use crate::exposure::ExposureAnalysis;
+use crate::transform::{Instanced, Transformed};
 
 
+// The most objects the groups of a scene can place, and the finest tessellation of a displaced sphere
+const MAX_OBJECTS: usize = 1 << 22;
+const MAX_RESOLUTION: usize = 4096;
+// Past these a scene from an untrusted source is refused before it's parsed: the containers nested in each other, the nodes once its aliases are expanded and the size of the image
+const MAX_NESTING: usize = 64;
+const MAX_NODES: u64 = 1 << 20;
+const MAX_DIMENSION: u32 = 1 << 14;
+
+// Loads the first document of the config file, with the overrides from the command line and the environment applied
+pub fn load_config(filename: &str) -> Option<Yaml> {
+    // The scene of an archive is the one at its root, its assets being read from the archive once it's mounted
+    let content: String = if assets::is_archive(filename) { assets::ZipSource::open(filename).ok()?.read_to_string(assets::ARCHIVE_SCENE).ok()? }
+        else { assets::read_to_string(filename).ok()? };
+    // The archives are shared, so their scene is checked for what would hang the parser first
+    if assets::is_archive(filename) {
+        if let Err(err) = check_document(&content) { println!("Refused the scene of {}: {}", filename, err); return None; }
+    }
+    // JSON scenes are read into the typed scene description, which writes them back as the YAML document
+    let mut doc: Yaml = if filename.ends_with(".json") { SceneDesc::from_json(&content).ok()?.to_yaml() }
+        else { YamlLoader::load_from_str(&content).ok()?.into_iter().next()? };
+    for (path, value) in cli::overrides() { cli::apply_override(&mut doc, &path, &value); }
+    if let Err(err) = resolve_materials(&mut doc) { println!("Failed to resolve the materials of {}: {}", filename, err); return None; }
+    Some(doc)
+}
+
+/****************** Named materials ******************/
+// Materials defined once under materials: { name: material } can be given by their name to any object (or clipping cap) as material: name
+// overrideMaterials: { name: material } replaces the materials of that name, or of the objects and groups of that name, so colorways share a scene (--set overrideMaterials.body_paint=red_paint)
+pub fn resolve_materials(doc: &mut Yaml) -> Result<(), String> {
+    let materials: yaml_rust::yaml::Hash = doc["materials"].as_hash().cloned().unwrap_or_default();
+    let overrides: yaml_rust::yaml::Hash = doc["overrideMaterials"].as_hash().cloned().unwrap_or_default();
+    if let Yaml::Hash(hash) = doc {
+        if let Some(world) = hash.get_mut(&yaml_rust::Yaml::String("world".to_string())) { _resolve_materials(world, &materials, &overrides, None)?; }
+    }
+    Ok(())
+}
+
+fn _resolve_materials(node: &mut Yaml, materials: &yaml_rust::yaml::Hash, overrides: &yaml_rust::yaml::Hash, inherited: Option<&Yaml>) -> Result<(), String> {
+    match node {
+        Yaml::Hash(hash) => {
+            // The override of a group goes to all of its children
+            let object_override: Option<Yaml> = hash.get(&yaml_rust::Yaml::String("name".to_string())).and_then(|name| overrides.get(name)).or(inherited).cloned();
+            for (key, value) in hash.iter_mut() {
+                match key.as_str() {
+                    Some("material") => *value = _named_material(object_override.as_ref().unwrap_or(value), materials, overrides)?,
+                    Some("cap") => *value = _named_material(value, materials, overrides)?,
+                    _ => _resolve_materials(value, materials, overrides, object_override.as_ref())?,
+                }
+            }
+        },
+        Yaml::Array(values) => for value in values.iter_mut() { _resolve_materials(value, materials, overrides, inherited)?; },
+        _ => {},
+    }
+    Ok(())
+}
+
+// Follows the overrides and the names down to the material
+fn _named_material(material: &Yaml, materials: &yaml_rust::yaml::Hash, overrides: &yaml_rust::yaml::Hash) -> Result<Yaml, String> {
+    let mut material: Yaml = material.clone();
+    let mut names: Vec<Yaml> = Vec::new();
+    while let Yaml::String(name) = &material {
+        if names.contains(&material) { return Err(format!("The material {} refers back to itself", name)); }
+        names.push(material.clone());
+        material = overrides.get(&material).or_else(|| materials.get(&material)).cloned().ok_or_else(|| format!("Unknown material: {}, expected one of the materials section", name))?;
+    }
+    Ok(material)
+}
+
+// The scene file as its typed description, see scene_desc.rs
+#[allow(dead_code)]
+pub fn load_scene_desc(filename: &str) -> Option<SceneDesc> { SceneDesc::from_yaml(&load_config(filename)?).ok() }
+
+fn _load_config_or_panic(filename: &str) -> Yaml { load_config(filename).unwrap_or_else(|| panic!("Failed to load the config file {}", filename)) }
+
 pub fn parse_yaml_constants(filename: &str) -> utility::Constants {
     if filename == "" { utility::Constants::default() }
-    else {
-        let content: String = std::fs::read_to_string(filename).unwrap();
-        let docs: Vec<Yaml> = YamlLoader::load_from_str(&content).unwrap();
-        let hashconsts = docs[0].as_hash().unwrap()[&yaml_rust::Yaml::String("constants".to_string())].as_hash().unwrap().clone();
-        let width = hashconsts[&yaml_rust::Yaml::String("width".to_string())].as_i64().unwrap() as u32;
-        let height = hashconsts[&yaml_rust::Yaml::String("height".to_string())].as_i64().unwrap() as u32;
-        let samples_per_pixel = hashconsts[&yaml_rust::Yaml::String("samplesPerPixel".to_string())].as_i64().unwrap() as u32;
-        let max_depth = hashconsts[&yaml_rust::Yaml::String("maxDepth".to_string())].as_i64().unwrap() as u32;
-        let min_depth = hashconsts[&yaml_rust::Yaml::String("minDepth".to_string())].as_i64().unwrap() as u32;
-        let environment_map = {
-            if hashconsts.contains_key(&yaml_rust::Yaml::String("environmentMap".to_string())) {
-                Some(hashconsts[&yaml_rust::Yaml::String("environmentMap".to_string())].as_str().unwrap().to_string())
-            } else { None }
-        };
-        let environment_distance = {
-            if hashconsts.contains_key(&yaml_rust::Yaml::String("environmentDistance".to_string())) {
-                Some(hashconsts[&yaml_rust::Yaml::String("environmentDistance".to_string())].as_f64().unwrap() as f32)
-            } else { None }
-        };
-        let environment_intensity = {
-            if hashconsts.contains_key(&yaml_rust::Yaml::String("environmentIntensity".to_string())) {
-                Some(hashconsts[&yaml_rust::Yaml::String("environmentIntensity".to_string())].as_f64().unwrap() as f32)
-            } else { None }
-        };
-        let filter: Option<String> = {
-            if hashconsts.contains_key(&yaml_rust::Yaml::String("filter".to_string())) {
-                Some(hashconsts[&yaml_rust::Yaml::String("filter".to_string())].as_str().unwrap().to_string())
-            } else { None }
-        };
-        let aspect_ratio = width as f32 / height as f32;
-        let sources_lambda: f32 = {
-            if hashconsts.contains_key(&yaml_rust::Yaml::String("sourcesLambda".to_string())) {
-                hashconsts[&yaml_rust::Yaml::String("sourcesLambda".to_string())].as_f64().unwrap() as f32
-            } else { 299792458.0 / 2.45e9 }
-        };
-        let power_render_center: Vec3A = {
-            if hashconsts.contains_key(&yaml_rust::Yaml::String("powerRenderCenter".to_string())) {
-                let pow_center = hashconsts[&yaml_rust::Yaml::String("powerRenderCenter".to_string())].as_vec().unwrap();
-                Vec3A::new(pow_center[0].as_f64().unwrap() as f32, pow_center[1].as_f64().unwrap() as f32, pow_center[2].as_f64().unwrap() as f32)
-            } else { Vec3A::new(0.0, 0.0, 0.0) }
+    else { parse_constants(&_load_config_or_panic(filename)) }
+}
+
+pub fn parse_constants(doc: &Yaml) -> utility::Constants { try_parse_constants(doc).unwrap_or_else(|err| panic!("{}", err)) }
+
+pub fn try_parse_constants(doc: &Yaml) -> Result<utility::Constants, String> {
+    let hashconsts = _get_hash(_as_hash(doc, "The scene")?, "constants")?;
+    let width: u32 = _get_u32(hashconsts, "width")?;
+    let height: u32 = _get_u32(hashconsts, "height")?;
+    let samples_per_pixel: u32 = _get_u32(hashconsts, "samplesPerPixel")?;
+    let max_depth: u32 = _get_u32(hashconsts, "maxDepth")?;
+    let min_depth: u32 = _get_u32(hashconsts, "minDepth")?;
+    let max_specular_depth = _parse_usize_or(hashconsts, "maxSpecularDepth", max_depth as usize)? as u32;
+    let max_diffuse_depth = _parse_usize_or(hashconsts, "maxDiffuseDepth", max_depth as usize)? as u32;
+    let light_sampling: bool = _parse_bool_or(hashconsts, "lightSampling", false)?;
+    let environment_map: Option<String> = _parse_str_opt(hashconsts, "environmentMap")?;
+    // A single cross image, or the six faces in the order +X, -X, +Y, -Y, +Z, -Z
+    let environment_cube_map: Option<Vec<String>> = match hashconsts.get(&yaml_rust::Yaml::String("environmentCubeMap".to_string())) {
+        Some(Yaml::Array(faces)) if faces.len() == 6 => Some(faces.iter().map(|face| _as_str(face, "environmentCubeMap").map(str::to_string)).collect::<Result<_, _>>()?),
+        Some(Yaml::Array(faces)) => return Err(format!("A cube map is a single cross image or six faces (+X, -X, +Y, -Y, +Z, -Z), got {} files", faces.len())),
+        Some(cross) => Some(vec![_as_str(cross, "environmentCubeMap")?.to_string()]),
+        None => None,
+    };
+    let environment_distance: Option<f32> = _parse_f32_opt(hashconsts, "environmentDistance")?;
+    let environment_intensity: Option<f32> = _parse_f32_opt(hashconsts, "environmentIntensity")?;
+    let background_map: Option<String> = _parse_str_opt(hashconsts, "backgroundMap")?;
+    let background_intensity: Option<f32> = _parse_f32_opt(hashconsts, "backgroundIntensity")?;
+    // projects the lower hemisphere of the environment map onto a ground plane
+    let ground_projection: Option<GroundProjection> = _parse_section(hashconsts, "groundProjection", |hashground| Ok(GroundProjection {
+        ground_level: _parse_f32_or(hashground, "groundLevel", 0.0)?,
+        height: _parse_f32_or(hashground, "height", 1.7)?,
+        radius: _parse_f32_or(hashground, "radius", 30.0)?,
+    }))?;
+    let filter: Option<String> = _parse_str_opt(hashconsts, "filter")?;
+    let aspect_ratio = width as f32 / height as f32;
+    let sources_lambda: f32 = _parse_f32_or(hashconsts, "sourcesLambda", 299792458.0 / 2.45e9)?;
+    let power_render_center: Vec3A = hashconsts.get(&yaml_rust::Yaml::String("powerRenderCenter".to_string())).map_or(Ok(Vec3A::ZERO), |center| _parse_vec3(center, "powerRenderCenter"))?;
+    let link_budget: Option<LinkBudget> = _parse_section(hashconsts, "linkBudget", |hashbudget| Ok(LinkBudget {
+        transmit_power: _parse_f32_or(hashbudget, "transmitPower", 20.0)?,
+        transmit_gain: _parse_f32_or(hashbudget, "transmitGain", 0.0)?,
+        receive_gain: _parse_f32_or(hashbudget, "receiveGain", 0.0)?,
+        reference_distance: _parse_f32_or(hashbudget, "referenceDistance", 1.0)?,
+    }))?;
+    let memory_budget: Option<usize> = hashconsts.get(&yaml_rust::Yaml::String("memoryBudget".to_string())).map(|megabytes| _as_usize(megabytes, "memoryBudget")).transpose()?;
+    let stream_textures: bool = _parse_bool_or(hashconsts, "streamTextures", false)?;
+    let threads: Option<usize> = hashconsts.get(&yaml_rust::Yaml::String("threads".to_string())).map(|threads| _as_usize(threads, "threads")).transpose()?.filter(|threads| *threads > 0);
+    let pin_threads: bool = _parse_bool_or(hashconsts, "pinThreads", false)?;
+    let max_render_seconds: Option<f32> = _parse_f32_opt(hashconsts, "maxRenderSeconds")?;
+    let progressive_interval: Option<f32> = _parse_f32_opt(hashconsts, "progressiveOutput")?;
+    let max_total_samples: Option<u64> = hashconsts.get(&yaml_rust::Yaml::String("maxTotalSamples".to_string())).map(|samples| _as_usize(samples, "maxTotalSamples").map(|samples| samples as u64)).transpose()?;
+    let seed: u64 = _parse_usize_or(hashconsts, "seed", 0)? as u64;
+    let frame: u64 = _parse_usize_or(hashconsts, "frame", 0)? as u64;
+    let seed_pattern: SeedPattern = hashconsts.get(&yaml_rust::Yaml::String("seedPattern".to_string())).map_or(Ok(SeedPattern::PerFrame), |pattern| _as_str(pattern, "seedPattern").and_then(SeedPattern::from_name))?;
+    let temporal: Option<TemporalFilter> = _parse_section(hashconsts, "temporal", |hashtemporal| Ok(TemporalFilter {
+        history_file: _get_str(hashtemporal, "history")?.to_string(),
+        blend: _parse_f32_or(hashtemporal, "blend", 0.8)?,
+        depth_tolerance: _parse_f32_or(hashtemporal, "depthTolerance", 0.05)?,
+    }))?;
+    let transient: Option<Transient> = _parse_section(hashconsts, "transient", |hashtransient| {
+        Ok(Transient { bins: _parse_usize_or(hashtransient, "bins", 64)?.max(1), max_distance: _parse_f32_or(hashtransient, "maxDistance", 30.0)? })
+    })?;
+    let lidar: Option<Lidar> = _parse_section(hashconsts, "lidar", |hashlidar| {
+        // A range image of the camera resolution, or a spinning scan
+        let pattern: ScanPattern = match hashlidar.get(&yaml_rust::Yaml::String("pattern".to_string())).map_or(Ok("camera"), |pattern| _as_str(pattern, "pattern"))? {
+            "camera" => ScanPattern::Camera { width, height },
+            "spinning" => ScanPattern::Spinning {
+                channels: _parse_usize_or(hashlidar, "channels", 32)? as u32,
+                steps: _parse_usize_or(hashlidar, "steps", 1024)? as u32,
+                min_elevation: _parse_f32_or(hashlidar, "minElevation", -15.0)?,
+                max_elevation: _parse_f32_or(hashlidar, "maxElevation", 15.0)?,
+            },
+            pattern => return Err(format!("Unknown LiDAR pattern: {}, expected camera or spinning", pattern)),
         };
-        utility::Constants { width, height, aspect_ratio, samples_per_pixel, max_depth, min_depth, environment_map, environment_distance, environment_intensity, filter, sources_lambda, power_render_center }
+        let format: PointCloudFormat = hashlidar.get(&yaml_rust::Yaml::String("format".to_string())).map_or(Ok(PointCloudFormat::Ply), |format| _as_str(format, "format").and_then(PointCloudFormat::from_name))?;
+        Ok(Lidar { pattern, max_range: _parse_f32_or(hashlidar, "maxRange", 100.0)?, range_noise: _parse_f32_or(hashlidar, "rangeNoise", 0.0)?, format })
+    })?;
+    let aovs: Vec<Aov> = match hashconsts.get(&yaml_rust::Yaml::String("aovs".to_string())) {
+        Some(aovs) => _as_vec(aovs, "aovs")?.iter().map(|aov| _as_str(aov, "aovs").and_then(Aov::from_name)).collect::<Result<_, _>>()?,
+        None => Vec::new(),
+    };
+    let adaptive_sampling: Option<AdaptiveSampling> = _parse_section(hashconsts, "adaptiveSampling", |hashadaptive| {
+        Ok(AdaptiveSampling { threshold: _parse_f32_or(hashadaptive, "threshold", 0.01)?, min_samples: _parse_usize_or(hashadaptive, "minSamples", 16)? as u32 })
+    })?;
+    let priority: Option<RenderPriority> = _parse_section(hashconsts, "priority", |hashpriority| Ok(RenderPriority {
+        mask: _parse_str_opt(hashpriority, "mask")?,
+        tile_size: _parse_usize_or(hashpriority, "tileSize", 32)? as u32,
+        min_weight: _parse_f32_or(hashpriority, "minWeight", 0.1)?,
+        max_samples: _parse_usize_or(hashpriority, "maxSamples", 4)?.max(1) as u32,
+    }))?;
+    let compact_accumulation: bool = _parse_bool_or(hashconsts, "compactAccumulation", false)?;
+    let environment_sh: bool = _parse_bool_or(hashconsts, "environmentSH", false)?;
+    let manifold_sampling: bool = _parse_bool_or(hashconsts, "manifoldSampling", false)?;
+    let depth_output: Option<DepthOutput> = _parse_section(hashconsts, "depthOutput", |hashdepth| {
+        Ok(DepthOutput { deep_samples: _parse_usize_or(hashdepth, "deepSamples", 0)? as u32, merge_tolerance: _parse_f32_or(hashdepth, "mergeTolerance", 0.01)? })
+    })?;
+    let position_output: Option<PositionOutput> = _parse_section(hashconsts, "positionOutput", |hashposition| {
+        Ok(PositionOutput { normal_offset: _parse_f32_or(hashposition, "normalOffset", 0.0)? })
+    })?;
+    let motion_vectors: Option<MotionVectors> = if _parse_bool_or(hashconsts, "motionVectors", false)? { parse_motion_vectors(doc, frame)? } else { None };
+    let exposure_analysis: Option<ExposureAnalysis> = _parse_section(hashconsts, "exposureAnalysis", |hashexposure| {
+        Ok(ExposureAnalysis { under: _parse_f32_or(hashexposure, "under", 0.001)?, over: _parse_f32_or(hashexposure, "over", 1.0)? })
+    })?;
+    Ok(utility::Constants { width, height, aspect_ratio, samples_per_pixel, max_depth, min_depth, max_specular_depth, max_diffuse_depth, light_sampling, environment_map, environment_cube_map, environment_distance, environment_intensity, background_map, background_intensity, ground_projection, filter, sources_lambda, power_render_center, link_budget, memory_budget, stream_textures, threads, pin_threads, max_render_seconds, progressive_interval, max_total_samples, seed, frame, seed_pattern, temporal, transient, lidar, aovs, adaptive_sampling, priority, compact_accumulation, environment_sh, manifold_sampling, depth_output, position_output, motion_vectors, exposure_analysis })
+}
+
+pub fn parse_yaml_camera(filename: &str) -> Camera { parse_camera(&_load_config_or_panic(filename)) }
+
+pub fn parse_camera(doc: &Yaml) -> Camera { try_parse_camera(doc).unwrap_or_else(|err| panic!("{}", err)) }
+
+pub fn try_parse_camera(doc: &Yaml) -> Result<Camera, String> {
+    let hashcam = _get_hash(_as_hash(doc, "The scene")?, "camera")?;
+    let lookfrom: Point3 = _get_vec3(hashcam, "lookFrom")?;
+    let lookat: Point3 = _get_vec3(hashcam, "lookAt")?;
+    let vup: Vec3A = _get_vec3(hashcam, "vup")?;
+    let vfov: f32 = _get_f32(hashcam, "vfov")?;
+    let aspect_ratio: f32 = _get_f32(hashcam, "aspectRatio")?;
+    let aperture: f32 = _get_f32(hashcam, "aperture")?;
+    let focus_dist: f32 = _get_f32(hashcam, "focusDistance")?;
+    let (lookfrom, lookat) = match parse_camera_path(doc, lookat)? {
+        Some((path, fps)) => path.at(doc["constants"]["frame"].as_i64().unwrap_or(0) as f32 / fps),
+        None => (lookfrom, lookat),
+    };
+    let camera: Camera = Camera::new(&lookfrom, &lookat, &vup, vfov, aspect_ratio, aperture, focus_dist);
+    // The aperture image shapes the lens, whose size is still the aperture
+    match hashcam.get(&yaml_rust::Yaml::String("apertureImage".to_string())) {
+        Some(filename) => Ok(camera.with_aperture(Aperture::from_file(_as_str(filename, "apertureImage")?)?)),
+        None => Ok(camera),
     }
 }
 
-pub fn parse_yaml_camera(filename: &str) -> Camera {
-    let content: String = std::fs::read_to_string(filename).unwrap();
-    let docs: Vec<Yaml> = YamlLoader::load_from_str(&content).unwrap();
-    let hashcam = docs[0].as_hash().unwrap()[&yaml_rust::Yaml::String("camera".to_string())].as_hash().unwrap().clone();
-    let lookfrom = hashcam[&yaml_rust::Yaml::String("lookFrom".to_string())].as_vec().unwrap();
-    let lookat = hashcam[&yaml_rust::Yaml::String("lookAt".to_string())].as_vec().unwrap();
-    let vup = hashcam[&yaml_rust::Yaml::String("vup".to_string())].as_vec().unwrap();
-    let vfov = hashcam[&yaml_rust::Yaml::String("vfov".to_string())].as_f64().unwrap();
-    let aspect_ratio = hashcam[&yaml_rust::Yaml::String("aspectRatio".to_string())].as_f64().unwrap();
-    let aperture = hashcam[&yaml_rust::Yaml::String("aperture".to_string())].as_f64().unwrap();
-    let focus_dist = hashcam[&yaml_rust::Yaml::String("focusDistance".to_string())].as_f64().unwrap();
-    Camera::new(
-        &Vec3A::new(lookfrom[0].as_f64().unwrap() as f32, lookfrom[1].as_f64().unwrap() as f32, lookfrom[2].as_f64().unwrap() as f32),
-        &Vec3A::new(lookat[0].as_f64().unwrap() as f32, lookat[1].as_f64().unwrap() as f32, lookat[2].as_f64().unwrap() as f32),
-        &Vec3A::new(vup[0].as_f64().unwrap() as f32, vup[1].as_f64().unwrap() as f32, vup[2].as_f64().unwrap() as f32),
-        vfov as f32,
-        aspect_ratio as f32,
-        aperture as f32,
-        focus_dist as f32
-    )
-}
-
-pub fn parse_yaml_scene(filename: &str) -> HittableList {
+// animation: { fps: 24, cameraPath: [{ time, lookFrom, lookAt }] }, the camera following the path at the time of constants.frame
+// The waypoints without a lookAt keep looking at the one of the camera
+pub fn parse_camera_path(doc: &Yaml, lookat: Point3) -> Result<Option<(CameraPath, f32)>, String> {
+    let waypoints: &Vec<Yaml> = match doc["animation"].as_hash().and_then(|hashanim| hashanim.get(&yaml_rust::Yaml::String("cameraPath".to_string()))) {
+        Some(waypoints) => _as_vec(waypoints, "cameraPath")?,
+        None => return Ok(None),
+    };
+    let waypoints: Vec<Waypoint> = waypoints.iter().map(|waypoint| -> Result<Waypoint, String> {
+        Ok(Waypoint {
+            time: _as_seconds(&waypoint["time"]).ok_or("Every waypoint of the camera path needs a time")?,
+            look_from: _parse_vec3(&waypoint["lookFrom"], "lookFrom")?,
+            look_at: if waypoint["lookAt"].is_badvalue() { lookat } else { _parse_vec3(&waypoint["lookAt"], "lookAt")? },
+        })
+    }).collect::<Result<_, _>>()?;
+    Ok(Some((CameraPath::new(waypoints)?, _as_seconds(&doc["animation"]["fps"]).unwrap_or(24.0))))
+}
+
+// The cameras of the frames around the given one, only moving along the camera path of the animation block
+pub fn parse_motion_vectors(doc: &Yaml, frame: u64) -> Result<Option<MotionVectors>, String> {
+    if doc["animation"]["cameraPath"].is_badvalue() { println!("The motion vectors follow the cameraPath of the animation, which the scene doesn't have"); return Ok(None); }
+    let camera_at = |frame: i64| -> Result<Camera, String> {
+        let mut doc: Yaml = doc.clone();
+        cli::apply_override(&mut doc, "constants.frame", &frame.to_string());
+        try_parse_camera(&doc)
+    };
+    Ok(Some(MotionVectors { previous: camera_at(frame as i64 - 1)?, next: camera_at(frame as i64 + 1)? }))
+}
+
+// Times and frame rates are often whole numbers
+fn _as_seconds(value: &Yaml) -> Option<f32> { value.as_f64().or_else(|| value.as_i64().map(|value| value as f64)).map(|value| value as f32) }
+
+pub fn parse_yaml_scene(filename: &str) -> HittableList { parse_scene(&_load_config_or_panic(filename)) }
+
+pub fn parse_scene(doc: &Yaml) -> HittableList { try_parse_world(doc).unwrap_or_else(|err| panic!("{}", err)) }
+
+pub fn try_parse_world(doc: &Yaml) -> Result<HittableList, String> {
     let mut world: HittableList = HittableList::new();
-    let content: String = std::fs::read_to_string(filename).unwrap();
-    let docs: Vec<Yaml> = YamlLoader::load_from_str(&content).unwrap();
-    let hashworld = docs[0].as_hash().unwrap()[&yaml_rust::Yaml::String("world".to_string())].as_vec().unwrap();
-    for hashobj in hashworld {
-        let hashobj = hashobj.as_hash().unwrap();
-        let objtype = hashobj[&yaml_rust::Yaml::String("objType".to_string())].as_str().unwrap();
-        if !objtype.contains("Array") {
-            let material: Box<dyn Material + Send + Sync> = _parse_material(hashobj);
-            let obj: Arc<dyn Hittable + Send + Sync> = _parse_geometry(hashobj, material);
-            world.push(obj);
-        } else if objtype.contains("Sphere") {
-            // * SphereArray *
-            // ! In future we will support other objects
-            let objects = hashobj[&yaml_rust::Yaml::String("objects".to_string())].as_vec().unwrap();
-            let mut spheres: Vec<Sphere> = Vec::<Sphere>::new();
-            for obj in objects {
-                let obj = obj.as_hash().unwrap();
-                let objtype = obj[&yaml_rust::Yaml::String("objType".to_string())].as_str().unwrap();
-                if objtype == "Sphere" {
-                    let center = obj[&yaml_rust::Yaml::String("center".to_string())].as_vec().unwrap();
-                    let radius = obj[&yaml_rust::Yaml::String("radius".to_string())].as_f64().unwrap();
-                    let material = _parse_material(obj);
-                    spheres.push(Sphere::new(Point3::new(center[0].as_f64().unwrap() as f32, center[1].as_f64().unwrap() as f32, center[2].as_f64().unwrap() as f32), radius as f32, material, 0));
-                }
+    let mut links: Vec<LightLink> = Vec::new();
+    for hashobj in _get_vec(_as_hash(doc, "The scene")?, "world")? {
+        for (object, hashleaf) in parse_world_objects(_as_hash(hashobj, "An object of the world")?)? {
+            world.push(object);
+            links.push(parse_light_link(&hashleaf)?);
+        }
+    }
+    light_linking::link(world, &links)
+}
+
+// The names of the objects of the world, in the order of parse_scene, for finding them with hittable_list::find_named
+#[allow(dead_code)]
+pub fn parse_object_names(doc: &Yaml) -> Vec<Option<String>> {
+    parse_object_leaves(doc).iter().map(|hashobj| hashobj["name"].as_str().map(|name| name.to_string())).collect()
+}
+
+// The entries of the world down to the leaves of the groups, in the order of parse_scene, for the keys of each object (its name, its material, ..)
+pub fn parse_object_leaves(doc: &Yaml) -> Vec<Yaml> {
+    doc["world"].as_vec().unwrap().iter().flat_map(_leaves).collect()
+}
+
+// Whether any texture of the entry is a RandomizePerInstance
+fn _varies_per_instance(hashobj: &Yaml) -> bool {
+    match hashobj {
+        Yaml::Hash(hash) => hash.iter().any(|(key, value)| (key.as_str() == Some("texType") && value.as_str() == Some("RandomizePerInstance")) || _varies_per_instance(value)),
+        Yaml::Array(values) => values.iter().any(_varies_per_instance),
+        _ => false,
+    }
+}
+
+fn _is_group(hashobj: &Yaml) -> bool { hashobj["objType"].as_str() == Some("Group") }
+
+fn _leaves(hashobj: &Yaml) -> Vec<Yaml> {
+    if !_is_group(hashobj) { return vec![hashobj.clone()]; }
+    let leaves: Vec<Yaml> = hashobj["children"].as_vec().unwrap().iter().flat_map(_leaves).collect();
+    let instances: usize = hashobj["instances"].as_vec().map_or(1, |instances| instances.len());
+    if let Err(err) = _check_instanced(leaves.len(), instances) { panic!("{}", err); }
+    leaves.iter().cycle().take(leaves.len() * instances).cloned().collect()
+}
+
+/****************** Groups ******************/
+// An object of the world with the entry it was parsed from, and a leaf of a group with the transform and the index of the instance placing it
+pub type WorldObject = (Arc<dyn Hittable + Send + Sync>, yaml_rust::yaml::Hash);
+type GroupLeaf = (Arc<dyn Hittable + Send + Sync>, Affine3A, u32, yaml_rust::yaml::Hash);
+
+// An entry of the world, one object or the leaves of a group ({ objType: Group, children: [..], transform }), with the hash of each for its name and light linking
+// The transforms of the groups are composed down to the leaves, which keep their own geometry and BVH, so the lights in groups are sampled like the others
+pub fn parse_world_objects(hashobj: &yaml_rust::yaml::Hash) -> Result<Vec<WorldObject>, String> {
+    _parse_group_leaves(hashobj)?.into_iter().map(|(object, transform, instance, hashleaf)| -> Result<WorldObject, String> {
+        // The objects varying per instance are told the instance placing them, the same on every parse of the scene and whatever comes before them in the world
+        let object: Arc<dyn Hittable + Send + Sync> = if _varies_per_instance(&Yaml::Hash(hashleaf.clone())) { Arc::new(Instanced::new(object, instance)) } else { object };
+        if transform == Affine3A::IDENTITY { return Ok((object, hashleaf)); }
+        // A scale of zero can't be undone to bring the rays into the object
+        if !transform.inverse().is_finite() { return Err(format!("The transform of the object isn't invertible: {:?}", transform)); }
+        Ok((Arc::new(Transformed::new(object, transform)), hashleaf))
+    }).collect()
+}
+
+fn _parse_group_leaves(hashobj: &yaml_rust::yaml::Hash) -> Result<Vec<GroupLeaf>, String> {
+    if !_is_group(&Yaml::Hash(hashobj.clone())) { return Ok(vec![(parse_world_object(hashobj)?, Affine3A::IDENTITY, 0, hashobj.clone())]); }
+    let children = hashobj.get(&yaml_rust::Yaml::String("children".to_string())).and_then(Yaml::as_vec).ok_or("A group needs its children")?;
+    let mut leaves: Vec<GroupLeaf> = Vec::new();
+    for child in children { leaves.extend(_parse_group_leaves(_as_hash(child, "A child of a group")?)?); }
+    let transform: Affine3A = hashobj.get(&yaml_rust::Yaml::String("transform".to_string())).map_or(Ok(Affine3A::IDENTITY), _parse_transform)?;
+    // Every instance places the same children, which are parsed (and their models loaded) only once
+    let instances: Vec<Affine3A> = match hashobj.get(&yaml_rust::Yaml::String("instances".to_string())) {
+        Some(instances) => _as_vec(instances, "instances")?.iter().map(_parse_transform).collect::<Result<_, _>>()?,
+        None => vec![Affine3A::IDENTITY],
+    };
+    _check_instanced(leaves.len(), instances.len())?;
+    // The instances of the nested groups are numbered within each instance of this one
+    let nested: u32 = leaves.iter().map(|(_, _, instance, _)| instance + 1).max().unwrap_or(1);
+    Ok(instances.iter().enumerate().flat_map(|(idx, instance)| leaves.iter().map(move |(object, local, nested_instance, hashleaf)| {
+        (object.clone(), transform * *instance * *local, idx as u32 * nested + nested_instance, hashleaf.clone())
+    })).collect())
+}
+
+// Nested groups multiply their instances, so that a few lines could place more objects than fit in memory
+fn _check_instanced(leaves: usize, instances: usize) -> Result<(), String> {
+    if leaves.saturating_mul(instances) > MAX_OBJECTS { return Err(format!("A group can place at most {} objects, got {} instances of {}", MAX_OBJECTS, instances, leaves)); }
+    Ok(())
+}
+
+// { translate: [x, y, z], rotate: [x, y, z] (degrees, around x then y then z like the meshes), scale: s or [x, y, z] }, scaled first and translated last
+fn _parse_transform(transform: &Yaml) -> Result<Affine3A, String> {
+    let vec3 = |key: &str, default: Vec3| -> Result<Vec3, String> { if transform[key].is_badvalue() { Ok(default) } else { _parse_vec3(&transform[key], key).map(Vec3::from) } };
+    let scale: Vec3 = match _as_real(&transform["scale"]) {
+        Some(scale) => Vec3::splat(scale as f32),
+        None => vec3("scale", Vec3::ONE)?,
+    };
+    let rotation: Vec3 = vec3("rotate", Vec3::ZERO)?;
+    let rotation: Mat3 = Mat3::from_rotation_x(rotation.x.to_radians()) * Mat3::from_rotation_y(rotation.y.to_radians()) * Mat3::from_rotation_z(rotation.z.to_radians());
+    Ok(Affine3A::from_translation(vec3("translate", Vec3::ZERO)?) * Affine3A::from_mat3(rotation) * Affine3A::from_scale(scale))
+}
+
+// An optional name, and the names of the lights lighting the object (or of the objects lit, for a light) under lightLinking: { include } or { exclude }
+pub fn parse_light_link(hashobj: &yaml_rust::yaml::Hash) -> Result<LightLink, String> {
+    let name: Option<String> = _parse_str_opt(hashobj, "name")?;
+    let rule = match hashobj.get(&yaml_rust::Yaml::String("lightLinking".to_string())) {
+        Some(linking) => {
+            let linking = _as_hash(linking, "lightLinking")?;
+            let names = |key: &str| -> Result<Option<Vec<String>>, String> {
+                linking.get(&yaml_rust::Yaml::String(key.to_string())).map(|names| -> Result<Vec<String>, String> {
+                    _as_vec(names, key)?.iter().map(|name| _as_str(name, key).map(str::to_string)).collect()
+                }).transpose()
+            };
+            match (names("include")?, names("exclude")?) {
+                (Some(_), Some(_)) => return Err("lightLinking can't have both include and exclude".to_string()),
+                (Some(included), None) => LinkRule::Include(included),
+                (None, Some(excluded)) => LinkRule::Exclude(excluded),
+                (None, None) => LinkRule::All,
+            }
+        },
+        None => LinkRule::All,
+    };
+    Ok(LightLink::new(name, rule))
+}
+
+pub fn parse_world_object(hashobj: &yaml_rust::yaml::Hash) -> Result<Arc<dyn Hittable + Send + Sync>, String> { parse_clipping(hashobj, _parse_unclipped_object(hashobj)?) }
+
+// Optional clipping planes under clip: { planes: [{ point, normal }], cap }, each normal pointing to the part that gets cut away
+// The cap is the material filling the cuts of closed objects, which are left open without it
+pub fn parse_clipping(hashobj: &yaml_rust::yaml::Hash, object: Arc<dyn Hittable + Send + Sync>) -> Result<Arc<dyn Hittable + Send + Sync>, String> {
+    let hashclip = match hashobj.get(&yaml_rust::Yaml::String("clip".to_string())) {
+        Some(hashclip) => _as_hash(hashclip, "clip")?,
+        None => return Ok(object),
+    };
+    let planes: Vec<ClipPlane> = _get_vec(hashclip, "planes")?.iter().map(|hashplane| -> Result<ClipPlane, String> {
+        Ok(ClipPlane::new(_parse_vec3(&hashplane["point"], "point")?, _parse_vec3(&hashplane["normal"], "normal")?))
+    }).collect::<Result<_, _>>()?;
+    let cap = hashclip.get(&yaml_rust::Yaml::String("cap".to_string())).map(|cap| -> Result<Box<dyn Material>, String> { Ok(_parse_material_hash(_as_hash(cap, "cap")?)?) }).transpose()?;
+    Ok(Arc::new(Clipped::new(object, planes, cap)))
+}
+
+fn _parse_unclipped_object(hashobj: &yaml_rust::yaml::Hash) -> Result<Arc<dyn Hittable + Send + Sync>, String> {
+    let objtype = _get_str(hashobj, "objType")?;
+    if !objtype.contains("Array") {
+        let material: Box<dyn Material + Send + Sync> = parse_material(hashobj)?;
+        _parse_geometry(hashobj, material)
+    } else if objtype.contains("Sphere") {
+        // * SphereArray *
+        // ! In future we will support other objects
+        let objects = _get_vec(hashobj, "objects")?;
+        let mut spheres: Vec<Sphere> = Vec::<Sphere>::new();
+        for obj in objects {
+            let obj = _as_hash(obj, "An object of the array")?;
+            if _get_str(obj, "objType")? == "Sphere" {
+                let center: Point3 = _get_vec3(obj, "center")?;
+                let radius: f32 = _get_f32(obj, "radius")?;
+                spheres.push(Sphere::new(center, radius, parse_material(obj)?, 0));
             }
-            let spherearray = SphereArray::new(&mut spheres);
-            world.push(Arc::new(spherearray));
-        } else { panic!("Unsupported object type: {}", objtype) }
+        }
+        // The bounds of the array are the ones of its spheres
+        if spheres.is_empty() { return Err("A SphereArray needs at least one sphere".to_string()); }
+        let spherearray = SphereArray::new(&mut spheres, &_parse_bvh_params(hashobj)?);
+        Ok(Arc::new(spherearray))
+    } else { Err(format!("Unsupported object type: {}", objtype)) }
+}
+
+pub fn parse_material(hashobj: &yaml_rust::yaml::Hash) -> Result<Box<dyn Material + Send + Sync>, String> {
+    // Planes can use a built-in pattern instead of a material
+    if !hashobj.contains_key(&yaml_rust::Yaml::String("material".to_string())) {
+        if let Some(pattern) = hashobj.get(&yaml_rust::Yaml::String("pattern".to_string())) { return _parse_plane_pattern(_as_hash(pattern, "pattern")?); }
     }
-    world
+    _parse_material_hash(_get_hash(hashobj, "material")?)
 }
 
-fn _parse_material(hashobj: &yaml_rust::yaml::Hash) -> Box<dyn Material + Send + Sync> {
-    let objmat = hashobj[&yaml_rust::Yaml::String("material".to_string())].as_hash().unwrap();
-    let objmattype = objmat[&yaml_rust::Yaml::String("matType".to_string())].as_str().unwrap();
+// A grid or checker pattern with cellSize wide cells, the lines (or odd cells) in the second of the colors
+fn _parse_plane_pattern(hashpattern: &yaml_rust::yaml::Hash) -> Result<Box<dyn Material + Send + Sync>, String> {
+    let pattern = PlanePattern::from_name(_get_str(hashpattern, "type")?, _parse_f32_or(hashpattern, "lineWidth", 0.02)?)?;
+    let colors: [Color; 2] = match hashpattern.get(&yaml_rust::Yaml::String("colors".to_string())) {
+        Some(colors) => match _as_vec(colors, "colors")?.as_slice() {
+            [first, second, ..] => [_parse_vec3(first, "colors")?, _parse_vec3(second, "colors")?],
+            _ => return Err("The colors of a pattern are a list of two colors".to_string()),
+        },
+        None => [Color::splat(0.8), Color::splat(0.2)],
+    };
+    Ok(pattern.material(_parse_f32_or(hashpattern, "cellSize", 1.0)?, colors))
+}
+
+fn _parse_material_hash(objmat: &yaml_rust::yaml::Hash) -> Result<Box<dyn Material + Send + Sync>, String> {
+    if objmat.contains_key(&yaml_rust::Yaml::String("alphaMask".to_string())) {
+        // any material can have a cutout mask, which wraps the material parsed from the rest of the keys
+        let mask = _parse_texture(_get_hash(objmat, "alphaMask")?)?;
+        let mut base = objmat.clone();
+        base.remove(&yaml_rust::Yaml::String("alphaMask".to_string()));
+        return Ok(Box::new(AlphaMasked::new(_parse_material_hash(&base)?, mask)));
+    }
+    if objmat.contains_key(&yaml_rust::Yaml::String("occlusionMap".to_string())) {
+        // and an ambient occlusion map, multiplied into its diffuse response
+        let occlusion = _parse_texture(_get_hash(objmat, "occlusionMap")?)?;
+        let mut base = objmat.clone();
+        base.remove(&yaml_rust::Yaml::String("occlusionMap".to_string()));
+        return Ok(Box::new(Occluded::new(_parse_material_hash(&base)?, occlusion)));
+    }
+    if objmat.contains_key(&yaml_rust::Yaml::String("bumpMap".to_string())) {
+        // and a height texture, with the height it scales up to
+        let hashbump = _get_hash(objmat, "bumpMap")?;
+        let mut base = objmat.clone();
+        base.remove(&yaml_rust::Yaml::String("bumpMap".to_string()));
+        return Ok(Box::new(BumpMapped::new(_parse_material_hash(&base)?, _parse_texture(hashbump)?, _parse_f32_or(hashbump, "scale", 1.0)?)));
+    }
+    let objmattype = _get_str(objmat, "matType")?;
     match objmattype {
-        "Lambertian" => { Box::new(Lambertian::new_texture(_parse_texture(objmat))) },
+        "Lambertian" => { Ok(Box::new(Lambertian::new_texture(_parse_texture(objmat)?))) },
+        "Translucent" => {
+            // has an albedo and the fraction of the scattered light which comes out of the other side
+            Ok(Box::new(Translucent::new_texture(_parse_texture(objmat)?, _parse_f32_or(objmat, "transmission", 0.5)?)))
+        },
+        "Fluorescent" => {
+            // has an albedo and the color it glows, and optionally the colors exciting it (blue by default) and the fraction of them glowed back
+            let excitation: Color = objmat.get(&yaml_rust::Yaml::String("excitation".to_string())).map_or(Ok(Color::Z), |excitation| _parse_vec3(excitation, "excitation"))?;
+            let emission: Color = _get_vec3(objmat, "emission")?;
+            Ok(Box::new(Fluorescent::new_texture(_parse_texture(objmat)?, excitation, emission, _parse_f32_or(objmat, "efficiency", 1.0)?)))
+        },
         "Metal" => {
-            // has an albedo and a fuzz
-            let fuzz = objmat[&yaml_rust::Yaml::String("fuzz".to_string())].as_f64().unwrap();
-            Box::new(Metal::new_texture(_parse_texture(objmat), fuzz as f32))
+            // has an albedo and a fuzz, or a roughness map
+            let fuzz: f32 = _parse_f32_or(objmat, "fuzz", 0.0)?;
+            let metal: Metal = Metal::new_texture(_parse_texture(objmat)?, fuzz);
+            match _parse_roughness_map(objmat)? {
+                Some(roughness_map) => Ok(Box::new(metal.with_roughness_map(roughness_map))),
+                None => Ok(Box::new(metal)),
+            }
         },
         "Dielectric" => {
             // has just an index of refraction
-            let ior = objmat[&yaml_rust::Yaml::String("refractionIdx".to_string())].as_f64().unwrap();
-            let opacity = objmat[&yaml_rust::Yaml::String("opacity".to_string())].as_f64().unwrap();
-            Box::new(Dielectric::new_texture(_parse_texture(objmat), ior as f32, opacity as f32))
+            let ior: f32 = _get_f32(objmat, "refractionIdx")?;
+            let opacity: f32 = _get_f32(objmat, "opacity")?;
+            // and optionally a priority and an absorption coefficient, for nested dielectrics like ice in a glass of water
+            let priority: i64 = objmat.get(&yaml_rust::Yaml::String("priority".to_string())).map_or(Ok(0), |priority| _as_int(priority, "priority"))?;
+            let absorption: Color = objmat.get(&yaml_rust::Yaml::String("absorption".to_string())).map_or(Ok(Color::ZERO), |absorption| _parse_vec3(absorption, "absorption"))?;
+            // and whether the manifold sampling gathers the lights seen through it, on by default
+            let caustics: bool = _parse_bool_or(objmat, "caustics", true)?;
+            Ok(Box::new(Dielectric::new_texture(_parse_texture(objmat)?, ior, opacity).with_medium(priority.max(0) as u32, absorption)
+                .with_fresnel(_parse_fresnel(objmat, Fresnel::Schlick)?).with_caustics(caustics)))
         },
         "Plastic" => {
-            // has an albedo, a reflectivity and a fuzz
-            let fuzz = objmat[&yaml_rust::Yaml::String("fuzz".to_string())].as_f64().unwrap();
-            let reflectivity = objmat[&yaml_rust::Yaml::String("reflectivity".to_string())].as_f64().unwrap();
-            Box::new(Plastic::new_texture(_parse_texture(objmat), reflectivity as f32, fuzz as f32))
+            // has an albedo, a reflectivity and a fuzz, or a roughness map
+            let fuzz: f32 = _parse_f32_or(objmat, "fuzz", 0.0)?;
+            let reflectivity: f32 = _get_f32(objmat, "reflectivity")?;
+            let plastic: Plastic = Plastic::new_texture(_parse_texture(objmat)?, reflectivity, fuzz);
+            match _parse_roughness_map(objmat)? {
+                Some(roughness_map) => Ok(Box::new(plastic.with_roughness_map(roughness_map))),
+                None => Ok(Box::new(plastic)),
+            }
         },
         "GGX" => {
-            // has an albedo, a roughness and a fuzz
-            let reflectivity = objmat[&yaml_rust::Yaml::String("reflectivity".to_string())].as_f64().unwrap();
-            let roughness = objmat[&yaml_rust::Yaml::String("roughness".to_string())].as_f64().unwrap();
-            Box::new(GGXGlossy::new_texture(_parse_texture(objmat), roughness as f32, reflectivity as f32))
+            // has an albedo, a reflectivity and a roughness, or a roughness map
+            let reflectivity: f32 = _get_f32(objmat, "reflectivity")?;
+            let roughness: f32 = _parse_f32_or(objmat, "roughness", 0.0)?;
+            let glossy: GGXGlossy = GGXGlossy::new_texture(_parse_texture(objmat)?, roughness, reflectivity);
+            match _parse_roughness_map(objmat)? {
+                Some(roughness_map) => Ok(Box::new(glossy.with_roughness_map(roughness_map))),
+                None => Ok(Box::new(glossy)),
+            }
         }
+        "MetallicRoughness" => {
+            // has a base color, and optionally the metallic and roughness factors (1.0 as in glTF) and the packed metallicRoughness texture they scale
+            let metallic: f32 = _parse_f32_or(objmat, "metallic", 1.0)?;
+            let roughness: f32 = _parse_f32_or(objmat, "roughness", 1.0)?;
+            let material: MetallicRoughness = MetallicRoughness::new_texture(_parse_texture(objmat)?, metallic, roughness);
+            match objmat.get(&yaml_rust::Yaml::String("metallicRoughness".to_string())) {
+                Some(hashmap) => Ok(Box::new(material.with_metallic_roughness_map(_parse_texture(_as_hash(hashmap, "metallicRoughness")?)?))),
+                None => Ok(Box::new(material)),
+            }
+        },
+        "Coated" => {
+            // has a base material, and optionally the index of refraction and roughness of the clearcoat
+            let base = _parse_material_hash(_get_hash(objmat, "base")?)?;
+            let ior: f32 = _parse_f32_or(objmat, "refractionIdx", 1.5)?;
+            let roughness: f32 = _parse_f32_or(objmat, "roughness", 0.0)?;
+            Ok(Box::new(Coated::new(base, ior, roughness).with_fresnel(_parse_fresnel(objmat, Fresnel::Schlick)?)))
+        },
+        "Conductor" => {
+            // has either the name of a common metal or its complex index of refraction (eta and k), and optionally a fuzz
+            let fuzz: f32 = _parse_f32_or(objmat, "fuzz", 0.0)?;
+            let conductor: Conductor = match objmat.get(&yaml_rust::Yaml::String("metal".to_string())) {
+                Some(metal) => Conductor::from_name(_as_str(metal, "metal")?, fuzz)?,
+                None => Conductor::new(_get_vec3(objmat, "eta")?, _get_vec3(objmat, "k")?, fuzz),
+            };
+            Ok(Box::new(conductor.with_fresnel(_parse_fresnel(objmat, Fresnel::Exact)?)))
+        },
         "DiffuseLight" => {
-            // has just an emittance
-            let intensity = objmat[&yaml_rust::Yaml::String("intensity".to_string())].as_f64().unwrap();
-            Box::new(DiffuseLight::new_texture(_parse_texture(objmat), intensity as f32))
+            // has an emittance, given either as a texture or as a black body temperature, and an optional intensity map
+            let intensity: f32 = _get_f32(objmat, "intensity")?;
+            let emit: Box<dyn Texture + Send + Sync> = match objmat.get(&yaml_rust::Yaml::String("temperature".to_string())) {
+                Some(temperature) => Box::new(SolidColor::new(color::blackbody(_as_f32(temperature, "temperature")?))),
+                None => _parse_texture(objmat)?,
+            };
+            // and optionally emits only along the normal of the surface
+            let light: DiffuseLight = DiffuseLight::new_texture(emit, intensity).with_one_sided(_parse_bool_or(objmat, "oneSided", false)?);
+            match objmat.get(&yaml_rust::Yaml::String("intensityMap".to_string())) {
+                Some(hashmap) => Ok(Box::new(light.with_intensity_map(_parse_texture(_as_hash(hashmap, "intensityMap")?)?))),
+                None => Ok(Box::new(light)),
+            }
         },
-        _ => { panic!("Unknown material type: {:?}", objmat); }
+        _ => { Err(format!("Unknown material type: {}", objmattype)) }
     }
 }
 
-fn _parse_texture(objmat: &yaml_rust::yaml::Hash) -> Box<dyn Texture + Send + Sync> {
-    let textype = objmat[&yaml_rust::Yaml::String("texType".to_string())].as_str().unwrap();
-    let hashtex = objmat[&yaml_rust::Yaml::String("texture".to_string())].as_hash().unwrap();
+// A grayscale texture of the perceptual roughness, given as { texType, texture } like the albedo
+fn _parse_roughness_map(objmat: &yaml_rust::yaml::Hash) -> Result<Option<Box<dyn Texture + Send + Sync>>, String> {
+    objmat.get(&yaml_rust::Yaml::String("roughnessMap".to_string())).map(|hashmap| _parse_texture(_as_hash(hashmap, "roughnessMap")?)).transpose()
+}
+
+fn _parse_texture(objmat: &yaml_rust::yaml::Hash) -> Result<Box<dyn Texture + Send + Sync>, String> {
+    let textype = _get_str(objmat, "texType")?;
+    let hashtex = _get_hash(objmat, "texture")?;
     match textype {
         "SolidColor" => {
             // albedo is inside of the hash of SolidColor
-            let albedo = hashtex[&yaml_rust::Yaml::String("albedo".to_string())].as_vec().unwrap();
-            Box::new(SolidColor::new(Color::new(albedo[0].as_f64().unwrap() as f32, albedo[1].as_f64().unwrap() as f32, albedo[2].as_f64().unwrap() as f32)))
+            Ok(Box::new(SolidColor::new(_get_vec3(hashtex, "albedo")?)))
         },
         "ChessBoard" => {
             // Contains two textures and a scale
-            let tex1 = _parse_texture(hashtex[&yaml_rust::Yaml::String("tex1".to_string())].as_hash().unwrap());
-            let tex2 = _parse_texture(hashtex[&yaml_rust::Yaml::String("tex2".to_string())].as_hash().unwrap());
-            let scale = hashtex[&yaml_rust::Yaml::String("scale".to_string())].as_f64().unwrap();
-            Box::new(ChessBoard::new(tex1, tex2, scale as f32))
+            let tex1 = _parse_texture(_get_hash(hashtex, "tex1")?)?;
+            let tex2 = _parse_texture(_get_hash(hashtex, "tex2")?)?;
+            let scale: f32 = _get_f32(hashtex, "scale")?;
+            Ok(Box::new(ChessBoard::new(tex1, tex2, scale)))
+        },
+        "UVChecker" => {
+            // Contains two textures and the number of checks along u and v
+            let tex1 = _parse_texture(_get_hash(hashtex, "tex1")?)?;
+            let tex2 = _parse_texture(_get_hash(hashtex, "tex2")?)?;
+            Ok(Box::new(UVChecker::new(tex1, tex2, _parse_uv_scale(hashtex)?)))
+        },
+        "Brick" => {
+            // Contains the brick and mortar textures, the number of bricks along u and v, the mortar width and the row offset
+            let brick = _parse_texture(_get_hash(hashtex, "brick")?)?;
+            let mortar = _parse_texture(_get_hash(hashtex, "mortar")?)?;
+            let mortar_width = _parse_f32_or(hashtex, "mortarWidth", 0.05)?;
+            let row_offset = _parse_f32_or(hashtex, "rowOffset", 0.5)?;
+            Ok(Box::new(Brick::new(brick, mortar, _parse_uv_scale(hashtex)?, mortar_width, row_offset)))
+        },
+        "Grid" => {
+            // Contains the line and background textures, the number of cells along u and v and the line width
+            let line = _parse_texture(_get_hash(hashtex, "line")?)?;
+            let background = _parse_texture(_get_hash(hashtex, "background")?)?;
+            let line_width = _parse_f32_or(hashtex, "lineWidth", 0.05)?;
+            Ok(Box::new(Grid::new(line, background, _parse_uv_scale(hashtex)?, line_width)))
+        },
+        "PolkaDot" => {
+            // Contains the dot and background textures, the number of dots along u and v and their radius
+            let dot = _parse_texture(_get_hash(hashtex, "dot")?)?;
+            let background = _parse_texture(_get_hash(hashtex, "background")?)?;
+            let radius = _parse_f32_or(hashtex, "radius", 0.3)?;
+            Ok(Box::new(PolkaDot::new(dot, background, _parse_uv_scale(hashtex)?, radius)))
+        },
+        "Noise" => {
+            // Contains the colors of the low and high noise, the number of noise cells along u and v and the number of octaves
+            let low: Color = _get_vec3(hashtex, "low")?;
+            let high: Color = _get_vec3(hashtex, "high")?;
+            let octaves = _parse_usize_or(hashtex, "octaves", 4)? as u32;
+            Ok(Box::new(Noise::new(low, high, _parse_uv_scale(hashtex)?, octaves)))
         },
         "ImageTexture" => {
-            let filename = hashtex[&yaml_rust::Yaml::String("filename".to_string())].as_str().unwrap();
-            Box::new(ImageTexture::new(filename))
+            let filename = _get_str(hashtex, "filename")?;
+            // A texture which doesn't fit in the memory budget anymore can be streamed as tiles instead
+            let size: usize = if memory::streams_textures() { assets::image_dimensions(filename).map_or(0, |(width, height)| width as usize * height as usize * 4) } else { 0 };
+            if memory::fits(size) { return Ok(Box::new(ImageTexture::try_new(filename)?)); }
+            println!("{} doesn't fit in the memory budget, streaming it as tiles", filename);
+            match TiledImageTexture::new(filename, 1024, 64, ARGS.tile_cache.as_deref()) {
+                Ok(texture) => Ok(Box::new(texture)),
+                Err(err) => {
+                    ARGS.missing_assets.check("tiled image texture", filename, &err)?;
+                    Ok(Box::new(ImageTexture::placeholder()))
+                }
+            }
+        },
+        "TiledImageTexture" => {
+            // Giant textures are streamed from disk as tiles, keeping only the most recently used ones in memory
+            let filename = _get_str(hashtex, "filename")?;
+            let tile_size = _parse_f32_or(hashtex, "tileSize", 1024.0)?;
+            let cache_tiles = _parse_f32_or(hashtex, "cacheTiles", 64.0)?;
+            match TiledImageTexture::new(filename, tile_size as u32, cache_tiles as usize, ARGS.tile_cache.as_deref()) {
+                Ok(texture) => Ok(Box::new(texture)),
+                Err(err) => {
+                    ARGS.missing_assets.check("tiled image texture", filename, &err)?;
+                    Ok(Box::new(ImageTexture::placeholder()))
+                }
+            }
+        },
+        "RandomizePerInstance" => {
+            // Contains a base texture and the amount of hue and brightness variation, the instance comes from the object that was hit
+            let base = _parse_texture(_get_hash(hashtex, "base")?)?;
+            let hue_variation: f32 = _parse_f32_or(hashtex, "hueVariation", 0.05)?;
+            let brightness_variation: f32 = _parse_f32_or(hashtex, "brightnessVariation", 0.2)?;
+            Ok(Box::new(RandomizePerInstance::new(base, hue_variation, brightness_variation)))
+        }
+        _ => { Err(format!("Unsupported texture type: {}", textype)) }
+    }
+}
+
+// The scale of the uv textures is either a single number or a [u, v] pair
+fn _parse_uv_scale(hashtex: &yaml_rust::yaml::Hash) -> Result<Vec2, String> { _parse_scale(_get(hashtex, "scale")?, "scale") }
+
+fn _parse_scale(scale: &Yaml, key: &str) -> Result<Vec2, String> {
+    match scale {
+        Yaml::Array(scale) if scale.len() == 2 => Ok(Vec2::new(_as_f32(&scale[0], key)?, _as_f32(&scale[1], key)?)),
+        scale => _as_real(scale).map(|scale| Vec2::splat(scale as f32)).ok_or_else(|| format!("{} must be a number or a [u, v] pair", key)),
+    }
+}
+
+// Optional uvScale (a number or a [u, v] pair) and uvRotation in degrees of the texture coordinates of rectangles
+fn _parse_uv_transform(hash: &yaml_rust::yaml::Hash, default: UVTransform) -> Result<UVTransform, String> {
+    let scale: Vec2 = match hash.get(&yaml_rust::Yaml::String("uvScale".to_string())) {
+        Some(scale) => _parse_scale(scale, "uvScale")?,
+        None => default.scale,
+    };
+    Ok(UVTransform::new(scale, _parse_f32_or(hash, "uvRotation", default.rotation)?))
+}
+
+// The uv transform of the whole box or room, overridden per face under faceUVs: { floor: { uvScale, uvRotation }, ... }
+fn _parse_face_uv_transforms(hashobj: &yaml_rust::yaml::Hash) -> Result<[UVTransform; 6], String> {
+    let default: UVTransform = _parse_uv_transform(hashobj, UVTransform::default())?;
+    let mut uv_transforms: [UVTransform; 6] = [default; 6];
+    if let Some(faces) = hashobj.get(&yaml_rust::Yaml::String("faceUVs".to_string())) {
+        for (face, hashuv) in _as_hash(faces, "faceUVs")? {
+            uv_transforms[BoxFace::from_name(_as_str(face, "faceUVs")?)? as usize] = _parse_uv_transform(_as_hash(hashuv, "faceUVs")?, default)?;
         }
-        _ => { panic!("Unsupported texture type: {}", textype) }
     }
+    Ok(uv_transforms)
+}
+
+// The fresnel equations of the material, fresnel: schlick or exact
+fn _parse_fresnel(hash: &yaml_rust::yaml::Hash, default: Fresnel) -> Result<Fresnel, String> {
+    hash.get(&yaml_rust::Yaml::String("fresnel".to_string())).map_or(Ok(default), |fresnel| _as_str(fresnel, "fresnel").and_then(Fresnel::from_name))
+}
+
+/****************** Values ******************/
+// The keys missing from the scene and the values of the wrong type come back as errors naming the key, rather than panics
+
+fn _get<'a>(hash: &'a yaml_rust::yaml::Hash, key: &str) -> Result<&'a Yaml, String> {
+    hash.get(&yaml_rust::Yaml::String(key.to_string())).ok_or_else(|| format!("Missing the key {}", key))
+}
+
+fn _get_hash<'a>(hash: &'a yaml_rust::yaml::Hash, key: &str) -> Result<&'a yaml_rust::yaml::Hash, String> { _as_hash(_get(hash, key)?, key) }
+
+fn _get_vec<'a>(hash: &'a yaml_rust::yaml::Hash, key: &str) -> Result<&'a Vec<Yaml>, String> { _as_vec(_get(hash, key)?, key) }
+
+fn _get_str<'a>(hash: &'a yaml_rust::yaml::Hash, key: &str) -> Result<&'a str, String> { _as_str(_get(hash, key)?, key) }
+
+fn _get_f32(hash: &yaml_rust::yaml::Hash, key: &str) -> Result<f32, String> { _as_f32(_get(hash, key)?, key) }
+
+fn _get_u32(hash: &yaml_rust::yaml::Hash, key: &str) -> Result<u32, String> {
+    let value: i64 = _as_int(_get(hash, key)?, key)?;
+    u32::try_from(value).map_err(|_| format!("{} must be between 0 and {}, got {}", key, u32::MAX, value))
+}
+
+fn _get_vec3(hash: &yaml_rust::yaml::Hash, key: &str) -> Result<Vec3A, String> { _parse_vec3(_get(hash, key)?, key) }
+
+fn _as_hash<'a>(value: &'a Yaml, key: &str) -> Result<&'a yaml_rust::yaml::Hash, String> { value.as_hash().ok_or_else(|| format!("{} must be a mapping", key)) }
+
+fn _as_vec<'a>(value: &'a Yaml, key: &str) -> Result<&'a Vec<Yaml>, String> { value.as_vec().ok_or_else(|| format!("{} must be a list", key)) }
+
+fn _as_str<'a>(value: &'a Yaml, key: &str) -> Result<&'a str, String> { value.as_str().ok_or_else(|| format!("{} must be a string", key)) }
+
+fn _as_f32(value: &Yaml, key: &str) -> Result<f32, String> { _as_real(value).map(|value| value as f32).ok_or_else(|| format!("{} must be a number", key)) }
+
+fn _as_int(value: &Yaml, key: &str) -> Result<i64, String> { value.as_i64().ok_or_else(|| format!("{} must be a whole number", key)) }
+
+fn _as_usize(value: &Yaml, key: &str) -> Result<usize, String> {
+    let value: i64 = _as_int(value, key)?;
+    usize::try_from(value).map_err(|_| format!("{} must not be negative, got {}", key, value))
+}
+
+// Colors, points and directions, as [x, y, z] (the alpha of the colors written as [r, g, b, a] is left out)
+fn _parse_vec3(value: &Yaml, key: &str) -> Result<Vec3A, String> {
+    match value.as_vec().map(Vec::as_slice) {
+        Some([x, y, z, ..]) => Ok(Vec3A::new(_as_f32(x, key)?, _as_f32(y, key)?, _as_f32(z, key)?)),
+        _ => Err(format!("{} must be a list of three numbers", key)),
+    }
+}
+
+// A real number, also written as a whole one, like the --set overrides of keys missing from the scene are
+fn _as_real(value: &Yaml) -> Option<f64> { value.as_f64().or(value.as_i64().map(|value| value as f64)) }
+
+fn _parse_f32_or(hash: &yaml_rust::yaml::Hash, key: &str, default: f32) -> Result<f32, String> {
+    hash.get(&yaml_rust::Yaml::String(key.to_string())).map_or(Ok(default), |value| _as_f32(value, key))
+}
+
+fn _parse_f32_opt(hash: &yaml_rust::yaml::Hash, key: &str) -> Result<Option<f32>, String> {
+    hash.get(&yaml_rust::Yaml::String(key.to_string())).map(|value| _as_f32(value, key)).transpose()
+}
+
+fn _parse_str_opt(hash: &yaml_rust::yaml::Hash, key: &str) -> Result<Option<String>, String> {
+    hash.get(&yaml_rust::Yaml::String(key.to_string())).map(|value| _as_str(value, key).map(str::to_string)).transpose()
+}
+
+fn _parse_bool_or(hash: &yaml_rust::yaml::Hash, key: &str, default: bool) -> Result<bool, String> {
+    hash.get(&yaml_rust::Yaml::String(key.to_string())).map_or(Ok(default), |value| value.as_bool().ok_or_else(|| format!("{} must be true or false", key)))
+}
+
+fn _parse_usize_or(hash: &yaml_rust::yaml::Hash, key: &str, default: usize) -> Result<usize, String> {
+    hash.get(&yaml_rust::Yaml::String(key.to_string())).map_or(Ok(default), |value| _as_usize(value, key))
 }
 
-fn _parse_geometry(hashobj: &yaml_rust::yaml::Hash, material: Box<dyn Material>) -> Arc<dyn Hittable + Send + Sync> {
-    let objtype = hashobj[&yaml_rust::Yaml::String("objType".to_string())].as_str().unwrap();
+// An optional section of the constants, { key: value, .. } read by the given function
+fn _parse_section<T>(hash: &yaml_rust::yaml::Hash, key: &str, parse: impl FnOnce(&yaml_rust::yaml::Hash) -> Result<T, String>) -> Result<Option<T>, String> {
+    hash.get(&yaml_rust::Yaml::String(key.to_string())).map(|section| parse(_as_hash(section, key)?)).transpose()
+}
+
+// Optional "bvh: { maxLeafPrimitives, bins }" of meshes, displaced spheres and sphere arrays
+fn _parse_bvh_params(hashobj: &yaml_rust::yaml::Hash) -> Result<BVHParams, String> {
+    let default: BVHParams = BVHParams::default();
+    match hashobj.get(&yaml_rust::Yaml::String("bvh".to_string())) {
+        Some(hashbvh) => {
+            let hashbvh = _as_hash(hashbvh, "bvh")?;
+            BVHParams::new(
+                _parse_usize_or(hashbvh, "maxLeafPrimitives", default.max_leaf_primitives)?,
+                _parse_usize_or(hashbvh, "bins", default.bins)?
+            )
+        },
+        None => Ok(default),
+    }
+}
+
+fn _parse_geometry(hashobj: &yaml_rust::yaml::Hash, material: Box<dyn Material>) -> Result<Arc<dyn Hittable + Send + Sync>, String> {
+    let objtype = _get_str(hashobj, "objType")?;
     match objtype {
         "Sphere" => {
-            // has a center and radius
-            let center = hashobj[&yaml_rust::Yaml::String("center".to_string())].as_vec().unwrap();
-            let radius = hashobj[&yaml_rust::Yaml::String("radius".to_string())].as_f64().unwrap();
-            Arc::new(Sphere::new(Point3::new(center[0].as_f64().unwrap() as f32, center[1].as_f64().unwrap() as f32, center[2].as_f64().unwrap() as f32), radius as f32, material, 0))
+            // has a center and radius, optionally a uv mapping and a displacement
+            let center: Point3 = _get_vec3(hashobj, "center")?;
+            let radius: f32 = _get_f32(hashobj, "radius")?;
+            let uv_tiling: Vec2 = hashobj.get(&yaml_rust::Yaml::String("uvTiling".to_string())).map_or(Ok(Vec2::ONE), |tiling| _parse_scale(tiling, "uvTiling"))?;
+            let uv_rotation: f32 = _parse_f32_or(hashobj, "uvRotation", 0.0)?;
+            let sphere: Sphere = Sphere::new(center, radius, material, 0).with_uv_mapping(uv_tiling, uv_rotation);
+            if hashobj.contains_key(&yaml_rust::Yaml::String("displacement".to_string())) {
+                // The displaced sphere gets tessellated into a mesh
+                let hashdisp = _get_hash(hashobj, "displacement")?;
+                let height_map = _parse_texture(hashdisp)?;
+                let scale: f32 = _get_f32(hashdisp, "scale")?;
+                let resolution: usize = _parse_usize_or(hashdisp, "resolution", 256)?;
+                if resolution > MAX_RESOLUTION { return Err(format!("The resolution of a displaced sphere can be at most {}, got {}", MAX_RESOLUTION, resolution)); }
+                Ok(Arc::new(sphere.tessellate(height_map.as_ref(), scale, resolution as u32, &_parse_bvh_params(hashobj)?)))
+            } else { Ok(Arc::new(sphere)) }
         },
         "XYRectangle" => {
-            // has a position, width and height
-            let position = hashobj[&yaml_rust::Yaml::String("position".to_string())].as_vec().unwrap();
-            let width = hashobj[&yaml_rust::Yaml::String("width".to_string())].as_f64().unwrap();
-            let height = hashobj[&yaml_rust::Yaml::String("height".to_string())].as_f64().unwrap();
-            Arc::new(XYRectangle::new(
-                position[0].as_f64().unwrap() as f32 - width as f32 / 2.0,
-                position[0].as_f64().unwrap() as f32 + width as f32 / 2.0,
-                position[1].as_f64().unwrap() as f32 - height as f32 / 2.0,
-                position[1].as_f64().unwrap() as f32 + height as f32 / 2.0,
-                position[2].as_f64().unwrap() as f32,
+            // has a position, width and height, and optionally a flipNormal to turn a one sided light around and a uv transform
+            let position: Point3 = _get_vec3(hashobj, "position")?;
+            let width: f32 = _get_f32(hashobj, "width")?;
+            let height: f32 = _get_f32(hashobj, "height")?;
+            Ok(Arc::new(XYRectangle::new(
+                position.x - width / 2.0,
+                position.x + width / 2.0,
+                position.y - height / 2.0,
+                position.y + height / 2.0,
+                position.z,
                 material,
                 0
-            ))
+            ).with_flipped_normal(_parse_bool_or(hashobj, "flipNormal", false)?).with_uv_transform(_parse_uv_transform(hashobj, UVTransform::default())?)))
         },
         "XZRectangle" => {
-            // has a position, width and height
-            let position = hashobj[&yaml_rust::Yaml::String("position".to_string())].as_vec().unwrap();
-            let width = hashobj[&yaml_rust::Yaml::String("width".to_string())].as_f64().unwrap();
-            let height = hashobj[&yaml_rust::Yaml::String("height".to_string())].as_f64().unwrap();
-            Arc::new(XZRectangle::new(
-                position[0].as_f64().unwrap() as f32 - width as f32 / 2.0,
-                position[0].as_f64().unwrap() as f32 + width as f32 / 2.0,
-                position[2].as_f64().unwrap() as f32 - height as f32 / 2.0,
-                position[2].as_f64().unwrap() as f32 + height as f32 / 2.0,
-                position[1].as_f64().unwrap() as f32,
+            // has a position, width and height, and optionally a flipNormal to turn a one sided light around and a uv transform
+            let position: Point3 = _get_vec3(hashobj, "position")?;
+            let width: f32 = _get_f32(hashobj, "width")?;
+            let height: f32 = _get_f32(hashobj, "height")?;
+            Ok(Arc::new(XZRectangle::new(
+                position.x - width / 2.0,
+                position.x + width / 2.0,
+                position.z - height / 2.0,
+                position.z + height / 2.0,
+                position.y,
                 material,
                 0
-            ))
+            ).with_flipped_normal(_parse_bool_or(hashobj, "flipNormal", false)?).with_uv_transform(_parse_uv_transform(hashobj, UVTransform::default())?)))
         },
         "YZRectangle" => {
-            // has a position, width and height
-            let position = hashobj[&yaml_rust::Yaml::String("position".to_string())].as_vec().unwrap();
-            let width = hashobj[&yaml_rust::Yaml::String("width".to_string())].as_f64().unwrap();
-            let height = hashobj[&yaml_rust::Yaml::String("height".to_string())].as_f64().unwrap();
-            Arc::new(YZRectangle::new(
-                position[1].as_f64().unwrap() as f32 - width as f32 / 2.0,
-                position[1].as_f64().unwrap() as f32 + width as f32 / 2.0,
-                position[2].as_f64().unwrap() as f32 - height as f32 / 2.0,
-                position[2].as_f64().unwrap() as f32 + height as f32 / 2.0,
-                position[0].as_f64().unwrap() as f32,
+            // has a position, width and height, and optionally a flipNormal to turn a one sided light around and a uv transform
+            let position: Point3 = _get_vec3(hashobj, "position")?;
+            let width: f32 = _get_f32(hashobj, "width")?;
+            let height: f32 = _get_f32(hashobj, "height")?;
+            Ok(Arc::new(YZRectangle::new(
+                position.y - width / 2.0,
+                position.y + width / 2.0,
+                position.z - height / 2.0,
+                position.z + height / 2.0,
+                position.x,
                 material,
                 0
-            ))
+            ).with_flipped_normal(_parse_bool_or(hashobj, "flipNormal", false)?).with_uv_transform(_parse_uv_transform(hashobj, UVTransform::default())?)))
         },
         "Box" => {
-            // has a position, width and height and depth
-            let position = hashobj[&yaml_rust::Yaml::String("position".to_string())].as_vec().unwrap();
-            let width = hashobj[&yaml_rust::Yaml::String("width".to_string())].as_f64().unwrap();
-            let height = hashobj[&yaml_rust::Yaml::String("height".to_string())].as_f64().unwrap();
-            let depth = hashobj[&yaml_rust::Yaml::String("depth".to_string())].as_f64().unwrap();
-            Arc::new(BBox::new(
-                Vec3A::new(position[0].as_f64().unwrap() as f32, position[1].as_f64().unwrap() as f32, position[2].as_f64().unwrap() as f32),
-                Vec3A::new(width as f32, height as f32, depth as f32),
-                material
-            ))
+            // has a position, width and height and depth, and optionally uv transforms for all of the faces or each one
+            let position: Point3 = _get_vec3(hashobj, "position")?;
+            let size: Vec3A = Vec3A::new(_get_f32(hashobj, "width")?, _get_f32(hashobj, "height")?, _get_f32(hashobj, "depth")?);
+            Ok(Arc::new(BBox::new_with_uv_transforms(position, size, material, _parse_face_uv_transforms(hashobj)?)))
         }
-        "Mesh" => {
-            // has a filename, position, rotation and scale
-            let filename = hashobj[&yaml_rust::Yaml::String("filename".to_string())].as_str().unwrap();
-            let position = hashobj[&yaml_rust::Yaml::String("position".to_string())].as_vec().unwrap();
-            let rotation = hashobj[&yaml_rust::Yaml::String("rotation".to_string())].as_vec().unwrap();
-            let scale = hashobj[&yaml_rust::Yaml::String("scalingFactor".to_string())].as_f64().unwrap();
-            Arc::new(
-                Mesh::new(
-                    Vec3A::new(position[0].as_f64().unwrap() as f32, position[1].as_f64().unwrap() as f32, position[2].as_f64().unwrap() as f32),
-                    scale as f32,
-                    Vec3A::new(rotation[0].as_f64().unwrap() as f32, rotation[1].as_f64().unwrap() as f32, rotation[2].as_f64().unwrap() as f32),
-                    filename,
-                    material
-                )
-            )
+        "Room" => {
+            // has a position, width, height and depth like a box, with the walls facing inwards
+            // the material can be overridden per wall under faces: { floor, ceiling, left, right, back, front }, and openFaces leaves some of them out
+            let position: Point3 = _get_vec3(hashobj, "position")?;
+            let size: Vec3A = Vec3A::new(_get_f32(hashobj, "width")?, _get_f32(hashobj, "height")?, _get_f32(hashobj, "depth")?);
+            let mut materials: [Option<Box<dyn Material>>; 6] = BoxFace::ALL.map(|_| Some(material.clone()));
+            if let Some(faces) = hashobj.get(&yaml_rust::Yaml::String("faces".to_string())) {
+                for (face, objmat) in _as_hash(faces, "faces")? {
+                    materials[BoxFace::from_name(_as_str(face, "faces")?)? as usize] = Some(_parse_material_hash(_as_hash(objmat, "faces")?)?);
+                }
+            }
+            if let Some(open_faces) = hashobj.get(&yaml_rust::Yaml::String("openFaces".to_string())) {
+                for face in _as_vec(open_faces, "openFaces")? { materials[BoxFace::from_name(_as_str(face, "openFaces")?)? as usize] = None; }
+            }
+            Ok(Arc::new(Room::new(position, size, materials, _parse_face_uv_transforms(hashobj)?)))
+        },
+        "RoundedBox" => {
+            // has a position, width, height and depth like a box, and the radius of its edges and corners
+            let position: Point3 = _get_vec3(hashobj, "position")?;
+            let size: Vec3A = Vec3A::new(_get_f32(hashobj, "width")?, _get_f32(hashobj, "height")?, _get_f32(hashobj, "depth")?);
+            Ok(Arc::new(RoundedBox::new(position, size, _get_f32(hashobj, "radius")?, material, 0)))
         },
-        _ => { panic!("Unknown object type: {}", objtype); }
+        "Capsule" => {
+            // has the start and end points of its axis and a radius
+            Ok(Arc::new(Capsule::new(_get_vec3(hashobj, "start")?, _get_vec3(hashobj, "end")?, _get_f32(hashobj, "radius")?, material, 0)))
+        },
+        "Plane" => {
+            // has a point and a normal, and optionally a uv transform, the texture coordinates being in scene units
+            Ok(Arc::new(Plane::new(_get_vec3(hashobj, "point")?, _get_vec3(hashobj, "normal")?, material).with_uv_transform(_parse_uv_transform(hashobj, UVTransform::default())?)))
+        },
+        "Mesh" => { Ok(Arc::new(parse_mesh(hashobj, material)?)) },
+        _ => { Err(format!("Unknown object type: {}", objtype)) }
+    }
+}
+
+pub fn parse_mesh(hashobj: &yaml_rust::yaml::Hash, material: Box<dyn Material>) -> Result<Mesh, String> {
+    // has a filename, position, rotation and scale
+    let filename = _get_str(hashobj, "filename")?;
+    let position: Point3 = _get_vec3(hashobj, "position")?;
+    let rotation: Vec3A = _get_vec3(hashobj, "rotation")?;
+    let scale: f32 = _get_f32(hashobj, "scalingFactor")?;
+    let mesh: Mesh = Mesh::try_new(position, scale, rotation, filename, material, &_parse_bvh_params(hashobj)?)?;
+    // and optionally dirt in its creases, of a color and an amount
+    let mesh: Mesh = match hashobj.get(&yaml_rust::Yaml::String("dirt".to_string())) {
+        Some(hashdirt) => {
+            let hashdirt = _as_hash(hashdirt, "dirt")?;
+            let color: Color = hashdirt.get(&yaml_rust::Yaml::String("color".to_string())).map_or(Ok(Color::new(0.2, 0.15, 0.1)), |color| _parse_vec3(color, "color"))?;
+            mesh.with_dirt(color, _parse_f32_or(hashdirt, "amount", 2.0)?)
+        },
+        None => mesh,
+    };
+    // and optionally the edges of its triangles drawn over it, of a color, a width in world units and an opacity
+    match hashobj.get(&yaml_rust::Yaml::String("wireframe".to_string())) {
+        Some(hashwire) => {
+            let hashwire = _as_hash(hashwire, "wireframe")?;
+            let color: Color = hashwire.get(&yaml_rust::Yaml::String("color".to_string())).map_or(Ok(Color::ZERO), |color| _parse_vec3(color, "color"))?;
+            Ok(mesh.with_wireframe(color, _parse_f32_or(hashwire, "width", 0.01)?, _parse_f32_or(hashwire, "opacity", 1.0)?))
+        },
+        None => Ok(mesh),
+    }
+}
+
+/****************** Untrusted scenes ******************/
+// The scenes of untrusted sources (the web demo, shared archives) come back as errors rather than panics, and are checked before parsing for what would hang it
+// Their assets are read like those of any other scene, the meshes and textures they name have to be registered or found on disk
+#[allow(dead_code)]
+pub fn try_parse_scene(content: &str) -> Result<(utility::Constants, Camera, HittableList), String> {
+    check_document(content)?;
+    let mut doc: Yaml = YamlLoader::load_from_str(content).map_err(|err| format!("Failed to parse the scene: {}", err))?
+        .into_iter().next().ok_or("The scene is empty")?;
+    resolve_materials(&mut doc)?;
+    let constants: utility::Constants = try_parse_constants(&doc)?;
+    if !(1..=MAX_DIMENSION).contains(&constants.width) || !(1..=MAX_DIMENSION).contains(&constants.height) {
+        return Err(format!("The image has to be between 1 and {} pixels wide and high, got {}x{}", MAX_DIMENSION, constants.width, constants.height));
+    }
+    Ok((constants, try_parse_camera(&doc)?, try_parse_world(&doc)?))
+}
+
+// Refuses the documents nested too deep for the parser, and the ones whose aliases expand into too many nodes ("billion laughs"), without expanding them
+pub fn check_document(content: &str) -> Result<(), String> {
+    let mut counter: _NodeCounter = _NodeCounter::default();
+    Parser::new(content.chars()).load(&mut counter, false).map_err(|err| format!("Failed to parse the scene: {}", err))?;
+    if counter.max_depth > MAX_NESTING { return Err(format!("The scene is nested {} levels deep, at most {} are allowed", counter.max_depth, MAX_NESTING)); }
+    if counter.nodes > MAX_NODES { return Err(format!("The scene expands into more than {} nodes", MAX_NODES)); }
+    Ok(())
+}
+
+// The nodes of the document as the loader would build them, counting every alias as a copy of its anchor
+#[derive(Default)]
+struct _NodeCounter {
+    // The open containers, with their anchor and the nodes inside of them so far
+    stack: Vec<(usize, u64)>,
+    anchors: HashMap<usize, u64>,
+    nodes: u64,
+    max_depth: usize,
+}
+
+impl _NodeCounter {
+    fn _close(&mut self, anchor: usize, nodes: u64) {
+        if anchor > 0 { self.anchors.insert(anchor, nodes); }
+        match self.stack.last_mut() {
+            Some((_, parent)) => *parent = parent.saturating_add(nodes),
+            None => self.nodes = self.nodes.saturating_add(nodes),
+        }
+    }
+}
+
+impl EventReceiver for _NodeCounter {
+    fn on_event(&mut self, event: Event) {
+        match event {
+            Event::SequenceStart(anchor) | Event::MappingStart(anchor) => {
+                self.stack.push((anchor, 1));
+                self.max_depth = self.max_depth.max(self.stack.len());
+            },
+            Event::SequenceEnd | Event::MappingEnd => {
+                let (anchor, nodes) = self.stack.pop().unwrap();
+                self._close(anchor, nodes);
+            },
+            Event::Scalar(_, _, anchor, _) => self._close(anchor, 1),
+            Event::Alias(anchor) => self._close(0, self.anchors.get(&anchor).copied().unwrap_or(1)),
+            _ => {},
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use yaml_rust::YamlLoader;
+    use crate::ray::Ray;
+
+    #[test]
+    fn test_groups() {
+        // A group of two spheres moved up and placed twice, the second copy half the size, next to a plain sphere
+        let doc: Yaml = YamlLoader::load_from_str("world: [
+            {objType: Sphere, name: ground, center: [0.0, -100.0, 0.0], radius: 99.0, material: {matType: Lambertian, texType: SolidColor, texture: {albedo: [0.5, 0.5, 0.5]}}},
+            {objType: Group, transform: {translate: [0.0, 2.0, 0.0]}, instances: [{translate: [-5.0, 0.0, 0.0]}, {translate: [5.0, 0.0, 0.0], scale: 0.5}], children: [
+                {objType: Sphere, name: ball, center: [0.0, 0.0, 0.0], radius: 1.0, material: {matType: Lambertian, texType: RandomizePerInstance, texture: {base: {texType: SolidColor, texture: {albedo: [0.5, 0.5, 0.5]}}}}},
+                {objType: Group, transform: {rotate: [0.0, 0.0, 90.0]}, children: [{objType: Sphere, center: [1.0, 0.0, 0.0], radius: 0.25, material: {matType: Lambertian, texType: SolidColor, texture: {albedo: [0.5, 0.5, 0.5]}}}]}
+            ]}
+        ]").unwrap().remove(0);
+        let world: HittableList = parse_scene(&doc);
+        assert_eq!(world.len(), 5);
+        assert_eq!(parse_object_names(&doc), vec![Some("ground".to_string()), Some("ball".to_string()), None, Some("ball".to_string()), None]);
+        let hit_y = |x: f32| world.hit(&Ray::new(Point3::new(x, 10.0, 0.0), -Vec3A::Y), 0.001, f32::INFINITY).map(|rec| rec.p.y);
+        // The small sphere is rotated around the center of the group to sit on top of the ball, then both are scaled down in the second copy
+        assert!((hit_y(-5.0).unwrap() - 3.25).abs() < 1e-4);
+        assert!((hit_y(5.0).unwrap() - 2.625).abs() < 1e-4);
+        assert!((hit_y(0.0).unwrap() + 1.0).abs() < 1e-4);
+        // Each copy of the ball is its own instance, so its texture varies apart from the other one
+        let instance = |x: f32| world.hit(&Ray::new(Point3::new(x, 10.0, 0.0), -Vec3A::Y), 0.001, f32::INFINITY).map(|rec| rec.instance);
+        assert_eq!((instance(-5.4), instance(5.4)), (Some(0), Some(1)));
+        let transform: Affine3A = _parse_transform(&YamlLoader::load_from_str("{translate: [1.0, 2.0, 3.0], rotate: [0.0, 90.0, 0.0], scale: [2.0, 1.0, 1.0]}").unwrap().remove(0)).unwrap();
+        assert!(transform.transform_point3(Vec3::X).abs_diff_eq(Vec3::new(1.0, 2.0, 1.0), 1e-5));
+    }
+
+    #[test]
+    fn test_instance_ids() {
+        // An array of three spheres with the same varying texture, placed twice by a group after a plain sphere, which needs no instance
+        let doc: Yaml = YamlLoader::load_from_str("world: [
+            {objType: Sphere, center: [0.0, -100.0, 0.0], radius: 99.0, material: {matType: Lambertian, texType: SolidColor, texture: {albedo: [0.5, 0.5, 0.5]}}},
+            {objType: Group, instances: [{translate: [0.0, 0.0, 0.0]}, {translate: [0.0, 0.0, 10.0]}], children: [{objType: SphereArray, objects: [
+                {objType: Sphere, center: [4.0, 0.0, 0.0], radius: 1.0, material: {matType: Lambertian, texType: RandomizePerInstance, texture: {base: {texType: SolidColor, texture: {albedo: [0.5, 0.5, 0.5]}}}}},
+                {objType: Sphere, center: [-4.0, 0.0, 0.0], radius: 1.0, material: {matType: Lambertian, texType: RandomizePerInstance, texture: {base: {texType: SolidColor, texture: {albedo: [0.5, 0.5, 0.5]}}}}},
+                {objType: Sphere, center: [0.0, 0.0, 0.0], radius: 1.0, material: {matType: Lambertian, texType: RandomizePerInstance, texture: {base: {texType: SolidColor, texture: {albedo: [0.5, 0.5, 0.5]}}}}}
+            ]}]}
+        ]").unwrap().remove(0);
+        let ids = |world: &HittableList| -> Vec<(u32, u32)> {
+            [(4.0, 0.0), (-4.0, 0.0), (0.0, 0.0), (-4.0, 10.0)].iter().map(|(x, z)| {
+                let rec = world.hit(&Ray::new(Point3::new(*x, 10.0, *z), -Vec3A::Y), 0.001, f32::INFINITY).unwrap();
+                (rec.instance, rec.primitive)
+            }).collect()
+        };
+        // The ids are the index of the instance of the group and the one of the sphere in the array, the same however many times the scene is parsed
+        assert_eq!(ids(&parse_scene(&doc)), vec![(0, 0), (0, 1), (0, 2), (1, 1)]);
+        assert_eq!(ids(&parse_scene(&doc)), ids(&parse_scene(&doc)));
+        assert!(_varies_per_instance(&doc["world"][1]) && !_varies_per_instance(&doc["world"][0]));
+    }
+
+    #[test]
+    fn test_named_materials() {
+        let mut doc: Yaml = YamlLoader::load_from_str("
+            materials: {paint: {matType: Metal, fuzz: 0.1}, red_paint: {matType: Lambertian}, chrome: {matType: Metal, fuzz: 0.0}}
+            overrideMaterials: {paint: red_paint, wheels: {matType: Dielectric}}
+            world: [
+                {objType: Sphere, name: body, material: paint, clip: {planes: [], cap: chrome}},
+                {objType: Group, name: wheels, children: [{objType: Sphere, material: chrome}, {objType: Sphere, material: {matType: Plastic}}]},
+                {objType: Sphere, material: chrome}
+            ]").unwrap().remove(0);
+        resolve_materials(&mut doc).unwrap();
+        let mat_type = |object: &Yaml| object["material"]["matType"].as_str().map(|mat_type| mat_type.to_string());
+        assert_eq!(mat_type(&doc["world"][0]), Some("Lambertian".to_string()));
+        assert_eq!(doc["world"][0]["clip"]["cap"]["fuzz"].as_f64(), Some(0.0));
+        // The override of the group replaces the inline materials of its children too, but not the other uses of the same material
+        assert_eq!(mat_type(&doc["world"][1]["children"][0]), Some("Dielectric".to_string()));
+        assert_eq!(mat_type(&doc["world"][1]["children"][1]), Some("Dielectric".to_string()));
+        assert_eq!(mat_type(&doc["world"][2]), Some("Metal".to_string()));
+    }
+
+    #[test]
+    fn test_untrusted_scenes() {
+        let scene = |world: &str, width: i64| format!("
+            constants: {{width: {}, height: 8, samplesPerPixel: 1, maxDepth: 1, minDepth: 1}}
+            camera: {{lookFrom: [0.0, 1.0, 5.0], lookAt: [0.0, 0.0, 0.0], vup: [0.0, 1.0, 0.0], vfov: 40.0, aspectRatio: 1.0, aperture: 0.0, focusDistance: 1.0}}
+            world: {}", width, world);
+        let sphere: &str = "[{objType: Sphere, center: [0.0, 0.0, 0.0], radius: 1.0, material: {matType: Lambertian, texType: SolidColor, texture: {albedo: [0.5, 0.5, 0.5]}}}]";
+        assert_eq!(try_parse_scene(&scene(sphere, 8)).unwrap().2.len(), 1);
+        // The panics of the parser come back as errors, and so do the sizes wrapping around
+        assert!(try_parse_scene(&scene("[{objType: Sphere, center: [0.0], radius: one}]", 8)).unwrap_err().contains("Missing the key material"));
+        assert!(try_parse_scene(&scene(sphere, -8)).is_err());
+        assert!(try_parse_scene("world: [").is_err());
+        // Nine levels of ten aliases each expand into a billion nodes, and are refused before the loader expands them
+        let mut laughs: String = String::from("a0: &a0 [lol]\n");
+        for level in 1..10 { laughs.push_str(&format!("a{}: &a{} [{}]\n", level, level, vec![format!("*a{}", level - 1); 10].join(", "))); }
+        assert!(try_parse_scene(&laughs).unwrap_err().contains("nodes"));
+        assert!(check_document(&format!("{}{}", "[".repeat(100), "]".repeat(100))).unwrap_err().contains("nested"));
+        assert!(check_document(&scene(sphere, 8)).is_ok());
+    }
+
+    #[test]
+    fn test_camera_path_frames() {
+        let doc = |frame: i64| YamlLoader::load_from_str(&format!("
+            constants: {{frame: {}}}
+            camera: {{lookFrom: [0.0, 1.0, 5.0], lookAt: [0.0, 0.0, 0.0], vup: [0.0, 1.0, 0.0], vfov: 40.0, aspectRatio: 1.0, aperture: 0.0, focusDistance: 1.0}}
+            animation: {{fps: 10, cameraPath: [{{time: 0, lookFrom: [0.0, 1.0, 5.0]}}, {{time: 2, lookFrom: [4.0, 1.0, 5.0], lookAt: [4.0, 0.0, 0.0]}}]}}", frame)).unwrap().remove(0);
+        // Halfway through the path at frame 10, at its end from frame 20 on
+        assert!((parse_camera(&doc(10)).origin - Vec3A::new(2.0, 1.0, 5.0)).length() < 1e-4);
+        assert!((parse_camera(&doc(25)).origin - Vec3A::new(4.0, 1.0, 5.0)).length() < 1e-4);
+        let (path, fps) = parse_camera_path(&doc(0), Point3::new(0.0, 0.0, 0.0)).unwrap().unwrap();
+        assert_eq!((path.at(0.0).1, fps), (Point3::ZERO, 10.0));
     }
 }
@@ -2,11 +2,10 @@
 // Date: 24/01/2023
 // Description: This file implements the PDF (Probability Density Function) trait
 
-use std::sync::Arc;
-
 use glam::Vec3A;
 
 use crate::onb::ONB;
+use crate::sampling;
 use crate::utility;
 use crate::hittable_list::Hittable;
 use crate::point3::Point3;
@@ -16,63 +15,93 @@ pub trait PDF {
     fn generate(&self) -> Vec3A;
 }
 
-#[derive(Clone)]
+#[derive(Clone, Copy)]
 pub struct CosinePDF {
     pub uvw: ONB,
 }
 
 impl CosinePDF {
     pub fn new(w: &Vec3A) -> Self {
-        let mut uvw = ONB::new();
-        uvw.build_from_w(w);
-        CosinePDF { uvw }
+        CosinePDF { uvw: ONB::from_w(w) }
     }
 }
 
 impl PDF for CosinePDF {
     fn value(&self, direction: &Vec3A) -> f32 {
-        let cosine = direction.normalize().dot(self.uvw.w);
-        if cosine > 0.0 { cosine / utility::PI } else { 0.0 }
+        sampling::cosine_hemisphere_pdf(direction.normalize().dot(self.uvw.w))
     }
     fn generate(&self) -> Vec3A { self.uvw.local_vec(&utility::random_cosine_direction()) }
 }
 
-#[derive(Clone)]
-pub struct HittablePDF {
+/****************** HittablePDF ******************/
+// The directions from the origin towards an object, for the lights list one of its lights is picked for each direction
+// Generic over the object, so that sampling a concrete light or list doesn't go through a vtable
+#[derive(Clone, Copy)]
+pub struct HittablePDF<'a, H: Hittable + ?Sized> {
     pub origin: Point3,
-    pub ptr: Arc<dyn Hittable>,
+    pub object: &'a H,
 }
 
-/*
-impl HittablePDF { pub fn new(origin: Point3, ptr: Arc<dyn Hittable>) -> Self { HittablePDF { origin, ptr } } }
-
-impl PDF for HittablePDF {
-    fn value(&self, direction: &Vec3A) -> f32 { self.ptr.pdf_value(&self.origin, &direction) }
-    fn generate(&self) -> Vec3A { self.ptr.random(&self.origin) }
+impl<'a, H: Hittable + ?Sized> HittablePDF<'a, H> {
+    pub fn new(origin: Point3, object: &'a H) -> Self { HittablePDF { origin, object } }
 }
 
-#[derive(Clone)]
-pub struct MixturePDF {
-    pub p0: Arc<dyn PDF>,
-    pub p1: Arc<dyn PDF>,
+impl<H: Hittable + ?Sized> PDF for HittablePDF<'_, H> {
+    fn value(&self, direction: &Vec3A) -> f32 { self.object.pdf_value(&self.origin, direction) }
+    fn generate(&self) -> Vec3A { self.object.random(&self.origin) }
 }
 
-impl MixturePDF { pub fn new(p0: Arc<dyn PDF>, p1: Arc<dyn PDF>) -> Self { MixturePDF { p0, p1 } } }
+/****************** MixturePDF ******************/
+// Samples the first pdf with the probability of the weight and the second one otherwise, its density being the weighted sum
+#[derive(Clone, Copy)]
+pub struct MixturePDF<A: PDF, B: PDF> {
+    pub p0: A,
+    pub p1: B,
+    pub weight: f32,
+}
 
-impl PDF for MixturePDF {
-    fn value(&self, direction: &Vec3A) -> f32 { 0.5 * self.p0.value(direction) + 0.5 * self.p1.value(direction) }
-    fn generate(&self) -> Vec3A { if utility::random_f32() < 0.5 { self.p0.generate() } else { self.p1.generate() } }
+impl<A: PDF, B: PDF> MixturePDF<A, B> {
+    pub fn new(p0: A, p1: B) -> Self { MixturePDF { p0, p1, weight: 0.5 } }
+    pub fn with_weight(mut self, weight: f32) -> Self {
+        self.weight = weight.clamp(0.0, 1.0);
+        self
+    }
 }
 
-// Example of usage in the ray_color function:
-// For only HittablePDF:
-//let light_pdf: HittablePDF = HittablePDF::new(rec.p, Arc::new(lights.clone()));
-//let mut scattered: Ray = Ray::new(rec.p, light_pdf.generate().normalize());
-//let pdf: f32 = light_pdf.value(&scattered.direction());
+impl<A: PDF, B: PDF> PDF for MixturePDF<A, B> {
+    fn value(&self, direction: &Vec3A) -> f32 { self.weight * self.p0.value(direction) + (1.0 - self.weight) * self.p1.value(direction) }
+    fn generate(&self) -> Vec3A { if utility::random_f32() < self.weight { self.p0.generate() } else { self.p1.generate() } }
+}
 
-// For MixturePDF:
-//let mixture_pdf: MixturePDF = MixturePDF::new(srec.pdf_ptr.unwrap(), Arc::new(light_pdf));
-//let mut scattered: Ray = Ray::new(rec.p, mixture_pdf.generate().normalize());
-//let pdf: f32 = mixture_pdf.value(&scattered.direction());
+// The pdfs of the scatter records are borrowed from the arena
+impl<P: PDF + ?Sized> PDF for &P {
+    fn value(&self, direction: &Vec3A) -> f32 { (**self).value(direction) }
+    fn generate(&self) -> Vec3A { (**self).generate() }
+}
 
-*/
\ No newline at end of file
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use crate::color::Color;
+    use crate::hittable_list::HittableList;
+    use crate::material::DiffuseLight;
+    use crate::ray::Ray;
+    use crate::sphere::Sphere;
+
+    #[test]
+    fn test_pdf_values() {
+        let cosine: CosinePDF = CosinePDF::new(&Vec3A::Y);
+        assert!((cosine.value(&Vec3A::Y) - 1.0 / utility::PI).abs() < 1e-6 && cosine.value(&-Vec3A::Y) == 0.0);
+        // Two unit spheres twice their radius away, each picked half of the times
+        let light = |center: Point3| -> Arc<dyn Hittable + Send + Sync> { Arc::new(Sphere::new(center, 1.0, Box::new(DiffuseLight::new(Color::ONE, 1.0)), 0)) };
+        let lights: HittableList = vec![light(Point3::new(0.0, 2.0, 0.0)), light(Point3::new(0.0, -2.0, 0.0))];
+        let sphere_pdf: f32 = 1.0 / (2.0 * utility::PI * (1.0 - 0.75_f32.sqrt()));
+        let lights_pdf: HittablePDF<HittableList> = HittablePDF::new(Point3::ZERO, &lights);
+        assert!((lights_pdf.value(&Vec3A::Y) - 0.5 * sphere_pdf).abs() < 1e-4 && lights_pdf.value(&Vec3A::X) == 0.0);
+        for _ in 0..8 { assert!(lights.hit(&Ray::new(Point3::ZERO, lights_pdf.generate()), 0.001, f32::INFINITY).is_some()); }
+        let mixture: MixturePDF<CosinePDF, HittablePDF<HittableList>> = MixturePDF::new(cosine, lights_pdf).with_weight(0.25);
+        assert!((mixture.value(&Vec3A::Y) - (0.25 / utility::PI + 0.75 * 0.5 * sphere_pdf)).abs() < 1e-4);
+        assert!((mixture.value(&-Vec3A::Y) - 0.75 * 0.5 * sphere_pdf).abs() < 1e-4);
+    }
+}
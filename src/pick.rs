@@ -0,0 +1,88 @@
+// Author: Giulian Biolo, github.com/giulianbiolo
+// Date: 16/10/2026
+// Description: This file implements the picking of the objects, telling what the camera sees through a pixel
+
+use glam::Vec3A;
+
+use crate::camera::Camera;
+use crate::compositing;
+use crate::hittable_list::HittableList;
+use crate::point3::Point3;
+use crate::ray::Ray;
+use crate::utility::{self, Constants};
+
+
+// The first surface seen through a pixel, with the object of the world it belongs to
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Pick {
+    pub index: usize,
+    pub material: &'static str,
+    pub distance: f32,
+    pub point: Point3,
+    pub normal: Vec3A,
+    pub front_face: bool,
+}
+
+// What the ray through the center of the lens and of the pixel (x, y) hits first, the pixels counted from the top left corner like in the image
+// A single ray without any bounce, so the objects seen through glass or in mirrors aren't picked
+pub fn pick(world: &HittableList, cam: &Camera, width: u32, height: u32, x: u32, y: u32) -> Option<Pick> {
+    let ray: Ray = Ray::new(cam.origin, compositing::pixel_direction(cam, width, height, x as f32 + 0.5, y as f32 + 0.5));
+    world.iter().enumerate()
+        .filter_map(|(index, object)| object.hit(&ray, utility::NEAR_ZERO, utility::INFINITY).map(|rec| Pick {
+            index,
+            material: rec.mat_ptr.name(),
+            distance: rec.t,
+            point: rec.p,
+            normal: rec.normal,
+            front_face: rec.front_face,
+        }))
+        .min_by(|a, b| a.distance.total_cmp(&b.distance))
+}
+
+fn _vec(v: Vec3A) -> String { format!("({:.3}, {:.3}, {:.3})", v.x, v.y, v.z) }
+
+// Prints what is seen through the pixel (x, y) of the render, the names being the ones of the objects of the world
+// A pixel outside of the image comes back as an error
+pub fn run(world: &HittableList, cam: &Camera, names: &[Option<String>], consts: &Constants, x: u32, y: u32) -> Result<(), String> {
+    if x >= consts.width || y >= consts.height { return Err(format!("The pixel ({}, {}) is outside of the {}x{} image", x, y, consts.width, consts.height)); }
+    match pick(world, cam, consts.width, consts.height, x, y) {
+        Some(picked) => {
+            let name: String = match names.get(picked.index) { Some(Some(name)) => format!(" \"{}\"", name), _ => String::new() };
+            println!(
+                "Pixel ({}, {}): object {}{}, {} at distance {:.4}, point {} normal {}{}",
+                x, y, picked.index, name, picked.material, picked.distance, _vec(picked.point), _vec(picked.normal), if picked.front_face { "" } else { " (back face)" }
+            );
+        },
+        None => println!("Pixel ({}, {}): nothing, the environment", x, y),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::color::Color;
+    use crate::material::{Lambertian, Metal};
+    use crate::sphere::Sphere;
+
+    #[test]
+    fn test_pick() {
+        // The sphere in front hides the bigger one behind it in the middle of the image, which is seen around it
+        let world: HittableList = vec![
+            Arc::new(Sphere::new(Point3::new(0.0, 0.0, -10.0), 4.0, Box::new(Lambertian::new(Color::ONE)), 0)),
+            Arc::new(Sphere::new(Point3::new(0.0, 0.0, -3.0), 0.5, Box::new(Metal::new(Color::ONE, 0.0)), 0)),
+        ];
+        let cam: Camera = Camera::new(&Vec3A::ZERO, &-Vec3A::Z, &Vec3A::Y, 60.0, 1.0, 0.0, 1.0);
+        let center: Pick = pick(&world, &cam, 65, 65, 32, 32).unwrap();
+        assert_eq!((center.index, center.material, center.front_face), (1, "Metal", true));
+        assert!((center.distance - 2.5).abs() < 1e-2 && (center.point - Point3::new(0.0, 0.0, -2.5)).length() < 0.05);
+        assert_eq!(pick(&world, &cam, 65, 65, 32, 20).map(|picked| picked.index), Some(0));
+        assert_eq!(pick(&world, &cam, 65, 65, 0, 0), None);
+        // The pixels of the command line outside of the render are reported, not picked
+        let consts: Constants = Constants::default();
+        assert!(run(&world, &cam, &[], &consts, consts.width, 0).unwrap_err().contains("outside"));
+        assert!(run(&world, &cam, &[], &consts, 0, consts.height).is_err());
+    }
+}
@@ -0,0 +1,198 @@
+// Author: Giulian Biolo, github.com/giulianbiolo
+// Date: 16/10/2026
+// Description: This file implements the pixel debugger, tracing a few paths through a single pixel and printing what happened at every bounce
+
+use std::cell::{Cell, RefCell};
+use std::sync::Arc;
+
+use glam::Vec3A;
+
+use crate::camera::Camera;
+use crate::color::Color;
+use crate::hit_record::HitRecord;
+use crate::hittable_list::{Hittable, HittableList};
+use crate::light_sampler::luminance;
+use crate::point3::Point3;
+use crate::ray::Ray;
+use crate::raytracer;
+use crate::utility::{self, Constants, load_environment, load_background, load_filter};
+
+
+// Set while a path is being traced, by the thread tracing it, so that the paths of the other threads aren't recorded
+// The renders only pay for reading the flag at every bounce
+thread_local! {
+    static TRACING: Cell<bool> = const { Cell::new(false) };
+    static TRACE: RefCell<Vec<Bounce>> = const { RefCell::new(Vec::new()) };
+}
+
+// How a bounce of the path went, in the order of the checks of the integrator
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Event {
+    // maxDepth, maxSpecularDepth and maxDiffuseDepth ending the path
+    DepthLimit,
+    SpecularLimit,
+    DiffuseLimit,
+    Light,
+    // A light seen from behind, or not linked to the surface the ray left
+    UnlinkedLight,
+    // A light seen through the caustic casters, already gathered by the manifold sampling at the last diffuse bounce
+    ManifoldLight,
+    // The material didn't scatter the ray
+    Absorbed,
+    RussianRoulette,
+    Specular,
+    Diffuse,
+    // The sampled direction has no density, the material not scattering towards the light that was sampled
+    ZeroPdf,
+    // The ray left the scene, to the environment (or to the background for the camera rays)
+    Environment,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Bounce {
+    pub depth: u32,
+    pub event: Event,
+    pub origin: Point3,
+    pub direction: Vec3A,
+    // The surface that was hit, if any
+    pub t: Option<f32>,
+    pub point: Point3,
+    pub normal: Vec3A,
+    pub front_face: bool,
+    pub material: &'static str,
+    // Light leaving the surface towards the previous vertex, the ambient light of the spherical harmonics included
+    pub emitted: Color,
+    // What the light coming back along the next ray is multiplied by, zero where the path ends
+    pub weight: Color,
+    // Density the scattered direction was drawn with and the one of the material, for the diffuse bounces
+    pub pdf: Option<f32>,
+    pub scattering_pdf: Option<f32>,
+}
+
+impl Bounce {
+    pub fn miss(depth: u32, event: Event, r: &Ray) -> Bounce {
+        Bounce {
+            depth, event, origin: r.origin(), direction: r.direction(), t: None, point: r.origin(), normal: Vec3A::ZERO, front_face: false,
+            material: "", emitted: Color::ZERO, weight: Color::ZERO, pdf: None, scattering_pdf: None,
+        }
+    }
+    pub fn hit(depth: u32, event: Event, r: &Ray, rec: &HitRecord) -> Bounce {
+        Bounce { t: Some(rec.t), point: rec.p, normal: rec.normal, front_face: rec.front_face, material: rec.mat_ptr.name(), ..Bounce::miss(depth, event, r) }
+    }
+    pub fn with_emitted(mut self, emitted: Color) -> Self { self.emitted = emitted; self }
+    pub fn with_weight(mut self, weight: Color) -> Self { self.weight = weight; self }
+    pub fn with_pdfs(mut self, pdf: f32, scattering_pdf: f32) -> Self { self.pdf = Some(pdf); self.scattering_pdf = Some(scattering_pdf); self }
+}
+
+// Adds the bounce to the trace of the path being debugged, the bounce being built only then
+#[inline(always)]
+pub fn record(bounce: impl FnOnce() -> Bounce) {
+    if TRACING.with(Cell::get) { TRACE.with(|trace| trace.borrow_mut().push(bounce())); }
+}
+
+// Traces a path of the camera ray with the constants, returning its radiance and its bounces from the camera on
+pub fn trace_path(r: &Ray, world: &HittableList, lights: &HittableList, environment_map: &Arc<dyn Hittable + Send + Sync>, background: Option<&Arc<dyn Hittable + Send + Sync>>, consts: &Constants) -> (Color, Vec<Bounce>) {
+    TRACE.with(|trace| trace.borrow_mut().clear());
+    TRACING.with(|tracing| tracing.set(true));
+    let mut path: Vec<Vec3A> = vec![r.origin()];
+    let radiance: Color = raytracer::ray_color(r, world, lights, environment_map, background, consts, 0, &mut path);
+    TRACING.with(|tracing| tracing.set(false));
+    (radiance, TRACE.with(|trace| trace.take()))
+}
+
+// The throughput of the path before each bounce, and the radiance the bounces add up to
+pub fn throughputs(bounces: &[Bounce]) -> (Vec<Color>, Color) {
+    let (mut throughput, mut radiance) = (Color::ONE, Color::ZERO);
+    let throughputs: Vec<Color> = bounces.iter().map(|bounce| {
+        let before: Color = throughput;
+        radiance += throughput * bounce.emitted;
+        throughput *= bounce.weight;
+        before
+    }).collect();
+    (throughputs, radiance)
+}
+
+/****************** Debug pixel mode ******************/
+// The object that was hit at the bounce, by its index in the world
+pub fn object_index(world: &HittableList, bounce: &Bounce) -> Option<usize> {
+    let t: f32 = bounce.t?;
+    let ray: Ray = Ray::new(bounce.origin, bounce.direction);
+    world.iter().position(|object| object.hit(&ray, utility::NEAR_ZERO, utility::INFINITY).is_some_and(|rec| (rec.t - t).abs() <= 1e-4 * t.max(1.0)))
+}
+
+fn _vec(v: Vec3A) -> String { format!("({:.3}, {:.3}, {:.3})", v.x, v.y, v.z) }
+
+// Traces the paths through the pixel (x, y) and prints them, the names being the ones of the objects of the world
+// A pixel outside of the image comes back as an error
+pub fn run(world: &HittableList, cam: &Camera, names: &[Option<String>], consts: &Constants, x: u32, y: u32, paths: u32) -> Result<(), String> {
+    if x >= consts.width || y >= consts.height { return Err(format!("The pixel ({}, {}) is outside of the {}x{} image", x, y, consts.width, consts.height)); }
+    let (environment_map, env_dist): (Arc<dyn Hittable + Send + Sync>, f32) = load_environment(world, consts);
+    let background: Option<Arc<dyn Hittable + Send + Sync>> = load_background(env_dist, consts);
+    let mut lights: HittableList = raytracer::get_lights(world);
+    if utility::environment_is_light(consts) { lights.push(environment_map.clone()); }
+    let lights: HittableList = raytracer::sample_lights(world, lights);
+    let filter = load_filter();
+    utility::seed_stream(*utility::RENDER_SEED, (y * consts.width + x) as u64);
+    println!("Pixel ({}, {}), {} paths", x, y, paths);
+    let mut radiances: Vec<Color> = Vec::new();
+    for idx in 0..paths {
+        let r: Ray = raytracer::camera_ray(filter.as_ref(), cam, consts, x as usize, y as usize);
+        let (radiance, bounces) = trace_path(&r, world, &lights, &environment_map, background.as_ref(), consts);
+        let (throughputs, _) = throughputs(&bounces);
+        println!("Path {}: radiance {}, {} bounces", idx, _vec(radiance), bounces.len());
+        for (bounce, throughput) in bounces.iter().zip(throughputs) {
+            print!("  {:>2} {:<14}", bounce.depth, format!("{:?}", bounce.event));
+            match object_index(world, bounce) {
+                Some(index) => {
+                    if let Some(Some(name)) = names.get(index) { print!(" object {} \"{}\"", index, name); } else { print!(" object {}", index); }
+                    print!(" {} at {} normal {}{}", bounce.material, _vec(bounce.point), _vec(bounce.normal), if bounce.front_face { "" } else { " (back face)" });
+                },
+                // The environment sphere and the sky aren't objects of the world
+                None => print!(" environment towards {}", _vec(bounce.direction.normalize())),
+            }
+            if let (Some(pdf), Some(scattering_pdf)) = (bounce.pdf, bounce.scattering_pdf) { print!(" pdf {:.4} scattering pdf {:.4}", pdf, scattering_pdf); }
+            println!(" emitted {} weight {} throughput {}", _vec(bounce.emitted), _vec(bounce.weight), _vec(throughput));
+        }
+        radiances.push(radiance);
+    }
+    let mean: Color = radiances.iter().sum::<Color>() / paths.max(1) as f32;
+    let (brightest, max) = radiances.iter().enumerate().fold((0, 0.0_f32), |(brightest, max), (idx, radiance)| if luminance(*radiance) > max { (idx, luminance(*radiance)) } else { (brightest, max) });
+    let black: usize = radiances.iter().filter(|radiance| luminance(**radiance) <= 0.0).count();
+    let invalid: usize = radiances.iter().filter(|radiance| !radiance.is_finite()).count();
+    println!("Mean radiance {}, brightest path {} (luminance {:.3}), {} black and {} NaN or infinite paths", _vec(mean), brightest, max, black, invalid);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::{DiffuseLight, Lambertian};
+    use crate::sphere::Sphere;
+
+    #[test]
+    fn test_trace_path() {
+        // A camera ray hitting a gray ball under a light, the bounces adding up to the radiance of the path
+        let world: HittableList = vec![
+            Arc::new(Sphere::new(Point3::new(0.0, 0.0, -3.0), 1.0, Box::new(Lambertian::new(Color::splat(0.5))), 0)),
+            Arc::new(Sphere::new(Point3::new(0.0, 4.0, -3.0), 1.0, Box::new(DiffuseLight::new(Color::ONE, 4.0)), 0)),
+        ];
+        let lights: HittableList = raytracer::get_lights(&world);
+        let environment: Arc<dyn Hittable + Send + Sync> = Arc::new(Sphere::new(Point3::ZERO, 100.0, Box::new(DiffuseLight::new(Color::ZERO, 0.0)), 0));
+        for _ in 0..16 {
+            let (radiance, bounces) = trace_path(&Ray::new(Point3::ZERO, -Vec3A::Z), &world, &lights, &environment, None, &Constants::default());
+            assert_eq!((bounces[0].depth, bounces[0].material, bounces[0].t.map(|t| (t - 2.0).abs() < 1e-4)), (0, "Lambertian", Some(true)));
+            assert!(bounces.windows(2).all(|pair| pair[1].depth == pair[0].depth + 1));
+            let (throughputs, total) = throughputs(&bounces);
+            assert_eq!(throughputs[0], Color::ONE);
+            assert!((total - radiance).length() <= 1e-4 * radiance.length().max(1.0));
+        }
+        assert!(!TRACING.with(Cell::get));
+    }
+    #[test]
+    fn test_run_outside() {
+        let cam: Camera = Camera::new(&Vec3A::ZERO, &-Vec3A::Z, &Vec3A::Y, 60.0, 1.0, 0.0, 1.0);
+        let consts: Constants = Constants::default();
+        assert!(run(&HittableList::new(), &cam, &[], &consts, consts.width, 0, 1).unwrap_err().contains("outside"));
+        assert!(run(&HittableList::new(), &cam, &[], &consts, 0, consts.height, 1).is_err());
+    }
+}
@@ -0,0 +1,141 @@
+// Author: Giulian Biolo, github.com/giulianbiolo
+// Date: 16/10/2026
+// Description: This file implements the source placement search, moving the emitters of the power grid scene to every candidate position and ranking them by the worst coverage over a target region
+
+use std::io::Write;
+use std::sync::Arc;
+
+use glam::Affine3A;
+use yaml_rust::Yaml;
+
+use crate::hittable_list::{Hittable, HittableList};
+use crate::link_budget::LinkBudget;
+use crate::parallel::*;
+use crate::parser;
+use crate::point3::Point3;
+use crate::raytracer;
+use crate::transform::Transformed;
+use crate::utility;
+use crate::visibility;
+
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Placement {
+    // Where the strongest source goes, the others keeping their offsets from it
+    pub position: Point3,
+    // The power of the worst and of the average point of the region
+    pub minimum: f32,
+    pub mean: f32,
+}
+
+// The points of the box spaced by the step along each axis, both corners included
+pub fn grid_points(min: Point3, max: Point3, step: f32) -> Vec<Point3> {
+    if step <= 0.0 { panic!("The step of the points must be positive, got {}", step); }
+    let counts: [usize; 3] = [0, 1, 2].map(|axis| ((max[axis] - min[axis]) / step + 1e-3).floor().max(0.0) as usize + 1);
+    (0..counts[2]).flat_map(|k| (0..counts[1]).flat_map(move |j| (0..counts[0]).map(move |i| (i, j, k))))
+        .map(|(i, j, k)| min + Point3::new(i as f32, j as f32, k as f32) * step)
+        .collect()
+}
+
+// Either a list of points, [[x, y, z], ..], or a box of them: { min: [x, y, z], max: [x, y, z], step: 0.5 }
+pub fn parse_points(block: &Yaml, key: &str) -> Vec<Point3> {
+    if block[key].as_vec().is_some() { return visibility::parse_points(block, key); }
+    let value = |name: &str| -> Vec<f32> {
+        let value: &Yaml = &block[key][name];
+        let values: Vec<Yaml> = value.as_vec().cloned().unwrap_or_else(|| vec![value.clone()]);
+        values.iter().map(|value| value.as_f64().or(value.as_i64().map(|value| value as f64)).unwrap_or_else(|| panic!("Missing the {} of the {} points", name, key)) as f32).collect()
+    };
+    let (min, max) = (value("min"), value("max"));
+    grid_points(Point3::new(min[0], min[1], min[2]), Point3::new(max[0], max[1], max[2]), value("step")[0])
+}
+
+// The world with its sources moved by the offset, the rest of the scene staying where it is
+pub fn moved_sources(world: &HittableList, offset: Point3) -> HittableList {
+    world.iter().map(|object| -> Arc<dyn Hittable + Send + Sync> {
+        if object.is_light() { Arc::new(Transformed::new(object.clone(), Affine3A::from_translation(offset.into()))) } else { object.clone() }
+    }).collect()
+}
+
+// The best placement first: the highest worst case, the highest average breaking the ties
+pub fn rank(placements: &mut [Placement]) {
+    placements.sort_by(|a, b| b.minimum.total_cmp(&a.minimum).then(b.mean.total_cmp(&a.mean)));
+}
+
+// The power grid of the region with the sources moved to the candidate
+pub fn evaluate(world: &HittableList, environment_map: &Arc<dyn Hittable + Send + Sync>, anchor: Point3, candidate: Point3, region: &[Point3]) -> Placement {
+    let world: HittableList = moved_sources(world, candidate - anchor);
+    let mut lights: HittableList = raytracer::get_lights(&world);
+    if utility::environment_is_light(&utility::CONSTS) { lights.push(environment_map.clone()); }
+    let lights: HittableList = raytracer::sample_lights(&world, lights);
+    let powers: Vec<f32> = region.into_par_iter().map(|point| raytracer::power_at(point, &world, &lights, environment_map)).collect();
+    Placement {
+        position: candidate,
+        minimum: powers.iter().copied().fold(f32::INFINITY, f32::min),
+        mean: powers.iter().sum::<f32>() / powers.len().max(1) as f32,
+    }
+}
+
+// The candidates and the target region come from the placement block of the scene:
+// placement: { candidates: [[x, y, z], ..] or { min, max, step }, region: [[x, y, z], ..] or { min, max, step }, top: 10 }
+// The powers are in dBm with a link budget, otherwise in dB below the best covered point of all the candidates
+pub fn run(world: &HittableList, config_file: &str) {
+    let doc: Yaml = parser::load_config(config_file).unwrap_or_else(|| panic!("Failed to load the config file {}", config_file));
+    let block: &Yaml = &doc["placement"];
+    if block.is_badvalue() { panic!("The config file {} has no placement block", config_file); }
+    let (candidates, region) = (parse_points(block, "candidates"), parse_points(block, "region"));
+    let top: usize = block["top"].as_i64().unwrap_or(10) as usize;
+    let sources: HittableList = raytracer::get_lights(world);
+    let anchor: Point3 = sources.iter().filter_map(|light| light.light_estimate()).max_by(|a, b| a.power.total_cmp(&b.power))
+        .expect("The placement search needs a source to move around").center;
+    println!("Placing {} sources at {} candidates, covering {} points", sources.len(), candidates.len(), region.len());
+    let (environment_map, _): (Arc<dyn Hittable + Send + Sync>, f32) = utility::load_environment(world, &utility::CONSTS);
+    let mut placements: Vec<Placement> = candidates.iter().enumerate().map(|(i, candidate)| {
+        let placement: Placement = evaluate(world, &environment_map, anchor, *candidate, &region);
+        print!("{:.2}% complete\r", (i + 1) as f32 / candidates.len() as f32 * 100.0);
+        let _ = std::io::stdout().flush();
+        placement
+    }).collect();
+    rank(&mut placements);
+    let calibration_offset: Option<f32> = raytracer::power_calibration(world, &environment_map);
+    let max: f32 = placements.iter().map(|placement| placement.mean.max(placement.minimum)).fold(0.0, f32::max);
+    let (unit, to_db): (&str, Box<dyn Fn(f32) -> f32>) = match calibration_offset {
+        Some(offset) => ("dBm", Box::new(move |power| LinkBudget::to_dbm(power, offset))),
+        None => ("dB", Box::new(move |power| 10.0 * (power / max).log10())),
+    };
+    println!("Best {} of {} placements, by the worst covered point of the region:", top.min(placements.len()), placements.len());
+    for (i, placement) in placements.iter().take(top).enumerate() {
+        let p: Point3 = placement.position;
+        println!("  {:>3}. [{:.2}, {:.2}, {:.2}]  min {:>7.1} {}  mean {:>7.1} {}", i + 1, p.x, p.y, p.z, to_db(placement.minimum), unit, to_db(placement.mean), unit);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::Color;
+    use crate::material::{DiffuseLight, Lambertian};
+    use crate::sphere::Sphere;
+
+    #[test]
+    fn test_placement() {
+        let points: Vec<Point3> = grid_points(Point3::new(0.0, 1.0, 0.0), Point3::new(1.0, 1.0, 2.0), 0.5);
+        assert_eq!(points.len(), 3 * 5);
+        assert_eq!((points[0], points[14]), (Point3::new(0.0, 1.0, 0.0), Point3::new(1.0, 1.0, 2.0)));
+        let block: Yaml = yaml_rust::YamlLoader::load_from_str("{candidates: [[0, 1, 0], [2.5, 1, 0]], region: {min: [0, 0, 0], max: [1, 0, 1], step: 1}}").unwrap().remove(0);
+        assert_eq!(parse_points(&block, "candidates"), vec![Point3::new(0.0, 1.0, 0.0), Point3::new(2.5, 1.0, 0.0)]);
+        assert_eq!(parse_points(&block, "region").len(), 4);
+        // Only the sources move
+        let world: HittableList = vec![
+            Arc::new(Sphere::new(Point3::ZERO, 0.5, Box::new(DiffuseLight::new(Color::ONE, 1.0)), 0)),
+            Arc::new(Sphere::new(Point3::X * 3.0, 1.0, Box::new(Lambertian::new(Color::splat(0.5))), 0)),
+        ];
+        let moved: HittableList = moved_sources(&world, Point3::Y * 2.0);
+        assert_eq!(moved[0].light_estimate().map(|estimate| estimate.center), Some(Point3::Y * 2.0));
+        assert!(moved[1].hit(&crate::ray::Ray::new(Point3::new(3.0, 5.0, 0.0), Point3::NEG_Y), 0.0, 10.0).is_some());
+        // The worst case ranks first, the average only breaking the ties
+        let placement = |x: f32, minimum: f32, mean: f32| Placement { position: Point3::X * x, minimum, mean };
+        let mut placements: Vec<Placement> = vec![placement(0.0, 0.1, 5.0), placement(1.0, 0.3, 1.0), placement(2.0, 0.3, 2.0)];
+        rank(&mut placements);
+        assert_eq!(placements.iter().map(|placement| placement.position.x).collect::<Vec<f32>>(), vec![2.0, 1.0, 0.0]);
+    }
+}
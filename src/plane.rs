@@ -0,0 +1,128 @@
+// Author: Giulian Biolo, github.com/giulianbiolo
+// Date: 16/10/2026
+// Description: This file implements the Plane struct, an infinite plane through a point, and its built-in look-dev patterns
+
+use glam::{Vec2, Vec3A};
+
+use crate::color::Color;
+use crate::ray::Ray;
+use crate::hit_record::HitRecord;
+use crate::hittable_list::Hittable;
+use crate::material::{Material, Lambertian};
+use crate::point3::Point3;
+use crate::rectangle::UVTransform;
+use crate::texture::{Grid, SolidColor, UVChecker};
+
+
+pub struct Plane {
+    point: Point3,
+    normal: Vec3A,
+    // Directions of the u and v texture coordinates on the plane, which are measured in scene units from the point
+    tangent: Vec3A,
+    bitangent: Vec3A,
+    uv_transform: UVTransform,
+    material: Box<dyn Material>,
+}
+
+unsafe impl Sync for Plane {}
+unsafe impl Send for Plane {}
+
+impl Plane {
+    pub fn new(point: Point3, normal: Vec3A, material: Box<dyn Material>) -> Plane {
+        let normal: Vec3A = normal.normalize();
+        // The u follows X and the v follows Z on a ground plane, like on an XZRectangle, and Y on a wall
+        let tangent: Vec3A = if normal.y.abs() > 0.999 { Vec3A::X } else { normal.cross(Vec3A::Y).normalize() };
+        let bitangent: Vec3A = tangent.cross(normal);
+        Plane { point, normal, tangent, bitangent, uv_transform: UVTransform::default(), material }
+    }
+    pub fn with_uv_transform(mut self, uv_transform: UVTransform) -> Plane {
+        self.uv_transform = uv_transform;
+        self
+    }
+    fn _get_plane_uv(&self, p: &Point3) -> (f32, f32) {
+        let local: Vec3A = *p - self.point;
+        self.uv_transform.apply(local.dot(self.tangent), local.dot(self.bitangent))
+    }
+    fn _intersect(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<(f32, f32, f32)> {
+        let denominator: f32 = ray.direction().dot(self.normal);
+        if denominator.abs() < 1e-8 { return None; } // The ray runs parallel to the plane
+        let t: f32 = (self.point - ray.origin()).dot(self.normal) / denominator;
+        if t < t_min || t > t_max { return None; }
+        let (u, v) = self._get_plane_uv(&ray.at(t));
+        if !self.material.alpha_test(u, v, &ray.at(t)) { return None; }
+        Some((u, v, t))
+    }
+}
+
+impl Hittable for Plane {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        let (u, v, t) = self._intersect(ray, t_min, t_max)?;
+        let mut rec: HitRecord = HitRecord::new(ray.at(t), self.normal, self.material.as_ref(), t, u, v, false);
+        rec.set_face_normal(ray, &self.normal);
+        (rec.dpdu, rec.dpdv) = self.uv_transform.derivatives(self.tangent, self.bitangent);
+        Some(rec)
+    }
+    fn occluded(&self, ray: &Ray, t_min: f32, t_max: f32) -> bool { self._intersect(ray, t_min, t_max).is_some() }
+    fn is_light(&self) -> bool { self.material.is_light() }
+}
+
+/****************** PlanePattern ******************/
+// Built-in shading for quick look-dev setups, with cells of a given size in scene units
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PlanePattern {
+    // Lines of the second color, as wide as the given fraction of a cell, over the first one
+    Grid { line_width: f32 },
+    Checker,
+}
+
+impl PlanePattern {
+    pub fn from_name(name: &str, line_width: f32) -> Result<PlanePattern, String> {
+        match name {
+            "grid" => Ok(PlanePattern::Grid { line_width }),
+            "checker" => Ok(PlanePattern::Checker),
+            _ => Err(format!("Unknown plane pattern: {}, expected grid or checker", name)),
+        }
+    }
+    pub fn material(&self, cell_size: f32, colors: [Color; 2]) -> Box<dyn Material + Send + Sync> {
+        let scale: Vec2 = Vec2::splat(1.0 / cell_size);
+        let (first, second) = (Box::new(SolidColor::new(colors[0])), Box::new(SolidColor::new(colors[1])));
+        match self {
+            PlanePattern::Grid { line_width } => Box::new(Lambertian::new_texture(Box::new(Grid::new(second, first, scale, *line_width)))),
+            PlanePattern::Checker => Box::new(Lambertian::new_texture(Box::new(UVChecker::new(second, first, scale)))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::ScatterRecord;
+    use crate::utility;
+
+    #[test]
+    fn test_plane_hit() {
+        let plane: Plane = Plane::new(Point3::new(0.0, -1.0, 0.0), Vec3A::Y, Box::new(Lambertian::new(Color::ONE)));
+        let rec: HitRecord = plane.hit(&Ray::new(Point3::new(2.0, 1.0, 3.0), Vec3A::new(0.0, -1.0, 1.0)), 0.001, f32::INFINITY).unwrap();
+        assert!(rec.front_face && (rec.t - 2.0).abs() < utility::EPSILON);
+        // The texture coordinates are the distances from the point along X and Z
+        assert!((rec.u - 2.0).abs() < utility::EPSILON && (rec.v - 5.0).abs() < utility::EPSILON);
+        // The plane is hit from below too, and never by the rays parallel to it
+        assert!(!plane.hit(&Ray::new(Point3::new(0.0, -3.0, 0.0), Vec3A::Y), 0.001, f32::INFINITY).unwrap().front_face);
+        assert!(plane.hit(&Ray::new(Point3::ZERO, Vec3A::X), 0.001, f32::INFINITY).is_none());
+    }
+    #[test]
+    fn test_plane_pattern() {
+        let colors: [Color; 2] = [Color::ONE, Color::ZERO];
+        let plane: Plane = Plane::new(Point3::ZERO, Vec3A::Y, PlanePattern::Checker.material(0.5, colors));
+        let albedo = |x: f32, z: f32| -> Color {
+            let rec: HitRecord = plane.hit(&Ray::new(Point3::new(x, 1.0, z), -Vec3A::Y), 0.001, f32::INFINITY).unwrap();
+            let arena: bumpalo::Bump = bumpalo::Bump::new();
+            let mut srec: ScatterRecord = ScatterRecord::new(&arena);
+            rec.mat_ptr.scatter(&Ray::new(Point3::new(x, 1.0, z), -Vec3A::Y), &rec, &mut srec);
+            srec.attenuation
+        };
+        // Half unit cells alternate along both axes
+        assert_eq!(albedo(0.25, 0.25), albedo(0.75, 0.75));
+        assert_ne!(albedo(0.25, 0.25), albedo(0.75, 0.25));
+    }
+}
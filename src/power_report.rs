@@ -0,0 +1,216 @@
+// Author: Giulian Biolo, github.com/giulianbiolo
+// Date: 16/10/2026
+// Description: This file implements the coverage report of the power renders, the statistics of the power grids and their worst covered regions saved as JSON and CSV
+
+use std::fmt::Write;
+
+use serde::Serialize;
+use yaml_rust::Yaml;
+
+use crate::point3::Point3;
+
+
+// The decibels of a power grid, a box of points spaced by the step, x first, then y, then z
+#[derive(Debug, Clone, PartialEq)]
+pub struct Volume {
+    pub name: String,
+    // The position of the first point, the lowest corner
+    pub origin: Point3,
+    pub step: f32,
+    pub dims: [usize; 3],
+    pub values: Vec<f32>,
+}
+
+impl Volume {
+    pub fn position(&self, index: usize) -> Point3 {
+        let (x, y, z) = (index % self.dims[0], index / self.dims[0] % self.dims[1], index / (self.dims[0] * self.dims[1]));
+        self.origin + Point3::new(x as f32, y as f32, z as f32) * self.step
+    }
+    // The points next to the point along the axes, inside of the volume
+    fn _neighbours(&self, index: usize) -> impl Iterator<Item = usize> + '_ {
+        let coordinates: [usize; 3] = [index % self.dims[0], index / self.dims[0] % self.dims[1], index / (self.dims[0] * self.dims[1])];
+        let strides: [usize; 3] = [1, self.dims[0], self.dims[0] * self.dims[1]];
+        (0..3).flat_map(move |axis| {
+            let below: Option<usize> = if coordinates[axis] > 0 { Some(index - strides[axis]) } else { None };
+            let above: Option<usize> = if coordinates[axis] + 1 < self.dims[axis] { Some(index + strides[axis]) } else { None };
+            below.into_iter().chain(above)
+        })
+    }
+}
+
+// What goes in the report, from the powerGrid block of the scene:
+// powerGrid: { thresholds: [-65.0, -75.0], percentiles: [5, 50, 95], worstRegions: 5 }
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReportOptions {
+    pub thresholds: Vec<f32>,
+    pub percentiles: Vec<f32>,
+    pub worst_regions: usize,
+}
+
+impl Default for ReportOptions {
+    fn default() -> Self { ReportOptions { thresholds: Vec::new(), percentiles: vec![5.0, 10.0, 25.0, 50.0, 75.0, 90.0, 95.0], worst_regions: 5 } }
+}
+
+fn _parse_f32(value: &Yaml) -> Option<f32> { value.as_f64().or(value.as_i64().map(|value| value as f64)).map(|value| value as f32) }
+
+impl ReportOptions {
+    // The single threshold of the floors counts as one of the thresholds
+    pub fn parse(doc: &Yaml) -> ReportOptions {
+        let block: &Yaml = &doc["powerGrid"];
+        let list = |key: &str| -> Option<Vec<f32>> { block[key].as_vec().map(|values| values.iter().filter_map(_parse_f32).collect()) };
+        let mut options: ReportOptions = ReportOptions::default();
+        if let Some(thresholds) = list("thresholds") { options.thresholds = thresholds; }
+        if let Some(threshold) = _parse_f32(&block["threshold"]) { if !options.thresholds.contains(&threshold) { options.thresholds.push(threshold); } }
+        if let Some(percentiles) = list("percentiles") { options.percentiles = percentiles; }
+        if let Some(worst_regions) = block["worstRegions"].as_i64() { options.worst_regions = worst_regions as usize; }
+        options
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct Percentile {
+    pub percent: f32,
+    pub value: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct ThresholdCoverage {
+    pub threshold: f32,
+    // The percentage of the points of the volume at or above the threshold
+    pub percent: f32,
+}
+
+// Connected points below the worst threshold (or the 10th percentile without thresholds), the dead zones of the coverage
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct Region {
+    pub center: [f32; 3],
+    pub min: [f32; 3],
+    pub max: [f32; 3],
+    pub points: usize,
+    pub worst: f32,
+}
+
+// The non-finite values, the points without any power, are saved as null in the JSON
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Report {
+    pub name: String,
+    pub unit: String,
+    pub points: usize,
+    pub minimum: f32,
+    pub maximum: f32,
+    // The mean of the powers, not of their decibels
+    pub mean: f32,
+    pub median: f32,
+    pub percentiles: Vec<Percentile>,
+    pub coverage: Vec<ThresholdCoverage>,
+    pub worst_regions: Vec<Region>,
+}
+
+// The nearest rank percentile of the sorted values
+fn _percentile(sorted: &[f32], percent: f32) -> f32 {
+    if sorted.is_empty() { return f32::NEG_INFINITY; }
+    sorted[(percent.clamp(0.0, 100.0) / 100.0 * (sorted.len() - 1) as f32).round() as usize]
+}
+
+pub fn worst_regions(volume: &Volume, cutoff: f32, count: usize) -> Vec<Region> {
+    let mut visited: Vec<bool> = vec![false; volume.values.len()];
+    let mut regions: Vec<Region> = Vec::new();
+    for start in 0..volume.values.len() {
+        if visited[start] || volume.values[start] >= cutoff { continue; }
+        visited[start] = true;
+        let (mut stack, mut members) = (vec![start], Vec::new());
+        while let Some(index) = stack.pop() {
+            members.push(index);
+            for neighbour in volume._neighbours(index) {
+                if !visited[neighbour] && volume.values[neighbour] < cutoff { visited[neighbour] = true; stack.push(neighbour); }
+            }
+        }
+        let positions: Vec<Point3> = members.iter().map(|index| volume.position(*index)).collect();
+        let center: Point3 = positions.iter().fold(Point3::ZERO, |sum, position| sum + *position) / positions.len() as f32;
+        regions.push(Region {
+            center: center.to_array(),
+            min: positions.iter().fold(Point3::splat(f32::INFINITY), |min, position| min.min(*position)).to_array(),
+            max: positions.iter().fold(Point3::splat(f32::NEG_INFINITY), |max, position| max.max(*position)).to_array(),
+            points: members.len(),
+            worst: members.iter().map(|index| volume.values[*index]).fold(f32::INFINITY, f32::min),
+        });
+    }
+    // The weakest first, the largest among the equally weak ones
+    regions.sort_by(|a, b| a.worst.total_cmp(&b.worst).then(b.points.cmp(&a.points)));
+    regions.truncate(count);
+    regions
+}
+
+pub fn report(volume: &Volume, unit: &str, options: &ReportOptions) -> Report {
+    let mut sorted: Vec<f32> = volume.values.clone();
+    sorted.sort_by(f32::total_cmp);
+    let points: usize = sorted.len();
+    let mean: f32 = sorted.iter().map(|value| 10.0_f32.powf(value / 10.0)).sum::<f32>() / points.max(1) as f32;
+    // With thresholds the worst regions are the points not covered by any of them
+    let cutoff: f32 = if options.thresholds.is_empty() { _percentile(&sorted, 10.0) } else { options.thresholds.iter().copied().fold(f32::INFINITY, f32::min) };
+    Report {
+        name: volume.name.clone(),
+        unit: unit.to_string(),
+        points,
+        minimum: sorted.first().copied().unwrap_or(f32::NEG_INFINITY),
+        maximum: sorted.last().copied().unwrap_or(f32::NEG_INFINITY),
+        mean: 10.0 * mean.log10(),
+        median: _percentile(&sorted, 50.0),
+        percentiles: options.percentiles.iter().map(|percent| Percentile { percent: *percent, value: _percentile(&sorted, *percent) }).collect(),
+        coverage: options.thresholds.iter().map(|threshold| ThresholdCoverage {
+            threshold: *threshold,
+            percent: 100.0 * (points - sorted.partition_point(|value| value < threshold)) as f32 / points.max(1) as f32,
+        }).collect(),
+        worst_regions: worst_regions(volume, cutoff, options.worst_regions),
+    }
+}
+
+// One statistic per row, the worst regions with the coordinates of their center
+pub fn to_csv(reports: &[Report]) -> String {
+    let mut csv: String = "volume,statistic,value,unit,x,y,z\n".to_string();
+    for report in reports {
+        let mut row = |statistic: String, value: f32, center: Option<[f32; 3]>| {
+            let center: String = center.map_or(",,".to_string(), |center| format!("{},{},{}", center[0], center[1], center[2]));
+            writeln!(csv, "{},{},{},{},{}", report.name, statistic, value, report.unit, center).unwrap();
+        };
+        row("minimum".to_string(), report.minimum, None);
+        row("maximum".to_string(), report.maximum, None);
+        row("mean".to_string(), report.mean, None);
+        row("median".to_string(), report.median, None);
+        for percentile in &report.percentiles { row(format!("p{}", percentile.percent), percentile.value, None); }
+        for coverage in &report.coverage { row(format!("% above {}", coverage.threshold), coverage.percent, None); }
+        for (i, region) in report.worst_regions.iter().enumerate() { row(format!("worst region {} ({} points)", i + 1, region.points), region.worst, Some(region.center)); }
+    }
+    csv
+}
+
+// Saves <stem>_report.json and <stem>_report.csv next to the volumes
+pub fn save(reports: &[Report], stem: &str) {
+    let (json_file, csv_file) = (format!("{}_report.json", stem), format!("{}_report.csv", stem));
+    std::fs::write(&json_file, serde_json::to_string_pretty(reports).unwrap()).unwrap_or_else(|err| panic!("Failed to write {}: {}", json_file, err));
+    std::fs::write(&csv_file, to_csv(reports)).unwrap_or_else(|err| panic!("Failed to write {}: {}", csv_file, err));
+    println!("Saved the coverage report to {} and {}", json_file, csv_file);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_report() {
+        // A 4x1x2 slice with a dead corner of two points and a lone weak point
+        let volume: Volume = Volume { name: "ground".to_string(), origin: Point3::new(0.0, 1.0, 0.0), step: 0.5, dims: [4, 1, 2], values: vec![-90.0, -50.0, -55.0, -72.0, f32::NEG_INFINITY, -60.0, -58.0, -52.0] };
+        assert_eq!(volume.position(5), Point3::new(0.5, 1.0, 0.5));
+        let options: ReportOptions = ReportOptions { thresholds: vec![-70.0, -56.0], percentiles: vec![0.0, 50.0, 100.0], worst_regions: 5 };
+        let report: Report = report(&volume, "dBm", &options);
+        assert_eq!((report.points, report.minimum, report.maximum, report.median), (8, f32::NEG_INFINITY, -50.0, -58.0));
+        assert_eq!(report.percentiles.iter().map(|percentile| percentile.value).collect::<Vec<f32>>(), vec![f32::NEG_INFINITY, -58.0, -50.0]);
+        assert_eq!(report.coverage, vec![ThresholdCoverage { threshold: -70.0, percent: 62.5 }, ThresholdCoverage { threshold: -56.0, percent: 37.5 }]);
+        assert_eq!(report.worst_regions.iter().map(|region| (region.points, region.worst, region.center)).collect::<Vec<_>>(), vec![(2, f32::NEG_INFINITY, [0.0, 1.0, 0.25]), (1, -72.0, [1.5, 1.0, 0.0])]);
+        let csv: String = to_csv(&[report]);
+        assert!(csv.contains("ground,% above -70,62.5,dBm,,,\n") && csv.contains("ground,worst region 2 (1 points),-72,dBm,1.5,1,0\n"));
+        let doc: Yaml = yaml_rust::YamlLoader::load_from_str("{powerGrid: {threshold: -60, thresholds: [-65.0, -75], worstRegions: 2}}").unwrap().remove(0);
+        assert_eq!(ReportOptions::parse(&doc), ReportOptions { thresholds: vec![-65.0, -75.0, -60.0], worst_regions: 2, ..ReportOptions::default() });
+    }
+}
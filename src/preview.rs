@@ -0,0 +1,208 @@
+// Author: Giulian Biolo, github.com/giulianbiolo
+// Date: 16/10/2026
+// Description: This file implements the progressive preview with hot-reload of the scene file
+
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use yaml_rust::Yaml;
+
+use crate::camera::Camera;
+use crate::color::Color;
+use crate::frustum;
+use crate::hittable_list::{Hittable, HittableList};
+use crate::light_linking::{self, LightLink};
+use crate::mesh::Mesh;
+use crate::parser;
+use crate::priority::PrioritizedRender;
+use crate::raytracer;
+use crate::sampling_filters::Filter;
+use crate::utility::{self, CONSTS, load_environment, load_background, load_filter};
+
+
+const PREVIEW_FILENAME: &str = "preview.png";
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+// A world object together with the yaml it was built from, so that unchanged objects can be reused on reload
+struct WatchedObject {
+    yaml: Yaml,
+    // The object, or the transformed leaves of a group, with the yaml of each for its light linking
+    objects: Vec<(Arc<dyn Hittable + Send + Sync>, Yaml)>,
+    // Meshes are kept around to swap their material without reloading the model file
+    mesh: Option<Mesh>,
+}
+
+#[derive(Debug, PartialEq)]
+enum ObjectChange {
+    Unchanged(usize),
+    MaterialOnly(usize),
+    Rebuilt,
+}
+
+struct ReloadSummary {
+    camera: bool,
+    constants: bool,
+    rebuilt: usize,
+    materials: usize,
+}
+
+// Renders the scene progressively to preview.png, reloading it every time the config file changes
+pub fn watch_scene(config_file: &str) {
+    let filter: Box<dyn Filter + Send + Sync> = load_filter();
+    let mut last_modified: Option<SystemTime> = _modified_time(config_file);
+    let mut doc: Yaml = parser::load_config(config_file).expect("Failed to parse the config file");
+    let mut cam: Camera = parser::parse_camera(&doc);
+    let mut objects: Vec<WatchedObject> = _world_yaml(&doc).iter().map(|hashobj| _build_object(hashobj, None)).collect();
+    // The environment is sized for the scene as it is at the start, and the reloads warn about the objects moved out of it
+    let first_world: HittableList = objects.iter().flat_map(|obj| obj.objects.iter().map(|(object, _)| object.clone())).collect();
+    let (environment_map, env_dist): (Arc<dyn Hittable + Send + Sync>, f32) = load_environment(&first_world, &CONSTS);
+    let mut background: Option<Arc<dyn Hittable + Send + Sync>> = load_background(env_dist, &CONSTS);
+    let (mut world, mut lights) = _collect_world(&objects, &environment_map);
+    let mut accumulator: Vec<Color> = vec![Color::ZERO; (CONSTS.width * CONSTS.height) as usize];
+    let mut passes: u32 = 0;
+    // With a priority, the important tiles get rendered first and converge before the rest, the preview going on without it if its mask can't be read
+    let mut prioritized: Option<PrioritizedRender> = CONSTS.priority.as_ref().and_then(|priority| match PrioritizedRender::new(priority, CONSTS.width, CONSTS.height) {
+        Ok(prioritized) => Some(prioritized),
+        Err(err) => { println!("{}, previewing without the priority", err); None },
+    });
+    println!("Watching {} for changes, the preview is written to {}", config_file, PREVIEW_FILENAME);
+    loop {
+        let modified: Option<SystemTime> = _modified_time(config_file);
+        if modified != last_modified {
+            last_modified = modified;
+            let reloaded = panic::catch_unwind(AssertUnwindSafe(|| _reload(config_file, &doc, objects.as_slice())));
+            match reloaded {
+                Ok(Some((new_doc, new_cam, new_objects, summary))) => {
+                    doc = new_doc;
+                    cam = new_cam.unwrap_or(cam);
+                    objects = new_objects;
+                    (world, lights) = _collect_world(&objects, &environment_map);
+                    utility::check_environment(&world, env_dist);
+                    // The background image is read again, so that its edits show up as the scene's do
+                    background = load_background(env_dist, &CONSTS);
+                    if summary.constants { println!("Constants changed, restart the preview to apply them"); }
+                    println!(
+                        "Scene reloaded: camera {}, {} objects rebuilt, {} materials swapped, {} of {} objects in view",
+                        if summary.camera { "updated" } else { "unchanged" }, summary.rebuilt, summary.materials, frustum::visible(&world, &cam).len(), world.len()
+                    );
+                    accumulator.fill(Color::ZERO);
+                    passes = 0;
+                    if let Some(prioritized) = &mut prioritized { prioritized.reset(); }
+                },
+                Ok(None) => {},
+                Err(_) => println!("Failed to reload {}, keeping the previous scene", config_file),
+            }
+        }
+        if let Some(prioritized) = &mut prioritized {
+            if prioritized.converged(CONSTS.samples_per_pixel) { std::thread::sleep(POLL_INTERVAL); continue; }
+            let tiles: usize = prioritized.render_pass(&world, &lights, &environment_map, background.as_ref(), filter.as_ref(), &cam, passes as u64, CONSTS.samples_per_pixel);
+            passes += 1;
+            prioritized.image().save(PREVIEW_FILENAME).unwrap();
+            print!("Preview: pass {}, {} tiles still converging\r", passes, tiles);
+        } else if passes < CONSTS.samples_per_pixel {
+            raytracer::render_pass(&world, &lights, &environment_map, background.as_ref(), filter.as_ref(), &cam, &CONSTS, &mut accumulator, passes as u64);
+            passes += 1;
+            raytracer::accumulator_to_image(&accumulator, &CONSTS, passes).save(PREVIEW_FILENAME).unwrap();
+            print!("Preview: {}/{} samples per pixel\r", passes, CONSTS.samples_per_pixel);
+        } else { std::thread::sleep(POLL_INTERVAL); }
+    }
+}
+
+// Returns None if the file didn't actually change
+fn _reload(config_file: &str, doc: &Yaml, objects: &[WatchedObject]) -> Option<(Yaml, Option<Camera>, Vec<WatchedObject>, ReloadSummary)> {
+    let new_doc: Yaml = match parser::load_config(config_file) {
+        Some(new_doc) => new_doc,
+        None => { println!("Failed to parse {}, keeping the previous scene", config_file); return None; }
+    };
+    if new_doc == *doc { return None; }
+    let mut summary: ReloadSummary = ReloadSummary { camera: false, constants: false, rebuilt: 0, materials: 0 };
+    summary.constants = _section(&new_doc, "constants") != _section(doc, "constants");
+    let new_cam: Option<Camera> = if _section(&new_doc, "camera") != _section(doc, "camera") {
+        summary.camera = true;
+        Some(parser::parse_camera(&new_doc))
+    } else { None };
+    let new_objects: Vec<WatchedObject> = _world_yaml(&new_doc).iter().map(|hashobj| {
+        match _classify_change(objects, hashobj) {
+            ObjectChange::Unchanged(idx) => WatchedObject { yaml: objects[idx].yaml.clone(), objects: objects[idx].objects.clone(), mesh: objects[idx].mesh.clone() },
+            ObjectChange::MaterialOnly(idx) => { summary.materials += 1; _build_object(hashobj, objects[idx].mesh.clone()) },
+            ObjectChange::Rebuilt => { summary.rebuilt += 1; _build_object(hashobj, None) },
+        }
+    }).collect();
+    Some((new_doc, new_cam, new_objects, summary))
+}
+
+fn _classify_change(objects: &[WatchedObject], hashobj: &Yaml) -> ObjectChange {
+    if let Some(idx) = objects.iter().position(|obj| obj.yaml == *hashobj) { return ObjectChange::Unchanged(idx); }
+    let geometry: Yaml = _without_material(hashobj);
+    match objects.iter().position(|obj| obj.mesh.is_some() && _without_material(&obj.yaml) == geometry) {
+        Some(idx) => ObjectChange::MaterialOnly(idx),
+        None => ObjectChange::Rebuilt,
+    }
+}
+
+fn _build_object(hashobj: &Yaml, cached_mesh: Option<Mesh>) -> WatchedObject {
+    let hash = hashobj.as_hash().unwrap();
+    let objtype = hash[&Yaml::String("objType".to_string())].as_str().unwrap();
+    if objtype != "Mesh" {
+        let leaves: Vec<(Arc<dyn Hittable + Send + Sync>, Yaml)> = parser::parse_world_objects(hash).unwrap_or_else(|err| panic!("{}", err)).into_iter().map(|(object, hashleaf)| (object, Yaml::Hash(hashleaf))).collect();
+        return WatchedObject { yaml: hashobj.clone(), objects: leaves, mesh: None };
+    }
+    let mesh: Mesh = match cached_mesh {
+        Some(mut mesh) => { mesh.set_material(parser::parse_material(hash).unwrap_or_else(|err| panic!("{}", err))); mesh },
+        None => parser::parse_mesh(hash, parser::parse_material(hash).unwrap_or_else(|err| panic!("{}", err))).unwrap_or_else(|err| panic!("{}", err)),
+    };
+    WatchedObject { yaml: hashobj.clone(), objects: vec![(parser::parse_clipping(hash, Arc::new(mesh.clone())).unwrap_or_else(|err| panic!("{}", err)), hashobj.clone())], mesh: Some(mesh) }
+}
+
+fn _collect_world(objects: &[WatchedObject], environment_map: &Arc<dyn Hittable + Send + Sync>) -> (HittableList, HittableList) {
+    let world: HittableList = objects.iter().flat_map(|obj| obj.objects.iter().map(|(object, _)| object.clone())).collect();
+    let links: Vec<LightLink> = objects.iter().flat_map(|obj| obj.objects.iter().map(|(_, yaml)| parser::parse_light_link(yaml.as_hash().unwrap()).unwrap_or_else(|err| panic!("{}", err)))).collect();
+    let world: HittableList = light_linking::link(world, &links).unwrap_or_else(|err| panic!("{}", err));
+    let mut lights: HittableList = raytracer::get_lights(&world);
+    if utility::environment_is_light(&CONSTS) { lights.push(environment_map.clone()); }
+    let lights: HittableList = raytracer::sample_lights(&world, lights);
+    (world, lights)
+}
+
+fn _without_material(hashobj: &Yaml) -> Yaml {
+    let mut hash = hashobj.as_hash().unwrap().clone();
+    hash.remove(&Yaml::String("material".to_string()));
+    Yaml::Hash(hash)
+}
+
+fn _section<'a>(doc: &'a Yaml, name: &str) -> &'a Yaml { &doc[name] }
+
+fn _world_yaml(doc: &Yaml) -> Vec<Yaml> { doc["world"].as_vec().unwrap().clone() }
+
+fn _modified_time(config_file: &str) -> Option<SystemTime> { std::fs::metadata(config_file).and_then(|meta| meta.modified()).ok() }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use yaml_rust::YamlLoader;
+    use crate::sphere::Sphere;
+    use crate::material::Lambertian;
+    use crate::point3::Point3;
+
+    fn _watched(yaml: &str, is_mesh: bool) -> WatchedObject {
+        let yaml: Yaml = YamlLoader::load_from_str(yaml).unwrap().remove(0);
+        let sphere: Sphere = Sphere::new(Point3::ZERO, 1.0, Box::new(Lambertian::new(Color::ONE)), 0);
+        let mesh: Option<Mesh> = if is_mesh { Some(Mesh::new_placeholder(Point3::ZERO, 1.0, Point3::ZERO, Box::new(Lambertian::new(Color::ONE)))) } else { None };
+        WatchedObject { yaml: yaml.clone(), objects: vec![(Arc::new(sphere), yaml)], mesh }
+    }
+
+    #[test]
+    fn test_classify_change() {
+        let objects: Vec<WatchedObject> = vec![
+            _watched("{objType: Sphere, radius: 1.0, material: {matType: Lambertian}}", false),
+            _watched("{objType: Mesh, filename: a.obj, material: {matType: Lambertian}}", true),
+        ];
+        let unchanged: Yaml = YamlLoader::load_from_str("{objType: Mesh, filename: a.obj, material: {matType: Lambertian}}").unwrap().remove(0);
+        let material: Yaml = YamlLoader::load_from_str("{objType: Mesh, filename: a.obj, material: {matType: Metal}}").unwrap().remove(0);
+        let sphere: Yaml = YamlLoader::load_from_str("{objType: Sphere, radius: 2.0, material: {matType: Lambertian}}").unwrap().remove(0);
+        assert_eq!(_classify_change(&objects, &unchanged), ObjectChange::Unchanged(1));
+        assert_eq!(_classify_change(&objects, &material), ObjectChange::MaterialOnly(1));
+        assert_eq!(_classify_change(&objects, &sphere), ObjectChange::Rebuilt);
+    }
+}
@@ -0,0 +1,194 @@
+// Author: Giulian Biolo, github.com/giulianbiolo
+// Date: 16/10/2026
+// Description: This file implements the prioritized progressive render, which orders the tiles and splits the samples of each pass by a priority mask or by the noise left in them
+
+use std::sync::Arc;
+
+use glam::Vec3A;
+use image::{GrayImage, ImageBuffer, Rgb};
+
+use crate::assets;
+use crate::camera::Camera;
+use crate::color::{Color, to_rgb};
+use crate::hittable_list::{Hittable, HittableList};
+use crate::light_sampler::luminance;
+use crate::parallel::{self, *};
+use crate::ray::Ray;
+use crate::raytracer;
+use crate::sampling_filters::Filter;
+use crate::utility::{self, random_f32};
+
+
+// Without a mask, the tiles with the largest relative error so far come first and get the most samples
+#[derive(Debug, Clone, PartialEq)]
+pub struct RenderPriority {
+    // Grayscale image over the frame, white where the render should converge first
+    pub mask: Option<String>,
+    pub tile_size: u32,
+    // The least important tiles still get this fraction of the samples, so that the whole image keeps converging
+    pub min_weight: f32,
+    // Samples per pixel of a pass for the most important tiles
+    pub max_samples: u32,
+}
+
+/****************** PrioritizedRender ******************/
+// A rendered tile with the samples per pixel it took, and the color and squared luminance sums of its pixels
+type RenderedTile = (usize, u32, Vec<(Color, f32)>);
+
+pub struct PrioritizedRender {
+    priority: RenderPriority,
+    width: u32,
+    height: u32,
+    tiles_x: u32,
+    // The weights of the mask, None to follow the variance
+    mask_weights: Option<Vec<f32>>,
+    accumulator: Vec<Color>,
+    // Sums of the squared luminance of the samples, for the variance
+    squares: Vec<f32>,
+    // The samples taken by every pixel of each tile
+    samples: Vec<u32>,
+}
+
+impl PrioritizedRender {
+    // A mask that can't be read comes back as an error
+    pub fn new(priority: &RenderPriority, width: u32, height: u32) -> Result<PrioritizedRender, String> {
+        let tile_size: u32 = priority.tile_size.max(1);
+        let (tiles_x, tiles_y) = (width.div_ceil(tile_size), height.div_ceil(tile_size));
+        let mut render: PrioritizedRender = PrioritizedRender {
+            priority: RenderPriority { tile_size, ..priority.clone() }, width, height, tiles_x, mask_weights: None,
+            accumulator: vec![Color::ZERO; (width * height) as usize], squares: vec![0.0; (width * height) as usize], samples: vec![0; (tiles_x * tiles_y) as usize],
+        };
+        if let Some(filename) = &priority.mask {
+            let mask: GrayImage = assets::open_image(filename).map_err(|err| format!("Failed to open the priority mask {}: {}", filename, err))?.to_luma8();
+            render.mask_weights = Some(render.mask_weights(&mask));
+        }
+        Ok(render)
+    }
+    // The average of the mask over each tile, stretched over the frame whatever its resolution
+    fn mask_weights(&self, mask: &GrayImage) -> Vec<f32> {
+        (0..self.samples.len()).map(|tile| {
+            let pixels: Vec<(u32, u32)> = self.tile_pixels(tile).collect();
+            let sum: f32 = pixels.iter().map(|(x, y)| mask.get_pixel(x * mask.width() / self.width, y * mask.height() / self.height)[0] as f32 / 255.0).sum();
+            (sum / pixels.len() as f32).max(self.priority.min_weight)
+        }).collect()
+    }
+    fn tile_pixels(&self, tile: usize) -> impl Iterator<Item = (u32, u32)> {
+        let (x0, y0) = ((tile as u32 % self.tiles_x) * self.priority.tile_size, (tile as u32 / self.tiles_x) * self.priority.tile_size);
+        let (x1, y1) = ((x0 + self.priority.tile_size).min(self.width), (y0 + self.priority.tile_size).min(self.height));
+        (y0..y1).flat_map(move |y| (x0..x1).map(move |x| (x, y)))
+    }
+    // Relative standard error of the pixels of the tile, averaged, infinite until it has two samples
+    fn tile_error(&self, tile: usize) -> f32 {
+        let samples: f32 = self.samples[tile] as f32;
+        if samples < 2.0 { return f32::INFINITY; }
+        let pixels: Vec<(u32, u32)> = self.tile_pixels(tile).collect();
+        pixels.iter().map(|(x, y)| {
+            let idx: usize = (y * self.width + x) as usize;
+            let mean: f32 = luminance(self.accumulator[idx]) / samples;
+            let variance: f32 = ((self.squares[idx] / samples - mean * mean) * samples / (samples - 1.0)).max(0.0);
+            (variance / samples).sqrt() / mean.max(0.01)
+        }).sum::<f32>() / pixels.len() as f32
+    }
+    // The priority of each tile, from min_weight to 1
+    pub fn weights(&self) -> Vec<f32> {
+        if let Some(weights) = &self.mask_weights { return weights.clone(); }
+        let errors: Vec<f32> = (0..self.samples.len()).map(|tile| self.tile_error(tile)).collect();
+        if errors.iter().any(|error| error.is_infinite()) { return errors.iter().map(|error| if error.is_infinite() { 1.0 } else { self.priority.min_weight }).collect(); }
+        let max: f32 = errors.iter().fold(0.0_f32, |max, error| max.max(*error));
+        errors.iter().map(|error| if max > 0.0 { (error / max).max(self.priority.min_weight) } else { 1.0 }).collect()
+    }
+    // The tiles to render in this pass in order of priority, with their samples per pixel, up to the given total
+    pub fn schedule(&self, samples_per_pixel: u32) -> Vec<(usize, u32)> {
+        let weights: Vec<f32> = self.weights();
+        let mut tiles: Vec<(usize, u32)> = (0..self.samples.len()).filter(|tile| self.samples[*tile] < samples_per_pixel).map(|tile| {
+            let samples: u32 = (weights[tile] * self.priority.max_samples as f32).round().max(1.0) as u32;
+            (tile, samples.min(samples_per_pixel - self.samples[tile]))
+        }).collect();
+        tiles.sort_by(|a, b| weights[b.0].total_cmp(&weights[a.0]));
+        tiles
+    }
+    pub fn converged(&self, samples_per_pixel: u32) -> bool { self.samples.iter().all(|samples| *samples >= samples_per_pixel) }
+    pub fn reset(&mut self) {
+        self.accumulator.fill(Color::ZERO);
+        self.squares.fill(0.0);
+        self.samples.fill(0);
+    }
+
+    // Renders the scheduled tiles, the most important ones starting first, and returns how many were
+    #[allow(clippy::too_many_arguments)]
+    pub fn render_pass(&mut self, world: &HittableList, lights: &HittableList, environment_map: &Arc<dyn Hittable + Send + Sync>, background: Option<&Arc<dyn Hittable + Send + Sync>>, filter: &(dyn Filter + Send + Sync), cam: &Camera, seed: u64, samples_per_pixel: u32) -> usize {
+        let schedule: Vec<(usize, u32)> = self.schedule(samples_per_pixel);
+        let this: &PrioritizedRender = self;
+        let rendered: Vec<RenderedTile> = schedule.clone().into_par_iter().map(|(tile, samples)| {
+            utility::seed_stream(seed, tile as u64);
+            let mut path: Vec<Vec3A> = Vec::with_capacity(utility::CONSTS.max_depth as usize + 2);
+            let pixels: Vec<(Color, f32)> = this.tile_pixels(tile).map(|(x, y)| {
+                let (mut color, mut squares) = (Color::ZERO, 0.0_f32);
+                for _s in 0..samples {
+                    let u: f32 = (x as f32 + filter.sample(random_f32())) / (this.width as f32 - 1.0);
+                    let v: f32 = (this.height as f32 - (y as f32 + filter.sample(random_f32()))) / (this.height as f32 - 1.0);
+                    let r: Ray = cam.get_ray_differential(u, v, 1.0 / (this.width as f32 - 1.0), 1.0 / (this.height as f32 - 1.0));
+                    path.clear();
+                    path.push(r.origin());
+                    let sample: Color = raytracer::ray_color(&r, world, lights, environment_map, background, &utility::CONSTS, 0, &mut path);
+                    if sample.is_finite() {
+                        color += sample;
+                        squares += luminance(sample).powi(2);
+                    }
+                }
+                (color, squares)
+            }).collect();
+            parallel::pause();
+            vec![(tile, samples, pixels)]
+        }).reduce(Vec::new, |mut a, b| { a.extend(b); a });
+        for (tile, samples, pixels) in rendered {
+            let coords: Vec<(u32, u32)> = self.tile_pixels(tile).collect();
+            for ((x, y), (color, squares)) in coords.into_iter().zip(pixels) {
+                let idx: usize = (y * self.width + x) as usize;
+                self.accumulator[idx] += color;
+                self.squares[idx] += squares;
+            }
+            self.samples[tile] += samples;
+        }
+        schedule.len()
+    }
+    pub fn image(&self) -> ImageBuffer<Rgb<u8>, Vec<u8>> {
+        ImageBuffer::from_fn(self.width, self.height, |x, y| {
+            let tile: usize = ((y / self.priority.tile_size) * self.tiles_x + x / self.priority.tile_size) as usize;
+            to_rgb(self.accumulator[(y * self.width + x) as usize], self.samples[tile].max(1) as f32)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+    use image::ImageFormat;
+
+    #[test]
+    fn test_priority_schedule() {
+        // A mask white on the right half, the bottom tiles being cut by the frame
+        let mask: GrayImage = ImageBuffer::from_fn(8, 8, |x, _| image::Luma([if x >= 4 { 255 } else { 0 }]));
+        let mut png: Vec<u8> = Vec::new();
+        mask.write_to(&mut Cursor::new(&mut png), ImageFormat::Png).unwrap();
+        assets::register("priority_test/mask.png", png);
+        let priority: RenderPriority = RenderPriority { mask: Some("priority_test/mask.png".to_string()), tile_size: 2, min_weight: 0.25, max_samples: 4 };
+        let mut render: PrioritizedRender = PrioritizedRender::new(&priority, 4, 3).unwrap();
+        assert_eq!(render.weights(), vec![0.25, 1.0, 0.25, 1.0]);
+        assert_eq!(render.schedule(16), vec![(1, 4), (3, 4), (0, 1), (2, 1)]);
+        // The important tiles stop once they have all of their samples
+        render.samples = vec![2, 15, 0, 16];
+        assert_eq!(render.schedule(16), vec![(1, 1), (0, 1), (2, 1)]);
+        // Without a mask, the noisiest tile comes first
+        render.mask_weights = None;
+        render.samples = vec![4; 4];
+        for (idx, pixel) in render.accumulator.iter_mut().enumerate() { *pixel = Color::splat(2.0); render.squares[idx] = 1.0; }
+        render.squares[0] = 2.0;
+        assert_eq!(render.schedule(16)[0], (0, 4));
+        assert!(!render.converged(16));
+        // A missing mask is an error rather than a panic
+        let missing: RenderPriority = RenderPriority { mask: Some("priority_test/missing.png".to_string()), ..priority };
+        assert!(PrioritizedRender::new(&missing, 4, 3).err().is_some_and(|err| err.contains("priority_test/missing.png")));
+    }
+}
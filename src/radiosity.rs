@@ -0,0 +1,218 @@
+// Author: Giulian Biolo, github.com/giulianbiolo
+// Date: 16/10/2026
+// Description: This file implements the radiosity precomputation of the RF coverage, the transfer between the surfaces of a static scene being traced once for every candidate source
+
+use std::collections::HashMap;
+
+use bumpalo::Bump;
+use glam::Vec3A;
+use yaml_rust::Yaml;
+
+use crate::diffraction::{self, Wedge};
+use crate::hittable_list::{Hittable, HittableList};
+use crate::light_sampler::luminance;
+use crate::material::ScatterRecord;
+use crate::parallel::*;
+use crate::parser;
+use crate::point3::Point3;
+use crate::ray::Ray;
+use crate::utility::{self, PI};
+use crate::visibility;
+use crate::voxels::{self, VoxelGrid};
+
+
+// A piece of surface reflecting diffusely, the part of a surface inside of a voxel facing one way
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Patch {
+    pub point: Point3,
+    // Unit normal, towards the side the patch reflects to
+    pub normal: Vec3A,
+    pub area: f32,
+    // The fraction of the incident power reflected, from the luminance of the material
+    pub reflectance: f32,
+}
+
+// A hit of the rays along the axes: its voxel, which way it faces, where it is, its normal and reflectance
+type Sample = ([u32; 3], usize, Point3, Vec3A, f32);
+// The voxel and which way the patch faces, 2 * axis plus 1 for the negative side
+type PatchKey = ([u32; 3], usize);
+
+// The surfaces of the scene seen by rays along the axes of the grid, in both directions, grouped by voxel and by the main axis of their normal
+pub fn patches(world: &HittableList, grid: &VoxelGrid) -> Vec<Patch> {
+    let samples: Vec<Sample> = (0..3).flat_map(|axis| (0..grid.lines(axis)).map(move |line| (axis, line))).collect::<Vec<(usize, usize)>>()
+        .into_par_iter().map(|(axis, line)| {
+            let (forward, length) = grid.line_ray(axis, line);
+            // Not the negated direction, whose -0.0 components turn the slabs of the bounding boxes inside out
+            let backward: Ray = Ray::new(forward.at(length), Vec3A::ZERO - forward.direction());
+            let arena: Bump = Bump::new();
+            let mut samples: Vec<Sample> = Vec::new();
+            for ray in [forward, backward] {
+                let mut t_min: f32 = utility::NEAR_ZERO;
+                let mut crossings: u32 = 0;
+                while let Some(rec) = world.hit(&ray, t_min, length) {
+                    t_min = rec.t + utility::NEAR_ZERO;
+                    crossings += 1;
+                    // After an odd number of surfaces the ray is inside of a closed object, whose insides reflect nothing to the outside
+                    if crossings.is_multiple_of(2) { continue; }
+                    let Some(voxel) = grid.voxel(rec.p) else { continue };
+                    // The normal of the hit record faces the ray, so each side of a surface gets its own patch
+                    let side: usize = (0..3).max_by(|a, b| rec.normal[*a].abs().total_cmp(&rec.normal[*b].abs())).unwrap();
+                    let side: usize = 2 * side + (rec.normal[side] < 0.0) as usize;
+                    let mut srec: ScatterRecord = ScatterRecord::new(&arena);
+                    let reflectance: f32 = if rec.mat_ptr.scatter(&ray, &rec, &mut srec) { luminance(srec.attenuation).clamp(0.0, 1.0) } else { 0.0 };
+                    samples.push((voxel, side, rec.p, rec.normal, reflectance));
+                }
+            }
+            samples
+        }).reduce(Vec::new, |mut a, b| { a.extend(b); a });
+    let mut merged: HashMap<PatchKey, (Point3, Vec3A, f32, u32)> = HashMap::new();
+    for (voxel, side, point, normal, reflectance) in samples {
+        let entry = merged.entry((voxel, side)).or_insert((Point3::ZERO, Vec3A::ZERO, 0.0, 0));
+        *entry = (entry.0 + point, entry.1 + normal, entry.2 + reflectance, entry.3 + 1);
+    }
+    let mut patches: Vec<(PatchKey, Patch)> = merged.into_iter().filter(|(_, (_, normal, _, _))| normal.length() > 0.0).map(|(key, (point, normal, reflectance, count))| {
+        let normal: Vec3A = normal.normalize();
+        // The area of a plane through a voxel, by its projection on the face of the voxel it mostly faces
+        let area: f32 = grid.voxel_size * grid.voxel_size / normal.abs().max_element();
+        (key, Patch { point: point / count as f32, normal, area, reflectance: reflectance / count as f32 })
+    }).collect();
+    // The same patches in the same order every run
+    patches.sort_by_key(|(key, _)| *key);
+    patches.into_iter().map(|(_, patch)| patch).collect()
+}
+
+/****************** Transfer ******************/
+// The power exchanged between the patches and from the patches to the receivers, which doesn't depend on the source
+pub struct Transfer {
+    pub patches: Vec<Patch>,
+    pub receivers: Vec<Point3>,
+    pub lambda: f32,
+    // For each patch, the patches it sends power to and the fraction of its reflected power they get (the form factors)
+    pub form_factors: Vec<Vec<(u32, f32)>>,
+    // For each receiver, the patches it sees and the fraction of their reflected power it picks up
+    pub pickups: Vec<Vec<(u32, f32)>>,
+    // The edges the direct path bends around when it's blocked
+    pub wedges: Vec<Wedge>,
+}
+
+// Just off of the surface of the patch, on the side it reflects to, so that the patch doesn't hide itself
+fn _lifted(patch: &Patch) -> Point3 { patch.point + patch.normal * 10.0 * utility::NEAR_ZERO }
+
+impl Transfer {
+    // Traces the visibility between every pair of patches and between the patches and the receivers
+    pub fn precompute(world: &HittableList, patches: Vec<Patch>, receivers: Vec<Point3>, wedges: Vec<Wedge>, lambda: f32) -> Transfer {
+        let form_factors: Vec<Vec<(u32, f32)>> = (0..patches.len()).into_par_iter().map(|i| {
+            let from: &Patch = &patches[i];
+            let mut row: Vec<(u32, f32)> = patches.iter().enumerate().filter(|(j, _)| *j != i).filter_map(|(j, to)| {
+                let offset: Vec3A = to.point - from.point;
+                let distance_squared: f32 = offset.length_squared();
+                let direction: Vec3A = offset / distance_squared.sqrt();
+                let (cos_from, cos_to) = (from.normal.dot(direction), -to.normal.dot(direction));
+                if cos_from <= 0.0 || cos_to <= 0.0 || !visibility::line_of_sight(world, &_lifted(from), &_lifted(to)) { return None; }
+                Some((j as u32, to.area * cos_from * cos_to / (PI * distance_squared)))
+            }).collect();
+            // Close patches would get more than everything, the reflected power is at most all sent on
+            let total: f32 = row.iter().map(|(_, factor)| factor).sum();
+            if total > 1.0 { for (_, factor) in row.iter_mut() { *factor /= total; } }
+            vec![row]
+        }).reduce(Vec::new, |mut a, b| { a.extend(b); a });
+        // The receivers are isotropic antennas, with an effective area of lambda^2 / 4pi
+        let aperture: f32 = lambda * lambda / (4.0 * PI);
+        let pickups: Vec<Vec<(u32, f32)>> = receivers.clone().into_par_iter().map(|receiver| {
+            vec![patches.iter().enumerate().filter_map(|(i, patch)| {
+                let offset: Vec3A = receiver - patch.point;
+                let cos: f32 = patch.normal.dot(offset.normalize());
+                if cos <= 0.0 || !visibility::line_of_sight(world, &_lifted(patch), &receiver) { return None; }
+                Some((i as u32, (aperture * cos / (PI * offset.length_squared())).min(1.0)))
+            }).collect::<Vec<(u32, f32)>>()]
+        }).reduce(Vec::new, |mut a, b| { a.extend(b); a });
+        Transfer { patches, receivers, lambda, form_factors, pickups, wedges }
+    }
+    // The path gain (received over transmitted power) of every receiver from an isotropic source at the point, after so many diffuse bounces
+    // Only the visibility from the source is traced, the rest being the precomputed transfer
+    pub fn coverage(&self, world: &HittableList, source: Point3, bounces: u32) -> Vec<f32> {
+        // The fraction of the power of the source each patch intercepts, and reflects
+        let incident: Vec<f32> = self.patches.clone().into_par_iter().map(|patch| {
+            let offset: Vec3A = patch.point - source;
+            let cos: f32 = -patch.normal.dot(offset.normalize());
+            if cos <= 0.0 || !visibility::line_of_sight(world, &source, &_lifted(&patch)) { return vec![0.0]; }
+            vec![(patch.area * cos / (4.0 * PI * offset.length_squared())).min(1.0)]
+        }).reduce(Vec::new, |mut a, b| { a.extend(b); a });
+        let mut reflected: Vec<f32> = incident.iter().zip(&self.patches).map(|(incident, patch)| incident * patch.reflectance).collect();
+        for _ in 1..bounces {
+            let mut received: Vec<f32> = incident.clone();
+            for (i, row) in self.form_factors.iter().enumerate() {
+                for (j, factor) in row { received[*j as usize] += reflected[i] * factor; }
+            }
+            reflected = received.iter().zip(&self.patches).map(|(received, patch)| received * patch.reflectance).collect();
+        }
+        if bounces == 0 { reflected.fill(0.0); }
+        self.receivers.iter().zip(&self.pickups).map(|(receiver, pickups)| {
+            let distance: f32 = (*receiver - source).length();
+            let direct: f32 = if visibility::line_of_sight(world, &source, receiver) { (self.lambda / (4.0 * PI * distance)).powi(2) } else { diffraction::gain(world, &self.wedges, source, *receiver, self.lambda) };
+            direct + pickups.iter().map(|(i, pickup)| reflected[*i as usize] * pickup).sum::<f32>()
+        }).collect()
+    }
+}
+
+// The patches come from the voxels block of the scene, the candidate sources and the receivers from the from and to points of the points file,
+// as with the visibility matrix, which also gives the bounces (3 by default) and whether the edges diffract (diffraction: true by default)
+// Writes the path loss in dB of every receiver (columns) from every candidate source (rows)
+pub fn run(config_file: &str, points_file: &str, output: &str) {
+    let doc: Yaml = parser::load_config(config_file).unwrap_or_else(|| panic!("Failed to load the config file {}", config_file));
+    let world: HittableList = parser::parse_scene(&doc).into_iter().filter(|object| !object.is_light()).collect();
+    // Only the wavelength of the constants matters, the rendering ones may be left out
+    let lambda: f32 = doc["constants"]["sourcesLambda"].as_f64().map_or(utility::Constants::default().sources_lambda, |lambda| lambda as f32);
+    let (min, max, resolution) = voxels::parse_grid(&doc);
+    let grid: VoxelGrid = VoxelGrid::new(min, max, resolution);
+    let points: Yaml = parser::load_config(points_file).unwrap_or_else(|| panic!("Failed to load the points file {}", points_file));
+    let (sources, receivers) = (visibility::parse_points(&points, "from"), visibility::parse_points(&points, "to"));
+    let bounces: u32 = points["bounces"].as_i64().unwrap_or(3) as u32;
+    let start = std::time::Instant::now();
+    let wedges: Vec<Wedge> = if points["diffraction"].as_bool().unwrap_or(true) { diffraction::detect(&world) } else { Vec::new() };
+    let transfer: Transfer = Transfer::precompute(&world, patches(&world, &grid), receivers, wedges, lambda);
+    println!("Precomputed the transfer between {} patches and {} receivers, with {} diffracting edges, in {:.1}s", transfer.patches.len(), transfer.receivers.len(), transfer.wedges.len(), start.elapsed().as_secs_f32());
+    let start = std::time::Instant::now();
+    let losses: Vec<Vec<f32>> = sources.iter().map(|source| transfer.coverage(&world, *source, bounces).into_iter().map(|gain| -10.0 * gain.log10()).collect()).collect();
+    println!("Evaluated {} sources in {:.1}s", sources.len(), start.elapsed().as_secs_f32());
+    std::fs::write(output, visibility::to_csv(&losses)).unwrap_or_else(|err| panic!("Failed to write {}: {}", output, err));
+    // The best candidate is the one with the lowest median loss
+    let median = |losses: &Vec<f32>| { let mut sorted: Vec<f32> = losses.clone(); sorted.sort_by(f32::total_cmp); sorted.get(sorted.len() / 2).copied().unwrap_or(f32::INFINITY) };
+    if let Some((best, loss)) = losses.iter().map(median).enumerate().min_by(|a, b| a.1.total_cmp(&b.1)) {
+        println!("Saved the {}x{} path loss matrix to {}, the best source being {} at {:?} with a median loss of {:.1} dB", sources.len(), transfer.receivers.len(), output, best, sources[best], loss);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use crate::color::Color;
+    use crate::material::Lambertian;
+    use crate::rectangle::XZRectangle;
+
+    #[test]
+    fn test_radiosity_coverage() {
+        let lambda: f32 = 0.125;
+        let (source, receivers) = (Point3::new(-1.0, 1.0, 0.0), vec![Point3::new(1.0, 1.0, 0.0), Point3::new(1.0, 1.0, 1.0)]);
+        // With nothing around only the free space loss is left
+        let empty: HittableList = HittableList::new();
+        let free_space: Vec<f32> = Transfer::precompute(&empty, Vec::new(), receivers.clone(), Vec::new(), lambda).coverage(&empty, source, 3);
+        assert!((free_space[0] - (lambda / (4.0 * PI * 2.0)).powi(2)).abs() < 1e-9);
+        // A gray floor adds what it reflects to the direct path, a bit more with every bounce
+        let floor: HittableList = vec![Arc::new(XZRectangle::new(-3.0, 3.0, -3.0, 3.0, 0.0, Box::new(Lambertian::new(Color::splat(0.5))), 0))];
+        let grid: VoxelGrid = VoxelGrid::new(Point3::new(-3.0, -0.5, -3.0), Point3::new(3.0, 1.5, 3.0), 24);
+        let floor_patches: Vec<Patch> = patches(&floor, &grid);
+        // Each side of the floor is a patch of its own in every voxel
+        assert_eq!(floor_patches.iter().filter(|patch| patch.normal == Vec3A::Y).count(), 24 * 24);
+        assert_eq!(floor_patches.iter().filter(|patch| patch.normal == -Vec3A::Y).count(), 24 * 24);
+        assert!(floor_patches.iter().all(|patch| (patch.area - 0.0625).abs() < 1e-5 && (patch.reflectance - 0.5).abs() < 1e-5));
+        let transfer: Transfer = Transfer::precompute(&floor, floor_patches, receivers, diffraction::detect(&floor), lambda);
+        // A flat floor doesn't see itself
+        assert!(transfer.form_factors.iter().all(|row| row.is_empty()));
+        let (direct, one_bounce) = (transfer.coverage(&floor, source, 0), transfer.coverage(&floor, source, 1));
+        assert!((direct[0] - free_space[0]).abs() < 1e-9);
+        assert!(one_bounce[0] > direct[0] && one_bounce[0] < 1.5 * direct[0]);
+        assert!(one_bounce[1] > direct[1]);
+    }
+}
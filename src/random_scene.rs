@@ -0,0 +1,104 @@
+// Author: Giulian Biolo, github.com/giulianbiolo
+// Date: 16/10/2026
+// Description: This file implements the seeded random scene, the small spheres scattered around three big ones, generated as a scene description so it can be rendered or saved and edited
+
+use glam::Vec3A;
+
+use crate::hittable_list::HittableList;
+use crate::parser;
+use crate::point3::Point3;
+use crate::scene_desc::{CameraDesc, MaterialDesc, ObjectDesc, SceneDesc};
+use crate::utility;
+
+
+// The parameters of the random scene, the same seed giving the same spheres
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RandomScene {
+    pub seed: u64,
+    // The small spheres are scattered over the cells of a grid from -grid to grid along x and z
+    pub grid: i32,
+    pub radius: f32,
+    // The chances of a small sphere being diffuse and metal, the rest are glass
+    pub lambertian: f32,
+    pub metal: f32,
+}
+
+impl Default for RandomScene {
+    fn default() -> Self { RandomScene { seed: 0, grid: 11, radius: 0.2, lambertian: 0.7, metal: 0.25 } }
+}
+
+fn _random_color() -> [f32; 3] { [utility::random_f32(), utility::random_f32(), utility::random_f32()] }
+
+fn _sphere(center: Point3, radius: f32, material: MaterialDesc) -> ObjectDesc {
+    ObjectDesc::new("Sphere").with("center", center.to_array()).with("radius", radius).with_material(material)
+}
+
+fn _glass() -> MaterialDesc { MaterialDesc::new("Dielectric").with_albedo([1.0, 1.0, 1.0]).with("refractionIdx", 1.5).with("opacity", 0.0) }
+
+impl RandomScene {
+    // The small spheres in a SphereArray, one per cell of the grid, leaving room around the big metal sphere
+    pub fn spheres(&self) -> ObjectDesc {
+        utility::seed_stream(self.seed, 0);
+        let mut spheres: Vec<ObjectDesc> = Vec::new();
+        for a in -self.grid..self.grid {
+            for b in -self.grid..self.grid {
+                let choose_mat: f32 = utility::random_f32();
+                let center: Point3 = Vec3A::new(a as f32 + 0.9 * utility::random_f32(), self.radius, b as f32 + 0.9 * utility::random_f32());
+                if (center - Vec3A::new(4.0, self.radius, 0.0)).length() <= 0.9 { continue; }
+                let material: MaterialDesc = if choose_mat < self.lambertian {
+                    let (tint, shade) = (Vec3A::from(_random_color()), Vec3A::from(_random_color()));
+                    MaterialDesc::new("Lambertian").with_albedo((tint * shade).to_array())
+                } else if choose_mat < self.lambertian + self.metal {
+                    let albedo: [f32; 3] = [utility::random_f32_range(0.5, 1.0), utility::random_f32_range(0.5, 1.0), utility::random_f32_range(0.5, 1.0)];
+                    MaterialDesc::new("Metal").with_albedo(albedo).with("fuzz", utility::random_f32_range(0.0, 0.5))
+                } else { _glass() };
+                spheres.push(_sphere(center, self.radius, material));
+            }
+        }
+        ObjectDesc::new("SphereArray").with("objects", spheres)
+    }
+
+    // The whole scene, on a big ground sphere and seen by a camera like the one of the book it comes from
+    pub fn desc(&self) -> SceneDesc {
+        SceneDesc::new(CameraDesc::new([13.0, 2.0, 3.0], [0.0, 0.0, 0.0], 20.0, 16.0 / 9.0).with_focus(0.1, 10.0))
+            .with_constant("width", 1200).with_constant("height", 675).with_constant("samplesPerPixel", 64)
+            .with_constant("maxDepth", 50).with_constant("minDepth", 5)
+            .with_object(self.spheres())
+            .with_object(_sphere(Point3::new(0.0, 1.0, 0.0), 1.0, _glass()))
+            .with_object(_sphere(Point3::new(-4.0, 1.0, 0.0), 1.0, MaterialDesc::new("Lambertian").with_albedo([0.4, 0.2, 0.1])))
+            .with_object(_sphere(Point3::new(4.0, 1.0, 0.0), 1.0, MaterialDesc::new("Metal").with_albedo([0.7, 0.6, 0.5]).with("fuzz", 0.0)))
+            .with_object(_sphere(Point3::new(0.0, -1000.0, 0.0), 1000.0, MaterialDesc::new("Lambertian").with_albedo([0.5, 0.5, 0.5])))
+    }
+
+    // The world of the scene, exactly as it would be parsed back from the exported file
+    pub fn build(&self) -> HittableList { parser::parse_scene(&self.desc().to_yaml()) }
+}
+
+// Writes the random scene of the seed to the file, to be rendered or edited like any other scene
+pub fn run(seed: u64, filename: &str) {
+    let content: String = RandomScene { seed, ..RandomScene::default() }.desc().to_yaml_string().unwrap_or_else(|err| panic!("Failed to serialize the random scene: {}", err));
+    std::fs::write(filename, content).unwrap_or_else(|err| panic!("Failed to write the random scene to {}: {}", filename, err));
+    println!("Saved the random scene of seed {} to {}", seed, filename);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_random_scene() {
+        let scene: RandomScene = RandomScene { seed: 7, grid: 3, ..RandomScene::default() };
+        // The same seed gives the same spheres, another one different ones
+        assert_eq!(scene.desc(), scene.desc());
+        assert_ne!(scene.spheres(), RandomScene { seed: 8, ..scene }.spheres());
+        // The exported file reads back to the same scene, the small spheres being one SphereArray next to the four big ones
+        let desc: SceneDesc = scene.desc();
+        assert_eq!(SceneDesc::from_yaml_str(&desc.to_yaml_string().unwrap()).unwrap(), desc);
+        assert_eq!(scene.build().len(), 5);
+        let objects: usize = desc.world[0].params.get("objects").and_then(|objects| objects.as_sequence()).map_or(0, |objects| objects.len());
+        assert!(objects > 0 && objects <= 36);
+        // Without metal and glass, only the lambertian spheres are left
+        let diffuse: String = serde_yaml::to_string(&RandomScene { lambertian: 1.0, metal: 0.0, ..scene }.spheres()).unwrap();
+        assert!(diffuse.contains("Lambertian") && !diffuse.contains("Metal") && !diffuse.contains("Dielectric"));
+    }
+}
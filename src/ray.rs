@@ -2,19 +2,89 @@
 // Date: 24/01/2023
 // Description: This file implements the Ray struct
 
-use glam::Vec3A;
+use glam::{Vec2, Vec3A};
 
+use crate::hit_record::HitRecord;
+use crate::manifold::ManifoldChain;
+use crate::medium::MediumStack;
 use crate::point3::Point3;
 
 
+// The rays through the neighbouring pixels, one pixel to the right (x) and one below (y)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RayDifferential {
+    pub rx_origin: Point3,
+    pub rx_direction: Vec3A,
+    pub ry_origin: Point3,
+    pub ry_direction: Vec3A,
+}
+
+impl RayDifferential {
+    // Where the offset ray crosses the plane tangent to the hit point
+    fn _on_tangent_plane(origin: Point3, direction: Vec3A, rec: &HitRecord) -> Option<Point3> {
+        let denominator: f32 = rec.normal.dot(direction);
+        if denominator.abs() < 1e-8 { return None; }
+        Some(origin + direction * (rec.normal.dot(rec.p - origin) / denominator))
+    }
+    // Solves dp = du * dpdu + dv * dpdv in the least squares sense
+    fn _to_uv(dp: Vec3A, rec: &HitRecord) -> Vec2 {
+        let (uu, uv, vv) = (rec.dpdu.dot(rec.dpdu), rec.dpdu.dot(rec.dpdv), rec.dpdv.dot(rec.dpdv));
+        let det: f32 = uu * vv - uv * uv;
+        if det.abs() < 1e-12 { return Vec2::ZERO; }
+        let (pu, pv) = (rec.dpdu.dot(dp), rec.dpdv.dot(dp));
+        Vec2::new(vv * pu - uv * pv, uu * pv - uv * pu) / det
+    }
+    // Texture space derivatives of the hit point, zero when the surface doesn't provide dpdu and dpdv
+    pub fn uv_derivatives(&self, rec: &HitRecord) -> (Vec2, Vec2) {
+        let px: Option<Point3> = Self::_on_tangent_plane(self.rx_origin, self.rx_direction, rec);
+        let py: Option<Point3> = Self::_on_tangent_plane(self.ry_origin, self.ry_direction, rec);
+        match (px, py) {
+            (Some(px), Some(py)) => (Self::_to_uv(px - rec.p, rec), Self::_to_uv(py - rec.p, rec)),
+            _ => (Vec2::ZERO, Vec2::ZERO),
+        }
+    }
+    // Follows a specular bounce, reflecting or refracting the offset rays like the main one (the surface is assumed locally flat)
+    pub fn scatter(&self, incoming: &Ray, rec: &HitRecord, scattered: &Ray) -> Option<RayDifferential> {
+        let (d, wo, n) = (incoming.direction().normalize(), scattered.direction().normalize(), rec.normal);
+        let transform = |direction: Vec3A| -> Option<Vec3A> {
+            let direction: Vec3A = direction.normalize();
+            if wo.dot(n) > 0.0 { return Some(direction - 2.0 * direction.dot(n) * n); }
+            // Refraction scales the tangential component by the ratio of the indices, measured on the main ray
+            let (d_tangent, wo_tangent) = (d - d.dot(n) * n, wo - wo.dot(n) * n);
+            let eta: f32 = if d_tangent.length() > 1e-6 { wo_tangent.length() / d_tangent.length() } else { 1.0 };
+            let tangent: Vec3A = (direction - direction.dot(n) * n) * eta;
+            let cos_squared: f32 = 1.0 - tangent.length_squared();
+            if cos_squared < 0.0 { return None; }
+            Some(tangent - n * cos_squared.sqrt())
+        };
+        let offset: Vec3A = scattered.origin() - rec.p;
+        Some(RayDifferential {
+            rx_origin: Self::_on_tangent_plane(self.rx_origin, self.rx_direction, rec)? + offset,
+            rx_direction: transform(self.rx_direction)?,
+            ry_origin: Self::_on_tangent_plane(self.ry_origin, self.ry_direction, rec)? + offset,
+            ry_direction: transform(self.ry_direction)?,
+        })
+    }
+}
+
 pub struct Ray {
     pub origin: Point3,
     pub direction: Vec3A,
+    // The nested dielectrics the ray is currently inside of
+    pub media: MediumStack,
+    // Only camera rays and their specular bounces carry differentials, used to filter the textures
+    pub differential: Option<RayDifferential>,
+    // Light linking bits of the lights this ray can see the emission of, those linked to the surface it left
+    pub light_mask: u64,
+    // Off for the bounces whose environment light was already gathered from its spherical harmonics
+    pub environment_visible: bool,
+    // The caustic casters refracted through since a diffuse bounce which gathered the lights behind them with the manifold sampling, None off of such chains
+    pub manifold_chain: Option<ManifoldChain>,
 }
 
 impl Ray {
-    pub fn new(origin: Point3, direction: Vec3A) -> Ray { Ray { origin, direction } }
-    pub fn empty() -> Ray { Ray { origin: Point3::new(0.0, 0.0, 0.0), direction: Vec3A::new(0.0, 0.0, 0.0) } }
+    pub fn new(origin: Point3, direction: Vec3A) -> Ray { Ray { origin, direction, media: MediumStack::new(), differential: None, light_mask: u64::MAX, environment_visible: true, manifold_chain: None } }
+    pub fn empty() -> Ray { Ray { origin: Point3::new(0.0, 0.0, 0.0), direction: Vec3A::new(0.0, 0.0, 0.0), media: MediumStack::new(), differential: None, light_mask: u64::MAX, environment_visible: true, manifold_chain: None } }
     pub fn origin(&self) -> Point3 { self.origin }
     pub fn direction(&self) -> Vec3A { self.direction }
     pub fn at(&self, t: f32) -> Point3 { self.origin + self.direction * t }
@@ -35,4 +105,29 @@ mod tests {
         assert_eq!(ray.at(2.5), Point3::new(4.5, 3.0, 4.0));
         Ok(())
     }
+    #[test]
+    fn test_ray_differential() {
+        use crate::material::Lambertian;
+        use crate::color::Color;
+        // A plane facing the camera, with the uvs spanning 4 units along x and y
+        let material: Lambertian = Lambertian::new(Color::ONE);
+        let mut rec: HitRecord = HitRecord::new(Point3::new(0.0, 0.0, -2.0), Vec3A::Z, &material, 2.0, 0.5, 0.5, true);
+        rec.dpdu = Vec3A::new(4.0, 0.0, 0.0);
+        rec.dpdv = Vec3A::new(0.0, 4.0, 0.0);
+        let differential: RayDifferential = RayDifferential {
+            rx_origin: Point3::ZERO,
+            rx_direction: Vec3A::new(0.01, 0.0, -1.0),
+            ry_origin: Point3::ZERO,
+            ry_direction: Vec3A::new(0.0, 0.01, -1.0),
+        };
+        let (duvdx, duvdy) = differential.uv_derivatives(&rec);
+        assert!(duvdx.abs_diff_eq(Vec2::new(0.005, 0.0), 1e-6));
+        assert!(duvdy.abs_diff_eq(Vec2::new(0.0, 0.005), 1e-6));
+        // A mirror keeps the spread of the offset rays
+        let incoming: Ray = Ray::new(Point3::ZERO, Vec3A::new(0.0, 0.0, -1.0));
+        let reflected: Ray = Ray::new(rec.p, Vec3A::Z);
+        let bounced: RayDifferential = differential.scatter(&incoming, &rec, &reflected).unwrap();
+        assert!(bounced.rx_origin.abs_diff_eq(Point3::new(0.02, 0.0, -2.0), 1e-5));
+        assert!(bounced.rx_direction.normalize().abs_diff_eq(Vec3A::new(0.01, 0.0, 1.0).normalize(), 1e-5));
+    }
 }
@@ -2,32 +2,48 @@
 // Date: 24/01/2023
 // Description: This file implements various raytracing functions
 
+use std::cell::RefCell;
 use std::io::Write;
 use std::sync::{Arc, Mutex};
+use std::time::Instant;
 use core::sync::atomic::{AtomicU32, Ordering};
 
+use bumpalo::Bump;
 use likely_stable::unlikely;
-use rayon::prelude::*;
+use crate::parallel::{self, *};
 use image::{ImageBuffer, Rgb};
 
 use glam::Vec3A;
 
-use crate::material::DiffuseLight;
+use crate::adaptive::{AdaptiveSampling, PixelEstimate};
+use crate::aov::{AovBuffer, PathStats};
 use crate::material::ScatterRecord;
 use crate::ray::Ray;
 use crate::hittable_list::HittableList;
 use crate::hittable_list::Hittable;
-use crate::sphere::Sphere;
-use crate::mesh::Mesh;
-use crate::material::{Lambertian, Metal, Dielectric};
+use crate::cancel;
+use crate::lidar;
+use crate::light_sampler::LightSampler;
+use crate::link_budget::LinkBudget;
+use crate::manifold::{self, ManifoldChain};
+use crate::memory;
+use crate::nan_report;
+use crate::random_scene::RandomScene;
+use crate::scene_builder::{MaterialBuilder, SceneBuilder};
 use crate::camera::Camera;
-use crate::sphere_array::SphereArray;
+use crate::mean_buffer::MeanAccumulator;
+use crate::temporal;
+use crate::transient;
 use crate::utility;
-use crate::utility::{CONSTS, random_f32, load_environment, load_filter};
+use crate::utility::{Constants, CONSTS, random_f32, load_environment, load_background, load_filter};
 use crate::color::{Color, to_rgb};
 use crate::point3::Point3;
 use crate::parser;
+use crate::pdf::{PDF, HittablePDF, MixturePDF};
+use crate::power_report::{self, ReportOptions, Volume};
+use crate::pixel_debug::{self, Bounce, Event};
 use crate::sampling_filters::Filter;
+use crate::sh;
 // use crate::pdf::{PDF, HittablePDF};
 
 
@@ -51,25 +67,43 @@ pub fn calculate_phase_and_power(path: &Vec<Vec3A>) -> (f32, f32) {
     (phase, power)
 }
 
+// Sums the power of the paths reaching the point from all the directions, weighted by their phase
+pub fn power_at(point: &Point3, world: &HittableList, lights: &HittableList, environment_map: &Arc<dyn Hittable + Send + Sync>) -> f32 {
+    let mut totpow: f32 = 0.0;
+    for _y in 0..(CONSTS.samples_per_pixel * 512) {
+        let current_ray: Ray = Ray::new(*point, utility::random_in_unit_sphere_uniform_distribution());
+        let mut path: Vec<Vec3A> = vec![current_ray.origin()];
+        let curr_color: Color = ray_color(&current_ray, world, lights, environment_map, None, &CONSTS, 0, &mut path);
+        // we weight the curr_color by the power of the ray, which is 1 / (4 * pi * r^2), and the sign depends on the phase (either constructive or destructive)
+        let (phase, power) = calculate_phase_and_power(&path);
+        // println!("Phase: {}, Power: {}", phase, power);
+        let curr_color: Color = curr_color * power * -phase.sin(); // togliere -sin() phase se peggiora...
+        if curr_color.is_finite() { totpow += curr_color.length(); }
+    }
+    totpow
+}
+
 // Renders the scene to an image
 #[allow(dead_code)]
 pub fn render_to_image(world: &HittableList, cam: &Camera, filename: &str) {
     // Render function
     let mut img: ImageBuffer<Rgb<u8>, Vec<u8>> = ImageBuffer::new(CONSTS.width, CONSTS.height);
-    let envmap: Arc<dyn Hittable + Send + Sync> = load_environment();
+    let (envmap, env_dist): (Arc<dyn Hittable + Send + Sync>, f32) = load_environment(world, &CONSTS);
+    let background: Option<Arc<dyn Hittable + Send + Sync>> = load_background(env_dist, &CONSTS);
     let mut lights: Vec<Arc<dyn Hittable + Send + Sync>> = get_lights(world);
-    if CONSTS.environment_intensity.unwrap_or(1.0) > 0.0 { lights.push(envmap.clone()); }
+    if utility::environment_is_light(&CONSTS) { lights.push(envmap.clone()); }
     let filter: Box<dyn Filter + Send + Sync> = load_filter();
     println!("Lights: {}", lights.len());
     println!("Chosen Filter: {}", filter);
+    let lights: HittableList = sample_lights(world, lights);
     for (x, y, pixel) in img.enumerate_pixels_mut() {
         let mut pixel_color: Color = Color::new(0.0, 0.0, 0.0);
         for _s in 0..CONSTS.samples_per_pixel {
             let u: f32 = (x as f32 + filter.sample(random_f32())) / (CONSTS.width as f32 - 1.0);
             let v: f32 = (CONSTS.height as f32 - (y as f32 + filter.sample(random_f32()))) as f32 / (CONSTS.height as f32 - 1.0);
-            let r: Ray = cam.get_ray(u, v);
+            let r: Ray = cam.get_ray_differential(u, v, 1.0 / (CONSTS.width as f32 - 1.0), 1.0 / (CONSTS.height as f32 - 1.0));
             let mut path: Vec<Vec3A> = vec![r.origin()];
-            let curr_color: Vec3A = ray_color(&r, world, &lights, &envmap, 0, &mut path);
+            let curr_color: Vec3A = ray_color(&r, world, &lights, &envmap, background.as_ref(), &CONSTS, 0, &mut path);
             if curr_color.is_finite() { pixel_color += curr_color; }
         }
         *pixel = to_rgb(pixel_color, CONSTS.samples_per_pixel as f32);
@@ -80,48 +114,261 @@ pub fn render_to_image(world: &HittableList, cam: &Camera, filename: &str) {
 
 #[allow(dead_code)]
 pub fn render_to_image_multithreaded(world: &HittableList, cam: Camera, filename: &str) {
-    let img: ImageBuffer<Rgb<u8>, Vec<u8>> = ImageBuffer::new(CONSTS.width, CONSTS.height);
-    let safe_img: Arc<Mutex<ImageBuffer<Rgb<u8>, Vec<u8>>>> = Arc::new(Mutex::new(img));
-    let environment_map: Arc<dyn Hittable + Send + Sync> = load_environment();
-    let safe_world: Arc<Vec<Arc<dyn Hittable + Send + Sync>>> = Arc::new(world.clone());
-    let mut lights: Vec<Arc<dyn Hittable + Send + Sync>> = get_lights(&world);
-    if CONSTS.environment_intensity.unwrap_or(1.0) > 0.0 { lights.push(environment_map.clone()); }
+    if let Some(lidar) = &CONSTS.lidar {
+        lidar::run(lidar, world, &cam, filename);
+        return;
+    }
+    let (environment_map, env_dist): (Arc<dyn Hittable + Send + Sync>, f32) = load_environment(world, &CONSTS);
+    let background: Option<Arc<dyn Hittable + Send + Sync>> = load_background(env_dist, &CONSTS);
+    let mut lights: Vec<Arc<dyn Hittable + Send + Sync>> = get_lights(world);
+    if utility::environment_is_light(&CONSTS) { lights.push(environment_map.clone()); }
     let filter: Box<dyn Filter + Send + Sync> = load_filter();
     println!("Lights: {}", lights.len());
     println!("Chosen Filter: {}", filter);
-    let total_rows: f32 = CONSTS.height as f32;
-    let completed_rows: AtomicU32 = AtomicU32::new(0);
-    (0..CONSTS.height).into_par_iter().for_each(|y| {
-        for x in 0..CONSTS.width {
-            let mut pixel_color: Color = Color::new(0.0, 0.0, 0.0);
-            for _s in 0..CONSTS.samples_per_pixel {
-                let u: f32 = (x as f32 + filter.sample(random_f32())) / (CONSTS.width as f32 - 1.0);
-                let v: f32 = (CONSTS.height as f32 - (y as f32 + filter.sample(random_f32()))) / (CONSTS.height as f32 - 1.0);
-                let r: Ray = cam.get_ray(u, v);
-                let mut path: Vec<Vec3A> = vec![r.origin()];
-                let curr_color: Color = ray_color(&r, &*safe_world, &lights, &environment_map, 0, &mut path);
-                if curr_color.is_finite() { pixel_color += curr_color; }
-            }
-            let rgb: Rgb<u8> = to_rgb(pixel_color, CONSTS.samples_per_pixel as f32);
-            let mut img: std::sync::MutexGuard<'_, ImageBuffer<Rgb<u8>, Vec<u8>>> = safe_img.lock().unwrap();
-            img.put_pixel(x, y as u32, rgb);
+    println!("{}", memory::report());
+    let lights: HittableList = sample_lights(world, lights);
+    if let Some(depth_output) = &CONSTS.depth_output { depth_output.save(world, &cam, CONSTS.width, CONSTS.height, filename); }
+    if let Some(position_output) = &CONSTS.position_output { position_output.save(world, &cam, CONSTS.width, CONSTS.height, filename); }
+    if let Some(motion_vectors) = &CONSTS.motion_vectors { motion_vectors.save(world, &cam, CONSTS.width, CONSTS.height, filename); }
+    if let Some(transient) = &CONSTS.transient {
+        transient::render(world, &lights, &environment_map, background.as_ref(), filter.as_ref(), &cam, transient).save(filename, CONSTS.samples_per_pixel);
+        return;
+    }
+    // The statistics of the paths are only gathered when some AOV is asked for
+    let aovs: Option<Mutex<AovBuffer>> = (!CONSTS.aovs.is_empty()).then(|| Mutex::new(AovBuffer::new(CONSTS.width, CONSTS.height)));
+    // The whole image gets one sample per pixel at a time, so that a cancelled, budgeted or converged render still saves a complete image
+    let budget: RenderBudget = RenderBudget::from_constants().unwrap_or(RenderBudget { max_seconds: None, max_passes: CONSTS.samples_per_pixel });
+    let mut progress: ProgressiveOutput = ProgressiveOutput::new(CONSTS.progressive_interval);
+    let pixels: usize = (CONSTS.width * CONSTS.height) as usize;
+    // The adaptive sampling leaves out the pixels that converged, each being averaged over its own samples
+    let mut estimates: Vec<PixelEstimate> = vec![PixelEstimate::default(); if CONSTS.adaptive_sampling.is_some() { pixels } else { 0 }];
+    let unconverged = |estimates: &[PixelEstimate]| CONSTS.adaptive_sampling.is_none_or(|adaptive| estimates.iter().any(|estimate| !adaptive.converged(estimate)));
+    // The temporal filter blends full precision colors anyway, so it keeps the f32 buffer
+    if CONSTS.compact_accumulation && CONSTS.temporal.is_none() {
+        let mut accumulator: MeanAccumulator = MeanAccumulator::new(CONSTS.width, CONSTS.height);
+        _render_with_budget(&budget, |seed| {
+            _render_pass_compact(world, &lights, &environment_map, background.as_ref(), filter.as_ref(), &cam, &CONSTS, &mut accumulator, _adaptive(&mut estimates), seed, aovs.as_ref());
+            if progress.due() { accumulator.to_image().save(filename).unwrap(); }
+            unconverged(&estimates)
+        });
+        accumulator.to_image().save(filename).unwrap();
+        if let Some(exposure) = &CONSTS.exposure_analysis { exposure.save(&accumulator.colors(), CONSTS.width, CONSTS.height, filename); }
+    } else {
+        let mut accumulator: Vec<Color> = vec![Color::ZERO; pixels];
+        let mut passes: u32 = 0;
+        _render_with_budget(&budget, |seed| {
+            _render_pass(world, &lights, &environment_map, background.as_ref(), filter.as_ref(), &cam, &CONSTS, &mut accumulator, _adaptive(&mut estimates), seed, aovs.as_ref());
+            passes += 1;
+            if progress.due() { accumulator_to_image(&_average(&accumulator, &estimates, passes), &CONSTS, 1).save(filename).unwrap(); }
+            unconverged(&estimates)
+        });
+        let mut colors: Vec<Color> = _average(&accumulator, &estimates, passes);
+        if let Some(temporal) = &CONSTS.temporal { colors = temporal::filter_frame(temporal, world, &cam, CONSTS.width, CONSTS.height, colors); }
+        accumulator_to_image(&colors, &CONSTS, 1).save(filename).unwrap();
+        if let Some(exposure) = &CONSTS.exposure_analysis { exposure.save(&colors, CONSTS.width, CONSTS.height, filename); }
+    }
+    if let Some(aovs) = aovs { aovs.into_inner().unwrap().save(&CONSTS.aovs, filename); }
+}
+
+// The adaptive sampling of the constants with the estimates of the pixels, none without it
+fn _adaptive(estimates: &mut [PixelEstimate]) -> Option<(&'static AdaptiveSampling, &mut [PixelEstimate])> {
+    CONSTS.adaptive_sampling.as_ref().map(|adaptive| (adaptive, estimates))
+}
+
+// The mean color of each pixel, over its own samples with the adaptive sampling
+fn _average(accumulator: &[Color], estimates: &[PixelEstimate], passes: u32) -> Vec<Color> {
+    accumulator.iter().enumerate().map(|(idx, color)| *color / estimates.get(idx).map_or(passes, |estimate| estimate.samples).max(1) as f32).collect()
+}
+
+/****************** Progressive output ******************/
+// Saves the image as it converges every interval seconds, to look at a long render or keep its progress if it's killed
+pub struct ProgressiveOutput {
+    interval: Option<f32>,
+    last_save: Instant,
+}
+
+impl ProgressiveOutput {
+    pub fn new(interval: Option<f32>) -> ProgressiveOutput { ProgressiveOutput { interval, last_save: Instant::now() } }
+    // Whether the image is due to be saved, restarting the interval if it is
+    pub fn due(&mut self) -> bool {
+        if self.interval.is_none_or(|interval| self.last_save.elapsed().as_secs_f32() < interval) { return false; }
+        self.last_save = Instant::now();
+        true
+    }
+}
+
+/****************** Render budget ******************/
+// The limits of a render on a farm slot, which renders one sample per pixel at a time so that it can stop with a converged image
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RenderBudget {
+    pub max_seconds: Option<f32>,
+    pub max_passes: u32,
+}
+
+impl RenderBudget {
+    // None when the constants don't set any limit
+    pub fn from_constants() -> Option<RenderBudget> {
+        if CONSTS.max_render_seconds.is_none() && CONSTS.max_total_samples.is_none() { return None; }
+        let pixels: u64 = CONSTS.width as u64 * CONSTS.height as u64;
+        let max_passes: u32 = CONSTS.max_total_samples.map_or(CONSTS.samples_per_pixel, |samples| (samples / pixels).min(CONSTS.samples_per_pixel as u64) as u32);
+        Some(RenderBudget { max_seconds: CONSTS.max_render_seconds, max_passes })
+    }
+    // Whether another pass can start, expecting it to take as long as the last one, the first one always does
+    pub fn allows_pass(&self, passes: u32, elapsed: f32, last_pass: f32) -> bool {
+        passes == 0 || (passes < self.max_passes && self.max_seconds.is_none_or(|max_seconds| elapsed + last_pass <= max_seconds))
+    }
+}
+
+// Renders passes of one sample per pixel with render_pass, given the seed of each, until it returns false or the budget runs out, and returns how many were rendered
+fn _render_with_budget(budget: &RenderBudget, mut render_pass: impl FnMut(u64) -> bool) -> u32 {
+    let start_time: Instant = Instant::now();
+    let (mut passes, mut last_pass) = (0_u32, 0.0_f32);
+    // A ctrl-C stops after the current pass too, the image being complete with fewer samples
+    while !cancel::cancelled() && budget.allows_pass(passes, start_time.elapsed().as_secs_f32(), last_pass) {
+        let pass_start: Instant = Instant::now();
+        let unconverged: bool = render_pass(utility::RENDER_SEED.wrapping_add(passes as u64));
+        last_pass = pass_start.elapsed().as_secs_f32();
+        passes += 1;
+        print!("{} of {} samples per pixel\r", passes, CONSTS.samples_per_pixel);
+        if !unconverged { println!("\nEvery pixel converged after {} samples", passes); return passes; }
+    }
+    if passes < CONSTS.samples_per_pixel { println!("\nStopped at {} of {} samples per pixel after {:.1}s", passes, CONSTS.samples_per_pixel, start_time.elapsed().as_secs_f32()); }
+    passes
+}
+
+// Renders a single sample per pixel, adding it to the accumulation buffer (one Color per pixel, row major)
+// Every row reseeds the random generator of its thread from the given seed, so that the pass is deterministic
+// Returns the number of path vertices, the bounces of the camera paths, which leave out the rays of the light sampling and of the caustics
+#[allow(clippy::too_many_arguments)]
+pub fn render_pass(world: &HittableList, lights: &HittableList, environment_map: &Arc<dyn Hittable + Send + Sync>, background: Option<&Arc<dyn Hittable + Send + Sync>>, filter: &(dyn Filter + Send + Sync), cam: &Camera, consts: &Constants, accumulator: &mut [Color], seed: u64) -> u64 {
+    _render_pass(world, lights, environment_map, background, filter, cam, consts, accumulator, None, seed, None)
+}
+
+// Runs render_row on every row of the pixels in parallel, with the row of the estimates of the adaptive sampling if any, summing what it returns
+fn _par_rows<T: Send>(pixels: &mut [T], width: u32, estimates: Option<&mut [PixelEstimate]>, render_row: impl Fn(usize, &mut [T], Option<&mut [PixelEstimate]>) -> u64 + Sync + Send) -> u64 {
+    let width: usize = width as usize;
+    match estimates {
+        Some(estimates) => pixels.par_chunks_mut(width).zip(estimates.par_chunks_mut(width)).enumerate().map(|(y, (row, estimates))| render_row(y, row, Some(estimates))).sum(),
+        None => pixels.par_chunks_mut(width).enumerate().map(|(y, row)| render_row(y, row, None)).sum(),
+    }
+}
+
+// The sample of a pixel, or None if the adaptive sampling found it converged, adding it to the running estimate of the pixel
+#[allow(clippy::too_many_arguments)]
+fn _adaptive_sample(world: &HittableList, lights: &HittableList, environment_map: &Arc<dyn Hittable + Send + Sync>, background: Option<&Arc<dyn Hittable + Send + Sync>>, filter: &(dyn Filter + Send + Sync), cam: &Camera, consts: &Constants, x: usize, y: usize, path: &mut Vec<Vec3A>, adaptive: Option<&AdaptiveSampling>, estimate: Option<&mut PixelEstimate>) -> Option<Color> {
+    if let (Some(adaptive), Some(estimate)) = (adaptive, &estimate) {
+        if adaptive.converged(estimate) { return None; }
+    }
+    let curr_color: Color = _pixel_sample(world, lights, environment_map, background, filter, cam, consts, x, y, path);
+    // The non-finite samples count as black ones
+    let sample: Color = if curr_color.is_finite() { curr_color } else { Color::ZERO };
+    if let Some(estimate) = estimate { estimate.add(sample); }
+    Some(sample)
+}
+
+// The pass leaving out the pixels the adaptive sampling found converged, if any, and adding the statistics of its paths to the AOVs too, if any
+#[allow(clippy::too_many_arguments)]
+fn _render_pass(world: &HittableList, lights: &HittableList, environment_map: &Arc<dyn Hittable + Send + Sync>, background: Option<&Arc<dyn Hittable + Send + Sync>>, filter: &(dyn Filter + Send + Sync), cam: &Camera, consts: &Constants, accumulator: &mut [Color], adaptive: Option<(&AdaptiveSampling, &mut [PixelEstimate])>, seed: u64, aovs: Option<&Mutex<AovBuffer>>) -> u64 {
+    let (adaptive, estimates) = adaptive.unzip();
+    _par_rows(accumulator, consts.width, estimates, |y, row, mut estimates| {
+        utility::seed_stream(seed, y as u64);
+        let mut vertices: u64 = 0;
+        let mut row_stats: Vec<PathStats> = vec![PathStats::default(); if aovs.is_some() { row.len() } else { 0 }];
+        // The path buffer is reused by all the pixels of the row
+        let mut path: Vec<Vec3A> = Vec::with_capacity(consts.max_depth as usize + 2);
+        for (x, pixel_color) in row.iter_mut().enumerate() {
+            let estimate: Option<&mut PixelEstimate> = estimates.as_deref_mut().map(|estimates| &mut estimates[x]);
+            let Some(sample) = _adaptive_sample(world, lights, environment_map, background, filter, cam, consts, x, y, &mut path, adaptive, estimate) else { continue };
+            *pixel_color += sample;
+            // Every bounce pushes its vertex to the path, after the camera origin
+            vertices += path.len() as u64 - 1;
+            if let Some(stats) = row_stats.get_mut(x) { stats.add_path(&path, consts.max_depth); }
         }
-        completed_rows.fetch_add(1, Ordering::Relaxed);
-        print!("{:.2}% complete\r", completed_rows.load(Ordering::Relaxed) as f32 / total_rows * 100.0);
+        _merge_row_stats(aovs, y, &row_stats);
+        parallel::pause();
+        vertices
+    })
+}
+
+// The same pass into the compact buffer of compactAccumulation, keeping the mean of the samples instead of their sum
+#[allow(clippy::too_many_arguments)]
+fn _render_pass_compact(world: &HittableList, lights: &HittableList, environment_map: &Arc<dyn Hittable + Send + Sync>, background: Option<&Arc<dyn Hittable + Send + Sync>>, filter: &(dyn Filter + Send + Sync), cam: &Camera, consts: &Constants, accumulator: &mut MeanAccumulator, adaptive: Option<(&AdaptiveSampling, &mut [PixelEstimate])>, seed: u64, aovs: Option<&Mutex<AovBuffer>>) {
+    let (adaptive, estimates) = adaptive.unzip();
+    let sample_count: u32 = accumulator.samples + 1;
+    _par_rows(&mut accumulator.pixels, consts.width, estimates, |y, row, mut estimates| {
+        utility::seed_stream(seed, y as u64);
+        let mut row_stats: Vec<PathStats> = vec![PathStats::default(); if aovs.is_some() { row.len() } else { 0 }];
+        let mut path: Vec<Vec3A> = Vec::with_capacity(consts.max_depth as usize + 2);
+        for (x, pixel) in row.iter_mut().enumerate() {
+            let mut estimate: Option<&mut PixelEstimate> = estimates.as_deref_mut().map(|estimates| &mut estimates[x]);
+            let Some(sample) = _adaptive_sample(world, lights, environment_map, background, filter, cam, consts, x, y, &mut path, adaptive, estimate.as_deref_mut()) else { continue };
+            // With the adaptive sampling, the mean is over the samples of the pixel itself
+            pixel.add(sample, estimate.map_or(sample_count, |estimate| estimate.samples));
+            if let Some(stats) = row_stats.get_mut(x) { stats.add_path(&path, consts.max_depth); }
+        }
+        _merge_row_stats(aovs, y, &row_stats);
+        parallel::pause();
+        0
     });
-    // Save the image
-    safe_img.lock().unwrap().save(filename).unwrap();
+    accumulator.samples = sample_count;
 }
 
-#[allow(dead_code)]
-pub fn render_power_grid(world: &HittableList, _: Camera, _: &str) {
-    let environment_map: Arc<dyn Hittable + Send + Sync> = load_environment();
+// One camera sample through the pixel, leaving its vertices in the path
+#[allow(clippy::too_many_arguments)]
+fn _pixel_sample(world: &HittableList, lights: &HittableList, environment_map: &Arc<dyn Hittable + Send + Sync>, background: Option<&Arc<dyn Hittable + Send + Sync>>, filter: &(dyn Filter + Send + Sync), cam: &Camera, consts: &Constants, x: usize, y: usize, path: &mut Vec<Vec3A>) -> Color {
+    let state: u64 = utility::random_state();
+    let r: Ray = camera_ray(filter, cam, consts, x, y);
+    path.clear();
+    path.push(r.origin());
+    let color: Color = ray_color(&r, world, lights, environment_map, background, consts, 0, path);
+    // The non-finite samples are dropped by the callers, the report finds out where they came from
+    if unlikely(!color.is_finite()) && nan_report::enabled() { nan_report::record(world, lights, environment_map, background, filter, cam, consts, x, y, state); }
+    color
+}
+
+// A camera ray through the pixel, jittered by the filter
+pub fn camera_ray(filter: &(dyn Filter + Send + Sync), cam: &Camera, consts: &Constants, x: usize, y: usize) -> Ray {
+    let u: f32 = (x as f32 + filter.sample(random_f32())) / (consts.width as f32 - 1.0);
+    let v: f32 = (consts.height as f32 - (y as f32 + filter.sample(random_f32()))) / (consts.height as f32 - 1.0);
+    cam.get_ray_differential(u, v, 1.0 / (consts.width as f32 - 1.0), 1.0 / (consts.height as f32 - 1.0))
+}
+
+fn _merge_row_stats(aovs: Option<&Mutex<AovBuffer>>, y: usize, row_stats: &[PathStats]) {
+    if let Some(aovs) = aovs {
+        let mut aovs = aovs.lock().unwrap();
+        for (total, stats) in aovs.row_mut(y as u32).iter_mut().zip(row_stats) { total.add(stats); }
+    }
+}
+
+// Converts an accumulation buffer holding the given number of samples per pixel to an image of the size of the constants
+pub fn accumulator_to_image(accumulator: &[Color], consts: &Constants, samples: u32) -> ImageBuffer<Rgb<u8>, Vec<u8>> {
+    ImageBuffer::from_fn(consts.width, consts.height, |x, y| to_rgb(accumulator[(y * consts.width + x) as usize], samples as f32))
+}
+
+// With a link budget the power values are calibrated to dBm against the sources alone, in free space
+pub fn power_calibration(world: &HittableList, environment_map: &Arc<dyn Hittable + Send + Sync>) -> Option<f32> {
+    CONSTS.link_budget.map(|link_budget| {
+        let sources: HittableList = get_lights(world);
+        let source = sources.iter().filter_map(|light| light.light_estimate()).max_by(|a, b| a.power.total_cmp(&b.power)).expect("The power grid needs a source to calibrate against");
+        let free_space_lights: HittableList = sample_lights(&sources, sources.clone());
+        let reference_values: Vec<f32> = link_budget.reference_distances(CONSTS.sources_lambda).into_par_iter()
+            .map(|distance| power_at(&(source.center + Vec3A::X * distance), &sources, &free_space_lights, environment_map))
+            .collect();
+        let offset: f32 = link_budget.calibration_offset(&reference_values, CONSTS.sources_lambda);
+        println!("Calibrated to {:.1} dBm at {}m from the source", link_budget.free_space_dbm(link_budget.reference_distance, CONSTS.sources_lambda), link_budget.reference_distance);
+        offset
+    })
+}
+
+pub fn render_power_grid(world: &HittableList, _: Camera, _: &str, options: &ReportOptions) {
+    let (environment_map, _): (Arc<dyn Hittable + Send + Sync>, f32) = load_environment(world, &CONSTS);
     let safe_world: Arc<Vec<Arc<dyn Hittable + Send + Sync>>> = Arc::new(world.clone());
     let mut lights: Vec<Arc<dyn Hittable + Send + Sync>> = get_lights(&world);
-    if CONSTS.environment_intensity.unwrap_or(1.0) > 0.0 { lights.push(environment_map.clone()); }
+    if utility::environment_is_light(&CONSTS) { lights.push(environment_map.clone()); }
     let filter: Box<dyn Filter + Send + Sync> = load_filter();
     println!("Lights: {}", lights.len());
     println!("Chosen Filter: {}", filter);
+    let lights: HittableList = sample_lights(world, lights);
     // The power grid to be calculated will be a square of size 50x50 datapoints.
     let pgsx: usize = 150; // Power Grid Size X [ Must be even and < 2^32 ]
     let pgsy: usize = 10; // Power Grid Size Y [ Must be even and < 2^32 ]
@@ -141,17 +388,7 @@ pub fn render_power_grid(world: &HittableList, _: Camera, _: &str) {
             for r in -pgsx2..=pgsx2 {
                 let offset_position: Vec3A = Vec3A::new(pgts * r as f32, pgts * p as f32, pgts * o as f32);
                 let camera_pos: Vec3A = CONSTS.power_render_center + offset_position;
-                let mut totpow: f32 = 0.0;
-                for _y in 0..(CONSTS.samples_per_pixel * 512) {
-                    let current_ray: Ray = Ray::new(camera_pos, utility::random_in_unit_sphere_uniform_distribution());
-                    let mut path: Vec<Vec3A> = vec![current_ray.origin()];
-                    let curr_color: Color = ray_color(&current_ray, &*safe_world, &lights, &environment_map, 0, &mut path);
-                    // we weight the curr_color by the power of the ray, which is 1 / (4 * pi * r^2), and the sign depends on the phase (either constructive or destructive)
-                    let (phase, power) = calculate_phase_and_power(&path);
-                    // println!("Phase: {}, Power: {}", phase, power);
-                    let curr_color: Color = curr_color * power * -phase.sin(); // togliere -sin() phase se peggiora...
-                    if curr_color.is_finite() { totpow += curr_color.length(); }
-                }
+                let totpow: f32 = power_at(&camera_pos, &safe_world, &lights, &environment_map);
                 // if totpow <= utility::NEAR_ZERO { totpow = utility::NEAR_ZERO; }
                 powergridplane[(p + pgsy2) as usize][(r + pgsx2) as usize] = totpow;
                 // powergridrow[(p + power_grid_size_2) as usize] = totpow;
@@ -189,6 +426,7 @@ pub fn render_power_grid(world: &HittableList, _: Camera, _: &str) {
     }
     power_grid = power_grid_filtered;
 
+    let calibration_offset: Option<f32> = power_calibration(world, &environment_map);
     println!("Now building the decibels representation...");
     let mut power_grid_decibel: Vec<Vec<Vec<f32>>> = vec![vec![vec![0.0; pgsx + 1]; pgsy + 1]; pgsz + 1];
     // let us create an image and save the power grid in it as values of gray
@@ -204,12 +442,21 @@ pub fn render_power_grid(world: &HittableList, _: Camera, _: &str) {
     for o in 0..=pgsz {
         for p in 0..=pgsy {
             for r in 0..=pgsx {
-                // assuming the max is the source power, rewrite values as decibels
+                // calibrated values are in dBm, otherwise assuming the max is the source power, rewrite values as decibels
+                if let Some(offset) = calibration_offset { power_grid_decibel[o][p][r] = LinkBudget::to_dbm(power_grid[o][p][r], offset); }
                 power_grid[o][p][r] /= max; // now they are all between 0 and 1
-                power_grid_decibel[o][p][r] = 10.0 * (power_grid[o][p][r]).log10(); // now they are all in decibels and negative!
+                if calibration_offset.is_none() { power_grid_decibel[o][p][r] = 10.0 * (power_grid[o][p][r]).log10(); } // now they are all in decibels and negative!
             }
         }
     }
+    let volume: Volume = Volume {
+        name: "grid".to_string(),
+        origin: CONSTS.power_render_center - Vec3A::new(pgsx2 as f32, pgsy2 as f32, pgsz2 as f32) * pgts,
+        step: pgts,
+        dims: [pgsx + 1, pgsy + 1, pgsz + 1],
+        values: power_grid_decibel.iter().flatten().flatten().copied().collect(),
+    };
+    power_report::save(&[power_report::report(&volume, if calibration_offset.is_some() { "dBm" } else { "dB" }, options)], "power_grid");
     println!("Now saving the test images...");
     // now we create the image
     let mut img: ImageBuffer<Rgb<u8>, Vec<u8>> = ImageBuffer::new(pgsx as u32 + 1, pgsy as u32 + 1);
@@ -254,59 +501,164 @@ pub fn render_power_grid(world: &HittableList, _: Camera, _: &str) {
     }
 }
 
-// Returns the color of a ray
-pub fn ray_color(r: &Ray, world: &HittableList, lights: &HittableList, envmap: &Arc<dyn Hittable + Sync + Send>, depth: u32, path: &mut Vec<Vec3A>) -> Color {
+/****************** Bounce limits ******************/
+// The depths the paths stop at, the specular and the diffuse bounces being counted apart so that the glass isn't cut short by the limit of the diffuse chains
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BounceLimits {
+    pub max_depth: u32,
+    // Past this depth the paths go through the Russian roulette
+    pub min_depth: u32,
+    pub max_specular_depth: u32,
+    pub max_diffuse_depth: u32,
+}
+
+impl BounceLimits {
+    pub fn from_constants(consts: &Constants) -> BounceLimits {
+        BounceLimits { max_depth: consts.max_depth, min_depth: consts.min_depth, max_specular_depth: consts.max_specular_depth, max_diffuse_depth: consts.max_diffuse_depth }
+    }
+}
+
+// Temporary per-ray data (the PDFs of the bounces), one bump arena per thread
+thread_local! { static ARENA: RefCell<Bump> = RefCell::new(Bump::new()); }
+
+// Returns the color of a ray, traced with the bounces and the sampling of the constants
+#[allow(clippy::too_many_arguments)]
+pub fn ray_color(r: &Ray, world: &HittableList, lights: &HittableList, envmap: &Arc<dyn Hittable + Sync + Send>, background: Option<&Arc<dyn Hittable + Sync + Send>>, consts: &Constants, depth: u32, path: &mut Vec<Vec3A>) -> Color {
+    ARENA.with(|arena| {
+        // A new camera ray frees all of the allocations of the previous path at once
+        if depth == 0 { arena.borrow_mut().reset(); }
+        _ray_color(r, world, lights, envmap, background, consts, &BounceLimits::from_constants(consts), depth, 0, Color::ONE, path, &arena.borrow())
+    })
+}
+
+// The probability that a path carrying the throughput goes on past the bounce, the ones old enough going through the Russian roulette
+#[inline(always)]
+fn _survival(limits: &BounceLimits, depth: u32, throughput: Color) -> f32 {
+    if depth > limits.min_depth { throughput.max_element().min(1.0) } else { 1.0 }
+}
+
+// The diffuse_depth bounces out of depth were diffuse ones, the others specular, the throughput being the weight the path carried up to the ray
+#[allow(clippy::too_many_arguments)]
+fn _ray_color(r: &Ray, world: &HittableList, lights: &HittableList, envmap: &Arc<dyn Hittable + Sync + Send>, background: Option<&Arc<dyn Hittable + Sync + Send>>, consts: &Constants, limits: &BounceLimits, depth: u32, diffuse_depth: u32, throughput: Color, path: &mut Vec<Vec3A>, arena: &Bump) -> Color {
     // If we've exceeded the ray bounce limit, no more light is gathered
-    if unlikely(depth >= CONSTS.max_depth) {
+    if unlikely(depth >= limits.max_depth) {
         path.push(r.origin()); // not sure if needed
+        pixel_debug::record(|| Bounce::miss(depth, Event::DepthLimit, r));
         return Color::new(0.0, 0.0, 0.0);
     }
     // Check for ray-object intersection
-    if let Some(rec) = world.hit(r, utility::NEAR_ZERO, utility::INFINITY) {
+    if let Some(mut rec) = world.hit(r, utility::NEAR_ZERO, utility::INFINITY) {
+        if let Some(differential) = &r.differential { (rec.duvdx, rec.duvdy) = differential.uv_derivatives(&rec); }
+        // Light reaching us from the hit point is absorbed by the medium the ray travelled through
+        let transmittance: Color = r.media.transmittance(rec.t * r.direction().length());
         let emitted: Vec3A = rec.mat_ptr.emitted(rec.u, rec.v, &rec.p);
-        // If the material is light, return the emittance
+        // If the material is light, return the emittance, unless the light isn't linked to the surface the ray left or it's seen from behind
         if rec.mat_ptr.is_light() {
             path.push(rec.p);
-            return emitted; // color * intensity(power)
+            if r.light_mask & rec.link_id == 0 || (rec.mat_ptr.is_one_sided() && !rec.front_face) {
+                pixel_debug::record(|| Bounce::hit(depth, Event::UnlinkedLight, r, &rec));
+                return Color::ZERO;
+            }
+            // The lights behind a chain of caustic casters were already gathered by the manifold sampling at the diffuse bounce before it, when it found this path
+            if r.manifold_chain.is_some_and(|chain| chain.gathered(world, lights, rec.p)) {
+                pixel_debug::record(|| Bounce::hit(depth, Event::ManifoldLight, r, &rec));
+                return Color::ZERO;
+            }
+            pixel_debug::record(|| Bounce::hit(depth, Event::Light, r, &rec).with_emitted(transmittance * emitted));
+            return transmittance * emitted; // color * intensity(power)
         }
         // If the material is not light, we first need to scatter the ray
-        let mut srec: ScatterRecord = ScatterRecord::new();
+        let mut srec: ScatterRecord = ScatterRecord::new(arena);
         // If the ray doesn't scatter, we return the emittance of the object, not scattering means the ray is absorbed by the object
         if !rec.mat_ptr.scatter(r, &rec, &mut srec) {
             path.push(rec.p);
-            return emitted;
+            pixel_debug::record(|| Bounce::hit(depth, Event::Absorbed, r, &rec).with_emitted(transmittance * emitted));
+            return transmittance * emitted;
         }
-        // We Russian Roulette some of the rays that are old enough
+        // We Russian Roulette the paths that are old enough by the throughput they carry, the specular ones further down
 
         //let pathlen: f32 = (path[path.len() - 1] - rec.p).length(); // new
         //let isotropic_power_loss: Vec3A = srec.attenuation * std::f32::consts::E.powf(-0.2 * pathlen); // this is air loss
         //let free_space_power_loss: f32 = ((4.0 * std::f32::consts::PI * pathlen) / utility::CONSTS.sources_lambda).powi(2); // this is free space loss
         //let powerloss: Vec3A = isotropic_power_loss * free_space_power_loss; // this is the total power loss
         
-        if depth > utility::CONSTS.min_depth && utility::random_f32() < srec.attenuation.max_element() { // srec.attenuation.max_element() {
+        let survival: f32 = _survival(limits, depth, throughput * transmittance * srec.attenuation);
+        if !srec.is_specular && survival < 1.0 && utility::random_f32() >= survival {
             path.push(rec.p);
             // srec.attenuation = powerloss; // new
-            return emitted;
+            pixel_debug::record(|| Bounce::hit(depth, Event::RussianRoulette, r, &rec).with_emitted(transmittance * emitted));
+            return transmittance * emitted;
         }
         // If the material is specular, we can just return the color of the specular ray
         if srec.is_specular {
+            if depth - diffuse_depth >= limits.max_specular_depth {
+                path.push(rec.p);
+                pixel_debug::record(|| Bounce::hit(depth, Event::SpecularLimit, r, &rec).with_emitted(transmittance * emitted));
+                return transmittance * emitted;
+            }
+            // The transmission stacks go on as long as they carry light, the paths surviving the roulette making up for the ones it stopped
+            if survival < 1.0 && utility::random_f32() >= survival {
+                path.push(rec.p);
+                pixel_debug::record(|| Bounce::hit(depth, Event::RussianRoulette, r, &rec).with_emitted(transmittance * emitted));
+                return transmittance * emitted;
+            }
             
             //let pathlen: f32 = (path[path.len() - 1] - srec.specular_ray.origin()).length(); // new
             //let isotropic_power_loss: Vec3A = srec.attenuation * std::f32::consts::E.powf(-0.2 * pathlen); // this is air loss
             //let free_space_power_loss: f32 = ((4.0 * std::f32::consts::PI * pathlen) / utility::CONSTS.sources_lambda).powi(2); // this is free space loss
             //srec.attenuation = isotropic_power_loss * free_space_power_loss; // this is the total power loss
             
+            srec.specular_ray.media = rec.mat_ptr.update_media(&r.media, &rec, &srec.specular_ray);
+            srec.specular_ray.differential = r.differential.and_then(|differential| differential.scatter(r, &rec, &srec.specular_ray));
+            srec.specular_ray.light_mask = rec.link_mask;
+            // Refracting through a caustic caster carries on the chain, anything else ends it
+            srec.specular_ray.manifold_chain = r.manifold_chain
+                .filter(|_| srec.specular_ray.direction().dot(rec.normal) < 0.0 && rec.mat_ptr.manifold_refraction(r, &rec).is_some())
+                .map(|chain| chain.refracted());
             path.push(srec.specular_ray.origin());
-            return srec.attenuation * ray_color(&srec.specular_ray, world, lights, envmap, depth + 1, path);
+            pixel_debug::record(|| Bounce::hit(depth, Event::Specular, r, &rec).with_weight(transmittance * srec.attenuation / survival));
+            let weight: Color = transmittance * srec.attenuation / survival;
+            return weight * _ray_color(&srec.specular_ray, world, lights, envmap, None, consts, limits, depth + 1, diffuse_depth, throughput * weight, path, arena);
+        }
+        if diffuse_depth >= limits.max_diffuse_depth {
+            path.push(rec.p);
+            pixel_debug::record(|| Bounce::hit(depth, Event::DiffuseLimit, r, &rec).with_emitted(transmittance * emitted));
+            return transmittance * emitted;
         }
         // We are now in the realm of diffuse materials, we work with PDFs
-        // Not using the PDF classes to improve performance, altough those classes are implemented in the pdf.rs file for reference
-        // let mut scattered: Ray = Ray::new(rec.p, if utility::random_f32() < 0.5 {srec.pdf_ptr.clone().unwrap().generate()} else {lights.random(&rec.p).normalize()});
-        // let pdf: f32 = 0.5 * srec.pdf_ptr.unwrap().value(&scattered.direction()) + 0.5 * lights.pdf_value(&rec.p, &scattered.direction());
-        
-        // ? For the Power Grid Rendering use this!!!
-        let mut scattered: Ray = Ray::new(rec.p, srec.pdf_ptr.clone().unwrap().generate());
-        let pdf: f32 = srec.pdf_ptr.unwrap().value(&scattered.direction());
+        // The pdfs are generic, so the mixture with the lights compiles down to the same code as writing it out by hand
+        let material_pdf: &dyn PDF = srec.pdf_ptr.unwrap();
+        let (mut scattered, pdf): (Ray, f32) = if consts.light_sampling && !lights.is_empty() {
+            _sample_pdf(&rec.p, &MixturePDF::new(material_pdf, HittablePDF::new(rec.p, lights)))
+        } else {
+            // ? For the Power Grid Rendering use this!!!
+            _sample_pdf(&rec.p, &material_pdf)
+        };
+        // A direction towards a light which the material doesn't scatter in has no density to divide by
+        if pdf <= 0.0 {
+            path.push(rec.p);
+            pixel_debug::record(|| Bounce::hit(depth, Event::ZeroPdf, r, &rec).with_emitted(transmittance * emitted));
+            return transmittance * emitted;
+        }
+        scattered.media = rec.mat_ptr.update_media(&r.media, &rec, &scattered);
+        scattered.light_mask = rec.link_mask;
+        // The lights seen through the dielectrics are gathered by solving for the refracted path to a point on them, which the scattered ray then skips
+        let caustics: Color = if consts.manifold_sampling && !lights.is_empty() {
+            scattered.manifold_chain = Some(ManifoldChain::start(&rec, &scattered, &r.media));
+            match manifold::sample(world, lights, &rec, &r.media) {
+                Some((direction, radiance)) => {
+                    let scattering_pdf: f32 = rec.mat_ptr.scattering_pdf(r, &rec, &mut Ray::new(rec.p, direction));
+                    (srec.attenuation * radiance + rec.mat_ptr.fluoresced(&rec, &radiance)) * scattering_pdf
+                },
+                None => Color::ZERO,
+            }
+        } else { Color::ZERO };
+        // The whole environment reaches the surface at once through its spherical harmonics, unshadowed, so the bounce doesn't see it again
+        let ambient: Color = if consts.environment_sh {
+            scattered.environment_visible = false;
+            let irradiance: Color = sh::environment(envmap).irradiance(rec.normal);
+            (srec.attenuation * irradiance + rec.mat_ptr.fluoresced(&rec, &irradiance)) / utility::PI
+        } else { Color::ZERO };
         
         // Finally, we return the color of the scattered ray
         path.push(scattered.origin());
@@ -316,96 +668,154 @@ pub fn ray_color(r: &Ray, world: &HittableList, lights: &HittableList, envmap: &
         //let free_space_power_loss: f32 = ((4.0 * std::f32::consts::PI * pathlen) / utility::CONSTS.sources_lambda).powi(2); // this is free space loss
         //srec.attenuation = isotropic_power_loss * free_space_power_loss; // this is the total power loss
 
-        return emitted
-        + srec.attenuation * rec.mat_ptr.scattering_pdf(r, &rec, &mut scattered)
-        * ray_color(&scattered, world, lights, envmap, depth + 1, path) / pdf;
+        let scattering_pdf: f32 = rec.mat_ptr.scattering_pdf(r, &rec, &mut scattered);
+        pixel_debug::record(|| Bounce::hit(depth, Event::Diffuse, r, &rec).with_emitted(transmittance * (emitted + (ambient + caustics) / survival))
+            .with_weight(transmittance * srec.attenuation * scattering_pdf / (pdf * survival)).with_pdfs(pdf, scattering_pdf));
+        // The incoming light is attenuated by the material, and glowed back in other colors by the fluorescent ones
+        let weight: Color = transmittance * srec.attenuation * scattering_pdf / (pdf * survival);
+        let incoming: Color = _ray_color(&scattered, world, lights, envmap, None, consts, limits, depth + 1, diffuse_depth + 1, throughput * weight, path, arena);
+        // Whatever the path gathers past the emission was left out of the paths the roulette stopped, so the surviving ones make up for it
+        return transmittance * (emitted + (ambient + caustics
+        + (srec.attenuation * incoming + rec.mat_ptr.fluoresced(&rec, &incoming)) * scattering_pdf / pdf) / survival);
     } else {
+        // Camera rays see the background layer, if any, while the bounces are lit by the environment
+        let envmap: &Arc<dyn Hittable + Sync + Send> = background.unwrap_or(envmap);
+        if !r.environment_visible {
+            path.push(r.origin());
+            pixel_debug::record(|| Bounce::miss(depth, Event::Environment, r));
+            return Color::ZERO;
+        }
         if let Some(rec) = envmap.hit(r, utility::NEAR_ZERO, utility::INFINITY) {
             path.push(rec.p);
-            rec.mat_ptr.emitted(rec.u, rec.v, &rec.p)
+            let emitted: Color = rec.mat_ptr.emitted(rec.u, rec.v, &rec.p);
+            pixel_debug::record(|| Bounce::hit(depth, Event::Environment, r, &rec).with_emitted(emitted));
+            emitted
         } else {
             path.push(r.origin());
-            Vec3A::ONE.lerp(utility::BLUE_SKY, 0.5 * (r.direction().normalize().y + 1.0))
+            let sky: Color = Vec3A::ONE.lerp(utility::BLUE_SKY, 0.5 * (r.direction().normalize().y + 1.0));
+            pixel_debug::record(|| Bounce::miss(depth, Event::Environment, r).with_emitted(sky));
+            sky
         }
     }
 }
 
-fn get_lights(world: &HittableList) -> HittableList { world.iter().filter(|x| x.is_light()).cloned().collect() }
+// The scattered ray from the point in a direction drawn from the pdf, and its density
+#[inline(always)]
+fn _sample_pdf<P: PDF>(origin: &Point3, pdf: &P) -> (Ray, f32) {
+    let scattered: Ray = Ray::new(*origin, pdf.generate());
+    let value: f32 = pdf.value(&scattered.direction());
+    (scattered, value)
+}
+
+pub fn get_lights(world: &HittableList) -> HittableList { world.iter().filter(|x| x.is_light()).cloned().collect() }
+
+// Wraps the lights so that each shading point samples them by their estimated contribution
+pub fn sample_lights(world: &HittableList, lights: HittableList) -> HittableList {
+    if lights.len() < 2 { return lights; }
+    vec![Arc::new(LightSampler::new(lights, world.clone()))]
+}
 
 // Inits the scene and returns it as a HittableList
 #[allow(dead_code)]
 pub fn init_scene() -> HittableList {
-    // Materials
-    let material_ground: Lambertian = Lambertian::new(Color::new(0.5, 0.5, 0.5));
-    let material_left: Metal = Metal::new(Color::new(0.3, 0.3, 0.3), 0.1);
-    // let material_right: Metal = Metal::new(Color::new(0.8, 0.6, 0.2), 0.0);
-    let material_high: DiffuseLight = DiffuseLight::new(Color::new(1.0, 1.0, 1.0), 8.0);
-
-    // World
-    let mut world: HittableList = HittableList::new();
-    world.push(Arc::new(Sphere::new(Point3::new(0.0, -1000.0, 0.0), 1000.0, Box::new(material_ground), 0)));
-    world.push(Arc::new(Mesh::new(Point3::new(-1.0, 1.0, 8.0), 2.5, Vec3A::new(90.0, 90.0, 220.0), "models/jet/jet2.obj", Box::new(material_left))));
-    //world.push(Arc::new(Sphere::new(Point3::new(1.5, 0.5, -1.0), 0.5, Box::new(material_right), 0)));
-    world.push(Arc::new(Sphere::new(Point3::new(0.0, 4.0, 0.0), 0.5, Box::new(material_high), 0)));
-    _add_random_world_spheres(&mut world).expect("Failed to add random world spheres");
-
-    let mat1: Dielectric = Dielectric::new(Vec3A::ONE, 1.5, 0.0);
-    world.push(Arc::new(Sphere::new(Point3::new(0.0, 1.0, 0.0), 1.0, Box::new(mat1), 0)));
-    let mat2: Lambertian = Lambertian::new(Color::new(0.4, 0.2, 0.1));
-    world.push(Arc::new(Sphere::new(Point3::new(-4.0, 1.0, 0.0), 1.0, Box::new(mat2), 0)));
-    let mat3: Metal = Metal::new(Color::new(0.7, 0.6, 0.5), 0.0);
-    world.push(Arc::new(Sphere::new(Point3::new(4.0, 1.0, 0.0), 1.0, Box::new(mat3), 0)));
-
-    world
+    SceneBuilder::new()
+        .sphere(Point3::new(0.0, -1000.0, 0.0), 1000.0, MaterialBuilder::lambertian(Color::new(0.5, 0.5, 0.5)))
+        .mesh("models/jet/jet2.obj", Point3::new(-1.0, 1.0, 8.0), 2.5, Vec3A::new(90.0, 90.0, 220.0), MaterialBuilder::metal(Color::new(0.3, 0.3, 0.3), 0.1))
+        .light(Point3::new(0.0, 4.0, 0.0), 0.5, Color::new(1.0, 1.0, 1.0), 8.0)
+        .object(_random_world_spheres(&RandomScene::default()))
+        .sphere(Point3::new(0.0, 1.0, 0.0), 1.0, MaterialBuilder::dielectric(1.5))
+        .sphere(Point3::new(-4.0, 1.0, 0.0), 1.0, MaterialBuilder::lambertian(Color::new(0.4, 0.2, 0.1)))
+        .sphere(Point3::new(4.0, 1.0, 0.0), 1.0, MaterialBuilder::metal(Color::new(0.7, 0.6, 0.5), 0.0))
+        .build()
 }
 
+// The seeded random scene of the parameters, see random_scene.rs
 #[allow(dead_code)]
-pub fn init_random_scene() -> HittableList {
-    let mut world: HittableList = HittableList::new();
-    _add_random_world_spheres(&mut world).expect("Failed to add random world spheres");
-
-    let mat1: Dielectric = Dielectric::new(Vec3A::ONE, 1.5, 0.0);
-    world.push(Arc::new(Sphere::new(Point3::new(0.0, 1.0, 0.0), 1.0, Box::new(mat1), 0)));
-    let mat2: Lambertian = Lambertian::new(Color::new(0.4, 0.2, 0.1));
-    world.push(Arc::new(Sphere::new(Point3::new(-4.0, 1.0, 0.0), 1.0, Box::new(mat2), 0)));
-    let mat3: Metal = Metal::new(Color::new(0.7, 0.6, 0.5), 0.0);
-    world.push(Arc::new(Sphere::new(Point3::new(4.0, 1.0, 0.0), 1.0, Box::new(mat3), 0)));
-
-    let ground_material: Lambertian = Lambertian::new(Color::new(0.5, 0.5, 0.5));
-    world.push(Arc::new(Sphere::new(Point3::new(0.0, -1000.0, 0.0), 1000.0, Box::new(ground_material), 0)));
-
-    world
-}
-
-fn _add_random_world_spheres(world: &mut HittableList) -> Result<(), std::io::Error> {
-    let mut spheres = Vec::<Sphere>::new();
-    for a in -11..11 {
-        for b in -11..11 {
-            let choose_mat: f32 = utility::random_f32();
-            let center: Point3 = Vec3A::new(a as f32 + 0.9 * utility::random_f32(), 0.2, b as f32 + 0.9 * utility::random_f32());
-            if (center - Vec3A::new(4.0, 0.2, 0.0)).length() > 0.9 {
-                if choose_mat < 0.7 {
-                    // Lambertian
-                    let albedo: Color = Vec3A::new(utility::random_f32(), utility::random_f32(), utility::random_f32()) * Vec3A::new(utility::random_f32(), utility::random_f32(), utility::random_f32());
-                    let sphere_material: Lambertian = Lambertian::new(albedo);
-                    spheres.push(Sphere::new(center, 0.2, Box::new(sphere_material), 0));
-                } else if choose_mat < 0.95 {
-                    // Metal
-                    let albedo: Color = Vec3A::new(utility::random_f32_range(0.5, 1.0), utility::random_f32_range(0.5, 1.0), utility::random_f32_range(0.5, 1.0));
-                    let fuzz: f32 = utility::random_f32_range(0.0, 0.5);
-                    let sphere_material: Metal = Metal::new(albedo, fuzz);
-                    spheres.push(Sphere::new(center, 0.2, Box::new(sphere_material), 0));
-                } else {
-                    // Glass
-                    let sphere_material: Dielectric = Dielectric::new(Vec3A::ONE, 1.5, 0.0);
-                    spheres.push(Sphere::new(center, 0.2, Box::new(sphere_material), 0));
-                }
-            }
-        }
-    }
-    let spheres_arr: SphereArray = SphereArray::new(&mut spheres);
-    world.push(Arc::new(spheres_arr));
-    Ok(())
+pub fn init_random_scene(params: &RandomScene) -> HittableList { params.build() }
+
+// The small spheres of the random scene, parsed from their description like the ones of an exported scene
+fn _random_world_spheres(params: &RandomScene) -> Arc<dyn Hittable + Send + Sync> {
+    let spheres: String = serde_yaml::to_string(&params.spheres()).unwrap_or_else(|err| panic!("Failed to serialize the random spheres: {}", err));
+    let spheres: yaml_rust::Yaml = yaml_rust::YamlLoader::load_from_str(&spheres).unwrap().remove(0);
+    parser::parse_world_objects(spheres.as_hash().unwrap()).unwrap_or_else(|err| panic!("{}", err)).remove(0).0
 }
 
 pub fn init_scene_from_yaml(filename: &str) -> HittableList { parser::parse_yaml_scene(filename) }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::{Dielectric, DiffuseLight, Lambertian};
+    use crate::sphere::Sphere;
+
+    #[test]
+    fn test_render_budget() {
+        let budget: RenderBudget = RenderBudget { max_seconds: Some(10.0), max_passes: 64 };
+        assert!(budget.allows_pass(0, 20.0, 20.0));
+        // The next pass would end past the deadline
+        assert!(budget.allows_pass(3, 6.0, 2.0) && !budget.allows_pass(3, 9.0, 2.0));
+        assert!(!budget.allows_pass(64, 0.0, 0.0));
+        let samples_only: RenderBudget = RenderBudget { max_seconds: None, max_passes: 4 };
+        assert!(samples_only.allows_pass(3, 1e6, 1e6) && !samples_only.allows_pass(4, 0.0, 0.0));
+    }
+    #[test]
+    fn test_adaptive_sample() {
+        let consts: Constants = Constants::default();
+        let (world, lights): (HittableList, HittableList) = (HittableList::new(), HittableList::new());
+        let (environment_map, _): (Arc<dyn Hittable + Send + Sync>, f32) = load_environment(&world, &consts);
+        let filter: Box<dyn Filter + Send + Sync> = load_filter();
+        let cam: Camera = Camera::new(&Vec3A::ZERO, &-Vec3A::Z, &Vec3A::Y, 60.0, 1.0, 0.0, 1.0);
+        let adaptive: AdaptiveSampling = AdaptiveSampling { threshold: 0.05, min_samples: 2 };
+        let mut path: Vec<Vec3A> = Vec::new();
+        let mut sample = |adaptive: Option<&AdaptiveSampling>, estimate: Option<&mut PixelEstimate>| _adaptive_sample(&world, &lights, &environment_map, None, filter.as_ref(), &cam, &consts, 0, 0, &mut path, adaptive, estimate);
+        // A sample is added to the running estimate of its pixel, until it converges and the pixel is left out
+        let mut estimate: PixelEstimate = PixelEstimate::default();
+        assert!(sample(Some(&adaptive), Some(&mut estimate)).is_some() && estimate.samples == 1);
+        let converged: PixelEstimate = PixelEstimate { samples: 4, sum: 2.0, sum_squared: 1.0 };
+        let mut left_out: PixelEstimate = converged;
+        assert_eq!(sample(Some(&adaptive), Some(&mut left_out)), None);
+        assert_eq!(left_out, converged);
+        assert!(sample(None, None).is_some());
+    }
+
+    #[test]
+    fn test_bounce_limits() {
+        let consts: Constants = Constants::default();
+        let limits: BounceLimits = BounceLimits { max_depth: 64, min_depth: 0, max_specular_depth: 64, max_diffuse_depth: 2 };
+        let light: Arc<dyn Hittable + Send + Sync> = Arc::new(Sphere::new(Point3::ZERO, 100.0, Box::new(DiffuseLight::new(Color::ONE, 1.0)), 0));
+        let trace = |world: &HittableList, limits: &BounceLimits, path: &mut Vec<Vec3A>| -> Color {
+            path.clear();
+            path.push(Point3::ZERO);
+            _ray_color(&Ray::new(Point3::ZERO, -Vec3A::Z), world, &HittableList::new(), &light, None, &consts, limits, 0, 0, Color::ONE, path, &Bump::new())
+        };
+        // Eight glass shells around the origin, which don't bend its rays, are more bounces than the diffuse ones allowed
+        let glass = |albedo: f32| -> HittableList {
+            (1..=8).map(|radius| Arc::new(Sphere::new(Point3::ZERO, radius as f32, Box::new(Dielectric::new(Color::splat(albedo), 1.0, 0.0)), 0)) as Arc<dyn Hittable + Send + Sync>).collect()
+        };
+        let mut path: Vec<Vec3A> = Vec::new();
+        assert_eq!(trace(&glass(1.0), &limits, &mut path), Color::ONE);
+        assert_eq!(path.len(), 10);
+        // Past the first shell the roulette stops the paths through tinted glass as their throughput drops, and the others make up for them
+        let tinted: HittableList = glass(0.5);
+        for _ in 0..64 {
+            let color: Color = trace(&tinted, &limits, &mut path);
+            assert!(color == Color::ZERO || (color - Color::ONE).abs().max_element() < 1e-4);
+        }
+        let dim: HittableList = glass(0.9);
+        let mean: f32 = (0..4096).map(|_| trace(&dim, &limits, &mut path).x).sum::<f32>() / 4096.0;
+        assert!((mean - 0.9f32.powi(8)).abs() < 0.05, "{}", mean);
+        // A diffuse bounce seen through clear glass goes through the roulette by its albedo, and the surviving paths are weighted up to make up for it
+        let diffuse: HittableList = vec![
+            Arc::new(Sphere::new(Point3::ZERO, 2.0, Box::new(Dielectric::new(Color::ONE, 1.0, 0.0)), 0)),
+            Arc::new(Sphere::new(Point3::new(0.0, 0.0, -5.0), 1.0, Box::new(Lambertian::new(Color::splat(0.5))), 0)),
+        ];
+        let samples: Vec<Color> = (0..4096).map(|_| trace(&diffuse, &limits, &mut path)).collect();
+        assert!(samples.iter().all(|color| *color == Color::ZERO || (*color - Color::ONE).abs().max_element() < 1e-4));
+        let mean: f32 = samples.iter().map(|color| color.x).sum::<f32>() / 4096.0;
+        assert!((mean - 0.5).abs() < 0.05, "{}", mean);
+        // A diffuse chain inside a closed sphere stops at its limit, the roulette being left out
+        let closed: HittableList = vec![Arc::new(Sphere::new(Point3::ZERO, 1.0, Box::new(Lambertian::new(Color::splat(0.5))), 0))];
+        assert_eq!(trace(&closed, &BounceLimits { min_depth: 64, ..limits }, &mut path), Color::ZERO);
+        assert_eq!(path.len(), 4);
+    }
+}
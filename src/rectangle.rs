@@ -6,12 +6,15 @@ use bvh::aabb::{AABB, Bounded};
 use bvh::bounding_hierarchy::BHShape;
 use bvh::Point3 as BVHPoint3;
 
-use glam::Vec3A;
+use glam::{Vec2, Vec3A};
 
+use crate::diffraction::{self, Edge};
+use crate::gpu_scene::{self, FlatTriangle};
 use crate::point3::Point3;
 use crate::ray::Ray;
 use crate::hit_record::HitRecord;
 use crate::hittable_list::Hittable;
+use crate::light_sampler::LightEstimate;
 use crate::material::Material;
 use crate::utility;
 
@@ -23,6 +26,30 @@ pub enum Rectangle {
     XYRectangle(XYRectangle),
 }
 
+impl Rectangle {
+    pub fn area(&self) -> f32 {
+        match self {
+            Rectangle::XZRectangle(xz_rectangle) => xz_rectangle.area(),
+            Rectangle::YZRectangle(yz_rectangle) => yz_rectangle.area(),
+            Rectangle::XYRectangle(xy_rectangle) => xy_rectangle.area(),
+        }
+    }
+    pub fn with_flipped_normal(self, flipped: bool) -> Rectangle {
+        match self {
+            Rectangle::XZRectangle(xz_rectangle) => Rectangle::XZRectangle(xz_rectangle.with_flipped_normal(flipped)),
+            Rectangle::YZRectangle(yz_rectangle) => Rectangle::YZRectangle(yz_rectangle.with_flipped_normal(flipped)),
+            Rectangle::XYRectangle(xy_rectangle) => Rectangle::XYRectangle(xy_rectangle.with_flipped_normal(flipped)),
+        }
+    }
+    pub fn with_uv_transform(self, uv_transform: UVTransform) -> Rectangle {
+        match self {
+            Rectangle::XZRectangle(xz_rectangle) => Rectangle::XZRectangle(xz_rectangle.with_uv_transform(uv_transform)),
+            Rectangle::YZRectangle(yz_rectangle) => Rectangle::YZRectangle(yz_rectangle.with_uv_transform(uv_transform)),
+            Rectangle::XYRectangle(xy_rectangle) => Rectangle::XYRectangle(xy_rectangle.with_uv_transform(uv_transform)),
+        }
+    }
+}
+
 impl Bounded for Rectangle {
     fn aabb(&self) -> AABB {
         match self {
@@ -51,13 +78,21 @@ impl BHShape for Rectangle {
 }
 
 impl Hittable for Rectangle {
-    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
         match self {
             Rectangle::XZRectangle(xz_rectangle) => xz_rectangle.hit(ray, t_min, t_max),
             Rectangle::YZRectangle(yz_rectangle) => yz_rectangle.hit(ray, t_min, t_max),
             Rectangle::XYRectangle(xy_rectangle) => xy_rectangle.hit(ray, t_min, t_max),
         }
     }
+    fn occluded(&self, ray: &Ray, t_min: f32, t_max: f32) -> bool {
+        match self {
+            Rectangle::XZRectangle(xz_rectangle) => xz_rectangle.occluded(ray, t_min, t_max),
+            Rectangle::YZRectangle(yz_rectangle) => yz_rectangle.occluded(ray, t_min, t_max),
+            Rectangle::XYRectangle(xy_rectangle) => xy_rectangle.occluded(ray, t_min, t_max),
+        }
+    }
+    fn world_bounds(&self) -> Option<AABB> { Some(self.aabb()) }
     fn is_light(&self) -> bool {
         match self {
             Rectangle::XZRectangle(xz_rectangle) => xz_rectangle.is_light(),
@@ -65,6 +100,13 @@ impl Hittable for Rectangle {
             Rectangle::XYRectangle(xy_rectangle) => xy_rectangle.is_light(),
         }
     }
+    fn light_estimate(&self) -> Option<LightEstimate> {
+        match self {
+            Rectangle::XZRectangle(xz_rectangle) => xz_rectangle.light_estimate(),
+            Rectangle::YZRectangle(yz_rectangle) => yz_rectangle.light_estimate(),
+            Rectangle::XYRectangle(xy_rectangle) => xy_rectangle.light_estimate(),
+        }
+    }
     fn pdf_value(&self, o: &crate::point3::Point3, v: &Vec3A) -> f32 {
         match self {
             Rectangle::XZRectangle(xz_rectangle) => xz_rectangle.pdf_value(o, v),
@@ -79,6 +121,47 @@ impl Hittable for Rectangle {
             Rectangle::XYRectangle(xy_rectangle) => xy_rectangle.random(o),
         }
     }
+    fn edges(&self) -> Vec<Edge> {
+        match self {
+            Rectangle::XZRectangle(xz_rectangle) => xz_rectangle.edges(),
+            Rectangle::YZRectangle(yz_rectangle) => yz_rectangle.edges(),
+            Rectangle::XYRectangle(xy_rectangle) => xy_rectangle.edges(),
+        }
+    }
+    fn tessellation(&self) -> Vec<FlatTriangle> {
+        match self {
+            Rectangle::XZRectangle(xz_rectangle) => xz_rectangle.tessellation(),
+            Rectangle::YZRectangle(yz_rectangle) => yz_rectangle.tessellation(),
+            Rectangle::XYRectangle(xy_rectangle) => xy_rectangle.tessellation(),
+        }
+    }
+}
+
+
+/*********************** UV Transform ***********************/
+// Rotation (in degrees, around the center of the face) and then tiling of the texture coordinates of a rectangle
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct UVTransform {
+    pub scale: Vec2,
+    pub rotation: f32,
+}
+
+impl Default for UVTransform {
+    fn default() -> Self { UVTransform { scale: Vec2::ONE, rotation: 0.0 } }
+}
+
+impl UVTransform {
+    pub fn new(scale: Vec2, rotation: f32) -> UVTransform { UVTransform { scale, rotation } }
+    pub fn apply(&self, u: f32, v: f32) -> (f32, f32) {
+        let (sin, cos) = self.rotation.to_radians().sin_cos();
+        let (u, v) = (u - 0.5, v - 0.5);
+        ((cos * u - sin * v + 0.5) * self.scale.x, (sin * u + cos * v + 0.5) * self.scale.y)
+    }
+    // Derivatives of the position along the transformed coordinates, from the ones along the original coordinates
+    pub fn derivatives(&self, dpdu: Vec3A, dpdv: Vec3A) -> (Vec3A, Vec3A) {
+        let (sin, cos) = self.rotation.to_radians().sin_cos();
+        ((dpdu * cos - dpdv * sin) / self.scale.x, (dpdu * sin + dpdv * cos) / self.scale.y)
+    }
 }
 
 
@@ -91,6 +174,9 @@ pub struct XYRectangle {
     y1: f32,
     k: f32,
     material: Box<dyn Material>,
+    // The normal points along -Z instead of +Z, which matters for one sided lights
+    flipped: bool,
+    uv_transform: UVTransform,
     node_index: usize,
 }
 
@@ -99,8 +185,22 @@ unsafe impl Send for XYRectangle {}
 
 impl XYRectangle {
     #[allow(dead_code)]
-    pub fn new(x0: f32, x1: f32, y0: f32, y1: f32, k: f32, material: Box<dyn Material>, node_index: usize) -> Self { XYRectangle { x0, x1, y0, y1, k, material, node_index } }
-    fn _get_xyrect_uv(&self, p: &Vec3A) -> (f32, f32) { ((p.x - self.x0) / (self.x1 - self.x0), (p.y - self.y0) / (self.y1 - self.y0)) }
+    pub fn new(x0: f32, x1: f32, y0: f32, y1: f32, k: f32, material: Box<dyn Material>, node_index: usize) -> Self { XYRectangle { x0, x1, y0, y1, k, material, flipped: false, uv_transform: UVTransform::default(), node_index } }
+    pub fn with_flipped_normal(mut self, flipped: bool) -> Self {
+        self.flipped = flipped;
+        self
+    }
+    pub fn with_uv_transform(mut self, uv_transform: UVTransform) -> Self {
+        self.uv_transform = uv_transform;
+        self
+    }
+    fn _normal(&self) -> Vec3A { if self.flipped { -Vec3A::Z } else { Vec3A::Z } }
+    pub fn area(&self) -> f32 { (self.x1 - self.x0) * (self.y1 - self.y0) }
+    fn _corners(&self) -> [Point3; 4] { [Point3::new(self.x0, self.y0, self.k), Point3::new(self.x1, self.y0, self.k), Point3::new(self.x1, self.y1, self.k), Point3::new(self.x0, self.y1, self.k)] }
+    fn _get_xyrect_uv(&self, p: &Vec3A) -> (f32, f32) {
+        let (u, v) = ((p.x - self.x0) / (self.x1 - self.x0), (p.y - self.y0) / (self.y1 - self.y0));
+        self.uv_transform.apply(u, v)
+    }
 }
 
 impl Bounded for XYRectangle {
@@ -117,34 +217,48 @@ impl BHShape for XYRectangle {
 }
 
 impl Hittable for XYRectangle {
-    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
         let t: f32 = (self.k - ray.origin().z) / ray.direction().z;
         if t < t_min || t > t_max { return None; }
         let xyz: Vec3A = ray.origin() + t * ray.direction();
         if xyz.x < self.x0 || xyz.x > self.x1 || xyz.y < self.y0 || xyz.y > self.y1 { return None; }
         let (u, v) = self._get_xyrect_uv(&xyz);
+        if !self.material.alpha_test(u, v, &xyz) { return None; }
         let mut rec: HitRecord = HitRecord::new(
             ray.at(t),
-            Vec3A::Z,
-            self.material.clone(),
+            self._normal(),
+            self.material.as_ref(),
             t,
             u,
             v,
             false
         );
         rec.set_face_normal(ray, &rec.normal.clone());
+        (rec.dpdu, rec.dpdv) = self.uv_transform.derivatives(Vec3A::new(self.x1 - self.x0, 0.0, 0.0), Vec3A::new(0.0, self.y1 - self.y0, 0.0));
         Some(rec)
     }
     fn is_light(&self) -> bool { self.material.is_light() }
+    fn world_bounds(&self) -> Option<AABB> { Some(self.aabb()) }
+    fn light_estimate(&self) -> Option<LightEstimate> {
+        if !self.material.is_light() { return None; }
+        let center: Point3 = Point3::new((self.x0 + self.x1) / 2.0, (self.y0 + self.y1) / 2.0, self.k);
+        Some(LightEstimate::new(center, Vec3A::new(self.x1 - self.x0, self.y1 - self.y0, 0.0).length() / 2.0, self.area(), self.material.emitted(0.5, 0.5, &center)))
+    }
     fn pdf_value(&self, origin: &Point3, v: &Vec3A) -> f32 {
+        // Directions reaching the back of a one sided light are never lit by it
+        if self.material.is_one_sided() && v.dot(self._normal()) >= 0.0 { return 0.0; }
         let t: f32 = (self.k - origin.z) / v.z;
         if t < utility::NEAR_ZERO || t > f32::INFINITY { return 0.0; }
         let xyz: Vec3A = *origin + t * *v;
         if xyz.x < self.x0 || xyz.x > self.x1 || xyz.y < self.y0 || xyz.y > self.y1 { return 0.0; }
-        let area: f32 = (self.x1 - self.x0) * (self.y1 - self.y0);
         let distance_squared: f32 = t * t * v.length_squared();
         let cosine: f32 = (v.dot(Vec3A::Z) / v.length()).abs();
-        distance_squared / (cosine * area)
+        distance_squared / (cosine * self.area())
+    }
+    fn edges(&self) -> Vec<Edge> { diffraction::polygon_edges(&self._corners(), self._normal()) }
+    fn tessellation(&self) -> Vec<FlatTriangle> {
+        let corners: [Point3; 4] = self._corners();
+        gpu_scene::polygon_triangles(&corners, self._normal(), &corners.map(|corner| { let (u, v) = self._get_xyrect_uv(&corner); Vec2::new(u, v) }))
     }
     fn random(&self, origin: &Point3) -> Vec3A {
         Point3::new(
@@ -165,6 +279,9 @@ pub struct XZRectangle {
     z1: f32,
     k: f32,
     material: Box<dyn Material>,
+    // The normal points along -Y instead of +Y, which matters for one sided lights
+    flipped: bool,
+    uv_transform: UVTransform,
     node_index: usize,
 }
 
@@ -173,8 +290,22 @@ unsafe impl Send for XZRectangle {}
 
 impl XZRectangle {
     #[allow(dead_code)]
-    pub fn new(x0: f32, x1: f32, z0: f32, z1: f32, k: f32, material: Box<dyn Material>, node_index: usize) -> Self { XZRectangle { x0, x1, z0, z1, k, material, node_index } }
-    fn _get_xzrect_uv(&self, p: &Vec3A) -> (f32, f32) { ((p.x - self.x0) / (self.x1 - self.x0), (p.z - self.z0) / (self.z1 - self.z0)) }
+    pub fn new(x0: f32, x1: f32, z0: f32, z1: f32, k: f32, material: Box<dyn Material>, node_index: usize) -> Self { XZRectangle { x0, x1, z0, z1, k, material, flipped: false, uv_transform: UVTransform::default(), node_index } }
+    pub fn with_flipped_normal(mut self, flipped: bool) -> Self {
+        self.flipped = flipped;
+        self
+    }
+    pub fn with_uv_transform(mut self, uv_transform: UVTransform) -> Self {
+        self.uv_transform = uv_transform;
+        self
+    }
+    fn _normal(&self) -> Vec3A { if self.flipped { -Vec3A::Y } else { Vec3A::Y } }
+    pub fn area(&self) -> f32 { (self.x1 - self.x0) * (self.z1 - self.z0) }
+    fn _corners(&self) -> [Point3; 4] { [Point3::new(self.x0, self.k, self.z0), Point3::new(self.x1, self.k, self.z0), Point3::new(self.x1, self.k, self.z1), Point3::new(self.x0, self.k, self.z1)] }
+    fn _get_xzrect_uv(&self, p: &Vec3A) -> (f32, f32) {
+        let (u, v) = ((p.x - self.x0) / (self.x1 - self.x0), (p.z - self.z0) / (self.z1 - self.z0));
+        self.uv_transform.apply(u, v)
+    }
 }
 
 impl Bounded for XZRectangle {
@@ -191,34 +322,48 @@ impl BHShape for XZRectangle {
 }
 
 impl Hittable for XZRectangle {
-    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
         let t: f32 = (self.k - ray.origin().y) / ray.direction().y;
         if t < t_min || t > t_max { return None; }
         let xyz: Vec3A = ray.origin() + t * ray.direction();
         if xyz.x < self.x0 || xyz.x > self.x1 || xyz.z < self.z0 || xyz.z > self.z1 { return None; }
         let (u, v) = self._get_xzrect_uv(&xyz);
+        if !self.material.alpha_test(u, v, &xyz) { return None; }
         let mut rec: HitRecord = HitRecord::new(
             ray.at(t),
-            Vec3A::Y,
-            self.material.clone(),
+            self._normal(),
+            self.material.as_ref(),
             t,
             u,
             v,
             false
         );
         rec.set_face_normal(ray, &rec.normal.clone());
+        (rec.dpdu, rec.dpdv) = self.uv_transform.derivatives(Vec3A::new(self.x1 - self.x0, 0.0, 0.0), Vec3A::new(0.0, 0.0, self.z1 - self.z0));
         Some(rec)
     }
     fn is_light(&self) -> bool { self.material.is_light() }
+    fn world_bounds(&self) -> Option<AABB> { Some(self.aabb()) }
+    fn light_estimate(&self) -> Option<LightEstimate> {
+        if !self.material.is_light() { return None; }
+        let center: Point3 = Point3::new((self.x0 + self.x1) / 2.0, self.k, (self.z0 + self.z1) / 2.0);
+        Some(LightEstimate::new(center, Vec3A::new(self.x1 - self.x0, 0.0, self.z1 - self.z0).length() / 2.0, self.area(), self.material.emitted(0.5, 0.5, &center)))
+    }
     fn pdf_value(&self, origin: &Point3, v: &Vec3A) -> f32 {
+        // Directions reaching the back of a one sided light are never lit by it
+        if self.material.is_one_sided() && v.dot(self._normal()) >= 0.0 { return 0.0; }
         let t: f32 = (self.k - origin.y) / v.y;
         if t < utility::NEAR_ZERO || t > f32::INFINITY { return 0.0; }
         let xyz: Vec3A = *origin + t * *v;
         if xyz.x < self.x0 || xyz.x > self.x1 || xyz.z < self.z0 || xyz.z > self.z1 { return 0.0; }
-        let area: f32 = (self.x1 - self.x0) * (self.z1 - self.z0);
         let distance_squared: f32 = t * t * v.length_squared();
         let cosine: f32 = (v.dot(Vec3A::Y) / v.length()).abs();
-        distance_squared / (cosine * area)
+        distance_squared / (cosine * self.area())
+    }
+    fn edges(&self) -> Vec<Edge> { diffraction::polygon_edges(&self._corners(), self._normal()) }
+    fn tessellation(&self) -> Vec<FlatTriangle> {
+        let corners: [Point3; 4] = self._corners();
+        gpu_scene::polygon_triangles(&corners, self._normal(), &corners.map(|corner| { let (u, v) = self._get_xzrect_uv(&corner); Vec2::new(u, v) }))
     }
     fn random(&self, origin: &Point3) -> Vec3A {
         Point3::new(
@@ -239,6 +384,9 @@ pub struct YZRectangle {
     z1: f32,
     k: f32,
     material: Box<dyn Material>,
+    // The normal points along -X instead of +X, which matters for one sided lights
+    flipped: bool,
+    uv_transform: UVTransform,
     node_index: usize,
 }
 
@@ -247,8 +395,22 @@ unsafe impl Send for YZRectangle {}
 
 impl YZRectangle {
     #[allow(dead_code)]
-    pub fn new(y0: f32, y1: f32, z0: f32, z1: f32, k: f32, material: Box<dyn Material>, node_index: usize) -> Self { YZRectangle { y0, y1, z0, z1, k, material, node_index } }
-    fn _get_yzrect_uv(&self, p: &Vec3A) -> (f32, f32) { ((p.y - self.y0) / (self.y1 - self.y0), (p.z - self.z0) / (self.z1 - self.z0)) }
+    pub fn new(y0: f32, y1: f32, z0: f32, z1: f32, k: f32, material: Box<dyn Material>, node_index: usize) -> Self { YZRectangle { y0, y1, z0, z1, k, material, flipped: false, uv_transform: UVTransform::default(), node_index } }
+    pub fn with_flipped_normal(mut self, flipped: bool) -> Self {
+        self.flipped = flipped;
+        self
+    }
+    pub fn with_uv_transform(mut self, uv_transform: UVTransform) -> Self {
+        self.uv_transform = uv_transform;
+        self
+    }
+    fn _normal(&self) -> Vec3A { if self.flipped { -Vec3A::X } else { Vec3A::X } }
+    pub fn area(&self) -> f32 { (self.y1 - self.y0) * (self.z1 - self.z0) }
+    fn _corners(&self) -> [Point3; 4] { [Point3::new(self.k, self.y0, self.z0), Point3::new(self.k, self.y1, self.z0), Point3::new(self.k, self.y1, self.z1), Point3::new(self.k, self.y0, self.z1)] }
+    fn _get_yzrect_uv(&self, p: &Vec3A) -> (f32, f32) {
+        let (u, v) = ((p.y - self.y0) / (self.y1 - self.y0), (p.z - self.z0) / (self.z1 - self.z0));
+        self.uv_transform.apply(u, v)
+    }
 }
 
 impl Bounded for YZRectangle {
@@ -265,34 +427,48 @@ impl BHShape for YZRectangle {
 }
 
 impl Hittable for YZRectangle {
-    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
         let t: f32 = (self.k - ray.origin().x) / ray.direction().x;
         if t < t_min || t > t_max { return None; }
         let xyz: Vec3A = ray.origin() + t * ray.direction();
         if xyz.y < self.y0 || xyz.y > self.y1 || xyz.z < self.z0 || xyz.z > self.z1 { return None; }
         let (u, v) = self._get_yzrect_uv(&xyz);
+        if !self.material.alpha_test(u, v, &xyz) { return None; }
         let mut rec: HitRecord = HitRecord::new(
             ray.at(t),
-            Vec3A::X,
-            self.material.clone(),
+            self._normal(),
+            self.material.as_ref(),
             t,
             u,
             v,
             false
         );
         rec.set_face_normal(ray, &rec.normal.clone());
+        (rec.dpdu, rec.dpdv) = self.uv_transform.derivatives(Vec3A::new(0.0, self.y1 - self.y0, 0.0), Vec3A::new(0.0, 0.0, self.z1 - self.z0));
         Some(rec)
     }
     fn is_light(&self) -> bool { self.material.is_light() }
+    fn world_bounds(&self) -> Option<AABB> { Some(self.aabb()) }
+    fn light_estimate(&self) -> Option<LightEstimate> {
+        if !self.material.is_light() { return None; }
+        let center: Point3 = Point3::new(self.k, (self.y0 + self.y1) / 2.0, (self.z0 + self.z1) / 2.0);
+        Some(LightEstimate::new(center, Vec3A::new(0.0, self.y1 - self.y0, self.z1 - self.z0).length() / 2.0, self.area(), self.material.emitted(0.5, 0.5, &center)))
+    }
     fn pdf_value(&self, origin: &Point3, v: &Vec3A) -> f32 {
+        // Directions reaching the back of a one sided light are never lit by it
+        if self.material.is_one_sided() && v.dot(self._normal()) >= 0.0 { return 0.0; }
         let t: f32 = (self.k - origin.x) / v.x;
         if t < utility::NEAR_ZERO || t > f32::INFINITY { return 0.0; }
         let xyz: Vec3A = *origin + t * *v;
         if xyz.y < self.y0 || xyz.y > self.y1 || xyz.z < self.z0 || xyz.z > self.z1 { return 0.0; } 
-        let area: f32 = (self.y1 - self.y0) * (self.z1 - self.z0);
         let distance_squared: f32 = t * t * v.length_squared();
         let cosine: f32 = (v.dot(Vec3A::X) / v.length()).abs();
-        distance_squared / (cosine * area)
+        distance_squared / (cosine * self.area())
+    }
+    fn edges(&self) -> Vec<Edge> { diffraction::polygon_edges(&self._corners(), self._normal()) }
+    fn tessellation(&self) -> Vec<FlatTriangle> {
+        let corners: [Point3; 4] = self._corners();
+        gpu_scene::polygon_triangles(&corners, self._normal(), &corners.map(|corner| { let (u, v) = self._get_yzrect_uv(&corner); Vec2::new(u, v) }))
     }
     fn random(&self, origin: &Point3) -> Vec3A {
         Point3::new(
@@ -307,7 +483,7 @@ impl Hittable for YZRectangle {
 mod tests {
     use super::*;
     use crate::color::Color;
-    use crate::material::Lambertian;
+    use crate::material::{DiffuseLight, Lambertian};
     use crate::point3::Point3;
 
     #[test]
@@ -338,4 +514,31 @@ mod tests {
         let ray: Ray = Ray::new(Point3::new(0.0, 0.0, -1.0), Vec3A::new(0.0, 0.0, 1.0));
         assert!(rectangle.hit(&ray, 0.0, 100.0).is_some());
     }
+    #[test]
+    fn test_uv_transform() {
+        let material: Box<dyn Material> = Box::new(Lambertian::new(Color::ONE));
+        let rectangle: XYRectangle = XYRectangle::new(0.0, 2.0, 0.0, 1.0, 0.0, material, 0).with_uv_transform(UVTransform::new(Vec2::new(2.0, 3.0), 90.0));
+        let uv_at = |x: f32, y: f32| -> Vec2 {
+            let rec: HitRecord = rectangle.hit(&Ray::new(Point3::new(x, y, -1.0), Vec3A::Z), 0.0, 100.0).unwrap();
+            Vec2::new(rec.u, rec.v)
+        };
+        // The center stays put under the rotation, and is then scaled
+        assert!((uv_at(1.0, 0.5) - Vec2::new(1.0, 1.5)).length() < 1e-5);
+        // Moving along the derivatives changes a single coordinate by the same amount
+        let rec: HitRecord = rectangle.hit(&Ray::new(Point3::new(0.7, 0.4, -1.0), Vec3A::Z), 0.0, 100.0).unwrap();
+        let (dpdu, dpdv) = (rec.dpdu * 0.01, rec.dpdv * 0.01);
+        assert!((uv_at(0.7 + dpdu.x, 0.4 + dpdu.y) - Vec2::new(rec.u + 0.01, rec.v)).length() < 1e-4);
+        assert!((uv_at(0.7 + dpdv.x, 0.4 + dpdv.y) - Vec2::new(rec.u, rec.v + 0.01)).length() < 1e-4);
+    }
+    #[test]
+    fn test_one_sided_light() {
+        // A ceiling light facing down only emits below itself
+        let material: Box<dyn Material> = Box::new(DiffuseLight::new(Color::ONE, 1.0).with_one_sided(true));
+        let rectangle: XZRectangle = XZRectangle::new(-1.0, 1.0, -1.0, 1.0, 2.0, material, 0).with_flipped_normal(true);
+        let (below, above) = (Point3::new(0.0, 0.0, 0.0), Point3::new(0.0, 4.0, 0.0));
+        assert!(rectangle.pdf_value(&below, &rectangle.random(&below)) > 0.0);
+        assert_eq!(rectangle.pdf_value(&above, &rectangle.random(&above)), 0.0);
+        assert!(rectangle.hit(&Ray::new(below, Vec3A::Y), 0.0, 100.0).unwrap().front_face);
+        assert!(!rectangle.hit(&Ray::new(above, -Vec3A::Y), 0.0, 100.0).unwrap().front_face);
+    }
 }
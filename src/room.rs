@@ -0,0 +1,139 @@
+// Author: Giulian Biolo, github.com/giulianbiolo
+// Date: 16/10/2026
+// Description: This file implements the Room struct, an inverted box whose walls face inwards
+
+use bvh::aabb::{AABB, Bounded};
+use bvh::bounding_hierarchy::BHShape;
+
+use glam::Vec3A;
+
+use crate::ray::Ray;
+use crate::hit_record::HitRecord;
+use crate::diffraction::Edge;
+use crate::gpu_scene::FlatTriangle;
+use crate::hittable_list::Hittable;
+use crate::light_sampler::LightEstimate;
+use crate::material::Material;
+use crate::point3::Point3;
+use crate::bbox::BoxFace;
+use crate::rectangle::{Rectangle, UVTransform};
+use crate::utility;
+
+
+pub struct Room {
+    faces: Vec<Rectangle>,
+    // The emissive walls and their cumulative areas, which are sampled proportionally to their area
+    emitters: Vec<usize>,
+    #[allow(dead_code)]
+    emitters_cdf: Vec<f32>,
+    node_index: usize,
+}
+
+unsafe impl Sync for Room {}
+unsafe impl Send for Room {}
+
+impl Room {
+    // One material and texture coordinates transform per wall in the order of BoxFace::ALL, a None material leaves the wall open
+    pub fn new(center: Point3, dimensions: Vec3A, materials: [Option<Box<dyn Material>>; 6], uv_transforms: [UVTransform; 6]) -> Room {
+        let (min, max) = (center - dimensions / 2.0, center + dimensions / 2.0);
+        let faces: Vec<Rectangle> = BoxFace::ALL.iter().zip(materials).zip(uv_transforms).filter_map(|((face, material), uv_transform)| {
+            // The walls on the positive side of each axis get their normal flipped, so that all of them face the inside
+            let flipped: bool = matches!(face, BoxFace::Ceiling | BoxFace::Right | BoxFace::Back);
+            Some(face.rectangle(min, max, material?).with_flipped_normal(flipped).with_uv_transform(uv_transform))
+        }).collect();
+        let emitters: Vec<usize> = (0..faces.len()).filter(|idx| faces[*idx].is_light()).collect();
+        let emitters_cdf: Vec<f32> = emitters.iter().scan(0.0, |total, idx| { *total += faces[*idx].area(); Some(*total) }).collect();
+        Room { faces, emitters, emitters_cdf, node_index: 0 }
+    }
+}
+
+impl Bounded for Room {
+    fn aabb(&self) -> AABB {
+        self.faces.iter().fold(AABB::empty(), |aabb, face| aabb.join(&face.aabb()))
+    }
+}
+
+impl BHShape for Room {
+    fn set_bh_node_index(&mut self, index: usize) { self.node_index = index; }
+    fn bh_node_index(&self) -> usize { self.node_index }
+}
+
+impl Hittable for Room {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        let mut closest: Option<HitRecord> = None;
+        let mut t_max: f32 = t_max;
+        for face in self.faces.iter() {
+            if let Some(rec) = face.hit(ray, t_min, t_max) {
+                t_max = rec.t;
+                closest = Some(rec);
+            }
+        }
+        closest
+    }
+    fn occluded(&self, ray: &Ray, t_min: f32, t_max: f32) -> bool { self.faces.iter().any(|face| face.occluded(ray, t_min, t_max)) }
+    fn is_light(&self) -> bool { !self.emitters.is_empty() }
+    fn world_bounds(&self) -> Option<AABB> { Some(self.aabb()) }
+    fn light_estimate(&self) -> Option<LightEstimate> { LightEstimate::merge(self.faces.iter().filter_map(|face| face.light_estimate())) }
+    // Only the emissive walls are sampled, weighted by their area
+    fn pdf_value(&self, origin: &Point3, v: &Vec3A) -> f32 {
+        let total_area: f32 = match self.emitters_cdf.last() {
+            Some(area) => *area,
+            None => return 0.0,
+        };
+        self.emitters.iter().enumerate().map(|(idx, face)| {
+            let area: f32 = self.emitters_cdf[idx] - if idx > 0 { self.emitters_cdf[idx - 1] } else { 0.0 };
+            self.faces[*face].pdf_value(origin, v) * area / total_area
+        }).sum()
+    }
+    fn random(&self, origin: &Point3) -> Vec3A {
+        let total_area: f32 = match self.emitters_cdf.last() {
+            Some(area) => *area,
+            None => return Vec3A::X,
+        };
+        let area: f32 = utility::random_f32() * total_area;
+        let idx: usize = self.emitters_cdf.partition_point(|cumulative| *cumulative <= area).min(self.emitters.len() - 1);
+        self.faces[self.emitters[idx]].random(origin)
+    }
+    fn edges(&self) -> Vec<Edge> { self.faces.iter().flat_map(|face| face.edges()).collect() }
+    fn tessellation(&self) -> Vec<FlatTriangle> { self.faces.iter().flat_map(|face| face.tessellation()).collect() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::Color;
+    use crate::material::{DiffuseLight, Lambertian};
+
+    fn _room(open_front: bool) -> Room {
+        let materials: [Option<Box<dyn Material>>; 6] = BoxFace::ALL.map(|face| -> Option<Box<dyn Material>> {
+            match face {
+                BoxFace::Ceiling => Some(Box::new(DiffuseLight::new(Color::ONE, 1.0).with_one_sided(true))),
+                BoxFace::Front if open_front => None,
+                _ => Some(Box::new(Lambertian::new(Color::ONE))),
+            }
+        });
+        Room::new(Point3::ZERO, Vec3A::new(4.0, 2.0, 3.0), materials, [UVTransform::default(); 6])
+    }
+
+    #[test]
+    fn test_room_hit() {
+        let room: Room = _room(true);
+        // From inside, the walls face the ray
+        let rec: HitRecord = room.hit(&Ray::new(Point3::ZERO, -Vec3A::Y), 0.001, f32::INFINITY).unwrap();
+        assert!(rec.front_face && (rec.t - 1.0).abs() < utility::EPSILON);
+        assert!(room.hit(&Ray::new(Point3::ZERO, Vec3A::X), 0.001, f32::INFINITY).unwrap().front_face);
+        // The camera looks in through the open front wall and sees the back one
+        let rec: HitRecord = room.hit(&Ray::new(Point3::new(0.0, 0.0, -10.0), Vec3A::Z), 0.001, f32::INFINITY).unwrap();
+        assert!(rec.front_face && (rec.t - 11.5).abs() < utility::EPSILON);
+    }
+    #[test]
+    fn test_room_light_pdf() {
+        // Only the ceiling emits, so the density integrates to one over the sphere of directions of a point inside
+        let room: Room = _room(false);
+        let origin: Point3 = Point3::new(0.5, -0.3, 0.2);
+        let samples: usize = 20000;
+        let integral: f32 = (0..samples).map(|_| room.pdf_value(&origin, &utility::random_unit_vector())).sum::<f32>() * 4.0 * utility::PI / samples as f32;
+        assert!((integral - 1.0).abs() < 0.05, "{}", integral);
+        assert!(room.random(&origin).y > 0.0);
+    }
+}
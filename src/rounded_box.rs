@@ -0,0 +1,160 @@
+// Author: Giulian Biolo, github.com/giulianbiolo
+// Date: 16/10/2026
+// Description: This file implements the RoundedBox struct, an axis aligned box with rounded edges and corners
+
+use bvh::aabb::{AABB, Bounded};
+use bvh::bounding_hierarchy::BHShape;
+use bvh::Point3 as BVHPoint3;
+
+use glam::Vec3A;
+
+use crate::ray::Ray;
+use crate::hit_record::HitRecord;
+use crate::hittable_list::Hittable;
+use crate::light_sampler::LightEstimate;
+use crate::material::Material;
+use crate::point3::Point3;
+use crate::utility::{self, PI};
+
+
+// The rounded box is the inner box grown by the radius in every direction: its surface is made of 6 flat faces,
+// 12 quarter cylinders along the edges and 8 sphere octants on the corners of the inner box
+pub struct RoundedBox {
+    center: Point3,
+    // Half of the dimensions of the inner box, without the rounding
+    core: Vec3A,
+    radius: f32,
+    material: Box<dyn Material>,
+    node_index: usize,
+}
+
+unsafe impl Sync for RoundedBox {}
+unsafe impl Send for RoundedBox {}
+
+impl RoundedBox {
+    // The radius is capped to half of the smallest dimension, where the box becomes a capsule or a sphere
+    pub fn new(center: Point3, dimensions: Vec3A, radius: f32, material: Box<dyn Material>, node_index: usize) -> RoundedBox {
+        let radius: f32 = radius.clamp(0.0, dimensions.min_element() / 2.0);
+        RoundedBox { center, core: dimensions / 2.0 - radius, radius, material, node_index }
+    }
+    pub fn area(&self) -> f32 {
+        let edges: Vec3A = self.core * 2.0;
+        2.0 * (edges.x * edges.y + edges.y * edges.z + edges.z * edges.x) + 2.0 * PI * self.radius * (edges.x + edges.y + edges.z) + 4.0 * PI * self.radius * self.radius
+    }
+    // Each of the 26 regions around the inner box (one per face, edge and corner, as the signs of the axes they lie on)
+    // is the piece of surface at radius distance from that feature: a pair of planes, a cylinder or a sphere
+    fn _roots(&self, ray: &Ray) -> Vec<f32> {
+        let origin: Vec3A = ray.origin() - self.center;
+        let direction: Vec3A = ray.direction();
+        let tolerance: f32 = utility::EPSILON * (self.core + self.radius).max_element();
+        let mut roots: Vec<f32> = Vec::new();
+        for region in 0..27 {
+            let signs: Vec3A = Vec3A::new((region % 3) as f32 - 1.0, (region / 3 % 3) as f32 - 1.0, (region / 9) as f32 - 1.0);
+            if signs == Vec3A::ZERO { continue; }
+            // Only the axes the feature is fixed on count towards the distance from it
+            let mask: Vec3A = signs.abs();
+            let oc: Vec3A = (origin - signs * self.core) * mask;
+            let d: Vec3A = direction * mask;
+            let (t0, t1) = match utility::solve_quadratic(d.dot(d), oc.dot(d), oc.dot(oc) - self.radius * self.radius) {
+                Some(roots) => roots,
+                None => continue,
+            };
+            for t in [t0, t1] {
+                let p: Vec3A = origin + t * direction;
+                let inside: bool = (0..3).all(|axis| {
+                    if signs[axis] != 0.0 { p[axis] * signs[axis] >= self.core[axis] - tolerance } else { p[axis].abs() <= self.core[axis] + tolerance }
+                });
+                if inside { roots.push(t); }
+            }
+        }
+        roots
+    }
+    fn _normal(&self, p: &Point3) -> Vec3A {
+        let local: Vec3A = *p - self.center;
+        match (local - local.clamp(-self.core, self.core)).try_normalize() {
+            Some(normal) => normal,
+            // Without rounding the normal is the one of the nearest face
+            None => {
+                let distance: Vec3A = local.abs() - self.core;
+                let axis: usize = if distance.x >= distance.y && distance.x >= distance.z { 0 } else if distance.y >= distance.z { 1 } else { 2 };
+                let mut normal: Vec3A = Vec3A::ZERO;
+                normal[axis] = local[axis].signum();
+                normal
+            },
+        }
+    }
+    // Box mapping: the texture is projected along the dominant axis of the normal and covers each side once
+    fn _get_rounded_box_uv(&self, p: &Point3, normal: &Vec3A) -> (f32, f32, Vec3A, Vec3A) {
+        let extent: Vec3A = (self.core + self.radius) * 2.0;
+        let uvw: Vec3A = (*p - self.center) / extent + 0.5;
+        let n: Vec3A = normal.abs();
+        if n.y >= n.x && n.y >= n.z { (uvw.x, uvw.z, Vec3A::new(extent.x, 0.0, 0.0), Vec3A::new(0.0, 0.0, extent.z)) }
+        else if n.x >= n.z { (uvw.y, uvw.z, Vec3A::new(0.0, extent.y, 0.0), Vec3A::new(0.0, 0.0, extent.z)) }
+        else { (uvw.x, uvw.y, Vec3A::new(extent.x, 0.0, 0.0), Vec3A::new(0.0, extent.y, 0.0)) }
+    }
+    // The nearest root in the acceptable range that isn't cut out by the material alpha
+    fn _nearest_root(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<f32> {
+        let mut roots: Vec<f32> = self._roots(ray).into_iter().filter(|root| t_min <= *root && *root <= t_max).collect();
+        roots.sort_by(|t1, t2| t1.partial_cmp(t2).unwrap());
+        roots.into_iter().find(|root| {
+            let p: Point3 = ray.at(*root);
+            let (u, v, _, _) = self._get_rounded_box_uv(&p, &self._normal(&p));
+            self.material.alpha_test(u, v, &p)
+        })
+    }
+}
+
+impl Bounded for RoundedBox {
+    fn aabb(&self) -> AABB {
+        let (min, max) = (self.center - self.core - self.radius, self.center + self.core + self.radius);
+        AABB::with_bounds(BVHPoint3::new(min.x, min.y, min.z), BVHPoint3::new(max.x, max.y, max.z))
+    }
+}
+
+impl BHShape for RoundedBox {
+    fn set_bh_node_index(&mut self, index: usize) { self.node_index = index; }
+    fn bh_node_index(&self) -> usize { self.node_index }
+}
+
+impl Hittable for RoundedBox {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        let root: f32 = self._nearest_root(ray, t_min, t_max)?;
+        let p: Point3 = ray.at(root);
+        let normal: Vec3A = self._normal(&p);
+        let (u, v, dpdu, dpdv) = self._get_rounded_box_uv(&p, &normal);
+        let mut rec: HitRecord = HitRecord::new(p, normal, self.material.as_ref(), root, u, v, false);
+        rec.set_face_normal(ray, &normal);
+        (rec.dpdu, rec.dpdv) = (dpdu, dpdv);
+        Some(rec)
+    }
+    fn occluded(&self, ray: &Ray, t_min: f32, t_max: f32) -> bool { self._nearest_root(ray, t_min, t_max).is_some() }
+    fn is_light(&self) -> bool { self.material.is_light() }
+    fn world_bounds(&self) -> Option<AABB> { Some(self.aabb()) }
+    fn light_estimate(&self) -> Option<LightEstimate> {
+        if !self.material.is_light() { return None; }
+        Some(LightEstimate::new(self.center, (self.core.length() + self.radius).max(utility::NEAR_ZERO), self.area(), self.material.emitted(0.5, 0.5, &self.center)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::Color;
+    use crate::material::Lambertian;
+
+    #[test]
+    fn test_rounded_box_hit() {
+        let rounded_box: RoundedBox = RoundedBox::new(Point3::ZERO, Vec3A::new(4.0, 2.0, 2.0), 0.5, Box::new(Lambertian::new(Color::ONE)), 0);
+        // The flat face
+        let rec: HitRecord = rounded_box.hit(&Ray::new(Point3::new(0.5, 0.2, -5.0), Vec3A::Z), 0.001, f32::INFINITY).unwrap();
+        assert!((rec.t - 4.0).abs() < utility::EPSILON && (rec.normal - -Vec3A::Z).length() < utility::EPSILON);
+        // A rounded corner, reached along its diagonal, is one radius away from the corner of the inner box
+        let corner: Vec3A = Vec3A::new(1.5, 0.5, 0.5);
+        let rec: HitRecord = rounded_box.hit(&Ray::new(corner + Vec3A::ONE * 5.0, -Vec3A::ONE), 0.001, f32::INFINITY).unwrap();
+        assert!((rec.p - (corner + Vec3A::ONE.normalize() * 0.5)).length() < 1e-3 && (rec.normal - Vec3A::ONE.normalize()).length() < 1e-3);
+        // Rays grazing the cut corner of the bounding box miss it, the ones from the inside hit the back of the surface
+        assert!(rounded_box.hit(&Ray::new(Point3::new(1.95, 0.95, -5.0), Vec3A::Z), 0.001, f32::INFINITY).is_none());
+        let rec: HitRecord = rounded_box.hit(&Ray::new(Point3::ZERO, Vec3A::X), 0.001, f32::INFINITY).unwrap();
+        assert!(!rec.front_face && (rec.t - 2.0).abs() < utility::EPSILON);
+    }
+}
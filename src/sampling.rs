@@ -0,0 +1,191 @@
+// Author: Giulian Biolo, github.com/giulianbiolo
+// Date: 16/10/2026
+// Description: This file implements the sampling of directions and points from uniform random numbers, with the densities of each warp
+
+#![allow(dead_code)]
+
+use glam::{Vec2, Vec3A};
+
+use crate::point3::Point3;
+use crate::utility::{self, PI};
+
+// The basis and the pdfs of the scattering, for the materials built on this module
+#[allow(unused_imports)]
+pub use crate::onb::ONB;
+#[allow(unused_imports)]
+pub use crate::pdf::{PDF, CosinePDF};
+
+
+// Every warp maps a pair of uniform numbers in [0, 1) to the domain, so that stratified or low discrepancy points can be used too
+// The directions are around +z, ONB::from_w(normal).local_vec(direction) puts them around a normal
+pub fn random_uniforms() -> Vec2 { Vec2::new(utility::random_f32(), utility::random_f32()) }
+
+/****************** Sphere and hemisphere ******************/
+pub fn uniform_sphere(u: Vec2) -> Vec3A {
+    let phi: f32 = u.x * (2.0 * PI);
+    let z: f32 = u.y * 2.0 - 1.0;
+    let r: f32 = (1.0 - z * z).sqrt();
+    Vec3A::new(r * phi.cos(), r * phi.sin(), z)
+}
+pub fn uniform_sphere_pdf() -> f32 { 1.0 / (4.0 * PI) }
+
+pub fn uniform_hemisphere(u: Vec2) -> Vec3A {
+    let phi: f32 = u.x * (2.0 * PI);
+    let z: f32 = u.y;
+    let r: f32 = (1.0 - z * z).sqrt();
+    Vec3A::new(r * phi.cos(), r * phi.sin(), z)
+}
+pub fn uniform_hemisphere_pdf() -> f32 { 1.0 / (2.0 * PI) }
+
+// Density proportional to the cosine with +z, as a lambertian surface scatters
+pub fn cosine_hemisphere(u: Vec2) -> Vec3A {
+    let z: f32 = (1.0 - u.y).sqrt();
+    let phi: f32 = 2.0 * PI * u.x;
+    Vec3A::new(phi.cos() * u.y.sqrt(), phi.sin() * u.y.sqrt(), z)
+}
+pub fn cosine_hemisphere_pdf(cos_theta: f32) -> f32 { if cos_theta > 0.0 { cos_theta / PI } else { 0.0 } }
+
+/****************** Cone ******************/
+// The directions within the angle of +z whose cosine is cos_theta_max
+pub fn uniform_cone(u: Vec2, cos_theta_max: f32) -> Vec3A {
+    let cos_theta: f32 = 1.0 - u.y * (1.0 - cos_theta_max);
+    let sin_theta: f32 = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+    let phi: f32 = 2.0 * PI * u.x;
+    Vec3A::new(phi.cos() * sin_theta, phi.sin() * sin_theta, cos_theta)
+}
+pub fn uniform_cone_pdf(cos_theta_max: f32) -> f32 { 1.0 / (2.0 * PI * (1.0 - cos_theta_max)) }
+
+/****************** GGX microfacets ******************/
+// The GGX (Trowbridge-Reitz) distribution of the microfacet normals around +z, alpha being the squared perceptual roughness
+pub fn ggx_ndf(cos_theta: f32, alpha: f32) -> f32 {
+    if cos_theta <= 0.0 { return 0.0; }
+    let alpha_squared: f32 = alpha * alpha;
+    let denominator: f32 = cos_theta * cos_theta * (alpha_squared - 1.0) + 1.0;
+    alpha_squared / (PI * denominator * denominator)
+}
+// A microfacet normal with density ggx_ndf times its cosine, inverting the cdf of tan^2(theta) = alpha^2 u / (1 - u)
+pub fn ggx_half_vector(u: Vec2, alpha: f32) -> Vec3A {
+    let cos_theta: f32 = ((1.0 - u.y) / (1.0 + (alpha * alpha - 1.0) * u.y)).max(0.0).sqrt();
+    let sin_theta: f32 = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+    let phi: f32 = 2.0 * PI * u.x;
+    Vec3A::new(phi.cos() * sin_theta, phi.sin() * sin_theta, cos_theta)
+}
+pub fn ggx_half_vector_pdf(cos_theta: f32, alpha: f32) -> f32 { ggx_ndf(cos_theta, alpha) * cos_theta.max(0.0) }
+// The density of the direction reflected about the half vector, from the one of the half vector
+pub fn ggx_reflection_pdf(cos_theta_half: f32, outgoing_dot_half: f32, alpha: f32) -> f32 {
+    if outgoing_dot_half <= 0.0 { return 0.0; }
+    ggx_half_vector_pdf(cos_theta_half, alpha) / (4.0 * outgoing_dot_half)
+}
+// Smith's masking of the microfacets seen at the cosine, the shadowing of both directions being the product of two
+pub fn ggx_smith_g1(cos_theta: f32, alpha: f32) -> f32 {
+    if cos_theta <= 0.0 { return 0.0; }
+    2.0 * cos_theta / (cos_theta + (alpha * alpha + (1.0 - alpha * alpha) * cos_theta * cos_theta).sqrt())
+}
+
+/****************** Sphere solid angle ******************/
+// Cosine of the half angle of the cone of directions from the origin to the sphere, None from inside of it
+pub fn sphere_cos_theta_max(origin: &Point3, center: &Point3, radius: f32) -> Option<f32> {
+    let distance_squared: f32 = (*center - *origin).length_squared();
+    if distance_squared <= radius * radius { return None; }
+    Some((1.0 - radius * radius / distance_squared).max(0.0).sqrt())
+}
+
+// A direction towards the sphere, uniform over the solid angle it covers (over all the directions from inside of it)
+pub fn sphere_solid_angle(u: Vec2, origin: &Point3, center: &Point3, radius: f32) -> Vec3A {
+    match sphere_cos_theta_max(origin, center, radius) {
+        Some(cos_theta_max) => ONB::from_w(&(*center - *origin)).local_vec(&uniform_cone(u, cos_theta_max)),
+        None => uniform_sphere(u),
+    }
+}
+
+// The density of sphere_solid_angle, zero for the directions missing the sphere
+pub fn sphere_solid_angle_pdf(origin: &Point3, center: &Point3, radius: f32, direction: &Vec3A) -> f32 {
+    match sphere_cos_theta_max(origin, center, radius) {
+        Some(cos_theta_max) => {
+            let cos_theta: f32 = direction.normalize().dot((*center - *origin).normalize());
+            if cos_theta < cos_theta_max - 1e-6 { 0.0 } else { uniform_cone_pdf(cos_theta_max) }
+        },
+        None => uniform_sphere_pdf(),
+    }
+}
+
+/****************** Disk and triangle ******************/
+// A point of the unit disk, with Shirley's concentric mapping which keeps the strata compact
+pub fn concentric_disk(u: Vec2) -> Vec2 {
+    let offset: Vec2 = u * 2.0 - Vec2::ONE;
+    if offset == Vec2::ZERO { return Vec2::ZERO; }
+    let (r, theta) = if offset.x.abs() > offset.y.abs() { (offset.x, PI / 4.0 * (offset.y / offset.x)) }
+        else { (offset.y, PI / 2.0 - PI / 4.0 * (offset.x / offset.y)) };
+    Vec2::new(theta.cos(), theta.sin()) * r
+}
+pub fn uniform_disk_pdf() -> f32 { 1.0 / PI }
+
+// The barycentric coordinates of the second and third vertices of a point uniform over a triangle, its density being one over the area
+pub fn uniform_triangle(u: Vec2) -> Vec2 {
+    // The points past the hypotenuse are folded back inside
+    if u.x + u.y > 1.0 { Vec2::ONE - u } else { u }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Monte Carlo estimate of the integral of the pdf over the directions, from uniform sphere samples
+    fn _integral(pdf: impl Fn(Vec3A) -> f32) -> f32 {
+        let n: usize = 64;
+        (0..n * n).map(|idx| pdf(uniform_sphere(Vec2::new((idx % n) as f32 + 0.5, (idx / n) as f32 + 0.5) / n as f32))).sum::<f32>() / (uniform_sphere_pdf() * (n * n) as f32)
+    }
+
+    #[test]
+    fn test_warps() {
+        let cos_theta_max: f32 = 0.75;
+        assert!((_integral(|d| if d.z > 0.0 { uniform_hemisphere_pdf() } else { 0.0 }) - 1.0).abs() < 0.01);
+        assert!((_integral(|d| cosine_hemisphere_pdf(d.z)) - 1.0).abs() < 0.01);
+        assert!((_integral(|d| if d.z >= cos_theta_max { uniform_cone_pdf(cos_theta_max) } else { 0.0 }) - 1.0).abs() < 0.02);
+        for idx in 0..100 {
+            let u: Vec2 = Vec2::new((idx % 10) as f32 / 10.0, (idx / 10) as f32 / 10.0);
+            assert!((uniform_sphere(u).length() - 1.0).abs() < 1e-5 && uniform_hemisphere(u).z >= 0.0);
+            assert!((cosine_hemisphere(u).length() - 1.0).abs() < 1e-5 && uniform_cone(u, cos_theta_max).z >= cos_theta_max - 1e-6);
+            assert!(concentric_disk(u).length() <= 1.0 + 1e-5 && uniform_triangle(u).x + uniform_triangle(u).y <= 1.0);
+        }
+        // A unit sphere twice its radius away covers 2pi(1 - cos(30)) steradians
+        let (origin, center) = (Point3::ZERO, Point3::new(0.0, 0.0, -2.0));
+        assert!((sphere_cos_theta_max(&origin, &center, 1.0).unwrap() - 0.75_f32.sqrt()).abs() < 1e-6);
+        assert!((1.0 / sphere_solid_angle_pdf(&origin, &center, 1.0, &-Vec3A::Z) - 2.0 * PI * (1.0 - 0.75_f32.sqrt())).abs() < 1e-5);
+        assert_eq!(sphere_solid_angle_pdf(&origin, &center, 1.0, &Vec3A::Z), 0.0);
+        assert_eq!(sphere_solid_angle_pdf(&center, &center, 1.0, &Vec3A::Z), uniform_sphere_pdf());
+        // A sphere seen under a cone of cosine 0.75 covers whole strata of the integral
+        let radius: f32 = 2.0 * 0.4375_f32.sqrt();
+        assert!((_integral(|d| sphere_solid_angle_pdf(&origin, &center, radius, &d)) - 1.0).abs() < 0.02);
+        let onb: ONB = ONB::from_w(&Vec3A::X);
+        assert!((onb.local_vec(&Vec3A::Z) - Vec3A::X).length() < 1e-6);
+    }
+
+    // The probability of the half vectors with their cosine in [low, high), integrating the distribution over the cosines
+    fn _ggx_band(alpha: f32, low: f32, high: f32) -> f32 {
+        let steps: usize = 10000;
+        let width: f32 = (high - low) / steps as f32;
+        (0..steps).map(|idx| { let cos_theta: f32 = low + (idx as f32 + 0.5) * width; 2.0 * PI * ggx_half_vector_pdf(cos_theta, alpha) * width }).sum()
+    }
+
+    #[test]
+    fn test_ggx() {
+        for alpha in [0.2, 0.5, 1.0] {
+            // The projected area of the microfacets is the one of the surface
+            assert!((_ggx_band(alpha, 0.0, 1.0) - 1.0).abs() < 1e-3, "{}", alpha);
+            // The sampled half vectors follow the reference distribution: the share of them in each band of cosines is its integral
+            let n: usize = 16384;
+            let samples: Vec<Vec3A> = (0..n).map(|idx| ggx_half_vector(Vec2::new((idx as f32 * 0.618034).fract(), (idx as f32 + 0.5) / n as f32), alpha)).collect();
+            for (low, high) in [(0.0, 0.5), (0.5, 0.8), (0.8, 0.95), (0.95, 1.0)] {
+                let sampled: f32 = samples.iter().filter(|h| h.z >= low && (h.z < high || high == 1.0)).count() as f32 / samples.len() as f32;
+                let reference: f32 = _ggx_band(alpha, low, high);
+                assert!((sampled - reference).abs() < 0.005, "alpha {} band [{}, {}): {} sampled, {} expected", alpha, low, high, sampled, reference);
+            }
+        }
+        // Alpha 1 is uniform over the hemisphere, the half vector straight up being the most likely, and the smooth limit is a mirror
+        assert!((ggx_ndf(0.3, 1.0) - 1.0 / PI).abs() < 1e-6 && ggx_ndf(1.0, 0.2) > ggx_ndf(0.9, 0.2));
+        assert_eq!(ggx_half_vector(Vec2::new(0.3, 0.7), 0.0), Vec3A::Z);
+        assert!((ggx_reflection_pdf(1.0, 1.0, 0.5) - ggx_ndf(1.0, 0.5) / 4.0).abs() < 1e-6);
+        assert!(ggx_smith_g1(1.0, 0.5) > 0.999 && ggx_smith_g1(0.1, 0.5) < ggx_smith_g1(0.1, 0.1));
+    }
+}
@@ -0,0 +1,103 @@
+// Author: Giulian Biolo, github.com/giulianbiolo
+// Date: 16/10/2026
+// Description: This file implements the Scene, a world which can be edited between renders without being rebuilt
+
+use std::sync::Arc;
+
+use glam::Affine3A;
+
+use crate::camera::Camera;
+use crate::hittable_list::{Hittable, HittableList};
+use crate::pick;
+use crate::transform::Transformed;
+
+
+// Stays the same for the life of the object, whatever gets added or removed around it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ObjectId(u64);
+
+// Every object is placed through its own transform, so moving one swaps its wrapper and keeps its geometry and BVH
+// The world of a render is a snapshot, editing the scene afterwards doesn't change it
+#[derive(Default)]
+pub struct Scene {
+    objects: Vec<(ObjectId, Arc<Transformed>)>,
+    next_id: u64,
+}
+
+#[allow(dead_code)]
+impl Scene {
+    pub fn new() -> Scene { Scene::default() }
+    // The objects of a parsed world, where they are
+    pub fn from_world(world: HittableList) -> Scene {
+        let mut scene: Scene = Scene::new();
+        for object in world { scene.add_object(object, Affine3A::IDENTITY); }
+        scene
+    }
+    pub fn add_object(&mut self, object: Arc<dyn Hittable + Send + Sync>, transform: Affine3A) -> ObjectId {
+        let id: ObjectId = ObjectId(self.next_id);
+        self.next_id += 1;
+        self.objects.push((id, Arc::new(Transformed::new(object, transform))));
+        id
+    }
+    // The removed object, None when the scene doesn't have it
+    pub fn remove_object(&mut self, id: ObjectId) -> Option<Arc<dyn Hittable + Send + Sync>> {
+        let idx: usize = self.objects.iter().position(|(object_id, _)| *object_id == id)?;
+        Some(self.objects.remove(idx).1.object().clone())
+    }
+    // Moves the object to the transform (from its own space, not from where it is), false when the scene doesn't have it
+    pub fn update_transform(&mut self, id: ObjectId, transform: Affine3A) -> bool {
+        match self.objects.iter_mut().find(|(object_id, _)| *object_id == id) {
+            Some((_, object)) => {
+                *object = Arc::new(object.with_transform(transform));
+                true
+            },
+            None => false,
+        }
+    }
+    pub fn transform(&self, id: ObjectId) -> Option<Affine3A> { self.objects.iter().find(|(object_id, _)| *object_id == id).map(|(_, object)| object.transform()) }
+    pub fn ids(&self) -> Vec<ObjectId> { self.objects.iter().map(|(id, _)| *id).collect() }
+    pub fn len(&self) -> usize { self.objects.len() }
+    pub fn is_empty(&self) -> bool { self.objects.is_empty() }
+    // The object seen through the pixel (x, y) of a width x height render, for selecting it by clicking on the preview
+    pub fn pick(&self, cam: &Camera, width: u32, height: u32, x: u32, y: u32) -> Option<ObjectId> {
+        pick::pick(&self.world(), cam, width, height, x, y).map(|picked| self.objects[picked.index].0)
+    }
+    // The world to render, sharing the objects with the scene
+    pub fn world(&self) -> HittableList { self.objects.iter().map(|(_, object)| -> Arc<dyn Hittable + Send + Sync> { object.clone() }).collect() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use glam::Vec3A;
+    use crate::color::Color;
+    use crate::material::{DiffuseLight, Lambertian};
+    use crate::point3::Point3;
+    use crate::ray::Ray;
+    use crate::sphere::Sphere;
+
+    #[test]
+    fn test_scene_editing() {
+        let mut scene: Scene = Scene::from_world(vec![Arc::new(Sphere::new(Point3::ZERO, 1.0, Box::new(Lambertian::new(Color::ONE)), 0))]);
+        let light = scene.add_object(Arc::new(Sphere::new(Point3::ZERO, 0.5, Box::new(DiffuseLight::new(Color::ONE, 1.0)), 0)), Affine3A::from_translation(Vec3A::new(0.0, 5.0, 0.0).into()));
+        let ray: Ray = Ray::new(Point3::new(0.0, 0.0, 5.0), -Vec3A::Z);
+        let before: HittableList = scene.world();
+        // Moving the sphere out of the way and scaling it up, the snapshot taken before still has it in place
+        let sphere = scene.ids()[0];
+        assert!(scene.update_transform(sphere, Affine3A::from_scale_rotation_translation(glam::Vec3::splat(2.0), glam::Quat::IDENTITY, glam::Vec3::new(10.0, 0.0, 0.0))));
+        assert!((before.hit(&ray, 0.001, 100.0).unwrap().t - 4.0).abs() < 1e-4);
+        let after: HittableList = scene.world();
+        assert!(after.hit(&ray, 0.001, 100.0).is_none());
+        let rec = after.hit(&Ray::new(Point3::new(10.0, 0.0, 5.0), -Vec3A::Z), 0.001, 100.0).unwrap();
+        assert!((rec.t - 3.0).abs() < 1e-4 && (rec.normal - Vec3A::Z).length() < 1e-4);
+        // The light is where it was moved to, and can be removed
+        let estimate = after[1].light_estimate().unwrap();
+        assert!((estimate.center - Point3::new(0.0, 5.0, 0.0)).length() < 1e-4);
+        assert!(scene.remove_object(light).unwrap().is_light());
+        assert!(scene.remove_object(light).is_none() && !scene.update_transform(light, Affine3A::IDENTITY));
+        assert_eq!(scene.len(), 1);
+        // The sphere is picked where it was moved to, by its id
+        let cam: crate::camera::Camera = crate::camera::Camera::new(&Vec3A::new(10.0, 0.0, 5.0), &Vec3A::new(10.0, 0.0, 0.0), &Vec3A::Y, 40.0, 1.0, 0.0, 5.0);
+        assert_eq!((scene.pick(&cam, 9, 9, 4, 4), scene.pick(&cam, 9, 9, 0, 0)), (Some(sphere), None));
+    }
+}
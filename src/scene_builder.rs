@@ -0,0 +1,193 @@
+// Author: Giulian Biolo, github.com/giulianbiolo
+// Date: 16/10/2026
+// Description: This file implements the builders of scenes and materials, for putting a world together from code without boxing every material and object by hand
+
+use std::sync::Arc;
+
+use glam::Vec3A;
+
+use crate::acceleration::BVHParams;
+use crate::bbox::BBox;
+use crate::color::Color;
+use crate::hittable_list::{Hittable, HittableList};
+use crate::material::{Material, Lambertian, Metal, Dielectric, Plastic, GGXGlossy, MetallicRoughness, Coated, Conductor, Fresnel, DiffuseLight, AlphaMasked, Occluded, BumpMapped};
+use crate::mesh::Mesh;
+use crate::plane::Plane;
+use crate::point3::Point3;
+use crate::sphere::Sphere;
+use crate::texture::{Texture, SolidColor};
+
+
+enum MaterialKind {
+    Lambertian,
+    Metal { fuzz: f32 },
+    Dielectric { ior: f32, opacity: f32 },
+    Plastic { reflectivity: f32, fuzz: f32 },
+    Glossy { roughness: f32, reflectivity: f32 },
+    MetallicRoughness { metallic: f32, roughness: f32 },
+    Light { intensity: f32 },
+    Conductor(Conductor),
+}
+
+/****************** MaterialBuilder ******************/
+// A material picked by its kind, e.g. MaterialBuilder::metal(color, 0.1).coated(1.5, 0.0), boxed by build()
+pub struct MaterialBuilder {
+    kind: MaterialKind,
+    albedo: Box<dyn Texture>,
+    fresnel: Option<Fresnel>,
+    // Index of refraction and roughness of a clear coat over the material
+    coat: Option<(f32, f32)>,
+    alpha_mask: Option<Box<dyn Texture>>,
+    roughness_map: Option<Box<dyn Texture>>,
+    metallic_roughness_map: Option<Box<dyn Texture>>,
+    occlusion_map: Option<Box<dyn Texture>>,
+    bump_map: Option<(Box<dyn Texture>, f32)>,
+}
+
+#[allow(dead_code)]
+impl MaterialBuilder {
+    fn _new(kind: MaterialKind, albedo: Color) -> MaterialBuilder { MaterialBuilder { kind, albedo: Box::new(SolidColor::new(albedo)), fresnel: None, coat: None, alpha_mask: None, roughness_map: None, metallic_roughness_map: None, occlusion_map: None, bump_map: None } }
+    pub fn lambertian(albedo: Color) -> MaterialBuilder { MaterialBuilder::_new(MaterialKind::Lambertian, albedo) }
+    pub fn metal(albedo: Color, fuzz: f32) -> MaterialBuilder { MaterialBuilder::_new(MaterialKind::Metal { fuzz }, albedo) }
+    // A clear glass, tinted by its albedo
+    pub fn dielectric(ior: f32) -> MaterialBuilder { MaterialBuilder::_new(MaterialKind::Dielectric { ior, opacity: 0.0 }, Color::ONE) }
+    pub fn plastic(albedo: Color, reflectivity: f32, fuzz: f32) -> MaterialBuilder { MaterialBuilder::_new(MaterialKind::Plastic { reflectivity, fuzz }, albedo) }
+    pub fn glossy(albedo: Color, roughness: f32, reflectivity: f32) -> MaterialBuilder { MaterialBuilder::_new(MaterialKind::Glossy { roughness, reflectivity }, albedo) }
+    // The glTF material, the roughness being perceptual
+    pub fn metallic_roughness(albedo: Color, metallic: f32, roughness: f32) -> MaterialBuilder { MaterialBuilder::_new(MaterialKind::MetallicRoughness { metallic, roughness }, albedo) }
+    pub fn light(color: Color, intensity: f32) -> MaterialBuilder { MaterialBuilder::_new(MaterialKind::Light { intensity }, color) }
+    // One of the metals of Conductor::from_name (gold, silver, copper, aluminium)
+    pub fn conductor(metal: &str, fuzz: f32) -> MaterialBuilder { MaterialBuilder::_new(MaterialKind::Conductor(Conductor::from_name(metal, fuzz).unwrap_or_else(|err| panic!("{}", err))), Color::ONE) }
+
+    // Replaces the color of the material (the emitted one for lights) with a texture
+    pub fn with_texture(mut self, texture: Box<dyn Texture>) -> Self {
+        self.albedo = texture;
+        self
+    }
+    pub fn with_albedo(mut self, albedo: Color) -> Self {
+        self.albedo = Box::new(SolidColor::new(albedo));
+        self
+    }
+    pub fn with_opacity(mut self, opacity: f32) -> Self {
+        if let MaterialKind::Dielectric { opacity: ref mut current, .. } = self.kind { *current = opacity; }
+        self
+    }
+    // Only for the dielectrics, conductors and coats, which default to Schlick's approximation (the exact equations for the conductors)
+    pub fn with_fresnel(mut self, fresnel: Fresnel) -> Self {
+        self.fresnel = Some(fresnel);
+        self
+    }
+    pub fn coated(mut self, ior: f32, roughness: f32) -> Self {
+        self.coat = Some((ior, roughness));
+        self
+    }
+    pub fn with_alpha_mask(mut self, mask: Box<dyn Texture>) -> Self {
+        self.alpha_mask = Some(mask);
+        self
+    }
+    // Only for the metals, plastics and glossy materials, the grayscale of the map is the perceptual roughness
+    pub fn with_roughness_map(mut self, roughness_map: Box<dyn Texture>) -> Self {
+        self.roughness_map = Some(roughness_map);
+        self
+    }
+
+    // Only for the metallic roughness materials, the packed texture scaling the metallic by its blue channel and the roughness by its green one
+    pub fn with_metallic_roughness_map(mut self, metallic_roughness_map: Box<dyn Texture>) -> Self {
+        self.metallic_roughness_map = Some(metallic_roughness_map);
+        self
+    }
+    // An ambient occlusion map, multiplied into the diffuse response of any material
+    pub fn with_occlusion_map(mut self, occlusion_map: Box<dyn Texture>) -> Self {
+        self.occlusion_map = Some(occlusion_map);
+        self
+    }
+    // A height texture, scaled up to the height
+    pub fn with_bump_map(mut self, height: Box<dyn Texture>, scale: f32) -> Self {
+        self.bump_map = Some((height, scale));
+        self
+    }
+
+    pub fn build(self) -> Box<dyn Material + Send + Sync> {
+        let albedo: Box<dyn Texture> = self.albedo;
+        let mut material: Box<dyn Material + Send + Sync> = match self.kind {
+            MaterialKind::Lambertian => Box::new(Lambertian::new_texture(albedo)),
+            MaterialKind::Metal { fuzz } => match self.roughness_map {
+                Some(roughness_map) => Box::new(Metal::new_texture(albedo, fuzz).with_roughness_map(roughness_map)),
+                None => Box::new(Metal::new_texture(albedo, fuzz)),
+            },
+            MaterialKind::Dielectric { ior, opacity } => Box::new(Dielectric::new_texture(albedo, ior, opacity).with_fresnel(self.fresnel.unwrap_or(Fresnel::Schlick))),
+            MaterialKind::Plastic { reflectivity, fuzz } => match self.roughness_map {
+                Some(roughness_map) => Box::new(Plastic::new_texture(albedo, reflectivity, fuzz).with_roughness_map(roughness_map)),
+                None => Box::new(Plastic::new_texture(albedo, reflectivity, fuzz)),
+            },
+            MaterialKind::Glossy { roughness, reflectivity } => match self.roughness_map {
+                Some(roughness_map) => Box::new(GGXGlossy::new_texture(albedo, roughness, reflectivity).with_roughness_map(roughness_map)),
+                None => Box::new(GGXGlossy::new_texture(albedo, roughness, reflectivity)),
+            },
+            MaterialKind::MetallicRoughness { metallic, roughness } => match self.metallic_roughness_map {
+                Some(metallic_roughness_map) => Box::new(MetallicRoughness::new_texture(albedo, metallic, roughness).with_metallic_roughness_map(metallic_roughness_map)),
+                None => Box::new(MetallicRoughness::new_texture(albedo, metallic, roughness)),
+            },
+            MaterialKind::Light { intensity } => Box::new(DiffuseLight::new_texture(albedo, intensity)),
+            MaterialKind::Conductor(conductor) => Box::new(conductor.with_fresnel(self.fresnel.unwrap_or(Fresnel::Exact))),
+        };
+        if let Some((ior, roughness)) = self.coat { material = Box::new(Coated::new(material, ior, roughness).with_fresnel(self.fresnel.unwrap_or(Fresnel::Schlick))); }
+        if let Some((height, scale)) = self.bump_map { material = Box::new(BumpMapped::new(material, height, scale)); }
+        if let Some(occlusion_map) = self.occlusion_map { material = Box::new(Occluded::new(material, occlusion_map)); }
+        if let Some(mask) = self.alpha_mask { material = Box::new(AlphaMasked::new(material, mask)); }
+        material
+    }
+}
+
+/****************** SceneBuilder ******************/
+// A world built object by object, e.g. SceneBuilder::new().sphere(center, 1.0, MaterialBuilder::lambertian(color)).light(..).build()
+#[derive(Default)]
+pub struct SceneBuilder {
+    world: HittableList,
+    bvh_params: BVHParams,
+}
+
+#[allow(dead_code)]
+impl SceneBuilder {
+    pub fn new() -> SceneBuilder { SceneBuilder::default() }
+    // The parameters of the BVHs of the meshes added after
+    pub fn with_bvh_params(mut self, bvh_params: BVHParams) -> Self {
+        self.bvh_params = bvh_params;
+        self
+    }
+    pub fn object(mut self, object: Arc<dyn Hittable + Send + Sync>) -> Self {
+        self.world.push(object);
+        self
+    }
+    pub fn sphere(self, center: Point3, radius: f32, material: MaterialBuilder) -> Self { self.object(Arc::new(Sphere::new(center, radius, material.build(), 0))) }
+    pub fn cuboid(self, center: Point3, dimensions: Vec3A, material: MaterialBuilder) -> Self { self.object(Arc::new(BBox::new(center, dimensions, material.build()))) }
+    pub fn plane(self, point: Point3, normal: Vec3A, material: MaterialBuilder) -> Self { self.object(Arc::new(Plane::new(point, normal, material.build()))) }
+    // An OBJ or STL model, scaled and then rotated by the angles (degrees) around the axes
+    pub fn mesh(self, filename: &str, position: Point3, scaling_factor: f32, rotation: Vec3A, material: MaterialBuilder) -> Self {
+        let mesh: Mesh = Mesh::new(position, scaling_factor, rotation, filename, material.build(), &self.bvh_params);
+        self.object(Arc::new(mesh))
+    }
+    // A spherical light of the color times the intensity
+    pub fn light(self, center: Point3, radius: f32, color: Color, intensity: f32) -> Self { self.sphere(center, radius, MaterialBuilder::light(color, intensity)) }
+    pub fn build(self) -> HittableList { self.world }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ray::Ray;
+
+    #[test]
+    fn test_scene_builder() {
+        let world: HittableList = SceneBuilder::new()
+            .sphere(Point3::new(0.0, 0.0, -3.0), 1.0, MaterialBuilder::conductor("gold", 0.0).coated(1.5, 0.0))
+            .cuboid(Point3::new(3.0, 0.0, -3.0), Vec3A::ONE, MaterialBuilder::dielectric(1.5).with_opacity(0.5))
+            .plane(Point3::new(0.0, -1.0, 0.0), Vec3A::Y, MaterialBuilder::lambertian(Color::splat(0.5)))
+            .light(Point3::new(0.0, 5.0, 0.0), 0.5, Color::ONE, 4.0)
+            .build();
+        assert_eq!(world.len(), 4);
+        assert_eq!(world.iter().filter(|object| object.is_light()).count(), 1);
+        let rec = world.hit(&Ray::new(Point3::ZERO, -Vec3A::Z), 0.001, f32::INFINITY).unwrap();
+        assert!((rec.t - 2.0).abs() < 1e-4);
+    }
+}
@@ -0,0 +1,188 @@
+// Author: Giulian Biolo, github.com/giulianbiolo
+// Date: 16/10/2026
+// Description: This file implements the typed scene description, which the YAML and JSON scenes are read into and which can be built and written back from code
+
+use serde::{Deserialize, Serialize};
+use serde_yaml::{Mapping, Value};
+use yaml_rust::{Yaml, YamlEmitter, YamlLoader};
+
+use crate::camera::Camera;
+use crate::hittable_list::HittableList;
+use crate::parser;
+use crate::utility;
+
+
+// A whole scene file, with the same keys as the YAML one
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SceneDesc {
+    // The constants section (width, height, samplesPerPixel, ..), see the README for the keys
+    #[serde(default)]
+    pub constants: Mapping,
+    pub camera: CameraDesc,
+    #[serde(default)]
+    pub world: Vec<ObjectDesc>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CameraDesc {
+    pub look_from: [f32; 3],
+    pub look_at: [f32; 3],
+    pub vup: [f32; 3],
+    // Vertical field of view, in degrees
+    pub vfov: f32,
+    pub aspect_ratio: f32,
+    pub aperture: f32,
+    pub focus_distance: f32,
+    // Grayscale image shaping the bokeh
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub aperture_image: Option<String>,
+}
+
+// An object of the world, its objType and material typed and the keys of its geometry (center, radius, position, ..) kept as they are
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ObjectDesc {
+    pub obj_type: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    // Planes can go without one and use a pattern instead
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub material: Option<MaterialDesc>,
+    #[serde(flatten)]
+    pub params: Mapping,
+}
+
+// A material, its matType and texture typed and the keys of the material (fuzz, refractionIdx, intensity, ..) kept as they are
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MaterialDesc {
+    pub mat_type: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tex_type: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub texture: Option<Value>,
+    #[serde(flatten)]
+    pub params: Mapping,
+}
+
+fn _value(value: impl Serialize) -> Value { serde_yaml::to_value(value).unwrap_or_else(|err| panic!("Failed to serialize the value: {}", err)) }
+
+/****************** Builders ******************/
+// For the library users, the binary only reads the scenes from files
+#[allow(dead_code)]
+impl CameraDesc {
+    pub fn new(look_from: [f32; 3], look_at: [f32; 3], vfov: f32, aspect_ratio: f32) -> CameraDesc {
+        CameraDesc { look_from, look_at, vup: [0.0, 1.0, 0.0], vfov, aspect_ratio, aperture: 0.0, focus_distance: 1.0, aperture_image: None }
+    }
+    pub fn with_focus(mut self, aperture: f32, focus_distance: f32) -> Self {
+        self.aperture = aperture;
+        self.focus_distance = focus_distance;
+        self
+    }
+}
+
+#[allow(dead_code)]
+impl ObjectDesc {
+    pub fn new(obj_type: &str) -> ObjectDesc { ObjectDesc { obj_type: obj_type.to_string(), name: None, material: None, params: Mapping::new() } }
+    pub fn with_name(mut self, name: &str) -> Self {
+        self.name = Some(name.to_string());
+        self
+    }
+    pub fn with_material(mut self, material: MaterialDesc) -> Self {
+        self.material = Some(material);
+        self
+    }
+    // Sets a key of the object, e.g. with("radius", 1.0) or with("center", [0.0, 1.0, 0.0])
+    pub fn with(mut self, key: &str, value: impl Serialize) -> Self {
+        self.params.insert(Value::from(key), _value(value));
+        self
+    }
+}
+
+#[allow(dead_code)]
+impl MaterialDesc {
+    pub fn new(mat_type: &str) -> MaterialDesc { MaterialDesc { mat_type: mat_type.to_string(), tex_type: None, texture: None, params: Mapping::new() } }
+    // A SolidColor texture of the albedo
+    pub fn with_albedo(mut self, albedo: [f32; 3]) -> Self {
+        self.tex_type = Some("SolidColor".to_string());
+        let mut texture: Mapping = Mapping::new();
+        texture.insert(Value::from("albedo"), _value(albedo));
+        self.texture = Some(Value::Mapping(texture));
+        self
+    }
+    pub fn with(mut self, key: &str, value: impl Serialize) -> Self {
+        self.params.insert(Value::from(key), _value(value));
+        self
+    }
+}
+
+#[allow(dead_code)]
+impl SceneDesc {
+    pub fn new(camera: CameraDesc) -> SceneDesc { SceneDesc { constants: Mapping::new(), camera, world: Vec::new() } }
+    // Sets a key of the constants, e.g. with_constant("samplesPerPixel", 64)
+    pub fn with_constant(mut self, key: &str, value: impl Serialize) -> Self {
+        self.constants.insert(Value::from(key), _value(value));
+        self
+    }
+    pub fn with_object(mut self, object: ObjectDesc) -> Self {
+        self.world.push(object);
+        self
+    }
+
+    /****************** Serialization ******************/
+    pub fn from_yaml_str(content: &str) -> Result<SceneDesc, serde_yaml::Error> { serde_yaml::from_str(content) }
+    pub fn to_yaml_string(&self) -> Result<String, serde_yaml::Error> { serde_yaml::to_string(self) }
+    pub fn from_json(content: &str) -> Result<SceneDesc, serde_json::Error> { serde_json::from_str(content) }
+    pub fn to_json(&self) -> Result<String, serde_json::Error> { serde_json::to_string_pretty(self) }
+    // From and to the documents the parser reads
+    pub fn from_yaml(doc: &Yaml) -> Result<SceneDesc, serde_yaml::Error> {
+        let mut content: String = String::new();
+        YamlEmitter::new(&mut content).dump(doc).unwrap_or_else(|err| panic!("Failed to write the YAML document: {:?}", err));
+        SceneDesc::from_yaml_str(&content)
+    }
+    pub fn to_yaml(&self) -> Yaml {
+        let content: String = self.to_yaml_string().unwrap_or_else(|err| panic!("Failed to serialize the scene: {}", err));
+        YamlLoader::load_from_str(&content).unwrap_or_else(|err| panic!("Failed to read back the scene: {}", err)).remove(0)
+    }
+
+    // The constants, camera and world of the scene, as parsed from a scene file
+    pub fn build(&self) -> (utility::Constants, Camera, HittableList) {
+        let doc: Yaml = self.to_yaml();
+        (parser::parse_constants(&doc), parser::parse_camera(&doc), parser::parse_scene(&doc))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scene_desc_round_trip() {
+        let scene: SceneDesc = SceneDesc::new(CameraDesc::new([0.0, 1.0, 5.0], [0.0, 0.0, 0.0], 40.0, 1.0))
+            .with_constant("width", 16).with_constant("height", 16).with_constant("samplesPerPixel", 1)
+            .with_constant("maxDepth", 4).with_constant("minDepth", 1).with_constant("sourcesLambda", 0.1)
+            .with_object(ObjectDesc::new("Sphere").with_name("ball").with("center", [0.0, 0.0, 0.0]).with("radius", 0.5)
+                .with_material(MaterialDesc::new("Metal").with_albedo([0.8, 0.6, 0.2]).with("fuzz", 0.1)));
+        assert_eq!(SceneDesc::from_yaml_str(&scene.to_yaml_string().unwrap()).unwrap(), scene);
+        assert_eq!(SceneDesc::from_json(&scene.to_json().unwrap()).unwrap(), scene);
+        assert_eq!(SceneDesc::from_yaml(&scene.to_yaml()).unwrap(), scene);
+        assert_eq!(scene.to_yaml()["world"][0]["material"]["texture"]["albedo"][1].as_f64().map(|value| value as f32), Some(0.6));
+        let (constants, camera, world) = scene.build();
+        assert_eq!((constants.width, constants.samples_per_pixel, world.len()), (16, 1, 1));
+        assert!((camera.origin - glam::Vec3A::new(0.0, 1.0, 5.0)).length() < utility::EPSILON);
+    }
+
+    #[test]
+    fn test_scene_desc_from_file() {
+        let doc: Yaml = YamlLoader::load_from_str(&std::fs::read_to_string("scene.yaml").unwrap()).unwrap().remove(0);
+        let scene: SceneDesc = SceneDesc::from_yaml(&doc).unwrap();
+        assert_eq!((scene.world.len(), scene.world[1].obj_type.as_str()), (5, "Box"));
+        assert_eq!(scene.world[2].material.as_ref().unwrap().params.get("intensity"), Some(&Value::from(10.0)));
+        // Written back, the scene parses to the same camera and world as the file
+        let rebuilt: Yaml = scene.to_yaml();
+        assert_eq!(parser::parse_camera(&rebuilt).origin, parser::parse_camera(&doc).origin);
+        assert_eq!(parser::parse_scene(&rebuilt).len(), parser::parse_scene(&doc).len());
+        assert_eq!(rebuilt["constants"], doc["constants"]);
+    }
+}
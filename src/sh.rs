@@ -0,0 +1,110 @@
+// Author: Giulian Biolo, github.com/giulianbiolo
+// Date: 16/10/2026
+// Description: This file implements the projection of the environment on the spherical harmonics up to the second band, for a noiseless diffuse ambient light
+
+use std::cell::RefCell;
+use std::sync::{Arc, Weak};
+
+use glam::{Vec2, Vec3A};
+
+use crate::color::Color;
+use crate::hittable_list::Hittable;
+use crate::point3::Point3;
+use crate::ray::Ray;
+use crate::sampling;
+use crate::utility::{self, PI};
+
+
+// The irradiance of a diffuse surface is smooth enough that the first 9 coefficients get it within a few percent (Ramamoorthi and Hanrahan)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SphericalHarmonics {
+    pub coefficients: [Color; 9],
+}
+
+thread_local! {
+    // The environment each render thread last lit with and its projection, projected again when a reload or another render replaces the environment
+    // The weak pointer keeps the allocation of the old environment from being reused by a new one at the same address
+    static ENVIRONMENT: RefCell<Option<(Weak<dyn Hittable + Sync + Send>, SphericalHarmonics)>> = const { RefCell::new(None) };
+}
+
+impl SphericalHarmonics {
+    // The real basis functions at the unit direction
+    pub fn basis(d: Vec3A) -> [f32; 9] {
+        [
+            0.282_095,
+            0.488_603 * d.y, 0.488_603 * d.z, 0.488_603 * d.x,
+            1.092_548 * d.x * d.y, 1.092_548 * d.y * d.z, 0.315_392 * (3.0 * d.z * d.z - 1.0), 1.092_548 * d.x * d.z, 0.546_274 * (d.x * d.x - d.y * d.y),
+        ]
+    }
+    // Integrates the radiance against the basis over a grid of resolution x resolution strata of the sphere
+    pub fn project(radiance: impl Fn(Vec3A) -> Color, resolution: u32) -> SphericalHarmonics {
+        let mut coefficients: [Color; 9] = [Color::ZERO; 9];
+        for idx in 0..resolution * resolution {
+            let u: Vec2 = Vec2::new((idx % resolution) as f32 + 0.5, (idx / resolution) as f32 + 0.5) / resolution as f32;
+            let direction: Vec3A = sampling::uniform_sphere(u);
+            let color: Color = radiance(direction);
+            for (coefficient, basis) in coefficients.iter_mut().zip(SphericalHarmonics::basis(direction)) { *coefficient += color * basis; }
+        }
+        let weight: f32 = 1.0 / (sampling::uniform_sphere_pdf() * (resolution * resolution) as f32);
+        SphericalHarmonics { coefficients: coefficients.map(|coefficient| coefficient * weight) }
+    }
+    // The light the environment sphere emits towards its center
+    pub fn from_environment(environment: &dyn Hittable) -> SphericalHarmonics {
+        SphericalHarmonics::project(|direction| match environment.hit(&Ray::new(Point3::ZERO, direction), utility::NEAR_ZERO, utility::INFINITY) {
+            Some(rec) => rec.mat_ptr.emitted(rec.u, rec.v, &rec.p),
+            None => Color::ZERO,
+        }, 128)
+    }
+    // The smooth approximation of the environment, for the library users
+    #[allow(dead_code)]
+    pub fn radiance(&self, direction: Vec3A) -> Color {
+        SphericalHarmonics::basis(direction.normalize()).iter().zip(self.coefficients).map(|(basis, coefficient)| coefficient * *basis).sum()
+    }
+    // The cosine weighted integral of the radiance over the hemisphere around the normal, the convolution scaling each band
+    pub fn irradiance(&self, normal: Vec3A) -> Color {
+        const BANDS: [f32; 9] = [PI, 2.0 * PI / 3.0, 2.0 * PI / 3.0, 2.0 * PI / 3.0, PI / 4.0, PI / 4.0, PI / 4.0, PI / 4.0, PI / 4.0];
+        let irradiance: Color = SphericalHarmonics::basis(normal.normalize()).iter().zip(self.coefficients).zip(BANDS).map(|((basis, coefficient), band)| coefficient * *basis * band).sum();
+        irradiance.max(Color::ZERO)
+    }
+}
+
+// The projection of the environment map being rendered, computed by the first call of every thread with it
+pub fn environment(environment: &Arc<dyn Hittable + Sync + Send>) -> SphericalHarmonics {
+    ENVIRONMENT.with(|cached| {
+        let mut cached = cached.borrow_mut();
+        match &*cached {
+            Some((projected, harmonics)) if std::ptr::addr_eq(projected.as_ptr(), Arc::as_ptr(environment)) => *harmonics,
+            _ => {
+                let harmonics: SphericalHarmonics = SphericalHarmonics::from_environment(&**environment);
+                *cached = Some((Arc::downgrade(environment), harmonics));
+                harmonics
+            },
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::DiffuseLight;
+    use crate::sphere::Sphere;
+
+    #[test]
+    fn test_sh_irradiance() {
+        // A constant radiance of 1 gives an irradiance of pi whatever the normal
+        let constant: SphericalHarmonics = SphericalHarmonics::project(|_| Color::ONE, 64);
+        assert!((constant.radiance(Vec3A::X) - Color::ONE).length() < 1e-3);
+        assert!((constant.irradiance(Vec3A::new(0.3, -0.5, 0.8)) - Color::splat(PI)).length() < 1e-2);
+        // A radiance growing with the height is in the first two bands, its irradiance being pi plus 2pi/3 times the height of the normal
+        let linear: SphericalHarmonics = SphericalHarmonics::project(|direction| Color::splat(direction.y + 1.0), 64);
+        assert!((linear.irradiance(Vec3A::Y).x - (PI + 2.0 * PI / 3.0)).abs() < 2e-2);
+        assert!((linear.irradiance(-Vec3A::Y).x - (PI - 2.0 * PI / 3.0)).abs() < 2e-2);
+        assert!((linear.radiance(Vec3A::new(0.0, 0.5, 0.5)).x - (1.0 + 0.5_f32.sqrt())).abs() < 1e-2);
+        // Another environment, like the one of a reloaded scene, is projected again
+        let sky = |intensity: f32| -> Arc<dyn Hittable + Sync + Send> { Arc::new(Sphere::new(Point3::ZERO, 10.0, Box::new(DiffuseLight::new(Color::ONE, intensity)), 0)) };
+        let (first, second) = (sky(1.0), sky(2.0));
+        assert!((environment(&first).irradiance(Vec3A::Y) - Color::splat(PI)).length() < 1e-2);
+        assert!((environment(&second).irradiance(Vec3A::Y) - Color::splat(2.0 * PI)).length() < 2e-2);
+        assert!((environment(&first).irradiance(Vec3A::Y) - Color::splat(PI)).length() < 1e-2);
+    }
+}
@@ -0,0 +1,230 @@
+// Author: Giulian Biolo, github.com/giulianbiolo
+// Date: 16/10/2026
+// Description: This file implements the 4-wide sphere and triangle intersection kernels used in the BVH leaves
+
+use bvh::aabb::{AABB, Bounded};
+use bvh::bounding_hierarchy::BHShape;
+use bvh::bvh::{BVH, BVHNode};
+use glam::{BVec4A, Vec4};
+
+use crate::acceleration::{self, BVHParams};
+use crate::point3::Point3;
+use crate::ray::Ray;
+use crate::sphere::Sphere;
+use crate::triangle::Triangle;
+use crate::utility::{EPSILON, INFINITY};
+
+
+pub const LANES: usize = 4;
+
+// The lanes past the end of a packet repeat its last primitive, and are never reported as hits
+fn _lanes<T>(primitives: &[T], f: impl Fn(&T) -> f32) -> Vec4 {
+    Vec4::from_array(std::array::from_fn(|lane| f(&primitives[lane.min(primitives.len() - 1)])))
+}
+
+// Sse is always available when the target enables it, so the intrinsic is safe to call
+#[cfg(all(target_arch = "x86_64", target_feature = "sse"))]
+fn _sqrt(v: Vec4) -> Vec4 { Vec4::from(unsafe { std::arch::x86_64::_mm_sqrt_ps(v.into()) }) }
+#[cfg(not(all(target_arch = "x86_64", target_feature = "sse")))]
+fn _sqrt(v: Vec4) -> Vec4 { Vec4::from_array(v.to_array().map(f32::sqrt)) }
+
+/****************** Sphere4 ******************/
+#[derive(Clone)]
+pub struct Sphere4 {
+    center: [Vec4; 3],
+    radius_squared: Vec4,
+}
+
+impl Sphere4 {
+    pub fn new(spheres: &[Sphere]) -> Sphere4 {
+        Sphere4 {
+            center: [_lanes(spheres, |s| s.center.x), _lanes(spheres, |s| s.center.y), _lanes(spheres, |s| s.center.z)],
+            radius_squared: _lanes(spheres, |s| s.radius.powi(2)),
+        }
+    }
+    // Distance of the nearest root in [t_min, t_max] of every lane, infinity when it misses
+    pub fn intersect(&self, ray: &Ray, t_min: f32, t_max: f32) -> Vec4 {
+        let (origin, direction) = (ray.origin(), ray.direction());
+        let oc: [Vec4; 3] = [Vec4::splat(origin.x) - self.center[0], Vec4::splat(origin.y) - self.center[1], Vec4::splat(origin.z) - self.center[2]];
+        let a: Vec4 = Vec4::splat(direction.dot(direction));
+        let half_b: Vec4 = oc[0] * direction.x + oc[1] * direction.y + oc[2] * direction.z;
+        let c: Vec4 = oc[0] * oc[0] + oc[1] * oc[1] + oc[2] * oc[2] - self.radius_squared;
+        let discriminant: Vec4 = half_b * half_b - a * c;
+        let hit: BVec4A = discriminant.cmpge(Vec4::ZERO);
+        let sqrtd: Vec4 = _sqrt(discriminant.max(Vec4::ZERO));
+        let (near, far) = ((-half_b - sqrtd) / a, (-half_b + sqrtd) / a);
+        let in_range = |t: Vec4| t.cmpge(Vec4::splat(t_min)) & t.cmple(Vec4::splat(t_max));
+        let t: Vec4 = Vec4::select(in_range(near), near, Vec4::select(in_range(far), far, Vec4::splat(INFINITY)));
+        Vec4::select(hit, t, Vec4::splat(INFINITY))
+    }
+}
+
+/****************** Triangle4 ******************/
+#[derive(Clone)]
+pub struct Triangle4 {
+    v0: [Vec4; 3],
+    e1: [Vec4; 3],
+    e2: [Vec4; 3],
+}
+
+impl Triangle4 {
+    pub fn new(triangles: &[Triangle]) -> Triangle4 {
+        let component = |f: fn(&Triangle) -> Point3| -> [Vec4; 3] {
+            [_lanes(triangles, |t| f(t).x), _lanes(triangles, |t| f(t).y), _lanes(triangles, |t| f(t).z)]
+        };
+        Triangle4 {
+            v0: component(|t| t.vertices()[0]),
+            e1: component(|t| t.vertices()[1] - t.vertices()[0]),
+            e2: component(|t| t.vertices()[2] - t.vertices()[0]),
+        }
+    }
+    // Moller-Trumbore on every lane at once, the same tests as Triangle::hit
+    pub fn intersect(&self, ray: &Ray, t_min: f32, t_max: f32) -> Vec4 {
+        let (origin, direction) = (ray.origin(), ray.direction());
+        let d: [Vec4; 3] = [Vec4::splat(direction.x), Vec4::splat(direction.y), Vec4::splat(direction.z)];
+        let cross = |a: &[Vec4; 3], b: &[Vec4; 3]| -> [Vec4; 3] { [a[1] * b[2] - a[2] * b[1], a[2] * b[0] - a[0] * b[2], a[0] * b[1] - a[1] * b[0]] };
+        let dot = |a: &[Vec4; 3], b: &[Vec4; 3]| -> Vec4 { a[0] * b[0] + a[1] * b[1] + a[2] * b[2] };
+        let p: [Vec4; 3] = cross(&d, &self.e2);
+        let det: Vec4 = dot(&self.e1, &p);
+        let mut hit: BVec4A = det.abs().cmpge(Vec4::splat(EPSILON));
+        let inv_det: Vec4 = Vec4::ONE / det;
+        let s: [Vec4; 3] = [Vec4::splat(origin.x) - self.v0[0], Vec4::splat(origin.y) - self.v0[1], Vec4::splat(origin.z) - self.v0[2]];
+        let u: Vec4 = inv_det * dot(&s, &p);
+        hit &= u.cmpge(Vec4::ZERO) & u.cmple(Vec4::ONE);
+        let q: [Vec4; 3] = cross(&s, &self.e1);
+        let v: Vec4 = inv_det * dot(&d, &q);
+        hit &= v.cmpge(Vec4::ZERO) & (u + v).cmple(Vec4::ONE);
+        let t: Vec4 = inv_det * dot(&self.e2, &q);
+        hit &= t.cmpgt(Vec4::splat(t_min)) & t.cmplt(Vec4::splat(t_max));
+        Vec4::select(hit, t, Vec4::splat(INFINITY))
+    }
+}
+
+/****************** Packet ******************/
+// A BVH leaf holding up to LANES consecutive primitives, tested together by the kernel
+#[derive(Clone)]
+pub struct Packet<K> {
+    pub kernel: K,
+    pub first: usize,
+    pub count: usize,
+    // The indices of its primitives before they were reordered, for the textures that vary per primitive
+    pub primitives: [u32; LANES],
+    aabb: AABB,
+    node_index: usize,
+}
+
+impl<K> Packet<K> {
+    // Reorders the primitives so that every group of leaf_groups is contiguous, and builds a packet for each group
+    pub fn build<T: Bounded + BHShape>(primitives: Vec<T>, params: &BVHParams, kernel: impl Fn(&[T]) -> K) -> (Vec<T>, Vec<Packet<K>>) {
+        let mut primitives: Vec<T> = primitives;
+        let groups: Vec<Vec<usize>> = leaf_groups(&mut primitives, params);
+        let mut slots: Vec<Option<T>> = primitives.into_iter().map(Some).collect();
+        let sorted: Vec<T> = groups.iter().flatten().map(|idx| slots[*idx].take().unwrap()).collect();
+        let mut first: usize = 0;
+        let packets: Vec<Packet<K>> = groups.iter().map(|group| {
+            let chunk: &[T] = &sorted[first..first + group.len()];
+            let packet: Packet<K> = Packet {
+                kernel: kernel(chunk),
+                first,
+                count: chunk.len(),
+                primitives: std::array::from_fn(|lane| group.get(lane).map_or(0, |idx| *idx as u32)),
+                aabb: chunk.iter().fold(AABB::empty(), |aabb, primitive| aabb.join(&primitive.aabb())),
+                node_index: 0,
+            };
+            first += group.len();
+            packet
+        }).collect();
+        (sorted, packets)
+    }
+    // The lanes the kernel reported as hit, nearest first
+    pub fn hit_lanes(&self, distances: Vec4) -> impl Iterator<Item = usize> {
+        let mut remaining: [f32; LANES] = distances.to_array();
+        remaining[self.count..].fill(INFINITY);
+        std::iter::from_fn(move || {
+            let (lane, distance) = remaining.iter().enumerate().min_by(|a, b| a.1.total_cmp(b.1))?;
+            if *distance == INFINITY { return None; }
+            remaining[lane] = INFINITY;
+            Some(lane)
+        })
+    }
+}
+
+impl<K> Bounded for Packet<K> {
+    fn aabb(&self) -> AABB { self.aabb }
+}
+
+impl<K> BHShape for Packet<K> {
+    fn set_bh_node_index(&mut self, index: usize) { self.node_index = index; }
+    fn bh_node_index(&self) -> usize { self.node_index }
+}
+
+// Groups of at most max_leaf_primitives primitives, the subtrees of a BVH built over them, so that each packet is as tight as possible
+pub fn leaf_groups<T: BHShape>(primitives: &mut [T], params: &BVHParams) -> Vec<Vec<usize>> {
+    if primitives.is_empty() { return Vec::new(); }
+    let bvh: BVH = acceleration::build(primitives, params);
+    // The nodes are stored after their parents, so the leaf counts can be summed up in reverse
+    let mut leaves: Vec<usize> = vec![0; bvh.nodes.len()];
+    for node in (0..bvh.nodes.len()).rev() {
+        leaves[node] = match bvh.nodes[node] {
+            BVHNode::Leaf { .. } => 1,
+            BVHNode::Node { child_l_index, child_r_index, .. } => leaves[child_l_index] + leaves[child_r_index],
+        };
+    }
+    let collect = |root: usize| -> Vec<usize> {
+        let (mut group, mut stack) = (Vec::with_capacity(params.max_leaf_primitives), vec![root]);
+        while let Some(node) = stack.pop() {
+            match bvh.nodes[node] {
+                BVHNode::Leaf { shape_index, .. } => group.push(shape_index),
+                BVHNode::Node { child_l_index, child_r_index, .. } => { stack.push(child_r_index); stack.push(child_l_index); },
+            }
+        }
+        group
+    };
+    let (mut groups, mut stack) = (Vec::new(), vec![0]);
+    while let Some(node) = stack.pop() {
+        match bvh.nodes[node] {
+            BVHNode::Node { child_l_index, child_r_index, .. } if leaves[node] > params.max_leaf_primitives => { stack.push(child_r_index); stack.push(child_l_index); },
+            _ => groups.push(collect(node)),
+        }
+    }
+    groups
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use glam::Vec3A;
+    use crate::hittable_list::Hittable;
+    use crate::material::Lambertian;
+    use crate::color::Color;
+
+    #[test]
+    fn test_sphere4() {
+        let spheres: Vec<Sphere> = (0..3).map(|i| Sphere::new(Point3::new(i as f32 * 2.0, 0.0, -5.0), 0.5 + 0.2 * i as f32, Box::new(Lambertian::new(Color::ONE)), 0)).collect();
+        let packet: Sphere4 = Sphere4::new(&spheres);
+        for direction in [Vec3A::new(0.0, 0.0, -1.0), Vec3A::new(0.4, 0.0, -1.0), Vec3A::new(0.8, 0.05, -1.0), Vec3A::new(0.0, 1.0, 0.0)] {
+            let ray: Ray = Ray::new(Point3::ZERO, direction);
+            let distances: Vec4 = packet.intersect(&ray, 0.001, INFINITY);
+            for (lane, sphere) in spheres.iter().enumerate() {
+                let expected: f32 = sphere.hit(&ray, 0.001, INFINITY).map_or(INFINITY, |rec| rec.t);
+                assert!((distances[lane] - expected).abs() < 1e-4 || distances[lane] == expected);
+            }
+        }
+    }
+    #[test]
+    fn test_triangle4() {
+        let triangles: Vec<Triangle> = (0..4).map(|i| {
+            let offset: Vec3A = Vec3A::new(i as f32 * 0.5, 0.0, -1.0 - i as f32);
+            Triangle::new(Box::new([offset, offset + Vec3A::X, offset + Vec3A::Y]), Box::new([Vec3A::ZERO; 3]), Box::new(Lambertian::new(Color::ONE)), 0)
+        }).collect();
+        let packet: Triangle4 = Triangle4::new(&triangles);
+        for direction in [Vec3A::new(0.1, 0.1, -1.0), Vec3A::new(0.3, 0.05, -1.0), Vec3A::new(-0.5, 0.1, -1.0)] {
+            let ray: Ray = Ray::new(Point3::ZERO, direction);
+            let distances: Vec4 = packet.intersect(&ray, 0.001, INFINITY);
+            for (lane, triangle) in triangles.iter().enumerate() {
+                let expected: f32 = triangle.hit(&ray, 0.001, INFINITY).map_or(INFINITY, |rec| rec.t);
+                assert_eq!(distances[lane], expected);
+            }
+        }
+    }
+}
@@ -6,14 +6,20 @@ use bvh::aabb::{AABB, Bounded};
 use bvh::bounding_hierarchy::BHShape;
 use bvh::Point3 as BVHPoint3;
 
-use glam::Vec3A;
+use glam::{Vec2, Vec3A};
 
-use crate::onb::ONB;
+use crate::acceleration::BVHParams;
+use crate::gpu_scene::{self, FlatTriangle};
+use crate::mesh::Mesh;
 use crate::ray::Ray;
 use crate::hit_record::HitRecord;
 use crate::hittable_list::Hittable;
+use crate::light_sampler::LightEstimate;
 use crate::material::Material;
 use crate::point3::Point3;
+use crate::sampling;
+use crate::texture::Texture;
+use crate::triangle::Triangle;
 use crate::utility;
 
 
@@ -22,6 +28,9 @@ pub struct Sphere {
     pub center: Point3,
     pub radius: f32,
     material: Box<dyn Material>,
+    // Longitude/latitude mapping: how many times the texture repeats and the longitude offset in degrees
+    uv_tiling: Vec2,
+    uv_rotation: f32,
     node_index: usize,
 }
 
@@ -30,10 +39,80 @@ unsafe impl Send for Sphere {}
 
 impl Sphere {
     #[allow(dead_code)]
-    pub fn new(center: Point3, radius: f32, material: Box<dyn Material>, node_index: usize) -> Sphere { Sphere { center, radius, material, node_index } }
+    pub fn new(center: Point3, radius: f32, material: Box<dyn Material>, node_index: usize) -> Sphere { Sphere { center, radius, material, uv_tiling: Vec2::ONE, uv_rotation: 0.0, node_index } }
+    #[allow(dead_code)]
+    pub fn set_material(&mut self, material: Box<dyn Material>) { self.material = material; }
+    pub fn with_uv_mapping(mut self, uv_tiling: Vec2, uv_rotation: f32) -> Sphere {
+        self.uv_tiling = uv_tiling;
+        self.uv_rotation = uv_rotation;
+        self
+    }
     fn _get_sphere_uv(&self, p: &Vec3A) -> (f32, f32) {
         let unit_p: Vec3A = (*p - self.center) / self.radius;
-        (((-unit_p.z).atan2(unit_p.x) + utility::PI) / (2.0 * utility::PI), ((-unit_p.y).acos()) / utility::PI)
+        self._map_uv(((-unit_p.z).atan2(unit_p.x) + utility::PI) / (2.0 * utility::PI), ((-unit_p.y).acos()) / utility::PI)
+    }
+    // Derivatives of the position along the uvs of _get_sphere_uv, degenerate at the poles
+    fn _get_sphere_derivatives(&self, p: &Vec3A) -> (Vec3A, Vec3A) {
+        let unit_p: Vec3A = (*p - self.center) / self.radius;
+        let sin_theta: f32 = (unit_p.x * unit_p.x + unit_p.z * unit_p.z).sqrt();
+        if sin_theta < 1e-6 { return (Vec3A::ZERO, Vec3A::ZERO); }
+        let dpdu: Vec3A = Vec3A::new(unit_p.z, 0.0, -unit_p.x) * (2.0 * utility::PI * self.radius);
+        let dpdv: Vec3A = Vec3A::new(-unit_p.x * unit_p.y / sin_theta, sin_theta, -unit_p.y * unit_p.z / sin_theta) * (utility::PI * self.radius);
+        (dpdu / self.uv_tiling.x, dpdv / self.uv_tiling.y)
+    }
+    fn _map_uv(&self, u: f32, v: f32) -> (f32, f32) { (u * self.uv_tiling.x + self.uv_rotation / 360.0, v * self.uv_tiling.y) }
+    // The uv and distance of the nearest root that lies in the acceptable range and isn't cut out by the material alpha
+    fn _nearest_root(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<(f32, f32, f32)> {
+        let oc: Vec3A = ray.origin() - self.center;
+        let a: f32 = ray.direction().dot(ray.direction());
+        let half_b: f32 = oc.dot(ray.direction());
+        let c: f32 = oc.dot(oc) - self.radius.powi(2);
+        let discriminant: f32 = (half_b * half_b) - (a * c);
+        if discriminant < 0.0 { return None; } // No real roots, so no intersection.
+        let sqrtd: f32 = discriminant.sqrt();
+        [(-half_b - sqrtd) / a, (-half_b + sqrtd) / a].into_iter()
+            .filter(|root| t_min <= *root && *root <= t_max)
+            .map(|root| { let (u, v) = self._get_sphere_uv(&ray.at(root)); (u, v, root) })
+            .find(|(u, v, root)| self.material.alpha_test(*u, *v, &ray.at(*root)))
+    }
+    // Builds a triangle mesh of the sphere, pushing each vertex outwards by the height texture (its average channel) times the scale
+    pub fn tessellate(&self, height_map: &dyn Texture, displacement_scale: f32, resolution: u32, params: &BVHParams) -> Mesh {
+        let (columns, rows) = (resolution.max(3) as usize, (resolution / 2).max(2) as usize);
+        // The grid has a duplicated seam column so that the uvs stay continuous around the sphere
+        let mut positions: Vec<Point3> = Vec::with_capacity((columns + 1) * (rows + 1));
+        let mut uvs: Vec<Vec2> = Vec::with_capacity((columns + 1) * (rows + 1));
+        for j in 0..=rows {
+            for i in 0..=columns {
+                let (u, v) = (i as f32 / columns as f32, j as f32 / rows as f32);
+                let (phi, theta) = (u * 2.0 * utility::PI - utility::PI, v * utility::PI);
+                let direction: Vec3A = Vec3A::new(theta.sin() * phi.cos(), -theta.cos(), -theta.sin() * phi.sin());
+                let (tex_u, tex_v) = self._map_uv(u, v);
+                let height: f32 = height_map.value(tex_u, tex_v, &(self.center + direction * self.radius)).dot(Vec3A::ONE) / 3.0;
+                positions.push(self.center + direction * (self.radius + displacement_scale * height));
+                uvs.push(Vec2::new(tex_u, tex_v));
+            }
+        }
+        let index = |i: usize, j: usize| j * (columns + 1) + i;
+        let faces: Vec<[usize; 3]> = (0..rows).flat_map(|j| (0..columns).flat_map(move |i| [
+            [index(i, j), index(i + 1, j), index(i, j + 1)],
+            [index(i + 1, j), index(i + 1, j + 1), index(i, j + 1)],
+        ])).collect();
+        // Smooth normals, averaging the normals of the faces around each vertex
+        let mut normals: Vec<Vec3A> = vec![Vec3A::ZERO; positions.len()];
+        for face in faces.iter() {
+            let normal: Vec3A = (positions[face[1]] - positions[face[0]]).cross(positions[face[2]] - positions[face[0]]);
+            for vertex in face.iter() { normals[*vertex] += normal; }
+        }
+        let triangles: Vec<Triangle> = faces.iter().map(|face| {
+            Triangle::new_with_uvs(
+                Box::new([positions[face[0]], positions[face[1]], positions[face[2]]]),
+                Box::new([normals[face[0]].normalize_or_zero(), normals[face[1]].normalize_or_zero(), normals[face[2]].normalize_or_zero()]),
+                Box::new([uvs[face[0]], uvs[face[1]], uvs[face[2]]]),
+                self.material.clone(),
+                0
+            )
+        }).filter(|triangle| triangle.check_not_degenerate()).collect();
+        Mesh::new_from_triangles(triangles, params)
     }
 }
 
@@ -51,65 +130,52 @@ impl BHShape for Sphere {
 }
 
 impl Hittable for Sphere {
-    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
-        let oc: Vec3A = ray.origin() - self.center;
-        let a: f32 = ray.direction().dot(ray.direction());
-        let half_b: f32 = oc.dot(ray.direction());
-        let c: f32 = oc.dot(oc) - self.radius.powi(2);
-        let discriminant: f32 = (half_b * half_b) - (a * c);
-        if discriminant < 0.0 { return None; } // No real roots, so no intersection.
-        let sqrtd: f32 = discriminant.sqrt();
-        // Find the nearest root that lies in the acceptable range.
-        let mut root: f32 = (-half_b - sqrtd) / a;
-        if root < t_min || t_max < root {
-            root = (-half_b + sqrtd) / a;
-            if root < t_min || t_max < root {
-                return None;
-            }
-        }
-        let (u, v) = self._get_sphere_uv(&ray.at(root));
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        let (u, v, root) = self._nearest_root(ray, t_min, t_max)?;
         let mut rec: HitRecord = HitRecord::new(
             ray.at(root),
             (ray.at(root) - self.center) / self.radius,
-            self.material.clone(),
+            self.material.as_ref(),
             root,
             u,
             v,
             false
         );
         rec.set_face_normal(ray, &rec.normal.clone());
+        (rec.dpdu, rec.dpdv) = self._get_sphere_derivatives(&rec.p);
         Some(rec)
     }
+    fn occluded(&self, ray: &Ray, t_min: f32, t_max: f32) -> bool { self._nearest_root(ray, t_min, t_max).is_some() }
     fn is_light(&self) -> bool { self.material.is_light() }
-    fn pdf_value(&self, origin: &Point3, _: &Vec3A) -> f32 {
-        let sphere_center_to_camera: Vec3A = *origin - self.center;
-        let distance_to_camera_squared: f32 = sphere_center_to_camera.length_squared();
-        let cos_theta_max: f32 = ((1.0 - (self.radius * self.radius)) / distance_to_camera_squared).max(0.0);
-        let solid_angle: f32 = 2.0 * utility::PI * (1.0 - cos_theta_max);
-        1.0 / solid_angle
-    }
-    fn random(&self, origin: &Point3) -> Vec3A {
-        let sphere_center_to_camera: Vec3A = *origin - self.center;
-        let distance_to_camera_squared: f32 = sphere_center_to_camera.length_squared();
-        let cos_theta_max: f32 = (1.0 - (self.radius * self.radius) / (distance_to_camera_squared)).max(0.0);
-        // let sin_theta_max: f32 = (1.0 - cos_theta_max*cos_theta_max).sqrt();
-        let phi: f32 = utility::random_f32_range(0.0, 2.0 * utility::PI);
-        let cos_theta: f32 = utility::random_f32_range(cos_theta_max, 1.0);
-        let sin_theta: f32 = (1.0 - (cos_theta * cos_theta)).sqrt();
-        let direction: Vec3A = Vec3A::new(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta);
-        let sphere_center_to_camera_unit: Vec3A = sphere_center_to_camera.normalize();
-        let mut onb: ONB = ONB::new();
-        onb.build_from_w(&sphere_center_to_camera_unit);
-        onb.local_vec(&direction)
+    fn world_bounds(&self) -> Option<AABB> { Some(self.aabb()) }
+    fn light_estimate(&self) -> Option<LightEstimate> {
+        if !self.material.is_light() { return None; }
+        Some(LightEstimate::sphere(self.center, self.radius, self.material.emitted(0.5, 0.5, &self.center)))
     }
+    // Uniform over the solid angle the sphere covers from the origin
+    fn pdf_value(&self, origin: &Point3, v: &Vec3A) -> f32 { sampling::sphere_solid_angle_pdf(origin, &self.center, self.radius, v) }
+    fn random(&self, origin: &Point3) -> Vec3A { sampling::sphere_solid_angle(sampling::random_uniforms(), origin, &self.center, self.radius) }
+    fn tessellation(&self) -> Vec<FlatTriangle> { gpu_scene::sphere_triangles(self.center, self.radius, |u, v| self._map_uv(u, v)) }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::color::Color;
-    use crate::material::Lambertian;
+    use crate::material::{Lambertian, AlphaMasked};
+    use crate::texture::SolidColor;
 
+    #[test]
+    fn test_sphere_light_sampling() {
+        let sphere: Sphere = Sphere::new(Point3::new(0.0, 0.0, -2.0), 1.0, Box::new(Lambertian::new(Color::ONE)), 0);
+        // The sampled directions reach the sphere, whose pdf is one over the solid angle it covers
+        for _ in 0..16 {
+            let direction: Vec3A = sphere.random(&Point3::ZERO);
+            assert!(sphere.hit(&Ray::new(Point3::ZERO, direction), utility::NEAR_ZERO, f32::INFINITY).is_some());
+            assert!((sphere.pdf_value(&Point3::ZERO, &direction) - 1.0 / (2.0 * utility::PI * (1.0 - 0.75_f32.sqrt()))).abs() < 1e-3);
+        }
+        assert_eq!(sphere.pdf_value(&Point3::ZERO, &Vec3A::X), 0.0);
+    }
     #[test]
     fn test_sphere_hit() -> Result<(), std::fmt::Error> {
         let sphere: Sphere = Sphere::new(Point3::new(0.0, 0.0, -1.0), 0.5, Box::new(Lambertian::new(Color::new(0.0, 0.0, 0.0))), 0);
@@ -117,4 +183,29 @@ mod tests {
         assert!(sphere.hit(&ray, 0.0, 100.0).is_some());
         Ok(())
     }
+    #[test]
+    fn test_sphere_uv_mapping() {
+        let sphere: Sphere = Sphere::new(Point3::ZERO, 1.0, Box::new(Lambertian::new(Color::ONE)), 0);
+        let (u, v) = sphere._get_sphere_uv(&Point3::new(1.0, 0.0, 0.0));
+        assert!((u - 0.5).abs() < utility::EPSILON && (v - 0.5).abs() < utility::EPSILON);
+        let sphere: Sphere = sphere.with_uv_mapping(Vec2::new(2.0, 3.0), 90.0);
+        let (u, v) = sphere._get_sphere_uv(&Point3::new(1.0, 0.0, 0.0));
+        assert!((u - 1.25).abs() < utility::EPSILON && (v - 1.5).abs() < utility::EPSILON);
+    }
+    #[test]
+    fn test_sphere_tessellate_displacement() {
+        let sphere: Sphere = Sphere::new(Point3::ZERO, 1.0, Box::new(Lambertian::new(Color::ONE)), 0);
+        let mesh: Mesh = sphere.tessellate(&SolidColor::new(Color::ONE), 0.5, 64, &BVHParams::default());
+        let ray: Ray = Ray::new(Point3::new(0.0, 0.0, -5.0), Vec3A::new(0.0, 0.0, 1.0));
+        let rec: HitRecord = mesh.hit(&ray, 0.0, 100.0).unwrap();
+        assert!((rec.t - 3.5).abs() < 0.01);
+        assert!(rec.front_face);
+    }
+    #[test]
+    fn test_sphere_alpha_mask() {
+        let material: AlphaMasked = AlphaMasked::new(Box::new(Lambertian::new(Color::ONE)), Box::new(SolidColor::new(Color::ZERO)));
+        let sphere: Sphere = Sphere::new(Point3::new(0.0, 0.0, -1.0), 0.5, Box::new(material), 0);
+        let ray: Ray = Ray::new(Point3::new(0.0, 0.0, 0.0), Vec3A::new(0.0, 0.0, -1.0));
+        assert!(sphere.hit(&ray, 0.0, 100.0).is_none());
+    }
 }
@@ -5,22 +5,24 @@
 use bvh::aabb::Bounded;
 use bvh::bounding_hierarchy::BHShape;
 use bvh::bvh::BVH;
-use bvh::{Point3 as BVHPoint3, Vector3 as BVHVector3};
-use bvh::ray::Ray as BVHRay;
 
-use glam::Vec3A;
+use glam::{Vec3A, Vec4};
 
+use crate::acceleration::{self, BVHParams};
 use crate::ray::Ray;
 use crate::hit_record::HitRecord;
 use crate::hittable_list::Hittable;
 use crate::sphere::Sphere;
 use crate::point3::Point3;
-use crate::utility;
+use crate::simd::{Packet, Sphere4};
+use crate::utility::{self, NEG_INFINITY};
 
 
 #[derive(Clone)]
 pub struct SphereArray {
     spheres: Vec<Sphere>,
+    // The BVH leaves are packets of spheres, tested 4 at a time
+    packets: Vec<Packet<Sphere4>>,
     bvh: BVH,
     node_index: usize,
 }
@@ -30,10 +32,10 @@ unsafe impl Send for SphereArray {}
 
 impl SphereArray {
     #[allow(dead_code)]
-    pub fn new(spheres: &mut Vec<Sphere>) -> SphereArray {
-        let mut spheres: Vec<Sphere> = spheres.clone();
-        let bvh: BVH = BVH::build(&mut spheres);
-        SphereArray { spheres, bvh, node_index: 0 }
+    pub fn new(spheres: &mut Vec<Sphere>, params: &BVHParams) -> SphereArray {
+        let (spheres, mut packets) = Packet::build(spheres.clone(), params, Sphere4::new);
+        let bvh: BVH = acceleration::build(&mut packets, params);
+        SphereArray { spheres, packets, bvh, node_index: 0 }
     }
 }
 
@@ -53,16 +55,39 @@ impl BHShape for SphereArray {
 }
 
 impl Hittable for SphereArray {
-    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
-        let bvhray: BVHRay = BVHRay::new(BVHPoint3::new(ray.origin[0], ray.origin[1], ray.origin[2]), BVHVector3::new(ray.direction[0], ray.direction[1], ray.direction[2]));
-        let hit_spheres_aabb: Vec<&Sphere> = self.bvh.traverse(&bvhray, &self.spheres);
-
-        hit_spheres_aabb.iter()
-        .filter_map(|sphere| sphere.hit(ray, t_min, t_max))
-        .filter(|hit| hit.t > t_min && hit.t < t_max)
-        .min_by(|hit1, hit2| { hit1.t.partial_cmp(&hit2.t).unwrap() })
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        let mut closest: Option<HitRecord> = None;
+        acceleration::traverse(&self.bvh, ray, t_min, t_max, |packet_index, mut t_max| {
+            let packet: &Packet<Sphere4> = &self.packets[packet_index];
+            acceleration::record_primitives(packet.count);
+            let distances: Vec4 = packet.kernel.intersect(ray, t_min, t_max);
+            // Only the candidates closer than the current hit need the full (scalar) intersection, which also handles the alpha
+            for lane in packet.hit_lanes(distances) {
+                if distances[lane] >= t_max { break; }
+                if let Some(mut rec) = self.spheres[packet.first + lane].hit(ray, t_min, t_max) {
+                    rec.primitive = packet.primitives[lane];
+                    t_max = rec.t;
+                    closest = Some(rec);
+                }
+            }
+            t_max
+        });
+        closest
+    }
+    fn occluded(&self, ray: &Ray, t_min: f32, t_max: f32) -> bool {
+        let mut occluded: bool = false;
+        acceleration::traverse(&self.bvh, ray, t_min, t_max, |packet_index, t_max| {
+            let packet: &Packet<Sphere4> = &self.packets[packet_index];
+            acceleration::record_primitives(packet.count);
+            let distances: Vec4 = packet.kernel.intersect(ray, t_min, t_max);
+            occluded = packet.hit_lanes(distances).any(|lane| self.spheres[packet.first + lane].occluded(ray, t_min, t_max));
+            // Any hit will do, so the traversal stops at the first one
+            if occluded { NEG_INFINITY } else { t_max }
+        });
+        occluded
     }
     fn is_light(&self) -> bool { false }
+    fn world_bounds(&self) -> Option<bvh::aabb::AABB> { Some(self.aabb()) }
     fn pdf_value(&self, origin: &Point3, v: &Vec3A) -> f32 {
         let weight: f32 = 1.0 / (self.spheres.len() as f32);
         self.spheres.iter().map(|sphere| sphere.pdf_value(origin, v) * weight).sum()
@@ -82,7 +107,7 @@ mod tests {
         let mut spheres: Vec<Sphere> = Vec::new();
         spheres.push(Sphere::new(Point3::new(0.0, 0.0, -1.0), 0.5, Box::new(Lambertian::new(Color::new(0.1, 0.2, 0.5))), 0));
         spheres.push(Sphere::new(Point3::new(0.0, -100.5, -1.0), 100.0, Box::new(Lambertian::new(Color::new(0.8, 0.8, 0.0))), 0));
-        let sphere_array: SphereArray = SphereArray::new(&mut spheres);
+        let sphere_array: SphereArray = SphereArray::new(&mut spheres, &BVHParams::default());
         let r: Ray = Ray::new(Point3::new(0.0, 0.0, 0.0), Point3::new(0.0, 0.0, -1.0));
         assert!(sphere_array.hit(&r, 0.0, 100.0).is_some());
         Ok(())
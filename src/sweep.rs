@@ -0,0 +1,116 @@
+// Author: Giulian Biolo, github.com/giulianbiolo
+// Date: 16/10/2026
+// Description: This file implements the batch render mode, rendering one image per combination of the sweep block of the config
+
+use std::process::Command;
+
+use yaml_rust::Yaml;
+
+use crate::parser;
+
+
+// A combination of values, each one replacing the value at its dotted path in the config
+pub type Combination = Vec<(String, Yaml)>;
+
+const DEFAULT_OUTPUT: &str = "sweep_{index}.png";
+
+// Every set of the sweep combined with every value of each parameter:
+// sweep: { output: "vfov_{camera.vfov}.png", sets: [{ camera.lookFrom: [..] }, ..], parameters: { camera.vfov: [30.0, 60.0] } }
+pub fn combinations(hashsweep: &yaml_rust::yaml::Hash) -> Vec<Combination> {
+    let mut combinations: Vec<Combination> = match hashsweep.get(&Yaml::String("sets".to_string())) {
+        Some(sets) => sets.as_vec().unwrap().iter().map(|set| {
+            set.as_hash().unwrap().iter().map(|(path, value)| (path.as_str().unwrap().to_string(), value.clone())).collect()
+        }).collect(),
+        None => vec![Vec::new()],
+    };
+    if let Some(parameters) = hashsweep.get(&Yaml::String("parameters".to_string())) {
+        for (path, values) in parameters.as_hash().unwrap() {
+            let path: &str = path.as_str().unwrap();
+            combinations = combinations.into_iter().flat_map(|combination| values.as_vec().unwrap().iter().map(move |value| {
+                let mut combination: Combination = combination.clone();
+                combination.push((path.to_string(), value.clone()));
+                combination
+            })).collect();
+        }
+    }
+    combinations
+}
+
+// Replaces {index} and {path} (any path of the combination) in the template, lists being joined by underscores
+pub fn output_name(template: &str, index: usize, count: usize, combination: &Combination) -> String {
+    let width: usize = count.saturating_sub(1).to_string().len();
+    combination.iter().fold(template.replace("{index}", &format!("{:0width$}", index, width = width)), |name, (path, value)| {
+        name.replace(&format!("{{{}}}", path), &_label(value))
+    })
+}
+
+fn _label(value: &Yaml) -> String {
+    match value {
+        Yaml::Array(values) => values.iter().map(_label).collect::<Vec<String>>().join("_"),
+        Yaml::String(value) | Yaml::Real(value) => value.clone(),
+        value => _flow(value),
+    }
+}
+
+// The value as flow style YAML, to be passed to --set
+fn _flow(value: &Yaml) -> String {
+    match value {
+        Yaml::Real(value) => value.clone(),
+        Yaml::Integer(value) => value.to_string(),
+        Yaml::Boolean(value) => value.to_string(),
+        Yaml::String(value) => format!("{:?}", value),
+        Yaml::Array(values) => format!("[{}]", values.iter().map(_flow).collect::<Vec<String>>().join(", ")),
+        Yaml::Hash(hash) => format!("{{{}}}", hash.iter().map(|(key, value)| format!("{}: {}", _flow(key), _flow(value))).collect::<Vec<String>>().join(", ")),
+        _ => "~".to_string(),
+    }
+}
+
+// Renders the combinations one after the other, each in its own process so that the constants can change too
+// The other arguments are passed along, and the values of the combination override the --set ones
+pub fn run(config_file: &str) {
+    let doc: Yaml = parser::load_config(config_file).unwrap_or_else(|| panic!("Failed to load the config file {}", config_file));
+    let hashsweep = doc["sweep"].as_hash().unwrap_or_else(|| panic!("The config file {} has no sweep block", config_file));
+    let template: &str = hashsweep.get(&Yaml::String("output".to_string())).map(|output| output.as_str().unwrap()).unwrap_or(DEFAULT_OUTPUT);
+    let combinations: Vec<Combination> = combinations(hashsweep);
+    let mut arguments: Vec<String> = Vec::new();
+    let mut forwarded = std::env::args().skip(1);
+    while let Some(arg) = forwarded.next() {
+        match arg.as_str() {
+            "--sweep" => {},
+            "--output" => { forwarded.next(); },
+            _ => arguments.push(arg),
+        }
+    }
+    let executable = std::env::current_exe().unwrap();
+    let mut failed: usize = 0;
+    for (index, combination) in combinations.iter().enumerate() {
+        let output: String = output_name(template, index, combinations.len(), combination);
+        println!("Sweep {}/{}: {}", index + 1, combinations.len(), output);
+        let mut command: Command = Command::new(&executable);
+        command.args(&arguments).arg("--output").arg(&output);
+        for (path, value) in combination.iter() { command.arg("--set").arg(format!("{}={}", path, _flow(value))); }
+        if !command.status().is_ok_and(|status| status.success()) {
+            println!("Sweep {}/{} failed", index + 1, combinations.len());
+            failed += 1;
+        }
+    }
+    println!("Rendered {} of {} combinations", combinations.len() - failed, combinations.len());
+    if failed > 0 { std::process::exit(1); }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use yaml_rust::YamlLoader;
+
+    #[test]
+    fn test_combinations() {
+        let doc: Yaml = YamlLoader::load_from_str("{sets: [{camera.lookFrom: [0.0, 1.0, -4.0]}, {camera.lookFrom: [4.0, 1.0, 0.0]}], parameters: {camera.vfov: [30.0, 60.0], constants.samplesPerPixel: [16]}}").unwrap().remove(0);
+        let combinations: Vec<Combination> = combinations(doc.as_hash().unwrap());
+        assert_eq!(combinations.len(), 4);
+        assert!(combinations.iter().all(|combination| combination.len() == 3));
+        let name: String = output_name("view{camera.lookFrom}_fov{camera.vfov}_{index}.png", 3, combinations.len(), &combinations[3]);
+        assert_eq!(name, "view4.0_1.0_0.0_fov60.0_3.png");
+        assert_eq!(_flow(&combinations[3][0].1), "[4.0, 1.0, 0.0]");
+    }
+}
@@ -0,0 +1,150 @@
+// Author: Giulian Biolo, github.com/giulianbiolo
+// Date: 16/10/2026
+// Description: This file implements the temporal filter, blending each frame of an animation with the previous one reprojected through the camera motion
+
+use std::io::{Error, ErrorKind};
+
+use crate::camera::Camera;
+use crate::color::Color;
+use crate::hittable_list::{Hittable, HittableList};
+use crate::parallel::*;
+use crate::point3::Point3;
+use crate::ray::Ray;
+use crate::utility;
+
+
+const MAGIC: &[u8; 8] = b"GBRTHIST";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TemporalFilter {
+    // Where the blended frame is saved for the next one
+    pub history_file: String,
+    // Weight of the reprojected history, the current frame gets the rest
+    pub blend: f32,
+    // Largest difference between the depths of a point in the two frames, relative to its distance, for the history to be trusted
+    pub depth_tolerance: f32,
+}
+
+/****************** Frame ******************/
+// The linear colors of a frame and the depth of the surfaces seen by the centers of its pixels
+pub struct Frame {
+    pub width: u32,
+    pub height: u32,
+    pub camera: Camera,
+    pub colors: Vec<Color>,
+    // Distance from the camera, infinite where the pixel sees the environment
+    pub depths: Vec<f32>,
+}
+
+impl Frame {
+    pub fn save(&self, filename: &str) -> std::io::Result<()> {
+        let mut bytes: Vec<u8> = MAGIC.to_vec();
+        bytes.extend_from_slice(&self.width.to_le_bytes());
+        bytes.extend_from_slice(&self.height.to_le_bytes());
+        let floats = self.camera.to_floats().into_iter()
+            .chain(self.colors.iter().flat_map(|color| color.to_array()))
+            .chain(self.depths.iter().copied());
+        for float in floats { bytes.extend_from_slice(&float.to_le_bytes()); }
+        std::fs::write(filename, bytes)
+    }
+    pub fn load(filename: &str) -> std::io::Result<Frame> {
+        let bytes: Vec<u8> = std::fs::read(filename)?;
+        if bytes.len() < 16 || &bytes[..8] != MAGIC { return Err(Error::new(ErrorKind::InvalidData, format!("{} isn't a frame history", filename))); }
+        let width: u32 = u32::from_le_bytes(bytes[8..12].try_into().unwrap());
+        let height: u32 = u32::from_le_bytes(bytes[12..16].try_into().unwrap());
+        let floats: Vec<f32> = bytes[16..].chunks_exact(4).map(|chunk| f32::from_le_bytes(chunk.try_into().unwrap())).collect();
+        let pixels: usize = (width * height) as usize;
+        if floats.len() != 22 + pixels * 4 { return Err(Error::new(ErrorKind::InvalidData, format!("{} is truncated", filename))); }
+        let camera: Camera = Camera::from_floats(floats[..22].try_into().unwrap());
+        let colors: Vec<Color> = floats[22..22 + pixels * 3].chunks_exact(3).map(Color::from_slice).collect();
+        Ok(Frame { width, height, camera, colors, depths: floats[22 + pixels * 3..].to_vec() })
+    }
+    // The (u, v) of the center of the pixel, as the renderer samples it
+    fn _pixel_uv(&self, x: f32, y: f32) -> (f32, f32) { ((x + 0.5) / (self.width as f32 - 1.0), (self.height as f32 - (y + 0.5)) / (self.height as f32 - 1.0)) }
+    // The point the center of the pixel sees, far away along the ray for the environment
+    fn _point(&self, idx: usize) -> Point3 {
+        let (x, y) = ((idx as u32 % self.width) as f32, (idx as u32 / self.width) as f32);
+        let (u, v) = self._pixel_uv(x, y);
+        let depth: f32 = if self.depths[idx].is_finite() { self.depths[idx] } else { 1e6 };
+        self.camera.origin + self.camera.pinhole_direction(u, v).normalize() * depth
+    }
+    // The color of the frame at the point, interpolated between the neighbouring pixels which see the same surface
+    fn _reproject(&self, p: &Point3, environment: bool, depth_tolerance: f32) -> Option<Color> {
+        let (u, v) = self.camera.project(p)?;
+        let (x, y) = (u * (self.width as f32 - 1.0) - 0.5, self.height as f32 - 0.5 - v * (self.height as f32 - 1.0));
+        let (x0, y0) = (x.floor(), y.floor());
+        let expected: f32 = (*p - self.camera.origin).length();
+        let (mut color, mut weight) = (Color::ZERO, 0.0);
+        for (dx, dy) in [(0.0, 0.0), (1.0, 0.0), (0.0, 1.0), (1.0, 1.0)] {
+            let (px, py) = (x0 + dx, y0 + dy);
+            if px < 0.0 || py < 0.0 || px >= self.width as f32 || py >= self.height as f32 { continue; }
+            let idx: usize = (py as u32 * self.width + px as u32) as usize;
+            let depth: f32 = self.depths[idx];
+            let same_surface: bool = if environment { !depth.is_finite() } else { (depth - expected).abs() <= depth_tolerance * expected };
+            if !same_surface { continue; }
+            let bilinear: f32 = (1.0 - (x - px).abs()) * (1.0 - (y - py).abs());
+            color += self.colors[idx] * bilinear;
+            weight += bilinear;
+        }
+        if weight < 1e-4 { None } else { Some(color / weight) }
+    }
+}
+
+// Distance to the first surface seen by the center of each pixel, through the center of the lens
+pub fn depth_pass(world: &HittableList, cam: &Camera, width: u32, height: u32) -> Vec<f32> {
+    let mut depths: Vec<f32> = vec![f32::INFINITY; (width * height) as usize];
+    depths.par_chunks_mut(width as usize).enumerate().map(|(y, row)| {
+        for (x, depth) in row.iter_mut().enumerate() {
+            let (u, v) = ((x as f32 + 0.5) / (width as f32 - 1.0), (height as f32 - (y as f32 + 0.5)) / (height as f32 - 1.0));
+            let ray: Ray = Ray::new(cam.origin, cam.pinhole_direction(u, v).normalize());
+            if let Some(rec) = world.hit(&ray, utility::NEAR_ZERO, utility::INFINITY) { *depth = rec.t; }
+        }
+    }).reduce(|| (), |_, _| ());
+    depths
+}
+
+// Blends the frame with the previous one where it sees the same surfaces, keeping the new colors where they got disoccluded
+pub fn blend(filter: &TemporalFilter, current: &Frame, previous: &Frame) -> Vec<Color> {
+    (0..current.colors.len()).map(|idx| {
+        let environment: bool = !current.depths[idx].is_finite();
+        match previous._reproject(&current._point(idx), environment, filter.depth_tolerance) {
+            Some(history) => current.colors[idx] * (1.0 - filter.blend) + history * filter.blend,
+            None => current.colors[idx],
+        }
+    }).collect()
+}
+
+// Filters the rendered colors with the history of the previous frame, which is then replaced with the result
+pub fn filter_frame(filter: &TemporalFilter, world: &HittableList, cam: &Camera, width: u32, height: u32, colors: Vec<Color>) -> Vec<Color> {
+    let mut frame: Frame = Frame { width, height, camera: cam.clone(), colors, depths: depth_pass(world, cam, width, height) };
+    match Frame::load(&filter.history_file) {
+        Ok(previous) if previous.width == width && previous.height == height => frame.colors = blend(filter, &frame, &previous),
+        Ok(_) => println!("The history in {} has a different size, starting a new one", filter.history_file),
+        Err(_) => println!("No history in {}, starting a new one", filter.history_file),
+    }
+    frame.save(&filter.history_file).unwrap_or_else(|err| panic!("Failed to save the history to {}: {}", filter.history_file, err));
+    frame.colors
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use glam::Vec3A;
+
+    fn _frame(camera: Camera, colors: Vec<Color>, depths: Vec<f32>) -> Frame { Frame { width: 4, height: 4, camera, colors, depths } }
+
+    #[test]
+    fn test_reprojection() {
+        let filter: TemporalFilter = TemporalFilter { history_file: String::new(), blend: 0.5, depth_tolerance: 0.05 };
+        let camera: Camera = Camera::new(&Point3::ZERO, &-Vec3A::Z, &Vec3A::Y, 60.0, 1.0, 0.0, 1.0);
+        // The previous frame saw a white wall where the current one sees it too, but at a different distance in the last pixel
+        let previous: Frame = _frame(camera.clone(), vec![Color::ONE; 16], vec![5.0; 16]);
+        let mut depths: Vec<f32> = vec![5.0; 16];
+        depths[15] = 2.0;
+        let current: Frame = _frame(camera, vec![Color::ZERO; 16], depths);
+        let blended: Vec<Color> = blend(&filter, &current, &previous);
+        assert!((blended[5] - Color::splat(0.5)).length() < utility::EPSILON);
+        // The disoccluded pixel keeps its own color
+        assert_eq!(blended[15], Color::ZERO);
+    }
+}
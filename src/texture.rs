@@ -2,14 +2,26 @@ use std::fmt::Debug;
 use std::sync::Arc;
 
 use dyn_clone::DynClone;
-use image::{GenericImageView, DynamicImage};
+use glam::{Vec2, Vec3A};
+use image::{GenericImageView, DynamicImage, RgbImage, Rgb};
 
+use crate::assets;
+use crate::memory;
+use crate::cli::ARGS;
+use crate::hit_record::HitRecord;
 use crate::point3::Point3;
 use crate::color::Color;
 
 
 pub trait Texture: DynClone + Debug + Send + Sync {
     fn value(&self, u: f32, v: f32, p: &Point3) -> Color;
+    // Average of the texture over a footprint of the given width in uv space, the textures that alias override it
+    fn filtered_value(&self, u: f32, v: f32, p: &Point3, _footprint: f32) -> Color { self.value(u, v, p) }
+    // The filtered value at a hit, whose record also tells the instance of the object for the textures that vary per instance
+    fn hit_value(&self, rec: &HitRecord) -> Color { self.filtered_value(rec.u, rec.v, &rec.p, rec.footprint()) }
+    // Derivatives along u and v of the height of the texture (the average of its channels), for bump mapping
+    // None when the texture has no closed form for them, the bump mapping falls back to finite differences then
+    fn gradient(&self, _u: f32, _v: f32, _p: &Point3) -> Option<Vec2> { None }
 }
 
 dyn_clone::clone_trait_object!(Texture);
@@ -35,6 +47,7 @@ impl Clone for SolidColor { fn clone(&self) -> Self { SolidColor { color_value:
 
 impl Texture for SolidColor {
     fn value(&self, _u: f32, _v: f32, _p: &Point3) -> Color { self.color_value }
+    fn gradient(&self, _u: f32, _v: f32, _p: &Point3) -> Option<Vec2> { Some(Vec2::ZERO) }
 }
 
 /****************** Chess Board ******************/
@@ -59,13 +72,247 @@ impl Debug for ChessBoard {
 
 impl Clone for ChessBoard { fn clone(&self) -> Self { ChessBoard { odd: self.odd.clone(), even: self.even.clone(), scale: self.scale.clone() } } }
 
-impl Texture for ChessBoard {
-    fn value(&self, u: f32, v: f32, p: &Point3) -> Color {
+impl ChessBoard {
+    fn _select(&self, p: &Point3) -> &dyn Texture {
         let sines: f32 = (self.scale * p.x).sin() * (self.scale * p.y).sin() * (self.scale * p.z).sin();
-        if sines < 0.0 { self.odd.value(u, v, p) } else { self.even.value(u, v, p) }
+        if sines < 0.0 { self.odd.as_ref() } else { self.even.as_ref() }
+    }
+}
+
+// The patterns are flat within their cells, so their gradient is the one of the texture of the cell, without the blur across the edges of finite differences
+impl Texture for ChessBoard {
+    fn value(&self, u: f32, v: f32, p: &Point3) -> Color { self._select(p).value(u, v, p) }
+    fn hit_value(&self, rec: &HitRecord) -> Color { self._select(&rec.p).hit_value(rec) }
+    fn gradient(&self, u: f32, v: f32, p: &Point3) -> Option<Vec2> { self._select(p).gradient(u, v, p) }
+}
+
+/****************** UV Procedural Textures ******************/
+// These textures work in uv space, so that they stick to the surface of moving or rotated objects
+pub struct UVChecker {
+    odd: Box<dyn Texture>,
+    even: Box<dyn Texture>,
+    // Number of checks along u and v
+    scale: Vec2,
+}
+
+impl UVChecker {
+    pub fn new(odd: Box<dyn Texture>, even: Box<dyn Texture>, scale: Vec2) -> UVChecker { UVChecker { odd, even, scale } }
+}
+
+impl Debug for UVChecker {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("UVChecker")
+            .field("odd", &self.odd)
+            .field("even", &self.even)
+            .field("scale", &self.scale)
+            .finish()
+    }
+}
+
+impl Clone for UVChecker { fn clone(&self) -> Self { UVChecker { odd: self.odd.clone(), even: self.even.clone(), scale: self.scale } } }
+
+impl UVChecker {
+    fn _select(&self, u: f32, v: f32) -> &dyn Texture {
+        let cell: Vec2 = (Vec2::new(u, v) * self.scale).floor();
+        if (cell.x + cell.y).rem_euclid(2.0) >= 1.0 { self.odd.as_ref() } else { self.even.as_ref() }
+    }
+}
+
+impl Texture for UVChecker {
+    fn value(&self, u: f32, v: f32, p: &Point3) -> Color { self._select(u, v).value(u, v, p) }
+    fn hit_value(&self, rec: &HitRecord) -> Color { self._select(rec.u, rec.v).hit_value(rec) }
+    fn gradient(&self, u: f32, v: f32, p: &Point3) -> Option<Vec2> { self._select(u, v).gradient(u, v, p) }
+}
+
+pub struct Brick {
+    brick: Box<dyn Texture>,
+    mortar: Box<dyn Texture>,
+    // Number of bricks along u and v, the width of the mortar as a fraction of a brick and the shift of every other row
+    scale: Vec2,
+    mortar_width: f32,
+    row_offset: f32,
+}
+
+impl Brick {
+    pub fn new(brick: Box<dyn Texture>, mortar: Box<dyn Texture>, scale: Vec2, mortar_width: f32, row_offset: f32) -> Brick {
+        Brick { brick, mortar, scale, mortar_width: mortar_width.clamp(0.0, 0.5), row_offset }
+    }
+}
+
+impl Debug for Brick {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Brick")
+            .field("brick", &self.brick)
+            .field("mortar", &self.mortar)
+            .field("scale", &self.scale)
+            .field("mortar_width", &self.mortar_width)
+            .field("row_offset", &self.row_offset)
+            .finish()
+    }
+}
+
+impl Clone for Brick {
+    fn clone(&self) -> Self {
+        Brick { brick: self.brick.clone(), mortar: self.mortar.clone(), scale: self.scale, mortar_width: self.mortar_width, row_offset: self.row_offset }
+    }
+}
+
+impl Brick {
+    fn _select(&self, u: f32, v: f32) -> &dyn Texture {
+        let mut coords: Vec2 = Vec2::new(u, v) * self.scale;
+        // Every other row is shifted to get the running bond pattern
+        if coords.y.floor().rem_euclid(2.0) >= 1.0 { coords.x += self.row_offset; }
+        let local: Vec2 = coords - coords.floor();
+        let half_mortar: f32 = self.mortar_width / 2.0;
+        let in_mortar: bool = local.min_element() < half_mortar || local.max_element() > 1.0 - half_mortar;
+        if in_mortar { self.mortar.as_ref() } else { self.brick.as_ref() }
+    }
+}
+
+impl Texture for Brick {
+    fn value(&self, u: f32, v: f32, p: &Point3) -> Color { self._select(u, v).value(u, v, p) }
+    fn hit_value(&self, rec: &HitRecord) -> Color { self._select(rec.u, rec.v).hit_value(rec) }
+    fn gradient(&self, u: f32, v: f32, p: &Point3) -> Option<Vec2> { self._select(u, v).gradient(u, v, p) }
+}
+
+pub struct Grid {
+    line: Box<dyn Texture>,
+    background: Box<dyn Texture>,
+    // Number of cells along u and v and the width of the lines as a fraction of a cell
+    scale: Vec2,
+    line_width: f32,
+}
+
+impl Grid {
+    pub fn new(line: Box<dyn Texture>, background: Box<dyn Texture>, scale: Vec2, line_width: f32) -> Grid { Grid { line, background, scale, line_width: line_width.clamp(0.0, 1.0) } }
+}
+
+impl Debug for Grid {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Grid")
+            .field("line", &self.line)
+            .field("background", &self.background)
+            .field("scale", &self.scale)
+            .field("line_width", &self.line_width)
+            .finish()
+    }
+}
+
+impl Clone for Grid {
+    fn clone(&self) -> Self { Grid { line: self.line.clone(), background: self.background.clone(), scale: self.scale, line_width: self.line_width } }
+}
+
+impl Grid {
+    fn _select(&self, u: f32, v: f32) -> &dyn Texture {
+        let coords: Vec2 = Vec2::new(u, v) * self.scale;
+        // Distance from the nearest line, in fractions of a cell
+        let distance: Vec2 = (coords - coords.round()).abs();
+        if distance.min_element() < self.line_width / 2.0 { self.line.as_ref() } else { self.background.as_ref() }
+    }
+}
+
+impl Texture for Grid {
+    fn value(&self, u: f32, v: f32, p: &Point3) -> Color { self._select(u, v).value(u, v, p) }
+    fn hit_value(&self, rec: &HitRecord) -> Color { self._select(rec.u, rec.v).hit_value(rec) }
+    fn gradient(&self, u: f32, v: f32, p: &Point3) -> Option<Vec2> { self._select(u, v).gradient(u, v, p) }
+}
+
+pub struct PolkaDot {
+    dot: Box<dyn Texture>,
+    background: Box<dyn Texture>,
+    // Number of dots along u and v and their radius as a fraction of a cell
+    scale: Vec2,
+    radius: f32,
+}
+
+impl PolkaDot {
+    pub fn new(dot: Box<dyn Texture>, background: Box<dyn Texture>, scale: Vec2, radius: f32) -> PolkaDot { PolkaDot { dot, background, scale, radius: radius.clamp(0.0, 0.5) } }
+}
+
+impl Debug for PolkaDot {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PolkaDot")
+            .field("dot", &self.dot)
+            .field("background", &self.background)
+            .field("scale", &self.scale)
+            .field("radius", &self.radius)
+            .finish()
+    }
+}
+
+impl Clone for PolkaDot {
+    fn clone(&self) -> Self { PolkaDot { dot: self.dot.clone(), background: self.background.clone(), scale: self.scale, radius: self.radius } }
+}
+
+impl PolkaDot {
+    fn _select(&self, u: f32, v: f32) -> &dyn Texture {
+        let coords: Vec2 = Vec2::new(u, v) * self.scale;
+        let local: Vec2 = coords - coords.floor() - Vec2::splat(0.5);
+        if local.length() < self.radius { self.dot.as_ref() } else { self.background.as_ref() }
+    }
+}
+
+impl Texture for PolkaDot {
+    fn value(&self, u: f32, v: f32, p: &Point3) -> Color { self._select(u, v).value(u, v, p) }
+    fn hit_value(&self, rec: &HitRecord) -> Color { self._select(rec.u, rec.v).hit_value(rec) }
+    fn gradient(&self, u: f32, v: f32, p: &Point3) -> Option<Vec2> { self._select(u, v).gradient(u, v, p) }
+}
+
+/****************** Noise ******************/
+// Fractal gradient (Perlin) noise in uv space, blending between two colors, with its exact gradient so that it can be bump mapped
+#[derive(Clone, Debug)]
+pub struct Noise {
+    low: Color,
+    high: Color,
+    // Number of noise cells along u and v of the first octave, every following one having twice as many and half the amplitude
+    scale: Vec2,
+    octaves: u32,
+}
+
+impl Noise {
+    pub fn new(low: Color, high: Color, scale: Vec2, octaves: u32) -> Noise { Noise { low, high, scale, octaves: octaves.max(1) } }
+    // The noise in [0, 1] (about, the gradient noise rarely gets to the ends) and its derivatives along u and v
+    fn _fractal(&self, u: f32, v: f32) -> (f32, Vec2) {
+        let (mut noise, mut gradient, mut amplitude, mut total) = (0.0, Vec2::ZERO, 1.0, 0.0);
+        for octave in 0..self.octaves {
+            let frequency: Vec2 = self.scale * (1 << octave) as f32;
+            let (value, derivatives) = _gradient_noise(Vec2::new(u, v) * frequency, octave);
+            noise += amplitude * value;
+            gradient += amplitude * derivatives * frequency;
+            total += amplitude;
+            amplitude *= 0.5;
+        }
+        (0.5 + 0.5 * noise / total, 0.5 * gradient / total)
     }
 }
 
+// 2D Perlin noise in [-1, 1] (about) and its derivatives, with the quintic fade so that they are continuous across the cells
+fn _gradient_noise(coords: Vec2, seed: u32) -> (f32, Vec2) {
+    let cell: Vec2 = coords.floor();
+    let f: Vec2 = coords - cell;
+    let corner_gradient = |dx: i32, dy: i32| -> Vec2 {
+        let hash: u32 = _hash_u32((cell.x as i32 + dx) as u32 ^ _hash_u32((cell.y as i32 + dy) as u32 ^ _hash_u32(seed)));
+        let angle: f32 = (hash & 7) as f32 * std::f32::consts::FRAC_PI_4;
+        Vec2::new(angle.cos(), angle.sin())
+    };
+    let (g00, g10, g01, g11) = (corner_gradient(0, 0), corner_gradient(1, 0), corner_gradient(0, 1), corner_gradient(1, 1));
+    let (n00, n10) = (g00.dot(f), g10.dot(f - Vec2::X));
+    let (n01, n11) = (g01.dot(f - Vec2::Y), g11.dot(f - Vec2::ONE));
+    let fade: Vec2 = f * f * f * (f * (f * 6.0 - 15.0) + 10.0);
+    let fade_derivative: Vec2 = f * f * (f * (f - 2.0) + 1.0) * 30.0;
+    let (k1, k2, k3) = (n10 - n00, n01 - n00, n00 - n10 - n01 + n11);
+    let value: f32 = n00 + fade.x * k1 + fade.y * k2 + fade.x * fade.y * k3;
+    let gradient: Vec2 = g00 + fade.x * (g10 - g00) + fade.y * (g01 - g00) + fade.x * fade.y * (g00 - g10 - g01 + g11)
+        + fade_derivative * Vec2::new(k1 + fade.y * k3, k2 + fade.x * k3);
+    // The 2D gradient noise is within [-1/sqrt(2), 1/sqrt(2)]
+    (value * std::f32::consts::SQRT_2, gradient * std::f32::consts::SQRT_2)
+}
+
+impl Texture for Noise {
+    fn value(&self, u: f32, v: f32, _p: &Point3) -> Color { self.low.lerp(self.high, self._fractal(u, v).0) }
+    fn gradient(&self, u: f32, v: f32, _p: &Point3) -> Option<Vec2> { Some(self._fractal(u, v).1 * (self.high - self.low).dot(Color::ONE) / 3.0) }
+}
+
 /****************** Gradient Color ******************/
 pub struct GradientColor {
     top: Box<dyn Texture>,
@@ -92,23 +339,125 @@ impl Texture for GradientColor {
         // We implement a vertical linear gradient
         glam::Vec3A::ONE.lerp(crate::utility::BLUE_SKY, v)
     }
+    fn gradient(&self, _u: f32, _v: f32, _p: &Point3) -> Option<Vec2> { Some(Vec2::new(0.0, (crate::utility::BLUE_SKY - glam::Vec3A::ONE).dot(glam::Vec3A::ONE) / 3.0)) }
+}
+
+/****************** Randomize Per Instance ******************/
+// The variation comes from the instance and the primitive in the hit record, so the instances of a group, the spheres of an array and the triangles of a mesh vary apart
+pub struct RandomizePerInstance {
+    base: Box<dyn Texture>,
+    // Maximum hue rotation (as a fraction of a full turn) and relative brightness change
+    hue_variation: f32,
+    brightness_variation: f32,
+}
+
+impl RandomizePerInstance {
+    pub fn new(base: Box<dyn Texture>, hue_variation: f32, brightness_variation: f32) -> RandomizePerInstance {
+        RandomizePerInstance { base, hue_variation: hue_variation.clamp(0.0, 1.0), brightness_variation: brightness_variation.clamp(0.0, 1.0) }
+    }
+    fn _vary(&self, color: Color, instance: u32, primitive: u32) -> Color {
+        // Random offsets in [-1, 1] derived from the instance and the primitive
+        let hash: u32 = _hash_u32(_hash_u32(instance) ^ primitive);
+        let (hue_offset, brightness_offset) = ((hash & 0xffff) as f32 / 32767.5 - 1.0, (hash >> 16) as f32 / 32767.5 - 1.0);
+        // Hue shift as a rotation around the gray axis (Rodrigues' formula), which keeps the luminance mostly unchanged
+        let angle: f32 = hue_offset * self.hue_variation * 2.0 * std::f32::consts::PI;
+        let axis: Color = Color::ONE.normalize();
+        let (sin, cos) = angle.sin_cos();
+        let rotated: Color = color * cos + axis.cross(color) * sin + axis * axis.dot(color) * (1.0 - cos);
+        (rotated * (1.0 + brightness_offset * self.brightness_variation)).max(Color::ZERO)
+    }
+}
+
+// Integer hash by Chris Wellons (lowbias32), so that consecutive ids give unrelated variations
+fn _hash_u32(mut x: u32) -> u32 {
+    x ^= x >> 16;
+    x = x.wrapping_mul(0x7feb352d);
+    x ^= x >> 15;
+    x = x.wrapping_mul(0x846ca68b);
+    x ^= x >> 16;
+    x
+}
+
+impl Debug for RandomizePerInstance {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RandomizePerInstance")
+            .field("base", &self.base)
+            .field("hue_variation", &self.hue_variation)
+            .field("brightness_variation", &self.brightness_variation)
+            .finish()
+    }
+}
+
+impl Clone for RandomizePerInstance {
+    fn clone(&self) -> Self {
+        RandomizePerInstance { base: self.base.clone(), hue_variation: self.hue_variation, brightness_variation: self.brightness_variation }
+    }
+}
+
+// Looked up without a hit, like by the emission, it takes the variation of the first primitive of the first instance
+impl Texture for RandomizePerInstance {
+    fn value(&self, u: f32, v: f32, p: &Point3) -> Color { self._vary(self.base.value(u, v, p), 0, 0) }
+    fn hit_value(&self, rec: &HitRecord) -> Color { self._vary(self.base.hit_value(rec), rec.instance, rec.primitive) }
 }
 
 /****************** Image Texture ******************/
 /****************** Environment Map Texture ******************/
+// How the coordinates outside of [0, 1] are looked up
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AddressMode {
+    // The image repeats, so that the uvs of a surface can be tiled
+    Wrap,
+    // The edge texels stretch out, so that the seam and the poles of an environment don't bleed into each other
+    Clamp,
+}
+
+impl AddressMode {
+    pub fn apply(self, coord: f32) -> f32 {
+        match self {
+            // The edges themselves stay where they are, 1 on the last texel and not back on the first
+            AddressMode::Wrap => if (0.0..=1.0).contains(&coord) { coord } else { coord.rem_euclid(1.0) },
+            AddressMode::Clamp => coord.clamp(0.0, 1.0),
+        }
+    }
+}
+
 pub struct ImageTexture {
     image: Arc<DynamicImage>,
     width: u32,
     height: u32,
+    address: AddressMode,
 }
 pub type EnvironmentMapTexture = ImageTexture;
 
 impl ImageTexture {
-    pub fn new(filename: &str) -> ImageTexture {
+    pub fn new(filename: &str) -> ImageTexture { ImageTexture::try_new(filename).unwrap_or_else(|err| panic!("{}", err)) }
+    // The images failing to load when they aren't replaced by placeholders come back as errors
+    pub fn try_new(filename: &str) -> Result<ImageTexture, String> {
         println!("Loading image texture from file: {}", filename);
-        let image = image::open(filename).unwrap();
-        let (width, height) = image.dimensions();
-        ImageTexture { image: Arc::new(image), width, height }
+        match assets::open_image(filename) {
+            Ok(image) => {
+                let (width, height) = image.dimensions();
+                memory::track(memory::Category::Textures, filename, image.as_bytes().len());
+                tracing::info!(filename, width, height, "texture loaded");
+                Ok(ImageTexture { image: Arc::new(image), width, height, address: AddressMode::Wrap })
+            },
+            Err(err) => {
+                ARGS.missing_assets.check("image texture", filename, &err)?;
+                Ok(ImageTexture::placeholder())
+            }
+        }
+    }
+    pub fn placeholder() -> ImageTexture {
+        // A magenta and black checkerboard, easy to spot in the final render
+        let (width, height, tiles) = (64, 64, 8);
+        let image = RgbImage::from_fn(width, height, |x, y| {
+            if (x / (width / tiles) + y / (height / tiles)) % 2 == 0 { Rgb([255, 0, 255]) } else { Rgb([0, 0, 0]) }
+        });
+        ImageTexture { image: Arc::new(DynamicImage::ImageRgb8(image)), width, height, address: AddressMode::Wrap }
+    }
+    pub fn with_address(mut self, address: AddressMode) -> ImageTexture {
+        self.address = address;
+        self
     }
 }
 
@@ -117,6 +466,7 @@ impl Debug for ImageTexture {
         f.debug_struct("ImageTexture")
             .field("width", &self.width)
             .field("height", &self.height)
+            .field("address", &self.address)
             .finish()
     }
 }
@@ -126,7 +476,8 @@ impl Clone for ImageTexture {
         ImageTexture {
             image: self.image.clone(),
             width: self.width,
-            height: self.height
+            height: self.height,
+            address: self.address,
         }
     }
 }
@@ -134,8 +485,8 @@ impl Clone for ImageTexture {
 impl Texture for ImageTexture {
     fn value(&self, u: f32, v: f32, _p: &Point3) -> Color {
         //println!("Input: u={}, v={}", u, v);
-        let u = u.clamp(0.0, 1.0);
-        let v = 1.0 - v.clamp(0.0, 1.0);
+        let u = self.address.apply(u);
+        let v = 1.0 - self.address.apply(v);
         let i = ((u * self.width as f32) as u32).min(self.width - 1);
         let j = ((v * self.height as f32) as u32).min(self.height - 1);
         let color_scale = 1.0 / 255.0;
@@ -143,4 +494,176 @@ impl Texture for ImageTexture {
         //println!("Pixel: {:?} at position [{}, {}]", pixel, i, j);
         Color::new(pixel[0] as f32 * color_scale, pixel[1] as f32 * color_scale, pixel[2] as f32 * color_scale)
     }
+    fn filtered_value(&self, u: f32, v: f32, p: &Point3, footprint: f32) -> Color {
+        // Box filter over the texels covered by the footprint, with at most 8x8 lookups
+        let texels: f32 = footprint * self.width.max(self.height) as f32;
+        if texels <= 1.0 { return self.value(u, v, p); }
+        let samples: u32 = (texels.ceil() as u32).min(8);
+        let step: f32 = footprint / samples as f32;
+        let start: f32 = -0.5 * footprint + 0.5 * step;
+        let mut color: Color = Color::ZERO;
+        for i in 0..samples {
+            for j in 0..samples {
+                color += self.value(u + start + i as f32 * step, v + start + j as f32 * step, p);
+            }
+        }
+        color / (samples * samples) as f32
+    }
+}
+
+/****************** Cube Map Texture ******************/
+// An environment of six square faces, looked up by the direction of the point from the center of the environment sphere
+// The faces follow the OpenGL layout of the cube map files, mirrored along x like three.js does, since the scenes are right handed
+#[derive(Debug, Clone)]
+pub struct CubeMapTexture {
+    // +X, -X, +Y, -Y, +Z, -Z
+    faces: [ImageTexture; 6],
+}
+
+impl CubeMapTexture {
+    pub fn new(filenames: &[String]) -> CubeMapTexture {
+        match filenames {
+            [cross] => CubeMapTexture::from_cross(cross),
+            [px, nx, py, ny, pz, nz] => CubeMapTexture { faces: [px, nx, py, ny, pz, nz].map(|filename| ImageTexture::new(filename).with_address(AddressMode::Clamp)) },
+            _ => panic!("A cube map is a single cross image or six faces (+X, -X, +Y, -Y, +Z, -Z), got {} files", filenames.len()),
+        }
+    }
+    // A horizontal cross (4x3 faces) or a vertical one (3x4, the -Z face upside down at the bottom)
+    pub fn from_cross(filename: &str) -> CubeMapTexture {
+        let cross: ImageTexture = ImageTexture::new(filename);
+        CubeMapTexture::from_cross_image(&cross.image)
+    }
+    pub fn from_cross_image(image: &DynamicImage) -> CubeMapTexture {
+        let (width, height) = image.dimensions();
+        let (size, cells): (u32, [(u32, u32); 6]) = if width * 3 == height * 4 { (width / 4, [(2, 1), (0, 1), (1, 0), (1, 2), (1, 1), (3, 1)]) }
+            else if width * 4 == height * 3 { (width / 3, [(2, 1), (0, 1), (1, 0), (1, 2), (1, 1), (1, 3)]) }
+            else { panic!("A cube map cross is 4x3 or 3x4 faces, got a {}x{} image", width, height) };
+        let faces: [ImageTexture; 6] = std::array::from_fn(|face| {
+            let (col, row) = cells[face];
+            let mut image: DynamicImage = image.crop_imm(col * size, row * size, size, size);
+            if face == 5 && height > width { image = image.rotate180(); }
+            ImageTexture { image: Arc::new(image), width: size, height: size, address: AddressMode::Clamp }
+        });
+        CubeMapTexture { faces }
+    }
+}
+
+// The face a direction points to, and where on it from the top left corner
+pub fn cube_face(direction: Vec3A) -> (usize, f32, f32) {
+    let (x, y, z) = (-direction.x, direction.y, direction.z);
+    let (ax, ay, az) = (x.abs(), y.abs(), z.abs());
+    let (face, sc, tc, ma) = if ax >= ay && ax >= az {
+        if x > 0.0 { (0, -z, -y, ax) } else { (1, z, -y, ax) }
+    } else if ay >= az {
+        if y > 0.0 { (2, x, z, ay) } else { (3, x, -z, ay) }
+    } else if z > 0.0 { (4, x, -y, az) } else { (5, -x, -y, az) };
+    if ma <= 0.0 { return (4, 0.5, 0.5); }
+    (face, 0.5 * (sc / ma + 1.0), 0.5 * (tc / ma + 1.0))
+}
+
+impl Texture for CubeMapTexture {
+    fn value(&self, _u: f32, _v: f32, p: &Point3) -> Color {
+        let (face, s, t) = cube_face(*p);
+        self.faces[face].value(s, 1.0 - t, p)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_image_texture_placeholder() {
+        let texture: ImageTexture = ImageTexture::placeholder();
+        assert_eq!(texture.value(0.01, 0.99, &Point3::ZERO), Color::new(1.0, 0.0, 1.0));
+        assert_eq!(texture.value(0.2, 0.99, &Point3::ZERO), Color::new(0.0, 0.0, 0.0));
+        // Tiled coordinates wrap around, while the edges stay on their own texels
+        assert_eq!(texture.value(1.2, 1.99, &Point3::ZERO), Color::new(0.0, 0.0, 0.0));
+        assert_eq!(texture.value(1.0, 1.0, &Point3::ZERO), Color::new(0.0, 0.0, 0.0));
+        // Clamped, as for the environment maps, they stretch the edge texels out
+        assert_eq!(texture.value(1.2, -0.5, &Point3::ZERO), Color::new(0.0, 0.0, 0.0));
+        let clamped: ImageTexture = texture.with_address(AddressMode::Clamp);
+        assert_eq!(clamped.value(1.2, -0.5, &Point3::ZERO), Color::new(1.0, 0.0, 1.0));
+    }
+    #[test]
+    fn test_cube_map() {
+        // Each face of a horizontal cross of 2x2 faces filled with its own gray level
+        let cells: [(u32, u32); 6] = [(2, 1), (0, 1), (1, 0), (1, 2), (1, 1), (3, 1)];
+        let cross: DynamicImage = DynamicImage::ImageRgb8(RgbImage::from_fn(8, 6, |x, y| {
+            let face: usize = cells.iter().position(|cell| *cell == (x / 2, y / 2)).unwrap_or(6);
+            Rgb([face as u8 * 40; 3])
+        }));
+        let cube: CubeMapTexture = CubeMapTexture::from_cross_image(&cross);
+        let gray = |direction: Vec3A| (cube.value(0.0, 0.0, &direction).x * 255.0).round() as u8;
+        // Mirrored along x, looking down +X shows the -X face
+        assert_eq!([Vec3A::X, -Vec3A::X, Vec3A::Y, -Vec3A::Y, Vec3A::Z, -Vec3A::Z].map(gray), [40, 0, 80, 120, 160, 200]);
+        assert_eq!(cube_face(-Vec3A::Z), (5, 0.5, 0.5));
+        // Right of the view down -Z is +X, so the image isn't mirrored, and up is the top of the face
+        let (_, s, t) = cube_face(Vec3A::new(0.5, 0.5, -1.0));
+        assert!(s > 0.5 && t < 0.5);
+    }
+    #[test]
+    fn test_image_texture_filtering() {
+        let texture: ImageTexture = ImageTexture::placeholder();
+        // A footprint smaller than a texel is point sampled
+        assert_eq!(texture.filtered_value(0.01, 0.99, &Point3::ZERO, 0.001), texture.value(0.01, 0.99, &Point3::ZERO));
+        // A footprint covering whole checker tiles averages them
+        let average: Color = texture.filtered_value(0.5, 0.5, &Point3::ZERO, 0.5);
+        assert!(average.abs_diff_eq(Color::new(0.5, 0.0, 0.5), 1e-6));
+    }
+    #[test]
+    fn test_uv_procedural_textures() {
+        let (black, white) = (Box::new(SolidColor::new(Color::ZERO)), Box::new(SolidColor::new(Color::ONE)));
+        let checker: UVChecker = UVChecker::new(black.clone(), white.clone(), Vec2::new(4.0, 2.0));
+        assert_eq!(checker.value(0.1, 0.1, &Point3::ZERO), Color::ONE);
+        assert_eq!(checker.value(0.3, 0.1, &Point3::ZERO), Color::ZERO);
+        assert_eq!(checker.value(0.3, 0.6, &Point3::ZERO), Color::ONE);
+        let brick: Brick = Brick::new(white.clone(), black.clone(), Vec2::new(2.0, 4.0), 0.1, 0.5);
+        assert_eq!(brick.value(0.25, 0.125, &Point3::ZERO), Color::ONE);
+        assert_eq!(brick.value(0.5, 0.125, &Point3::ZERO), Color::ZERO);
+        // The second row is shifted by half a brick
+        assert_eq!(brick.value(0.5, 0.375, &Point3::ZERO), Color::ONE);
+        let grid: Grid = Grid::new(black.clone(), white.clone(), Vec2::splat(10.0), 0.1);
+        assert_eq!(grid.value(0.301, 0.55, &Point3::ZERO), Color::ZERO);
+        assert_eq!(grid.value(0.35, 0.55, &Point3::ZERO), Color::ONE);
+        let dots: PolkaDot = PolkaDot::new(black, white, Vec2::splat(10.0), 0.25);
+        assert_eq!(dots.value(0.05, 0.05, &Point3::ZERO), Color::ZERO);
+        assert_eq!(dots.value(0.01, 0.01, &Point3::ZERO), Color::ONE);
+    }
+    #[test]
+    fn test_texture_gradients() {
+        // The gradient of the noise matches central differences of its value
+        let noise: Noise = Noise::new(Color::ZERO, Color::new(1.0, 0.5, 0.0), Vec2::new(3.0, 5.0), 3);
+        let height = |u: f32, v: f32| -> f32 { noise.value(u, v, &Point3::ZERO).dot(Color::ONE) / 3.0 };
+        let delta: f32 = 1e-3;
+        for (u, v) in [(0.13, 0.71), (0.5, 0.25), (0.92, 0.08)] {
+            let gradient: Vec2 = noise.gradient(u, v, &Point3::ZERO).unwrap();
+            let differences: Vec2 = Vec2::new(height(u + delta, v) - height(u - delta, v), height(u, v + delta) - height(u, v - delta)) / (2.0 * delta);
+            assert!((gradient - differences).length() < 0.02 * differences.length().max(1.0), "{} {}", gradient, differences);
+        }
+        // The patterns take the gradient of the texture of their cell, and the images have none
+        let checker: UVChecker = UVChecker::new(Box::new(noise.clone()), Box::new(SolidColor::new(Color::ONE)), Vec2::splat(2.0));
+        assert_eq!(checker.gradient(0.25, 0.25, &Point3::ZERO), Some(Vec2::ZERO));
+        assert_eq!(checker.gradient(0.75, 0.25, &Point3::ZERO), noise.gradient(0.75, 0.25, &Point3::ZERO));
+        assert_eq!(ImageTexture::placeholder().gradient(0.5, 0.5, &Point3::ZERO), None);
+    }
+    #[test]
+    fn test_randomize_per_instance() {
+        let base: Color = Color::new(0.6, 0.3, 0.2);
+        let texture: RandomizePerInstance = RandomizePerInstance::new(Box::new(SolidColor::new(base)), 0.1, 0.2);
+        let material: crate::material::Lambertian = crate::material::Lambertian::new(Color::ONE);
+        let (mut first, mut second) = (HitRecord::new(Point3::ZERO, Vec3A::Y, &material, 1.0, 0.0, 0.0, true), HitRecord::new(Point3::ZERO, Vec3A::Y, &material, 1.0, 0.0, 0.0, true));
+        (first.instance, second.instance) = (0, 1);
+        assert_ne!(texture.hit_value(&first), texture.hit_value(&second));
+        // and so does the primitive inside of the object
+        let mut primitive: HitRecord = first.clone();
+        primitive.primitive = 1;
+        assert_ne!(texture.hit_value(&first), texture.hit_value(&primitive));
+        // Nested in a pattern, the instance still reaches it
+        let checker: UVChecker = UVChecker::new(Box::new(texture.clone()), Box::new(texture), Vec2::splat(2.0));
+        assert_ne!(checker.hit_value(&first), checker.hit_value(&second));
+        // Without variation the base texture is returned untouched
+        let unchanged: RandomizePerInstance = RandomizePerInstance::new(Box::new(SolidColor::new(base)), 0.0, 0.0);
+        assert!(unchanged.hit_value(&second).abs_diff_eq(base, 1e-6));
+    }
 }
@@ -0,0 +1,269 @@
+// Author: Giulian Biolo, github.com/giulianbiolo
+// Date: 16/10/2026
+// Description: This file implements image textures streamed from disk as tiles, for textures too big to keep in memory
+
+use std::error::Error;
+use std::fmt::Debug;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+
+use image::{GenericImageView, ImageFormat, Rgb, RgbImage};
+
+use crate::assets;
+use crate::color::Color;
+use crate::point3::Point3;
+use crate::texture::{AddressMode, Texture};
+
+
+const TILES_INFO: &str = "tiles.txt";
+
+// The temporary directory, none in the browser where there's no file system
+fn _default_cache_dir() -> Option<PathBuf> {
+    if cfg!(target_arch = "wasm32") { None } else { Some(std::env::temp_dir().join("gbrt_tiles")) }
+}
+
+// A tile of the texture, in memory or not, and when it was last used
+struct TileSlot {
+    image: RwLock<Option<Arc<RgbImage>>>,
+    last_used: AtomicU64,
+    // Held by the thread loading the tile, so that the others missing it wait for it rather than load it again
+    loading: Mutex<()>,
+}
+
+// The tiles currently in memory, evicting the least recently used one when full
+// The hits only read the slot of their tile, so the render threads don't wait on each other
+// A miss decodes its tile holding the slot only, and locks the whole cache just to evict and publish it
+struct TileCache {
+    slots: Vec<TileSlot>,
+    columns: u32,
+    capacity: usize,
+    // Moves on at every miss, the hits since the last one all counting as used at the same time
+    clock: AtomicU64,
+    // The slots holding a tile
+    resident: Mutex<Vec<usize>>,
+}
+
+impl TileCache {
+    fn new(columns: u32, rows: u32, capacity: usize) -> TileCache {
+        let slots: Vec<TileSlot> = (0..columns * rows).map(|_| TileSlot { image: RwLock::new(None), last_used: AtomicU64::new(0), loading: Mutex::new(()) }).collect();
+        TileCache { slots, columns, capacity: capacity.max(1), clock: AtomicU64::new(0), resident: Mutex::new(Vec::new()) }
+    }
+    fn _hit(&self, slot: &TileSlot) -> Option<Arc<RgbImage>> {
+        let image: Arc<RgbImage> = slot.image.read().unwrap().as_ref()?.clone();
+        let now: u64 = self.clock.load(Ordering::Relaxed);
+        // Only written when it changes, not to bounce the cache line between the threads at every lookup
+        if slot.last_used.load(Ordering::Relaxed) != now { slot.last_used.store(now, Ordering::Relaxed); }
+        Some(image)
+    }
+    fn get(&self, tile: (u32, u32), load: impl FnOnce() -> Arc<RgbImage>) -> Arc<RgbImage> {
+        let index: usize = (tile.1 * self.columns + tile.0) as usize;
+        let slot: &TileSlot = &self.slots[index];
+        if let Some(image) = self._hit(slot) { return image; }
+        let _loading = slot.loading.lock().unwrap();
+        // Another thread may have loaded it while this one waited
+        if let Some(image) = self._hit(slot) { return image; }
+        let image: Arc<RgbImage> = load();
+        let mut resident = self.resident.lock().unwrap();
+        if resident.len() >= self.capacity {
+            let oldest: usize = (0..resident.len()).min_by_key(|position| self.slots[resident[*position]].last_used.load(Ordering::Relaxed)).unwrap();
+            *self.slots[resident.swap_remove(oldest)].image.write().unwrap() = None;
+        }
+        slot.last_used.store(self.clock.fetch_add(1, Ordering::Relaxed) + 1, Ordering::Relaxed);
+        *slot.image.write().unwrap() = Some(image.clone());
+        resident.push(index);
+        image
+    }
+}
+
+pub struct TiledImageTexture {
+    // The asset path of the directory of the tiles
+    tiles_dir: String,
+    width: u32,
+    height: u32,
+    tile_size: u32,
+    cache: Arc<TileCache>,
+}
+
+impl TiledImageTexture {
+    // The tiles are read through the assets, from a "<filename>.tiles" directory next to the image if it was split beforehand
+    // Otherwise the image is split the first time it's used, into the cache directory (the temporary one by default), or in memory if it can't be written
+    pub fn new(filename: &str, tile_size: u32, cache_tiles: usize, cache_dir: Option<&str>) -> Result<TiledImageTexture, Box<dyn Error>> {
+        println!("Loading tiled image texture from file: {}", filename);
+        let tile_size: u32 = tile_size.max(16);
+        let pretiled: String = format!("{}.tiles", filename);
+        let (tiles_dir, (width, height)) = match Self::_read_tiles_info(&pretiled, tile_size) {
+            Some(size) => (pretiled, size),
+            None => {
+                // The content names the tiles, so that an edited image gets split again
+                let bytes: Vec<u8> = assets::read(filename)?;
+                let mut hasher: DefaultHasher = DefaultHasher::new();
+                bytes.hash(&mut hasher);
+                let name: String = format!("{:016x}_{}", hasher.finish(), tile_size);
+                let cache_dir: Option<PathBuf> = cache_dir.map(PathBuf::from).or_else(_default_cache_dir);
+                let tiles_dir: String = cache_dir.as_ref().map_or(name.clone(), |cache_dir| cache_dir.join(&name).to_string_lossy().to_string());
+                let size: (u32, u32) = match Self::_read_tiles_info(&tiles_dir, tile_size) {
+                    Some(size) => size,
+                    None => Self::_build_tiles(filename, &bytes, &tiles_dir, cache_dir.is_some(), tile_size)?,
+                };
+                (tiles_dir, size)
+            },
+        };
+        Ok(TiledImageTexture { tiles_dir, width, height, tile_size, cache: Arc::new(TileCache::new(width.div_ceil(tile_size), height.div_ceil(tile_size), cache_tiles)) })
+    }
+    // Returns the size of the image if the tiles are there, split to the same size
+    fn _read_tiles_info(tiles_dir: &str, tile_size: u32) -> Option<(u32, u32)> {
+        let info: Vec<u32> = assets::read_to_string(&Self::_path(tiles_dir, TILES_INFO)).ok()?.split_whitespace().filter_map(|value| value.parse().ok()).collect();
+        if info.len() != 3 || info[2] != tile_size { return None; }
+        Some((info[0], info[1]))
+    }
+    // Decodes the image a band of tile_size rows at a time, so that it never is in memory as a whole
+    // Only the PNG images can be decoded that way, the other formats have to be split beforehand
+    fn _build_tiles(filename: &str, bytes: &[u8], tiles_dir: &str, on_disk: bool, tile_size: u32) -> Result<(u32, u32), Box<dyn Error>> {
+        println!("Splitting {} into {}x{} tiles, this only happens once", filename, tile_size, tile_size);
+        if ImageFormat::from_path(filename).ok().or_else(|| image::guess_format(bytes).ok()) != Some(ImageFormat::Png) {
+            return Err(format!("only PNG images can be split into tiles while decoding them, split {} into {}.tiles beforehand", filename, filename).into());
+        }
+        let mut decoder: png::Decoder<Cursor<&[u8]>> = png::Decoder::new(Cursor::new(bytes));
+        decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+        let mut reader = decoder.read_info()?;
+        if reader.info().interlaced { return Err(format!("{} is interlaced, its rows can't be decoded in order", filename).into()); }
+        let (width, height) = reader.info().size();
+        let channels: usize = reader.output_color_type().0.samples();
+        // The tiles are kept in memory when the cache directory can't be written, like in the browser
+        let on_disk: bool = on_disk && match std::fs::create_dir_all(tiles_dir) {
+            Ok(()) => true,
+            Err(err) => { println!("Warning: can't write the tiles of {} to {} ({}), keeping them in memory", filename, tiles_dir, err); false },
+        };
+        let store = |name: &str, content: Vec<u8>| -> Result<(), Box<dyn Error>> {
+            let path: String = Self::_path(tiles_dir, name);
+            if on_disk { std::fs::write(&path, content)?; } else { assets::register(&path, content); }
+            Ok(())
+        };
+        for ty in 0..height.div_ceil(tile_size) {
+            let rows: u32 = tile_size.min(height - ty * tile_size);
+            let mut band: RgbImage = RgbImage::new(width, rows);
+            for y in 0..rows {
+                let row = reader.next_row()?.ok_or_else(|| format!("{} ended before its last row", filename))?;
+                for (x, texel) in row.data().chunks_exact(channels).enumerate() {
+                    // The gray images are expanded to rgb, the alpha is dropped
+                    band.put_pixel(x as u32, y, if channels < 3 { Rgb([texel[0]; 3]) } else { Rgb([texel[0], texel[1], texel[2]]) });
+                }
+            }
+            for tx in 0..width.div_ceil(tile_size) {
+                let x: u32 = tx * tile_size;
+                let mut content: Vec<u8> = Vec::new();
+                band.view(x, 0, tile_size.min(width - x), rows).to_image().write_to(&mut Cursor::new(&mut content), ImageFormat::Png)?;
+                store(&format!("{}_{}.png", tx, ty), content)?;
+            }
+        }
+        store(TILES_INFO, format!("{} {} {}", width, height, tile_size).into_bytes())?;
+        Ok((width, height))
+    }
+    fn _path(tiles_dir: &str, name: &str) -> String { Path::new(tiles_dir).join(name).to_string_lossy().to_string() }
+    fn _load_tile(&self, tx: u32, ty: u32) -> Arc<RgbImage> {
+        let path: String = Self::_path(&self.tiles_dir, &format!("{}_{}.png", tx, ty));
+        match assets::open_image(&path) {
+            Ok(tile) => Arc::new(tile.to_rgb8()),
+            Err(err) => panic!("Failed to load texture tile {}: {}", path, err),
+        }
+    }
+    #[allow(dead_code)]
+    pub fn cached_tiles(&self) -> usize { self.cache.resident.lock().unwrap().len() }
+}
+
+impl Debug for TiledImageTexture {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TiledImageTexture")
+            .field("tiles_dir", &self.tiles_dir)
+            .field("width", &self.width)
+            .field("height", &self.height)
+            .field("tile_size", &self.tile_size)
+            .finish()
+    }
+}
+
+impl Clone for TiledImageTexture {
+    fn clone(&self) -> Self {
+        TiledImageTexture {
+            tiles_dir: self.tiles_dir.clone(),
+            width: self.width,
+            height: self.height,
+            tile_size: self.tile_size,
+            cache: self.cache.clone(),
+        }
+    }
+}
+
+impl Texture for TiledImageTexture {
+    fn value(&self, u: f32, v: f32, _p: &Point3) -> Color {
+        // Same addressing as the ImageTexture of a surface, with the uvs wrapping around
+        let u = AddressMode::Wrap.apply(u);
+        let v = 1.0 - AddressMode::Wrap.apply(v);
+        let i = ((u * self.width as f32) as u32).min(self.width - 1);
+        let j = ((v * self.height as f32) as u32).min(self.height - 1);
+        let (tx, ty) = (i / self.tile_size, j / self.tile_size);
+        let tile: Arc<RgbImage> = self.cache.get((tx, ty), || self._load_tile(tx, ty));
+        let pixel = tile.get_pixel(i % self.tile_size, j % self.tile_size);
+        let color_scale = 1.0 / 255.0;
+        Color::new(pixel[0] as f32 * color_scale, pixel[1] as f32 * color_scale, pixel[2] as f32 * color_scale)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::texture::ImageTexture;
+
+    #[test]
+    fn test_tiled_image_texture() {
+        let filename: PathBuf = std::env::temp_dir().join(format!("gbrt_tiled_{}.png", std::process::id()));
+        let cache_dir: PathBuf = std::env::temp_dir().join(format!("gbrt_tiled_{}_cache", std::process::id()));
+        let image: RgbImage = RgbImage::from_fn(40, 24, |x, y| image::Rgb([(x * 6) as u8, (y * 10) as u8, ((x + y) * 3) as u8]));
+        image.save(&filename).unwrap();
+        let texture: TiledImageTexture = TiledImageTexture::new(filename.to_str().unwrap(), 16, 2, cache_dir.to_str()).unwrap();
+        // The tiles go to the cache directory, not next to the image
+        assert!(texture.tiles_dir.starts_with(cache_dir.to_str().unwrap()) && !Path::new(&format!("{}.tiles", filename.display())).exists());
+        let reference: ImageTexture = ImageTexture::new(filename.to_str().unwrap());
+        for (u, v) in [(0.01, 0.01), (0.5, 0.5), (0.99, 0.2), (0.3, 0.9), (1.7, -0.4)] {
+            assert_eq!(texture.value(u, v, &Point3::ZERO), reference.value(u, v, &Point3::ZERO));
+        }
+        assert!(texture.cached_tiles() <= 2);
+        // Looked up from several threads at once, the lookups still agree and the cache stays within its capacity
+        std::thread::scope(|scope| {
+            for thread in 0..4 {
+                let (texture, reference) = (texture.clone(), reference.clone());
+                scope.spawn(move || {
+                    for step in 0..200 {
+                        let (u, v) = ((step * 7 + thread) as f32 / 200.0, (step * 3) as f32 / 200.0);
+                        assert_eq!(texture.value(u, v, &Point3::ZERO), reference.value(u, v, &Point3::ZERO));
+                    }
+                });
+            }
+        });
+        assert!(texture.cached_tiles() <= 2);
+        // Two threads missing different tiles decode them at the same time, each waiting in its load for the other to be loading too
+        let (cache, barrier): (TileCache, std::sync::Barrier) = (TileCache::new(2, 1, 1), std::sync::Barrier::new(2));
+        std::thread::scope(|scope| {
+            for tx in 0..2 {
+                let (cache, barrier) = (&cache, &barrier);
+                scope.spawn(move || cache.get((tx, 0), || { barrier.wait(); Arc::new(RgbImage::new(1, 1)) }));
+            }
+        });
+        assert_eq!(cache.resident.lock().unwrap().len(), 1);
+        // An image only in memory, with a cache directory that can't be created, is split in memory and looked up the same
+        let mut content: Vec<u8> = Vec::new();
+        image.write_to(&mut Cursor::new(&mut content), ImageFormat::Png).unwrap();
+        assets::register("tiled_in_memory.png", content);
+        let in_memory: TiledImageTexture = TiledImageTexture::new("tiled_in_memory.png", 16, 2, filename.join("cache").to_str()).unwrap();
+        assert_eq!(in_memory.value(0.3, 0.9, &Point3::ZERO), reference.value(0.3, 0.9, &Point3::ZERO));
+        // The formats that can't be decoded by rows have to be split beforehand
+        assets::register("tiled_in_memory.bmp", vec![0; 16]);
+        assert!(TiledImageTexture::new("tiled_in_memory.bmp", 16, 2, cache_dir.to_str()).is_err());
+        std::fs::remove_dir_all(&cache_dir).unwrap();
+        std::fs::remove_file(&filename).unwrap();
+    }
+}
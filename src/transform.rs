@@ -0,0 +1,147 @@
+// Author: Giulian Biolo, github.com/giulianbiolo
+// Date: 16/10/2026
+// Description: This file implements the Transformed wrapper, placing an object in the world with an affine transform without touching its geometry
+
+use std::sync::Arc;
+
+use bvh::aabb::AABB;
+use bvh::Point3 as BVHPoint3;
+use glam::{Affine3A, Mat3A, Vec3A};
+
+use crate::diffraction::Edge;
+use crate::gpu_scene::FlatTriangle;
+use crate::hit_record::HitRecord;
+use crate::hittable_list::{self, Hittable};
+use crate::light_sampler::LightEstimate;
+use crate::point3::Point3;
+use crate::ray::Ray;
+
+
+// The rays are brought into the space of the object, so moving it only replaces the matrices and its own BVH stays valid
+// The light sampling pdfs carry the change of solid angle of the linear part, so they stay exact under non-uniform scales and shears
+pub struct Transformed {
+    object: Arc<dyn Hittable + Send + Sync>,
+    transform: Affine3A,
+    inverse: Affine3A,
+    // The inverse transpose, for the normals
+    normal_matrix: Mat3A,
+}
+
+impl Transformed {
+    pub fn new(object: Arc<dyn Hittable + Send + Sync>, transform: Affine3A) -> Transformed {
+        let inverse: Affine3A = transform.inverse();
+        if !inverse.is_finite() { panic!("The transform of the object isn't invertible: {:?}", transform); }
+        Transformed { object, transform, inverse, normal_matrix: inverse.matrix3.transpose() }
+    }
+    pub fn transform(&self) -> Affine3A { self.transform }
+    // The same object under another transform, sharing its geometry
+    pub fn with_transform(&self, transform: Affine3A) -> Transformed { Transformed::new(self.object.clone(), transform) }
+    pub fn object(&self) -> &Arc<dyn Hittable + Send + Sync> { &self.object }
+    fn _local_ray(&self, ray: &Ray) -> Ray {
+        let mut local: Ray = Ray::new(self.inverse.transform_point3a(ray.origin), self.inverse.transform_vector3a(ray.direction));
+        local.light_mask = ray.light_mask;
+        local
+    }
+    // How much the transform scales the lengths, on average over the axes
+    fn _scale(&self) -> f32 { self.transform.matrix3.determinant().abs().cbrt() }
+}
+
+impl Hittable for Transformed {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        // The local direction isn't normalized, so the distances along the ray are the same in both spaces
+        let mut rec: HitRecord = self.object.hit(&self._local_ray(ray), t_min, t_max)?;
+        rec.p = self.transform.transform_point3a(rec.p);
+        rec.normal = (self.normal_matrix * rec.normal).normalize();
+        rec.dpdu = self.transform.transform_vector3a(rec.dpdu);
+        rec.dpdv = self.transform.transform_vector3a(rec.dpdv);
+        Some(rec)
+    }
+    fn occluded(&self, ray: &Ray, t_min: f32, t_max: f32) -> bool { self.object.occluded(&self._local_ray(ray), t_min, t_max) }
+    fn is_light(&self) -> bool { self.object.is_light() }
+    fn light_estimate(&self) -> Option<LightEstimate> {
+        let estimate: LightEstimate = self.object.light_estimate()?;
+        let scale: f32 = self._scale();
+        Some(LightEstimate { center: self.transform.transform_point3a(estimate.center), radius: estimate.radius * scale, power: estimate.power * scale * scale })
+    }
+    // The density of the object is over the directions of its own space, which the inverse maps the unit direction u to A u, the solid angles growing by |det A| / |A u|^3
+    fn pdf_value(&self, origin: &Point3, v: &Vec3A) -> f32 {
+        let local: Vec3A = self.inverse.transform_vector3a(v.normalize());
+        self.object.pdf_value(&self.inverse.transform_point3a(*origin), &local) * self.inverse.matrix3.determinant().abs() / local.length().powi(3)
+    }
+    fn random(&self, origin: &Point3) -> Vec3A { self.transform.transform_vector3a(self.object.random(&self.inverse.transform_point3a(*origin))) }
+    fn edges(&self) -> Vec<Edge> {
+        self.object.edges().into_iter().map(|edge| Edge {
+            start: self.transform.transform_point3a(edge.start),
+            end: self.transform.transform_point3a(edge.end),
+            normal: (self.normal_matrix * edge.normal).normalize_or_zero(),
+        }).collect()
+    }
+    fn tessellation(&self) -> Vec<FlatTriangle> {
+        self.object.tessellation().into_iter().map(|triangle| FlatTriangle {
+            vertices: triangle.vertices.map(|vertex| self.transform.transform_point3a(vertex)),
+            normals: triangle.normals.map(|normal| (self.normal_matrix * normal).normalize_or_zero()),
+            uvs: triangle.uvs,
+        }).collect()
+    }
+    // The box around the transformed corners of the box of the object, loose for the rotations
+    fn world_bounds(&self) -> Option<AABB> {
+        let bounds: AABB = self.object.world_bounds()?;
+        Some(hittable_list::bounds_corners(&bounds).iter().fold(AABB::empty(), |world, corner| {
+            let corner: Point3 = self.transform.transform_point3a(*corner);
+            world.grow(&BVHPoint3::new(corner.x, corner.y, corner.z))
+        }))
+    }
+    fn wrapped(&self) -> Option<&(dyn Hittable + Send + Sync)> { Some(&*self.object) }
+    fn wrapped_mut(&mut self) -> Option<&mut (dyn Hittable + Send + Sync + 'static)> { Arc::get_mut(&mut self.object) }
+}
+
+// Tells the hits of the object which instance of its group placed it, for the textures that vary per instance
+pub struct Instanced {
+    object: Arc<dyn Hittable + Send + Sync>,
+    instance: u32,
+}
+
+impl Instanced {
+    pub fn new(object: Arc<dyn Hittable + Send + Sync>, instance: u32) -> Instanced { Instanced { object, instance } }
+}
+
+impl Hittable for Instanced {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        let mut rec: HitRecord = self.object.hit(ray, t_min, t_max)?;
+        rec.instance = self.instance;
+        Some(rec)
+    }
+    fn occluded(&self, ray: &Ray, t_min: f32, t_max: f32) -> bool { self.object.occluded(ray, t_min, t_max) }
+    fn is_light(&self) -> bool { self.object.is_light() }
+    fn light_estimate(&self) -> Option<LightEstimate> { self.object.light_estimate() }
+    fn pdf_value(&self, origin: &Point3, v: &Vec3A) -> f32 { self.object.pdf_value(origin, v) }
+    fn random(&self, origin: &Point3) -> Vec3A { self.object.random(origin) }
+    fn wrapped(&self) -> Option<&(dyn Hittable + Send + Sync)> { Some(&*self.object) }
+    fn wrapped_mut(&mut self) -> Option<&mut (dyn Hittable + Send + Sync + 'static)> { Arc::get_mut(&mut self.object) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use glam::{Quat, Vec3};
+    use crate::color::Color;
+    use crate::material::DiffuseLight;
+    use crate::sphere::Sphere;
+    use crate::utility;
+
+    #[test]
+    fn test_transformed_pdf() {
+        let light: Arc<dyn Hittable + Send + Sync> = Arc::new(Sphere::new(Point3::new(0.0, 0.0, -3.0), 1.0, Box::new(DiffuseLight::new(Color::ONE, 1.0)), 0));
+        let origin: Point3 = Point3::new(0.0, 0.5, 0.5);
+        // Stretched and turned, the sphere still has its pdf sum up to one over the directions, and the directions it samples hit it
+        let stretched: Transformed = Transformed::new(light, Affine3A::from_scale_rotation_translation(Vec3::new(3.0, 1.0, 1.0), Quat::from_rotation_y(0.3), Vec3::ZERO));
+        let samples: usize = 200000;
+        let integral: f32 = (0..samples).map(|_| stretched.pdf_value(&origin, &utility::random_unit_vector())).sum::<f32>() * 4.0 * utility::PI / samples as f32;
+        assert!((integral - 1.0).abs() < 0.05, "{}", integral);
+        for _ in 0..64 {
+            let direction: Vec3A = stretched.random(&origin);
+            assert!(stretched.hit(&Ray::new(origin, direction), utility::NEAR_ZERO, utility::INFINITY).is_some());
+            assert!(stretched.pdf_value(&origin, &direction) > 0.0);
+        }
+    }
+}
@@ -0,0 +1,128 @@
+// Author: Giulian Biolo, github.com/giulianbiolo
+// Date: 16/10/2026
+// Description: This file implements the transient rendering mode, binning the radiance reaching each pixel by the optical length of its path (its time of arrival)
+
+use std::io::Write;
+use std::path::Path;
+use std::sync::Arc;
+
+use glam::Vec3A;
+use image::{ImageBuffer, Rgb};
+
+use crate::camera::Camera;
+use crate::color::{Color, to_rgb};
+use crate::hittable_list::{Hittable, HittableList};
+use crate::parallel::*;
+use crate::ray::Ray;
+use crate::raytracer;
+use crate::sampling_filters::Filter;
+use crate::utility::{self, CONSTS, random_f32};
+
+
+// Speed of light, in meters per nanosecond
+const LIGHT_SPEED: f32 = 0.299_792_46;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Transient {
+    pub bins: usize,
+    // Longest optical path binned, in scene units (meters), the longer ones are dropped
+    pub max_distance: f32,
+}
+
+impl Transient {
+    pub fn bin_width(&self) -> f32 { self.max_distance / self.bins as f32 }
+    pub fn bin(&self, distance: f32) -> Option<usize> {
+        if !(0.0..self.max_distance).contains(&distance) { return None; }
+        Some(((distance / self.bin_width()) as usize).min(self.bins - 1))
+    }
+    // Time of arrival of the center of the bin, in nanoseconds
+    pub fn bin_time(&self, bin: usize) -> f32 { (bin as f32 + 0.5) * self.bin_width() / LIGHT_SPEED }
+}
+
+/****************** Histograms ******************/
+// The radiance reaching each pixel in each time bin, summed over the samples
+pub struct Histograms {
+    pub transient: Transient,
+    pub width: u32,
+    pub height: u32,
+    // Row major pixels, each with its bins in order of arrival
+    pub bins: Vec<Color>,
+}
+
+impl Histograms {
+    pub fn pixel(&self, x: u32, y: u32) -> &[Color] {
+        let start: usize = (y * self.width + x) as usize * self.transient.bins;
+        &self.bins[start..start + self.transient.bins]
+    }
+    // The steady state image, all the bins together
+    pub fn integrated(&self, x: u32, y: u32) -> Color { self.pixel(x, y).iter().sum() }
+    // Root mean square delay spread of the power reaching the pixel, in nanoseconds, None when nothing reaches it
+    pub fn rms_delay_spread(&self, x: u32, y: u32) -> Option<f32> {
+        let powers: Vec<f32> = self.pixel(x, y).iter().map(|color| color.length()).collect();
+        let total: f32 = powers.iter().sum();
+        if total <= 0.0 { return None; }
+        let mean: f32 = powers.iter().enumerate().map(|(bin, power)| power * self.transient.bin_time(bin)).sum::<f32>() / total;
+        let variance: f32 = powers.iter().enumerate().map(|(bin, power)| power * (self.transient.bin_time(bin) - mean).powi(2)).sum::<f32>() / total;
+        Some(variance.sqrt())
+    }
+    // Saves the steady state image, one time of flight image per bin next to it, and the raw histograms for delay spread analysis
+    pub fn save(&self, filename: &str, samples: u32) {
+        let image: ImageBuffer<Rgb<u8>, Vec<u8>> = ImageBuffer::from_fn(self.width, self.height, |x, y| to_rgb(self.integrated(x, y), samples as f32));
+        image.save(filename).unwrap();
+        let path: &Path = Path::new(filename);
+        let stem: String = path.with_extension("").to_string_lossy().to_string();
+        let extension: String = path.extension().map_or("png".to_string(), |extension| extension.to_string_lossy().to_string());
+        for bin in 0..self.transient.bins {
+            let frame: ImageBuffer<Rgb<u8>, Vec<u8>> = ImageBuffer::from_fn(self.width, self.height, |x, y| to_rgb(self.pixel(x, y)[bin], samples as f32));
+            frame.save(format!("{}_tof_{:03}.{}", stem, bin, extension)).unwrap();
+        }
+        // Header of the width, height and number of bins as u32 and the bin width in meters as f32, then the rgb of every bin of every pixel, little endian
+        let mut file = std::fs::File::create(format!("{}_transient.bin", stem)).unwrap();
+        for size in [self.width, self.height, self.transient.bins as u32] { file.write_all(&size.to_le_bytes()).unwrap(); }
+        file.write_all(&self.transient.bin_width().to_le_bytes()).unwrap();
+        let bytes: Vec<u8> = self.bins.iter().flat_map(|color| (*color / samples as f32).to_array()).flat_map(|value| value.to_le_bytes()).collect();
+        file.write_all(&bytes).unwrap();
+        let spreads: Vec<f32> = (0..self.height).flat_map(|y| (0..self.width).filter_map(move |x| self.rms_delay_spread(x, y))).collect();
+        if !spreads.is_empty() { println!("Saved {} time of flight images, mean RMS delay spread of {:.3}ns", self.transient.bins, spreads.iter().sum::<f32>() / spreads.len() as f32); }
+    }
+}
+
+// Traces the camera rays as the steady state render does, adding each sample to the bin of the length of its path
+pub fn render(world: &HittableList, lights: &HittableList, environment_map: &Arc<dyn Hittable + Send + Sync>, background: Option<&Arc<dyn Hittable + Send + Sync>>, filter: &(dyn Filter + Send + Sync), cam: &Camera, transient: &Transient) -> Histograms {
+    let mut bins: Vec<Color> = vec![Color::ZERO; (CONSTS.width * CONSTS.height) as usize * transient.bins];
+    bins.par_chunks_mut(CONSTS.width as usize * transient.bins).enumerate().map(|(y, row)| {
+        utility::seed_stream(*utility::RENDER_SEED, y as u64);
+        let mut path: Vec<Vec3A> = Vec::with_capacity(CONSTS.max_depth as usize + 2);
+        for (x, pixel) in row.chunks_mut(transient.bins).enumerate() {
+            for _s in 0..CONSTS.samples_per_pixel {
+                let u: f32 = (x as f32 + filter.sample(random_f32())) / (CONSTS.width as f32 - 1.0);
+                let v: f32 = (CONSTS.height as f32 - (y as f32 + filter.sample(random_f32()))) / (CONSTS.height as f32 - 1.0);
+                let r: Ray = cam.get_ray_differential(u, v, 1.0 / (CONSTS.width as f32 - 1.0), 1.0 / (CONSTS.height as f32 - 1.0));
+                path.clear();
+                path.push(r.origin());
+                let color: Color = raytracer::ray_color(&r, world, lights, environment_map, background, &CONSTS, 0, &mut path);
+                // Only the light at the end of the path is gathered, so the whole sample arrives after the length of the path
+                let distance: f32 = path.windows(2).map(|segment| (segment[1] - segment[0]).length()).sum();
+                if let Some(bin) = transient.bin(distance) { if color.is_finite() { pixel[bin] += color; } }
+            }
+        }
+    }).reduce(|| (), |_, _| ());
+    Histograms { transient: *transient, width: CONSTS.width, height: CONSTS.height, bins }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_delay_spread() {
+        let transient: Transient = Transient { bins: 4, max_distance: 4.0 * LIGHT_SPEED };
+        assert_eq!((transient.bin(0.5 * LIGHT_SPEED), transient.bin(3.9 * LIGHT_SPEED), transient.bin(5.0)), (Some(0), Some(3), None));
+        // Two equally strong arrivals 2ns apart spread the pulse by 1ns
+        let mut histograms: Histograms = Histograms { transient, width: 1, height: 1, bins: vec![Color::ZERO; 4] };
+        histograms.bins[0] = Color::ONE;
+        histograms.bins[2] = Color::ONE;
+        assert!((histograms.rms_delay_spread(0, 0).unwrap() - 1.0).abs() < utility::EPSILON);
+        assert_eq!(histograms.integrated(0, 0), Color::splat(2.0));
+    }
+}
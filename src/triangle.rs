@@ -6,13 +6,17 @@ use bvh::aabb::{AABB, Bounded};
 use bvh::bounding_hierarchy::BHShape;
 use bvh::Point3 as BVHPoint3;
 
-use glam::Vec3A;
+use glam::{Vec2, Vec3A};
 
 use crate::ray::Ray;
 use crate::hit_record::HitRecord;
+use crate::diffraction::{self, Edge};
+use crate::gpu_scene::FlatTriangle;
 use crate::hittable_list::Hittable;
+use crate::light_sampler::LightEstimate;
 use crate::material::Material;
 use crate::point3::Point3;
+use crate::sampling;
 use crate::utility::{NEAR_ZERO, EPSILON, self};
 
 
@@ -20,6 +24,12 @@ use crate::utility::{NEAR_ZERO, EPSILON, self};
 pub struct Triangle {
     vertices: Box<[Point3; 3]>,
     normals: Box<[Vec3A; 3]>,
+    // Per vertex texture coordinates, when missing the barycentric coordinates are used instead
+    uvs: Option<Box<[Vec2; 3]>>,
+    // Per vertex cavity, for the dirt of the meshes
+    cavity: Option<Box<[f32; 3]>>,
+    // Heights of the vertices over their opposite edges, for the wireframe of the meshes
+    heights: Option<Box<[f32; 3]>>,
     material: Box<dyn Material>,
     node_index: usize,
 }
@@ -32,7 +42,12 @@ impl Triangle {
     pub fn new(mut vertices: Box<[Point3; 3]>, mut normals: Box<[Vec3A; 3]>, material: Box<dyn Material>, node_index: usize) -> Triangle {
         // We repair the normals if they are not pointing in the right direction
         _check_repair_normals(&mut vertices, &mut normals);
-        Triangle { vertices, normals, material, node_index }
+        Triangle { vertices, normals, uvs: None, cavity: None, heights: None, material, node_index }
+    }
+    pub fn new_with_uvs(mut vertices: Box<[Point3; 3]>, mut normals: Box<[Vec3A; 3]>, mut uvs: Box<[Vec2; 3]>, material: Box<dyn Material>, node_index: usize) -> Triangle {
+        // The uvs must follow the vertices if the winding order gets fixed
+        if _check_repair_normals(&mut vertices, &mut normals) { uvs.swap(1, 2); }
+        Triangle { vertices, normals, uvs: Some(uvs), cavity: None, heights: None, material, node_index }
     }
     // fn _get_triangle_uv(&self, p: &Vec3A) -> (f32, f32) { ((p.x - self.vertices[0].x) / (self.vertices[1].x - self.vertices[0].x), (p.y - self.vertices[0].y) / (self.vertices[2].y - self.vertices[0].y)) }
     fn _get_triangle_uv(&self, p: &Vec3A) -> (f32, f32) {
@@ -45,6 +60,64 @@ impl Triangle {
         (u, v)
     }
     fn _get_triangle_normal(&self, u: f32, v: f32) -> Vec3A { self.normals[0] * (1.0 - u - v) + self.normals[1] * u + self.normals[2] * v }
+    // Derivatives of the position along the texture coordinates, from the edges and the uvs of the vertices
+    fn _get_triangle_derivatives(&self, e1: Vec3A, e2: Vec3A) -> (Vec3A, Vec3A) {
+        let uvs = match &self.uvs {
+            Some(uvs) => uvs,
+            None => return (e1, e2),
+        };
+        let (duv1, duv2) = (uvs[1] - uvs[0], uvs[2] - uvs[0]);
+        let det: f32 = duv1.x * duv2.y - duv1.y * duv2.x;
+        if det.abs() < 1e-12 { return (Vec3A::ZERO, Vec3A::ZERO); }
+        ((e1 * duv2.y - e2 * duv1.y) / det, (e2 * duv1.x - e1 * duv2.x) / det)
+    }
+    // Distance, barycentric and texture coordinates of the hit, if it isn't cut out by the material alpha
+    fn _intersect(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<(f32, f32, f32, Vec2)> {
+        // This is a simple implementation of the Moller-Trumbore algorithm for ray-triangle intersection
+        let v0: Vec3A = self.vertices[0];
+
+        let e1: Vec3A = self.vertices[1] - v0;
+        let e2: Vec3A = self.vertices[2] - v0;
+
+        let p: Vec3A = ray.direction.cross(e2);
+        let det: f32 = e1.dot(p);
+
+        if det.abs() < EPSILON { return None; }
+
+        let inv_det: f32 = 1.0 / det;
+        let s: Vec3A = ray.origin - v0;
+        let u: f32 = inv_det * s.dot(p);
+
+        if u < 0.0 || u > 1.0 { return None; }
+
+        let q: Vec3A = s.cross(e1);
+        let v: f32 = inv_det * ray.direction.dot(q);
+
+        if v < 0.0 || u + v > 1.0 { return None; }
+
+        let t: f32 = inv_det * e2.dot(q);
+
+        if t <= t_min || t >= t_max { return None; }
+        // let (u, v) = self._get_triangle_uv(&ray.at(t));
+        let tex_uv: Vec2 = match &self.uvs {
+            Some(uvs) => uvs[0] * (1.0 - u - v) + uvs[1] * u + uvs[2] * v,
+            None => Vec2::new(u, v),
+        };
+        if !self.material.alpha_test(tex_uv.x, tex_uv.y, &ray.at(t)) { return None; }
+        Some((t, u, v, tex_uv))
+    }
+    pub fn vertices(&self) -> &[Point3; 3] { &self.vertices }
+    pub fn uvs(&self) -> Option<&[Vec2; 3]> { self.uvs.as_deref() }
+    pub fn material(&self) -> &(dyn Material + 'static) { self.material.as_ref() }
+    pub fn area(&self) -> f32 { (self.vertices[1] - self.vertices[0]).cross(self.vertices[2] - self.vertices[0]).length() / 2.0 }
+    pub fn set_material(&mut self, material: Box<dyn Material>) { self.material = material; }
+    pub fn set_cavity(&mut self, cavity: [f32; 3]) { self.cavity = Some(Box::new(cavity)); }
+    // Twice the area over the length of each edge, so that the barycentric coordinate of a vertex scales into the distance to the edge opposite of it
+    pub fn set_wireframe(&mut self) {
+        let area: f32 = 2.0 * self.area();
+        let [a, b, c] = *self.vertices;
+        self.heights = Some(Box::new([area / (c - b).length(), area / (a - c).length(), area / (b - a).length()]));
+    }
     pub fn check_not_degenerate(&self) -> bool {
         (self.vertices[0] - self.vertices[1]).length() > NEAR_ZERO &&
         (self.vertices[1] - self.vertices[2]).length() > NEAR_ZERO &&
@@ -52,14 +125,15 @@ impl Triangle {
     }
 }
 
-fn _check_repair_normals(vertices: &mut Box<[Point3; 3]>, normals: &mut Box<[Vec3A; 3]>) {
+// Returns whether the winding order had to be fixed
+fn _check_repair_normals(vertices: &mut Box<[Point3; 3]>, normals: &mut Box<[Vec3A; 3]>) -> bool {
     let n = (vertices[1] - vertices[0]).cross(vertices[2] - vertices[0]).normalize();
     if normals[0].length_squared() < NEAR_ZERO { normals[0] = n; }
     if normals[1].length_squared() < NEAR_ZERO { normals[1] = n; }
     if normals[2].length_squared() < NEAR_ZERO { normals[2] = n; }
-    _fix_winding_order(vertices, normals);
+    _fix_winding_order(vertices, normals)
 }
-fn _fix_winding_order(vertices: &mut Box<[Point3; 3]>, normals: &mut Box<[Vec3A; 3]>) {
+fn _fix_winding_order(vertices: &mut Box<[Point3; 3]>, normals: &mut Box<[Vec3A; 3]>) -> bool {
     let n = (vertices[1] - vertices[0]).cross(vertices[2] - vertices[0]).normalize();
     let all_normals_have_wrong_orientation = normals[0].dot(n) < 0.0 && normals[1].dot(n) < 0.0 && normals[2].dot(n) < 0.0;
     if all_normals_have_wrong_orientation {
@@ -71,6 +145,7 @@ fn _fix_winding_order(vertices: &mut Box<[Point3; 3]>, normals: &mut Box<[Vec3A;
         normals[1] = normals[2];
         normals[2] = temp_normal;
     }
+    all_normals_have_wrong_orientation
 }
 
 impl Bounded for Triangle {
@@ -92,59 +167,58 @@ impl BHShape for Triangle {
 }
 
 impl Hittable for Triangle {
-    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
-        // This is a simple implementation of the Moller-Trumbore algorithm for ray-triangle intersection
-        let v0: Vec3A = self.vertices[0];
-
-        let e1: Vec3A = self.vertices[1] - v0;
-        let e2: Vec3A = self.vertices[2] - v0;
-
-        let p: Vec3A = ray.direction.cross(e2);
-        let det: f32 = e1.dot(p);
-
-        if det.abs() < EPSILON { return None; }
-
-        let inv_det: f32 = 1.0 / det;
-        let s: Vec3A = ray.origin - v0;
-        let u: f32 = inv_det * s.dot(p);
-
-        if u < 0.0 || u > 1.0 { return None; }
-
-        let q: Vec3A = s.cross(e1);
-        let v: f32 = inv_det * ray.direction.dot(q);
-
-        if v < 0.0 || u + v > 1.0 { return None; }
-
-        let t: f32 = inv_det * e2.dot(q);
-
-        if t > t_min && t < t_max {
-            // let (u, v) = self._get_triangle_uv(&ray.at(t));
-            let mut rec: HitRecord = HitRecord::new(
-                ray.at(t),
-                self._get_triangle_normal(u, v).normalize(),
-                self.material.clone(),
-                t,
-                u,
-                v,
-                false
-            );
-            rec.set_face_normal(ray, &rec.normal.clone());
-            Some(rec)
-        } else { None }
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        let (t, u, v, tex_uv) = self._intersect(ray, t_min, t_max)?;
+        let mut rec: HitRecord = HitRecord::new(
+            ray.at(t),
+            self._get_triangle_normal(u, v).normalize(),
+            self.material.as_ref(),
+            t,
+            tex_uv.x,
+            tex_uv.y,
+            false
+        );
+        rec.set_face_normal(ray, &rec.normal.clone());
+        (rec.dpdu, rec.dpdv) = self._get_triangle_derivatives(self.vertices[1] - self.vertices[0], self.vertices[2] - self.vertices[0]);
+        if let Some(cavity) = &self.cavity { rec.cavity = cavity[0] * (1.0 - u - v) + cavity[1] * u + cavity[2] * v; }
+        if let Some(heights) = &self.heights { rec.edge_distance = (heights[0] * (1.0 - u - v)).min(heights[1] * u).min(heights[2] * v); }
+        Some(rec)
     }
+    fn occluded(&self, ray: &Ray, t_min: f32, t_max: f32) -> bool { self._intersect(ray, t_min, t_max).is_some() }
     fn is_light(&self) -> bool { self.material.is_light() }
-    fn pdf_value(&self, origin: &Point3, _v: &Vec3A) -> f32 {
-        let area: f32 = (self.vertices[1] - self.vertices[0]).cross(self.vertices[2] - self.vertices[0]).length() / 2.0;
-        let distance_squared: f32 = (self.vertices[0] - *origin).length_squared();
-        let cosine: f32 = (self.vertices[0] - *origin).dot(self._get_triangle_normal(0.0, 0.0).normalize()) / (self.vertices[0] - *origin).length();
-        distance_squared / (cosine * area)
+    fn world_bounds(&self) -> Option<AABB> { Some(self.aabb()) }
+    fn light_estimate(&self) -> Option<LightEstimate> {
+        if !self.material.is_light() { return None; }
+        let center: Point3 = (self.vertices[0] + self.vertices[1] + self.vertices[2]) / 3.0;
+        let radius: f32 = self.vertices.iter().map(|vertex| (*vertex - center).length()).fold(0.0, f32::max);
+        Some(LightEstimate::new(center, radius, self.area(), self.material.emitted(0.5, 0.5, &center)))
+    }
+    // Solid angle density of the directions sampled by random, zero for the directions missing the triangle
+    fn pdf_value(&self, origin: &Point3, v: &Vec3A) -> f32 {
+        let t: f32 = match self._intersect(&Ray::new(*origin, *v), NEAR_ZERO, utility::INFINITY) {
+            Some((t, _, _, _)) => t,
+            None => return 0.0,
+        };
+        let normal: Vec3A = (self.vertices[1] - self.vertices[0]).cross(self.vertices[2] - self.vertices[0]).normalize();
+        // Directions reaching the back of a one sided light are never lit by it
+        if self.material.is_one_sided() && v.dot(normal) >= 0.0 { return 0.0; }
+        let distance_squared: f32 = t * t * v.length_squared();
+        let cosine: f32 = (v.dot(normal) / v.length()).abs();
+        distance_squared / (cosine * self.area())
     }
     fn random(&self, origin: &Point3) -> Vec3A {
-        let u: f32 = utility::random_f32();
-        let v: f32 = utility::random_f32();
+        let (u, v) = sampling::uniform_triangle(sampling::random_uniforms()).into();
         let w: f32 = 1.0 - u - v;
         (self.vertices[0] * w + self.vertices[1] * u + self.vertices[2] * v) - *origin
     }
+    fn edges(&self) -> Vec<Edge> {
+        let normal: Vec3A = (self.vertices[1] - self.vertices[0]).cross(self.vertices[2] - self.vertices[0]).normalize_or_zero();
+        diffraction::polygon_edges(&*self.vertices, normal)
+    }
+    fn tessellation(&self) -> Vec<FlatTriangle> {
+        let uvs: [Vec2; 3] = self.uvs.as_deref().copied().unwrap_or([Vec2::ZERO, Vec2::X, Vec2::Y]);
+        vec![FlatTriangle { vertices: *self.vertices, normals: *self.normals, uvs }]
+    }
 }
 
 #[cfg(test)]
@@ -163,4 +237,15 @@ mod tests {
         assert!(triangle.hit(&ray, 0.0, 100.0).is_some());
         Ok(())
     }
+    #[test]
+    fn test_triangle_uvs() {
+        let vertices: [Point3; 3] = [Point3::new(0.0, 0.0, 0.0), Point3::new(1.0, 0.0, 0.0), Point3::new(0.0, 1.0, 0.0)];
+        let material: Box<dyn Material> = Box::new(Lambertian::new(Color::new(0.0, 0.0, 0.0)));
+        let uvs: Box<[Vec2; 3]> = Box::new([Vec2::new(0.0, 0.0), Vec2::new(2.0, 0.0), Vec2::new(0.0, 2.0)]);
+        let triangle: Triangle = Triangle::new_with_uvs(Box::new(vertices), Box::new([Vec3A::ZERO; 3]), uvs, material, 0);
+        let ray: Ray = Ray::new(Point3::new(0.25, 0.25, -1.0), Vec3A::new(0.0, 0.0, 1.0));
+        let rec: HitRecord = triangle.hit(&ray, 0.0, 100.0).unwrap();
+        assert!((rec.u - 0.5).abs() < EPSILON);
+        assert!((rec.v - 0.5).abs() < EPSILON);
+    }
 }
@@ -2,19 +2,32 @@
 // Date: 24/01/2023
 // Description: This file implements various utility constants and functions used throughout the project
 
-use std::env::args;
 use std::sync::Arc;
 use lazy_static::lazy_static;
 use fastrand;
 
 use glam::Vec3A;
 
-use crate::hittable_list::Hittable;
+use crate::adaptive::AdaptiveSampling;
+use crate::aov::Aov;
+use crate::benchmark;
+use crate::matpreview;
+use crate::cli::ARGS;
+use crate::environment::{GroundProjection, GroundProjectedEnvironment};
+use crate::hittable_list::{self, Hittable, HittableList};
+use crate::lidar::Lidar;
 use crate::material::DiffuseLight;
+use crate::link_budget::LinkBudget;
 use crate::parser;
 use crate::point3::Point3;
+use crate::priority::RenderPriority;
+use crate::compositing::{DepthOutput, MotionVectors, PositionOutput};
+use crate::exposure::ExposureAnalysis;
+use crate::sampling;
 use crate::sphere::Sphere;
-use crate::texture::{self, GradientColor, ImageTexture};
+use crate::temporal::TemporalFilter;
+use crate::transient::Transient;
+use crate::texture::{self, Texture, CubeMapTexture, GradientColor};
 use crate::sampling_filters::{Filter, TentFilter, UniformFilter, LanczosFilter};
 
 
@@ -26,12 +39,66 @@ pub struct Constants {
     pub samples_per_pixel: u32,
     pub max_depth: u32,
     pub min_depth: u32,
+    // Separate bounce limits for mirror/glass and diffuse bounces, max_depth still bounds the whole path
+    pub max_specular_depth: u32,
+    pub max_diffuse_depth: u32,
+    // Samples the lights as well as the materials at the diffuse bounces, off for the power grid which only follows the material
+    pub light_sampling: bool,
     pub environment_map: Option<String>,
+    // A cube map environment instead, a single cross image or its six faces
+    pub environment_cube_map: Option<Vec<String>>,
     pub environment_distance: Option<f32>,
     pub environment_intensity: Option<f32>,
+    // Optional environment map seen by camera rays only, while environment_map keeps lighting the scene
+    pub background_map: Option<String>,
+    pub background_intensity: Option<f32>,
+    pub ground_projection: Option<GroundProjection>,
     pub filter: Option<String>,
     pub sources_lambda: f32,
     pub power_render_center: Vec3A,
+    // Calibrates the power grid to dBm, instead of dB relative to its maximum
+    pub link_budget: Option<LinkBudget>,
+    // Megabytes the meshes, BVHs and textures can take, and whether the textures over it get streamed as tiles
+    pub memory_budget: Option<usize>,
+    pub stream_textures: bool,
+    // Worker threads of the render, all of the cores by default, and whether each one is pinned to its own core
+    pub threads: Option<usize>,
+    pub pin_threads: bool,
+    // Wall-clock and camera sample budgets, the render stopping after the pass that reaches them
+    pub max_render_seconds: Option<f32>,
+    pub max_total_samples: Option<u64>,
+    // Saves the image every so many seconds while it converges
+    pub progressive_interval: Option<f32>,
+    // Seed of the sampler, offset by the frame of an animation as the pattern says
+    pub seed: u64,
+    pub frame: u64,
+    pub seed_pattern: SeedPattern,
+    // Blends the frames of an animation with the previous one, reprojected through the camera motion
+    pub temporal: Option<TemporalFilter>,
+    // Bins the radiance by the length of its path instead of rendering the steady state only
+    pub transient: Option<Transient>,
+    // Scans the scene from the camera and saves the point cloud instead of rendering
+    pub lidar: Option<Lidar>,
+    // Statistics of the paths saved as heatmaps next to the image
+    pub aovs: Vec<Aov>,
+    // Stops sampling the pixels whose estimate converged before samples_per_pixel
+    pub adaptive_sampling: Option<AdaptiveSampling>,
+    // Orders the tiles of the preview and splits its samples by a priority mask or the noise left
+    pub priority: Option<RenderPriority>,
+    // Accumulates the pass-based renders as unpadded running means, for the resolutions whose padded buffers don't fit in memory
+    pub compact_accumulation: bool,
+    // Lights the diffuse bounces with the spherical harmonics of the environment instead of tracing it, for noiseless previews
+    pub environment_sh: bool,
+    // Gathers the lights seen through the dielectrics at the diffuse bounces, solving for the refracted paths towards them
+    pub manifold_sampling: bool,
+    // Saves a depth pass for compositing next to the image, and the deep pixels if asked for
+    pub depth_output: Option<DepthOutput>,
+    // Saves the world positions and normals seen by the pixels for compositing next to the image
+    pub position_output: Option<PositionOutput>,
+    // Saves the motion of the pixels along the camera path of the animation, for the motion blur in post
+    pub motion_vectors: Option<MotionVectors>,
+    // Saves the luminance histogram and the false color image of the exposure next to the image
+    pub exposure_analysis: Option<ExposureAnalysis>,
 }
 
 impl Default for Constants {
@@ -43,18 +110,74 @@ impl Default for Constants {
             samples_per_pixel: 64,
             max_depth: 5000,
             min_depth: 5,
+            max_specular_depth: 5000,
+            max_diffuse_depth: 5000,
+            light_sampling: false,
             environment_map: None,
+            environment_cube_map: None,
             environment_distance: None,
             environment_intensity: None,
+            background_map: None,
+            background_intensity: None,
+            ground_projection: None,
             filter: None,
             sources_lambda: 299792458.0 / 2.45e9,
             power_render_center: Vec3A::new(0.0, 0.0, 0.0),
+            link_budget: None,
+            memory_budget: None,
+            stream_textures: false,
+            threads: None,
+            pin_threads: false,
+            max_render_seconds: None,
+            max_total_samples: None,
+            progressive_interval: None,
+            seed: 0,
+            frame: 0,
+            seed_pattern: SeedPattern::PerFrame,
+            temporal: None,
+            transient: None,
+            lidar: None,
+            aovs: Vec::new(),
+            adaptive_sampling: None,
+            priority: None,
+            compact_accumulation: false,
+            environment_sh: false,
+            manifold_sampling: false,
+            depth_output: None,
+            position_output: None,
+            motion_vectors: None,
+            exposure_analysis: None,
         }
     }
 }
 
-// Image constants
-lazy_static! { pub static ref CONSTS: Constants = parser::parse_yaml_constants(&args().nth(1).unwrap_or("".to_string())); }
+impl Constants {
+    // The constants of a preview at 1/scale of the resolution and of the samples, framed like the final render since the aspect ratio is kept
+    // The temporal filter is left out, so that the preview neither reads nor overwrites the history of the final frames
+    pub fn downscaled(mut self, scale: u32) -> Constants {
+        let scale: u32 = scale.max(1);
+        (self.width, self.height) = ((self.width / scale).max(1), (self.height / scale).max(1));
+        self.samples_per_pixel = (self.samples_per_pixel / scale).max(1);
+        if let Some(adaptive) = &mut self.adaptive_sampling { adaptive.min_samples = adaptive.min_samples.min(self.samples_per_pixel); }
+        self.temporal = None;
+        self
+    }
+}
+
+// Image constants, the defaults under the tests, whose command line is the one of the test harness
+lazy_static! { pub static ref CONSTS: Constants = if cfg!(test) { Constants::default() } else { load_constants() }; }
+
+// The constants of the mode the renderer runs in, else the ones of the config file
+fn load_constants() -> Constants {
+    if ARGS.benchmark { return benchmark::constants(); }
+    if ARGS.regression.is_some() { return benchmark::regression_constants(); }
+    if ARGS.matpreview.is_some() { return matpreview::constants(); }
+    let consts: Constants = parser::parse_yaml_constants(&ARGS.config_file);
+    match ARGS.preview_scale {
+        Some(scale) => consts.downscaled(scale),
+        None => consts,
+    }
+}
 
 // Numerical Constants
 pub const INFINITY: f32 = std::f32::INFINITY;
@@ -65,19 +188,61 @@ pub const NEAR_ZERO: f32 = 0.001;
 
 // Background SkyBox
 pub const BLUE_SKY: Vec3A = Vec3A::new(0.5, 0.7, 1.0);
+// The environment sphere is at least this far, and fitted to this many times the distance of the farthest object from the origin when that's bigger
+pub const DEFAULT_ENVIRONMENT_DISTANCE: f32 = 1000.0;
+const ENVIRONMENT_MARGIN: f32 = 10.0;
+// Past this the intersections with the environment sphere lose too much precision, so it's a hard limit even for the environmentDistance of the constants
+pub const MAX_ENVIRONMENT_DISTANCE: f32 = 1.0e6;
 
 // Utility functions
-pub fn load_environment() -> Arc<dyn Hittable + Send + Sync> {
-    let env_dist: f32 = if CONSTS.environment_distance.is_some() { CONSTS.environment_distance.unwrap() } else { 1000.0 };
-    let env_intensity: f32 = if CONSTS.environment_intensity.is_some() { CONSTS.environment_intensity.unwrap() } else { 1.0 };
+// The radius of the environment sphere, the one asked for or else fitted to the bounds of the world, within MAX_ENVIRONMENT_DISTANCE
+pub fn environment_distance(world: &HittableList, asked: Option<f32>) -> f32 {
+    match asked {
+        Some(env_dist) if env_dist > MAX_ENVIRONMENT_DISTANCE => {
+            println!("Warning: the environmentDistance {} is over the limit, using {} instead", env_dist, MAX_ENVIRONMENT_DISTANCE);
+            tracing::warn!(environment_distance = env_dist, limit = MAX_ENVIRONMENT_DISTANCE, "environment distance over the limit");
+            MAX_ENVIRONMENT_DISTANCE
+        },
+        Some(env_dist) => env_dist,
+        None => {
+            let farthest: f32 = world.world_bounds().map_or(0.0, |bounds| hittable_list::bounds_corners(&bounds).iter().map(|corner| corner.length()).fold(0.0, f32::max));
+            (farthest * ENVIRONMENT_MARGIN).clamp(DEFAULT_ENVIRONMENT_DISTANCE, MAX_ENVIRONMENT_DISTANCE)
+        },
+    }
+}
+// The indices of the objects of the world reaching out of the environment sphere, which then hides them from the inside and lights them wrong
+// The unbounded ones, like the planes, always cross it and are left out
+pub fn outside_environment(world: &HittableList, env_dist: f32) -> Vec<usize> {
+    world.iter().enumerate()
+        .filter(|(_, object)| object.world_bounds().is_some_and(|bounds| hittable_list::bounds_corners(&bounds).iter().any(|corner| corner.length() > env_dist)))
+        .map(|(idx, _)| idx)
+        .collect()
+}
+// Prints a warning about the objects out of the environment sphere, returning how many there are
+pub fn check_environment(world: &HittableList, env_dist: f32) -> usize {
+    let outside: Vec<usize> = outside_environment(world, env_dist);
+    if !outside.is_empty() {
+        println!(
+            "Warning: {} object(s) reach out of the environment sphere of radius {} (the first is object {}), raise environmentDistance up to {} or bring the scene closer to the origin",
+            outside.len(), env_dist, outside[0], MAX_ENVIRONMENT_DISTANCE
+        );
+        tracing::warn!(objects = outside.len(), first = outside[0], environment_distance = env_dist, "objects out of the environment sphere");
+    }
+    outside.len()
+}
+// The environment sphere of the constants and its radius, which the background shares
+pub fn load_environment(world: &HittableList, consts: &Constants) -> (Arc<dyn Hittable + Send + Sync>, f32) {
+    let env_dist: f32 = environment_distance(world, consts.environment_distance);
+    let env_intensity: f32 = consts.environment_intensity.unwrap_or(1.0);
     println!("Environment distance: {}", env_dist);
-    println!("Environment map: {:?}", CONSTS.environment_map);
-    if CONSTS.environment_map.is_some() {
-        // environment map is just a textured sphere with a diffuse light material
-        let env_tex: ImageTexture = texture::EnvironmentMapTexture::new(CONSTS.environment_map.as_ref().unwrap());
-        let env_mat: DiffuseLight = DiffuseLight::new_texture(Box::new(env_tex), env_intensity);
-        let env_sphere: Sphere = Sphere::new(Point3::new(0.0, 0.0, 0.0), env_dist, Box::new(env_mat), 0);
-        Arc::new(env_sphere)
+    tracing::info!(environment_distance = env_dist, environment_map = consts.environment_map.as_deref(), "environment loaded");
+    check_environment(world, env_dist);
+    println!("Environment map: {:?}", consts.environment_map);
+    let environment_map: Arc<dyn Hittable + Send + Sync> = if let Some(faces) = &consts.environment_cube_map {
+        println!("Environment cube map: {:?}", faces);
+        _environment_sphere(Box::new(CubeMapTexture::new(faces)), env_intensity, env_dist, consts.ground_projection)
+    } else if let Some(environment_map) = &consts.environment_map {
+        _environment_sphere(Box::new(texture::EnvironmentMapTexture::new(environment_map).with_address(texture::AddressMode::Clamp)), env_intensity, env_dist, consts.ground_projection)
     } else {
         let env_tex: GradientColor = texture::GradientColor::new(
             Box::new(texture::SolidColor::new(BLUE_SKY)),
@@ -85,19 +250,83 @@ pub fn load_environment() -> Arc<dyn Hittable + Send + Sync> {
         );
         // Box::new(Sphere::new(Vec3A::new(0.0, 0.0, 0.0), env_dist, Box::new(DiffuseLight::new_texture(Box::new(env_tex), 1.0)), 0))
         Arc::new(Sphere::new(Vec3A::new(0.0, 0.0, 0.0), env_dist, Box::new(DiffuseLight::new_texture(Box::new(env_tex), env_intensity)), 0))
+    };
+    (environment_map, env_dist)
+}
+// Whether the environment gets sampled as a light, which it isn't when it's off or approximated by its spherical harmonics
+pub fn environment_is_light(consts: &Constants) -> bool { consts.environment_intensity.unwrap_or(1.0) > 0.0 && !consts.environment_sh }
+// The visible background, if it differs from the lighting environment, seen by the camera rays in its place
+pub fn load_background(env_dist: f32, consts: &Constants) -> Option<Arc<dyn Hittable + Send + Sync>> {
+    let intensity: f32 = consts.background_intensity.unwrap_or(consts.environment_intensity.unwrap_or(1.0));
+    consts.background_map.as_ref().map(|background_map| {
+        println!("Background map: {}", background_map);
+        _environment_sphere(Box::new(texture::EnvironmentMapTexture::new(background_map).with_address(texture::AddressMode::Clamp)), intensity, env_dist, consts.ground_projection)
+    })
+}
+fn _environment_sphere(env_tex: Box<dyn Texture>, intensity: f32, env_dist: f32, ground_projection: Option<GroundProjection>) -> Arc<dyn Hittable + Send + Sync> {
+    // environment map is just a textured sphere with a diffuse light material
+    let env_mat: DiffuseLight = DiffuseLight::new_texture(env_tex, intensity);
+    let env_sphere: Sphere = Sphere::new(Point3::new(0.0, 0.0, 0.0), env_dist, Box::new(env_mat), 0);
+    match ground_projection {
+        Some(projection) => Arc::new(GroundProjectedEnvironment::new(Arc::new(env_sphere), projection)),
+        None => Arc::new(env_sphere),
+    }
+}
+pub fn load_filter() -> Box<dyn Filter + Send + Sync> { filter_from_name(CONSTS.filter.as_deref()) }
+// The filter of a name in the constants, the uniform one if unknown or missing
+pub fn filter_from_name(name: Option<&str>) -> Box<dyn Filter + Send + Sync> {
+    match name {
+        Some("TentFilter") => Box::new(TentFilter::new()),
+        Some("LanczosFilter") => Box::new(LanczosFilter::new()),
+        _ => Box::new(UniformFilter::new())
     }
 }
-pub fn load_filter() -> Box<dyn Filter + Send + Sync> {
-    if CONSTS.filter.is_some() {
-        match CONSTS.filter.as_ref().unwrap().as_str() {
-            "UniformFilter" => Box::new(UniformFilter::new()),
-            "TentFilter" => Box::new(TentFilter::new()),
-            "LanczosFilter" => Box::new(LanczosFilter::new()),
-            _ => Box::new(UniformFilter::new())
+
+/****************** SeedPattern ******************/
+// How the seed of the sampler changes across the frames of an animation
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SeedPattern {
+    // A different seed every frame, so that the residual noise doesn't stick to the image like a screen door
+    PerFrame,
+    // The same seed every frame, the noise following the image (handy to compare frames or settings)
+    Fixed,
+    // A new seed every render, even of the same frame
+    Random,
+}
+
+impl SeedPattern {
+    pub fn from_name(name: &str) -> Result<SeedPattern, String> {
+        match name {
+            "perFrame" => Ok(SeedPattern::PerFrame),
+            "fixed" => Ok(SeedPattern::Fixed),
+            "random" => Ok(SeedPattern::Random),
+            _ => Err(format!("Unknown seed pattern: {}, expected perFrame, fixed or random", name)),
         }
-    } else { Box::new(UniformFilter::new()) }
+    }
+    pub fn name(&self) -> &'static str {
+        match self {
+            SeedPattern::PerFrame => "perFrame",
+            SeedPattern::Fixed => "fixed",
+            SeedPattern::Random => "random",
+        }
+    }
+    pub fn frame_seed(&self, seed: u64, frame: u64) -> u64 {
+        match self {
+            SeedPattern::PerFrame => seed.wrapping_add(frame.wrapping_mul(0xbf58_476d_1ce4_e5b9)),
+            SeedPattern::Fixed => seed,
+            SeedPattern::Random => fastrand::u64(..),
+        }
+    }
 }
 
+// Seed of this render, the rows and passes derive their streams from it
+lazy_static! { pub static ref RENDER_SEED: u64 = CONSTS.seed_pattern.frame_seed(CONSTS.seed, CONSTS.frame); }
+
+// Every thread has its own generator, seeding it with a stream id gives independent and reproducible sequences
+pub fn seed_stream(seed: u64, stream: u64) { fastrand::seed(seed.wrapping_mul(0x9e37_79b9_7f4a_7c15) ^ stream); }
+// The state of the random numbers of the thread, for drawing the same ones again
+pub fn random_state() -> u64 { fastrand::get_seed() }
+pub fn set_random_state(state: u64) { fastrand::seed(state); }
 pub fn random_f32() -> f32 { fastrand::f32() }
 pub fn random_f32_range(min: f32, max: f32) -> f32 { fastrand::f32() * (max - min) + min }
 pub fn random_usize_range(min: usize, max: usize) -> usize { fastrand::usize(min..max) }
@@ -111,12 +340,7 @@ pub fn random_in_unit_disk() -> Vec3A {
 }
 
 #[allow(dead_code)]
-pub fn random_unit_vector() -> Vec3A {
-    let a: f32 = random_f32_range(0.0, 2.0 * PI);
-    let z: f32 = random_f32_range(-1.0, 1.0);
-    let r: f32 = (1.0 - z * z).sqrt();
-    Vec3A::new(r * a.cos(), r * a.sin(), z)
-}
+pub fn random_unit_vector() -> Vec3A { sampling::uniform_sphere(sampling::random_uniforms()) }
 
 pub fn random_in_unit_sphere() -> Vec3A {
     let mut p: Vec3A;
@@ -135,6 +359,14 @@ pub fn random_in_unit_sphere_uniform_distribution() -> Vec3A {
     Vec3A::new(r * theta.sin() * phi.cos(), r * theta.sin() * phi.sin(), r * theta.cos())
 }
 
+// Roots of a*t^2 + 2*half_b*t + c = 0 in increasing order (a > 0), None if there are no real ones
+pub fn solve_quadratic(a: f32, half_b: f32, c: f32) -> Option<(f32, f32)> {
+    let discriminant: f32 = half_b * half_b - a * c;
+    if a <= 0.0 || discriminant < 0.0 { return None; }
+    let sqrtd: f32 = discriminant.sqrt();
+    Some(((-half_b - sqrtd) / a, (-half_b + sqrtd) / a))
+}
+
 /*
 pub fn random_to_sphere(radius: f32, distance_squared: f32) -> Vec3A {
     let r1: f32 = random_f32();
@@ -147,15 +379,7 @@ pub fn random_to_sphere(radius: f32, distance_squared: f32) -> Vec3A {
 }
 */
 
-pub fn random_cosine_direction() -> Vec3A {
-    let r1: f32 = random_f32();
-    let r2: f32 = random_f32();
-    let z: f32 = (1.0 - r2).sqrt();
-    let phi: f32 = 2.0 * PI * r1;
-    let x: f32 = phi.cos() * r2.sqrt();
-    let y: f32 = phi.sin() * r2.sqrt();
-    Vec3A::new(x, y, z)
-}
+pub fn random_cosine_direction() -> Vec3A { sampling::cosine_hemisphere(sampling::random_uniforms()) }
 
 #[cfg(test)]
 mod tests {
@@ -191,4 +415,39 @@ mod tests {
         assert!(r.dot(r) < 1.0);
         Ok(())
     }
+    #[test]
+    fn test_seed_pattern() {
+        let per_frame: SeedPattern = SeedPattern::from_name("perFrame").unwrap();
+        assert_eq!(per_frame.frame_seed(7, 3), per_frame.frame_seed(7, 3));
+        assert_ne!(per_frame.frame_seed(7, 3), per_frame.frame_seed(7, 4));
+        assert_eq!(SeedPattern::from_name("fixed").unwrap().frame_seed(7, 4), 7);
+    }
+    #[test]
+    fn test_environment_distance() {
+        use crate::color::Color;
+        use crate::material::Lambertian;
+        use crate::plane::Plane;
+        // A small scene keeps the default sphere, a big one gets a fitted one, and the plane is never out of it
+        let sphere = |x: f32| -> Arc<dyn Hittable + Send + Sync> { Arc::new(Sphere::new(Point3::new(x, 0.0, 0.0), 1.0, Box::new(Lambertian::new(Color::ONE)), 0)) };
+        let mut world: HittableList = vec![sphere(10.0), Arc::new(Plane::new(Point3::ZERO, Vec3A::Y, Box::new(Lambertian::new(Color::ONE))))];
+        assert_eq!(environment_distance(&world, None), DEFAULT_ENVIRONMENT_DISTANCE);
+        world.push(sphere(499.0));
+        assert!((environment_distance(&world, None) - 5000.0).abs() < 0.1);
+        assert_eq!(outside_environment(&world, environment_distance(&world, None)), Vec::<usize>::new());
+        // The distance asked for is kept, and the objects beyond it are found, up to the hard limit
+        assert_eq!(outside_environment(&world, environment_distance(&world, Some(200.0))), vec![2]);
+        assert_eq!(environment_distance(&world, Some(1.0e9)), MAX_ENVIRONMENT_DISTANCE);
+        world.push(sphere(2.0e6));
+        assert_eq!(environment_distance(&world, None), MAX_ENVIRONMENT_DISTANCE);
+        assert_eq!(outside_environment(&world, MAX_ENVIRONMENT_DISTANCE), vec![3]);
+    }
+    #[test]
+    fn test_downscaled() {
+        let consts: Constants = Constants { adaptive_sampling: Some(AdaptiveSampling { threshold: 0.01, min_samples: 16 }), ..Constants::default() }.downscaled(4);
+        assert_eq!((consts.width, consts.height, consts.samples_per_pixel, consts.aspect_ratio), (480, 270, 16, 1920.0 / 1080.0));
+        assert_eq!(consts.adaptive_sampling.map(|adaptive| adaptive.min_samples), Some(16));
+        // Never below a pixel and a sample
+        let consts: Constants = Constants { width: 100, height: 3, samples_per_pixel: 2, ..Constants::default() }.downscaled(8);
+        assert_eq!((consts.width, consts.height, consts.samples_per_pixel), (12, 1, 1));
+    }
 }
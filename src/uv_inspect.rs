@@ -0,0 +1,167 @@
+// Author: Giulian Biolo, github.com/giulianbiolo
+// Date: 16/10/2026
+// Description: This file implements the UV inspection mode, drawing the UV layouts of the meshes and a false color view of the texel density of the scene
+
+use std::path::Path;
+
+use glam::{Vec2, Vec3A};
+use image::{Rgb, RgbImage};
+
+use crate::aov;
+use crate::camera::Camera;
+use crate::compositing;
+use crate::hittable_list::{self, Hittable, HittableList};
+use crate::mesh::Mesh;
+use crate::parallel::*;
+use crate::ray::Ray;
+use crate::triangle::Triangle;
+use crate::utility;
+
+
+// Size in pixels of the square images of the UV layouts
+const LAYOUT_SIZE: u32 = 1024;
+// The false colors span this many doublings of the density below and above the median, blue to red
+const OCTAVES: f32 = 2.0;
+// The UVs folded into a point or a line have no density, and stand out in magenta
+const COLLAPSED: Rgb<u8> = Rgb([255, 0, 255]);
+const BACKGROUND: Rgb<u8> = Rgb([32, 32, 32]);
+const EDGE: Rgb<u8> = Rgb([0, 0, 0]);
+
+// The UV units per world unit of a triangle, the square root of its area in UV space over its area in the world, None without UVs or when they collapse
+pub fn triangle_density(triangle: &Triangle) -> Option<f32> {
+    let uvs: &[Vec2; 3] = triangle.uvs()?;
+    let uv_area: f32 = (uvs[1] - uvs[0]).perp_dot(uvs[2] - uvs[0]).abs() / 2.0;
+    if uv_area < 1e-12 || triangle.area() < 1e-12 { return None; }
+    Some((uv_area / triangle.area()).sqrt())
+}
+
+// The UV units per world unit at a hit, from the derivatives of the position along the UVs, None where they collapse
+fn _hit_density(dpdu: Vec3A, dpdv: Vec3A) -> Option<f32> {
+    let area: f32 = dpdu.cross(dpdv).length();
+    if area < 1e-12 { None } else { Some(1.0 / area.sqrt()) }
+}
+
+fn _median(values: &[f32]) -> f32 {
+    let mut sorted: Vec<f32> = values.to_vec();
+    sorted.sort_by(|a, b| a.total_cmp(b));
+    if sorted.is_empty() { 1.0 } else { sorted[sorted.len() / 2] }
+}
+
+// The density as a false color, green at the reference and blue to red from OCTAVES doublings below it to as many above
+pub fn false_color(density: Option<f32>, reference: f32) -> Rgb<u8> {
+    match density {
+        Some(density) => aov::heatmap(((density / reference).log2() / OCTAVES + 1.0) / 2.0),
+        None => COLLAPSED,
+    }
+}
+
+// The triangles of the mesh drawn in the unit square of the UVs (v up), filled with the false color of their density against the median of the mesh and outlined
+// The tiled UVs are wrapped into the square, triangle by triangle
+pub fn layout(mesh: &Mesh, size: u32) -> RgbImage {
+    let mut image: RgbImage = RgbImage::from_pixel(size, size, BACKGROUND);
+    let triangles: Vec<(&[Vec2; 3], Option<f32>)> = mesh.triangles().iter().filter_map(|triangle| triangle.uvs().map(|uvs| (uvs, triangle_density(triangle)))).collect();
+    let reference: f32 = _median(&triangles.iter().filter_map(|(_, density)| *density).collect::<Vec<f32>>());
+    let to_pixels = |uv: Vec2, offset: Vec2| Vec2::new(uv.x - offset.x, 1.0 - (uv.y - offset.y)) * size as f32;
+    for (uvs, density) in &triangles {
+        let offset: Vec2 = ((uvs[0] + uvs[1] + uvs[2]) / 3.0).floor();
+        let corners: [Vec2; 3] = uvs.map(|uv| to_pixels(uv, offset));
+        _fill(&mut image, &corners, false_color(*density, reference));
+        for i in 0..3 { _line(&mut image, corners[i], corners[(i + 1) % 3], EDGE); }
+    }
+    image
+}
+
+// The pixels whose centers are inside of the triangle, either way around
+fn _fill(image: &mut RgbImage, corners: &[Vec2; 3], color: Rgb<u8>) {
+    let (min, max) = (corners[0].min(corners[1]).min(corners[2]).floor().max(Vec2::ZERO), corners[0].max(corners[1]).max(corners[2]).ceil());
+    let area: f32 = (corners[1] - corners[0]).perp_dot(corners[2] - corners[0]);
+    if area == 0.0 { return; }
+    for y in min.y as u32..(max.y as u32).min(image.height()) {
+        for x in min.x as u32..(max.x as u32).min(image.width()) {
+            let p: Vec2 = Vec2::new(x as f32 + 0.5, y as f32 + 0.5);
+            let inside: bool = (0..3).all(|i| (corners[(i + 1) % 3] - corners[i]).perp_dot(p - corners[i]) * area.signum() >= 0.0);
+            if inside { image.put_pixel(x, y, color); }
+        }
+    }
+}
+
+fn _line(image: &mut RgbImage, start: Vec2, end: Vec2, color: Rgb<u8>) {
+    let steps: usize = (end - start).abs().max_element().ceil() as usize + 1;
+    for i in 0..=steps {
+        let p: Vec2 = start.lerp(end, i as f32 / steps as f32);
+        if p.x >= 0.0 && p.y >= 0.0 && (p.x as u32) < image.width() && (p.y as u32) < image.height() { image.put_pixel(p.x as u32, p.y as u32, color); }
+    }
+}
+
+// The density seen through the center of each pixel, row by row, the outer None where the camera ray misses everything
+pub fn density_pass(world: &HittableList, cam: &Camera, width: u32, height: u32) -> Vec<Option<Option<f32>>> {
+    (0..height).into_par_iter().map(|y| {
+        (0..width).map(|x| {
+            let direction: Vec3A = compositing::pixel_direction(cam, width, height, x as f32 + 0.5, y as f32 + 0.5);
+            world.hit(&Ray::new(cam.origin, direction), utility::NEAR_ZERO, utility::INFINITY).map(|rec| _hit_density(rec.dpdu, rec.dpdv))
+        }).collect::<Vec<Option<Option<f32>>>>()
+    }).reduce(Vec::new, |mut a, b| { a.extend(b); a })
+}
+
+// The 3D view of the density, against the median of what the camera sees, the background black
+pub fn density_view(world: &HittableList, cam: &Camera, width: u32, height: u32) -> RgbImage {
+    let densities: Vec<Option<Option<f32>>> = density_pass(world, cam, width, height);
+    let reference: f32 = _median(&densities.iter().filter_map(|density| density.flatten()).collect::<Vec<f32>>());
+    RgbImage::from_fn(width, height, |x, y| densities[(y * width + x) as usize].map_or(Rgb([0, 0, 0]), |density| false_color(density, reference)))
+}
+
+// Saves the density view to the output and the layout of every mesh with UVs next to it, as <output>_uv_<name or index>.png
+pub fn run(world: &HittableList, cam: &Camera, names: &[Option<String>], output: &str) {
+    let (width, height) = (utility::CONSTS.width, utility::CONSTS.height);
+    density_view(world, cam, width, height).save(output).unwrap_or_else(|err| panic!("Failed to save the texel density view to {}: {}", output, err));
+    println!("Saved the texel density view to {}, magenta where the UVs collapse", output);
+    let stem: String = Path::new(output).with_extension("").to_string_lossy().to_string();
+    for (idx, object) in world.iter().enumerate() {
+        let mesh: &Mesh = match hittable_list::downcast_ref::<Mesh>(&**object) {
+            Some(mesh) if mesh.triangles().iter().any(|triangle| triangle.uvs().is_some()) => mesh,
+            _ => continue,
+        };
+        let name: String = names.get(idx).cloned().flatten().unwrap_or_else(|| idx.to_string());
+        let filename: String = format!("{}_uv_{}.png", stem, name);
+        layout(mesh, LAYOUT_SIZE).save(&filename).unwrap_or_else(|err| panic!("Failed to save the UV layout to {}: {}", filename, err));
+        println!("Saved the UV layout of {} to {}", name, filename);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::acceleration::BVHParams;
+    use crate::color::Color;
+    use crate::material::Lambertian;
+    use crate::point3::Point3;
+
+    // A square of the given side in the world mapped to a square of the given side in the UVs
+    fn _quad(side: f32, uv_side: f32, x: f32) -> Vec<Triangle> {
+        let corners: [Point3; 4] = [Point3::new(x, 0.0, 0.0), Point3::new(x + side, 0.0, 0.0), Point3::new(x + side, side, 0.0), Point3::new(x, side, 0.0)];
+        let uvs: [Vec2; 4] = [Vec2::ZERO, Vec2::new(uv_side, 0.0), Vec2::splat(uv_side), Vec2::new(0.0, uv_side)];
+        [[0, 1, 2], [0, 2, 3]].iter().map(|face: &[usize; 3]| Triangle::new_with_uvs(
+            Box::new(face.map(|i| corners[i])), Box::new([Vec3A::Z; 3]), Box::new(face.map(|i| uvs[i])), Box::new(Lambertian::new(Color::ONE)), 0
+        )).collect()
+    }
+
+    #[test]
+    fn test_density() {
+        // Half of the UV square on a unit square is half a UV unit per world unit, and the layout is filled where the triangles are
+        let mut triangles: Vec<Triangle> = _quad(1.0, 0.5, 0.0);
+        assert!((triangle_density(&triangles[0]).unwrap() - 0.5).abs() < 1e-5);
+        triangles.extend(_quad(1.0, 0.0, 2.0));
+        assert_eq!(triangle_density(&triangles[2]), None);
+        let mesh: Mesh = Mesh::new_from_triangles(triangles, &BVHParams::default());
+        let image: RgbImage = layout(&mesh, 64);
+        assert_eq!(*image.get_pixel(10, 50), false_color(Some(0.5), 0.5));
+        assert_eq!(*image.get_pixel(50, 10), BACKGROUND);
+        // Seen from the front, the hits have the density of their triangles
+        let cam: Camera = Camera::new(&Vec3A::new(0.5, 0.5, 3.0), &Vec3A::new(0.5, 0.5, 0.0), &Vec3A::Y, 20.0, 1.0, 0.0, 3.0);
+        let world: HittableList = vec![Arc::new(mesh)];
+        let densities: Vec<Option<Option<f32>>> = density_pass(&world, &cam, 8, 8);
+        assert!((densities[4 * 8 + 4].unwrap().unwrap() - 0.5).abs() < 1e-4);
+    }
+}
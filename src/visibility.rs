@@ -0,0 +1,89 @@
+// Author: Giulian Biolo, github.com/giulianbiolo
+// Date: 16/10/2026
+// Description: This file implements the visibility matrix between two sets of points, with the line of sight or the attenuation through the scene of each pair
+
+use yaml_rust::Yaml;
+
+use crate::hittable_list::{Hittable, HittableList};
+use crate::parallel::*;
+use crate::parser;
+use crate::point3::Point3;
+use crate::ray::Ray;
+use crate::utility;
+
+
+// The points of the list under the key, as [x, y, z]
+pub fn parse_points(doc: &Yaml, key: &str) -> Vec<Point3> {
+    doc[key].as_vec().unwrap_or_else(|| panic!("Missing the list of points {}", key)).iter().map(|point| {
+        let point: Vec<f32> = point.as_vec().unwrap().iter().map(|value| value.as_f64().or(value.as_i64().map(|value| value as f64)).unwrap() as f32).collect();
+        Point3::new(point[0], point[1], point[2])
+    }).collect()
+}
+
+pub fn line_of_sight(world: &HittableList, from: &Point3, to: &Point3) -> bool {
+    let distance: f32 = (*to - *from).length();
+    !world.occluded(&Ray::new(*from, (*to - *from) / distance), utility::NEAR_ZERO, distance - utility::NEAR_ZERO)
+}
+
+// The number of surfaces between the two points, each wall being crossed twice when entering and leaving it
+pub fn crossings(world: &HittableList, from: &Point3, to: &Point3) -> u32 {
+    let distance: f32 = (*to - *from).length();
+    let ray: Ray = Ray::new(*from, (*to - *from) / distance);
+    let (mut count, mut t_min) = (0, utility::NEAR_ZERO);
+    while let Some(rec) = world.hit(&ray, t_min, distance - utility::NEAR_ZERO) {
+        count += 1;
+        t_min = rec.t + utility::NEAR_ZERO;
+    }
+    count
+}
+
+// Rows of the matrix are the from points and columns the to ones: 1 for line of sight and 0 otherwise, or the attenuation in dB
+// (the free space loss at the wavelength plus the loss of every surface crossed) when a loss per surface is given
+pub fn matrix(world: &HittableList, from: &[Point3], to: &[Point3], surface_loss: Option<f32>, lambda: f32) -> Vec<Vec<f32>> {
+    from.into_par_iter().map(|source| to.iter().map(|receiver| match surface_loss {
+        Some(surface_loss) => -20.0 * (lambda / (4.0 * utility::PI * (*receiver - *source).length())).log10() + surface_loss * crossings(world, source, receiver) as f32,
+        None => if line_of_sight(world, source, receiver) { 1.0 } else { 0.0 },
+    }).collect()).collect()
+}
+
+pub fn to_csv(matrix: &[Vec<f32>]) -> String {
+    matrix.iter().map(|row| row.iter().map(|value| value.to_string()).collect::<Vec<String>>().join(",") + "\n").collect()
+}
+
+// The points are read from points: { from: [[x, y, z], ..], to: [[x, y, z], ..], surfaceLoss: dB }, the emitters of the scene not blocking them
+pub fn run(config_file: &str, points_file: &str, output: &str) {
+    let world: HittableList = parser::parse_yaml_scene(config_file).into_iter().filter(|object| !object.is_light()).collect();
+    let lambda: f32 = parser::parse_yaml_constants(config_file).sources_lambda;
+    let doc: Yaml = parser::load_config(points_file).unwrap_or_else(|| panic!("Failed to load the points file {}", points_file));
+    let (from, to) = (parse_points(&doc, "from"), parse_points(&doc, "to"));
+    let surface_loss: Option<f32> = doc["surfaceLoss"].as_f64().or(doc["surfaceLoss"].as_i64().map(|loss| loss as f64)).map(|loss| loss as f32);
+    let matrix: Vec<Vec<f32>> = matrix(&world, &from, &to, surface_loss, lambda);
+    std::fs::write(output, to_csv(&matrix)).unwrap_or_else(|err| panic!("Failed to write {}: {}", output, err));
+    match surface_loss {
+        Some(_) => println!("Saved the {}x{} attenuation matrix to {}", from.len(), to.len(), output),
+        None => println!("Saved the {}x{} visibility matrix to {}, {} pairs in line of sight", from.len(), to.len(), output, matrix.iter().flatten().filter(|value| **value > 0.0).count()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use crate::color::Color;
+    use crate::material::Lambertian;
+    use crate::sphere::Sphere;
+
+    #[test]
+    fn test_visibility_matrix() {
+        let world: HittableList = vec![Arc::new(Sphere::new(Point3::ZERO, 1.0, Box::new(Lambertian::new(Color::ONE)), 0))];
+        let from: Vec<Point3> = vec![Point3::new(-3.0, 0.0, 0.0)];
+        let to: Vec<Point3> = vec![Point3::new(3.0, 0.0, 0.0), Point3::new(-3.0, 3.0, 0.0)];
+        assert_eq!(matrix(&world, &from, &to, None, 0.1), vec![vec![0.0, 1.0]]);
+        // The ray through the sphere crosses its surface twice, the other one only loses to the distance
+        let attenuation: Vec<Vec<f32>> = matrix(&world, &from, &to, Some(10.0), 4.0 * utility::PI);
+        assert!((attenuation[0][0] - (20.0 * 6_f32.log10() + 20.0)).abs() < 1e-3);
+        assert!((attenuation[0][1] - 20.0 * 3_f32.log10()).abs() < 1e-3);
+        assert_eq!(to_csv(&[vec![0.0, 1.0], vec![1.0, 1.0]]), "0,1\n1,1\n");
+        assert_eq!(crossings(&world, &from[0], &Point3::ZERO), 1);
+    }
+}
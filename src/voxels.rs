@@ -0,0 +1,208 @@
+// Author: Giulian Biolo, github.com/giulianbiolo
+// Date: 16/10/2026
+// Description: This file implements the voxelization of the scene into a sparse occupancy grid, and its export
+
+use std::collections::HashSet;
+
+use yaml_rust::Yaml;
+
+use crate::hittable_list::{Hittable, HittableList};
+use crate::parallel::*;
+use crate::parser;
+use crate::point3::Point3;
+use crate::ray::Ray;
+use crate::utility;
+
+
+const VOXEL_MAGIC: &[u8; 8] = b"GBRTVOXL";
+
+// Cubic voxels over a box of the scene, only the solid ones being stored
+#[derive(Debug, Clone, PartialEq)]
+pub struct VoxelGrid {
+    // Corner of the first voxel, and the side of every voxel
+    pub min: Point3,
+    pub voxel_size: f32,
+    // Voxels along x, y and z
+    pub dims: [u32; 3],
+    // The solid voxels by their x, y and z index
+    pub solid: HashSet<[u32; 3]>,
+}
+
+impl VoxelGrid {
+    // An empty grid over the box, with resolution voxels along its longest side
+    pub fn new(min: Point3, max: Point3, resolution: u32) -> VoxelGrid {
+        let size: Point3 = max - min;
+        if size.min_element() <= 0.0 || resolution == 0 { panic!("Invalid voxel grid from {:?} to {:?} with {} voxels", min, max, resolution); }
+        let voxel_size: f32 = size.max_element() / resolution as f32;
+        let dims: [u32; 3] = [0, 1, 2].map(|axis| ((size[axis] / voxel_size).ceil() as u32).max(1));
+        VoxelGrid { min, voxel_size, dims, solid: HashSet::new() }
+    }
+    // A voxel is solid where a surface goes through it, or inside of a closed object
+    // Every voxel is crossed by a ray along each axis, and it's inside when an odd number of surfaces come before its center along at least two of them,
+    // so that a lone open surface (a wall, a ground plane) doesn't fill the space behind it
+    pub fn voxelize(world: &HittableList, min: Point3, max: Point3, resolution: u32) -> VoxelGrid {
+        let mut grid: VoxelGrid = VoxelGrid::new(min, max, resolution);
+        let mut votes: Vec<u8> = vec![0; grid.total_voxels()];
+        for axis in 0..3 {
+            let crossings: Vec<Vec<f32>> = (0..grid.lines(axis)).into_par_iter().map(|line| {
+                let (ray, length) = grid.line_ray(axis, line);
+                vec![_crossings(world, &ray, length)]
+            }).reduce(Vec::new, |mut a, b| { a.extend(b); a });
+            for (line, crossings) in crossings.iter().enumerate() {
+                let mut voxel: [u32; 3] = grid.line_voxel(axis, line);
+                // The distances along the ray start a voxel before the grid
+                let positions: Vec<f32> = crossings.iter().map(|t| t / grid.voxel_size - 1.0).collect();
+                for position in positions.iter().filter(|position| **position >= 0.0 && **position < grid.dims[axis] as f32) {
+                    voxel[axis] = *position as u32;
+                    grid.solid.insert(voxel);
+                }
+                for k in 0..grid.dims[axis] {
+                    voxel[axis] = k;
+                    if positions.iter().filter(|position| **position < k as f32 + 0.5).count() % 2 == 1 { votes[grid.index(voxel)] += 1; }
+                }
+            }
+        }
+        for (idx, _) in votes.iter().enumerate().filter(|(_, votes)| **votes >= 2) { grid.solid.insert(grid.voxel_at(idx)); }
+        grid
+    }
+    // The rows of voxels along the axis, and the ray through the centers of each one, from a voxel before the grid to a voxel past it
+    pub fn lines(&self, axis: usize) -> usize { self.dims[(axis + 1) % 3] as usize * self.dims[(axis + 2) % 3] as usize }
+    pub fn line_ray(&self, axis: usize, line: usize) -> (Ray, f32) {
+        let voxel: [u32; 3] = self.line_voxel(axis, line);
+        let mut origin: Point3 = self.center(voxel);
+        origin[axis] = self.min[axis] - self.voxel_size;
+        let mut direction: Point3 = Point3::ZERO;
+        direction[axis] = 1.0;
+        (Ray::new(origin, direction), (self.dims[axis] + 2) as f32 * self.voxel_size)
+    }
+    // The first voxel of the row, at index 0 along the axis
+    pub fn line_voxel(&self, axis: usize, line: usize) -> [u32; 3] {
+        let (b, c) = ((axis + 1) % 3, (axis + 2) % 3);
+        let mut voxel: [u32; 3] = [0; 3];
+        (voxel[b], voxel[c]) = ((line % self.dims[b] as usize) as u32, (line / self.dims[b] as usize) as u32);
+        voxel
+    }
+    // All of the voxels of the grid, solid or not
+    pub fn total_voxels(&self) -> usize { self.dims.iter().map(|dim| *dim as usize).product() }
+    // Row major index of the voxel, x first
+    fn index(&self, voxel: [u32; 3]) -> usize { voxel[0] as usize + self.dims[0] as usize * (voxel[1] as usize + self.dims[1] as usize * voxel[2] as usize) }
+    fn voxel_at(&self, idx: usize) -> [u32; 3] {
+        let (x, y) = (self.dims[0] as usize, self.dims[1] as usize);
+        [(idx % x) as u32, (idx / x % y) as u32, (idx / (x * y)) as u32]
+    }
+    // The voxel holding the point, if it's inside of the grid, for the library users
+    #[allow(dead_code)]
+    pub fn voxel(&self, point: Point3) -> Option<[u32; 3]> {
+        let position: Point3 = (point - self.min) / self.voxel_size;
+        if position.min_element() < 0.0 { return None; }
+        let voxel: [u32; 3] = [position.x as u32, position.y as u32, position.z as u32];
+        if (0..3).any(|axis| voxel[axis] >= self.dims[axis]) { return None; }
+        Some(voxel)
+    }
+    pub fn center(&self, voxel: [u32; 3]) -> Point3 { self.min + (Point3::new(voxel[0] as f32, voxel[1] as f32, voxel[2] as f32) + 0.5) * self.voxel_size }
+    // Whether the point is in a solid voxel, the space outside of the grid being empty
+    #[allow(dead_code)]
+    pub fn is_solid(&self, point: Point3) -> bool { self.voxel(point).is_some_and(|voxel| self.solid.contains(&voxel)) }
+    // The solid voxels as runs along x, [x, y, z, length], sorted by z, y and x
+    pub fn runs(&self) -> Vec<[u32; 4]> {
+        let mut voxels: Vec<[u32; 3]> = self.solid.iter().copied().collect();
+        voxels.sort_by_key(|voxel| (voxel[2], voxel[1], voxel[0]));
+        let mut runs: Vec<[u32; 4]> = Vec::new();
+        for voxel in voxels {
+            match runs.last_mut() {
+                Some(run) if run[1] == voxel[1] && run[2] == voxel[2] && run[0] + run[3] == voxel[0] => run[3] += 1,
+                _ => runs.push([voxel[0], voxel[1], voxel[2], 1]),
+            }
+        }
+        runs
+    }
+    // GBRTVOXL, the dims, the min corner and the voxel size, then the number of runs followed by the runs, all little endian
+    pub fn save(&self, filename: &str) -> std::io::Result<()> {
+        let mut bytes: Vec<u8> = VOXEL_MAGIC.to_vec();
+        for dim in self.dims { bytes.extend_from_slice(&dim.to_le_bytes()); }
+        for float in [self.min.x, self.min.y, self.min.z, self.voxel_size] { bytes.extend_from_slice(&float.to_le_bytes()); }
+        let runs: Vec<[u32; 4]> = self.runs();
+        bytes.extend_from_slice(&(runs.len() as u32).to_le_bytes());
+        for value in runs.iter().flatten() { bytes.extend_from_slice(&value.to_le_bytes()); }
+        std::fs::write(filename, bytes)
+    }
+    #[allow(dead_code)]
+    pub fn load(filename: &str) -> std::io::Result<VoxelGrid> {
+        let bytes: Vec<u8> = std::fs::read(filename)?;
+        let invalid = || std::io::Error::new(std::io::ErrorKind::InvalidData, format!("{} isn't a voxel grid", filename));
+        if !bytes.starts_with(VOXEL_MAGIC) { return Err(invalid()); }
+        let words: Vec<[u8; 4]> = bytes[VOXEL_MAGIC.len()..].chunks_exact(4).map(|word| word.try_into().unwrap()).collect();
+        if words.len() < 8 { return Err(invalid()); }
+        let (dims, floats) = ([0, 1, 2].map(|idx| u32::from_le_bytes(words[idx])), [3, 4, 5, 6].map(|idx| f32::from_le_bytes(words[idx])));
+        let runs: &[[u8; 4]] = &words[8..];
+        if runs.len() != 4 * u32::from_le_bytes(words[7]) as usize { return Err(invalid()); }
+        let solid: HashSet<[u32; 3]> = runs.chunks_exact(4).flat_map(|run| {
+            let [x, y, z, length] = [0, 1, 2, 3].map(|idx| u32::from_le_bytes(run[idx]));
+            (x..x + length).map(move |x| [x, y, z])
+        }).collect();
+        Ok(VoxelGrid { min: Point3::new(floats[0], floats[1], floats[2]), voxel_size: floats[3], dims, solid })
+    }
+}
+
+// The distances of every surface along the ray up to the length
+fn _crossings(world: &HittableList, ray: &Ray, length: f32) -> Vec<f32> {
+    let (mut crossings, mut t_min) = (Vec::new(), utility::NEAR_ZERO);
+    while let Some(rec) = world.hit(ray, t_min, length) {
+        crossings.push(rec.t);
+        t_min = rec.t + utility::NEAR_ZERO;
+    }
+    crossings
+}
+
+fn _parse_point(point: &Yaml, key: &str) -> Point3 {
+    let point: Vec<f32> = point.as_vec().unwrap_or_else(|| panic!("Missing the {} of the voxels", key)).iter().map(|value| value.as_f64().or(value.as_i64().map(|value| value as f64)).unwrap() as f32).collect();
+    Point3::new(point[0], point[1], point[2])
+}
+
+// The box and the resolution of the voxels: { min: [x, y, z], max: [x, y, z], resolution: 64 } block of the scene
+pub fn parse_grid(doc: &Yaml) -> (Point3, Point3, u32) {
+    let voxels: &Yaml = &doc["voxels"];
+    (_parse_point(&voxels["min"], "min"), _parse_point(&voxels["max"], "max"), voxels["resolution"].as_i64().unwrap_or(64) as u32)
+}
+
+// The emitters of the scene aren't solid
+pub fn run(config_file: &str, output: &str) {
+    let doc: Yaml = parser::load_config(config_file).unwrap_or_else(|| panic!("Failed to load the config file {}", config_file));
+    let world: HittableList = parser::parse_scene(&doc).into_iter().filter(|object| !object.is_light()).collect();
+    let (min, max, resolution) = parse_grid(&doc);
+    let grid: VoxelGrid = VoxelGrid::voxelize(&world, min, max, resolution);
+    grid.save(output).unwrap_or_else(|err| panic!("Failed to write {}: {}", output, err));
+    println!("Saved the {}x{}x{} voxel grid to {}, {} solid voxels in {} runs", grid.dims[0], grid.dims[1], grid.dims[2], output, grid.solid.len(), grid.runs().len());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use crate::rectangle::XZRectangle;
+    use crate::color::Color;
+    use crate::material::Lambertian;
+    use crate::sphere::Sphere;
+
+    #[test]
+    fn test_voxelize() {
+        // A ball filled in, over a ground rectangle that only fills its own layer of voxels
+        let world: HittableList = vec![
+            Arc::new(Sphere::new(Point3::new(0.0, 1.0, 0.0), 0.8, Box::new(Lambertian::new(Color::ONE)), 0)),
+            Arc::new(XZRectangle::new(-2.0, 2.0, -2.0, 2.0, 0.03, Box::new(Lambertian::new(Color::ONE)), 0)),
+        ];
+        let grid: VoxelGrid = VoxelGrid::voxelize(&world, Point3::new(-2.0, 0.0, -2.0), Point3::new(2.0, 2.0, 2.0), 40);
+        assert_eq!((grid.dims, grid.voxel_size), ([40, 20, 40], 0.1));
+        assert!(grid.is_solid(Point3::new(0.0, 1.0, 0.0)) && grid.is_solid(Point3::new(0.5, 1.2, 0.3)));
+        assert!(!grid.is_solid(Point3::new(0.0, 1.0, 0.95)) && !grid.is_solid(Point3::new(1.5, 1.0, 1.5)));
+        assert!(grid.is_solid(Point3::new(1.5, 0.05, 1.5)) && !grid.is_solid(Point3::new(1.5, 0.15, 1.5)));
+        assert!(!grid.is_solid(Point3::new(0.0, 5.0, 0.0)));
+        // About the volume of the ball plus the ground layer
+        let volume: f32 = grid.solid.len() as f32 * 0.001;
+        assert!((volume - (4.0 / 3.0 * utility::PI * 0.8_f32.powi(3) + 1.6)).abs() < 0.4, "{}", volume);
+        let filename: String = std::env::temp_dir().join("gbrt_test_voxels.bin").to_string_lossy().to_string();
+        grid.save(&filename).unwrap();
+        assert_eq!(VoxelGrid::load(&filename).unwrap(), grid);
+        assert!(grid.runs().len() < grid.solid.len() / 4);
+    }
+}
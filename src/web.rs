@@ -0,0 +1,99 @@
+// Author: Giulian Biolo, github.com/giulianbiolo
+// Date: 16/10/2026
+// Description: This file implements the rendering of scenes handed over in memory, which is the entry point of the wasm32 builds
+
+use std::sync::Arc;
+
+use image::{DynamicImage, RgbaImage};
+use yaml_rust::{Yaml, YamlLoader};
+
+use crate::camera::Camera;
+use crate::color::Color;
+use crate::hittable_list::{Hittable, HittableList};
+use crate::parser;
+use crate::raytracer;
+use crate::sampling_filters::Filter;
+use crate::utility::{self, Constants, load_environment, load_background};
+
+
+#[allow(dead_code)]
+const SEED: u64 = 0x5eed;
+
+// Renders the scene, whose textures and meshes have to be registered in the assets beforehand
+// Every scene is rendered with its own constants, the global ones being left untouched
+// The malformed scenes come back as errors, the panics aborting the wasm builds rather than unwinding
+#[allow(dead_code)]
+pub fn render(scene: &str) -> Result<RgbaImage, String> {
+    let (consts, cam, world): (Constants, Camera, HittableList) = parser::try_parse_scene(scene)?;
+    let (environment_map, env_dist): (Arc<dyn Hittable + Send + Sync>, f32) = load_environment(&world, &consts);
+    let background: Option<Arc<dyn Hittable + Send + Sync>> = load_background(env_dist, &consts);
+    let filter: Box<dyn Filter + Send + Sync> = utility::filter_from_name(consts.filter.as_deref());
+    let mut lights: HittableList = raytracer::get_lights(&world);
+    if utility::environment_is_light(&consts) { lights.push(environment_map.clone()); }
+    let lights: HittableList = raytracer::sample_lights(&world, lights);
+    let mut accumulator: Vec<Color> = vec![Color::ZERO; (consts.width * consts.height) as usize];
+    for pass in 0..consts.samples_per_pixel {
+        raytracer::render_pass(&world, &lights, &environment_map, background.as_ref(), filter.as_ref(), &cam, &consts, &mut accumulator, SEED + pass as u64);
+    }
+    Ok(DynamicImage::ImageRgb8(raytracer::accumulator_to_image(&accumulator, &consts, consts.samples_per_pixel)).to_rgba8())
+}
+
+// The width and the height of the image the scene renders to, read from its constants alone
+#[allow(dead_code)]
+pub fn image_size(scene: &str) -> Result<(u32, u32), String> {
+    parser::check_document(scene)?;
+    let doc: Yaml = YamlLoader::load_from_str(scene).map_err(|err| format!("Failed to parse the scene: {}", err))?
+        .into_iter().next().ok_or("The scene is empty")?;
+    let consts: Constants = parser::try_parse_constants(&doc)?;
+    Ok((consts.width, consts.height))
+}
+
+// The bindings of the browser demo in web/, built by wasm-bindgen
+#[cfg(target_arch = "wasm32")]
+mod bindings {
+    use wasm_bindgen::prelude::*;
+
+    use crate::assets;
+
+    #[wasm_bindgen(js_name = registerAsset)]
+    pub fn register_asset(filename: &str, bytes: &[u8]) { assets::register(filename, bytes.to_vec()); }
+
+    // The pixels of the image as RGBA bytes, row by row, ready for an ImageData, or the error of the scene thrown to the page
+    #[wasm_bindgen]
+    pub fn render(scene: &str) -> Result<Vec<u8>, JsValue> { super::render(scene).map(|image| image.into_raw()).map_err(|err| JsValue::from_str(&err)) }
+
+    // The size of the image of the scene, which every render takes from its own constants
+    #[wasm_bindgen(js_name = imageWidth)]
+    pub fn image_width(scene: &str) -> Result<u32, JsValue> { super::image_size(scene).map(|(width, _)| width).map_err(|err| JsValue::from_str(&err)) }
+
+    #[wasm_bindgen(js_name = imageHeight)]
+    pub fn image_height(scene: &str) -> Result<u32, JsValue> { super::image_size(scene).map(|(_, height)| height).map_err(|err| JsValue::from_str(&err)) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_image_size() {
+        let scene = |width: u32, height: u32| format!("constants: {{width: {}, height: {}, samplesPerPixel: 1, maxDepth: 1, minDepth: 1}}\nworld: []", width, height);
+        // Every scene has the size of its own constants, not of the first one
+        assert_eq!(image_size(&scene(160, 120)), Ok((160, 120)));
+        assert_eq!(image_size(&scene(32, 48)), Ok((32, 48)));
+        assert!(image_size("world: [").is_err());
+        assert!(image_size("world: []").unwrap_err().contains("constants"));
+    }
+    #[test]
+    fn test_render_own_constants() {
+        let scene = |intensity: f32| format!(
+            "constants: {{width: 4, height: 4, samplesPerPixel: 1, maxDepth: 2, minDepth: 2, environmentIntensity: {:.1}}}\n\
+            camera: {{lookFrom: [0.0, 0.0, 0.0], lookAt: [0.0, 0.0, -1.0], vup: [0.0, 1.0, 0.0], vfov: 40.0, aspectRatio: 1.0, aperture: 0.0, focusDistance: 1.0}}\n\
+            world: []", intensity
+        );
+        let brightest = |image: RgbaImage| image.pixels().map(|pixel| pixel[0].max(pixel[1]).max(pixel[2])).max().unwrap();
+        // The environment of a scene is its own, not the one of the scene rendered before it
+        assert_eq!(brightest(render(&scene(0.0)).unwrap()), 0);
+        assert!(brightest(render(&scene(1.0)).unwrap()) > 0);
+        assert_eq!(brightest(render(&scene(0.0)).unwrap()), 0);
+    }
+}
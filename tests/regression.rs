@@ -0,0 +1,17 @@
+// Author: Giulian Biolo, github.com/giulianbiolo
+// Date: 16/10/2026
+// Description: This file implements the regression test, rendering the benchmark scenes and the golden scenes (one per material, primitive and filter) and comparing them with the stored renders
+
+use std::process::Command;
+
+
+// Run "gbrt regression tests/references --update-references" after an intended change to the output
+#[test]
+fn test_reference_renders() {
+    let output = Command::new(env!("CARGO_BIN_EXE_gbrt"))
+        .args(["regression", "tests/references"])
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{}{}", String::from_utf8_lossy(&output.stdout), String::from_utf8_lossy(&output.stderr));
+}